@@ -22,6 +22,7 @@ use pem;
 use std::fmt;
 
 const ED_25519_OID: [u64; 4] = [1, 3, 101, 112];
+const X_25519_OID: [u64; 4] = [1, 3, 101, 110];
 
 // ---- Error handling ----
 
@@ -77,6 +78,9 @@ impl fmt::Display for ED25519ParserError {
 /// From RFC8032, to obtain the corresponding `x25519_dalek::StaticSecret`:
 ///   `clamping(Sha512(DATA)[0..32])`
 /// with `clamping` operation already done on `StaticSecret` creation
+///
+/// X25519 keys (RFC8410) share this structure, with OID(1.3.101.110), and
+/// DATA directly being the `x25519_dalek::StaticSecret`
 
 #[derive(Debug, PartialEq)]
 struct DerEd25519PrivateHeader<'a> {
@@ -115,14 +119,18 @@ fn parse_ed25519_private(
 
 const TAG_OCTETSTRING: u8 = 4;
 
-/// Parse a DER ED25519 private key, and return the corresponding
+/// Parse a DER ED25519 or X25519 private key, and return the corresponding
 /// `x25519_dalek::StaticSecret`
 pub fn parse_openssl_ed25519_privkey_der(data: &[u8]) -> Result<StaticSecret, ED25519ParserError> {
-    let ed25519_oid = Oid::from(&ED_25519_OID);
     let (_remain, (_header, ed25519_private)) = parse_ed25519_private(data)?;
-    if ed25519_private.header.tag.as_oid()? != &ed25519_oid {
+    let oid = ed25519_private.header.tag.as_oid()?;
+    let is_x25519 = if oid == &Oid::from(&ED_25519_OID) {
+        false
+    } else if oid == &Oid::from(&X_25519_OID) {
+        true
+    } else {
         return Err(ED25519ParserError::UnknownOid);
-    }
+    };
     let data = ed25519_private.data.content.as_slice()?;
     // data[0] == TAG_OCTETSTRING(4)
     // data[1] == LENGTH
@@ -130,7 +138,11 @@ pub fn parse_openssl_ed25519_privkey_der(data: &[u8]) -> Result<StaticSecret, ED
         return Err(ED25519ParserError::InvalidData);
     }
     let mut key_data = [0u8; 32];
-    key_data.copy_from_slice(&Sha512::digest(&data[2..34])[0..32]);
+    if is_x25519 {
+        key_data.copy_from_slice(&data[2..34]);
+    } else {
+        key_data.copy_from_slice(&Sha512::digest(&data[2..34])[0..32]);
+    }
     Ok(StaticSecret::from(key_data))
 }
 
@@ -155,6 +167,9 @@ pub fn parse_openssl_ed25519_privkey_der(data: &[u8]) -> Result<StaticSecret, ED
 /// From RFC8032 and OpenSSL format, to obtain the corresponding
 /// `x25519_dalek::PublicKey`, which internally use the Montgomery form:
 ///   to_montgomery(decompress_edwardspoint(DATA))
+///
+/// X25519 keys (RFC8410) share this structure, with OID(1.3.101.110), and
+/// DATA directly being the Montgomery form
 
 #[derive(Debug, PartialEq)]
 struct DerEd25519PublicHeader<'a> {
@@ -190,18 +205,27 @@ fn parse_ed25519_public(
     )
 }
 
-/// Parse a DER ED25519 public key, and return the corresponding
+/// Parse a DER ED25519 or X25519 public key, and return the corresponding
 /// `x25519_dalek::PublicKey`
 pub fn parse_openssl_ed25519_pubkey_der(data: &[u8]) -> Result<PublicKey, ED25519ParserError> {
-    let ed25519_oid = Oid::from(&ED_25519_OID);
     let (_remain, (_header, ed25519_public)) = parse_ed25519_public(data)?;
-    if ed25519_public.header.tag.as_oid()? != &ed25519_oid {
+    let oid = ed25519_public.header.tag.as_oid()?;
+    let is_x25519 = if oid == &Oid::from(&ED_25519_OID) {
+        false
+    } else if oid == &Oid::from(&X_25519_OID) {
+        true
+    } else {
         return Err(ED25519ParserError::UnknownOid);
-    }
+    };
     let data = ed25519_public.data.content.as_slice()?;
     if data.len() != 32 {
         return Err(ED25519ParserError::InvalidData);
     }
+    if is_x25519 {
+        let mut key_data = [0u8; 32];
+        key_data.copy_from_slice(data);
+        return Ok(PublicKey::from(key_data));
+    }
     if let Some(edwards_val) = CompressedEdwardsY::from_slice(&data[..32]).decompress() {
         Ok(PublicKey::from(edwards_val.to_montgomery().to_bytes()))
     } else {
@@ -214,7 +238,7 @@ pub fn parse_openssl_ed25519_pubkey_der(data: &[u8]) -> Result<PublicKey, ED2551
 const PUBLIC_TAG: &[u8] = b"PUBLIC KEY";
 const PRIVATE_TAG: &[u8] = b"PRIVATE KEY";
 
-/// Parse an OpenSSL ED25519 public key, either in PEM or DER format
+/// Parse an OpenSSL ED25519 or X25519 public key, either in PEM or DER format
 pub fn parse_openssl_ed25519_pubkey(data: &[u8]) -> Result<PublicKey, ED25519ParserError> {
     if let Ok(pem_data) = pem::parse(data) {
         // First, try as a PEM
@@ -228,7 +252,8 @@ pub fn parse_openssl_ed25519_pubkey(data: &[u8]) -> Result<PublicKey, ED25519Par
     }
 }
 
-/// Parse an OpenSSL ED25519 private key, either in PEM or DER format
+/// Parse an OpenSSL ED25519 or X25519 private key, either in PEM or DER
+/// format
 pub fn parse_openssl_ed25519_privkey(data: &[u8]) -> Result<StaticSecret, ED25519ParserError> {
     if let Ok(pem_data) = pem::parse(data) {
         // First, try as a PEM
@@ -355,6 +380,15 @@ mod tests {
     // Many[0] is PEM_PUB
     static PEM_PUB_MANY: &[u8] = include_bytes!("../../samples/test25519_pub_many.pem");
 
+    // openssl genpkey -algorithm x25519 -outform DER -out test_x25519.der
+    static X25519_DER_PRIV: &[u8] = include_bytes!("../../samples/test_x25519.der");
+    // openssl pkey -in test_x25519.der -inform DER -pubout -out test_x25519_pub.der -outform DER
+    static X25519_DER_PUB: &[u8] = include_bytes!("../../samples/test_x25519_pub.der");
+    // openssl pkey -in test_x25519.der -inform DER -out test_x25519.pem -outform PEM
+    static X25519_PEM_PRIV: &[u8] = include_bytes!("../../samples/test_x25519.pem");
+    // openssl pkey -in test_x25519.der -inform DER -pubout -out test_x25519_pub.pem -outform PEM
+    static X25519_PEM_PUB: &[u8] = include_bytes!("../../samples/test_x25519_pub.pem");
+
     #[test]
     fn parse_and_check_pubkeys_der() {
         let priv_key = parse_openssl_ed25519_privkey_der(DER_PRIV).unwrap();
@@ -377,6 +411,20 @@ mod tests {
         assert_eq!(priv_key_der.to_bytes(), priv_key_pem.to_bytes());
     }
 
+    #[test]
+    fn parse_and_check_x25519_keys() {
+        let priv_key = parse_openssl_ed25519_privkey(X25519_DER_PRIV).unwrap();
+        let pub_key = parse_openssl_ed25519_pubkey(X25519_DER_PUB).unwrap();
+        assert_eq!(PublicKey::from(&priv_key).as_bytes(), pub_key.as_bytes());
+        // The private key is used as is
+        assert_eq!(&priv_key.to_bytes()[..], &X25519_DER_PRIV[16..]);
+
+        let priv_key_pem = parse_openssl_ed25519_privkey(X25519_PEM_PRIV).unwrap();
+        let pub_key_pem = parse_openssl_ed25519_pubkey(X25519_PEM_PUB).unwrap();
+        assert_eq!(priv_key_pem.to_bytes(), priv_key.to_bytes());
+        assert_eq!(pub_key_pem.as_bytes(), pub_key.as_bytes());
+    }
+
     #[test]
     fn parse_many_pubkeys() {
         let pub_keys_pem = parse_openssl_ed25519_pubkeys_pem_many(PEM_PUB).unwrap();
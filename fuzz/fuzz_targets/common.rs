@@ -0,0 +1,37 @@
+use mla::config::ArchiveReaderConfig;
+use mla::ArchiveReader;
+use std::io::{Read, Seek};
+use x25519_dalek::StaticSecret;
+
+/// Private key of the encrypted seeds of `corpus/`
+const KEY: [u8; 32] = [42; 32];
+
+/// Maximum total size of the files of an archive fully read by the targets.
+///
+/// A small archive can declare huge files made of sparse holes, which are
+/// slow to read but not a bug: these archives are skipped to keep each run
+/// short
+const MAX_DECLARED_SIZE: u64 = 1024 * 1024;
+
+/// Reader configuration able to decrypt the encrypted seeds
+pub fn reader_config() -> ArchiveReaderConfig {
+    let mut config = ArchiveReaderConfig::new();
+    config.add_private_keys(&[StaticSecret::from(KEY)]);
+    config
+}
+
+/// Return the filenames of `mla`, if its files can be read in a reasonable
+/// time, see `MAX_DECLARED_SIZE`
+pub fn readable_files<R: Read + Seek>(mla: &mut ArchiveReader<R>) -> Option<Vec<String>> {
+    let fnames: Vec<String> = mla.list_files().ok()?.cloned().collect();
+    let mut total: u64 = 0;
+    for fname in &fnames {
+        if let Ok(Some(file)) = mla.get_file(fname.clone()) {
+            total = total.checked_add(file.size)?;
+        }
+    }
+    if total > MAX_DECLARED_SIZE {
+        return None;
+    }
+    Some(fnames)
+}
@@ -0,0 +1,24 @@
+//! Arbitrary bytes as a sequence of archive blocks, parsed until the first
+//! error
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use std::io::{self, Cursor, Read};
+
+use mla::ArchiveFileBlock;
+
+fuzz_target!(|data: &[u8]| {
+    let mut src = Cursor::new(data);
+    while let Ok(block) = ArchiveFileBlock::fuzz_from(&mut src) {
+        match block {
+            // The content is not part of the block, but follows it
+            ArchiveFileBlock::FileContent { length, .. } => {
+                let copied = io::copy(&mut (&mut src).take(length), &mut io::sink()).unwrap();
+                if copied != length {
+                    return;
+                }
+            }
+            ArchiveFileBlock::EndOfArchiveData => return,
+            _ => {}
+        }
+    }
+});
@@ -0,0 +1,25 @@
+//! Arbitrary bytes as a complete archive, linearly extracted to no-op writers
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use std::collections::HashMap;
+use std::io::{self, Cursor};
+
+use mla::helpers::linear_extract;
+use mla::ArchiveReader;
+
+mod common;
+
+fuzz_target!(|data: &[u8]| {
+    let mut mla = match ArchiveReader::from_config(Cursor::new(data), common::reader_config()) {
+        Ok(mla) => mla,
+        Err(_) => return,
+    };
+    let fnames = match common::readable_files(&mut mla) {
+        Some(fnames) => fnames,
+        None => return,
+    };
+    let mut export: HashMap<&String, io::Sink> =
+        fnames.iter().map(|fname| (fname, io::sink())).collect();
+    // Errors are expected, but never a panic
+    let _ = linear_extract(&mut mla, &mut export);
+});
@@ -0,0 +1,30 @@
+//! Arbitrary bytes as a complete archive: open it, then read every file and
+//! check its integrity
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use std::io::{Cursor, Read};
+
+use mla::ArchiveReader;
+
+mod common;
+
+fuzz_target!(|data: &[u8]| {
+    // Errors are expected, but never a panic
+    let mut mla = match ArchiveReader::from_config(Cursor::new(data), common::reader_config()) {
+        Ok(mla) => mla,
+        Err(_) => return,
+    };
+    let fnames = match common::readable_files(&mut mla) {
+        Some(fnames) => fnames,
+        None => return,
+    };
+    let _ = mla.verify_integrity();
+    for fname in fnames {
+        if let Ok(Some(file)) = mla.get_file(fname) {
+            // The content can be longer than the recorded size, then made of
+            // sparse holes: only the bounded part is read
+            let mut content = Vec::new();
+            let _ = file.data.take(file.size).read_to_end(&mut content);
+        }
+    }
+});
@@ -0,0 +1,7 @@
+fn main() {
+    // Used by tests/c_api.rs to build the C test program for the same target
+    println!(
+        "cargo:rustc-env=TARGET={}",
+        std::env::var("TARGET").unwrap()
+    );
+}
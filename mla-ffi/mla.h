@@ -0,0 +1,163 @@
+#ifndef MLA_H
+#define MLA_H
+
+/* Generated by cbindgen from src/lib.rs, do not edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Size, in bytes, of a X25519 key
+ */
+#define MLA_KEY_SIZE 32
+
+/**
+ * Status returned by every function of the API
+ */
+typedef enum {
+  MLA_OK = 0,
+  /**
+   * A required pointer is NULL
+   */
+  MLA_ERR_NULL_POINTER = 1,
+  /**
+   * A filename is not valid UTF-8
+   */
+  MLA_ERR_INVALID_UTF8 = 2,
+  /**
+   * An argument is not valid, or the call is not expected in this state
+   */
+  MLA_ERR_BAD_ARGUMENT = 3,
+  /**
+   * Input / output error, including errors reported by a callback
+   */
+  MLA_ERR_IO = 4,
+  /**
+   * The archive is malformed, or can't be decrypted with the given keys
+   */
+  MLA_ERR_ARCHIVE = 5,
+  /**
+   * The requested file is not in the archive
+   */
+  MLA_ERR_NOT_FOUND = 6,
+  /**
+   * The output buffer is too small
+   */
+  MLA_ERR_BUFFER_TOO_SMALL = 7,
+  /**
+   * A filename is already used in the archive
+   */
+  MLA_ERR_DUPLICATE_FILENAME = 8,
+} MLAStatus;
+
+/**
+ * Opaque archive reader
+ */
+typedef struct MLAReader MLAReader;
+
+/**
+ * Opaque archive writer
+ */
+typedef struct MLAWriter MLAWriter;
+
+/**
+ * Called with the archive data to write. Must return 0 if the whole
+ * `length` bytes have been written
+ */
+typedef int32_t (*MLAWriteCallback)(const uint8_t *data, uintptr_t length, void *context);
+
+/**
+ * Called with each filename of an archive. A non-zero return value stops the
+ * listing
+ */
+typedef int32_t (*MLAListCallback)(const char *filename, void *context);
+
+/**
+ * Create a new archive writer, calling `write_callback` with the archive
+ * data as they are produced
+ *
+ * The archive is encrypted for the `public_keys_count` keys of
+ * `MLA_KEY_SIZE` bytes in `public_keys`, and compressed. On success, the
+ * writer is stored in `handle_out`
+ */
+MLAStatus mla_writer_new(const uint8_t *public_keys,
+                         uintptr_t public_keys_count,
+                         MLAWriteCallback write_callback,
+                         void *context,
+                         MLAWriter **handle_out);
+
+/**
+ * Start a new file named `filename` in the archive. On success, its
+ * identifier is stored in `id_out`
+ */
+MLAStatus mla_writer_start_file(MLAWriter *handle, const char *filename, uint64_t *id_out);
+
+/**
+ * Append the `length` bytes of `data` to the file `id`
+ */
+MLAStatus mla_writer_append_bytes(MLAWriter *handle,
+                                  uint64_t id,
+                                  const uint8_t *data,
+                                  uintptr_t length);
+
+/**
+ * Mark the file `id` as finished
+ */
+MLAStatus mla_writer_end_file(MLAWriter *handle, uint64_t id);
+
+/**
+ * Finish the archive, writing its footer. The handle must still be released
+ * with `mla_writer_free`
+ */
+MLAStatus mla_writer_finalize(MLAWriter *handle);
+
+/**
+ * Release a writer. A writer not finalized produces an incomplete archive.
+ * NULL is accepted, and ignored
+ */
+MLAStatus mla_writer_free(MLAWriter *handle);
+
+/**
+ * Open the archive in the `length` bytes of `data`, with the
+ * `private_keys_count` keys of `MLA_KEY_SIZE` bytes in `private_keys`
+ *
+ * `data` is not copied: it must stay valid until the reader is released
+ * with `mla_reader_free`. On success, the reader is stored in `handle_out`
+ */
+MLAStatus mla_reader_new(const uint8_t *data,
+                         uintptr_t length,
+                         const uint8_t *private_keys,
+                         uintptr_t private_keys_count,
+                         MLAReader **handle_out);
+
+/**
+ * Call `callback` with the name of each file in the archive
+ */
+MLAStatus mla_reader_list_files(MLAReader *handle, MLAListCallback callback, void *context);
+
+/**
+ * Store the size of the file `filename` in `size_out`
+ */
+MLAStatus mla_reader_file_size(MLAReader *handle, const char *filename, uint64_t *size_out);
+
+/**
+ * Read the content of the file `filename` into the `length` bytes of
+ * `buffer`, and store its size in `size_out`
+ *
+ * If `buffer` is too small, `MLA_ERR_BUFFER_TOO_SMALL` is returned, and the
+ * size needed is stored in `size_out`
+ */
+MLAStatus mla_reader_read_file(MLAReader *handle,
+                               const char *filename,
+                               uint8_t *buffer,
+                               uintptr_t length,
+                               uint64_t *size_out);
+
+/**
+ * Release a reader. NULL is accepted, and ignored
+ */
+MLAStatus mla_reader_free(MLAReader *handle);
+
+#endif /* MLA_H */
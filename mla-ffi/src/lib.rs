@@ -0,0 +1,525 @@
+//! C bindings for MLA
+//!
+//! The API is declared in `mla.h`, generated from this file with `cbindgen`
+//! (see `cbindgen.toml`). Every function returns a `MLAStatus`, `MLA_OK` on
+//! success. Handles are created by `mla_writer_new` / `mla_reader_new`, and
+//! must be released with `mla_writer_free` / `mla_reader_free`.
+//!
+//! Keys are raw 32 bytes X25519 keys. Without keys, the archive is not
+//! encrypted.
+#![allow(clippy::missing_safety_doc)]
+
+use mla::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+use mla::errors::Error;
+use mla::{ArchiveFileID, ArchiveReader, ArchiveWriter, Layers};
+use std::ffi::{c_void, CStr};
+use std::io::{self, Cursor, Read, Write};
+use std::os::raw::c_char;
+use std::slice;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// Size, in bytes, of a X25519 key
+pub const MLA_KEY_SIZE: usize = 32;
+
+/// Status returned by every function of the API
+#[allow(non_camel_case_types)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MLAStatus {
+    MLA_OK = 0,
+    /// A required pointer is NULL
+    MLA_ERR_NULL_POINTER = 1,
+    /// A filename is not valid UTF-8
+    MLA_ERR_INVALID_UTF8 = 2,
+    /// An argument is not valid, or the call is not expected in this state
+    MLA_ERR_BAD_ARGUMENT = 3,
+    /// Input / output error, including errors reported by a callback
+    MLA_ERR_IO = 4,
+    /// The archive is malformed, or can't be decrypted with the given keys
+    MLA_ERR_ARCHIVE = 5,
+    /// The requested file is not in the archive
+    MLA_ERR_NOT_FOUND = 6,
+    /// The output buffer is too small
+    MLA_ERR_BUFFER_TOO_SMALL = 7,
+    /// A filename is already used in the archive
+    MLA_ERR_DUPLICATE_FILENAME = 8,
+}
+
+impl From<Error> for MLAStatus {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::IOError(_) => MLAStatus::MLA_ERR_IO,
+            Error::BadAPIArgument(_)
+            | Error::InvalidFilename(_)
+            | Error::WrongArchiveWriterState { .. }
+            | Error::WrongWriterState(_) => MLAStatus::MLA_ERR_BAD_ARGUMENT,
+            Error::DuplicateFilename => MLAStatus::MLA_ERR_DUPLICATE_FILENAME,
+            _ => MLAStatus::MLA_ERR_ARCHIVE,
+        }
+    }
+}
+
+/// Called with the archive data to write. Must return 0 if the whole
+/// `length` bytes have been written
+pub type MLAWriteCallback =
+    Option<extern "C" fn(data: *const u8, length: usize, context: *mut c_void) -> i32>;
+
+/// Called with each filename of an archive. A non-zero return value stops the
+/// listing
+pub type MLAListCallback =
+    Option<extern "C" fn(filename: *const c_char, context: *mut c_void) -> i32>;
+
+/// Archive output, forwarded to a `MLAWriteCallback`
+struct CallbackWriter {
+    callback: extern "C" fn(*const u8, usize, *mut c_void) -> i32,
+    context: *mut c_void,
+}
+
+impl Write for CallbackWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match (self.callback)(buf.as_ptr(), buf.len(), self.context) {
+            0 => Ok(buf.len()),
+            code => Err(io::Error::other(format!(
+                "Write callback failed with code {}",
+                code
+            ))),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Opaque archive writer
+pub struct MLAWriter {
+    archive: ArchiveWriter<'static, CallbackWriter>,
+}
+
+/// Opaque archive reader
+pub struct MLAReader {
+    archive: ArchiveReader<'static, Cursor<&'static [u8]>>,
+}
+
+/// Turn `$result` into its value, or return the corresponding status
+macro_rules! ffi_try {
+    ( $result:expr ) => {
+        match $result {
+            Ok(value) => value,
+            Err(error) => return MLAStatus::from(error),
+        }
+    };
+}
+
+/// Read a NULL terminated UTF-8 filename
+unsafe fn filename<'a>(name: *const c_char) -> Result<&'a str, MLAStatus> {
+    if name.is_null() {
+        return Err(MLAStatus::MLA_ERR_NULL_POINTER);
+    }
+    CStr::from_ptr(name)
+        .to_str()
+        .map_err(|_| MLAStatus::MLA_ERR_INVALID_UTF8)
+}
+
+/// Get `count` keys of `MLA_KEY_SIZE` bytes from `keys`
+unsafe fn raw_keys<'a>(
+    keys: *const u8,
+    count: usize,
+) -> Result<impl Iterator<Item = [u8; MLA_KEY_SIZE]> + 'a, MLAStatus> {
+    if keys.is_null() && count > 0 {
+        return Err(MLAStatus::MLA_ERR_NULL_POINTER);
+    }
+    let size = count
+        .checked_mul(MLA_KEY_SIZE)
+        .ok_or(MLAStatus::MLA_ERR_BAD_ARGUMENT)?;
+    let data: &'a [u8] = if size == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(keys, size)
+    };
+    Ok(data.chunks_exact(MLA_KEY_SIZE).map(|key| {
+        let mut out = [0u8; MLA_KEY_SIZE];
+        out.copy_from_slice(key);
+        out
+    }))
+}
+
+// -------- Writer --------
+
+/// Create a new archive writer, calling `write_callback` with the archive
+/// data as they are produced
+///
+/// The archive is encrypted for the `public_keys_count` keys of
+/// `MLA_KEY_SIZE` bytes in `public_keys`, and compressed. On success, the
+/// writer is stored in `handle_out`
+#[no_mangle]
+pub unsafe extern "C" fn mla_writer_new(
+    public_keys: *const u8,
+    public_keys_count: usize,
+    write_callback: MLAWriteCallback,
+    context: *mut c_void,
+    handle_out: *mut *mut MLAWriter,
+) -> MLAStatus {
+    let callback = match write_callback {
+        Some(callback) => callback,
+        None => return MLAStatus::MLA_ERR_NULL_POINTER,
+    };
+    if handle_out.is_null() {
+        return MLAStatus::MLA_ERR_NULL_POINTER;
+    }
+    let keys: Vec<PublicKey> = match raw_keys(public_keys, public_keys_count) {
+        Ok(keys) => keys.map(PublicKey::from).collect(),
+        Err(status) => return status,
+    };
+
+    let mut config = ArchiveWriterConfig::new();
+    if keys.is_empty() {
+        config.set_layers(Layers::COMPRESS);
+    } else {
+        config.set_layers(Layers::DEFAULT).add_public_keys(&keys);
+    }
+    let archive = ffi_try!(ArchiveWriter::from_config(
+        CallbackWriter { callback, context },
+        config
+    ));
+    *handle_out = Box::into_raw(Box::new(MLAWriter { archive }));
+    MLAStatus::MLA_OK
+}
+
+/// Start a new file named `filename` in the archive. On success, its
+/// identifier is stored in `id_out`
+#[no_mangle]
+pub unsafe extern "C" fn mla_writer_start_file(
+    handle: *mut MLAWriter,
+    filename: *const c_char,
+    id_out: *mut u64,
+) -> MLAStatus {
+    let writer = match handle.as_mut() {
+        Some(writer) if !id_out.is_null() => writer,
+        _ => return MLAStatus::MLA_ERR_NULL_POINTER,
+    };
+    let filename = match self::filename(filename) {
+        Ok(filename) => filename,
+        Err(status) => return status,
+    };
+    *id_out = ffi_try!(writer.archive.start_file(filename)).0;
+    MLAStatus::MLA_OK
+}
+
+/// Append the `length` bytes of `data` to the file `id`
+#[no_mangle]
+pub unsafe extern "C" fn mla_writer_append_bytes(
+    handle: *mut MLAWriter,
+    id: u64,
+    data: *const u8,
+    length: usize,
+) -> MLAStatus {
+    let writer = match handle.as_mut() {
+        Some(writer) if !data.is_null() || length == 0 => writer,
+        _ => return MLAStatus::MLA_ERR_NULL_POINTER,
+    };
+    let data: &[u8] = if length == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, length)
+    };
+    ffi_try!(writer
+        .archive
+        .append_file_content(ArchiveFileID(id), length as u64, data));
+    MLAStatus::MLA_OK
+}
+
+/// Mark the file `id` as finished
+#[no_mangle]
+pub unsafe extern "C" fn mla_writer_end_file(handle: *mut MLAWriter, id: u64) -> MLAStatus {
+    let writer = match handle.as_mut() {
+        Some(writer) => writer,
+        None => return MLAStatus::MLA_ERR_NULL_POINTER,
+    };
+    ffi_try!(writer.archive.end_file(ArchiveFileID(id)));
+    MLAStatus::MLA_OK
+}
+
+/// Finish the archive, writing its footer. The handle must still be released
+/// with `mla_writer_free`
+#[no_mangle]
+pub unsafe extern "C" fn mla_writer_finalize(handle: *mut MLAWriter) -> MLAStatus {
+    let writer = match handle.as_mut() {
+        Some(writer) => writer,
+        None => return MLAStatus::MLA_ERR_NULL_POINTER,
+    };
+    ffi_try!(writer.archive.finalize());
+    ffi_try!(writer.archive.flush().map_err(Error::from));
+    MLAStatus::MLA_OK
+}
+
+/// Release a writer. A writer not finalized produces an incomplete archive.
+/// NULL is accepted, and ignored
+#[no_mangle]
+pub unsafe extern "C" fn mla_writer_free(handle: *mut MLAWriter) -> MLAStatus {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+    MLAStatus::MLA_OK
+}
+
+// -------- Reader --------
+
+/// Open the archive in the `length` bytes of `data`, with the
+/// `private_keys_count` keys of `MLA_KEY_SIZE` bytes in `private_keys`
+///
+/// `data` is not copied: it must stay valid until the reader is released
+/// with `mla_reader_free`. On success, the reader is stored in `handle_out`
+#[no_mangle]
+pub unsafe extern "C" fn mla_reader_new(
+    data: *const u8,
+    length: usize,
+    private_keys: *const u8,
+    private_keys_count: usize,
+    handle_out: *mut *mut MLAReader,
+) -> MLAStatus {
+    if data.is_null() || handle_out.is_null() {
+        return MLAStatus::MLA_ERR_NULL_POINTER;
+    }
+    let keys: Vec<StaticSecret> = match raw_keys(private_keys, private_keys_count) {
+        Ok(keys) => keys.map(StaticSecret::from).collect(),
+        Err(status) => return status,
+    };
+
+    let mut config = ArchiveReaderConfig::new();
+    config.add_private_keys(&keys);
+    let src = Cursor::new(slice::from_raw_parts(data, length));
+    let archive = ffi_try!(ArchiveReader::from_config(src, config));
+    *handle_out = Box::into_raw(Box::new(MLAReader { archive }));
+    MLAStatus::MLA_OK
+}
+
+/// Call `callback` with the name of each file in the archive
+#[no_mangle]
+pub unsafe extern "C" fn mla_reader_list_files(
+    handle: *mut MLAReader,
+    callback: MLAListCallback,
+    context: *mut c_void,
+) -> MLAStatus {
+    let (reader, callback) = match (handle.as_mut(), callback) {
+        (Some(reader), Some(callback)) => (reader, callback),
+        _ => return MLAStatus::MLA_ERR_NULL_POINTER,
+    };
+    let mut filenames: Vec<&String> = ffi_try!(reader.archive.list_files()).collect();
+    filenames.sort();
+    for filename in filenames {
+        // A NUL byte can't be in a C string: such a name is not reachable
+        // through this API anyway
+        let mut bytes = filename.clone().into_bytes();
+        if bytes.contains(&0) {
+            continue;
+        }
+        bytes.push(0);
+        if callback(bytes.as_ptr() as *const c_char, context) != 0 {
+            break;
+        }
+    }
+    MLAStatus::MLA_OK
+}
+
+/// Store the size of the file `filename` in `size_out`
+#[no_mangle]
+pub unsafe extern "C" fn mla_reader_file_size(
+    handle: *mut MLAReader,
+    filename: *const c_char,
+    size_out: *mut u64,
+) -> MLAStatus {
+    let reader = match handle.as_mut() {
+        Some(reader) if !size_out.is_null() => reader,
+        _ => return MLAStatus::MLA_ERR_NULL_POINTER,
+    };
+    let filename = match self::filename(filename) {
+        Ok(filename) => filename,
+        Err(status) => return status,
+    };
+    match ffi_try!(reader.archive.get_file(filename)) {
+        Some(file) => {
+            *size_out = file.size;
+            MLAStatus::MLA_OK
+        }
+        None => MLAStatus::MLA_ERR_NOT_FOUND,
+    }
+}
+
+/// Read the content of the file `filename` into the `length` bytes of
+/// `buffer`, and store its size in `size_out`
+///
+/// If `buffer` is too small, `MLA_ERR_BUFFER_TOO_SMALL` is returned, and the
+/// size needed is stored in `size_out`
+#[no_mangle]
+pub unsafe extern "C" fn mla_reader_read_file(
+    handle: *mut MLAReader,
+    filename: *const c_char,
+    buffer: *mut u8,
+    length: usize,
+    size_out: *mut u64,
+) -> MLAStatus {
+    let reader = match handle.as_mut() {
+        Some(reader) if !size_out.is_null() && (!buffer.is_null() || length == 0) => reader,
+        _ => return MLAStatus::MLA_ERR_NULL_POINTER,
+    };
+    let filename = match self::filename(filename) {
+        Ok(filename) => filename,
+        Err(status) => return status,
+    };
+    let mut file = match ffi_try!(reader.archive.get_file(filename)) {
+        Some(file) => file,
+        None => return MLAStatus::MLA_ERR_NOT_FOUND,
+    };
+    *size_out = file.size;
+    if file.size > length as u64 {
+        return MLAStatus::MLA_ERR_BUFFER_TOO_SMALL;
+    }
+    let size = file.size as usize;
+    if size > 0 {
+        let buffer = slice::from_raw_parts_mut(buffer, size);
+        ffi_try!(file.data.read_exact(buffer).map_err(Error::from));
+    }
+    MLAStatus::MLA_OK
+}
+
+/// Release a reader. NULL is accepted, and ignored
+#[no_mangle]
+pub unsafe extern "C" fn mla_reader_free(handle: *mut MLAReader) -> MLAStatus {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+    MLAStatus::MLA_OK
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    extern "C" fn write_to_vec(data: *const u8, length: usize, context: *mut c_void) -> i32 {
+        let output = unsafe { &mut *(context as *mut Vec<u8>) };
+        output.extend_from_slice(unsafe { slice::from_raw_parts(data, length) });
+        0
+    }
+
+    extern "C" fn collect_names(filename: *const c_char, context: *mut c_void) -> i32 {
+        let names = unsafe { &mut *(context as *mut Vec<String>) };
+        names.push(
+            unsafe { CStr::from_ptr(filename) }
+                .to_str()
+                .unwrap()
+                .to_string(),
+        );
+        0
+    }
+
+    #[test]
+    fn write_and_read() {
+        let private_key = [3u8; MLA_KEY_SIZE];
+        let public_key = *PublicKey::from(&StaticSecret::from(private_key)).as_bytes();
+
+        let mut output: Vec<u8> = Vec::new();
+        let mut writer = ptr::null_mut();
+        unsafe {
+            assert_eq!(
+                mla_writer_new(
+                    public_key.as_ptr(),
+                    1,
+                    Some(write_to_vec),
+                    &mut output as *mut Vec<u8> as *mut c_void,
+                    &mut writer,
+                ),
+                MLAStatus::MLA_OK
+            );
+            let mut id = 0;
+            assert_eq!(
+                mla_writer_start_file(writer, b"hello\0".as_ptr() as *const c_char, &mut id),
+                MLAStatus::MLA_OK
+            );
+            assert_eq!(
+                mla_writer_start_file(writer, b"hello\0".as_ptr() as *const c_char, &mut id),
+                MLAStatus::MLA_ERR_DUPLICATE_FILENAME
+            );
+            assert_eq!(
+                mla_writer_start_file(writer, ptr::null(), &mut id),
+                MLAStatus::MLA_ERR_NULL_POINTER
+            );
+            assert_eq!(
+                mla_writer_append_bytes(writer, id, b"Hello".as_ptr(), 5),
+                MLAStatus::MLA_OK
+            );
+            assert_eq!(
+                mla_writer_append_bytes(writer, id, b", world".as_ptr(), 7),
+                MLAStatus::MLA_OK
+            );
+            assert_eq!(mla_writer_end_file(writer, id), MLAStatus::MLA_OK);
+            assert_eq!(
+                mla_writer_append_bytes(writer, id, b"!".as_ptr(), 1),
+                MLAStatus::MLA_ERR_BAD_ARGUMENT
+            );
+            assert_eq!(mla_writer_finalize(writer), MLAStatus::MLA_OK);
+            assert_eq!(mla_writer_free(writer), MLAStatus::MLA_OK);
+        }
+
+        let mut reader = ptr::null_mut();
+        unsafe {
+            // Wrong key
+            assert_eq!(
+                mla_reader_new(
+                    output.as_ptr(),
+                    output.len(),
+                    [4u8; MLA_KEY_SIZE].as_ptr(),
+                    1,
+                    &mut reader,
+                ),
+                MLAStatus::MLA_ERR_ARCHIVE
+            );
+            assert_eq!(
+                mla_reader_new(
+                    output.as_ptr(),
+                    output.len(),
+                    private_key.as_ptr(),
+                    1,
+                    &mut reader,
+                ),
+                MLAStatus::MLA_OK
+            );
+
+            let mut names: Vec<String> = Vec::new();
+            assert_eq!(
+                mla_reader_list_files(
+                    reader,
+                    Some(collect_names),
+                    &mut names as *mut Vec<String> as *mut c_void
+                ),
+                MLAStatus::MLA_OK
+            );
+            assert_eq!(names, vec!["hello".to_string()]);
+
+            let name = b"hello\0".as_ptr() as *const c_char;
+            let mut size = 0;
+            assert_eq!(
+                mla_reader_file_size(reader, name, &mut size),
+                MLAStatus::MLA_OK
+            );
+            assert_eq!(size, 12);
+            let mut buffer = [0u8; 16];
+            assert_eq!(
+                mla_reader_read_file(reader, name, buffer.as_mut_ptr(), 4, &mut size),
+                MLAStatus::MLA_ERR_BUFFER_TOO_SMALL
+            );
+            assert_eq!(size, 12);
+            assert_eq!(
+                mla_reader_read_file(reader, name, buffer.as_mut_ptr(), buffer.len(), &mut size),
+                MLAStatus::MLA_OK
+            );
+            assert_eq!(&buffer[..size as usize], b"Hello, world");
+            assert_eq!(
+                mla_reader_file_size(reader, b"other\0".as_ptr() as *const c_char, &mut size),
+                MLAStatus::MLA_ERR_NOT_FOUND
+            );
+            assert_eq!(mla_reader_free(reader), MLAStatus::MLA_OK);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+/* Write an archive through the C API, then read it back */
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "mla.h"
+
+#define CHECK(call)                                                          \
+    do {                                                                     \
+        MLAStatus status = (call);                                           \
+        if (status != MLA_OK) {                                              \
+            fprintf(stderr, "%s:%d: %s returned %d\n", __FILE__, __LINE__,  \
+                    #call, (int)status);                                     \
+            return 1;                                                        \
+        }                                                                    \
+    } while (0)
+
+struct buffer {
+    uint8_t *data;
+    size_t length;
+};
+
+static int32_t write_to_buffer(const uint8_t *data, uintptr_t length, void *context)
+{
+    struct buffer *output = context;
+    uint8_t *grown = realloc(output->data, output->length + length);
+    if (grown == NULL) {
+        return -1;
+    }
+    memcpy(grown + output->length, data, length);
+    output->data = grown;
+    output->length += length;
+    return 0;
+}
+
+static int32_t count_files(const char *filename, void *context)
+{
+    (void)filename;
+    (*(int *)context)++;
+    return 0;
+}
+
+int main(void)
+{
+    /* X25519 key pair, private key [1; 32] */
+    static const uint8_t private_key[MLA_KEY_SIZE] = {
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1};
+    static const uint8_t public_key[MLA_KEY_SIZE] = {
+        0xa4, 0xe0, 0x92, 0x92, 0xb6, 0x51, 0xc2, 0x78, 0xb9, 0x77, 0x2c,
+        0x56, 0x9f, 0x5f, 0xa9, 0xbb, 0x13, 0xd9, 0x06, 0xb4, 0x6a, 0xb6,
+        0x8c, 0x9d, 0xf9, 0xdc, 0x2b, 0x44, 0x09, 0xf8, 0xa2, 0x09};
+    static const char content[] = "Hello from C";
+
+    struct buffer archive = {NULL, 0};
+    MLAWriter *writer = NULL;
+    uint64_t id;
+    CHECK(mla_writer_new(public_key, 1, write_to_buffer, &archive, &writer));
+    CHECK(mla_writer_start_file(writer, "hello.txt", &id));
+    CHECK(mla_writer_append_bytes(writer, id, (const uint8_t *)content, 5));
+    CHECK(mla_writer_append_bytes(writer, id, (const uint8_t *)content + 5,
+                                  strlen(content) - 5));
+    CHECK(mla_writer_end_file(writer, id));
+    CHECK(mla_writer_start_file(writer, "empty", &id));
+    CHECK(mla_writer_end_file(writer, id));
+    CHECK(mla_writer_finalize(writer));
+    CHECK(mla_writer_free(writer));
+
+    MLAReader *reader = NULL;
+    CHECK(mla_reader_new(archive.data, archive.length, private_key, 1, &reader));
+    int count = 0;
+    CHECK(mla_reader_list_files(reader, count_files, &count));
+    if (count != 2) {
+        fprintf(stderr, "Expected 2 files, got %d\n", count);
+        return 1;
+    }
+    uint8_t read[64];
+    uint64_t size;
+    CHECK(mla_reader_read_file(reader, "hello.txt", read, sizeof(read), &size));
+    if (size != strlen(content) || memcmp(read, content, size) != 0) {
+        fprintf(stderr, "Unexpected content\n");
+        return 1;
+    }
+    if (mla_reader_file_size(reader, "missing", &size) != MLA_ERR_NOT_FOUND) {
+        fprintf(stderr, "Missing file found\n");
+        return 1;
+    }
+    CHECK(mla_reader_free(reader));
+    free(archive.data);
+    return 0;
+}
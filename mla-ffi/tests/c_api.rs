@@ -0,0 +1,52 @@
+//! Build a C program against `mla.h` and the shared library, then run it
+#![cfg(unix)]
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Directory of the libraries built by cargo for tests, alongside the test
+/// executable in `target/<profile>/deps`
+fn libraries_dir() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    exe.parent().unwrap().to_path_buf()
+}
+
+#[test]
+fn c_roundtrip() {
+    let crate_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let lib_dir = libraries_dir();
+    let out_dir = env::temp_dir().join(format!("mla-ffi-test-{}", std::process::id()));
+    std::fs::create_dir_all(&out_dir).unwrap();
+    let exe = out_dir.join("roundtrip");
+
+    let compiler = cc::Build::new()
+        .target(env!("TARGET"))
+        .host(env!("TARGET"))
+        .opt_level(0)
+        .cargo_metadata(false)
+        .warnings_into_errors(true)
+        .get_compiler();
+    let status = compiler
+        .to_command()
+        .arg("-Wall")
+        .arg("-I")
+        .arg(crate_dir)
+        .arg(crate_dir.join("tests").join("c").join("roundtrip.c"))
+        .arg("-o")
+        .arg(&exe)
+        .arg(format!("-L{}", lib_dir.display()))
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-lmla_ffi")
+        .status()
+        .unwrap();
+    assert!(status.success(), "Unable to build the C test program");
+
+    let output = Command::new(&exe).output().unwrap();
+    std::fs::remove_dir_all(&out_dir).unwrap();
+    assert!(
+        output.status.success(),
+        "C test program failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
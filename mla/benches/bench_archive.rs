@@ -1,15 +1,16 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
 use criterion::BenchmarkId;
 use criterion::Throughput;
 
-use mla::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+use mla::config::{ArchiveReaderConfig, ArchiveWriterConfig, CompressionAlgorithm};
+use mla::crypto::aesgcm::{ConstantTimeEq, Tag, TAG_LENGTH};
 use mla::helpers::linear_extract;
 use mla::Layers;
 use mla::{ArchiveReader, ArchiveWriter};
 use rand::distributions::{Alphanumeric, Distribution};
 use rand::seq::index::sample;
-use rand::SeedableRng;
+use rand::{RngCore, SeedableRng};
 use rand_chacha::ChaChaRng;
 use std::collections::HashMap;
 use std::io::{self, Cursor, Read};
@@ -120,6 +121,60 @@ pub fn multiple_compression_quality(c: &mut Criterion) {
     group.finish();
 }
 
+/// Benchmark the layer Compress only, using the different compression
+/// algorithms on compressible (alphanumeric) and incompressible (random) data
+///
+/// As for `multiple_compression_quality`, the Throughput obtained depends on
+/// the data received, not on the emitted data
+pub fn multiple_compression_algorithm(c: &mut Criterion) {
+    let size = 256 * KB;
+
+    // Setup
+    // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    let compressible: Vec<u8> = Alphanumeric
+        .sample_iter(&mut rng)
+        .take(size)
+        .map(|c| c as u8)
+        .collect();
+    let mut incompressible = vec![0u8; size];
+    rng.fill_bytes(&mut incompressible);
+
+    let mut group = c.benchmark_group("multiple_compression_algorithm");
+    group.measurement_time(Duration::from_secs(10));
+    group.throughput(Throughput::Bytes(size as u64));
+    for algorithm in &[
+        CompressionAlgorithm::None,
+        CompressionAlgorithm::Brotli { quality: 5 },
+        CompressionAlgorithm::Zstd { level: 3 },
+        CompressionAlgorithm::Lz4,
+    ] {
+        for (data_name, data) in &[
+            ("compressible", &compressible),
+            ("incompressible", &incompressible),
+        ] {
+            // Create an archive
+            let file = Vec::new();
+            let mut config = ArchiveWriterConfig::new();
+            config
+                .enable_layer(Layers::COMPRESS)
+                .with_compression_algorithm(*algorithm)
+                .unwrap();
+            let mut mla = ArchiveWriter::from_config(file, config).expect("Writer init failed");
+
+            let id = mla.start_file("file").unwrap();
+            group.bench_with_input(
+                BenchmarkId::new(format!("{:?} / {}", algorithm, data_name), size),
+                &size,
+                |b, &_size| {
+                    b.iter(|| mla.append_file_content(id, data.len() as u64, data.as_slice()));
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
 /// Create an archive with a file of `iters`*`size` bytes using `layers` and
 /// measure the time needed to read it
 ///
@@ -342,12 +397,151 @@ pub fn linear_vs_normal_extract(c: &mut Criterion) {
     group.finish();
 }
 
+/// Create an archive with a `iters` files of `size` bytes using `layers` and
+/// measure the time needed to load them all in memory, with `get_file`
+fn iter_get_files_manual(iters: u64, size: u64, layers: Layers) -> Duration {
+    let mut mla_read = build_archive(iters, size, layers);
+
+    let fnames: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+    let start = Instant::now();
+    let mut contents = HashMap::new();
+    for fname in fnames {
+        let mut subfile = mla_read.get_file(&fname).unwrap().unwrap();
+        let mut content = Vec::new();
+        subfile.data.read_to_end(&mut content).unwrap();
+        contents.insert(fname, content);
+    }
+    black_box(contents);
+    start.elapsed()
+}
+
+/// Create an archive with a `iters` files of `size` bytes using `layers` and
+/// measure the time needed to load them all in memory, with `get_all_files`
+fn iter_get_all_files(iters: u64, size: u64, layers: Layers) -> Duration {
+    let mut mla_read = build_archive(iters, size, layers);
+
+    let start = Instant::now();
+    black_box(mla_read.get_all_files().unwrap());
+    start.elapsed()
+}
+
+/// This benchmark compares loading every file in memory with
+/// `ArchiveReader::get_all_files` against the `list_files` + `get_file` loop
+pub fn get_all_files_vs_manual(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_all_files_vs_manual");
+    // Reduce the number of sample to avoid taking too much time
+    group.sample_size(SAMPLE_SIZE_SMALL);
+    for size in [MB, 4 * MB].iter() {
+        group.throughput(Throughput::Bytes(*size as u64));
+
+        for layers in &[Layers::EMPTY, Layers::COMPRESS | Layers::ENCRYPT] {
+            group.bench_function(
+                BenchmarkId::new(format!("MANUAL / Layers {:?}", layers), size),
+                move |b| b.iter_custom(|iters| iter_get_files_manual(iters, *size as u64, *layers)),
+            );
+            group.bench_function(
+                BenchmarkId::new(format!("GET_ALL_FILES / Layers {:?}", layers), size),
+                move |b| b.iter_custom(|iters| iter_get_all_files(iters, *size as u64, *layers)),
+            );
+        }
+    }
+    group.finish();
+}
+
+/// This benchmark measures the time needed to compare an AES-GCM tag with
+/// the expected one, against 1000 tags: the expected one, random ones, and
+/// ones differing only by their last byte
+///
+/// Tags are compared with `subtle::ConstantTimeEq`: the three timings must be
+/// within the noise of each other. A comparison exiting on the first
+/// differing byte would make the random case faster.
+pub fn tag_comparison(c: &mut Criterion) {
+    // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    let mut expected = Tag::default();
+    rng.fill_bytes(&mut expected);
+
+    let valid: Vec<Tag> = vec![expected; 1000];
+    let random: Vec<Tag> = (0..1000)
+        .map(|_| {
+            let mut tag = Tag::default();
+            rng.fill_bytes(&mut tag);
+            tag
+        })
+        .collect();
+    let last_byte_differs: Vec<Tag> = (0..1000)
+        .map(|i| {
+            let mut tag = expected;
+            tag[TAG_LENGTH - 1] ^= (i % 255) as u8 + 1;
+            tag
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("tag_comparison");
+    for (name, tags) in [
+        ("valid", &valid),
+        ("random", &random),
+        ("last_byte_differs", &last_byte_differs),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for tag in tags {
+                    black_box(expected.ct_eq(black_box(tag)).unwrap_u8());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+/// This benchmark measures the time needed to append 10 000 contents of 10
+/// bytes to a file, with and without coalescing them
+///
+/// The size of the resulting archives is printed: without coalescing, each
+/// content costs a `FileContent` block header
+pub fn small_writes_coalescing(c: &mut Criterion) {
+    const COUNT: usize = 10_000;
+    let data = [0x42u8; 10];
+    let write_archive = |threshold: usize| {
+        let mut config = ArchiveWriterConfig::new();
+        config.coalesce_threshold(threshold);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let id = mla.start_file("file").unwrap();
+        for _ in 0..COUNT {
+            mla.append_file_content(id, data.len() as u64, &data[..])
+                .unwrap();
+        }
+        mla.end_file(id).unwrap();
+        mla.finalize().unwrap();
+        mla.into_raw()
+    };
+
+    let mut group = c.benchmark_group("small_writes_coalescing");
+    group.throughput(Throughput::Bytes((COUNT * data.len()) as u64));
+    for threshold in [0, 4 * KB] {
+        println!(
+            "Coalescing threshold {}: {} bytes archive, for {} bytes of content",
+            threshold,
+            write_archive(threshold).len(),
+            COUNT * data.len()
+        );
+        group.bench_function(BenchmarkId::new("CoalesceThreshold", threshold), |b| {
+            b.iter(|| black_box(write_archive(threshold)))
+        });
+    }
+    group.finish();
+}
+
 criterion_group!(
     benches,
     multiple_layers_multiple_block_size,
     multiple_compression_quality,
+    multiple_compression_algorithm,
     multiple_layers_multiple_block_size_decompress,
     multiple_layers_multiple_block_size_decompress_multifiles_random,
     linear_vs_normal_extract,
+    get_all_files_vs_manual,
+    tag_comparison,
+    small_writes_coalescing,
 );
 criterion_main!(benches);
@@ -0,0 +1,111 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use criterion::BenchmarkId;
+use criterion::Throughput;
+
+use mla::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+use mla::helpers::linear_extract;
+use mla::ArchiveReader;
+use mla::ArchiveWriter;
+use mla::Layers;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaChaRng;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+const MB: usize = 1024 * 1024;
+
+/// Number of files in the benchmarked archives
+const FILE_COUNT: usize = 50;
+const SAMPLE_SIZE_SMALL: usize = 10;
+
+/// Write an archive of `FILE_COUNT` files of `size` bytes using `layers` to a
+/// temporary file, and return its path with the reader configuration to open
+/// it
+fn build_archive(size: usize, layers: Layers) -> (PathBuf, ArchiveReaderConfig) {
+    // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    let key = StaticSecret::new(&mut rng);
+
+    let path = std::env::temp_dir().join(format!("mla_bench_mmap_{:?}_{}", layers, size));
+    let mut config = ArchiveWriterConfig::new();
+    config
+        .enable_layer(layers)
+        .add_public_keys(&[PublicKey::from(&key)]);
+    let mut mla = ArchiveWriter::from_config(File::create(&path).unwrap(), config)
+        .expect("Writer init failed");
+    let mut data = vec![0u8; size];
+    for i in 0..FILE_COUNT {
+        rng.fill_bytes(&mut data);
+        mla.add_file(format!("file_{}", i), data.len() as u64, data.as_slice())
+            .unwrap();
+    }
+    mla.finalize().unwrap();
+
+    let mut config = ArchiveReaderConfig::new();
+    config.add_private_keys(std::slice::from_ref(&key));
+    (path, config)
+}
+
+/// Measure the time needed to linearly extract `iters` times the whole
+/// archive at `path`, opening it with `open`
+fn iter_extract<R: 'static + Read + Seek>(
+    iters: u64,
+    path: &Path,
+    config: &ArchiveReaderConfig,
+    open: impl Fn(&Path, ArchiveReaderConfig) -> ArchiveReader<'static, R>,
+) -> Duration {
+    let fnames: Vec<String> = (0..FILE_COUNT).map(|i| format!("file_{}", i)).collect();
+    let start = Instant::now();
+    for _ in 0..iters {
+        let mut mla_read = open(path, config.clone());
+        let mut export: HashMap<&String, io::Sink> =
+            fnames.iter().map(|fname| (fname, io::sink())).collect();
+        linear_extract(&mut mla_read, &mut export).unwrap();
+    }
+    start.elapsed()
+}
+
+/// This benchmark compares the extraction time of a whole archive of 500MB
+/// on disk, read through a memory mapping (`ArchiveReader::from_mmap`) or a
+/// `BufReader<File>`
+pub fn mmap_vs_buffered_extract(c: &mut Criterion) {
+    let size = 10 * MB;
+    let mut group = c.benchmark_group("mmap_vs_buffered_extract");
+    // Reduce the number of sample to avoid taking too much time
+    group.sample_size(SAMPLE_SIZE_SMALL);
+    group.throughput(Throughput::Bytes((FILE_COUNT * size) as u64));
+    for layers in &[Layers::EMPTY, Layers::ENCRYPT] {
+        let (path, config) = build_archive(size, *layers);
+        group.bench_function(
+            BenchmarkId::new(format!("MMAP / Layers {:?}", layers), size),
+            |b| {
+                b.iter_custom(|iters| {
+                    iter_extract(iters, &path, &config, |path, config| {
+                        ArchiveReader::from_mmap(path, config).unwrap()
+                    })
+                })
+            },
+        );
+        group.bench_function(
+            BenchmarkId::new(format!("BUFFERED / Layers {:?}", layers), size),
+            |b| {
+                b.iter_custom(|iters| {
+                    iter_extract(iters, &path, &config, |path, config| {
+                        let file = BufReader::new(File::open(path).unwrap());
+                        ArchiveReader::from_config(file, config).unwrap()
+                    })
+                })
+            },
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+    group.finish();
+}
+
+criterion_group!(benches, mmap_vs_buffered_extract);
+criterion_main!(benches);
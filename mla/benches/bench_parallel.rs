@@ -0,0 +1,111 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use criterion::BenchmarkId;
+use criterion::Throughput;
+
+use mla::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+use mla::helpers::parallel_extract;
+use mla::ArchiveWriter;
+use mla::Layers;
+use rand::distributions::{Alphanumeric, Distribution};
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use std::collections::HashMap;
+use std::io::{self, Cursor};
+use std::time::{Duration, Instant};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+const MB: usize = 1024 * 1024;
+
+/// Number of files in the benchmarked archives
+const FILE_COUNT: usize = 64;
+const SAMPLE_SIZE_SMALL: usize = 10;
+
+/// Build an archive of `FILE_COUNT` files of `size` bytes using `layers`, and
+/// return it with the reader configuration to open it
+fn build_archive(size: usize, layers: Layers) -> (Vec<u8>, ArchiveReaderConfig) {
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    let key = StaticSecret::new(&mut rng);
+
+    let mut config = ArchiveWriterConfig::new();
+    config
+        .enable_layer(layers)
+        .add_public_keys(&[PublicKey::from(&key)]);
+    let mut mla = ArchiveWriter::from_config(Vec::new(), config).expect("Writer init failed");
+    for i in 0..FILE_COUNT {
+        let data: Vec<u8> = Alphanumeric
+            .sample_iter(&mut rng)
+            .take(size)
+            .map(|c| c as u8)
+            .collect();
+        mla.add_file(format!("file_{}", i), data.len() as u64, data.as_slice())
+            .unwrap();
+    }
+    mla.finalize().unwrap();
+
+    let mut config = ArchiveReaderConfig::new();
+    config.add_private_keys(std::slice::from_ref(&key));
+    (mla.into_raw(), config)
+}
+
+/// Measure the time needed to extract `iters` times the whole archive with
+/// `num_threads` threads
+fn iter_parallel_extract(
+    iters: u64,
+    archive: &[u8],
+    config: &ArchiveReaderConfig,
+    num_threads: usize,
+) -> Duration {
+    let fnames: Vec<String> = (0..FILE_COUNT).map(|i| format!("file_{}", i)).collect();
+    let start = Instant::now();
+    for _ in 0..iters {
+        let mut export: HashMap<&String, io::Sink> =
+            fnames.iter().map(|fname| (fname, io::sink())).collect();
+        parallel_extract(
+            || Ok(Cursor::new(archive)),
+            config,
+            &mut export,
+            num_threads,
+        )
+        .unwrap();
+    }
+    start.elapsed()
+}
+
+/// This benchmark compares the extraction time of a whole archive between the
+/// sequential extraction (`linear_extract`, used for 1 thread) and the parallel
+/// extraction on 4 threads
+pub fn sequential_vs_parallel_extract(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sequential_vs_parallel_extract");
+    // Reduce the number of sample to avoid taking too much time
+    group.sample_size(SAMPLE_SIZE_SMALL);
+    // Up to 1GB archives
+    for size in [MB, 4 * MB, 16 * MB].iter() {
+        group.throughput(Throughput::Bytes((FILE_COUNT * size) as u64));
+
+        for layers in &[
+            Layers::COMPRESS,
+            Layers::ENCRYPT,
+            Layers::COMPRESS | Layers::ENCRYPT,
+        ] {
+            let (archive, config) = build_archive(*size, *layers);
+            for num_threads in [1, 4].iter() {
+                group.bench_function(
+                    BenchmarkId::new(
+                        format!("{} thread(s) / Layers {:?}", num_threads, layers),
+                        size,
+                    ),
+                    |b| {
+                        b.iter_custom(|iters| {
+                            iter_parallel_extract(iters, &archive, &config, *num_threads)
+                        })
+                    },
+                );
+            }
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, sequential_vs_parallel_extract);
+criterion_main!(benches);
@@ -0,0 +1,1176 @@
+//! Asynchronous API for MLA Archives, on top of `tokio::io`
+//!
+//! MLA layers are implemented over the blocking `Read`, `Seek` and `Write`
+//! traits. To avoid blocking, they are never handed the real source or
+//! destination:
+//! - on writing, bytes produced by the layers are kept in memory, then
+//!   asynchronously written to the `AsyncWrite` destination after each operation
+//! - on reading, the layers read from an in-memory cache of the source. If a
+//!   needed part is missing, the operation is interrupted, the missing part is
+//!   asynchronously fetched from the `AsyncRead + AsyncSeek` source, and the
+//!   operation is resumed
+//!
+//! As the layers are not `Send`, the futures returned are not `Send` either.
+use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+use crate::crypto::hash::Sha256Hash;
+use crate::errors::Error;
+use crate::layers::sign::verify_signature;
+use crate::layers::traits::LayerReader;
+use crate::metadata::FileMetadata;
+use crate::progress::ReadProgressReporter;
+use crate::stats::WriteStatistics;
+use crate::{
+    check_unknown_block, decompress_block_content, ArchiveFileBlock, ArchiveFileID, ArchiveHeader,
+    ArchiveReader, ArchiveWriter, BlocksToFileReader, BlocksToFileReaderCheckpoint, Layers,
+};
+use ed25519_dalek::{VerifyingKey, SIGNATURE_LENGTH};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::future::poll_fn;
+use std::hash::BuildHasher;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{ready, Context, Poll};
+use tokio::io::{
+    AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt, ReadBuf,
+};
+use x25519_dalek::PublicKey;
+
+// -------- Constants --------
+
+/// Size of the buffer used to move data from and to async objects
+const BUFFER_SIZE: usize = 128 * 1024;
+/// Granularity of the source cache
+const PAGE_SIZE: u64 = 64 * 1024;
+/// Amount of data to fetch at once from the source, when a part is missing.
+/// This is about the size of a compressed block, to limit the number of
+/// interrupted operations
+const READAHEAD_SIZE: u64 = 4 * 1024 * 1024;
+/// Size above which least recently used pages are evicted from the source cache
+const CACHE_MAX_SIZE: u64 = 32 * 1024 * 1024;
+
+// -------- Writer --------
+
+/// Bytes produced by the layers, waiting to be written to the real destination
+#[derive(Clone, Default)]
+struct PendingBytes(Rc<RefCell<Vec<u8>>>);
+
+impl PendingBytes {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.borrow_mut())
+    }
+}
+
+impl Write for PendingBytes {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Asynchronous counterpart of `ArchiveWriter`
+pub struct AsyncArchiveWriter<'a, W: AsyncWrite + Unpin> {
+    /// Synchronous writer, producing bytes in `pending`
+    archive: ArchiveWriter<'a, PendingBytes>,
+    pending: PendingBytes,
+    /// Real destination
+    dest: W,
+}
+
+impl<'a, W: AsyncWrite + Unpin> AsyncArchiveWriter<'a, W> {
+    pub async fn from_config(dest: W, config: ArchiveWriterConfig) -> Result<Self, Error> {
+        let pending = PendingBytes::default();
+        let archive = ArchiveWriter::from_config(pending.clone(), config)?;
+        let mut writer = AsyncArchiveWriter {
+            archive,
+            pending,
+            dest,
+        };
+        // Write the header
+        writer.write_pending().await?;
+        Ok(writer)
+    }
+
+    pub async fn new(dest: W, public_keys: &[PublicKey]) -> Result<Self, Error> {
+        let mut config = ArchiveWriterConfig::default();
+        config.add_public_keys(public_keys);
+        Self::from_config(dest, config).await
+    }
+
+    /// Write the bytes produced so far by the layers to the destination
+    async fn write_pending(&mut self) -> Result<(), Error> {
+        let data = self.pending.take();
+        self.dest.write_all(&data).await?;
+        Ok(())
+    }
+
+    pub async fn start_file(&mut self, filename: impl AsRef<str>) -> Result<ArchiveFileID, Error> {
+        let id = self.archive.start_file(filename)?;
+        self.write_pending().await?;
+        Ok(id)
+    }
+
+    pub async fn start_file_with_metadata(
+        &mut self,
+        filename: impl AsRef<str>,
+        metadata: &FileMetadata,
+    ) -> Result<ArchiveFileID, Error> {
+        let id = self.archive.start_file_with_metadata(filename, metadata)?;
+        self.write_pending().await?;
+        Ok(id)
+    }
+
+    /// Append `size` bytes of `src` to the file identified by `id`
+    ///
+    /// `src` is read by chunks of at most `BUFFER_SIZE` bytes, each chunk
+    /// resulting in a new block
+    pub async fn append_file_content<U: AsyncRead + Unpin>(
+        &mut self,
+        id: ArchiveFileID,
+        size: u64,
+        mut src: U,
+    ) -> Result<(), Error> {
+        let mut buf = vec![0u8; std::cmp::min(size, BUFFER_SIZE as u64) as usize];
+        let mut remaining = size;
+        loop {
+            let count = std::cmp::min(remaining, buf.len() as u64) as usize;
+            src.read_exact(&mut buf[..count]).await?;
+            self.archive
+                .append_file_content(id, count as u64, &buf[..count])?;
+            self.write_pending().await?;
+            remaining -= count as u64;
+            if remaining == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    pub async fn end_file(&mut self, id: ArchiveFileID) -> Result<(), Error> {
+        self.archive.end_file(id)?;
+        self.write_pending().await
+    }
+
+    /// Helper, calling `start_file`, `append_file_content` and `end_file`
+    pub async fn add_file<U: AsyncRead + Unpin>(
+        &mut self,
+        filename: impl AsRef<str>,
+        size: u64,
+        src: U,
+    ) -> Result<(), Error> {
+        let id = self.start_file(filename).await?;
+        self.append_file_content(id, size, src).await?;
+        self.end_file(id).await
+    }
+
+    pub async fn finalize(&mut self) -> Result<WriteStatistics, Error> {
+        let statistics = self.archive.finalize()?;
+        self.write_pending().await?;
+        self.dest.flush().await?;
+        Ok(statistics)
+    }
+
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        self.archive.flush()?;
+        self.write_pending().await?;
+        self.dest.flush().await?;
+        Ok(())
+    }
+
+    pub fn into_raw(self) -> W {
+        self.dest
+    }
+
+    /// Abandon the archive being written, see `ArchiveWriter::abort`
+    pub fn abort(self) -> W {
+        self.dest
+    }
+}
+
+// -------- Source cache --------
+
+struct Page {
+    data: Vec<u8>,
+    /// Last operation using this page
+    last_use: u64,
+}
+
+struct SourceCache {
+    /// Page index -> cached page
+    pages: BTreeMap<u64, Page>,
+    /// Total size of cached data
+    cached: u64,
+    /// Size of the source
+    len: u64,
+    /// Current position in the source
+    position: u64,
+    /// Current operation. Pages used by the current operation are never evicted
+    operation: u64,
+    /// Position of the missing data which interrupted the last operation, if any
+    missing: Option<u64>,
+}
+
+impl SourceCache {
+    /// Returns the range (start, length) to fetch to get `position` in cache
+    fn range_to_fetch(&self, position: u64) -> (u64, usize) {
+        let first = position / PAGE_SIZE;
+        let pages_count = self.len.div_ceil(PAGE_SIZE);
+        let mut end = first + 1;
+        while end < pages_count
+            && (end - first) * PAGE_SIZE < READAHEAD_SIZE
+            && !self.pages.contains_key(&end)
+        {
+            end += 1;
+        }
+        let start = first * PAGE_SIZE;
+        let stop = std::cmp::min(end * PAGE_SIZE, self.len);
+        (start, (stop - start) as usize)
+    }
+
+    /// Insert `data`, fetched from `start`, which must be a page boundary
+    fn insert(&mut self, start: u64, data: &[u8]) {
+        for (i, chunk) in data.chunks(PAGE_SIZE as usize).enumerate() {
+            let page = Page {
+                data: chunk.to_vec(),
+                last_use: self.operation,
+            };
+            self.cached += page.data.len() as u64;
+            if let Some(old) = self.pages.insert(start / PAGE_SIZE + i as u64, page) {
+                self.cached -= old.data.len() as u64;
+            }
+        }
+
+        // Evict the least recently used pages, if needed
+        while self.cached > CACHE_MAX_SIZE {
+            let candidate = self
+                .pages
+                .iter()
+                .filter(|(_, page)| page.last_use < self.operation)
+                .min_by_key(|(_, page)| page.last_use)
+                .map(|(index, _)| *index);
+            match candidate {
+                Some(index) => {
+                    if let Some(page) = self.pages.remove(&index) {
+                        self.cached -= page.data.len() as u64;
+                    }
+                }
+                // Everything is needed by the current operation
+                None => break,
+            }
+        }
+    }
+}
+
+/// Blocking `Read + Seek` view on the cached part of an async source
+///
+/// Reading a missing part fails, and records the position of that part
+#[derive(Clone)]
+pub struct CachedSource(Rc<RefCell<SourceCache>>);
+
+impl CachedSource {
+    fn new(len: u64) -> Self {
+        CachedSource(Rc::new(RefCell::new(SourceCache {
+            pages: BTreeMap::new(),
+            cached: 0,
+            len,
+            position: 0,
+            operation: 0,
+            missing: None,
+        })))
+    }
+
+    /// Start a new operation, whose pages can't be evicted
+    fn begin_operation(&self) {
+        let mut cache = self.0.borrow_mut();
+        cache.operation += 1;
+        cache.missing = None;
+    }
+
+    fn take_missing(&self) -> Option<u64> {
+        self.0.borrow_mut().missing.take()
+    }
+}
+
+impl Read for CachedSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let cache = &mut *self.0.borrow_mut();
+        if cache.position >= cache.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let page = match cache.pages.get_mut(&(cache.position / PAGE_SIZE)) {
+            Some(page) => page,
+            None => {
+                cache.missing = Some(cache.position);
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "Data not fetched yet from the source",
+                ));
+            }
+        };
+        page.last_use = cache.operation;
+        let offset = (cache.position % PAGE_SIZE) as usize;
+        let count = std::cmp::min(buf.len(), page.data.len() - offset);
+        buf[..count].copy_from_slice(&page.data[offset..offset + count]);
+        cache.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl Seek for CachedSource {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let mut cache = self.0.borrow_mut();
+        let position = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(pos) => cache.position.checked_add_signed(pos),
+            SeekFrom::End(pos) => cache.len.checked_add_signed(pos),
+        };
+        match position {
+            Some(position) => {
+                cache.position = position;
+                Ok(position)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+/// Non-blocking retrieval of a part of the source into the cache
+enum Fetch {
+    Idle,
+    Seek {
+        start: u64,
+        length: usize,
+    },
+    WaitSeek {
+        start: u64,
+        length: usize,
+    },
+    Read {
+        start: u64,
+        data: Vec<u8>,
+        filled: usize,
+    },
+}
+
+impl Fetch {
+    /// Prepare the retrieval of the data at `position`, and the following one
+    fn new(position: u64, cache: &CachedSource) -> Self {
+        let (start, length) = cache.0.borrow().range_to_fetch(position);
+        Fetch::Seek { start, length }
+    }
+
+    fn poll<R: AsyncRead + AsyncSeek + Unpin>(
+        &mut self,
+        cx: &mut Context<'_>,
+        src: &mut R,
+        cache: &CachedSource,
+    ) -> Poll<io::Result<()>> {
+        let result = self.poll_inner(cx, src, cache);
+        if let Poll::Ready(Err(_)) = result {
+            *self = Fetch::Idle;
+        }
+        result
+    }
+
+    fn poll_inner<R: AsyncRead + AsyncSeek + Unpin>(
+        &mut self,
+        cx: &mut Context<'_>,
+        src: &mut R,
+        cache: &CachedSource,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            match self {
+                Fetch::Idle => return Poll::Ready(Ok(())),
+                Fetch::Seek { start, length } => {
+                    let (start, length) = (*start, *length);
+                    Pin::new(&mut *src).start_seek(SeekFrom::Start(start))?;
+                    *self = Fetch::WaitSeek { start, length };
+                }
+                Fetch::WaitSeek { start, length } => {
+                    let (start, length) = (*start, *length);
+                    ready!(Pin::new(&mut *src).poll_complete(cx))?;
+                    *self = Fetch::Read {
+                        start,
+                        data: vec![0u8; length],
+                        filled: 0,
+                    };
+                }
+                Fetch::Read {
+                    start,
+                    data,
+                    filled,
+                } => {
+                    while *filled < data.len() {
+                        let mut buf = ReadBuf::new(&mut data[*filled..]);
+                        ready!(Pin::new(&mut *src).poll_read(cx, &mut buf))?;
+                        if buf.filled().is_empty() {
+                            return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                        }
+                        *filled += buf.filled().len();
+                    }
+                    cache.0.borrow_mut().insert(*start, data);
+                    *self = Fetch::Idle;
+                }
+            }
+        }
+    }
+}
+
+/// If an operation failed with `err` because of missing data, fetch it.
+/// Otherwise, returns `err`
+async fn fetch_missing<R: AsyncRead + AsyncSeek + Unpin>(
+    src: &mut R,
+    cache: &CachedSource,
+    err: Error,
+) -> Result<(), Error> {
+    match cache.take_missing() {
+        Some(position) => {
+            let mut fetch = Fetch::new(position, cache);
+            poll_fn(|cx| fetch.poll(cx, src, cache)).await?;
+            Ok(())
+        }
+        None => Err(err),
+    }
+}
+
+/// Run `op` on a cached view of `src` positioned at 0, until it succeeds,
+/// fetching the data it needs meanwhile
+async fn run_from_start<R: AsyncRead + AsyncSeek + Unpin, T>(
+    src: &mut R,
+    cache: &CachedSource,
+    mut op: impl FnMut(CachedSource) -> Result<T, Error>,
+) -> Result<T, Error> {
+    cache.begin_operation();
+    loop {
+        let mut cached_src = cache.clone();
+        let result = cached_src
+            .seek(SeekFrom::Start(0))
+            .map_err(Error::from)
+            .and_then(|_| op(cached_src));
+        match result {
+            Ok(value) => return Ok(value),
+            Err(err) => fetch_missing(src, cache, err).await?,
+        }
+    }
+}
+
+/// Check the signature of the whole archive `src`, of size `len`
+///
+/// `src` is read once, without being cached: the signed data is
+/// `[header][data]`, followed by the signature
+async fn check_signature<R: AsyncRead + AsyncSeek + Unpin>(
+    src: &mut R,
+    len: u64,
+    verifying_key: &VerifyingKey,
+) -> Result<(), Error> {
+    let signed_len = len
+        .checked_sub(SIGNATURE_LENGTH as u64)
+        .ok_or(Error::SignatureVerificationFailed)?;
+    src.seek(SeekFrom::Start(0)).await?;
+    let mut hash = Sha256::new();
+    let mut signed = src.take(signed_len);
+    let mut buf = vec![0u8; BUFFER_SIZE];
+    loop {
+        let read = signed.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        hash.update(&buf[..read]);
+    }
+    if signed.limit() != 0 {
+        return Err(Error::SignatureVerificationFailed);
+    }
+    let mut signature = [0u8; SIGNATURE_LENGTH];
+    src.read_exact(&mut signature).await?;
+    verify_signature(verifying_key, hash, &signature)
+}
+
+// -------- Reader --------
+
+/// Asynchronous counterpart of `ArchiveFile`
+#[derive(Debug)]
+pub struct AsyncArchiveFile<T: AsyncRead> {
+    /// File inside a MLA Archive
+    pub filename: String,
+    pub data: T,
+    pub size: u64,
+    /// Metadata of the file, if stored in the archive
+    pub metadata: Option<FileMetadata>,
+}
+
+/// Asynchronous counterpart of `ArchiveReader`
+pub struct AsyncArchiveReader<'a, R: AsyncRead + AsyncSeek + Unpin> {
+    /// Real source
+    src: R,
+    /// Cache of `src`, used by `archive`
+    cache: CachedSource,
+    /// Synchronous reader, over the cache
+    archive: ArchiveReader<'a, CachedSource>,
+}
+
+impl<'a, R: AsyncRead + AsyncSeek + Unpin> AsyncArchiveReader<'a, R> {
+    pub async fn from_config(mut src: R, mut config: ArchiveReaderConfig) -> Result<Self, Error> {
+        let len = src.seek(SeekFrom::End(0)).await?;
+        let cache = CachedSource::new(len);
+        // The signature covers the whole archive: check it in one pass over
+        // `src`, rather than through the cache, which would then hold it all
+        if let Some(verifying_key) = config.take_verifying_key() {
+            let layers = run_from_start(&mut src, &cache, |mut cached_src| {
+                Ok(ArchiveHeader::from(&mut cached_src)?.config.layers_enabled)
+            })
+            .await?;
+            if !layers.contains(Layers::SIGN) {
+                return Err(Error::MissingSignature);
+            }
+            check_signature(&mut src, len, &verifying_key).await?;
+        }
+        let archive = run_from_start(&mut src, &cache, |cached_src| {
+            ArchiveReader::from_config(cached_src, config.clone())
+        })
+        .await?;
+        Ok(AsyncArchiveReader {
+            src,
+            cache,
+            archive,
+        })
+    }
+
+    pub async fn new(src: R) -> Result<Self, Error> {
+        Self::from_config(src, ArchiveReaderConfig::new()).await
+    }
+
+    /// Report the progress of files read with `get_file` to `reporter`, see
+    /// `ArchiveReader::with_progress`
+    pub fn with_progress(&mut self, reporter: Box<dyn ReadProgressReporter>) -> &mut Self {
+        self.archive.with_progress(reporter);
+        self
+    }
+
+    /// Run `op` until it succeeds, fetching the data it needs meanwhile
+    ///
+    /// If `position` is provided, the archive source is moved to it before
+    /// each new attempt
+    async fn run<T>(
+        &mut self,
+        position: Option<u64>,
+        mut op: impl FnMut(&mut ArchiveReader<'a, CachedSource>) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        self.cache.begin_operation();
+        let mut retry = false;
+        loop {
+            let result = match position {
+                Some(position) if retry => self
+                    .archive
+                    .src
+                    .seek(SeekFrom::Start(position))
+                    .map_err(Error::from)
+                    .and_then(|_| op(&mut self.archive)),
+                _ => op(&mut self.archive),
+            };
+            match result {
+                Ok(value) => return Ok(value),
+                Err(err) => fetch_missing(&mut self.src, &self.cache, err).await?,
+            }
+            retry = true;
+        }
+    }
+
+    /// Return an iterator on filenames present in the archive
+    ///
+    /// Order is not relevant, and may change
+    pub fn list_files(&self) -> Result<impl Iterator<Item = &String>, Error> {
+        self.archive.list_files()
+    }
+
+    pub async fn get_hash(&mut self, filename: &str) -> Result<Option<Sha256Hash>, Error> {
+        self.run(None, |archive| archive.get_hash(filename)).await
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub async fn get_file(
+        &mut self,
+        filename: impl AsRef<str>,
+    ) -> Result<Option<AsyncArchiveFile<AsyncBlocksToFileReader<'_, 'a, R>>>, Error> {
+        let filename = filename.as_ref();
+        // Fetch the data needed to open the file. It will then stay in cache
+        // until the next operation. The access is reported once, when the
+        // file is actually opened
+        let found = self
+            .run(None, |archive| archive.prepare_file(filename))
+            .await?;
+        if !found {
+            return Ok(None);
+        }
+
+        let file = match self.archive.get_file(filename)? {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+        let mut reader = file.data;
+        let checkpoint = reader.checkpoint()?;
+        Ok(Some(AsyncArchiveFile {
+            filename: file.filename,
+            data: AsyncBlocksToFileReader {
+                reader,
+                src: &mut self.src,
+                cache: self.cache.clone(),
+                checkpoint,
+                fetch: Fetch::Idle,
+                resuming: false,
+            },
+            size: file.size,
+            metadata: file.metadata,
+        }))
+    }
+
+    /// Current position in the archive source
+    fn position(&mut self) -> Result<u64, Error> {
+        Ok(self.archive.src.stream_position()?)
+    }
+}
+
+/// Asynchronous counterpart of `BlocksToFileReader`
+pub struct AsyncBlocksToFileReader<'b, 'a, R: AsyncRead + AsyncSeek + Unpin> {
+    reader: BlocksToFileReader<'b, Box<dyn 'a + LayerReader<'a, CachedSource>>>,
+    /// Real source, to fetch missing data
+    src: &'b mut R,
+    cache: CachedSource,
+    /// Position after the last successful read
+    checkpoint: BlocksToFileReaderCheckpoint,
+    fetch: Fetch,
+    /// The last read has been interrupted, and must be resumed
+    resuming: bool,
+}
+
+impl<'b, 'a, R: AsyncRead + AsyncSeek + Unpin> AsyncBlocksToFileReader<'b, 'a, R> {
+    fn read_once(&mut self, into: &mut [u8]) -> Result<usize, Error> {
+        if self.resuming {
+            self.reader.restore(&self.checkpoint)?;
+        }
+        let count = self.reader.read(into)?;
+        self.checkpoint = self.reader.checkpoint()?;
+        Ok(count)
+    }
+}
+
+impl<'b, 'a, R: AsyncRead + AsyncSeek + Unpin> AsyncRead for AsyncBlocksToFileReader<'b, 'a, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.resuming {
+            this.cache.begin_operation();
+        }
+        loop {
+            if this.resuming {
+                ready!(this.fetch.poll(cx, this.src, &this.cache))?;
+            }
+            match this.read_once(buf.initialize_unfilled()) {
+                Ok(count) => {
+                    buf.advance(count);
+                    this.resuming = false;
+                    return Poll::Ready(Ok(()));
+                }
+                Err(err) => match this.cache.take_missing() {
+                    Some(position) => {
+                        this.fetch = Fetch::new(position, &this.cache);
+                        this.resuming = true;
+                    }
+                    None => {
+                        this.resuming = false;
+                        return Poll::Ready(Err(err.into()));
+                    }
+                },
+            }
+        }
+    }
+}
+
+// -------- Helpers --------
+
+/// Asynchronous counterpart of `helpers::linear_extract`
+///
+/// `export` maps filenames to AsyncWrite objects, which will receives the
+/// corresponding file's content. If a file is in the archive but not in
+/// `export`, this file will be silently ignored.
+pub async fn async_linear_extract<
+    W: AsyncWrite + Unpin,
+    R: AsyncRead + AsyncSeek + Unpin,
+    S: BuildHasher,
+>(
+    archive: &mut AsyncArchiveReader<'_, R>,
+    export: &mut HashMap<&String, W, S>,
+) -> Result<(), Error> {
+    // Seek at the beginning
+    archive
+        .run(None, |archive| Ok(archive.src.seek(SeekFrom::Start(0))?))
+        .await?;
+    let mut position = 0;
+
+    // Associate an ID in the archive to the corresponding filename
+    let mut id2filename: HashMap<ArchiveFileID, String> = HashMap::new();
+    // Files whose content is the one of a file written before, or is
+    // encrypted for their own recipients, extracted once the linear scan is
+    // done
+    let mut deferred: Vec<String> = Vec::new();
+    let mut buf = vec![0u8; BUFFER_SIZE];
+
+    loop {
+        let block = archive
+            .run(Some(position), |archive| {
+                ArchiveFileBlock::from(&mut archive.src)
+            })
+            .await?;
+        position = archive.position()?;
+        match block {
+            ArchiveFileBlock::FileStart { filename, id } => {
+                if export.contains_key(&filename) {
+                    id2filename.insert(id, filename);
+                }
+            }
+            ArchiveFileBlock::FileChecksum { .. }
+            | ArchiveFileBlock::FileMetadata { .. }
+            | ArchiveFileBlock::FileSizeHint { .. }
+            | ArchiveFileBlock::UserMetadata { .. }
+            | ArchiveFileBlock::DirectoryEntry { .. }
+            | ArchiveFileBlock::Symlink { .. }
+            | ArchiveFileBlock::Padding { .. } => {}
+            ArchiveFileBlock::Unknown { kind, .. } => {
+                check_unknown_block(kind, archive.archive.config.skip_unknown_blocks)?;
+            }
+            ArchiveFileBlock::Dedup { id, .. } => {
+                if let Some(fname) = id2filename.get(&id) {
+                    deferred.push(fname.clone());
+                }
+            }
+            ArchiveFileBlock::FileRecipients { id, .. } => {
+                // Its content blocks, encrypted, are ignored from now on
+                if let Some(fname) = id2filename.remove(&id) {
+                    deferred.push(fname);
+                }
+            }
+            ArchiveFileBlock::FileAlias { new_name, .. } => {
+                if export.contains_key(&new_name) {
+                    deferred.push(new_name);
+                }
+            }
+            ArchiveFileBlock::FileTag { .. } => {}
+            ArchiveFileBlock::EndOfFile { id, .. } => {
+                if let Some(fname) = id2filename.remove(&id) {
+                    if let Some(writer) = export.get_mut(&fname) {
+                        writer.flush().await?;
+                    }
+                }
+            }
+            ArchiveFileBlock::FileSparseHole { id, length } => {
+                if let Some(writer) = id2filename.get(&id).and_then(|fname| export.get_mut(fname)) {
+                    tokio::io::copy(&mut tokio::io::repeat(0).take(length), writer).await?;
+                }
+            }
+            ArchiveFileBlock::FilePrecompressedContent {
+                id,
+                algorithm,
+                uncompressed_length,
+                data,
+            } => {
+                if let Some(writer) = id2filename.get(&id).and_then(|fname| export.get_mut(fname)) {
+                    let content = decompress_block_content(algorithm, &data, uncompressed_length)?;
+                    writer.write_all(&content).await?;
+                }
+            }
+            ArchiveFileBlock::FileContent { length, id, .. } => {
+                let mut remaining = length;
+                while remaining > 0 {
+                    let size = std::cmp::min(remaining, buf.len() as u64) as usize;
+                    let count = archive
+                        .run(Some(position), |archive| {
+                            Ok(archive.src.read(&mut buf[..size])?)
+                        })
+                        .await?;
+                    if count == 0 {
+                        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                    }
+                    position += count as u64;
+                    remaining -= count as u64;
+                    // Is the file considered?
+                    if let Some(writer) =
+                        id2filename.get(&id).and_then(|fname| export.get_mut(fname))
+                    {
+                        writer.write_all(&buf[..count]).await?;
+                    }
+                }
+            }
+            ArchiveFileBlock::EndOfArchiveData => {
+                // Proper termination
+                break;
+            }
+        }
+    }
+
+    for fname in deferred {
+        if let Some(mut file) = archive.get_file(&fname).await? {
+            if let Some(writer) = export.get_mut(&fname) {
+                tokio::io::copy(&mut file.data, writer).await?;
+                writer.flush().await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{AuditEvent, AuditEventType, AuditLogger};
+    use crate::tests::build_archive;
+    use crate::Layers;
+    use ed25519_dalek::SigningKey;
+    use rand::{RngCore, SeedableRng};
+    use rand_chacha::ChaChaRng;
+    use std::io::Cursor;
+    use std::sync::{Arc, Mutex};
+    use x25519_dalek::StaticSecret;
+
+    /// Write `files` with an `AsyncArchiveWriter` on one end of a duplex, and
+    /// return the archive read from the other end
+    ///
+    /// If `interleaved`, files contents are written in two parts, interleaved
+    async fn write_archive(
+        layers: Layers,
+        files: &[(String, Vec<u8>)],
+        interleaved: bool,
+    ) -> (Vec<u8>, StaticSecret) {
+        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(layers)
+            .add_public_keys(&[PublicKey::from(&key)]);
+
+        // Use a small duplex buffer, to force the writer to wait for the reader
+        let (dest, mut output) = tokio::io::duplex(4096);
+        let write = async move {
+            let mut mla = AsyncArchiveWriter::from_config(dest, config).await.unwrap();
+            if interleaved {
+                let mut ids = Vec::new();
+                for (fname, _) in files {
+                    ids.push(mla.start_file(fname).await.unwrap());
+                }
+                for part in 0..2 {
+                    for (id, (_, content)) in ids.iter().zip(files) {
+                        let part =
+                            &content[part * content.len() / 2..(part + 1) * content.len() / 2];
+                        mla.append_file_content(*id, part.len() as u64, part)
+                            .await
+                            .unwrap();
+                    }
+                }
+                for id in ids {
+                    mla.end_file(id).await.unwrap();
+                }
+            } else {
+                for (fname, content) in files {
+                    mla.add_file(fname, content.len() as u64, content.as_slice())
+                        .await
+                        .unwrap();
+                }
+            }
+            mla.finalize().await.unwrap();
+            // Dropping the duplex end ends the stream
+            mla.into_raw();
+        };
+        let mut archive = Vec::new();
+        let (_, read) = tokio::join!(write, output.read_to_end(&mut archive));
+        read.unwrap();
+        (archive, key)
+    }
+
+    fn reader_config(key: &StaticSecret) -> ArchiveReaderConfig {
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(key));
+        config
+    }
+
+    fn big_files() -> Vec<(String, Vec<u8>)> {
+        // Several compressed blocks and more than the readahead size
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let mut big = vec![0u8; 9 * 1024 * 1024];
+        rng.fill_bytes(&mut big);
+        vec![
+            ("big".to_string(), big),
+            ("small".to_string(), vec![1, 2, 3, 4]),
+        ]
+    }
+
+    #[tokio::test]
+    async fn async_writer() {
+        let files = vec![
+            ("my_file1".to_string(), vec![1, 2, 3, 4, 5, 6, 7, 8]),
+            ("my_file2".to_string(), vec![9, 10, 11, 12]),
+            ("my_file3".to_string(), Vec::new()),
+        ];
+        for layers in &[
+            Layers::DEFAULT,
+            Layers::ENCRYPT,
+            Layers::COMPRESS,
+            Layers::EMPTY,
+        ] {
+            for interleaved in &[false, true] {
+                let (archive, key) = write_archive(*layers, &files, *interleaved).await;
+
+                // Read it back with the synchronous API
+                let mut mla_read =
+                    ArchiveReader::from_config(Cursor::new(archive), reader_config(&key)).unwrap();
+                assert_eq!(mla_read.list_files().unwrap().count(), files.len());
+                for (fname, content) in &files {
+                    let mut file = mla_read.get_file(fname.clone()).unwrap().unwrap();
+                    let mut data = Vec::new();
+                    file.data.read_to_end(&mut data).unwrap();
+                    assert_eq!(&data, content);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn async_reader() {
+        for interleaved in &[false, true] {
+            let (mla, key, files) = build_archive(None, *interleaved);
+            let archive = mla.into_raw();
+            let mut sync_read =
+                ArchiveReader::from_config(Cursor::new(archive.clone()), reader_config(&key))
+                    .unwrap();
+
+            let mut mla_read =
+                AsyncArchiveReader::from_config(Cursor::new(archive), reader_config(&key))
+                    .await
+                    .unwrap();
+            let mut fnames: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+            fnames.sort();
+            assert_eq!(
+                fnames,
+                files
+                    .iter()
+                    .map(|(fname, _)| fname.clone())
+                    .collect::<Vec<_>>()
+            );
+            for (fname, content) in &files {
+                let mut file = mla_read.get_file(fname.clone()).await.unwrap().unwrap();
+                assert_eq!(&file.filename, fname);
+                assert_eq!(file.size, content.len() as u64);
+                let mut data = Vec::new();
+                file.data.read_to_end(&mut data).await.unwrap();
+                assert_eq!(&data, content);
+
+                assert_eq!(
+                    mla_read.get_hash(fname).await.unwrap(),
+                    sync_read.get_hash(fname).unwrap()
+                );
+            }
+            assert!(mla_read
+                .get_file("unknown".to_string())
+                .await
+                .unwrap()
+                .is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn async_linear_extract_all() {
+        for interleaved in &[false, true] {
+            let (mla, key, files) = build_archive(None, *interleaved);
+            let mut mla_read =
+                AsyncArchiveReader::from_config(Cursor::new(mla.into_raw()), reader_config(&key))
+                    .await
+                    .unwrap();
+
+            let fnames: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+            let mut export: HashMap<&String, Vec<u8>> =
+                fnames.iter().map(|fname| (fname, Vec::new())).collect();
+            async_linear_extract(&mut mla_read, &mut export)
+                .await
+                .unwrap();
+
+            for (fname, content) in files.iter() {
+                assert_eq!(export.get(fname).unwrap(), content);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn async_big_files() {
+        // Data is missing from the cache several times during each operation
+        let files = big_files();
+        for layers in &[Layers::DEFAULT, Layers::ENCRYPT, Layers::EMPTY] {
+            let (archive, key) = write_archive(*layers, &files, true).await;
+
+            let mut mla_read =
+                AsyncArchiveReader::from_config(Cursor::new(archive), reader_config(&key))
+                    .await
+                    .unwrap();
+            for (fname, content) in &files {
+                let mut file = mla_read.get_file(fname.clone()).await.unwrap().unwrap();
+                let mut data = Vec::new();
+                file.data.read_to_end(&mut data).await.unwrap();
+                assert_eq!(&data, content);
+            }
+
+            let fnames: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+            let mut export: HashMap<&String, Vec<u8>> =
+                fnames.iter().map(|fname| (fname, Vec::new())).collect();
+            async_linear_extract(&mut mla_read, &mut export)
+                .await
+                .unwrap();
+            for (fname, content) in &files {
+                assert_eq!(export.get(fname).unwrap(), content);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn async_signed_archive() {
+        let signing_key = SigningKey::from_bytes(&[1; 32]);
+        let other_key = SigningKey::from_bytes(&[2; 32]);
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let files = big_files();
+
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .add_public_key(&PublicKey::from(&key))
+            .sign_with(&signing_key);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        for (fname, content) in &files {
+            mla.add_file(fname, content.len() as u64, content.as_slice())
+                .unwrap();
+        }
+        mla.finalize().unwrap();
+        let archive = mla.into_raw();
+
+        let open = |data: Vec<u8>, verifying_key: &VerifyingKey| {
+            let mut config = reader_config(&key);
+            config.verify_with(verifying_key);
+            AsyncArchiveReader::from_config(Cursor::new(data), config)
+        };
+
+        // The archive is hashed while opening, but not kept in the cache
+        let mut mla_read = open(archive.clone(), &signing_key.verifying_key())
+            .await
+            .unwrap();
+        assert!(mla_read.cache.0.borrow().cached < archive.len() as u64 / 2);
+        for (fname, content) in &files {
+            let mut file = mla_read.get_file(fname).await.unwrap().unwrap();
+            let mut data = Vec::new();
+            file.data.read_to_end(&mut data).await.unwrap();
+            assert_eq!(&data, content);
+        }
+
+        // Wrong key
+        assert!(matches!(
+            open(archive.clone(), &other_key.verifying_key()).await,
+            Err(Error::SignatureVerificationFailed)
+        ));
+
+        // Modified data
+        let mut modified = archive.clone();
+        let middle = modified.len() / 2;
+        modified[middle] ^= 1;
+        assert!(matches!(
+            open(modified, &signing_key.verifying_key()).await,
+            Err(Error::SignatureVerificationFailed)
+        ));
+
+        // Unsigned archive
+        let (unsigned, _) = write_archive(Layers::DEFAULT, &files[1..], false).await;
+        assert!(matches!(
+            open(unsigned, &signing_key.verifying_key()).await,
+            Err(Error::MissingSignature)
+        ));
+    }
+
+    #[tokio::test]
+    async fn async_audit_log() {
+        struct RecordingLogger(Arc<Mutex<Vec<AuditEventType>>>);
+
+        impl AuditLogger for RecordingLogger {
+            fn log(&mut self, event: AuditEvent) {
+                self.0.lock().unwrap().push(event.event_type);
+            }
+        }
+
+        // Data is missing from the cache while opening the files
+        let files = big_files();
+        let (archive, key) = write_archive(Layers::DEFAULT, &files, true).await;
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut config = reader_config(&key);
+        config.enable_audit_log(Box::new(RecordingLogger(events.clone())));
+        let mut mla_read = AsyncArchiveReader::from_config(Cursor::new(archive), config)
+            .await
+            .unwrap();
+
+        // Each `get_file` is reported once
+        for (fname, content) in &files {
+            let mut file = mla_read.get_file(fname).await.unwrap().unwrap();
+            let mut data = Vec::new();
+            file.data.read_to_end(&mut data).await.unwrap();
+            assert_eq!(&data, content);
+        }
+        assert!(mla_read.get_file("unknown").await.unwrap().is_none());
+        let expected: Vec<AuditEventType> = files
+            .iter()
+            .map(|(fname, content)| AuditEventType::FileContentAccess {
+                filename: fname.clone(),
+                bytes_read: content.len() as u64,
+            })
+            .collect();
+        assert_eq!(*events.lock().unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn async_dedup() {
+        let content = vec![3u8; 100_000];
+        let key = StaticSecret::from([2; 32]);
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::DEFAULT)
+            .add_public_keys(&[PublicKey::from(&key)])
+            .with_dedup(true);
+        let mut mla = crate::ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let fnames: Vec<String> = (0..3).map(|i| format!("copy_{}", i)).collect();
+        for fname in &fnames {
+            mla.add_file(fname, content.len() as u64, content.as_slice())
+                .unwrap();
+        }
+        mla.finalize().unwrap();
+
+        let mut mla_read =
+            AsyncArchiveReader::from_config(Cursor::new(mla.into_raw()), reader_config(&key))
+                .await
+                .unwrap();
+        for fname in &fnames {
+            let mut file = mla_read.get_file(fname).await.unwrap().unwrap();
+            let mut data = Vec::new();
+            file.data.read_to_end(&mut data).await.unwrap();
+            assert_eq!(data, content);
+        }
+        let mut export: HashMap<&String, Vec<u8>> =
+            fnames.iter().map(|fname| (fname, Vec::new())).collect();
+        async_linear_extract(&mut mla_read, &mut export)
+            .await
+            .unwrap();
+        for fname in &fnames {
+            assert_eq!(export.get(fname).unwrap(), &content);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::ARCHIVE_ID_SIZE;
+use std::time::SystemTime;
+
+/// Kind of access to an archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditEventType {
+    /// The list of files has been accessed
+    FileListAccess,
+    /// The content of file `filename` has been accessed, `bytes_read` bytes
+    /// being provided
+    FileContentAccess { filename: String, bytes_read: u64 },
+}
+
+/// Access to an archive, as reported to an `AuditLogger`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEvent {
+    pub event_type: AuditEventType,
+    /// Time of the access
+    pub timestamp: SystemTime,
+    /// Identifier of the accessed archive, see `ArchiveReader::archive_id`.
+    /// Zeroes for archives older than format v6, which have none
+    pub archive_id: [u8; ARCHIVE_ID_SIZE],
+}
+
+/// Receiver of the accesses to an archive, for instance to keep an audit
+/// trail of the files read
+///
+/// Install one with `ArchiveReaderConfig::enable_audit_log`. Events are
+/// reported by `ArchiveReader::list_files`, `list_files_with_sizes` and
+/// `get_file` (one event per file found, with its size), by
+/// `helpers::linear_extract` and its variants (one event per extracted file,
+/// once ended), and by `ConcurrentArchiveReader`. The logger is shared by
+/// the clones of the configuration
+pub trait AuditLogger: Send {
+    fn log(&mut self, event: AuditEvent);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ArchiveReaderConfig;
+    use crate::helpers::linear_extract;
+    use crate::tests::build_archive;
+    use crate::ArchiveReader;
+    use std::collections::HashMap;
+    use std::io::{Cursor, Read};
+    use std::sync::{Arc, Mutex};
+
+    struct RecordingLogger(Arc<Mutex<Vec<AuditEvent>>>);
+
+    impl AuditLogger for RecordingLogger {
+        fn log(&mut self, event: AuditEvent) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    #[test]
+    fn audit_log() {
+        let (mla, key, files) = build_archive(None, true);
+        let archive = mla.into_raw();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut config = ArchiveReaderConfig::new();
+        config
+            .add_private_keys(std::slice::from_ref(&key))
+            .enable_audit_log(Box::new(RecordingLogger(events.clone())));
+        let start = SystemTime::now();
+        let mut mla_read = ArchiveReader::from_config(Cursor::new(archive), config).unwrap();
+        let archive_id = mla_read.archive_id().unwrap();
+        assert!(events.lock().unwrap().is_empty());
+
+        assert_eq!(mla_read.list_files().unwrap().count(), files.len());
+        {
+            let events = events.lock().unwrap();
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].event_type, AuditEventType::FileListAccess);
+            assert_eq!(events[0].archive_id, archive_id);
+            assert!(events[0].timestamp >= start);
+        }
+
+        // Each `get_file` is reported once, with the size of the file
+        events.lock().unwrap().clear();
+        for (fname, content) in &files {
+            let mut file = mla_read.get_file(fname).unwrap().unwrap();
+            let mut data = Vec::new();
+            file.data.read_to_end(&mut data).unwrap();
+            assert_eq!(&data, content);
+        }
+        assert!(mla_read.get_file("unknown").unwrap().is_none());
+        let expected: Vec<AuditEventType> = files
+            .iter()
+            .map(|(fname, content)| AuditEventType::FileContentAccess {
+                filename: fname.clone(),
+                bytes_read: content.len() as u64,
+            })
+            .collect();
+        {
+            let events = events.lock().unwrap();
+            assert_eq!(
+                events
+                    .iter()
+                    .map(|event| event.event_type.clone())
+                    .collect::<Vec<_>>(),
+                expected
+            );
+            assert!(events.iter().all(|event| event.archive_id == archive_id));
+            assert!(events
+                .windows(2)
+                .all(|pair| pair[0].timestamp <= pair[1].timestamp));
+        }
+
+        // `linear_extract` reports each extracted file
+        events.lock().unwrap().clear();
+        let mut export: HashMap<&String, Vec<u8>> =
+            files.iter().map(|(fname, _)| (fname, Vec::new())).collect();
+        linear_extract(&mut mla_read, &mut export).unwrap();
+        let mut reported: Vec<AuditEventType> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|event| event.event_type.clone())
+            .collect();
+        let mut expected = expected;
+        let key = |event: &AuditEventType| format!("{:?}", event);
+        reported.sort_by_key(key);
+        expected.sort_by_key(key);
+        assert_eq!(reported, expected);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::errors::Error;
+use crate::ArchiveReader;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use sha2::{Digest, Sha256};
+use std::convert::TryInto;
+use std::io::{Read, Seek};
+
+/// Bloom filter on file names, to know if a file is in an archive without
+/// opening it, see `ArchiveReader::build_bloom_filter`
+///
+/// `probably_contains` never misses a name inserted, but might wrongly report
+/// a name as present, with the false positive rate the filter was built for.
+/// The filter can be saved with `to_bytes`, for instance alongside the
+/// archive, and loaded back with `from_bytes`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    /// Number of bit positions checked per name
+    num_hashes: u32,
+    /// Number of bits of the filter
+    num_bits: u64,
+    bits: Vec<u64>,
+}
+
+impl BloomFilter {
+    /// Create an empty filter, sized for `expected_items` names with a false
+    /// positive rate of `false_positive_rate`, in `]0, 1[`
+    pub fn new(expected_items: usize, false_positive_rate: f64) -> Result<Self, Error> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(Error::BadAPIArgument(
+                "[BloomFilter] The false positive rate must be in ]0, 1[".to_string(),
+            ));
+        }
+        let items = std::cmp::max(expected_items, 1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        // Optimal sizes: m = -n.ln(p) / ln(2)^2 bits, and k = m / n.ln(2)
+        let num_bits = (-items * false_positive_rate.ln() / (ln2 * ln2))
+            .ceil()
+            .max(64.0);
+        let num_hashes = std::cmp::max((num_bits / items * ln2).round() as u32, 1);
+        let num_bits = num_bits as u64;
+        Ok(BloomFilter {
+            num_hashes,
+            num_bits,
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+        })
+    }
+
+    /// Positions of the bits corresponding to `name`
+    ///
+    /// Derived from a SHA-256 of `name` with double hashing, so that they are
+    /// stable across platforms and versions, for saved filters
+    fn positions(&self, name: &str) -> impl Iterator<Item = u64> {
+        let digest = Sha256::digest(name.as_bytes());
+        let h1 = u64::from_le_bytes(digest[..8].try_into().unwrap());
+        // Odd, to reach every position
+        let h2 = u64::from_le_bytes(digest[8..16].try_into().unwrap()) | 1;
+        let num_bits = self.num_bits;
+        (0..u64::from(self.num_hashes)).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+
+    /// Add `name` to the filter
+    pub fn insert(&mut self, name: &str) {
+        let positions: Vec<u64> = self.positions(name).collect();
+        for position in positions {
+            self.bits[(position / 64) as usize] |= 1 << (position % 64);
+        }
+    }
+
+    /// Whether `name` has probably been inserted. `false` is always right
+    pub fn probably_contains(&self, name: &str) -> bool {
+        self.positions(name)
+            .all(|position| self.bits[(position / 64) as usize] & (1 << (position % 64)) != 0)
+    }
+
+    /// Serialize the filter, to be loaded with `from_bytes`
+    ///
+    /// Layout: `num_hashes` (u32), `num_bits` (u64), then the bits as u64
+    /// words, all little endian
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(12 + self.bits.len() * 8);
+        // Writing to a Vec never fails
+        data.write_u32::<LittleEndian>(self.num_hashes).unwrap();
+        data.write_u64::<LittleEndian>(self.num_bits).unwrap();
+        for word in &self.bits {
+            data.write_u64::<LittleEndian>(*word).unwrap();
+        }
+        data
+    }
+
+    /// Load a filter serialized with `to_bytes`
+    pub fn from_bytes(mut data: &[u8]) -> Result<Self, Error> {
+        let num_hashes = data.read_u32::<LittleEndian>()?;
+        let num_bits = data.read_u64::<LittleEndian>()?;
+        if num_hashes == 0
+            || num_bits == 0
+            || num_bits.div_ceil(64).checked_mul(8) != Some(data.len() as u64)
+        {
+            return Err(Error::DeserializationError);
+        }
+        let mut bits = Vec::with_capacity(data.len() / 8);
+        while !data.is_empty() {
+            bits.push(data.read_u64::<LittleEndian>()?);
+        }
+        Ok(BloomFilter {
+            num_hashes,
+            num_bits,
+            bits,
+        })
+    }
+}
+
+impl<'b, R: 'b + Read + Seek> ArchiveReader<'b, R> {
+    /// Build a `BloomFilter` of the archive file names, sized for
+    /// `expected_items` names with a false positive rate of
+    /// `false_positive_rate`
+    ///
+    /// Names are taken from the footer, as `list_files`. Directories and
+    /// symbolic links are not included
+    pub fn build_bloom_filter(
+        &self,
+        expected_items: usize,
+        false_positive_rate: f64,
+    ) -> Result<BloomFilter, Error> {
+        let mut filter = BloomFilter::new(expected_items, false_positive_rate)?;
+        for fname in self.list_files()? {
+            filter.insert(fname);
+        }
+        Ok(filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+    use crate::ArchiveWriter;
+    use std::io::Cursor;
+
+    #[test]
+    fn bloom_filter() {
+        let count = 10_000;
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        for i in 0..count {
+            mla.add_file(format!("dir/file_{}", i), 0, std::io::empty())
+                .unwrap();
+        }
+        mla.finalize().unwrap();
+        let mla_read =
+            ArchiveReader::from_config(Cursor::new(mla.into_raw()), ArchiveReaderConfig::new())
+                .unwrap();
+
+        let rate = 0.01;
+        let filter = mla_read.build_bloom_filter(count, rate).unwrap();
+        // No false negative
+        for i in 0..count {
+            assert!(filter.probably_contains(&format!("dir/file_{}", i)));
+        }
+        // False positives within the tolerance
+        let false_positives = (0..count)
+            .filter(|i| filter.probably_contains(&format!("other/file_{}", i)))
+            .count();
+        assert!((false_positives as f64) < rate * 1.5 * count as f64);
+
+        // Serialization
+        let data = filter.to_bytes();
+        assert_eq!(BloomFilter::from_bytes(&data).unwrap(), filter);
+        assert!(BloomFilter::from_bytes(&data[..data.len() - 1]).is_err());
+        assert!(BloomFilter::from_bytes(&[]).is_err());
+
+        // Bad parameters
+        assert!(mla_read.build_bloom_filter(count, 0.0).is_err());
+        assert!(mla_read.build_bloom_filter(count, 1.0).is_err());
+    }
+}
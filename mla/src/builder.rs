@@ -0,0 +1,133 @@
+use crate::config::ArchiveWriterConfig;
+use crate::errors::Error;
+use crate::ArchiveWriter;
+use std::io::Read;
+
+/// Build an archive in memory, for tests and simple tools which don't need
+/// streaming
+///
+/// This wraps an `ArchiveWriter` over a `Vec<u8>`: `build` finalizes the
+/// archive and returns its data.
+///
+/// ```
+/// use mla::builder::ArchiveBuilder;
+/// use mla::config::ArchiveWriterConfig;
+/// use mla::Layers;
+///
+/// let mut config = ArchiveWriterConfig::new();
+/// config.set_layers(Layers::COMPRESS);
+/// let mut builder = ArchiveBuilder::new(config).unwrap();
+/// builder
+///     .add_directory("dir").unwrap()
+///     .add_file("dir/hello", b"Hello, world").unwrap();
+/// let archive: Vec<u8> = builder.build().unwrap();
+/// ```
+pub struct ArchiveBuilder {
+    writer: ArchiveWriter<'static, Vec<u8>>,
+}
+
+impl ArchiveBuilder {
+    /// Start an archive with `config`, see `ArchiveWriter::from_config`
+    pub fn new(config: ArchiveWriterConfig) -> Result<Self, Error> {
+        Ok(ArchiveBuilder {
+            writer: ArchiveWriter::from_config(Vec::new(), config)?,
+        })
+    }
+
+    /// Add a file named `filename`, with `content`
+    pub fn add_file(
+        &mut self,
+        filename: &str,
+        content: &[u8],
+    ) -> Result<&mut ArchiveBuilder, Error> {
+        self.writer
+            .add_file(filename, content.len() as u64, content)?;
+        Ok(self)
+    }
+
+    /// Add a file named `filename`, whose content is read from `src` until
+    /// its end
+    pub fn add_file_from_reader<R: Read>(
+        &mut self,
+        filename: &str,
+        mut src: R,
+    ) -> Result<&mut ArchiveBuilder, Error> {
+        self.writer.add_file_from_reader(filename, &mut src)?;
+        Ok(self)
+    }
+
+    /// Add an entry for the directory `path`
+    pub fn add_directory(&mut self, path: &str) -> Result<&mut ArchiveBuilder, Error> {
+        self.writer.add_directory(path, None)?;
+        Ok(self)
+    }
+
+    /// Finalize the archive, and return its data
+    pub fn build(mut self) -> Result<Vec<u8>, Error> {
+        self.writer.finalize()?;
+        Ok(self.writer.into_raw())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ArchiveReaderConfig;
+    use crate::{ArchiveReader, EntryKind, Layers};
+    use std::io::{self, Cursor};
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    #[test]
+    fn build_and_read() {
+        let key = StaticSecret::from([1; 32]);
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::DEFAULT)
+            .add_public_keys(&[PublicKey::from(&key)]);
+        let mut builder = ArchiveBuilder::new(config).unwrap();
+        builder
+            .add_directory("dir")
+            .unwrap()
+            .add_file("dir/small", &[1, 2, 3])
+            .unwrap()
+            .add_file_from_reader("large", io::repeat(4).take(100_000))
+            .unwrap();
+        // Errors are reported, and the builder is still usable
+        assert!(matches!(
+            builder.add_file("large", &[5]),
+            Err(Error::DuplicateFilename)
+        ));
+        builder.add_file("empty", &[]).unwrap();
+        let archive = builder.build().unwrap();
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&[key]);
+        let mut mla_read = ArchiveReader::from_config(Cursor::new(archive), config).unwrap();
+        let mut fnames: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+        fnames.sort();
+        assert_eq!(fnames, vec!["dir/small", "empty", "large"]);
+        for (fname, expected) in [
+            ("dir/small", vec![1, 2, 3]),
+            ("large", vec![4; 100_000]),
+            ("empty", vec![]),
+        ] {
+            let mut content = Vec::new();
+            let mut file = mla_read.get_file(fname).unwrap().unwrap();
+            file.data.read_to_end(&mut content).unwrap();
+            assert_eq!(content, expected);
+        }
+        let first = mla_read.entries().unwrap().next().unwrap().unwrap();
+        assert_eq!(
+            (first.filename, first.kind),
+            ("dir".to_string(), EntryKind::Directory)
+        );
+    }
+
+    #[test]
+    fn bad_config() {
+        // The encryption layer requires a key
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::ENCRYPT);
+        assert!(ArchiveBuilder::new(config).is_err());
+    }
+}
@@ -0,0 +1,362 @@
+//! CBOR encoding of the archive blocks describing the entries, see
+//! `MetadataEncoding::Cbor`
+//!
+//! Such a block is stored as `[CborEncoded type][length (u64)][CBOR item]`.
+//! The item maps the block kind to its fields, for instance
+//! `{"FileStart": {"id": 0, "filename": "my_file"}}`. File contents
+//! (`FileContent`, `FilePrecompressedContent`) are always stored natively
+use crate::crypto::aesgcm::TAG_LENGTH;
+use crate::crypto::file::FileRecipientsPersistent;
+use crate::crypto::hash::{Checksum, Sha256Hash};
+use crate::errors::Error;
+use crate::metadata::FileMetadata;
+use crate::{
+    ArchiveFileBlock, ArchiveFileBlockType, ArchiveFileID, FILENAME_MAX_SIZE, RECIPIENTS_MAX_SIZE,
+    USER_METADATA_MAX_SIZE,
+};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// Maximum size of a CBOR-encoded block, to avoid allocating too much on
+/// corrupted data. The biggest blocks are the `FileRecipients` ones
+const CBOR_BLOCK_MAX_SIZE: u64 = 2 * RECIPIENTS_MAX_SIZE;
+
+/// CBOR counterpart of the `ArchiveFileBlock` describing the entries
+#[derive(Serialize, Deserialize)]
+enum CborBlock {
+    FileStart {
+        id: ArchiveFileID,
+        filename: String,
+    },
+    FileSparseHole {
+        id: ArchiveFileID,
+        length: u64,
+    },
+    Dedup {
+        id: ArchiveFileID,
+        refers_to_id: ArchiveFileID,
+    },
+    FileChecksum {
+        id: ArchiveFileID,
+        algorithm: u8,
+        digest: Vec<u8>,
+    },
+    FileMetadata {
+        id: ArchiveFileID,
+        metadata: FileMetadata,
+    },
+    DirectoryEntry {
+        path: String,
+        metadata: Option<FileMetadata>,
+    },
+    Symlink {
+        link_name: String,
+        target: String,
+        metadata: Option<FileMetadata>,
+    },
+    FileSizeHint {
+        id: ArchiveFileID,
+        size: u64,
+    },
+    FileRecipients {
+        id: ArchiveFileID,
+        recipients: FileRecipientsPersistent,
+    },
+    FileTag {
+        id: ArchiveFileID,
+        tag: [u8; TAG_LENGTH],
+    },
+    UserMetadata {
+        id: ArchiveFileID,
+        key: String,
+        value: Vec<u8>,
+    },
+    FileAlias {
+        original_id: ArchiveFileID,
+        new_name: String,
+    },
+    EndOfFile {
+        id: ArchiveFileID,
+        hash: Sha256Hash,
+    },
+    EndOfArchiveData,
+}
+
+/// Check the limits of the native encoding on the names and user metadata of
+/// `block`
+fn check_sizes(block: &CborBlock) -> Result<(), Error> {
+    let names: &[&String] = match block {
+        CborBlock::FileStart { filename, .. } => &[filename],
+        CborBlock::FileAlias { new_name, .. } => &[new_name],
+        CborBlock::DirectoryEntry { path, .. } => &[path],
+        CborBlock::Symlink {
+            link_name, target, ..
+        } => &[link_name, target],
+        _ => &[],
+    };
+    if names
+        .iter()
+        .any(|name| name.len() as u64 > FILENAME_MAX_SIZE)
+    {
+        return Err(Error::FilenameTooLong);
+    }
+    if let CborBlock::UserMetadata { key, value, .. } = block {
+        if (key.len() + value.len()) as u64 > USER_METADATA_MAX_SIZE {
+            return Err(Error::SerializationError);
+        }
+    }
+    Ok(())
+}
+
+/// Write `block` to `dest`, CBOR-encoded. File contents are refused
+pub(crate) fn dump<T: Read, W: Write>(
+    block: &ArchiveFileBlock<T>,
+    dest: &mut W,
+) -> Result<(), Error> {
+    let cbor_block = match block {
+        ArchiveFileBlock::FileStart { filename, id } => CborBlock::FileStart {
+            id: *id,
+            filename: filename.clone(),
+        },
+        ArchiveFileBlock::FileSparseHole { id, length } => CborBlock::FileSparseHole {
+            id: *id,
+            length: *length,
+        },
+        ArchiveFileBlock::Dedup { id, refers_to_id } => CborBlock::Dedup {
+            id: *id,
+            refers_to_id: *refers_to_id,
+        },
+        ArchiveFileBlock::FileChecksum { id, digest } => CborBlock::FileChecksum {
+            id: *id,
+            algorithm: digest.algorithm() as u8,
+            digest: digest.as_bytes().to_vec(),
+        },
+        ArchiveFileBlock::FileMetadata { id, metadata } => CborBlock::FileMetadata {
+            id: *id,
+            metadata: metadata.clone(),
+        },
+        ArchiveFileBlock::DirectoryEntry { path, metadata } => CborBlock::DirectoryEntry {
+            path: path.clone(),
+            metadata: metadata.clone(),
+        },
+        ArchiveFileBlock::Symlink {
+            link_name,
+            target,
+            metadata,
+        } => CborBlock::Symlink {
+            link_name: link_name.clone(),
+            target: target.clone(),
+            metadata: metadata.clone(),
+        },
+        ArchiveFileBlock::FileSizeHint { id, size } => CborBlock::FileSizeHint {
+            id: *id,
+            size: *size,
+        },
+        ArchiveFileBlock::FileRecipients { id, recipients } => CborBlock::FileRecipients {
+            id: *id,
+            recipients: recipients.clone(),
+        },
+        ArchiveFileBlock::FileTag { id, tag } => CborBlock::FileTag { id: *id, tag: *tag },
+        ArchiveFileBlock::UserMetadata { id, key, value } => CborBlock::UserMetadata {
+            id: *id,
+            key: key.clone(),
+            value: value.clone(),
+        },
+        ArchiveFileBlock::FileAlias {
+            original_id,
+            new_name,
+        } => CborBlock::FileAlias {
+            original_id: *original_id,
+            new_name: new_name.clone(),
+        },
+        ArchiveFileBlock::EndOfFile { id, hash } => CborBlock::EndOfFile {
+            id: *id,
+            hash: *hash,
+        },
+        ArchiveFileBlock::EndOfArchiveData => CborBlock::EndOfArchiveData,
+        ArchiveFileBlock::FileContent { .. }
+        | ArchiveFileBlock::FilePrecompressedContent { .. }
+        | ArchiveFileBlock::Padding { .. }
+        | ArchiveFileBlock::Unknown { .. } => {
+            return Err(Error::AssertionError(String::from(
+                "Only the blocks describing the entries are CBOR-encoded",
+            )));
+        }
+    };
+    check_sizes(&cbor_block)?;
+    let bytes = serde_cbor::to_vec(&cbor_block).map_err(|_| Error::SerializationError)?;
+    let length = bytes.len() as u64;
+    if length > CBOR_BLOCK_MAX_SIZE {
+        return Err(Error::SerializationError);
+    }
+    dest.write_u8(ArchiveFileBlockType::CborEncoded as u8)?;
+    dest.write_u64::<LittleEndian>(length)?;
+    dest.write_all(&bytes)?;
+    Ok(())
+}
+
+/// Read a CBOR-encoded block from `src`, positioned after its type
+pub(crate) fn from<T: Read>(src: &mut T) -> Result<ArchiveFileBlock<T>, Error> {
+    let length = src.read_u64::<LittleEndian>()?;
+    if length > CBOR_BLOCK_MAX_SIZE {
+        return Err(Error::DeserializationError);
+    }
+    let mut data = vec![0u8; length as usize];
+    src.read_exact(&mut data)?;
+    let cbor_block: CborBlock =
+        serde_cbor::from_slice(&data).map_err(|_| Error::DeserializationError)?;
+    check_sizes(&cbor_block).map_err(|err| match err {
+        Error::SerializationError => Error::DeserializationError,
+        err => err,
+    })?;
+    Ok(match cbor_block {
+        CborBlock::FileStart { id, filename } => ArchiveFileBlock::FileStart { filename, id },
+        CborBlock::FileSparseHole { id, length } => ArchiveFileBlock::FileSparseHole { id, length },
+        CborBlock::Dedup { id, refers_to_id } => ArchiveFileBlock::Dedup { id, refers_to_id },
+        CborBlock::FileChecksum {
+            id,
+            algorithm,
+            digest,
+        } => ArchiveFileBlock::FileChecksum {
+            id,
+            digest: Checksum::from_bytes(algorithm, &digest)?,
+        },
+        CborBlock::FileMetadata { id, metadata } => ArchiveFileBlock::FileMetadata { id, metadata },
+        CborBlock::DirectoryEntry { path, metadata } => {
+            ArchiveFileBlock::DirectoryEntry { path, metadata }
+        }
+        CborBlock::Symlink {
+            link_name,
+            target,
+            metadata,
+        } => ArchiveFileBlock::Symlink {
+            link_name,
+            target,
+            metadata,
+        },
+        CborBlock::FileSizeHint { id, size } => ArchiveFileBlock::FileSizeHint { id, size },
+        CborBlock::FileRecipients { id, recipients } => {
+            ArchiveFileBlock::FileRecipients { id, recipients }
+        }
+        CborBlock::FileTag { id, tag } => ArchiveFileBlock::FileTag { id, tag },
+        CborBlock::UserMetadata { id, key, value } => {
+            ArchiveFileBlock::UserMetadata { id, key, value }
+        }
+        CborBlock::FileAlias {
+            original_id,
+            new_name,
+        } => ArchiveFileBlock::FileAlias {
+            original_id,
+            new_name,
+        },
+        CborBlock::EndOfFile { id, hash } => ArchiveFileBlock::EndOfFile { id, hash },
+        CborBlock::EndOfArchiveData => ArchiveFileBlock::EndOfArchiveData,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig, MetadataEncoding};
+    use crate::crypto::hash::ChecksumAlgorithm;
+    use crate::helpers::linear_extract;
+    use crate::{ArchiveReader, ArchiveWriter, FeatureFlags, Layers};
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use std::collections::HashMap;
+    use std::io::Cursor;
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    /// Archive with files, a directory and a symbolic link
+    fn build(layers: Layers, key: &StaticSecret, encoding: MetadataEncoding) -> Vec<u8> {
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(layers)
+            .add_public_keys(&[PublicKey::from(key)])
+            .enable_file_checksums(ChecksumAlgorithm::Blake3)
+            .metadata_encoding(encoding);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let id = mla.start_file("my_file1").unwrap();
+        mla.set_file_metadata(id, "source", b"somewhere").unwrap();
+        mla.append_file_content(id, 3, &[1, 2, 3][..]).unwrap();
+        mla.end_file(id).unwrap();
+        mla.add_file("my_file2", 4, &[4, 5, 6, 7][..]).unwrap();
+        mla.add_directory("my_dir", None).unwrap();
+        mla.add_symlink("my_link", "my_file1", None).unwrap();
+        mla.finalize().unwrap();
+        mla.into_raw()
+    }
+
+    fn read_all(archive: &[u8], config: ArchiveReaderConfig) -> HashMap<String, Vec<u8>> {
+        let mut mla_read = ArchiveReader::from_config(Cursor::new(archive), config).unwrap();
+        assert!(mla_read
+            .verify_integrity()
+            .unwrap()
+            .files_with_errors
+            .is_empty());
+        assert_eq!(
+            mla_read.get_file_metadata("my_file1").unwrap()["source"],
+            b"somewhere"
+        );
+        assert_eq!(
+            mla_read.list_directories().unwrap().collect::<Vec<_>>(),
+            ["my_dir"]
+        );
+        assert_eq!(
+            mla_read.list_symlinks().unwrap().collect::<Vec<_>>(),
+            [("my_link", "my_file1")]
+        );
+        let fnames: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+        let mut export: HashMap<&String, Vec<u8>> =
+            fnames.iter().map(|fname| (fname, Vec::new())).collect();
+        linear_extract(&mut mla_read, &mut export).unwrap();
+        export
+            .into_iter()
+            .map(|(fname, content)| (fname.clone(), content))
+            .collect()
+    }
+
+    #[test]
+    fn cbor_blocks() {
+        let key = StaticSecret::new(&mut ChaChaRng::seed_from_u64(0));
+        for layers in [Layers::EMPTY, Layers::DEFAULT] {
+            let reader_config = |encoding| {
+                let mut config = ArchiveReaderConfig::new();
+                config
+                    .add_private_keys(std::slice::from_ref(&key))
+                    .metadata_encoding(encoding);
+                config
+            };
+            let native = build(layers, &key, MetadataEncoding::Native);
+            let archive = build(layers, &key, MetadataEncoding::Cbor);
+            if layers.is_empty() {
+                // Blocks are readable as CBOR items, not the native ones
+                let needle: &[u8] = b"FileStart";
+                let has_needle = |data: &[u8]| data.windows(needle.len()).any(|w| w == needle);
+                assert!(has_needle(&archive));
+                assert!(!has_needle(&native));
+            }
+
+            // Readers expecting the native encoding refuse the archive
+            let err = ArchiveReader::from_config(
+                Cursor::new(archive.as_slice()),
+                reader_config(MetadataEncoding::Native),
+            )
+            .err()
+            .unwrap();
+            assert!(
+                matches!(err, Error::UnsupportedFeature(features) if features == FeatureFlags::HAS_CBOR_BLOCKS)
+            );
+
+            // The content is the same as with the native encoding
+            let contents = read_all(&archive, reader_config(MetadataEncoding::Cbor));
+            assert_eq!(contents["my_file1"], [1, 2, 3]);
+            assert_eq!(contents["my_file2"], [4, 5, 6, 7]);
+            assert_eq!(
+                contents,
+                read_all(&native, reader_config(MetadataEncoding::Cbor))
+            );
+        }
+    }
+}
@@ -0,0 +1,436 @@
+//! Checkpoints of an archive creation, to resume it after an interruption
+//!
+//! `ArchiveWriter::checkpoint` ends the opened files and flushes the archive
+//! written so far. If the creation is then interrupted, for instance because
+//! the process has been killed, `ArchiveWriter::resume_from_checkpoint`
+//! continues the same archive from the returned `CheckpointToken`, adding
+//! more files before finalizing it.
+//!
+//! Similarly, `ArchiveWriter::open_append` continues a finalized archive, for
+//! instance to add files or rename some of them with
+//! `ArchiveWriter::rename_file`.
+//!
+//! Encryption, compression and signature keep an internal state (keys,
+//! current compressed block, running hash) which is not saved: checkpoints and
+//! appends are only supported for archives without layers.
+use crate::config::ArchiveWriterConfig;
+use crate::errors::{ConfigError, Error};
+use crate::layers::position::PositionLayerWriter;
+use crate::layers::raw::RawLayerWriter;
+use crate::recover::{rebuild_footer, RebuiltFooter};
+use crate::{
+    ArchiveFileBlock, ArchiveFileID, ArchiveFooter, ArchiveHeader, ArchiveWriter,
+    ArchiveWriterState, FormatVersion, Layers,
+};
+use byteorder::{LittleEndian, ReadBytesExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// State of an archive creation, as returned by `ArchiveWriter::checkpoint`
+///
+/// It can be serialized, to be kept along with the archive until its
+/// creation ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckpointToken {
+    /// Size of the archive written at the checkpoint, header included
+    pub byte_offset: u64,
+    /// Number of files ended at the checkpoint, directories and symbolic
+    /// links excluded
+    pub files_written: usize,
+}
+
+fn check_resumable(layers: Layers) -> Result<(), Error> {
+    if layers.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::ConfigError(ConfigError::CheckpointRequiresNoLayers))
+    }
+}
+
+impl<'a, W: Write> ArchiveWriter<'a, W> {
+    /// End every opened file, flush the archive written so far, and return
+    /// the corresponding `CheckpointToken`
+    ///
+    /// The writer can still be used afterwards. If the archive creation is
+    /// interrupted, it can be resumed from the token with
+    /// `resume_from_checkpoint`: files added after the checkpoint are then
+    /// lost. Only archives without layers support checkpoints
+    pub fn checkpoint(&mut self) -> Result<CheckpointToken, Error> {
+        check_resumable(self.config.layers_enabled)?;
+        let ids = match &self.state {
+            ArchiveWriterState::OpenedFiles { ids, .. } => ids.clone(),
+            _ => {
+                return Err(Error::WrongArchiveWriterState {
+                    current_state: format!("{:?}", self.state),
+                    expected_state: "ArchiveWriterState::OpenedFiles".to_string(),
+                })
+            }
+        };
+        for id in ids {
+            self.end_file(id)?;
+        }
+        self.dest.flush()?;
+        Ok(CheckpointToken {
+            byte_offset: self.raw_written.get(),
+            files_written: self.files_info.len(),
+        })
+    }
+}
+
+impl<'a, W: Read + Write + Seek> ArchiveWriter<'a, W> {
+    /// Read the header of the archive in `dest`, which must be resumable.
+    /// Returns its size
+    fn read_resumable_header(dest: &mut W) -> Result<u64, Error> {
+        dest.seek(SeekFrom::Start(0))?;
+        let header = ArchiveHeader::from(dest)?;
+        if header.format != FormatVersion::CURRENT {
+            return Err(Error::UnsupportedVersion);
+        }
+        check_resumable(header.config.layers_enabled)?;
+        Ok(dest.stream_position()?)
+    }
+
+    /// Writer continuing the archive in `dest` after `data_size` bytes of
+    /// archive data, from the state rebuilt from these data
+    fn restore(
+        mut dest: W,
+        header_size: u64,
+        data_size: u64,
+        rebuilt: RebuiltFooter,
+        config: ArchiveWriterConfig,
+    ) -> Result<Self, Error> {
+        let byte_offset = header_size + data_size;
+        dest.seek(SeekFrom::Start(byte_offset))?;
+        let raw = RawLayerWriter::new(dest);
+        let raw_written = raw.written_counter();
+        raw_written.set(byte_offset);
+        let mut final_dest = Box::new(PositionLayerWriter::new(Box::new(raw)));
+        final_dest.set_position(data_size);
+
+        let mut writer = Self::with_dest(config, final_dest, header_size, raw_written, None);
+        let footer = rebuilt.footer;
+        writer.next_id = rebuilt.next_id;
+        writer.features = footer.features;
+        writer.directories = footer.directories;
+        writer.symlinks = footer.symlinks;
+        for (filename, file_info) in footer.files_info {
+            let id = *rebuilt
+                .ids
+                .get(&filename)
+                .ok_or(Error::DeserializationError)?;
+            // Aliases share the information of their original file
+            if !writer.ids_info.contains_key(&id) {
+                writer.content_size += file_info.size;
+                writer.ids_info.insert(id, file_info);
+            }
+            writer.files_info.insert(filename, id);
+        }
+        Ok(writer)
+    }
+
+    /// Continue the creation of the archive in `dest`, from `token` returned
+    /// by `checkpoint`
+    ///
+    /// The archive written up to the checkpoint is read back, block by block,
+    /// to rebuild the writer state, and new data are written from
+    /// `token.byte_offset`. Data
+    /// written after the checkpoint are overwritten: as the end of the archive
+    /// must be its actual end, `dest` should be truncated to
+    /// `token.byte_offset` beforehand (for instance with `File::set_len`).
+    ///
+    /// `config` must be the one used to create the archive; its comment is
+    /// ignored, as the header is already written. Archives with layers can't
+    /// be resumed: `ConfigError::CheckpointRequiresNoLayers` is returned
+    pub fn resume_from_checkpoint(
+        mut dest: W,
+        token: &CheckpointToken,
+        config: ArchiveWriterConfig,
+    ) -> Result<Self, Error> {
+        config.check()?;
+        check_resumable(config.layers_enabled)?;
+
+        // Read back the archive written so far
+        let header_size = Self::read_resumable_header(&mut dest)?;
+        let data_size = token.byte_offset.checked_sub(header_size).ok_or_else(|| {
+            Error::BadAPIArgument("The checkpoint is before the archive data".to_string())
+        })?;
+        if dest.seek(SeekFrom::End(0))? < token.byte_offset {
+            return Err(Error::BadAPIArgument(
+                "The archive is shorter than the checkpoint".to_string(),
+            ));
+        }
+        dest.seek(SeekFrom::Start(header_size))?;
+        let rebuilt = rebuild_footer(&mut dest, data_size, true)?;
+        if rebuilt.footer.files_info.len() != token.files_written {
+            return Err(Error::BadAPIArgument(
+                "The archive does not match the checkpoint".to_string(),
+            ));
+        }
+
+        // Continue writing after the checkpoint
+        Self::restore(dest, header_size, data_size, rebuilt, config)
+    }
+
+    /// Continue the finalized archive in `dest`, to add entries to it
+    ///
+    /// The writer state is restored from the footer of the archive, without
+    /// reading the archive data. New data are written in place of the end of
+    /// the archive data and the footer, which is written again by `finalize`.
+    /// If the resulting archive is shorter, `dest` should be truncated to
+    /// `WriteStatistics::total_bytes_written` afterwards.
+    ///
+    /// `config` must be the one used to create the archive: the footer comment
+    /// and creation time are written again from it
+    pub fn open_append(mut dest: W, config: ArchiveWriterConfig) -> Result<Self, Error> {
+        config.check()?;
+        check_resumable(config.layers_enabled)?;
+
+        let header_size = Self::read_resumable_header(&mut dest)?;
+        let (data_size, rebuilt) = read_finalized_footer(&mut dest, header_size)
+            .map_err(|_| Error::BadAPIArgument("The archive is not finalized".to_string()))?;
+
+        Self::restore(dest, header_size, data_size, rebuilt, config)
+    }
+}
+
+/// Read the footer of the finalized archive without layers in `dest`, whose
+/// header is `header_size` bytes long, as `ArchiveReader` does. Returns the
+/// size of the archive data, up to the `EndOfArchiveData` block, and the
+/// writer state to restore
+///
+/// Only the `EndOfFile` block of each file is read, for its ID
+fn read_finalized_footer<W: Read + Seek>(
+    dest: &mut W,
+    header_size: u64,
+) -> Result<(u64, RebuiltFooter), Error> {
+    let footer = ArchiveFooter::deserialize_from(&mut *dest, FormatVersion::CURRENT)?;
+
+    // The archive data end with an `EndOfArchiveData` block, just before the
+    // footer
+    let footer_end = dest.seek(SeekFrom::End(-4))?;
+    let footer_len = u64::from(dest.read_u32::<LittleEndian>()?);
+    let data_size = footer_end
+        .checked_sub(footer_len + 1)
+        .and_then(|end| end.checked_sub(header_size))
+        .ok_or(Error::DeserializationError)?;
+    dest.seek(SeekFrom::Start(header_size + data_size))?;
+    if !matches!(
+        ArchiveFileBlock::from(&mut *dest)?,
+        ArchiveFileBlock::EndOfArchiveData
+    ) {
+        return Err(Error::DeserializationError);
+    }
+
+    let mut ids = HashMap::new();
+    let mut next_id = ArchiveFileID(0);
+    for (filename, file_info) in &footer.files_info {
+        dest.seek(SeekFrom::Start(header_size + file_info.eof_offset))?;
+        let id = match ArchiveFileBlock::from(&mut *dest)? {
+            ArchiveFileBlock::EndOfFile { id, .. } => id,
+            _ => return Err(Error::DeserializationError),
+        };
+        if id >= next_id {
+            next_id = id.checked_next().ok_or(Error::FileIDExhausted)?;
+        }
+        // Aliases share the `EndOfFile` of their original file
+        ids.insert(filename.clone(), id);
+    }
+    Ok((
+        data_size,
+        RebuiltFooter {
+            footer,
+            end_offset: Some(data_size),
+            ids,
+            next_id,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ArchiveReaderConfig;
+    use crate::ArchiveReader;
+    use std::io::Cursor;
+
+    fn read_file<R: Read + Seek>(mla: &mut ArchiveReader<R>, filename: &str) -> Vec<u8> {
+        let mut content = Vec::new();
+        mla.get_file(filename)
+            .unwrap()
+            .unwrap()
+            .data
+            .read_to_end(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    fn checkpoint_resume() {
+        let files: Vec<(String, Vec<u8>)> = (0..5)
+            .map(|i| (format!("file{}", i), vec![i as u8; 1000 * (i + 1)]))
+            .collect();
+
+        // Write the first files, one of them still opened at the checkpoint
+        let mut mla =
+            ArchiveWriter::from_config(Cursor::new(Vec::new()), ArchiveWriterConfig::new())
+                .unwrap();
+        mla.add_file(&files[0].0, files[0].1.len() as u64, files[0].1.as_slice())
+            .unwrap();
+        mla.add_directory("dir", None).unwrap();
+        let id = mla.start_file(&files[1].0).unwrap();
+        mla.append_file_content(id, files[1].1.len() as u64, files[1].1.as_slice())
+            .unwrap();
+        let token = mla.checkpoint().unwrap();
+        assert_eq!(token.files_written, 2);
+
+        // Crash while writing the next file
+        let id = mla.start_file("lost").unwrap();
+        mla.append_file_content(id, 3, &[1, 2, 3][..]).unwrap();
+        let mut archive = mla.into_raw().into_inner();
+        assert!(archive.len() as u64 > token.byte_offset);
+
+        // Resume, and add the remaining files
+        archive.truncate(token.byte_offset as usize);
+        let mut mla = ArchiveWriter::resume_from_checkpoint(
+            Cursor::new(archive),
+            &token,
+            ArchiveWriterConfig::new(),
+        )
+        .unwrap();
+        for (fname, content) in &files[2..] {
+            mla.add_file(fname, content.len() as u64, content.as_slice())
+                .unwrap();
+        }
+        let stats = mla.finalize().unwrap();
+        assert_eq!(stats.files_written, files.len());
+        let archive = mla.into_raw().into_inner();
+        assert_eq!(stats.total_bytes_written, archive.len() as u64);
+
+        // Files from before and after the checkpoint are there
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(archive), ArchiveReaderConfig::new()).unwrap();
+        let mut fnames: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+        fnames.sort();
+        assert_eq!(
+            fnames,
+            files
+                .iter()
+                .map(|(fname, _)| fname.clone())
+                .collect::<Vec<_>>()
+        );
+        for (fname, content) in &files {
+            assert_eq!(&read_file(&mut mla_read, fname), content);
+        }
+        assert!(mla_read.list_directories().unwrap().any(|dir| dir == "dir"));
+        mla_read.verify_integrity().unwrap();
+    }
+
+    #[test]
+    fn checkpoint_errors() {
+        // Layers keep a state which is not saved
+        let mut config = ArchiveWriterConfig::new();
+        config.enable_layer(Layers::COMPRESS);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        assert!(matches!(
+            mla.checkpoint(),
+            Err(Error::ConfigError(ConfigError::CheckpointRequiresNoLayers))
+        ));
+        // Nor can an archive with layers be resumed
+        mla.finalize().unwrap();
+        let archive = mla.into_raw();
+        let token = CheckpointToken {
+            byte_offset: archive.len() as u64,
+            files_written: 0,
+        };
+        assert!(matches!(
+            ArchiveWriter::resume_from_checkpoint(
+                Cursor::new(archive),
+                &token,
+                ArchiveWriterConfig::new()
+            ),
+            Err(Error::ConfigError(ConfigError::CheckpointRequiresNoLayers))
+        ));
+
+        // A token which does not match the archive
+        let mut mla =
+            ArchiveWriter::from_config(Cursor::new(Vec::new()), ArchiveWriterConfig::new())
+                .unwrap();
+        mla.add_file("a", 1, &[1][..]).unwrap();
+        let token = mla.checkpoint().unwrap();
+        let archive = mla.into_raw().into_inner();
+        let wrong = CheckpointToken {
+            files_written: 2,
+            ..token
+        };
+        assert!(matches!(
+            ArchiveWriter::resume_from_checkpoint(
+                Cursor::new(archive.clone()),
+                &wrong,
+                ArchiveWriterConfig::new()
+            ),
+            Err(Error::BadAPIArgument(_))
+        ));
+        let wrong = CheckpointToken {
+            byte_offset: token.byte_offset + 1,
+            ..token
+        };
+        assert!(matches!(
+            ArchiveWriter::resume_from_checkpoint(
+                Cursor::new(archive),
+                &wrong,
+                ArchiveWriterConfig::new()
+            ),
+            Err(Error::BadAPIArgument(_))
+        ));
+    }
+
+    #[test]
+    fn append_rename() {
+        let mut mla =
+            ArchiveWriter::from_config(Cursor::new(Vec::new()), ArchiveWriterConfig::new())
+                .unwrap();
+        mla.add_file("a", 3, &[1, 2, 3][..]).unwrap();
+        mla.add_file("b", 2, &[4, 5][..]).unwrap();
+        mla.finalize().unwrap();
+        let archive = mla.into_raw();
+
+        // Rename a file of the finalized archive, and add another one
+        let mut mla = ArchiveWriter::open_append(archive, ArchiveWriterConfig::new()).unwrap();
+        assert!(matches!(
+            mla.rename_file("a", "b"),
+            Err(Error::DuplicateFilename)
+        ));
+        assert!(matches!(
+            mla.rename_file("unknown", "c"),
+            Err(Error::BadAPIArgument(_))
+        ));
+        mla.rename_file("a", "renamed").unwrap();
+        mla.add_file("c", 1, &[6][..]).unwrap();
+        let stats = mla.finalize().unwrap();
+        let archive = mla.into_raw().into_inner();
+        assert_eq!(stats.total_bytes_written, archive.len() as u64);
+
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(archive), ArchiveReaderConfig::new()).unwrap();
+        assert_eq!(read_file(&mut mla_read, "renamed"), vec![1, 2, 3]);
+        assert_eq!(read_file(&mut mla_read, "a"), vec![1, 2, 3]);
+        assert_eq!(read_file(&mut mla_read, "c"), vec![6]);
+        assert_eq!(
+            mla_read.list_files_with_aliases().unwrap(),
+            vec![
+                ("a".to_string(), vec!["renamed".to_string()]),
+                ("b".to_string(), Vec::new()),
+                ("c".to_string(), Vec::new()),
+            ]
+        );
+        mla_read.verify_integrity().unwrap();
+
+        // Linear extraction also provides the new name
+        let fname = "renamed".to_string();
+        let mut export = HashMap::new();
+        export.insert(&fname, Vec::new());
+        crate::helpers::linear_extract(&mut mla_read, &mut export).unwrap();
+        assert_eq!(export.get(&fname).unwrap(), &vec![1, 2, 3]);
+    }
+}
@@ -0,0 +1,155 @@
+//! Cut of file contents in `FileContent` blocks, see
+//! `ArchiveWriterConfig::block_boundary`
+//!
+//! With `BlockBoundary::ContentDefined`, a Gear rolling hash is computed over
+//! the content, starting `min` bytes after the previous boundary. Each byte
+//! shifts the hash left by one bit, so that its upper bits only depend on the
+//! last 64 bytes. A block ends when these upper bits are all zero, or when it
+//! reaches `max` bytes. Boundaries depending on the content only, inserting or
+//! removing bytes changes the surrounding blocks, and not the following ones
+use crate::config::BlockBoundary;
+
+/// Random values of the Gear hash, one for each byte value, generated with
+/// SplitMix64
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x4d4c_4120_4344_4321;
+    let mut index = 0;
+    while index < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut value = state;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[index] = value ^ (value >> 31);
+        index += 1;
+    }
+    table
+}
+
+/// Content of a file not written yet, cut in blocks as it is appended
+pub(crate) struct Chunker {
+    boundary: BlockBoundary,
+    /// Upper bits of the hash which must be zero at a boundary
+    mask: u64,
+    hash: u64,
+    /// Content since the last boundary
+    data: Vec<u8>,
+}
+
+impl Chunker {
+    pub(crate) fn new(boundary: BlockBoundary) -> Self {
+        let mask = match boundary {
+            BlockBoundary::ContentDefined { min, avg, .. } => {
+                // A boundary is found every 2^bits hashed bytes, on average
+                let spread = avg.saturating_sub(min).max(1);
+                let bits = usize::BITS - 1 - spread.leading_zeros();
+                if bits == 0 {
+                    0
+                } else {
+                    u64::MAX << (64 - bits)
+                }
+            }
+            BlockBoundary::Fixed(_) => 0,
+        };
+        Chunker {
+            boundary,
+            mask,
+            hash: 0,
+            data: Vec::new(),
+        }
+    }
+
+    /// Append `buf` to the content, and return the blocks it completes
+    pub(crate) fn push(&mut self, mut buf: &[u8]) -> Vec<Vec<u8>> {
+        let mut blocks = Vec::new();
+        while let Some(cut) = self.find_boundary(buf) {
+            self.data.extend_from_slice(&buf[..cut]);
+            blocks.push(std::mem::take(&mut self.data));
+            self.hash = 0;
+            buf = &buf[cut..];
+        }
+        self.data.extend_from_slice(buf);
+        blocks
+    }
+
+    /// Return the content of the last block, not complete
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Number of bytes of `buf` ending the current block, if it ends in
+    /// `buf`
+    fn find_boundary(&mut self, buf: &[u8]) -> Option<usize> {
+        let mut length = self.data.len();
+        match self.boundary {
+            BlockBoundary::Fixed(size) => {
+                if length + buf.len() >= size {
+                    Some(size - length)
+                } else {
+                    None
+                }
+            }
+            BlockBoundary::ContentDefined { min, max, .. } => {
+                for (index, byte) in buf.iter().enumerate() {
+                    length += 1;
+                    if length >= max {
+                        return Some(index + 1);
+                    }
+                    if length > min {
+                        self.hash = (self.hash << 1).wrapping_add(GEAR[usize::from(*byte)]);
+                        if self.hash & self.mask == 0 {
+                            return Some(index + 1);
+                        }
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{RngCore, SeedableRng};
+    use rand_chacha::ChaChaRng;
+
+    fn cut(boundary: BlockBoundary, content: &[u8], push_size: usize) -> Vec<Vec<u8>> {
+        let mut chunker = Chunker::new(boundary);
+        let mut blocks = Vec::new();
+        for piece in content.chunks(push_size) {
+            blocks.extend(chunker.push(piece));
+        }
+        blocks.push(chunker.finish());
+        blocks
+    }
+
+    #[test]
+    fn boundaries() {
+        let mut content = vec![0u8; 200_000];
+        ChaChaRng::seed_from_u64(0).fill_bytes(&mut content);
+
+        let blocks = cut(BlockBoundary::Fixed(1000), &content, 333);
+        assert_eq!(blocks.len(), 201);
+        assert!(blocks[..200].iter().all(|block| block.len() == 1000));
+        assert!(blocks[200].is_empty());
+
+        let boundary = BlockBoundary::ContentDefined {
+            min: 1024,
+            avg: 4096,
+            max: 8192,
+        };
+        let blocks = cut(boundary, &content, 4000);
+        assert_eq!(blocks.concat(), content);
+        let (last, complete) = blocks.split_last().unwrap();
+        assert!(last.len() < 8192);
+        assert!(complete
+            .iter()
+            .all(|block| block.len() > 1024 && block.len() <= 8192));
+        // Boundaries do not depend on how the content is appended
+        assert_eq!(cut(boundary, &content, 1), blocks);
+        assert_eq!(cut(boundary, &content, content.len()), blocks);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::audit::AuditEventType;
+use crate::config::ArchiveReaderConfig;
+use crate::errors::Error;
+use crate::{open_file, open_layers, ArchiveFooter, ArchiveHeader, ArchiveReader, ARCHIVE_ID_SIZE};
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+
+/// Shared state of a `ConcurrentArchiveReader`
+struct ConcurrentArchive<R> {
+    /// Archive source, locked during each read
+    src: Mutex<R>,
+    /// Position of the archive data, after the header
+    data_start: u64,
+    /// Identifier, from the header
+    archive_id: Option<[u8; ARCHIVE_ID_SIZE]>,
+    config: ArchiveReaderConfig,
+    footer: ArchiveFooter,
+}
+
+/// Archive reader usable from several threads at once, see
+/// `ArchiveReader::into_concurrent`
+///
+/// Each `get_file` call locks the archive source, reads the whole file
+/// content and releases the lock: reads are serialized, but threads each
+/// reading a different file can share the same reader. Cloning it is cheap,
+/// and clones share the same source.
+///
+/// If the source can be opened several times, for instance for a file on
+/// disk, `helpers::parallel_extract` reads files in parallel instead
+pub struct ConcurrentArchiveReader<R> {
+    inner: Arc<ConcurrentArchive<R>>,
+}
+
+impl<R> Clone for ConcurrentArchiveReader<R> {
+    fn clone(&self) -> Self {
+        ConcurrentArchiveReader {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<R: Read + Seek> ConcurrentArchiveReader<R> {
+    /// Return the content of the file `filename`, or None if it is not in
+    /// the archive
+    pub fn get_file(&self, filename: &str) -> Result<Option<Vec<u8>>, Error> {
+        let inner = &self.inner;
+        let mut src = inner.src.lock().map_err(|_| {
+            Error::WrongReaderState(
+                "[ConcurrentArchiveReader] A previous read panicked".to_string(),
+            )
+        })?;
+        src.seek(SeekFrom::Start(inner.data_start))?;
+        let mut layers = open_layers(&mut *src, &inner.config)?;
+        let mut file = match open_file(
+            &mut layers,
+            &inner.footer.files_info,
+            &inner.config,
+            filename,
+        )? {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+        let mut content = Vec::with_capacity(file.size as usize);
+        file.data.read_to_end(&mut content)?;
+        inner.config.audit(
+            inner.archive_id,
+            AuditEventType::FileContentAccess {
+                filename: filename.to_string(),
+                bytes_read: content.len() as u64,
+            },
+        );
+        Ok(Some(content))
+    }
+
+    /// Return an iterator on filenames present in the archive, see
+    /// `ArchiveReader::list_files`
+    pub fn list_files(&self) -> impl Iterator<Item = &String> {
+        self.inner
+            .config
+            .audit(self.inner.archive_id, AuditEventType::FileListAccess);
+        self.inner.footer.files_info.keys()
+    }
+}
+
+impl<'b, R: 'b + Read + Seek> ArchiveReader<'b, R> {
+    /// Turn this reader into a `ConcurrentArchiveReader`, to read files from
+    /// several threads
+    ///
+    /// The archive must have been opened with its footer: archives recovered
+    /// from truncated data are not supported
+    pub fn into_concurrent(self) -> Result<ConcurrentArchiveReader<R>, Error> {
+        if self.truncated {
+            return Err(Error::BadAPIArgument(
+                "[into_concurrent] Truncated archives are not supported".to_string(),
+            ));
+        }
+        let footer = self.metadata.ok_or(Error::MissingMetadata)?;
+        let mut src = self.src.into_raw();
+        // The header has already been checked, only its length is needed
+        src.seek(SeekFrom::Start(0))?;
+        ArchiveHeader::from(&mut src)?;
+        let data_start = src.stream_position()?;
+        Ok(ConcurrentArchiveReader {
+            inner: Arc::new(ConcurrentArchive {
+                src: Mutex::new(src),
+                data_start,
+                archive_id: self.archive_id,
+                config: self.config,
+                footer,
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ArchiveWriterConfig;
+    use crate::{ArchiveWriter, Layers};
+    use std::io::Cursor;
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    #[test]
+    fn concurrent_get_file() {
+        let key = StaticSecret::from([4; 32]);
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::DEFAULT)
+            .add_public_keys(&[PublicKey::from(&key)]);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let files: Vec<(String, Vec<u8>)> = (0..10)
+            .map(|i| (format!("file_{}", i), vec![i as u8; 10_000 * (i + 1)]))
+            .collect();
+        for (fname, content) in &files {
+            mla.add_file(fname, content.len() as u64, content.as_slice())
+                .unwrap();
+        }
+        mla.finalize().unwrap();
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&[key]);
+        let mla_read = ArchiveReader::from_config(Cursor::new(mla.into_raw()), config)
+            .unwrap()
+            .into_concurrent()
+            .unwrap();
+        assert_eq!(mla_read.list_files().count(), files.len());
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = files
+                .iter()
+                .map(|(fname, content)| {
+                    let mla_read = &mla_read;
+                    scope.spawn(move || {
+                        assert_eq!(&mla_read.get_file(fname).unwrap().unwrap(), content);
+                    })
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+        });
+        assert!(mla_read.get_file("unknown").unwrap().is_none());
+
+        // Clones can be moved to other threads
+        let clone = mla_read.clone();
+        let content = std::thread::spawn(move || clone.get_file("file_0").unwrap().unwrap())
+            .join()
+            .unwrap();
+        assert_eq!(content, files[0].1);
+    }
+}
@@ -1,22 +1,256 @@
-use crate::errors::ConfigError;
-use crate::layers::compress::CompressionConfig;
+use crate::audit::{AuditEvent, AuditEventType, AuditLogger};
+use crate::crypto::hash::ChecksumAlgorithm;
+use crate::errors::{ConfigError, Error};
+use crate::layers::compress::{
+    CompressionConfig, CompressionPersistentConfig, CompressionReaderConfig,
+};
 use crate::layers::encrypt::{
-    EncryptionConfig, EncryptionPersistentConfig, EncryptionReaderConfig,
+    EncryptionConfig, EncryptionPersistentConfig, EncryptionPersistentConfigV1,
+    EncryptionPersistentConfigV2, EncryptionReaderConfig,
 };
-use crate::Layers;
+use crate::layers::sign::{SignatureConfig, SignatureReaderConfig};
+use crate::progress::{NoopProgressReporter, ProgressReporter};
+use crate::{Layers, ARCHIVE_ID_SIZE};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zeroize::ZeroizeOnDrop;
 
 /// This module implements the configuration capabilities of MLA Archive
 
 /// User's configuration used to prepare an archive
 pub struct ArchiveWriterConfig {
-    layers_enabled: Layers,
+    pub(crate) layers_enabled: Layers,
 
     // Layers specifics
     pub(crate) compress: CompressionConfig,
     pub(crate) encrypt: EncryptionConfig,
+    pub(crate) sign: SignatureConfig,
+
+    /// Algorithm of per-file checksums, if enabled
+    checksum: Option<ChecksumAlgorithm>,
+
+    /// Hooks reporting the writing progress
+    pub(crate) progress: Box<dyn ProgressReporter>,
+
+    /// Store files with an already written content as references to it
+    pub(crate) dedup: bool,
+    /// Size above which files are written without deduplication, in bytes
+    pub(crate) dedup_max_file_size: u64,
+
+    /// Comment stored unencrypted in the header
+    pub(crate) comment: Option<String>,
+    /// Comment stored in the footer, protected by the layers
+    pub(crate) encrypted_comment: Option<String>,
+    /// Creation time stored in the footer, instead of the finalization time
+    pub(crate) created_at: Option<SystemTime>,
+
+    /// Handling of files started with an already used name
+    pub(crate) duplicate_policy: DuplicatePolicy,
+
+    /// Maximum size of the archive, as stored, in bytes
+    pub(crate) max_bytes: Option<u64>,
+    /// Maximum number of files in the archive
+    pub(crate) max_files: Option<usize>,
+
+    /// Encoding of the blocks other than file contents
+    pub(crate) metadata_encoding: MetadataEncoding,
+
+    /// Size under which file contents are kept in memory, to be written
+    /// together (0 if disabled)
+    pub(crate) coalesce_threshold: usize,
+
+    /// Arrangement of the contents of files written concurrently
+    pub(crate) file_layout: FileLayout,
+
+    /// Cut of the file contents in blocks, instead of the appended contents
+    pub(crate) block_boundary: Option<BlockBoundary>,
+
+    /// Padding written before each file
+    pub(crate) padding_strategy: PaddingStrategy,
+
+    /// Normalization of the entry names
+    pub(crate) path_normalization: PathNormalizationPolicy,
+
+    /// Path of the archive, recorded in the `tracing` spans
+    pub(crate) archive_path: Option<String>,
+}
+
+/// How `ArchiveWriter::start_file` handles a name already used by another
+/// entry, see `ArchiveWriterConfig::duplicate_file_policy`
+///
+/// An archive never contains two entries with the same name, as the footer
+/// maps each name to a single entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// Return `Error::DuplicateFilename`
+    #[default]
+    Error,
+    /// Use the first available name among `<name>_2`, `<name>_3`, etc.
+    /// instead (see `ArchiveWriter::filename`)
+    Rename,
+}
+
+/// Encoding of the archive blocks describing the entries (`FileStart`,
+/// `EndOfFile`, `DirectoryEntry`, etc.), see
+/// `ArchiveWriterConfig::metadata_encoding`
+///
+/// File contents are always stored as is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MetadataEncoding {
+    /// MLA binary encoding
+    #[default]
+    Native,
+    /// CBOR (RFC 8949), readable with off-the-shelf tools. Archives using it
+    /// are only read by readers accepting it
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+/// Arrangement in the archive of the contents of files opened at the same
+/// time, see `ArchiveWriterConfig::file_layout`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileLayout {
+    /// The contents are written as they are appended, in the order of the
+    /// calls, without being kept in memory
+    #[default]
+    AsAppended,
+    /// The content of a file is written in one piece: while a file is being
+    /// written, the contents appended to the other opened files are kept in
+    /// memory, until they are ended or until this file is
+    Sequential,
+    /// The contents of the opened files are kept in memory, and written in
+    /// turn, `chunk_size` bytes of each file at a time, in the order the
+    /// files were started
+    Interleaved { chunk_size: usize },
+}
+
+/// Where file contents are cut in `FileContent` blocks, see
+/// `ArchiveWriterConfig::block_boundary`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockBoundary {
+    /// Blocks of this size, in bytes
+    Fixed(usize),
+    /// Blocks cut where a rolling hash of the content matches a pattern,
+    /// between `min` and `max` bytes long, and `avg` bytes long on average
+    ContentDefined { min: usize, avg: usize, max: usize },
+}
+
+/// Padding written in the archive data before each file, so that the file
+/// positions do not reveal the size of the previous contents, see
+/// `ArchiveWriterConfig::padding_strategy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaddingStrategy {
+    /// No padding
+    #[default]
+    None,
+    /// Start each file at a multiple of this number of bytes
+    AlignTo(usize),
+    /// Padding content of a random length, between `min` and `max` bytes
+    /// (included)
+    RandomJitter { min: usize, max: usize },
 }
 
+impl PaddingStrategy {
+    /// Size of a `Padding` block without its content, in bytes
+    const BLOCK_HEADER_SIZE: u64 = 17;
+
+    /// Length of the `Padding` block content to write at `position` in the
+    /// archive data, if any. `rng` gives the random lengths
+    pub(crate) fn length<R: Rng>(&self, position: u64, rng: &mut R) -> Option<u64> {
+        match *self {
+            PaddingStrategy::None => None,
+            PaddingStrategy::AlignTo(alignment) => {
+                let alignment = alignment as u64;
+                let mut gap = (alignment - position % alignment) % alignment;
+                if gap == 0 {
+                    return None;
+                }
+                // The block must fit in the gap
+                while gap < Self::BLOCK_HEADER_SIZE {
+                    gap += alignment;
+                }
+                Some(gap - Self::BLOCK_HEADER_SIZE)
+            }
+            PaddingStrategy::RandomJitter { min, max } => {
+                Some(rng.gen_range(min as u64, max as u64 + 1))
+            }
+        }
+    }
+
+    /// Upper bound of the size of the padding before a file, in bytes
+    pub(crate) fn max_size(&self) -> u64 {
+        match *self {
+            PaddingStrategy::None => 0,
+            PaddingStrategy::AlignTo(alignment) => Self::BLOCK_HEADER_SIZE + alignment as u64 - 1,
+            PaddingStrategy::RandomJitter { max, .. } => Self::BLOCK_HEADER_SIZE + max as u64,
+        }
+    }
+}
+
+/// Maximum length of a path component, accepted by
+/// `PathNormalizationPolicy::Strict`, in bytes
+const MAX_PATH_COMPONENT_LENGTH: usize = 255;
+
+/// Normalization of the names of the entries (files, directories and symbolic
+/// links) given to `ArchiveWriter`, see `ArchiveWriterConfig::path_normalization`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathNormalizationPolicy {
+    /// Names are stored as given
+    #[default]
+    None,
+    /// `\` are replaced by `/`, for instance in Windows paths
+    ForwardSlash,
+    /// Names are stored as given, but names with a `..` component, starting
+    /// with a separator, with a null byte, or with a component longer than
+    /// 255 bytes are refused with `Error::InvalidFilename`. Both `/` and `\`
+    /// are considered as separators
+    Strict,
+}
+
+impl PathNormalizationPolicy {
+    /// Name to store for the entry `path`
+    pub(crate) fn apply(&self, path: &str) -> Result<String, Error> {
+        match self {
+            PathNormalizationPolicy::None => Ok(path.to_string()),
+            PathNormalizationPolicy::ForwardSlash => Ok(path.replace('\\', "/")),
+            PathNormalizationPolicy::Strict => {
+                let reason = if path.contains('\0') {
+                    Some("null byte")
+                } else if path.starts_with(['/', '\\']) {
+                    Some("absolute path")
+                } else {
+                    path.split(['/', '\\']).find_map(|component| {
+                        if component == ".." {
+                            Some("parent directory component")
+                        } else if component.len() > MAX_PATH_COMPONENT_LENGTH {
+                            Some("component too long")
+                        } else {
+                            None
+                        }
+                    })
+                };
+                match reason {
+                    Some(reason) => Err(Error::InvalidFilename(format!("{}: {}", reason, path))),
+                    None => Ok(path.to_string()),
+                }
+            }
+        }
+    }
+}
+
+/// Maximum length of archive comments, in bytes
+pub const MAX_COMMENT_LENGTH: usize = 4096;
+
+/// Default `ArchiveWriterConfig::coalesce_threshold`, in bytes
+const DEFAULT_COALESCE_THRESHOLD: usize = 4096;
+
+/// Default `ArchiveWriterConfig::dedup_max_file_size`, in bytes
+const DEFAULT_DEDUP_MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+pub use crate::layers::compress::{CompressionAlgorithm, CompressionHint, CompressionLevel};
+
 /// Internal configuration stored in the header, to be reloaded
 #[derive(Serialize, Deserialize)]
 pub struct ArchivePersistentConfig {
@@ -24,6 +258,82 @@ pub struct ArchivePersistentConfig {
 
     // Layers specifics
     pub(crate) encrypt: Option<EncryptionPersistentConfig>,
+    pub(crate) compress: Option<CompressionPersistentConfig>,
+}
+
+/// Internal configuration stored in the header of format v1 archives
+#[derive(Deserialize)]
+pub(crate) struct ArchivePersistentConfigV1 {
+    layers_enabled: Layers,
+
+    // Layers specifics
+    encrypt: Option<EncryptionPersistentConfigV1>,
+}
+
+impl From<ArchivePersistentConfigV1> for ArchivePersistentConfig {
+    fn from(config: ArchivePersistentConfigV1) -> Self {
+        ArchivePersistentConfig {
+            layers_enabled: config.layers_enabled,
+            encrypt: config.encrypt.map(EncryptionPersistentConfig::from),
+            compress: None,
+        }
+    }
+}
+
+/// Internal configuration stored in the header of format v2 archives
+#[derive(Deserialize)]
+pub(crate) struct ArchivePersistentConfigV2 {
+    layers_enabled: Layers,
+
+    // Layers specifics
+    encrypt: Option<EncryptionPersistentConfigV2>,
+}
+
+impl From<ArchivePersistentConfigV2> for ArchivePersistentConfig {
+    fn from(config: ArchivePersistentConfigV2) -> Self {
+        ArchivePersistentConfig {
+            layers_enabled: config.layers_enabled,
+            encrypt: config.encrypt.map(EncryptionPersistentConfig::from),
+            // Compressed with brotli
+            compress: None,
+        }
+    }
+}
+
+/// Internal configuration stored in the header of format v3 to v6 archives
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ArchivePersistentConfigV3 {
+    layers_enabled: Layers,
+
+    // Layers specifics
+    encrypt: Option<EncryptionPersistentConfigV2>,
+    compress: Option<CompressionPersistentConfig>,
+}
+
+impl From<ArchivePersistentConfigV3> for ArchivePersistentConfig {
+    fn from(config: ArchivePersistentConfigV3) -> Self {
+        ArchivePersistentConfig {
+            layers_enabled: config.layers_enabled,
+            encrypt: config.encrypt.map(EncryptionPersistentConfig::from),
+            compress: config.compress,
+        }
+    }
+}
+
+impl ArchivePersistentConfig {
+    /// Configuration in the layout of format v3 to v6 archives, `None` if it
+    /// uses features they do not support
+    pub(crate) fn to_v3(&self) -> Option<ArchivePersistentConfigV3> {
+        let encrypt = match &self.encrypt {
+            Some(encrypt) => Some(encrypt.to_v2()?),
+            None => None,
+        };
+        Some(ArchivePersistentConfigV3 {
+            layers_enabled: self.layers_enabled,
+            encrypt,
+            compress: self.compress.clone(),
+        })
+    }
 }
 
 pub type ConfigResult<'a> = Result<&'a mut ArchiveWriterConfig, ConfigError>;
@@ -35,6 +345,24 @@ impl ArchiveWriterConfig {
             layers_enabled: Layers::EMPTY,
             compress: CompressionConfig::default(),
             encrypt: EncryptionConfig::default(),
+            sign: SignatureConfig::default(),
+            checksum: None,
+            progress: Box::new(NoopProgressReporter),
+            dedup: false,
+            dedup_max_file_size: DEFAULT_DEDUP_MAX_FILE_SIZE,
+            comment: None,
+            encrypted_comment: None,
+            created_at: None,
+            duplicate_policy: DuplicatePolicy::Error,
+            max_bytes: None,
+            max_files: None,
+            metadata_encoding: MetadataEncoding::Native,
+            coalesce_threshold: DEFAULT_COALESCE_THRESHOLD,
+            file_layout: FileLayout::AsAppended,
+            block_boundary: None,
+            padding_strategy: PaddingStrategy::None,
+            path_normalization: PathNormalizationPolicy::None,
+            archive_path: None,
         }
     }
 
@@ -67,6 +395,13 @@ impl ArchiveWriterConfig {
                     None
                 }
             },
+            compress: {
+                if self.is_layers_enabled(Layers::COMPRESS) {
+                    Some(self.compress.to_persistent())
+                } else {
+                    None
+                }
+            },
         })
     }
 
@@ -75,11 +410,282 @@ impl ArchiveWriterConfig {
         self.layers_enabled.contains(layer)
     }
 
+    /// Compute a checksum of each file content, with `algorithm`, and store it
+    /// in the archive
+    pub fn enable_file_checksums(
+        &mut self,
+        algorithm: ChecksumAlgorithm,
+    ) -> &mut ArchiveWriterConfig {
+        self.checksum = Some(algorithm);
+        self
+    }
+
+    /// Algorithm of per-file checksums, if enabled
+    pub fn checksum_algorithm(&self) -> Option<ChecksumAlgorithm> {
+        self.checksum
+    }
+
+    /// Store `text`, up to `MAX_COMMENT_LENGTH` bytes, as a comment in the
+    /// archive header
+    ///
+    /// This comment is NOT encrypted nor compressed, so that it can be shown
+    /// without any key (see `ArchiveReader::comment`). It is covered by the
+    /// signature, if enabled. Use `encrypted_comment` for a confidential one.
+    /// An empty comment is the same as no comment
+    pub fn comment(&mut self, text: &str) -> &mut ArchiveWriterConfig {
+        self.comment = Some(text.to_string()).filter(|text| !text.is_empty());
+        self
+    }
+
+    /// Store `text`, up to `MAX_COMMENT_LENGTH` bytes, as a comment in the
+    /// archive footer
+    ///
+    /// As the footer, this comment goes through the layers: it is only
+    /// revealed after decryption, if the encryption layer is enabled (see
+    /// `ArchiveReader::encrypted_comment`)
+    pub fn encrypted_comment(&mut self, text: &str) -> &mut ArchiveWriterConfig {
+        self.encrypted_comment = Some(text.to_string());
+        self
+    }
+
+    /// Record `time` as the archive creation time, instead of the time of
+    /// `ArchiveWriter::finalize` (see `ArchiveReader::created_at`), for
+    /// instance to produce reproducible archives
+    ///
+    /// It is stored to the second, in the footer; times before the UNIX epoch
+    /// are stored as the epoch
+    pub fn set_created_at(&mut self, time: SystemTime) -> &mut ArchiveWriterConfig {
+        self.created_at = Some(time);
+        self
+    }
+
+    /// Make the archive reproducible: writing the same entries, in the same
+    /// order, with the same configuration and `seed`, produces the same bytes
+    ///
+    /// The encryption key, nonce, and every other random value (ephemeral
+    /// keys, password salt, keys of files encrypted for their own recipients)
+    /// are derived from `seed` and the order of writing. Unless already set,
+    /// the creation time is the UNIX epoch (see `set_created_at`).
+    ///
+    /// As it determines the encryption key, `seed` must be kept as secret as a
+    /// private key, and must not be reused for an archive with another content
+    pub fn deterministic_seed(&mut self, seed: [u8; 32]) -> &mut ArchiveWriterConfig {
+        self.encrypt.set_seed(seed);
+        self.created_at.get_or_insert(UNIX_EPOCH);
+        self
+    }
+
+    /// Report the writing progress to `reporter`
+    pub fn with_progress(
+        &mut self,
+        reporter: Box<dyn ProgressReporter>,
+    ) -> &mut ArchiveWriterConfig {
+        self.progress = reporter;
+        self
+    }
+
+    /// Enable or disable files deduplication
+    ///
+    /// When enabled, a file whose content is identical to the one of a file
+    /// already ended (same size and SHA-256) is stored as a reference to it.
+    /// To do so, the content of each opened file is kept in memory until its
+    /// end, and only written then, if needed. A file with sparse holes is not
+    /// deduplicated
+    ///
+    /// The memory used is therefore up to the size of the opened files, each
+    /// of them being limited to `dedup_max_file_size` (64MB by default)
+    pub fn with_dedup(&mut self, dedup: bool) -> &mut ArchiveWriterConfig {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Set the size, in bytes, above which a file is not deduplicated (see
+    /// `with_dedup`), 64MB by default
+    ///
+    /// Once the content of an opened file exceeds `size`, the content kept in
+    /// memory is written, and the rest of the file is written as it is
+    /// appended, as without deduplication
+    pub fn dedup_max_file_size(&mut self, size: u64) -> &mut ArchiveWriterConfig {
+        self.dedup_max_file_size = size;
+        self
+    }
+
+    /// Set how files started with an already used name are handled
+    /// (`DuplicatePolicy::Error` by default)
+    ///
+    /// Directories and symbolic links with an already used name always
+    /// return `Error::DuplicateFilename`
+    pub fn duplicate_file_policy(&mut self, policy: DuplicatePolicy) -> &mut ArchiveWriterConfig {
+        self.duplicate_policy = policy;
+        self
+    }
+
+    /// Set the encoding of the blocks describing the entries
+    /// (`MetadataEncoding::Native` by default)
+    ///
+    /// With `MetadataEncoding::Cbor`, each of these blocks is stored as a CBOR
+    /// item, which can be inspected with off-the-shelf CBOR tools, while file
+    /// contents stay binary. Such archives are declared as using CBOR blocks:
+    /// readers must accept them, with `ArchiveReaderConfig::metadata_encoding`
+    pub fn metadata_encoding(&mut self, encoding: MetadataEncoding) -> &mut ArchiveWriterConfig {
+        self.metadata_encoding = encoding;
+        self
+    }
+
+    /// Refuse, with `Error::ArchiveSizeLimitExceeded`, a file content which
+    /// could make the archive bigger than `limit` bytes
+    ///
+    /// The limit applies to the archive as stored, ie. after compression and
+    /// encryption. Before writing a content, the bytes already written plus
+    /// an upper bound of the ones this content may add are checked against
+    /// it. Data buffered by the compression layer, and the archive footer,
+    /// are not accounted for until they are written
+    pub fn max_bytes(&mut self, limit: u64) -> &mut ArchiveWriterConfig {
+        self.max_bytes = Some(limit);
+        self
+    }
+
+    /// Refuse, with `Error::FileCountLimitExceeded`, to start more than
+    /// `limit` files. Directories and symbolic links are not counted
+    pub fn max_files(&mut self, limit: usize) -> &mut ArchiveWriterConfig {
+        self.max_files = Some(limit);
+        self
+    }
+
+    /// Keep the contents of less than `threshold` bytes appended to a file in
+    /// memory, and write them as a single `FileContent` block once they reach
+    /// `threshold` bytes together (default: 4KB). 0 disables it
+    ///
+    /// This limits the overhead of block headers when many small contents are
+    /// appended, for instance through a `StreamWriter`. The contents kept are
+    /// written when the file is ended, when another content is appended, or
+    /// on `ArchiveWriter::flush`
+    pub fn coalesce_threshold(&mut self, threshold: usize) -> &mut ArchiveWriterConfig {
+        self.coalesce_threshold = threshold;
+        self
+    }
+
+    /// Set how the contents of files opened at the same time are arranged in
+    /// the archive (`FileLayout::AsAppended` by default)
+    ///
+    /// With `FileLayout::AsAppended`, contents are written in the order they
+    /// are appended, interleaving the files written at the same time.
+    ///
+    /// With `FileLayout::Sequential`, the content of each file is contiguous,
+    /// making it faster to read on its own, at the cost of keeping in memory
+    /// the contents appended to a file while another one is being written.
+    /// Writing one file at a time keeps nothing in memory. A file ended while
+    /// another one is being written is written at once, in the middle of the
+    /// other one.
+    ///
+    /// With `FileLayout::Interleaved`, a round of `chunk_size` bytes of each
+    /// opened file is written as soon as every one of them has that much
+    /// content pending. The rest of a file is written when it is ended. As
+    /// long as one of the opened files gets no content, the contents of the
+    /// others are kept in memory.
+    ///
+    /// The memory used by these two layouts is not bounded: they are meant
+    /// for files of known, reasonable sizes. With `Sequential` and
+    /// `Interleaved`, the contents kept are written before a sparse hole or a
+    /// pre-compressed content of the same file, and files deduplicated (see
+    /// `with_dedup`) are written when they are ended
+    pub fn file_layout(&mut self, layout: FileLayout) -> &mut ArchiveWriterConfig {
+        self.file_layout = layout;
+        self
+    }
+
+    /// Cut the file contents in `FileContent` blocks according to `boundary`,
+    /// instead of writing a block for each content appended (coalesced, see
+    /// `coalesce_threshold`)
+    ///
+    /// With `BlockBoundary::ContentDefined`, block boundaries follow the
+    /// content: inserting bytes in a file only changes the blocks around the
+    /// insertion, while it changes all the following ones with
+    /// `BlockBoundary::Fixed`. Identical parts of files, or of successive
+    /// versions of an archive, are then stored in identical blocks, which
+    /// helps deduplicating or delta-encoding the archives without layers.
+    ///
+    /// The end of the content not forming a whole block is kept in memory
+    /// until the file is ended, or until `ArchiveWriter::flush`. Contents of
+    /// files deduplicated (see `with_dedup`) are still written as one block
+    pub fn block_boundary(&mut self, boundary: BlockBoundary) -> &mut ArchiveWriterConfig {
+        self.block_boundary = Some(boundary);
+        self
+    }
+
+    /// Write a `Padding` block, filled with random bytes, before each file
+    /// according to `strategy` (`PaddingStrategy::None` by default)
+    ///
+    /// Positions are the ones in the archive data, before the compression and
+    /// encryption layers: with `PaddingStrategy::AlignTo`, the `FileStart`
+    /// blocks are aligned in the archive data, and in the stored archive only
+    /// without layers. Padding is skipped by readers. Random bytes and
+    /// lengths follow `deterministic_seed`, if set
+    pub fn padding_strategy(&mut self, strategy: PaddingStrategy) -> &mut ArchiveWriterConfig {
+        self.padding_strategy = strategy;
+        self
+    }
+
+    /// Normalize the names of the files, directories and symbolic links
+    /// added to the archive according to `policy`
+    /// (`PathNormalizationPolicy::None` by default)
+    ///
+    /// Names given to `ArchiveWriter::rename_file` are normalized too.
+    /// Targets of symbolic links are stored as is
+    pub fn path_normalization(
+        &mut self,
+        policy: PathNormalizationPolicy,
+    ) -> &mut ArchiveWriterConfig {
+        self.path_normalization = policy;
+        self
+    }
+
+    /// Record `path` as the `archive_path` field of the spans, with the
+    /// `tracing` feature
+    ///
+    /// The archive is written to any `Write`, whose path, if any, is unknown:
+    /// without this, spans have no `archive_path` field
+    pub fn archive_path(&mut self, path: &str) -> &mut ArchiveWriterConfig {
+        self.archive_path = Some(path.to_string());
+        self
+    }
+
     /// Consistency check
     pub fn check(&self) -> Result<(), ConfigError> {
         if self.is_layers_enabled(Layers::ENCRYPT) {
             self.encrypt.check()?;
         }
+        if self.is_layers_enabled(Layers::SIGN) {
+            self.sign.check()?;
+        }
+        if self.is_layers_enabled(Layers::COMPRESS) {
+            self.compress.check()?;
+        }
+        if [&self.comment, &self.encrypted_comment]
+            .iter()
+            .any(|comment| comment.as_ref().map_or(0, String::len) > MAX_COMMENT_LENGTH)
+        {
+            return Err(ConfigError::CommentTooLong);
+        }
+        if self.file_layout == (FileLayout::Interleaved { chunk_size: 0 }) {
+            return Err(ConfigError::InvalidChunkSize);
+        }
+        match self.block_boundary {
+            Some(BlockBoundary::Fixed(0)) => return Err(ConfigError::InvalidChunkSize),
+            Some(BlockBoundary::ContentDefined { min, avg, max })
+                if min == 0 || min > avg || avg > max =>
+            {
+                return Err(ConfigError::InvalidChunkSize)
+            }
+            _ => {}
+        }
+        match self.padding_strategy {
+            PaddingStrategy::AlignTo(0) => return Err(ConfigError::InvalidPaddingStrategy),
+            PaddingStrategy::RandomJitter { min, max } if min > max => {
+                return Err(ConfigError::InvalidPaddingStrategy)
+            }
+            _ => {}
+        }
         Ok(())
     }
 }
@@ -93,25 +699,148 @@ impl std::default::Default for ArchiveWriterConfig {
             layers_enabled: Layers::default(),
             compress: CompressionConfig::default(),
             encrypt: EncryptionConfig::default(),
+            sign: SignatureConfig::default(),
+            checksum: None,
+            progress: Box::new(NoopProgressReporter),
+            dedup: false,
+            dedup_max_file_size: DEFAULT_DEDUP_MAX_FILE_SIZE,
+            comment: None,
+            encrypted_comment: None,
+            created_at: None,
+            duplicate_policy: DuplicatePolicy::Error,
+            max_bytes: None,
+            max_files: None,
+            metadata_encoding: MetadataEncoding::Native,
+            coalesce_threshold: DEFAULT_COALESCE_THRESHOLD,
+            file_layout: FileLayout::AsAppended,
+            block_boundary: None,
+            padding_strategy: PaddingStrategy::None,
+            path_normalization: PathNormalizationPolicy::None,
+            archive_path: None,
         }
     }
 }
 
+/// Keys and passwords are zeroized on drop, by the layers configurations
+impl ZeroizeOnDrop for ArchiveWriterConfig {}
+
 /// User's configuration used to read an archive
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct ArchiveReaderConfig {
     pub layers_enabled: Layers,
 
     // Layers specifics
     pub encrypt: EncryptionReaderConfig,
+    pub sign: SignatureReaderConfig,
+    pub compress: CompressionReaderConfig,
+
+    /// Verify per-file checksums, if any, on extraction
+    pub(crate) checksums_verification: bool,
+    /// Skip optional blocks of unknown types, instead of failing
+    pub(crate) skip_unknown_blocks: bool,
+    /// Go on with the next files when one can't be extracted
+    pub(crate) best_effort_extraction: bool,
+    /// Encoding of the blocks accepted, in addition to the native one
+    pub(crate) metadata_encoding: MetadataEncoding,
+    /// Receiver of the accesses to the archive, shared by clones
+    pub(crate) audit_log: Option<Arc<Mutex<Box<dyn AuditLogger>>>>,
+    /// Path of the archive, recorded in the `tracing` spans
+    pub(crate) archive_path: Option<String>,
 }
 
+/// Keys and passwords are zeroized on drop, by the layers configurations
+impl ZeroizeOnDrop for ArchiveReaderConfig {}
+
 impl ArchiveReaderConfig {
     /// Start a builder, without any specific option
     pub fn new() -> Self {
         Self {
             layers_enabled: Layers::EMPTY,
             encrypt: EncryptionReaderConfig::default(),
+            sign: SignatureReaderConfig::default(),
+            compress: CompressionReaderConfig::default(),
+            checksums_verification: false,
+            skip_unknown_blocks: true,
+            best_effort_extraction: false,
+            metadata_encoding: MetadataEncoding::Native,
+            audit_log: None,
+            archive_path: None,
+        }
+    }
+
+    /// Skip (default), or refuse with `Error::UnknownBlockType`, the optional
+    /// blocks of a type unknown to this implementation, such as blocks added
+    /// by newer versions of the format
+    pub fn skip_unknown_blocks(&mut self, skip: bool) -> &mut ArchiveReaderConfig {
+        self.skip_unknown_blocks = skip;
+        self
+    }
+
+    /// Enable or disable per-file checksums verification
+    ///
+    /// When enabled, the checksum of files (if present in the archive) are
+    /// computed on extraction and compared with the stored ones; on mismatch,
+    /// `Error::ChecksumMismatch` is returned
+    pub fn verify_checksums(&mut self, verify: bool) -> &mut ArchiveReaderConfig {
+        self.checksums_verification = verify;
+        self
+    }
+
+    /// Enable or disable best-effort extraction, with
+    /// `helpers::linear_extract_best_effort`
+    ///
+    /// When enabled, a file which can't be read, for instance because of a
+    /// corrupted block (wrong encryption tag, invalid compressed data), is
+    /// extracted as empty and reported, and the extraction goes on with the
+    /// next files. When disabled (default), the extraction stops at the first
+    /// error
+    pub fn best_effort_extraction(&mut self, enabled: bool) -> &mut ArchiveReaderConfig {
+        self.best_effort_extraction = enabled;
+        self
+    }
+
+    /// Accept archives whose blocks use `encoding`, see
+    /// `ArchiveWriterConfig::metadata_encoding`
+    ///
+    /// By default, only the native encoding is accepted: opening an archive
+    /// using CBOR blocks returns `Error::UnsupportedFeature`
+    pub fn metadata_encoding(&mut self, encoding: MetadataEncoding) -> &mut ArchiveReaderConfig {
+        self.metadata_encoding = encoding;
+        self
+    }
+
+    /// Report every access to the archive to `logger`, see `AuditLogger`
+    pub fn enable_audit_log(&mut self, logger: Box<dyn AuditLogger>) -> &mut ArchiveReaderConfig {
+        self.audit_log = Some(Arc::new(Mutex::new(logger)));
+        self
+    }
+
+    /// Record `path` as the `archive_path` field of the spans, with the
+    /// `tracing` feature
+    ///
+    /// The archive is read from any `Read + Seek`, whose path, if any, is
+    /// unknown: without this, spans have no `archive_path` field. It is set
+    /// by `ArchiveReader::from_mmap`
+    pub fn archive_path(&mut self, path: &str) -> &mut ArchiveReaderConfig {
+        self.archive_path = Some(path.to_string());
+        self
+    }
+
+    /// Report an access of type `event_type` to the archive `archive_id` to
+    /// the audit logger, if any
+    pub(crate) fn audit(
+        &self,
+        archive_id: Option<[u8; ARCHIVE_ID_SIZE]>,
+        event_type: AuditEventType,
+    ) {
+        if let Some(audit_log) = &self.audit_log {
+            // A logger which panicked can still be used
+            let mut logger = audit_log.lock().unwrap_or_else(PoisonError::into_inner);
+            logger.log(AuditEvent {
+                event_type,
+                timestamp: SystemTime::now(),
+                archive_id: archive_id.unwrap_or_default(),
+            });
         }
     }
 
@@ -130,6 +859,9 @@ impl ArchiveReaderConfig {
                 }
             }
         }
+        if let Some(to_load) = config.compress {
+            self.compress.load_persistent(to_load);
+        }
         Ok(self)
     }
 }
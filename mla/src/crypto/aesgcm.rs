@@ -18,6 +18,7 @@ pub const BLOCK_SIZE: usize = 128 / 8;
 pub const TAG_LENGTH: usize = BLOCK_SIZE;
 
 // Inspired from RustCrypto's AesGcm implementation
+#[derive(Clone)]
 pub struct AesGcm256 {
     cipher: aes_ctr::Aes256Ctr,
     /// Gallois Hash, for data authentication
@@ -72,20 +73,30 @@ impl AesGcm256 {
         })
     }
 
-    pub fn encrypt(&mut self, mut buffer: &mut [u8]) {
+    pub fn encrypt(&mut self, buffer: &mut [u8]) {
+        self.cipher.encrypt(buffer);
+        self.authenticate(buffer);
+    }
+
+    /// Decrypt `buffer`, possibly in several calls as with `encrypt`. The tag
+    /// to compare to the expected one is then obtained with `into_tag`
+    pub fn decrypt_update(&mut self, buffer: &mut [u8]) {
+        self.authenticate(buffer);
+        self.cipher.decrypt(buffer);
+    }
+
+    /// Update the `ghash` with the encrypted data `buffer`
+    fn authenticate(&mut self, mut buffer: &[u8]) {
         // Update the number of byte encrypted
         self.bytes_encrypted += buffer.len() as u64;
 
         // Finish the current block, if any
         if !self.current_block.is_empty() {
             if (self.current_block.len() + buffer.len()) < BLOCK_SIZE {
-                self.cipher.encrypt(buffer);
                 self.current_block.extend_from_slice(buffer);
                 return;
             } else {
-                let (in_block, out_block) =
-                    buffer.split_at_mut(BLOCK_SIZE - self.current_block.len());
-                self.cipher.encrypt(in_block);
+                let (in_block, out_block) = buffer.split_at(BLOCK_SIZE - self.current_block.len());
                 self.current_block.extend_from_slice(in_block);
                 // `current_block` length is now BLOCK_SIZE -> update GHash and
                 // clear it
@@ -98,20 +109,13 @@ impl AesGcm256 {
             }
         }
 
-        let mut chunks = buffer.chunks_exact_mut(BLOCK_SIZE);
-
-        // Interleaved ghash update
+        let mut chunks = buffer.chunks_exact(BLOCK_SIZE);
         for chunk in &mut chunks {
-            self.cipher.encrypt(chunk);
             self.ghash.update(GenericArray::from_slice(chunk));
         }
 
-        // Encrypt and save extra encrypted bytes for further GHash computation
-        let rem = chunks.into_remainder();
-        if !rem.is_empty() {
-            self.cipher.encrypt(rem);
-            self.current_block.extend_from_slice(rem);
-        }
+        // Save extra encrypted bytes for further GHash computation
+        self.current_block.extend_from_slice(chunks.remainder());
     }
 
     /// Finalize encryption and returns the associated tag
@@ -289,5 +293,16 @@ mod tests {
         let tag = crate_cipher.decrypt(&mut buf);
         assert_eq!(buf.as_slice(), &msg[..]);
         assert_eq!(&tag[..], expected_tag.as_slice());
+
+        // Authenticated decryption, in several parts
+        for size in &[1, BLOCK_SIZE + 1] {
+            let mut crate_cipher = AesGcm256::new(key, nonce, associated_data).unwrap();
+            let mut buf = extern_ciphertext[..extern_ciphertext.len() - TAG_LENGTH].to_vec();
+            for chunk in buf.chunks_mut(*size) {
+                crate_cipher.decrypt_update(chunk);
+            }
+            assert_eq!(buf.as_slice(), &msg[..]);
+            assert_eq!(&crate_cipher.into_tag()[..], expected_tag.as_slice());
+        }
     }
 }
@@ -27,19 +27,26 @@ fn derive_key(
     Ok(output)
 }
 
-#[derive(Serialize, Deserialize)]
-struct KeyAndTag {
-    key: [u8; KEY_SIZE],
-    tag: [u8; aesgcm::TAG_LENGTH],
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct KeyAndTag {
+    pub(crate) key: [u8; KEY_SIZE],
+    pub(crate) tag: [u8; aesgcm::TAG_LENGTH],
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub(crate) struct MultiRecipientPersistent {
     /// Ephemeral public key
     public: [u8; 32],
     encrypted_keys: Vec<KeyAndTag>,
 }
 
+impl MultiRecipientPersistent {
+    /// Number of recipients the key is stored for
+    pub(crate) fn recipients_count(&self) -> usize {
+        self.encrypted_keys.len()
+    }
+}
+
 /// Perform ECIES with several recipients, to share a common `key`, and return a
 /// serializable structure (Key-wrapping made thanks to AesGcm256)
 pub(crate) fn store_key_for_multi_recipients<T>(
@@ -47,6 +54,18 @@ pub(crate) fn store_key_for_multi_recipients<T>(
     key: &[u8; KEY_SIZE],
     csprng: &mut T,
 ) -> Result<MultiRecipientPersistent, Error>
+where
+    T: RngCore + CryptoRng,
+{
+    store_keys_for_recipients(recipients.iter().map(|recipient| (recipient, key)), csprng)
+}
+
+/// Same as `store_key_for_multi_recipients`, each recipient getting its own
+/// key
+pub(crate) fn store_keys_for_recipients<'a, T>(
+    recipients: impl Iterator<Item = (&'a PublicKey, &'a [u8; KEY_SIZE])>,
+    csprng: &mut T,
+) -> Result<MultiRecipientPersistent, Error>
 where
     T: RngCore + CryptoRng,
 {
@@ -56,7 +75,7 @@ where
 
     let public = PublicKey::from(&ephemeral);
     let mut encrypted_keys = Vec::new();
-    for recipient in recipients.iter() {
+    for (recipient, key) in recipients {
         // Perform an ECIES to obtain the common key
         let dh_key = derive_key(&ephemeral, recipient, KEY_SIZE)?;
 
@@ -87,17 +106,26 @@ pub(crate) fn retrieve_key(
     persist: &MultiRecipientPersistent,
     private_key: &StaticSecret,
 ) -> Result<Option<[u8; KEY_SIZE]>, Error> {
+    Ok(retrieve_key_with_index(persist, private_key)?.map(|(_index, key)| key))
+}
+
+/// Same as `retrieve_key`, also returning the index of the recipient
+/// corresponding to `private_key`
+pub(crate) fn retrieve_key_with_index(
+    persist: &MultiRecipientPersistent,
+    private_key: &StaticSecret,
+) -> Result<Option<(usize, [u8; KEY_SIZE])>, Error> {
     // Perform an ECIES to obtain the common key
     let key = derive_key(private_key, &PublicKey::from(persist.public), KEY_SIZE)?;
 
     // Try to find the correct key using the tag validation
-    for keytag in persist.encrypted_keys.iter() {
+    for (index, keytag) in persist.encrypted_keys.iter().enumerate() {
         let mut cipher = aesgcm::AesGcm256::new(key.as_slice(), ECIES_NONCE, b"")?;
         let mut data = [0u8; KEY_SIZE];
         data.copy_from_slice(&keytag.key);
         let tag = cipher.decrypt(&mut data);
         if tag.ct_eq(&keytag.tag).unwrap_u8() == 1 {
-            return Ok(Some(data));
+            return Ok(Some((index, data)));
         }
     }
     Ok(None)
@@ -0,0 +1,200 @@
+use crate::crypto::aesgcm::{AesGcm256, ConstantTimeEq, TAG_LENGTH};
+use crate::crypto::ecc::{retrieve_key, store_key_for_multi_recipients, MultiRecipientPersistent};
+use crate::errors::Error;
+use rand::{CryptoRng, Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, Read};
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroize;
+
+const KEY_SIZE: usize = 32;
+// As the key is completely random and used only once, no need for a random
+// NONCE
+const FILE_NONCE: &[u8; 12] = b"FILE NONCE00";
+
+/// Key of a file encrypted for its own recipients, stored in its
+/// `FileRecipients` block
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FileRecipientsPersistent {
+    multi_recipient: MultiRecipientPersistent,
+}
+
+impl fmt::Debug for FileRecipientsPersistent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileRecipientsPersistent")
+            .field("recipients", &self.multi_recipient.recipients_count())
+            .finish()
+    }
+}
+
+/// Encryption of the content of a file for its own recipients
+pub(crate) struct FileEncryptor {
+    cipher: AesGcm256,
+}
+
+impl FileEncryptor {
+    /// Create an encryptor with a fresh key from `csprng`, and the key stored
+    /// for `recipients`
+    pub(crate) fn new<T>(
+        recipients: &[PublicKey],
+        csprng: &mut T,
+    ) -> Result<(FileEncryptor, FileRecipientsPersistent), Error>
+    where
+        T: RngCore + CryptoRng,
+    {
+        let mut key = csprng.gen::<[u8; KEY_SIZE]>();
+        let multi_recipient = store_key_for_multi_recipients(recipients, &key, csprng);
+        let cipher = AesGcm256::new(&key, FILE_NONCE, b"");
+        key.zeroize();
+        Ok((
+            FileEncryptor { cipher: cipher? },
+            FileRecipientsPersistent {
+                multi_recipient: multi_recipient?,
+            },
+        ))
+    }
+
+    /// Finalize the encryption, and return the tag to store in the
+    /// `FileTag` block
+    pub(crate) fn into_tag(self) -> [u8; TAG_LENGTH] {
+        self.cipher.into_tag().into()
+    }
+}
+
+/// Decryption of the content of a file encrypted for its own recipients
+#[derive(Clone)]
+pub(crate) struct FileDecryptor {
+    cipher: AesGcm256,
+}
+
+impl FileDecryptor {
+    /// Retrieve the key of a file with one of `private_keys`
+    ///
+    /// Fails with `Error::PrivateKeyNeeded` if none of them is a recipient
+    pub(crate) fn new(
+        persist: &FileRecipientsPersistent,
+        private_keys: &[StaticSecret],
+    ) -> Result<FileDecryptor, Error> {
+        for private_key in private_keys {
+            if let Some(mut key) = retrieve_key(&persist.multi_recipient, private_key)? {
+                let cipher = AesGcm256::new(&key, FILE_NONCE, b"");
+                key.zeroize();
+                return Ok(FileDecryptor { cipher: cipher? });
+            }
+        }
+        Err(Error::PrivateKeyNeeded)
+    }
+
+    /// Decrypt `data`, the next part of the stored content
+    pub(crate) fn decrypt(&mut self, data: &mut [u8]) {
+        self.cipher.decrypt_update(data);
+    }
+
+    /// Check the whole content decrypted against `tag`, from the `FileTag`
+    /// block
+    pub(crate) fn check_tag(self, tag: &[u8; TAG_LENGTH]) -> Result<(), Error> {
+        if self.cipher.into_tag().ct_eq(tag).unwrap_u8() == 1 {
+            Ok(())
+        } else {
+            Err(Error::AuthenticatedDecryptionWrongTag)
+        }
+    }
+}
+
+impl fmt::Debug for FileDecryptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FileDecryptor")
+    }
+}
+
+/// Wrapper over a `impl Read`, encrypting the data read if `encryptor` is set
+pub(crate) struct FileEncryptReader<'a, R: Read> {
+    inner: R,
+    encryptor: Option<&'a mut FileEncryptor>,
+}
+
+impl<'a, R: Read> FileEncryptReader<'a, R> {
+    pub(crate) fn new(inner: R, encryptor: Option<&'a mut FileEncryptor>) -> Self {
+        Self { inner, encryptor }
+    }
+}
+
+impl<'a, R: Read> Read for FileEncryptReader<'a, R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(into)?;
+        if let Some(encryptor) = &mut self.encryptor {
+            encryptor.cipher.encrypt(&mut into[..read]);
+        }
+        Ok(read)
+    }
+}
+
+/// Wrapper over a `impl Read`, decrypting the data read if `decryptor` is set
+pub(crate) struct FileDecryptReader<'a, R: Read> {
+    inner: R,
+    decryptor: Option<&'a mut FileDecryptor>,
+}
+
+impl<'a, R: Read> FileDecryptReader<'a, R> {
+    pub(crate) fn new(inner: R, decryptor: Option<&'a mut FileDecryptor>) -> Self {
+        Self { inner, decryptor }
+    }
+}
+
+impl<'a, R: Read> Read for FileDecryptReader<'a, R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(into)?;
+        if let Some(decryptor) = &mut self.decryptor {
+            decryptor.decrypt(&mut into[..read]);
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    #[test]
+    fn file_encryption() {
+        let mut csprng = ChaChaRng::from_entropy();
+        let recipient = StaticSecret::new(&mut csprng);
+        let other = StaticSecret::new(&mut csprng);
+        let (mut encryptor, persist) =
+            FileEncryptor::new(&[PublicKey::from(&recipient)], &mut csprng).unwrap();
+
+        let content = vec![42u8; 100];
+        let mut encrypted = Vec::new();
+        FileEncryptReader::new(content.as_slice(), Some(&mut encryptor))
+            .read_to_end(&mut encrypted)
+            .unwrap();
+        assert_ne!(encrypted, content);
+        let tag = encryptor.into_tag();
+
+        assert!(matches!(
+            FileDecryptor::new(&persist, std::slice::from_ref(&other)),
+            Err(Error::PrivateKeyNeeded)
+        ));
+        let decryptor = FileDecryptor::new(&persist, &[other, recipient]).unwrap();
+
+        let mut ok = decryptor.clone();
+        let mut decrypted = Vec::new();
+        FileDecryptReader::new(encrypted.as_slice(), Some(&mut ok))
+            .read_to_end(&mut decrypted)
+            .unwrap();
+        assert_eq!(decrypted, content);
+        ok.check_tag(&tag).unwrap();
+
+        // Tampered content
+        let mut tampered = decryptor;
+        encrypted[0] ^= 1;
+        tampered.decrypt(&mut encrypted);
+        assert!(matches!(
+            tampered.check_tag(&tag),
+            Err(Error::AuthenticatedDecryptionWrongTag)
+        ));
+    }
+}
@@ -1,4 +1,6 @@
+use crate::errors::Error;
 use sha2::{Digest, Sha256};
+use std::convert::TryInto;
 use std::io;
 use std::io::Read;
 
@@ -24,3 +26,116 @@ impl<'a, R: Read> Read for HashWrapperReader<'a, R> {
         Ok(read)
     }
 }
+
+/// Algorithms available for per-file checksums
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ChecksumAlgorithm {
+    Blake3 = 0x00,
+}
+
+/// Per-file checksum, as stored in the archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Checksum {
+    Blake3([u8; blake3::OUT_LEN]),
+}
+
+impl Checksum {
+    pub fn algorithm(&self) -> ChecksumAlgorithm {
+        match self {
+            Checksum::Blake3(_) => ChecksumAlgorithm::Blake3,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Checksum::Blake3(digest) => digest,
+        }
+    }
+
+    /// Build a `Checksum` from its algorithm identifier and digest
+    pub(crate) fn from_bytes(algorithm: u8, digest: &[u8]) -> Result<Self, Error> {
+        if algorithm == ChecksumAlgorithm::Blake3 as u8 {
+            Ok(Checksum::Blake3(
+                digest.try_into().or(Err(Error::DeserializationError))?,
+            ))
+        } else {
+            Err(Error::DeserializationError)
+        }
+    }
+}
+
+/// Compute a `Checksum` on data
+#[derive(Clone, Debug)]
+pub(crate) enum ChecksumHasher {
+    Blake3(blake3::Hasher),
+}
+
+impl ChecksumHasher {
+    pub(crate) fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Blake3 => ChecksumHasher::Blake3(blake3::Hasher::new()),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            ChecksumHasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    pub(crate) fn finalize(&self) -> Checksum {
+        match self {
+            ChecksumHasher::Blake3(hasher) => Checksum::Blake3(*hasher.finalize().as_bytes()),
+        }
+    }
+}
+
+pub(crate) struct ChecksumWrapperReader<'a, R: Read> {
+    /// Wrapper over a `impl Read` updating `hasher` on each call to `read`
+    inner: R,
+    hasher: &'a mut ChecksumHasher,
+}
+
+impl<'a, R: Read> ChecksumWrapperReader<'a, R> {
+    pub(crate) fn new(inner: R, hasher: &'a mut ChecksumHasher) -> Self {
+        Self { inner, hasher }
+    }
+}
+
+impl<'a, R: Read> Read for ChecksumWrapperReader<'a, R> {
+    /// Wrapper on inner with checksum update
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(into)?;
+        self.hasher.update(&into[..read]);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blake3_checksum() {
+        // Test vector from the BLAKE3 reference implementation
+        let mut hasher = ChecksumHasher::new(ChecksumAlgorithm::Blake3);
+        hasher.update(b"");
+        let checksum = hasher.finalize();
+        assert_eq!(checksum.algorithm(), ChecksumAlgorithm::Blake3);
+        assert_eq!(
+            checksum.as_bytes(),
+            hex::decode("af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262")
+                .unwrap()
+                .as_slice()
+        );
+        assert_eq!(
+            Checksum::from_bytes(ChecksumAlgorithm::Blake3 as u8, checksum.as_bytes()).unwrap(),
+            checksum
+        );
+        assert!(Checksum::from_bytes(0xAA, checksum.as_bytes()).is_err());
+        assert!(Checksum::from_bytes(ChecksumAlgorithm::Blake3 as u8, &[0u8; 3]).is_err());
+    }
+}
@@ -1,3 +1,6 @@
 pub mod aesgcm;
 pub mod ecc;
+pub mod file;
 pub mod hash;
+pub mod password;
+pub mod threshold;
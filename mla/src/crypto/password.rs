@@ -0,0 +1,212 @@
+use crate::crypto::aesgcm;
+use crate::crypto::aesgcm::ConstantTimeEq;
+use crate::crypto::ecc::KeyAndTag;
+use crate::errors::{ConfigError, Error};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+const KEY_SIZE: usize = 32;
+const SALT_SIZE: usize = 16;
+const PASSWORD_NONCE: &[u8; 12] = b"PASSWD NONCE";
+
+/// Maximum memory size accepted, in KiB (1 GiB). As the parameters are read
+/// from the archive header, these maximums prevent a crafted archive from
+/// making the reader allocate too much memory, or run for too long
+pub const MAX_M_COST: u32 = 1024 * 1024;
+/// Maximum number of iterations accepted, see `MAX_M_COST`
+pub const MAX_T_COST: u32 = 64;
+/// Maximum degree of parallelism accepted, see `MAX_M_COST`
+pub const MAX_P_COST: u32 = 64;
+
+/// Argon2id parameters, used to derive a key from a password
+///
+/// The higher these costs, the harder a bruteforce on the password is, but
+/// the longer the archive takes to open. Defaults are the ones recommended by
+/// the `argon2` crate. Costs are at most `MAX_M_COST`, `MAX_T_COST` and
+/// `MAX_P_COST`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Argon2Params {
+    /// Memory size, in KiB
+    pub m_cost: u32,
+    /// Number of iterations
+    pub t_cost: u32,
+    /// Degree of parallelism
+    pub p_cost: u32,
+}
+
+impl std::default::Default for Argon2Params {
+    fn default() -> Self {
+        Argon2Params {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl Argon2Params {
+    /// Check the parameters are accepted by Argon2, and below the maximums
+    pub(crate) fn check(&self) -> Result<(), ConfigError> {
+        self.to_argon2().map(drop)
+    }
+
+    fn to_argon2(self) -> Result<Argon2<'static>, ConfigError> {
+        if self.m_cost > MAX_M_COST || self.t_cost > MAX_T_COST || self.p_cost > MAX_P_COST {
+            return Err(ConfigError::InvalidArgon2Params);
+        }
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(KEY_SIZE))
+            .map_err(|_| ConfigError::InvalidArgon2Params)?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+/// Derive a key from `password` using Argon2id
+fn derive_key(
+    password: &[u8],
+    salt: &[u8; SALT_SIZE],
+    params: Argon2Params,
+) -> Result<[u8; KEY_SIZE], Error> {
+    let mut output = [0u8; KEY_SIZE];
+    params
+        .to_argon2()?
+        .hash_password_into(password, salt, &mut output)
+        .map_err(|_| ConfigError::InvalidArgon2Params)?;
+    Ok(output)
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct PasswordPersistent {
+    /// Random salt, unique per archive
+    salt: [u8; SALT_SIZE],
+    params: Argon2Params,
+    encrypted_key: KeyAndTag,
+}
+
+/// Wrap the shared `key` with a key derived from `password`, and return a
+/// serializable structure (Key-wrapping made thanks to AesGcm256)
+pub(crate) fn store_key_for_password<T>(
+    password: &[u8],
+    params: Argon2Params,
+    key: &[u8; KEY_SIZE],
+    csprng: &mut T,
+) -> Result<PasswordPersistent, Error>
+where
+    T: RngCore + CryptoRng,
+{
+    let mut salt = [0u8; SALT_SIZE];
+    csprng.try_fill_bytes(&mut salt)?;
+    let mut password_key = derive_key(password, &salt, params)?;
+
+    // As the derived key depends on a random salt and is used only once, no
+    // need for a random NONCE
+    let mut cipher = aesgcm::AesGcm256::new(&password_key, PASSWORD_NONCE, b"")?;
+    password_key.zeroize();
+    let mut encrypted_key = [0u8; KEY_SIZE];
+    encrypted_key.copy_from_slice(key);
+    cipher.encrypt(&mut encrypted_key);
+    let mut tag = [0u8; aesgcm::TAG_LENGTH];
+    tag.copy_from_slice(&cipher.into_tag());
+
+    Ok(PasswordPersistent {
+        salt,
+        params,
+        encrypted_key: KeyAndTag {
+            key: encrypted_key,
+            tag,
+        },
+    })
+}
+
+/// Try to recover the shared key from the `PasswordPersistent`, using `password`
+pub(crate) fn retrieve_key_with_password(
+    persist: &PasswordPersistent,
+    password: &[u8],
+) -> Result<Option<[u8; KEY_SIZE]>, Error> {
+    let mut password_key = derive_key(password, &persist.salt, persist.params)?;
+    let mut cipher = aesgcm::AesGcm256::new(&password_key, PASSWORD_NONCE, b"")?;
+    password_key.zeroize();
+    let mut data = [0u8; KEY_SIZE];
+    data.copy_from_slice(&persist.encrypted_key.key);
+    let tag = cipher.decrypt(&mut data);
+    if tag.ct_eq(&persist.encrypted_key.tag).unwrap_u8() == 1 {
+        Ok(Some(data))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaChaRng;
+
+    /// Weak parameters, to keep tests fast
+    const TEST_PARAMS: Argon2Params = Argon2Params {
+        m_cost: 64,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    #[test]
+    fn password() {
+        let mut csprng = ChaChaRng::from_entropy();
+        let key = csprng.gen::<[u8; KEY_SIZE]>();
+        let persist =
+            store_key_for_password(b"my password", TEST_PARAMS, &key, &mut csprng).unwrap();
+
+        // The right password retrieves the shared key
+        let ret_key = retrieve_key_with_password(&persist, b"my password")
+            .unwrap()
+            .unwrap();
+        assert_eq!(ret_key, key);
+
+        // A wrong one does not
+        assert!(retrieve_key_with_password(&persist, b"wrong password")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn invalid_params() {
+        let params = Argon2Params {
+            m_cost: 64,
+            t_cost: 0,
+            p_cost: 1,
+        };
+        assert!(params.check().is_err());
+        assert!(TEST_PARAMS.check().is_ok());
+
+        // Costs from a crafted header are refused before any derivation
+        let mut csprng = ChaChaRng::seed_from_u64(0);
+        let key = csprng.gen::<[u8; KEY_SIZE]>();
+        let persist =
+            store_key_for_password(b"my password", TEST_PARAMS, &key, &mut csprng).unwrap();
+        for params in [
+            Argon2Params {
+                m_cost: u32::MAX,
+                ..TEST_PARAMS
+            },
+            Argon2Params {
+                t_cost: u32::MAX,
+                ..TEST_PARAMS
+            },
+            Argon2Params {
+                p_cost: MAX_P_COST + 1,
+                ..TEST_PARAMS
+            },
+        ] {
+            assert!(params.check().is_err());
+            let crafted = PasswordPersistent {
+                params,
+                ..persist.clone()
+            };
+            assert!(matches!(
+                retrieve_key_with_password(&crafted, b"my password"),
+                Err(Error::ConfigError(ConfigError::InvalidArgon2Params))
+            ));
+        }
+    }
+}
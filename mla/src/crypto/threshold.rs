@@ -0,0 +1,239 @@
+//! Threshold encryption: the archive key is split with Shamir's Secret
+//! Sharing, so that `k` of the `n` recipients are needed to decrypt it
+//!
+//! Each byte of the key is the constant term of a random polynomial of degree
+//! `k - 1` over GF(2^8). The share of the i-th recipient (from 0) is made of
+//! the evaluations of these polynomials at `x = i + 1`, and is stored for this
+//! recipient only, as the archive key is. Any `k` shares give back the key
+//! through Lagrange interpolation at `x = 0`, while `k - 1` shares give no
+//! information on it.
+use crate::crypto::ecc::{
+    retrieve_key_with_index, store_keys_for_recipients, MultiRecipientPersistent,
+};
+use crate::errors::{ConfigError, Error};
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::{Zeroize, Zeroizing};
+
+const KEY_SIZE: usize = 32;
+/// Shares are numbered by a non-zero byte
+const MAX_SHARES: usize = 255;
+
+/// Recipients of an archive key split in shares, and the number of them
+/// needed to decrypt it
+#[derive(Clone)]
+pub struct ThresholdConfig {
+    threshold: usize,
+    recipients: Vec<PublicKey>,
+}
+
+impl ThresholdConfig {
+    /// `k` of the owners of `shares` private keys will be needed to decrypt
+    /// the archive. Each public key receives one share
+    pub fn new(k: usize, shares: &[PublicKey]) -> Self {
+        ThresholdConfig {
+            threshold: k,
+            recipients: shares.to_vec(),
+        }
+    }
+
+    /// Consistency check: `1 <= k <= n <= 255`
+    pub(crate) fn check(&self) -> Result<(), ConfigError> {
+        if self.threshold == 0
+            || self.threshold > self.recipients.len()
+            || self.recipients.len() > MAX_SHARES
+        {
+            return Err(ConfigError::InvalidThreshold);
+        }
+        Ok(())
+    }
+}
+
+/// Shares of the archive key, stored in the header
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct ThresholdPersistent {
+    /// Number of shares needed
+    threshold: u8,
+    /// Share of the i-th recipient, evaluated at `x = i + 1`
+    shares: MultiRecipientPersistent,
+}
+
+// -------- GF(2^8) arithmetic, with the AES polynomial --------
+
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// Inverse of a non-zero element, as `a^254`
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    for _ in 0..254 {
+        result = gf_mul(result, a);
+    }
+    result
+}
+
+/// Split `secret` in `count` shares, `threshold` of them being needed to
+/// rebuild it. The i-th share is evaluated at `x = i + 1`
+fn split<T: RngCore + CryptoRng>(
+    secret: &[u8; KEY_SIZE],
+    threshold: usize,
+    count: usize,
+    csprng: &mut T,
+) -> Vec<Zeroizing<[u8; KEY_SIZE]>> {
+    // Coefficients of degree 1 to `threshold - 1`, for each byte
+    let mut coefficients = Zeroizing::new(vec![[0u8; KEY_SIZE]; threshold - 1]);
+    for coefficient in coefficients.iter_mut() {
+        csprng.fill_bytes(coefficient);
+    }
+    (1..=count)
+        .map(|x| {
+            let x = x as u8;
+            let mut share = Zeroizing::new([0u8; KEY_SIZE]);
+            for (index, byte) in share.iter_mut().enumerate() {
+                // Horner's method
+                let mut value = 0;
+                for coefficient in coefficients.iter().rev() {
+                    value = gf_mul(value, x) ^ coefficient[index];
+                }
+                *byte = gf_mul(value, x) ^ secret[index];
+            }
+            share
+        })
+        .collect()
+}
+
+/// Rebuild the secret from shares, by their `x`, through Lagrange
+/// interpolation at `x = 0`. Enough distinct shares must be provided
+fn combine(shares: &BTreeMap<u8, Zeroizing<[u8; KEY_SIZE]>>) -> [u8; KEY_SIZE] {
+    let mut secret = [0u8; KEY_SIZE];
+    for (xi, yi) in shares {
+        // Lagrange basis polynomial of `xi`, at 0
+        let mut basis = 1;
+        for xj in shares.keys().filter(|xj| *xj != xi) {
+            basis = gf_mul(basis, gf_mul(*xj, gf_inv(xj ^ xi)));
+        }
+        for (byte, share_byte) in secret.iter_mut().zip(yi.iter()) {
+            *byte ^= gf_mul(basis, *share_byte);
+        }
+    }
+    secret
+}
+
+/// Split `key` in shares, each stored for one of the recipients of `config`
+pub(crate) fn store_key_for_threshold<T: RngCore + CryptoRng>(
+    config: &ThresholdConfig,
+    key: &[u8; KEY_SIZE],
+    csprng: &mut T,
+) -> Result<ThresholdPersistent, Error> {
+    config.check()?;
+    let shares = split(key, config.threshold, config.recipients.len(), csprng);
+    let shares = store_keys_for_recipients(
+        config
+            .recipients
+            .iter()
+            .zip(shares.iter().map(|share| &**share)),
+        csprng,
+    )?;
+    Ok(ThresholdPersistent {
+        threshold: config.threshold as u8,
+        shares,
+    })
+}
+
+/// Try to recover the key from the shares of `private_keys`, `None` if less
+/// than the threshold of shares are found
+pub(crate) fn retrieve_key_with_threshold(
+    persist: &ThresholdPersistent,
+    private_keys: &[StaticSecret],
+) -> Result<Option<[u8; KEY_SIZE]>, Error> {
+    let threshold = usize::from(persist.threshold);
+    let mut shares = BTreeMap::new();
+    for private_key in private_keys {
+        if let Some((index, mut share)) = retrieve_key_with_index(&persist.shares, private_key)? {
+            let x = u8::try_from(index + 1).map_err(|_| Error::DeserializationError)?;
+            shares.insert(x, Zeroizing::new(share));
+            share.zeroize();
+            if shares.len() == threshold {
+                return Ok(Some(combine(&shares)));
+            }
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    #[test]
+    fn gf_arithmetic() {
+        // Example of FIPS-197, section 4.2
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        for a in 1..=255 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn split_combine() {
+        let mut csprng = ChaChaRng::seed_from_u64(0);
+        let mut secret = [0u8; KEY_SIZE];
+        csprng.fill_bytes(&mut secret);
+        let shares = split(&secret, 3, 5, &mut csprng);
+
+        // Any 3 shares give the secret back
+        for (a, b, c) in [(1, 2, 3), (1, 3, 5), (2, 4, 5), (5, 4, 1)] {
+            let subset: BTreeMap<u8, Zeroizing<[u8; KEY_SIZE]>> = [a, b, c]
+                .iter()
+                .map(|x: &u8| (*x, shares[usize::from(*x) - 1].clone()))
+                .collect();
+            assert_eq!(combine(&subset), secret);
+        }
+        // 2 of them do not
+        let subset: BTreeMap<u8, Zeroizing<[u8; KEY_SIZE]>> =
+            vec![(1, shares[0].clone()), (2, shares[1].clone())]
+                .into_iter()
+                .collect();
+        assert_ne!(combine(&subset), secret);
+    }
+
+    #[test]
+    fn single_share_reveals_nothing() {
+        let mut csprng = ChaChaRng::seed_from_u64(0);
+        let secret = [7u8; KEY_SIZE];
+        let shares = split(&secret, 2, 2, &mut csprng);
+        assert_ne!(*shares[0], secret);
+
+        // Knowing the first share, each value of the second one leads to a
+        // different secret: every secret is equally possible
+        let mut candidates = std::collections::HashSet::new();
+        for guess in 0..=255u8 {
+            let mut second = shares[1].clone();
+            second[0] = guess;
+            let subset: BTreeMap<u8, Zeroizing<[u8; KEY_SIZE]>> =
+                vec![(1, shares[0].clone()), (2, second)]
+                    .into_iter()
+                    .collect();
+            candidates.insert(combine(&subset)[0]);
+        }
+        assert_eq!(candidates.len(), 256);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::errors::Error;
+use crate::metadata::FileMetadata;
+use crate::ArchiveReader;
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+
+/// How a file present in both archives differs, see `diff_archives`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileDiff {
+    /// The size, and so the content, differs
+    Size { before: u64, after: u64 },
+    /// Same size, but the content hash differs
+    Hash,
+    /// Same content, only the file metadata differs
+    Metadata,
+}
+
+/// Differences between two archives, see `diff_archives`
+///
+/// Names are sorted. Only files are compared, directories and symbolic
+/// links are not
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArchiveDiff {
+    /// Files only present in the second archive
+    pub added: Vec<String>,
+    /// Files only present in the first archive
+    pub removed: Vec<String>,
+    /// Files present in both archives, with a different content or metadata
+    pub changed: Vec<(String, FileDiff)>,
+}
+
+impl ArchiveDiff {
+    /// Whether both archives hold the same files
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Size, content hash and metadata of a file
+struct FileSummary {
+    size: u64,
+    hash: [u8; 32],
+    metadata: Option<FileMetadata>,
+}
+
+/// Summarize each file of `archive`, without reading their content: the
+/// hashes and metadata are stored at their start and end
+fn summarize<'b, R: 'b + Read + Seek>(
+    archive: &mut ArchiveReader<'b, R>,
+) -> Result<HashMap<String, FileSummary>, Error> {
+    let fnames: Vec<String> = archive.list_files()?.cloned().collect();
+    let mut summaries = HashMap::with_capacity(fnames.len());
+    for fname in fnames {
+        let (size, metadata) = match archive.get_file(&fname)? {
+            Some(file) => (file.size, file.metadata),
+            None => continue,
+        };
+        let hash = archive.get_hash(&fname)?.ok_or_else(|| {
+            Error::WrongReaderState("[diff_archives] File without hash".to_string())
+        })?;
+        summaries.insert(
+            fname,
+            FileSummary {
+                size,
+                hash,
+                metadata,
+            },
+        );
+    }
+    Ok(summaries)
+}
+
+/// Compare the files of `archive_a` and `archive_b`
+///
+/// Files are compared by name: a renamed file is reported as removed, then
+/// added. Contents are compared through their size, then their SHA-256
+/// hash, stored in the archives: nothing is extracted, and each archive is
+/// only read at the start and end of each file
+pub fn diff_archives<'a, 'b, R1: 'a + Read + Seek, R2: 'b + Read + Seek>(
+    archive_a: &mut ArchiveReader<'a, R1>,
+    archive_b: &mut ArchiveReader<'b, R2>,
+) -> Result<ArchiveDiff, Error> {
+    let files_a = summarize(archive_a)?;
+    let mut files_b = summarize(archive_b)?;
+
+    let mut diff = ArchiveDiff::default();
+    for (fname, a) in files_a {
+        let b = match files_b.remove(&fname) {
+            Some(b) => b,
+            None => {
+                diff.removed.push(fname);
+                continue;
+            }
+        };
+        let file_diff = if a.size != b.size {
+            FileDiff::Size {
+                before: a.size,
+                after: b.size,
+            }
+        } else if a.hash != b.hash {
+            FileDiff::Hash
+        } else if a.metadata != b.metadata {
+            FileDiff::Metadata
+        } else {
+            continue;
+        };
+        diff.changed.push((fname, file_diff));
+    }
+    diff.added = files_b.into_keys().collect();
+
+    diff.added.sort();
+    diff.removed.sort();
+    diff.changed
+        .sort_by(|(fname_a, _), (fname_b, _)| fname_a.cmp(fname_b));
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+    use crate::{ArchiveWriter, Layers};
+    use std::io::Cursor;
+    use std::time::{Duration, UNIX_EPOCH};
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    fn archive(
+        files: &[(&str, &[u8], Option<FileMetadata>)],
+        key: &StaticSecret,
+    ) -> ArchiveReader<'static, Cursor<Vec<u8>>> {
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::DEFAULT)
+            .add_public_keys(&[PublicKey::from(key)]);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        for (fname, content, metadata) in files {
+            let id = match metadata {
+                Some(metadata) => mla.start_file_with_metadata(fname, metadata).unwrap(),
+                None => mla.start_file(fname).unwrap(),
+            };
+            mla.append_file_content(id, content.len() as u64, *content)
+                .unwrap();
+            mla.end_file(id).unwrap();
+        }
+        mla.finalize().unwrap();
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(key));
+        ArchiveReader::from_config(Cursor::new(mla.into_raw()), config).unwrap()
+    }
+
+    #[test]
+    fn diff() {
+        let key = StaticSecret::from([7; 32]);
+        let metadata = |secs| {
+            Some(FileMetadata {
+                mtime: Some(UNIX_EPOCH + Duration::from_secs(secs)),
+                ..FileMetadata::default()
+            })
+        };
+        let mut archive_a = archive(
+            &[
+                ("same", b"same", None),
+                ("renamed", b"renamed content", None),
+                ("removed", b"removed", None),
+                ("resized", b"short", None),
+                ("modified", b"before", None),
+                ("touched", b"touched", metadata(1)),
+            ],
+            &key,
+        );
+        let mut archive_b = archive(
+            &[
+                ("same", b"same", None),
+                ("renamed_to", b"renamed content", None),
+                ("added", b"added", None),
+                ("resized", b"longer", None),
+                ("modified", b"after!", None),
+                ("touched", b"touched", metadata(2)),
+            ],
+            &key,
+        );
+
+        let diff = diff_archives(&mut archive_a, &mut archive_b).unwrap();
+        assert_eq!(diff.added, vec!["added", "renamed_to"]);
+        assert_eq!(diff.removed, vec!["removed", "renamed"]);
+        assert_eq!(
+            diff.changed,
+            vec![
+                ("modified".to_string(), FileDiff::Hash),
+                (
+                    "resized".to_string(),
+                    FileDiff::Size {
+                        before: 5,
+                        after: 6
+                    }
+                ),
+                ("touched".to_string(), FileDiff::Metadata),
+            ]
+        );
+        assert!(!diff.is_empty());
+
+        // Reversed
+        let diff = diff_archives(&mut archive_b, &mut archive_a).unwrap();
+        assert_eq!(diff.added, vec!["removed", "renamed"]);
+        assert_eq!(diff.removed, vec!["added", "renamed_to"]);
+
+        // An archive against itself
+        let mut archive_c = archive(&[("same", b"same", None)], &key);
+        let mut archive_d = archive(&[("same", b"same", None)], &key);
+        assert!(diff_archives(&mut archive_c, &mut archive_d)
+            .unwrap()
+            .is_empty());
+    }
+}
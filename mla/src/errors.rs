@@ -1,4 +1,4 @@
-use crate::ArchiveFileID;
+use crate::{ArchiveFileID, FeatureFlags};
 use aes_ctr::stream_cipher::InvalidKeyNonceLength;
 use bincode;
 use hkdf::InvalidLength;
@@ -12,7 +12,7 @@ pub enum Error {
     IOError(io::Error),
     /// Wrong magic, must be "MLA"
     WrongMagic,
-    /// Unsupported version, must be 1
+    /// Unsupported format version, such as one newer than `MLA_FORMAT_VERSION`
     UnsupportedVersion,
     /// Supplied ECC key is not in the expected format
     InvalidECCKeyFormat,
@@ -61,6 +61,44 @@ pub enum Error {
     AuthenticatedDecryptionWrongTag,
     /// Unable to expand while using the HKDF
     HKDFInvalidKeyLength,
+    /// The archive signature is not the expected one
+    SignatureVerificationFailed,
+    /// A signature verification is required, but the archive is not signed
+    MissingSignature,
+    /// The public listing of the archive is requested, but it has none (see
+    /// `ArchiveWriterConfig::public_file_listing`)
+    MissingPublicListing,
+    /// The checksum of a file content does not match the stored one
+    ChecksumMismatch,
+    /// The SHA-256 hash of a file content does not match the one stored in
+    /// its `EndOfFile`
+    HashMismatch,
+    /// A file has been started, but never ended (the archive might be
+    /// truncated or corrupted)
+    UnterminatedFile,
+    /// The volumes of a split archive are incomplete, duplicated or not
+    /// finalized
+    WrongVolume(String),
+    /// The archive uses features this reader does not implement
+    UnsupportedFeature(FeatureFlags),
+    /// An optional block of an unknown type has been met, while
+    /// `ArchiveReaderConfig::skip_unknown_blocks` is disabled
+    UnknownBlockType(u8),
+    /// All the `ArchiveFileID` have been used, no more file can be added
+    FileIDExhausted,
+    /// Writing a content could make the archive bigger than the limit, in
+    /// bytes, set with `ArchiveWriterConfig::max_bytes`
+    ArchiveSizeLimitExceeded(u64),
+    /// The limit on the number of files, set with
+    /// `ArchiveWriterConfig::max_files`, has been reached
+    FileCountLimitExceeded(usize),
+    /// The archive key is split in shares (see
+    /// `ArchiveWriterConfig::set_threshold`), and the keys provided with
+    /// `ArchiveReaderConfig::add_threshold_keys` give less shares than needed
+    InsufficientShares,
+    /// An entry name is refused by `PathNormalizationPolicy::Strict`, for the
+    /// given reason
+    InvalidFilename(String),
 }
 
 impl fmt::Display for Error {
@@ -110,6 +148,7 @@ impl From<ConfigError> for Error {
     fn from(error: ConfigError) -> Self {
         match error {
             ConfigError::PrivateKeyNotSet => Error::PrivateKeyNeeded,
+            ConfigError::InsufficientShares => Error::InsufficientShares,
             _ => Error::ConfigError(error),
         }
     }
@@ -195,11 +234,33 @@ pub enum ConfigError {
     IncoherentPersistentConfig,
     // Compression specifics
     CompressionLevelOutOfRange,
+    // A dictionary is set, while the algorithm is not Zstandard
+    DictionaryRequiresZstd,
     // Encryption specifics
     EncryptionKeyIsMissing,
     PrivateKeyNotSet,
     PrivateKeyNotFound,
     ECIESComputationError,
+    // Threshold specifics: `k` must be between 1 and the number of shares,
+    // at most 255
+    InvalidThreshold,
+    InsufficientShares,
+    // Password specifics
+    InvalidArgon2Params,
+    PasswordKeyDerivationError,
+    // Signature specifics
+    SigningKeyIsMissing,
+    // Comment longer than `MAX_COMMENT_LENGTH`
+    CommentTooLong,
+    // Checkpoints are only supported for archives without layers
+    CheckpointRequiresNoLayers,
+    // `FileLayout::Interleaved` or `BlockBoundary::Fixed` with chunks of 0
+    // bytes, or `BlockBoundary::ContentDefined` sizes not verifying
+    // `0 < min <= avg <= max`
+    InvalidChunkSize,
+    // `PaddingStrategy::AlignTo(0)`, or `PaddingStrategy::RandomJitter` with
+    // `min > max`
+    InvalidPaddingStrategy,
 }
 
 impl fmt::Display for ConfigError {
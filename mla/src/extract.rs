@@ -0,0 +1,266 @@
+use crate::errors::Error;
+use crate::helpers::{apply_metadata, linear_extract_inner, relative_path, LinearExtractOptions};
+use crate::ArchiveReader;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+/// Handling of the files to extract which already exist, see
+/// `ArchiveReader::extract_to_dir`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// Keep the existing file, and report an `io::ErrorKind::AlreadyExists`
+    /// error for it
+    #[default]
+    Fail,
+    /// Keep the existing file, silently
+    Skip,
+    /// Replace the existing file
+    Overwrite,
+}
+
+/// Options of `ArchiveReader::extract_to_dir`
+#[derive(Debug, Clone, Default)]
+pub struct ExtractOptions {
+    /// Apply the metadata (modification time, permissions, owner) stored in
+    /// the archive to the extracted files, see `helpers::apply_metadata`
+    pub preserve_metadata: bool,
+    pub conflict_policy: ConflictPolicy,
+}
+
+impl ExtractOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Summary of an extraction, see `ArchiveReader::extract_to_dir`
+#[derive(Debug, Default)]
+pub struct ExtractReport {
+    pub files_extracted: u64,
+    /// Total size of the files content written, in bytes
+    pub bytes_written: u64,
+    /// Files which could not be extracted, or whose metadata could not be
+    /// applied, by name in the archive, with the corresponding error
+    pub errors: Vec<(String, io::Error)>,
+}
+
+/// Extracted file, counting the bytes written in a shared total
+struct CountingFile<'a> {
+    file: File,
+    written: &'a Cell<u64>,
+}
+
+impl<'a> Write for CountingFile<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = self.file.write(buf)?;
+        self.written.set(self.written.get() + count as u64);
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl<'b, R: 'b + Read + Seek> ArchiveReader<'b, R> {
+    /// Extract the archive files in `dest`, created if needed
+    ///
+    /// Each file is written to `dest/<filename>`, its parent directories
+    /// being created, in one linear pass over the archive. Directory entries
+    /// of the archive are created too, without their metadata. Symbolic links
+    /// are not created, see `helpers::create_symlink`.
+    ///
+    /// Errors on the filesystem side (paths containing a `..` component,
+    /// conflicts with existing files, files which can't be created, etc.) do
+    /// not stop the extraction, and are reported in `ExtractReport::errors`.
+    /// Archive errors, and errors while writing a file content, are returned
+    pub fn extract_to_dir(
+        &mut self,
+        dest: &Path,
+        options: ExtractOptions,
+    ) -> Result<ExtractReport, Error> {
+        fs::create_dir_all(dest)?;
+        let mut report = ExtractReport::default();
+        let written = Cell::new(0);
+        // Extracted files, by name, to apply their metadata afterwards
+        let mut extracted: HashMap<String, PathBuf> = HashMap::new();
+        let linear_options = LinearExtractOptions::for_archive(self);
+        linear_extract_inner(
+            self,
+            &linear_options,
+            |_fname| true,
+            |fname| {
+                let path = match relative_path(dest, fname) {
+                    Some(path) => path,
+                    None => {
+                        report.errors.push((
+                            fname.to_string(),
+                            io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                "Path escapes the destination directory",
+                            ),
+                        ));
+                        return None;
+                    }
+                };
+                if path.symlink_metadata().is_ok() {
+                    match options.conflict_policy {
+                        ConflictPolicy::Fail => {
+                            report.errors.push((
+                                fname.to_string(),
+                                io::Error::new(io::ErrorKind::AlreadyExists, "File already exists"),
+                            ));
+                            return None;
+                        }
+                        ConflictPolicy::Skip => return None,
+                        ConflictPolicy::Overwrite => {}
+                    }
+                }
+                let created = match path.parent() {
+                    Some(parent) => fs::create_dir_all(parent),
+                    None => Ok(()),
+                }
+                .and_then(|_| File::create(&path));
+                match created {
+                    Ok(file) => {
+                        report.files_extracted += 1;
+                        extracted.insert(fname.to_string(), path);
+                        Some(CountingFile {
+                            file,
+                            written: &written,
+                        })
+                    }
+                    Err(err) => {
+                        report.errors.push((fname.to_string(), err));
+                        None
+                    }
+                }
+            },
+            |path| {
+                if let Some(path) = relative_path(dest, path) {
+                    fs::create_dir_all(path)?;
+                }
+                Ok(())
+            },
+        )?;
+        report.bytes_written = written.get();
+
+        if options.preserve_metadata {
+            let mut extracted: Vec<(String, PathBuf)> = extracted.into_iter().collect();
+            extracted.sort();
+            for (fname, path) in extracted {
+                let metadata = match self.get_file(&fname)? {
+                    Some(file) => file.metadata,
+                    None => None,
+                };
+                if let Some(metadata) = metadata {
+                    if let Err(err) = apply_metadata(&path, &metadata) {
+                        report.errors.push((fname, err));
+                    }
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+    use crate::metadata::FileMetadata;
+    use crate::ArchiveWriter;
+    use std::io::Cursor;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn build_archive() -> Vec<u8> {
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        mla.add_file("a.txt", 9, &b"a content"[..]).unwrap();
+        let metadata = FileMetadata {
+            mtime: Some(UNIX_EPOCH + Duration::new(1_600_000_000, 0)),
+            ..Default::default()
+        };
+        let id = mla
+            .start_file_with_metadata("sub/b.txt", &metadata)
+            .unwrap();
+        mla.append_file_content(id, 9, &b"b content"[..]).unwrap();
+        mla.end_file(id).unwrap();
+        mla.add_file("sub/deeper/c.txt", 3, &b"ccc"[..]).unwrap();
+        mla.add_directory("empty", None).unwrap();
+        mla.add_file("../escaped", 1, &b"e"[..]).unwrap();
+        mla.finalize().unwrap();
+        mla.into_raw()
+    }
+
+    fn extract(archive: &[u8], dest: &Path, options: ExtractOptions) -> ExtractReport {
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(archive), ArchiveReaderConfig::new()).unwrap();
+        mla_read.extract_to_dir(dest, options).unwrap()
+    }
+
+    #[test]
+    fn extract_to_dir() {
+        let archive = build_archive();
+        let root = std::env::temp_dir().join(format!("mla_extract_{}", std::process::id()));
+        let dest = root.join("dest");
+
+        let report = extract(
+            &archive,
+            &dest,
+            ExtractOptions {
+                preserve_metadata: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(report.files_extracted, 3);
+        assert_eq!(report.bytes_written, 9 + 9 + 3);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].0, "../escaped");
+        assert_eq!(fs::read(dest.join("a.txt")).unwrap(), b"a content");
+        assert_eq!(fs::read(dest.join("sub/b.txt")).unwrap(), b"b content");
+        assert_eq!(fs::read(dest.join("sub/deeper/c.txt")).unwrap(), b"ccc");
+        assert!(dest.join("empty").is_dir());
+        assert!(!root.join("escaped").exists());
+        assert_eq!(
+            FileMetadata::from(&fs::metadata(dest.join("sub/b.txt")).unwrap()).mtime,
+            Some(UNIX_EPOCH + Duration::new(1_600_000_000, 0))
+        );
+
+        // Existing files are kept, unless overwriting them
+        fs::write(dest.join("a.txt"), b"modified").unwrap();
+        let report = extract(&archive, &dest, ExtractOptions::new());
+        assert_eq!(report.files_extracted, 0);
+        let conflicts: Vec<io::ErrorKind> = report
+            .errors
+            .iter()
+            .filter(|(fname, _)| fname != "../escaped")
+            .map(|(_, err)| err.kind())
+            .collect();
+        assert_eq!(conflicts, vec![io::ErrorKind::AlreadyExists; 3]);
+        let report = extract(
+            &archive,
+            &dest,
+            ExtractOptions {
+                conflict_policy: ConflictPolicy::Skip,
+                ..Default::default()
+            },
+        );
+        assert_eq!(report.files_extracted, 0);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(fs::read(dest.join("a.txt")).unwrap(), b"modified");
+        let report = extract(
+            &archive,
+            &dest,
+            ExtractOptions {
+                conflict_policy: ConflictPolicy::Overwrite,
+                ..Default::default()
+            },
+        );
+        assert_eq!(report.files_extracted, 3);
+        assert_eq!(fs::read(dest.join("a.txt")).unwrap(), b"a content");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
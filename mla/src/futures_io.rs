@@ -0,0 +1,144 @@
+//! Asynchronous API for MLA Archives, on top of the `futures::io` traits
+//!
+//! This makes the `async_io` API usable outside of tokio, for instance with
+//! async-std or smol. Sources and destinations implementing the
+//! `futures::io` traits are wrapped into a `Compat`, implementing the
+//! `tokio::io` ones: the `async_io` readers and writers are then used as is.
+use crate::async_io::{AsyncArchiveReader, AsyncArchiveWriter};
+use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+use crate::errors::Error;
+use futures_io::{AsyncRead, AsyncSeek, AsyncWrite};
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use tokio_util::compat::{Compat, FuturesAsyncReadCompatExt, FuturesAsyncWriteCompatExt};
+
+/// `AsyncArchiveReader` over a `futures::io` source
+pub type FuturesArchiveReader<'a, R> = AsyncArchiveReader<'a, Compat<R>>;
+
+/// `AsyncArchiveWriter` over a `futures::io` destination
+pub type FuturesArchiveWriter<'a, W> = AsyncArchiveWriter<'a, Compat<W>>;
+
+/// Open the archive `src`, see `AsyncArchiveReader::from_config`
+pub async fn reader_from_config<'a, R: AsyncRead + AsyncSeek + Unpin>(
+    src: R,
+    config: ArchiveReaderConfig,
+) -> Result<FuturesArchiveReader<'a, R>, Error> {
+    AsyncArchiveReader::from_config(src.compat(), config).await
+}
+
+/// Create an archive writing to `dest`, see `AsyncArchiveWriter::from_config`
+pub async fn writer_from_config<'a, W: AsyncWrite + Unpin>(
+    dest: W,
+    config: ArchiveWriterConfig,
+) -> Result<FuturesArchiveWriter<'a, W>, Error> {
+    AsyncArchiveWriter::from_config(dest.compat_write(), config).await
+}
+
+/// Counterpart of `async_io::async_linear_extract`, over `futures::io`
+///
+/// `export` maps filenames to AsyncWrite objects, which will receives the
+/// corresponding file's content. If a file is in the archive but not in
+/// `export`, this file will be silently ignored.
+pub async fn async_linear_extract<
+    W: AsyncWrite + Unpin,
+    R: AsyncRead + AsyncSeek + Unpin,
+    S: BuildHasher,
+>(
+    archive: &mut FuturesArchiveReader<'_, R>,
+    export: &mut HashMap<&String, W, S>,
+) -> Result<(), Error> {
+    let mut export: HashMap<&String, Compat<&mut W>> = export
+        .iter_mut()
+        .map(|(fname, writer)| (*fname, writer.compat_write()))
+        .collect();
+    crate::async_io::async_linear_extract(archive, &mut export).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::linear_extract;
+    use crate::tests::build_archive;
+    use crate::ArchiveReader;
+    use futures::io::Cursor;
+
+    #[test]
+    fn futures_linear_extract() {
+        for interleaved in [false, true] {
+            let (mla, key, files) = build_archive(None, interleaved);
+            let archive = mla.into_raw();
+            let reader_config = || {
+                let mut config = ArchiveReaderConfig::new();
+                config.add_private_keys(std::slice::from_ref(&key));
+                config
+            };
+            let fnames: Vec<String> = files.iter().map(|(fname, _)| fname.clone()).collect();
+
+            // Reference: the synchronous extraction
+            let mut sync_read = ArchiveReader::from_config(
+                std::io::Cursor::new(archive.as_slice()),
+                reader_config(),
+            )
+            .unwrap();
+            let mut sync_export: HashMap<&String, Vec<u8>> =
+                fnames.iter().map(|fname| (fname, Vec::new())).collect();
+            linear_extract(&mut sync_read, &mut sync_export).unwrap();
+
+            smol::block_on(async {
+                let mut mla_read =
+                    reader_from_config(Cursor::new(archive.as_slice()), reader_config())
+                        .await
+                        .unwrap();
+                let mut export: HashMap<&String, Cursor<Vec<u8>>> = fnames
+                    .iter()
+                    .map(|fname| (fname, Cursor::new(Vec::new())))
+                    .collect();
+                async_linear_extract(&mut mla_read, &mut export)
+                    .await
+                    .unwrap();
+                for (fname, content) in &files {
+                    assert_eq!(export[fname].get_ref(), content);
+                    assert_eq!(export[fname].get_ref(), &sync_export[fname]);
+                }
+
+                // Files are read as with tokio
+                let mut file = mla_read.get_file(&fnames[0]).await.unwrap().unwrap();
+                let mut data = Vec::new();
+                tokio::io::AsyncReadExt::read_to_end(&mut file.data, &mut data)
+                    .await
+                    .unwrap();
+                assert_eq!(data, files[0].1);
+            });
+        }
+
+        // Round trip, writing with futures::io
+        let (_, key, files) = build_archive(None, false);
+        let archive = smol::block_on(async {
+            let mut config = ArchiveWriterConfig::default();
+            config.add_public_keys(&[x25519_dalek::PublicKey::from(&key)]);
+            let mut mla = writer_from_config(Cursor::new(Vec::new()), config)
+                .await
+                .unwrap();
+            for (fname, content) in &files {
+                mla.add_file(fname, content.len() as u64, content.as_slice())
+                    .await
+                    .unwrap();
+            }
+            mla.finalize().await.unwrap();
+            mla.into_raw().into_inner().into_inner()
+        });
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read =
+            ArchiveReader::from_config(std::io::Cursor::new(archive), config).unwrap();
+        for (fname, content) in &files {
+            let mut data = Vec::new();
+            std::io::Read::read_to_end(
+                &mut mla_read.get_file(fname).unwrap().unwrap().data,
+                &mut data,
+            )
+            .unwrap();
+            assert_eq!(&data, content);
+        }
+    }
+}
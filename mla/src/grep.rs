@@ -0,0 +1,303 @@
+use crate::errors::Error;
+use crate::helpers::{linear_extract_inner, LinearExtractOptions};
+use crate::ArchiveReader;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::io::{self, Read, Seek, Write};
+
+/// Maximum number of bytes of context reported before and after a match, see
+/// `ArchiveReader::grep`
+pub const GREP_CONTEXT_SIZE: usize = 32;
+
+/// Occurrence of a pattern in a file content, see `ArchiveReader::grep`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrepMatch {
+    pub filename: String,
+    /// Offset of the occurrence in the file content
+    pub offset: u64,
+    /// Up to `GREP_CONTEXT_SIZE` bytes preceding the occurrence
+    pub context_before: Vec<u8>,
+    /// Up to `GREP_CONTEXT_SIZE` bytes following the occurrence
+    pub context_after: Vec<u8>,
+}
+
+/// Pattern searched with the Boyer-Moore-Horspool algorithm
+struct Pattern<'a> {
+    bytes: &'a [u8],
+    /// Shift of the search, depending on the last byte compared
+    shift: [usize; 256],
+}
+
+impl<'a> Pattern<'a> {
+    /// `bytes` must not be empty
+    fn new(bytes: &'a [u8]) -> Self {
+        let mut shift = [bytes.len(); 256];
+        let last = bytes.len() - 1;
+        for (index, byte) in bytes[..last].iter().enumerate() {
+            shift[usize::from(*byte)] = last - index;
+        }
+        Pattern { bytes, shift }
+    }
+
+    fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Index of the first occurrence in `haystack`, if any
+    fn find(&self, haystack: &[u8]) -> Option<usize> {
+        let last = self.bytes.len() - 1;
+        let mut position = 0;
+        while position + last < haystack.len() {
+            if &haystack[position..=position + last] == self.bytes {
+                return Some(position);
+            }
+            position += self.shift[usize::from(haystack[position + last])];
+        }
+        None
+    }
+}
+
+/// Search shared by the files, and occurrences reported so far
+struct GrepState<'a, F: Fn(&GrepMatch) -> bool> {
+    pattern: Pattern<'a>,
+    callback: F,
+    matches: RefCell<Vec<GrepMatch>>,
+    /// Set once the callback asked to stop
+    stopped: Cell<bool>,
+}
+
+impl<'a, F: Fn(&GrepMatch) -> bool> GrepState<'a, F> {
+    fn report(&self, found: GrepMatch) {
+        if self.stopped.get() {
+            return;
+        }
+        if !(self.callback)(&found) {
+            self.stopped.set(true);
+        }
+        self.matches.borrow_mut().push(found);
+    }
+}
+
+/// Error interrupting the archive reading once the search is stopped
+fn stopped_error() -> io::Error {
+    io::Error::other("Search stopped")
+}
+
+/// Search in a file content, written to it as it is read
+struct FileSearch<'s, 'a, F: Fn(&GrepMatch) -> bool> {
+    state: &'s GrepState<'a, F>,
+    filename: String,
+    /// End of the content read so far, starting at offset `window_start`
+    window: Vec<u8>,
+    window_start: u64,
+    /// Offset from which occurrences are searched
+    next_search: u64,
+    /// Offsets of the occurrences found, waiting for their context after
+    pending: VecDeque<u64>,
+}
+
+impl<'s, 'a, F: Fn(&GrepMatch) -> bool> FileSearch<'s, 'a, F> {
+    fn new(state: &'s GrepState<'a, F>, filename: &str) -> Self {
+        FileSearch {
+            state,
+            filename: filename.to_string(),
+            window: Vec::new(),
+            window_start: 0,
+            next_search: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Report the occurrences whose context after is complete, or all of
+    /// them at the end of the content
+    fn report_pending(&mut self, at_end: bool) {
+        let window_end = self.window_start + self.window.len() as u64;
+        let pattern_len = self.state.pattern.len();
+        while let Some(offset) = self.pending.front().copied() {
+            if !at_end && offset + (pattern_len + GREP_CONTEXT_SIZE) as u64 > window_end {
+                break;
+            }
+            self.pending.pop_front();
+            let start = (offset - self.window_start) as usize;
+            let end = start + pattern_len;
+            self.state.report(GrepMatch {
+                filename: self.filename.clone(),
+                offset,
+                context_before: self.window[start.saturating_sub(GREP_CONTEXT_SIZE)..start]
+                    .to_vec(),
+                context_after: self.window[end..(end + GREP_CONTEXT_SIZE).min(self.window.len())]
+                    .to_vec(),
+            });
+        }
+    }
+}
+
+impl<'s, 'a, F: Fn(&GrepMatch) -> bool> Write for FileSearch<'s, 'a, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.state.stopped.get() {
+            return Err(stopped_error());
+        }
+        self.window.extend_from_slice(buf);
+        let window_end = self.window_start + self.window.len() as u64;
+        let pattern_len = self.state.pattern.len() as u64;
+        loop {
+            let from = (self.next_search - self.window_start) as usize;
+            match self.state.pattern.find(&self.window[from..]) {
+                Some(index) => {
+                    let offset = self.next_search + index as u64;
+                    self.pending.push_back(offset);
+                    self.next_search = offset + pattern_len;
+                }
+                None => {
+                    // An occurrence may start in the last bytes, and end in
+                    // the content to come
+                    self.next_search = self
+                        .next_search
+                        .max((window_end + 1).saturating_sub(pattern_len));
+                    break;
+                }
+            }
+        }
+        self.report_pending(false);
+        if self.state.stopped.get() {
+            return Err(stopped_error());
+        }
+
+        // Only keep the bytes needed by the occurrences to report or to find
+        let keep_from = self
+            .pending
+            .front()
+            .map_or(self.next_search, |offset| (*offset).min(self.next_search))
+            .saturating_sub(GREP_CONTEXT_SIZE as u64)
+            .max(self.window_start);
+        self.window
+            .drain(..(keep_from - self.window_start) as usize);
+        self.window_start = keep_from;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'s, 'a, F: Fn(&GrepMatch) -> bool> Drop for FileSearch<'s, 'a, F> {
+    /// The writer is dropped at the end of the file
+    fn drop(&mut self) {
+        self.report_pending(true);
+    }
+}
+
+impl<'b, R: 'b + Read + Seek> ArchiveReader<'b, R> {
+    /// Search `pattern` in the content of the archive files, in one linear
+    /// pass over the archive
+    ///
+    /// Each occurrence is given to `callback` once found, with up to
+    /// `GREP_CONTEXT_SIZE` bytes of context before and after it. Returning
+    /// `false` stops the search. Occurrences spanning several blocks of
+    /// content are found; overlapping occurrences are not.
+    ///
+    /// Return the occurrences given to `callback`, in this order
+    pub fn grep<F: Fn(&GrepMatch) -> bool>(
+        &mut self,
+        pattern: &[u8],
+        callback: F,
+    ) -> Result<Vec<GrepMatch>, Error> {
+        if pattern.is_empty() {
+            return Err(Error::BadAPIArgument(
+                "[grep] The pattern must not be empty".to_string(),
+            ));
+        }
+        let state = GrepState {
+            pattern: Pattern::new(pattern),
+            callback,
+            matches: RefCell::new(Vec::new()),
+            stopped: Cell::new(false),
+        };
+        let options = LinearExtractOptions::for_archive(self);
+        let result = linear_extract_inner(
+            self,
+            &options,
+            |_fname| !state.stopped.get(),
+            |fname| Some(FileSearch::new(&state, fname)),
+            |_path| Ok(()),
+        );
+        // Errors due to the search being stopped are expected
+        if !state.stopped.get() {
+            result?;
+        }
+        Ok(state.matches.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+    use crate::{ArchiveWriter, Layers};
+    use std::io::Cursor;
+
+    #[test]
+    fn pattern_find() {
+        let pattern = Pattern::new(b"abcab");
+        assert_eq!(pattern.find(b"abcabcab"), Some(0));
+        assert_eq!(pattern.find(b"xxabcxabcab"), Some(6));
+        assert_eq!(pattern.find(b"abca"), None);
+        assert_eq!(Pattern::new(b"x").find(b"aaax"), Some(3));
+    }
+
+    #[test]
+    fn grep() {
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .enable_layer(Layers::COMPRESS)
+            // Keep the appended contents in distinct blocks
+            .coalesce_threshold(0);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let mut content_a = vec![b'.'; 200];
+        content_a[10..16].copy_from_slice(b"needle");
+        content_a[190..196].copy_from_slice(b"needle");
+        mla.add_file("a", 200, content_a.as_slice()).unwrap();
+        // Occurrence spanning two blocks
+        let id = mla.start_file("b").unwrap();
+        mla.append_file_content(id, 6, &b"...nee"[..]).unwrap();
+        mla.append_file_content(id, 6, &b"dle..."[..]).unwrap();
+        mla.end_file(id).unwrap();
+        mla.add_file("c", 8, &b"haystack"[..]).unwrap();
+        mla.finalize().unwrap();
+        let archive = mla.into_raw();
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(archive), ArchiveReaderConfig::new()).unwrap();
+
+        let matches = mla_read.grep(b"needle", |_| true).unwrap();
+        let found: Vec<(&str, u64)> = matches
+            .iter()
+            .map(|found| (found.filename.as_str(), found.offset))
+            .collect();
+        assert_eq!(found, vec![("a", 10), ("a", 190), ("b", 3)]);
+        assert_eq!(matches[0].context_before, vec![b'.'; 10]);
+        assert_eq!(matches[0].context_after, vec![b'.'; GREP_CONTEXT_SIZE]);
+        assert_eq!(matches[1].context_before, vec![b'.'; GREP_CONTEXT_SIZE]);
+        assert_eq!(matches[1].context_after, b"....");
+        assert_eq!(matches[2].context_before, b"...");
+        assert_eq!(matches[2].context_after, b"...");
+
+        // The callback stops the search
+        let calls = Cell::new(0);
+        let matches = mla_read
+            .grep(b"needle", |_| {
+                calls.set(calls.get() + 1);
+                false
+            })
+            .unwrap();
+        assert_eq!(calls.get(), 1);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].offset, 10);
+
+        assert!(mla_read.grep(b"missing", |_| true).unwrap().is_empty());
+        assert!(matches!(
+            mla_read.grep(b"", |_| true),
+            Err(Error::BadAPIArgument(_))
+        ));
+    }
+}
@@ -1,8 +1,87 @@
 /// Helpers for common operation with MLA Archives
-use super::{ArchiveFileBlock, ArchiveFileID, ArchiveReader, ArchiveWriter, Error};
-use std::collections::HashMap;
+use super::layers::raw::RawLayerWriter;
+use super::layers::sign::{check_signature, SignatureLayerWriter};
+use super::layers::traits::LayerWriter;
+use super::{
+    check_unknown_block, decompress_block_content, open_file, read_referred_content,
+    ArchiveFailSafeReader, ArchiveFileBlock, ArchiveFileID, ArchiveHeader, ArchiveReader,
+    ArchiveWriter, Error, FeatureFlags, FormatVersion, Layers,
+};
+use crate::audit::AuditEventType;
+use crate::config::{ArchivePersistentConfig, ArchiveReaderConfig, ArchiveWriterConfig};
+use crate::crypto::file::{FileDecryptReader, FileDecryptor};
+use crate::crypto::hash::{
+    ChecksumAlgorithm, ChecksumHasher, ChecksumWrapperReader, HashWrapperReader,
+};
+use crate::errors::FailSafeReadError;
+use crate::metadata::FileMetadata;
+use crate::progress::ReadProgressReporter;
+use ed25519_dalek::{VerifyingKey, SIGNATURE_LENGTH};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs;
 use std::hash::BuildHasher;
 use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Bounds of `LinearExtractOptions::buf_capacity`
+const MIN_BUF_CAPACITY: usize = 4096;
+const MAX_BUF_CAPACITY: usize = 128 * 1024 * 1024;
+
+/// Options of `linear_extract_with_options`
+#[derive(Debug, Clone)]
+pub struct LinearExtractOptions {
+    /// Capacity, in bytes, of the buffer used to read the archive. Must be in
+    /// [4096, 128MB]; larger buffers might improve throughput on fast storage
+    /// (default: 8KB)
+    pub buf_capacity: usize,
+    /// Verify the checksum of extracted files (default: `false`)
+    pub verify_checksums: bool,
+    /// Maximum number of bytes read from the archive per second, see
+    /// `RateLimitedReader`. Must not be 0 (default: `None`, unlimited)
+    pub max_bytes_per_sec: Option<u64>,
+}
+
+impl std::default::Default for LinearExtractOptions {
+    fn default() -> Self {
+        LinearExtractOptions {
+            buf_capacity: 8 * 1024,
+            verify_checksums: false,
+            max_bytes_per_sec: None,
+        }
+    }
+}
+
+impl LinearExtractOptions {
+    /// Default options, verifying checksums if it is required by the
+    /// configuration of `archive`
+    pub(crate) fn for_archive<R: Read + Seek>(archive: &ArchiveReader<R>) -> Self {
+        LinearExtractOptions {
+            verify_checksums: archive.config.checksums_verification,
+            ..Default::default()
+        }
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        if !(MIN_BUF_CAPACITY..=MAX_BUF_CAPACITY).contains(&self.buf_capacity) {
+            return Err(Error::BadAPIArgument(format!(
+                "[LinearExtractOptions] buf_capacity must be in [{}, {}], got {}",
+                MIN_BUF_CAPACITY, MAX_BUF_CAPACITY, self.buf_capacity
+            )));
+        }
+        if self.max_bytes_per_sec == Some(0) {
+            return Err(Error::BadAPIArgument(
+                "[LinearExtractOptions] max_bytes_per_sec must not be 0".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
 
 /// Extract an Archive linearly.
 ///
@@ -18,49 +97,525 @@ use std::io::{self, Read, Seek, SeekFrom, Write};
 /// encryption tag).
 /// Linear extraction avoids these costs by reading once and only once each byte,
 /// and by reducing the amount of seeks.
+///
+/// If checksums verification is enabled in the reader configuration, the
+/// checksum of extracted files is verified. See `linear_extract_with_options`
+/// for more options.
+///
+/// Files encrypted for their own recipients (see
+/// `ArchiveWriter::start_file_for_recipients`) are decrypted with the
+/// private keys of the reader configuration: `Error::PrivateKeyNeeded` is
+/// returned if none of them is a recipient of an extracted file.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        skip_all,
+        fields(archive_path = archive.config.archive_path.as_deref(), files = export.len())
+    )
+)]
 pub fn linear_extract<W1: Write, R: Read + Seek, S: BuildHasher>(
     archive: &mut ArchiveReader<R>,
     export: &mut HashMap<&String, W1, S>,
 ) -> Result<(), Error> {
+    let options = LinearExtractOptions::for_archive(archive);
+    linear_extract_with_options(archive, export, &options)
+}
+
+/// Same as `linear_extract`, with `options`
+///
+/// If `options.verify_checksums` is set, `Error::ChecksumMismatch` is returned
+/// if the checksum of an extracted file does not match. Invalid options are
+/// reported with `Error::BadAPIArgument`.
+pub fn linear_extract_with_options<W1: Write, R: Read + Seek, S: BuildHasher>(
+    archive: &mut ArchiveReader<R>,
+    export: &mut HashMap<&String, W1, S>,
+    options: &LinearExtractOptions,
+) -> Result<(), Error> {
+    // Split `export` in independent writers, handed over on file start
+    let mut writers: HashMap<&str, &mut W1> = export
+        .iter_mut()
+        .map(|(fname, writer)| (fname.as_str(), writer))
+        .collect();
+    linear_extract_inner(
+        archive,
+        options,
+        |_fname| true,
+        |fname| writers.remove(fname),
+        |_path| Ok(()),
+    )
+}
+
+/// Same as `linear_extract`, but also create the directory entries of the
+/// archive in `output_dir`
+///
+/// Directory paths are taken relatively to `output_dir`. Paths containing a
+/// `..` component are ignored. Directories metadata are not applied, see
+/// `apply_metadata`.
+pub fn linear_extract_with_directories<W1: Write, R: Read + Seek, S: BuildHasher>(
+    archive: &mut ArchiveReader<R>,
+    export: &mut HashMap<&String, W1, S>,
+    output_dir: &Path,
+) -> Result<(), Error> {
+    // Split `export` in independent writers, handed over on file start
+    let mut writers: HashMap<&str, &mut W1> = export
+        .iter_mut()
+        .map(|(fname, writer)| (fname.as_str(), writer))
+        .collect();
+    let options = LinearExtractOptions::for_archive(archive);
+    linear_extract_inner(
+        archive,
+        &options,
+        |_fname| true,
+        |fname| writers.remove(fname),
+        |path| {
+            if let Some(path) = relative_path(output_dir, path) {
+                fs::create_dir_all(path)?;
+            }
+            Ok(())
+        },
+    )
+}
+
+/// Same as `linear_extract`, but going on with the next files when one can't
+/// be read, if `ArchiveReaderConfig::best_effort_extraction` is enabled
+///
+/// Files which can't be read, for instance because one of their blocks is
+/// corrupted, are returned with the corresponding error, and nothing is
+/// written to their writer. Other files are correctly extracted. Files are
+/// read one by one through the archive footer, which is required, and each
+/// content is kept in memory until it is entirely read.
+///
+/// If best-effort extraction is not enabled, this is `linear_extract`: the
+/// first error is returned, and the returned list is always empty
+pub fn linear_extract_best_effort<W1: Write, R: Read + Seek, S: BuildHasher>(
+    archive: &mut ArchiveReader<R>,
+    export: &mut HashMap<&String, W1, S>,
+) -> Result<Vec<(String, Error)>, Error> {
+    if !archive.config.best_effort_extraction {
+        linear_extract(archive, export)?;
+        return Ok(Vec::new());
+    }
+    let files_info = match &archive.metadata {
+        Some(footer) => &footer.files_info,
+        None => return Err(Error::MissingMetadata),
+    };
+    // Read the files in their order in the archive, to mostly seek forward
+    let mut fnames: Vec<(u64, &String)> = export
+        .keys()
+        .filter_map(|fname| {
+            files_info.get(fname.as_str()).map(|file_info| {
+                let offset = file_info.offsets.first().copied().unwrap_or_default();
+                (offset, *fname)
+            })
+        })
+        .collect();
+    fnames.sort();
+
+    let mut failures = Vec::new();
+    for (_offset, fname) in fnames {
+        let mut content = Vec::new();
+        let read = archive.get_file(fname).and_then(|file| match file {
+            Some(mut file) => Ok(file.data.read_to_end(&mut content)?),
+            None => Ok(0),
+        });
+        match read {
+            Ok(_) => {
+                if let Some(writer) = export.get_mut(fname) {
+                    writer.write_all(&content)?;
+                }
+            }
+            Err(err) => failures.push((fname.clone(), err)),
+        }
+    }
+    Ok(failures)
+}
+
+/// Join `path` to `root`, ignoring root and prefix components. Returns `None`
+/// if `path` contains a `..` component
+pub(crate) fn relative_path(root: &Path, path: &str) -> Option<PathBuf> {
+    let mut dest = root.to_path_buf();
+    for part in Path::new(path).components() {
+        match part {
+            Component::Prefix(..) | Component::RootDir | Component::CurDir => continue,
+            Component::ParentDir => return None,
+            Component::Normal(part) => dest.push(part),
+        }
+    }
+    Some(dest)
+}
+
+/// Extract an Archive linearly, choosing files to extract on the fly.
+///
+/// When the start of a file is met, `filter` is called with its filename. If
+/// it returns `true`, `make_writer` is then called to get the Write object
+/// which will receives the file's content. If `filter` returns `false`, or
+/// `make_writer` returns `None`, the file is ignored. The Write object is
+/// dropped at the end of the file.
+///
+/// Unlike `linear_extract`, there is no need to know the list of files
+/// beforehand (ie. using `list_files`).
+///
+/// If checksums verification is enabled in the reader configuration, the
+/// checksum of extracted files is verified and `Error::ChecksumMismatch` is
+/// returned on mismatch.
+pub fn linear_extract_with_filter<W, R, F, G>(
+    archive: &mut ArchiveReader<R>,
+    filter: F,
+    make_writer: G,
+) -> Result<(), Error>
+where
+    W: Write,
+    R: Read + Seek,
+    F: FnMut(&str) -> bool,
+    G: FnMut(&str) -> Option<W>,
+{
+    let options = LinearExtractOptions::for_archive(archive);
+    linear_extract_inner(archive, &options, filter, make_writer, |_path| Ok(()))
+}
+
+/// Extract the files named in `names`, in one linear pass over the archive
+///
+/// Return their contents, by filename. Names not found in the archive are
+/// absent from the result.
+///
+/// As for `linear_extract`, checksums are verified if enabled in the reader
+/// configuration.
+pub fn extract_subset<R: Read + Seek, S: BuildHasher>(
+    archive: &mut ArchiveReader<R>,
+    names: &HashSet<String, S>,
+) -> Result<HashMap<String, Vec<u8>>, Error> {
+    let mut contents: HashMap<String, Vec<u8>> = names
+        .iter()
+        .map(|fname| (fname.clone(), Vec::new()))
+        .collect();
+    // Writers left once over are the ones of files not met
+    let mut writers: HashMap<&str, &mut Vec<u8>> = contents
+        .iter_mut()
+        .map(|(fname, content)| (fname.as_str(), content))
+        .collect();
+    linear_extract_with_filter(archive, |_fname| true, |fname| writers.remove(fname))?;
+    let missing: Vec<String> = writers.into_keys().map(str::to_string).collect();
+    for fname in missing {
+        contents.remove(&fname);
+    }
+    Ok(contents)
+}
+
+/// Same as `extract_subset`, but write the files to disk, in `target_dir`
+///
+/// File paths are taken relatively to `target_dir`, and their parent
+/// directories are created. Paths containing a `..` component are ignored.
+/// Files metadata are not applied, see `apply_metadata`.
+pub fn extract_subset_to_dir<R: Read + Seek, S: BuildHasher>(
+    archive: &mut ArchiveReader<R>,
+    names: &HashSet<String, S>,
+    target_dir: &Path,
+) -> Result<(), Error> {
+    // `make_writer` can't fail: keep the first error to report it after
+    let mut create_error = None;
+    linear_extract_with_filter(
+        archive,
+        |fname| names.contains(fname),
+        |fname| {
+            if create_error.is_some() {
+                return None;
+            }
+            let path = relative_path(target_dir, fname)?;
+            let created = match path.parent() {
+                Some(parent) => fs::create_dir_all(parent),
+                None => Ok(()),
+            }
+            .and_then(|_| fs::File::create(&path));
+            match created {
+                Ok(file) => Some(file),
+                Err(err) => {
+                    create_error = Some(err);
+                    None
+                }
+            }
+        },
+    )?;
+    match create_error {
+        Some(err) => Err(err.into()),
+        None => Ok(()),
+    }
+}
+
+/// Same as `linear_extract`, reading at most `max_bytes_per_sec` bytes per
+/// second from the archive, to avoid starving other processes of I/O
+///
+/// The limit applies to the archive data, once decrypted and decompressed.
+/// To limit the raw bytes read instead, wrap the source given to
+/// `ArchiveReader::from_config` in a `RateLimitedReader`.
+/// `max_bytes_per_sec` must not be 0.
+pub fn linear_extract_rate_limited<W1: Write, R: Read + Seek, S: BuildHasher>(
+    archive: &mut ArchiveReader<R>,
+    export: &mut HashMap<&String, W1, S>,
+    max_bytes_per_sec: u64,
+) -> Result<(), Error> {
+    let options = LinearExtractOptions {
+        max_bytes_per_sec: Some(max_bytes_per_sec),
+        ..LinearExtractOptions::for_archive(archive)
+    };
+    linear_extract_with_options(archive, export, &options)
+}
+
+/// Add `count` bytes to the progress of the file `id`, if extracted, and
+/// report it
+fn report_bytes_read(
+    progress: &mut dyn ReadProgressReporter,
+    id2progress: &mut HashMap<ArchiveFileID, (String, u64)>,
+    id: ArchiveFileID,
+    count: u64,
+) {
+    if let Some((filename, total)) = id2progress.get_mut(&id) {
+        *total += count;
+        progress.on_bytes_read(filename, *total);
+    }
+}
+
+/// Linear extraction, see `linear_extract_with_filter`. `on_directory` is
+/// called with the path of each directory entry met
+pub(crate) fn linear_extract_inner<W, R, F, G, D>(
+    archive: &mut ArchiveReader<R>,
+    options: &LinearExtractOptions,
+    mut filter: F,
+    mut make_writer: G,
+    mut on_directory: D,
+) -> Result<(), Error>
+where
+    W: Write,
+    R: Read + Seek,
+    F: FnMut(&str) -> bool,
+    G: FnMut(&str) -> Option<W>,
+    D: FnMut(&str) -> Result<(), Error>,
+{
+    options.check()?;
+
+    let has_dedup = archive.features().contains(FeatureFlags::HAS_DEDUP);
+
     // Seek at the beginning
     archive.src.seek(SeekFrom::Start(0))?;
 
     // Use a BufReader to cache, by merging them into one bigger read, small
     // read calls (like the ones on ArchiveFileBlock reading)
-    let mut src = io::BufReader::new(&mut archive.src);
+    let mut src = io::BufReader::with_capacity(
+        options.buf_capacity,
+        RateLimitedReader::new(
+            &mut archive.src,
+            options.max_bytes_per_sec.unwrap_or(u64::MAX),
+        ),
+    );
 
-    // Associate an ID in the archive to the corresponding filename
-    // Do not directly associate to the writer to keep an easier fn API
+    // Associate an ID in the archive to the corresponding writer
+    let mut id2writer: HashMap<ArchiveFileID, W> = HashMap::new();
+    // Associate an ID in the archive to the checksum of the extracted content
+    let mut id2checksum: HashMap<ArchiveFileID, ChecksumHasher> = HashMap::new();
+    let verify_checksums = options.verify_checksums;
+    let skip_unknown_blocks = archive.config.skip_unknown_blocks;
+    // Names of the files, to find the content of deduplicated ones
     let mut id2filename: HashMap<ArchiveFileID, String> = HashMap::new();
+    let files_info = archive.metadata.as_ref().map(|footer| &footer.files_info);
+    // Associate an ID in the archive to the decryption of its content, if
+    // encrypted for its own recipients
+    let mut id2decryptor: HashMap<ArchiveFileID, FileDecryptor> = HashMap::new();
+    let encrypt = &archive.config.encrypt;
+    // Progress of the extracted files: name and bytes written so far
+    let progress = archive.progress.as_mut();
+    let mut id2progress: HashMap<ArchiveFileID, (String, u64)> = HashMap::new();
 
     'read_block: loop {
         match ArchiveFileBlock::from(&mut src)? {
             ArchiveFileBlock::FileStart { filename, id } => {
                 // If the starting file is meant to be extracted, get the
                 // corresponding writer
-                if export.contains_key(&filename) {
-                    id2filename.insert(id, filename.clone());
+                if filter(&filename) {
+                    if let Some(writer) = make_writer(&filename) {
+                        id2writer.insert(id, writer);
+                        if verify_checksums {
+                            id2checksum.insert(id, ChecksumHasher::new(ChecksumAlgorithm::Blake3));
+                        }
+                        let size_hint = files_info
+                            .and_then(|files_info| files_info.get(&filename))
+                            .map(|file_info| file_info.size);
+                        progress.on_file_start(&filename, size_hint);
+                        id2progress.insert(id, (filename.clone(), 0));
+                    }
+                }
+                if has_dedup {
+                    id2filename.insert(id, filename);
+                }
+            }
+            ArchiveFileBlock::Dedup { id, refers_to_id } => {
+                // Copy the content of `refers_to_id`, written before, then
+                // continue from here
+                if let Some(writer) = id2writer.get_mut(&id) {
+                    let position = src.stream_position()?;
+                    let filename = id2filename.get(&refers_to_id).ok_or_else(|| {
+                        Error::WrongReaderState(
+                            "[linear_extract] Unknown deduplicated file".to_string(),
+                        )
+                    })?;
+                    let copy_src = &mut read_referred_content(
+                        src.get_mut(),
+                        files_info,
+                        filename,
+                        refers_to_id,
+                    )?;
+                    let count = match id2checksum.get_mut(&id) {
+                        Some(checksum) => {
+                            io::copy(&mut ChecksumWrapperReader::new(copy_src, checksum), writer)?
+                        }
+                        None => io::copy(copy_src, writer)?,
+                    };
+                    report_bytes_read(progress, &mut id2progress, id, count);
+                    src.seek(SeekFrom::Start(position))?;
+                }
+            }
+            ArchiveFileBlock::FileAlias { new_name, .. } => {
+                // Copy the content of the original file, ended before, then
+                // continue from here
+                let files_info = files_info.ok_or(Error::MissingMetadata)?;
+                if filter(&new_name) {
+                    if let Some(mut writer) = make_writer(&new_name) {
+                        let position = src.stream_position()?;
+                        let file =
+                            open_file(src.get_mut(), files_info, &archive.config, &new_name)?;
+                        if let Some(mut file) = file {
+                            progress.on_file_start(&new_name, Some(file.size));
+                            let count = io::copy(&mut file.data, &mut writer)?;
+                            progress.on_bytes_read(&new_name, count);
+                            progress.on_file_end(&new_name);
+                            archive.config.audit(
+                                archive.archive_id,
+                                AuditEventType::FileContentAccess {
+                                    filename: new_name,
+                                    bytes_read: count,
+                                },
+                            );
+                        }
+                        src.seek(SeekFrom::Start(position))?;
+                    }
+                }
+            }
+            ArchiveFileBlock::FileChecksum { id, digest } => {
+                if let Some(checksum) = id2checksum.remove(&id) {
+                    if checksum.finalize() != digest {
+                        return Err(Error::ChecksumMismatch);
+                    }
+                }
+            }
+            ArchiveFileBlock::FileMetadata { .. } => {
+                // Only the content is extracted, see `apply_metadata`
+            }
+            ArchiveFileBlock::FileSizeHint { .. }
+            | ArchiveFileBlock::UserMetadata { .. }
+            | ArchiveFileBlock::Padding { .. } => {}
+            ArchiveFileBlock::FileRecipients { id, recipients } => {
+                if id2writer.contains_key(&id) {
+                    id2decryptor.insert(id, encrypt.file_decryptor(&recipients)?);
+                }
+            }
+            ArchiveFileBlock::FileTag { id, tag } => {
+                if let Some(decryptor) = id2decryptor.remove(&id) {
+                    decryptor.check_tag(&tag)?;
                 }
             }
+            ArchiveFileBlock::DirectoryEntry { path, .. } => {
+                on_directory(&path)?;
+            }
+            ArchiveFileBlock::Symlink { .. } => {
+                // Symbolic links are not created, see `create_symlink`
+            }
+            ArchiveFileBlock::Unknown { kind, .. } => {
+                check_unknown_block(kind, skip_unknown_blocks)?;
+            }
             ArchiveFileBlock::EndOfFile { id, .. } => {
+                if id2decryptor.remove(&id).is_some() {
+                    // The content has not been authenticated
+                    return Err(Error::AuthenticatedDecryptionWrongTag);
+                }
                 // Drop the corresponding writer
-                id2filename.remove(&id);
+                id2writer.remove(&id);
+                id2checksum.remove(&id);
+                if let Some((filename, total)) = id2progress.remove(&id) {
+                    progress.on_file_end(&filename);
+                    archive.config.audit(
+                        archive.archive_id,
+                        AuditEventType::FileContentAccess {
+                            filename,
+                            bytes_read: total,
+                        },
+                    );
+                }
+            }
+            ArchiveFileBlock::FileSparseHole { id, length } => {
+                // Write zeroes to the corresponding output, if any. Use a
+                // `SparseFileWriter` to turn them back into a hole
+                if let Some(writer) = id2writer.get_mut(&id) {
+                    if id2decryptor.contains_key(&id) {
+                        // Holes are not authenticated
+                        return Err(Error::WrongReaderState(
+                            "[linear_extract] Hole in an encrypted file".to_string(),
+                        ));
+                    }
+                    let copy_src = &mut io::repeat(0).take(length);
+                    let count = match id2checksum.get_mut(&id) {
+                        Some(checksum) => {
+                            io::copy(&mut ChecksumWrapperReader::new(copy_src, checksum), writer)?
+                        }
+                        None => io::copy(copy_src, writer)?,
+                    };
+                    report_bytes_read(progress, &mut id2progress, id, count);
+                }
             }
             ArchiveFileBlock::FileContent { length, id, .. } => {
                 // Write a block to the corresponding output, if any
 
                 let copy_src = &mut (&mut src).take(length);
-                // Is the file considered?
-                let mut extracted: bool = false;
-                if let Some(fname) = id2filename.get(&id) {
-                    if let Some(writer) = export.get_mut(fname) {
-                        io::copy(copy_src, writer)?;
-                        extracted = true;
+                match id2writer.get_mut(&id) {
+                    // Checksums are computed on the stored content
+                    Some(writer) => {
+                        let count = match id2checksum.get_mut(&id) {
+                            Some(checksum) => io::copy(
+                                &mut FileDecryptReader::new(
+                                    ChecksumWrapperReader::new(copy_src, checksum),
+                                    id2decryptor.get_mut(&id),
+                                ),
+                                writer,
+                            )?,
+                            None => io::copy(
+                                &mut FileDecryptReader::new(copy_src, id2decryptor.get_mut(&id)),
+                                writer,
+                            )?,
+                        };
+                        report_bytes_read(progress, &mut id2progress, id, count);
+                    }
+                    None => {
+                        // Exhaust the block to Sink to forward the reader
+                        io::copy(copy_src, &mut io::sink())?;
                     }
-                };
-                if !extracted {
-                    // Exhaust the block to Sink to forward the reader
-                    io::copy(copy_src, &mut io::sink())?;
+                }
+            }
+            ArchiveFileBlock::FilePrecompressedContent {
+                id,
+                algorithm,
+                uncompressed_length,
+                data,
+            } => {
+                if let Some(writer) = id2writer.get_mut(&id) {
+                    if id2decryptor.contains_key(&id) {
+                        return Err(Error::WrongReaderState(
+                            "[linear_extract] Pre-compressed content in an encrypted file"
+                                .to_string(),
+                        ));
+                    }
+                    let content = decompress_block_content(algorithm, &data, uncompressed_length)?;
+                    if let Some(checksum) = id2checksum.get_mut(&id) {
+                        checksum.update(&content);
+                    }
+                    writer.write_all(&content)?;
+                    report_bytes_read(progress, &mut id2progress, id, content.len() as u64);
                 }
             }
             ArchiveFileBlock::EndOfArchiveData {} => {
@@ -72,40 +627,735 @@ pub fn linear_extract<W1: Write, R: Read + Seek, S: BuildHasher>(
     Ok(())
 }
 
+/// Extract an Archive with `num_threads` threads, each one extracting whole
+/// files
+///
+/// `export` maps filenames to Write objects, as in `linear_extract`.
+///
+/// As an `ArchiveReader` can't be shared between threads, each thread opens
+/// its own reader, with `config`, on a source returned by `open_source` (for
+/// instance, the archive file opened again). The archive signature, if its
+/// verification is required by `config`, is only verified once. Files are
+/// distributed to threads from the largest to the smallest, using the footer
+/// index of file offsets and sizes.
+///
+/// If `num_threads` is 1, `linear_extract` is used instead.
+#[cfg(feature = "parallel")]
+pub fn parallel_extract<W, R, S, F>(
+    open_source: F,
+    config: &ArchiveReaderConfig,
+    export: &mut HashMap<&String, W, S>,
+    num_threads: usize,
+) -> Result<(), Error>
+where
+    W: Write + Send,
+    R: Read + Seek,
+    S: BuildHasher,
+    F: Fn() -> io::Result<R> + Sync,
+{
+    use rayon::prelude::*;
+
+    let mut archive = ArchiveReader::from_config(open_source()?, config.clone())?;
+    if num_threads == 1 {
+        return linear_extract(&mut archive, export);
+    }
+
+    // Only keep files present in the archive, largest first
+    let files_info = match &archive.metadata {
+        Some(footer) => &footer.files_info,
+        None => {
+            return Err(Error::MissingMetadata);
+        }
+    };
+    let mut jobs: Vec<(u64, &String, &mut W)> = export
+        .iter_mut()
+        .filter_map(|(fname, writer)| {
+            files_info
+                .get(*fname)
+                .map(|file_info| (file_info.size, *fname, writer))
+        })
+        .collect();
+    jobs.sort_by(|(size1, fname1, _), (size2, fname2, _)| {
+        size2.cmp(size1).then(fname1.cmp(fname2))
+    });
+
+    // The signature has already been checked by `archive`
+    let mut thread_config = config.clone();
+    thread_config.sign = Default::default();
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build()
+        .map_err(io::Error::other)?;
+    pool.install(|| {
+        jobs.into_par_iter().try_for_each_init(
+            || {
+                open_source()
+                    .map_err(Error::from)
+                    .and_then(|src| ArchiveReader::from_config(src, thread_config.clone()))
+            },
+            |archive, (_size, fname, writer)| {
+                let archive = match archive {
+                    Ok(archive) => archive,
+                    Err(err) => {
+                        return Err(Error::WrongReaderState(format!(
+                            "[parallel_extract] Unable to open the archive ({})",
+                            err
+                        )));
+                    }
+                };
+                let mut file = archive.get_file(fname.clone())?.ok_or_else(|| {
+                    Error::WrongReaderState(
+                        "[parallel_extract] A listed file can't be found".to_string(),
+                    )
+                })?;
+                io::copy(&mut file.data, writer)?;
+                Ok(())
+            },
+        )
+    })
+}
+
+/// Check the signature of an archive against `key`, without decrypting it
+///
+/// Returns `Error::MissingSignature` if the archive is not signed, and
+/// `Error::SignatureVerificationFailed` if the signature does not match (for
+/// instance, if the archive has been modified or truncated)
+pub fn verify_archive<R: Read + Seek>(mut src: R, key: &VerifyingKey) -> Result<(), Error> {
+    let mut header_hash = Sha256::new();
+    let header = ArchiveHeader::from(&mut HashWrapperReader::new(&mut src, &mut header_hash))?;
+    if !header.config.layers_enabled.contains(Layers::SIGN) {
+        return Err(Error::MissingSignature);
+    }
+    check_signature(src, key, header_hash)
+}
+
+/// Re-encrypt the archive `source` for the recipients (public keys and
+/// password) of `new_config`, writing the result to `dest`
+///
+/// Only the archive key is decrypted, using `old_config`, and re-encrypted:
+/// the encrypted data are copied as is, making it much faster than a full
+/// extraction and re-creation. As a consequence, the archive key is kept;
+/// holders of the old keys who already retrieved it can still decrypt the
+/// data of the new archive.
+///
+/// Layers of `source` are kept, except the signature: the new archive is
+/// signed only if `new_config` has a signing key (see
+/// `ArchiveWriterConfig::sign_with`). If `old_config` has a verifying key,
+/// the signature of `source` is checked first
+pub fn rekey_archive<R: Read + Seek, W: Write>(
+    mut source: R,
+    mut old_config: ArchiveReaderConfig,
+    dest: W,
+    new_config: ArchiveWriterConfig,
+) -> Result<(), Error> {
+    let mut header_hash = Sha256::new();
+    let header = ArchiveHeader::from(&mut HashWrapperReader::new(&mut source, &mut header_hash))?;
+    let layers = header.config.layers_enabled;
+    if !layers.contains(Layers::ENCRYPT) {
+        return Err(Error::BadAPIArgument(
+            "[rekey_archive] The source archive is not encrypted".to_string(),
+        ));
+    }
+    let data_start = source.stream_position()?;
+    if let Some(verifying_key) = old_config.verifying_key() {
+        if !layers.contains(Layers::SIGN) {
+            return Err(Error::MissingSignature);
+        }
+        check_signature(&mut source, verifying_key, header_hash)?;
+    }
+    let compress = header.config.compress.clone();
+    let format = header.format;
+    let comment = header.comment;
+    let archive_id = header.archive_id;
+    old_config.load_persistent(header.config)?;
+    let (key, nonce) = old_config
+        .get_encrypt_parameters()
+        .ok_or(Error::PrivateKeyNeeded)?;
+
+    // Data to copy, without the former signature
+    let mut data_size = source.seek(SeekFrom::End(0))? - data_start;
+    if layers.contains(Layers::SIGN) {
+        data_size = data_size
+            .checked_sub(SIGNATURE_LENGTH as u64)
+            .ok_or(Error::SignatureVerificationFailed)?;
+    }
+    source.seek(SeekFrom::Start(data_start))?;
+
+    // New header, with the archive key encrypted for the new recipients
+    let mut new_config = new_config;
+    new_config.encrypt.check()?;
+    new_config.encrypt.set_parameters(key, nonce);
+    let sign = new_config.is_layers_enabled(Layers::SIGN);
+    if sign {
+        new_config.sign.check()?;
+    }
+    let mut layers_enabled = layers - Layers::SIGN;
+    if sign {
+        layers_enabled |= Layers::SIGN;
+    }
+    let mut header = Vec::new();
+    ArchiveHeader {
+        // Data and footer are copied as is: keep their format. Older formats
+        // only differ from v3 by their header, which is rewritten, as v6 does
+        // from v7
+        format: if format.major < 3 {
+            FormatVersion { major: 3, minor: 0 }
+        } else if format.major == 6 {
+            FormatVersion {
+                major: FormatVersion::CURRENT.major,
+                minor: format.minor,
+            }
+        } else {
+            format
+        },
+        comment,
+        archive_id,
+        config: ArchivePersistentConfig {
+            layers_enabled,
+            encrypt: Some(new_config.encrypt.to_persistent()?),
+            compress,
+        },
+    }
+    .dump(&mut header)?;
+
+    let mut dest: Box<dyn LayerWriter<W>> = Box::new(RawLayerWriter::new(dest));
+    dest.write_all(&header)?;
+    if sign {
+        dest = Box::new(SignatureLayerWriter::new(dest, &new_config.sign, &header)?);
+    }
+    io::copy(&mut source.take(data_size), &mut dest)?;
+    dest.finalize()?;
+    Ok(())
+}
+
+/// Result of `repair_archive`
+#[derive(Debug)]
+pub struct RepairReport {
+    /// Files without `EndOfFile` in the source archive, for instance because
+    /// its writer has been killed before `end_file`, sorted. They are ended in
+    /// the repaired archive with the content recovered so far
+    pub repaired_files: Vec<String>,
+    /// Reason the scan of the source archive stopped, ideally
+    /// `FailSafeReadError::EndOfOriginalArchiveData`
+    pub stopping_error: FailSafeReadError,
+}
+
+/// Repair the archive `src`, read with `config_r`, by writing a valid archive
+/// with its recoverable content to `dest`, with `config_w`
+///
+/// `src` is scanned forward, without relying on its footer, until its end or
+/// the first error, as `ArchiveFailSafeReader::convert_to_archive` does. Files
+/// left unfinished are ended with the content met, and listed in the returned
+/// `RepairReport`
+pub fn repair_archive<R: Read, W: Write>(
+    src: R,
+    dest: W,
+    config_r: ArchiveReaderConfig,
+    config_w: ArchiveWriterConfig,
+) -> Result<RepairReport, Error> {
+    let mut mla_fsread = ArchiveFailSafeReader::from_config(src, config_r)?;
+    let mut mla_w = ArchiveWriter::from_config(dest, config_w)?;
+    let (mut repaired_files, stopping_error) = match mla_fsread.convert_to_archive(&mut mla_w)? {
+        FailSafeReadError::UnfinishedFiles {
+            filenames,
+            stopping_error,
+        } => (filenames, *stopping_error),
+        stopping_error => (Vec::new(), stopping_error),
+    };
+    repaired_files.sort();
+    Ok(RepairReport {
+        repaired_files,
+        stopping_error,
+    })
+}
+
+/// Result of `convert_archive`
+#[derive(Debug)]
+pub struct ConvertReport {
+    /// Number of files converted, directories and symbolic links excluded
+    pub files_converted: usize,
+    /// Size of the converted archive, in bytes
+    pub bytes_written: u64,
+}
+
+/// Convert the archive `src`, read with `src_config`, into a new archive
+/// written to `dest` with `dest_config`
+///
+/// Each file is decrypted and decompressed, then written again with its
+/// metadata, so that the new archive can have other layers, recipients or
+/// compression settings: for instance, an encrypted archive can be stored
+/// unencrypted, and the other way around. Directories and symbolic links are
+/// kept, without their metadata, which are not in the footer. Unlike
+/// `rekey_archive`, the whole content is processed
+pub fn convert_archive<R: Read + Seek, W: Write>(
+    src: R,
+    src_config: ArchiveReaderConfig,
+    dest: W,
+    dest_config: ArchiveWriterConfig,
+) -> Result<ConvertReport, Error> {
+    let mut mla = ArchiveReader::from_config(src, src_config)?;
+    let mut mla_out = ArchiveWriter::from_config(dest, dest_config)?;
+
+    for path in mla.list_directories()? {
+        mla_out.add_directory(path, None)?;
+    }
+    for (link_name, target) in mla.list_symlinks()? {
+        mla_out.add_symlink(link_name, target, None)?;
+    }
+    let mut fnames: Vec<String> = mla.list_files()?.cloned().collect();
+    fnames.sort();
+    for fname in &fnames {
+        let file = mla.get_file(fname)?.ok_or(Error::MissingMetadata)?;
+        let id = match &file.metadata {
+            Some(metadata) => mla_out.start_file_with_metadata(fname, metadata)?,
+            None => mla_out.start_file(fname)?,
+        };
+        mla_out.append_file_content(id, file.size, file.data)?;
+        mla_out.end_file(id)?;
+    }
+    let stats = mla_out.finalize()?;
+    Ok(ConvertReport {
+        files_converted: fnames.len(),
+        bytes_written: stats.total_bytes_written,
+    })
+}
+
+/// Set the attributes of the file at `path` according to `metadata`
+///
+/// Only the fields set in `metadata` are applied. Ownership is changed first,
+/// as it may reset some permission bits, and permissions last, as they may
+/// forbid opening the file to set its modification time.
+/// On non-Unix platforms, `mode`, `uid` and `gid` are ignored.
+pub fn apply_metadata(path: &Path, metadata: &FileMetadata) -> io::Result<()> {
+    #[cfg(unix)]
+    if metadata.uid.is_some() || metadata.gid.is_some() {
+        std::os::unix::fs::chown(path, metadata.uid, metadata.gid)?;
+    }
+    if let Some(mtime) = metadata.mtime {
+        fs::File::open(path)?.set_modified(mtime)?;
+    }
+    #[cfg(unix)]
+    if let Some(mode) = metadata.mode {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+    Ok(())
+}
+
+/// Create a symbolic link at `link`, pointing to `target`
+///
+/// `target` is used as is: as it may point outside of the extraction
+/// directory, symbolic links are never created by the linear extraction
+/// helpers, and callers should check it before calling this function.
+/// On Windows, a file symbolic link is created. On other non-Unix platforms,
+/// an `io::ErrorKind::Unsupported` error is returned.
+pub fn create_symlink(link: &Path, target: &str) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(target, link)
+    }
+    #[cfg(windows)]
+    {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = (link, target);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "symbolic links are not supported on this platform",
+        ))
+    }
+}
+
+/// Train a Zstandard dictionary of at most `target_size` bytes on `samples`,
+/// such as a few files similar to the ones to archive, for
+/// `ArchiveWriterConfig::with_zstd_dictionary`
+///
+/// Training fails with `Error::IOError` if there are too few samples, or if
+/// they are too small. A dictionary of around 100KB is usually a good choice
+pub fn train_dictionary_from_samples(
+    samples: &[&[u8]],
+    target_size: usize,
+) -> Result<Vec<u8>, Error> {
+    Ok(zstd::dict::from_samples(samples, target_size)?)
+}
+
+/// `Read` wrapper limiting the transfer rate to `bytes_per_sec` bytes per
+/// second, by sleeping when it is exceeded
+///
+/// The rate is averaged since the creation of the wrapper. A single read
+/// returns at most `bytes_per_sec` bytes. With `u64::MAX`, reads are not
+/// limited. Seeking, when `R` implements `Seek`, is forwarded as is.
+pub struct RateLimitedReader<R: Read> {
+    inner: R,
+    bytes_per_sec: u64,
+    /// Start of the transfer
+    last_instant: Instant,
+    /// Bytes read since `last_instant`
+    accumulated: u64,
+}
+
+impl<R: Read> RateLimitedReader<R> {
+    /// Limit `inner` to `bytes_per_sec` bytes per second. A limit of 0 is
+    /// raised to 1
+    pub fn new(inner: R, bytes_per_sec: u64) -> Self {
+        RateLimitedReader {
+            inner,
+            bytes_per_sec: std::cmp::max(bytes_per_sec, 1),
+            last_instant: Instant::now(),
+            accumulated: 0,
+        }
+    }
+
+    /// Return the wrapped reader
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for RateLimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.bytes_per_sec == u64::MAX {
+            return self.inner.read(buf);
+        }
+        let max_len = usize::try_from(self.bytes_per_sec).unwrap_or(usize::MAX);
+        let len = std::cmp::min(buf.len(), max_len);
+        let read = self.inner.read(&mut buf[..len])?;
+        self.accumulated = self.accumulated.saturating_add(read as u64);
+
+        // Wait until the bytes read so far fit in the allowed rate
+        let expected = Duration::from_secs_f64(self.accumulated as f64 / self.bytes_per_sec as f64);
+        let elapsed = self.last_instant.elapsed();
+        if expected > elapsed {
+            thread::sleep(expected - elapsed);
+        }
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek> Seek for RateLimitedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// `Write` wrapper writing every byte to two sinks, for instance a local file
+/// and a network stream, to be used as the output of an `ArchiveWriter`
+///
+/// Both sinks receive the complete archive. Once a sink fails, it is no
+/// longer written to, and writes keep succeeding as long as the other one
+/// works, so that it still receives a complete archive. The error of the
+/// failed sink is kept, and returned by the next `flush` (which
+/// `ArchiveWriter::finalize` calls once the archive is complete) or by
+/// `take_error`. Writes fail only once both sinks have failed.
+pub struct TeeArchiveWriter<W1: Write, W2: Write> {
+    first: W1,
+    second: W2,
+    first_failed: bool,
+    second_failed: bool,
+    /// First error of a sink, not reported yet
+    error: Option<io::Error>,
+}
+
+impl<W1: Write, W2: Write> TeeArchiveWriter<W1, W2> {
+    pub fn new(first: W1, second: W2) -> Self {
+        TeeArchiveWriter {
+            first,
+            second,
+            first_failed: false,
+            second_failed: false,
+            error: None,
+        }
+    }
+
+    /// Whether the first and the second sinks have failed, respectively
+    pub fn failed(&self) -> (bool, bool) {
+        (self.first_failed, self.second_failed)
+    }
+
+    /// Return the error of the first failed sink, if it has not been
+    /// reported yet
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    /// Return the wrapped sinks
+    pub fn into_inner(self) -> (W1, W2) {
+        (self.first, self.second)
+    }
+
+    /// Run `op` on each sink not failed yet, keeping the first error. Fail
+    /// only if no sink works anymore
+    fn for_each_sink(
+        &mut self,
+        mut op: impl FnMut(&mut dyn Write) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let mut errors = Vec::new();
+        if !self.first_failed {
+            if let Err(err) = op(&mut self.first) {
+                self.first_failed = true;
+                errors.push(err);
+            }
+        }
+        if !self.second_failed {
+            if let Err(err) = op(&mut self.second) {
+                self.second_failed = true;
+                errors.push(err);
+            }
+        }
+        if self.error.is_none() {
+            self.error = errors.into_iter().next();
+        }
+        if self.first_failed && self.second_failed {
+            return Err(io::Error::other(
+                "[TeeArchiveWriter] Both sinks have failed",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<W1: Write, W2: Write> Write for TeeArchiveWriter<W1, W2> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Sinks must receive the same bytes: each one gets the whole buffer
+        self.for_each_sink(|sink| sink.write_all(buf))?;
+        Ok(buf.len())
+    }
+
+    /// Flush the working sinks, then report the error of a failed sink, if any
+    fn flush(&mut self) -> io::Result<()> {
+        self.for_each_sink(|sink| sink.flush())?;
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
 /// Provides a Write interface on an ArchiveWriter file
 ///
 /// This interface is meant to be used in situations where length of the data
 /// source is unknown, such as a stream. One can then use the `io::copy`
 /// facilities to perform multiples block addition in the archive
+///
+/// The file is ended (see `ArchiveWriter::end_file`) on `finalize`, or when the
+/// `StreamWriter` is dropped. In the latter case, errors are ignored; use
+/// `finalize` to get them
 pub struct StreamWriter<'a, 'b, W: Write> {
-    archive: &'b mut ArchiveWriter<'a, W>,
+    archive: StreamArchive<'a, 'b, W>,
     file_id: ArchiveFileID,
+    /// Whether the file has already been ended
+    finalized: bool,
+}
+
+/// Archive written by a `StreamWriter`, either borrowed by it alone or shared
+/// with other `StreamWriter`s (see `ArchiveWriter::open_files`)
+enum StreamArchive<'a, 'b, W: Write> {
+    Unique(&'b mut ArchiveWriter<'a, W>),
+    Shared(Rc<RefCell<&'b mut ArchiveWriter<'a, W>>>),
 }
 
 impl<'a, 'b, W: Write> StreamWriter<'a, 'b, W> {
     pub fn new(archive: &'b mut ArchiveWriter<'a, W>, file_id: ArchiveFileID) -> Self {
-        Self { archive, file_id }
+        Self {
+            archive: StreamArchive::Unique(archive),
+            file_id,
+            finalized: false,
+        }
+    }
+
+    /// Write the file `file_id` to an archive shared with other
+    /// `StreamWriter`s
+    pub(crate) fn shared(
+        archive: Rc<RefCell<&'b mut ArchiveWriter<'a, W>>>,
+        file_id: ArchiveFileID,
+    ) -> Self {
+        Self {
+            archive: StreamArchive::Shared(archive),
+            file_id,
+            finalized: false,
+        }
+    }
+
+    /// Run `f` on the archive. The shared archive is only borrowed for the
+    /// duration of the call, so other `StreamWriter`s are never blocked
+    fn with_archive<T>(&mut self, f: impl FnOnce(&mut ArchiveWriter<'a, W>) -> T) -> T {
+        match &mut self.archive {
+            StreamArchive::Unique(archive) => f(archive),
+            StreamArchive::Shared(archive) => f(&mut archive.borrow_mut()),
+        }
+    }
+
+    /// End the file
+    pub fn finalize(mut self) -> Result<(), Error> {
+        self.finalized = true;
+        let file_id = self.file_id;
+        self.with_archive(|archive| archive.end_file(file_id))
+    }
+
+    /// Abandon the file instead of ending it, see
+    /// `ArchiveWriter::add_file_from_reader`
+    pub(crate) fn abandon(mut self) -> Result<(), Error> {
+        self.finalized = true;
+        let file_id = self.file_id;
+        self.with_archive(|archive| archive.abandon_file(file_id))
+    }
+}
+
+impl<'a, 'b, W: Write> Drop for StreamWriter<'a, 'b, W> {
+    fn drop(&mut self) {
+        if !self.finalized {
+            // Errors can't be reported from here, `finalize` must be used to
+            // get them
+            let file_id = self.file_id;
+            let _ = self.with_archive(|archive| archive.end_file(file_id));
+        }
     }
 }
 
 impl<'a, 'b, W: Write> Write for StreamWriter<'a, 'b, W> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.archive
-            .append_file_content(self.file_id, buf.len() as u64, buf)?;
+        let file_id = self.file_id;
+        self.with_archive(|archive| archive.append_file_content(file_id, buf.len() as u64, buf))?;
         Ok(buf.len())
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.archive.flush()
+        self.with_archive(|archive| archive.flush())
+    }
+}
+
+/// Check whether the filesystem holding `path` (a file or a directory)
+/// supports sparse files
+///
+/// A small probe file is created, then removed, next to `path`. On non-Unix
+/// platforms, `false` is always returned
+pub fn supports_sparse(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = if path.is_dir() {
+            path
+        } else {
+            match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            }
+        };
+        let probe = dir.join(format!(".mla_sparse_probe_{}", std::process::id()));
+        // A fully sparse file uses (almost) no block
+        const PROBE_SIZE: u64 = 1024 * 1024;
+        let result = fs::File::create(&probe)
+            .and_then(|file| file.set_len(PROBE_SIZE))
+            .and_then(|_| fs::metadata(&probe))
+            .map(|metadata| metadata.blocks() * 512 < PROBE_SIZE);
+        let _ = fs::remove_file(&probe);
+        result.unwrap_or(false)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// Write to a `fs::File`, turning runs of zeroes into holes
+///
+/// If `sparse` is set, each write made only of zeroes (such as the content
+/// of a `FileSparseHole`, as written by `linear_extract` or read from
+/// `ArchiveReader::get_file`) is replaced by a seek past it. Otherwise, data
+/// are written as is.
+///
+/// The file length is fixed, in case of a trailing hole, on `finalize`, or
+/// when the `SparseFileWriter` is dropped. In the latter case, errors are
+/// ignored; use `finalize` to get them
+pub struct SparseFileWriter {
+    file: fs::File,
+    sparse: bool,
+    /// Whether the last write has been replaced by a seek
+    in_hole: bool,
+    /// Whether the file length has already been fixed
+    finalized: bool,
+}
+
+impl SparseFileWriter {
+    pub fn new(file: fs::File, sparse: bool) -> Self {
+        Self {
+            file,
+            sparse,
+            in_hole: false,
+            finalized: false,
+        }
+    }
+
+    /// Create the file at `path`, with holes if its filesystem supports them
+    /// (see `supports_sparse`)
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let sparse = supports_sparse(path);
+        Ok(Self::new(fs::File::create(path)?, sparse))
+    }
+
+    /// Fix the file length, if it ends with a hole
+    pub fn finalize(mut self) -> io::Result<()> {
+        self.finalized = true;
+        self.set_final_len()
+    }
+
+    fn set_final_len(&mut self) -> io::Result<()> {
+        if self.in_hole {
+            let len = self.file.stream_position()?;
+            self.file.set_len(len)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SparseFileWriter {
+    fn drop(&mut self) {
+        if !self.finalized {
+            // Errors can't be reported from here, `finalize` must be used to
+            // get them
+            let _ = self.set_final_len();
+        }
+    }
+}
+
+impl Write for SparseFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.sparse && !buf.is_empty() && buf.iter().all(|byte| *byte == 0) {
+            let len = i64::try_from(buf.len())
+                .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+            self.file.seek(SeekFrom::Current(len))?;
+            self.in_hole = true;
+            return Ok(buf.len());
+        }
+        let count = self.file.write(buf)?;
+        if count > 0 {
+            self.in_hole = false;
+        }
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::crypto::password::Argon2Params;
+    use crate::errors::ConfigError;
     use crate::tests::build_archive;
     use crate::*;
+    use ed25519_dalek::SigningKey;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
     use std::io::Cursor;
+    use x25519_dalek::{PublicKey, StaticSecret};
 
     #[test]
     fn full_linear_extract() {
@@ -134,6 +1384,145 @@ mod tests {
         }
     }
 
+    #[test]
+    fn best_effort_linear_extract() {
+        // Build an archive with 3 files, spanning several encryption chunks
+        const SIZE: usize = 300 * 1024;
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::ENCRYPT)
+            .add_public_keys(&[PublicKey::from(&key)]);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let files: Vec<(String, Vec<u8>)> = (0..3)
+            .map(|i| {
+                let content = (0..SIZE).map(|j| ((i + j) % 251) as u8).collect();
+                (format!("file{}", i), content)
+            })
+            .collect();
+        for (fname, content) in &files {
+            mla.add_file(fname, content.len() as u64, content.as_slice())
+                .unwrap();
+        }
+        mla.finalize().unwrap();
+        let mut archive = mla.into_raw();
+
+        // Corrupt a chunk in the middle of the second file
+        archive[SIZE + SIZE / 2] ^= 0xFF;
+
+        let fnames: Vec<String> = files.iter().map(|(fname, _)| fname.clone()).collect();
+        let reader_config = |best_effort| {
+            let mut config = ArchiveReaderConfig::new();
+            config
+                .add_private_keys(std::slice::from_ref(&key))
+                .best_effort_extraction(best_effort);
+            config
+        };
+
+        // Without best-effort, the extraction stops on the corrupted file
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(archive.as_slice()), reader_config(false))
+                .unwrap();
+        let mut export: HashMap<&String, Vec<u8>> =
+            fnames.iter().map(|fname| (fname, Vec::new())).collect();
+        assert!(linear_extract_best_effort(&mut mla_read, &mut export).is_err());
+
+        // With best-effort, the other files are extracted
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(archive.as_slice()), reader_config(true))
+                .unwrap();
+        let mut export: HashMap<&String, Vec<u8>> =
+            fnames.iter().map(|fname| (fname, Vec::new())).collect();
+        let failures = linear_extract_best_effort(&mut mla_read, &mut export).unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, files[1].0);
+        assert_eq!(export[&files[0].0], files[0].1);
+        assert!(export[&files[1].0].is_empty());
+        assert_eq!(export[&files[2].0], files[2].1);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_extract_files() {
+        // Build an archive with 3 interleaved files
+        let (mla, key, files) = build_archive(None, true);
+        let dest = mla.into_raw();
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+
+        for num_threads in [1, 2, 4] {
+            let mut export: HashMap<&String, Vec<u8>> =
+                files.iter().map(|(fname, _)| (fname, Vec::new())).collect();
+            // Files not in the archive are ignored
+            let unknown = "unknown".to_string();
+            export.insert(&unknown, Vec::new());
+            parallel_extract(
+                || Ok(Cursor::new(dest.as_slice())),
+                &config,
+                &mut export,
+                num_threads,
+            )
+            .unwrap();
+            for (fname, content) in files.iter() {
+                assert_eq!(export.get(fname).unwrap(), content);
+            }
+            assert!(export.get(&unknown).unwrap().is_empty());
+        }
+
+        // Errors on opening are reported
+        let mut export: HashMap<&String, Vec<u8>> = HashMap::new();
+        assert!(parallel_extract(
+            || Err::<Cursor<&[u8]>, _>(io::Error::from(io::ErrorKind::NotFound)),
+            &config,
+            &mut export,
+            4,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn linear_extract_buf_capacity() {
+        let (mla, key, files) = build_archive(Some(Layers::default()), true);
+        let dest = mla.into_raw();
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(Cursor::new(dest), config).unwrap();
+
+        let expected: Vec<_> = files
+            .iter()
+            .map(|(_fname, content)| Sha256::digest(content))
+            .collect();
+        for buf_capacity in [4096, 8 * 1024, 64 * 1024, 512 * 1024, 128 * 1024 * 1024] {
+            let options = LinearExtractOptions {
+                buf_capacity,
+                verify_checksums: true,
+                ..Default::default()
+            };
+            let mut export: HashMap<&String, Vec<u8>> =
+                files.iter().map(|(fname, _)| (fname, Vec::new())).collect();
+            linear_extract_with_options(&mut mla_read, &mut export, &options).unwrap();
+            let hashes: Vec<_> = files
+                .iter()
+                .map(|(fname, _)| Sha256::digest(&export[fname]))
+                .collect();
+            assert_eq!(hashes, expected);
+        }
+
+        // Out of bounds capacities are rejected
+        for buf_capacity in [0, 4095, 128 * 1024 * 1024 + 1] {
+            let options = LinearExtractOptions {
+                buf_capacity,
+                ..Default::default()
+            };
+            let mut export: HashMap<&String, Vec<u8>> = HashMap::new();
+            assert!(matches!(
+                linear_extract_with_options(&mut mla_read, &mut export, &options),
+                Err(Error::BadAPIArgument(_))
+            ));
+        }
+    }
+
     #[test]
     fn one_linear_extract() {
         // Build an archive with 3 files
@@ -154,6 +1543,731 @@ mod tests {
         assert_eq!(export.get(&files[0].0).unwrap(), &files[0].1);
     }
 
+    #[test]
+    fn filtered_linear_extract() {
+        // Build an archive with 3 interleaved files
+        let (mla, key, files) = build_archive(None, true);
+
+        // Prepare the reader
+        let dest = Cursor::new(mla.into_raw());
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(dest, config).unwrap();
+
+        // Extract all files but the second one, without listing them first
+        let mut outputs: HashMap<String, Vec<u8>> = files
+            .iter()
+            .map(|(fname, _content)| (fname.clone(), Vec::new()))
+            .collect();
+        let mut writers: HashMap<&str, &mut Vec<u8>> = outputs
+            .iter_mut()
+            .map(|(fname, output)| (fname.as_str(), output))
+            .collect();
+        linear_extract_with_filter(
+            &mut mla_read,
+            |fname| fname != files[1].0,
+            |fname| writers.remove(fname),
+        )
+        .expect("Extract error");
+        // Writers are only requested for non-filtered files
+        assert_eq!(writers.len(), 1);
+        assert!(writers.contains_key(files[1].0.as_str()));
+
+        // Check file per file
+        assert_eq!(outputs.get(&files[0].0).unwrap(), &files[0].1);
+        assert!(outputs.get(&files[1].0).unwrap().is_empty());
+        assert_eq!(outputs.get(&files[2].0).unwrap(), &files[2].1);
+    }
+
+    #[test]
+    fn rate_limited_linear_extract() {
+        const MB: usize = 1024 * 1024;
+        let content: Vec<u8> = (0..10 * MB).map(|i| (i % 251) as u8).collect();
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        mla.add_file("my_file", content.len() as u64, content.as_slice())
+            .unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+        let fname = "my_file".to_string();
+
+        // 10MB at 1MB/s
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut export: HashMap<&String, Vec<u8>> = HashMap::new();
+        export.insert(&fname, Vec::new());
+        let start = Instant::now();
+        linear_extract_rate_limited(&mut mla_read, &mut export, MB as u64).unwrap();
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed >= Duration::from_secs(9) && elapsed <= Duration::from_secs(11),
+            "{:?}",
+            elapsed
+        );
+        assert_eq!(export[&fname], content);
+
+        // No limit
+        let mut export_max: HashMap<&String, Vec<u8>> = HashMap::new();
+        export_max.insert(&fname, Vec::new());
+        let start = Instant::now();
+        linear_extract_rate_limited(&mut mla_read, &mut export_max, u64::MAX).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(9));
+        let mut export_unlimited: HashMap<&String, Vec<u8>> = HashMap::new();
+        export_unlimited.insert(&fname, Vec::new());
+        linear_extract(&mut mla_read, &mut export_unlimited).unwrap();
+        assert_eq!(export_max, export_unlimited);
+
+        assert!(matches!(
+            linear_extract_rate_limited(&mut mla_read, &mut export_max, 0),
+            Err(Error::BadAPIArgument(_))
+        ));
+    }
+
+    #[test]
+    fn rate_limited_reader() {
+        let data = vec![1u8; 300];
+        let mut reader = RateLimitedReader::new(data.as_slice(), 1000);
+        let mut buf = [0u8; 2000];
+        // Reads are cut to the rate
+        assert_eq!(reader.read(&mut buf).unwrap(), 300);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+        assert!(reader.last_instant.elapsed() >= Duration::from_millis(300));
+
+        let mut reader = RateLimitedReader::new(Cursor::new(vec![1u8, 2, 3, 4]), 2);
+        let mut buf = [0u8; 4];
+        assert_eq!(reader.read(&mut buf).unwrap(), 2);
+        reader.seek(SeekFrom::Start(3)).unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], 4);
+        assert_eq!(reader.into_inner().position(), 4);
+    }
+
+    /// Sink failing once more than `limit` bytes would have been written
+    struct FailingWriter {
+        written: Vec<u8>,
+        limit: usize,
+    }
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.written.len() + buf.len() > self.limit {
+                return Err(io::Error::other("Sink full"));
+            }
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn tee_archive_writer() {
+        // Both sinks receive the same archive
+        let (_, key, files) = build_archive(None, false);
+        let mut config = ArchiveWriterConfig::default();
+        config.add_public_key(&PublicKey::from(&key));
+        let mut mla =
+            ArchiveWriter::from_config(TeeArchiveWriter::new(Vec::new(), Vec::new()), config)
+                .unwrap();
+        for (fname, content) in &files {
+            mla.add_file(fname, content.len() as u64, content.as_slice())
+                .unwrap();
+        }
+        mla.finalize().unwrap();
+        let tee = mla.into_raw();
+        assert_eq!(tee.failed(), (false, false));
+        let (first, second) = tee.into_inner();
+        assert_eq!(first, second);
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        assert_eq!(read_all(&first, config).unwrap(), files);
+
+        // A failing sink doesn't corrupt the other one
+        // The header is 60 bytes long: fail while writing the first file
+        let failing = FailingWriter {
+            written: Vec::new(),
+            limit: 80,
+        };
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::EMPTY);
+        let mut mla =
+            ArchiveWriter::from_config(TeeArchiveWriter::new(failing, Vec::new()), config).unwrap();
+        for (fname, content) in &files {
+            mla.add_file(fname, content.len() as u64, content.as_slice())
+                .unwrap();
+        }
+        // The error is reported once the archive is complete
+        assert!(matches!(mla.finalize(), Err(Error::IOError(_))));
+        let mut tee = mla.into_raw();
+        assert_eq!(tee.failed(), (true, false));
+        assert!(tee.take_error().is_none());
+        let (failing, healthy) = tee.into_inner();
+        assert!(failing.written.len() <= 80);
+        assert!(healthy.starts_with(&failing.written));
+        // The other sink holds the complete archive
+        assert_eq!(
+            read_all(&healthy, ArchiveReaderConfig::new()).unwrap(),
+            files
+        );
+
+        // The error can also be taken before flushing
+        let mut tee = TeeArchiveWriter::new(
+            FailingWriter {
+                written: Vec::new(),
+                limit: 4,
+            },
+            Vec::new(),
+        );
+        tee.write_all(&[1; 8]).unwrap();
+        assert!(tee.take_error().is_some());
+        tee.flush().unwrap();
+
+        // Writes fail once both sinks have failed
+        let mut tee = TeeArchiveWriter::new(
+            FailingWriter {
+                written: Vec::new(),
+                limit: 4,
+            },
+            FailingWriter {
+                written: Vec::new(),
+                limit: 8,
+            },
+        );
+        tee.write_all(&[1; 4]).unwrap();
+        tee.write_all(&[2; 4]).unwrap();
+        assert!(tee.write_all(&[3; 4]).is_err());
+        assert!(tee.write_all(&[4; 4]).is_err());
+        assert_eq!(tee.failed(), (true, true));
+        let (first, second) = tee.into_inner();
+        assert_eq!(first.written, [1; 4]);
+        assert_eq!(second.written, [1, 1, 1, 1, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn subset_extract() {
+        // Build an archive with 3 interleaved files
+        let (mla, key, files) = build_archive(None, true);
+        let dest = mla.into_raw();
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(Cursor::new(dest), config).unwrap();
+
+        // Full extraction, for reference
+        let mut export: HashMap<&String, Vec<u8>> =
+            files.iter().map(|(fname, _)| (fname, Vec::new())).collect();
+        linear_extract(&mut mla_read, &mut export).unwrap();
+
+        let names: HashSet<String> = vec![files[0].0.clone(), files[2].0.clone(), "unknown".into()]
+            .into_iter()
+            .collect();
+        let subset = extract_subset(&mut mla_read, &names).unwrap();
+        // Only requested files present in the archive are returned
+        assert_eq!(subset.len(), 2);
+        assert_eq!(&subset[&files[0].0], &export[&files[0].0]);
+        assert_eq!(&subset[&files[2].0], &export[&files[2].0]);
+
+        assert!(extract_subset(&mut mla_read, &HashSet::new())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn subset_extract_to_dir() {
+        let file = Vec::new();
+        let mut mla = ArchiveWriter::from_config(file, ArchiveWriterConfig::new()).unwrap();
+        mla.add_file("a/b/my_file", 2, [1, 2].as_ref()).unwrap();
+        mla.add_file("other_file", 1, [3].as_ref()).unwrap();
+        mla.add_file("../outside", 1, [4].as_ref()).unwrap();
+        mla.finalize().unwrap();
+
+        let dest = mla.into_raw();
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest), ArchiveReaderConfig::new()).unwrap();
+        let output_dir = std::env::temp_dir().join(format!("mla_subset_{}", std::process::id()));
+        fs::create_dir(&output_dir).unwrap();
+        let names: HashSet<String> = vec!["a/b/my_file".to_string(), "../outside".to_string()]
+            .into_iter()
+            .collect();
+        let result = extract_subset_to_dir(&mut mla_read, &names, &output_dir);
+
+        let content = fs::read(output_dir.join("a").join("b").join("my_file"));
+        let other = output_dir.join("other_file").exists();
+        let escaped = output_dir.join("..").join("outside").exists();
+        fs::remove_dir_all(&output_dir).unwrap();
+        result.unwrap();
+        assert_eq!(content.unwrap(), vec![1, 2]);
+        assert!(!other);
+        assert!(!escaped);
+    }
+
+    /// Read every file of `archive` with `config`, as (name, content)
+    fn read_all(
+        archive: &[u8],
+        config: ArchiveReaderConfig,
+    ) -> Result<Vec<(String, Vec<u8>)>, Error> {
+        let mut mla_read = ArchiveReader::from_config(Cursor::new(archive), config)?;
+        let mut names: Vec<String> = mla_read.list_files()?.cloned().collect();
+        names.sort();
+        let mut files = Vec::new();
+        for name in names {
+            let mut content = Vec::new();
+            let mut file = mla_read.get_file(&name)?.unwrap();
+            file.data.read_to_end(&mut content)?;
+            files.push((name, content));
+        }
+        Ok(files)
+    }
+
+    #[test]
+    fn rekey_archive_recipients() {
+        let (mla, old_key, mut files) = build_archive(None, true);
+        files.sort();
+        let archive = mla.into_raw();
+        let new_key = StaticSecret::from([4; 32]);
+
+        let mut old_config = ArchiveReaderConfig::new();
+        old_config.add_private_keys(std::slice::from_ref(&old_key));
+        let mut new_config = ArchiveWriterConfig::new();
+        new_config
+            .add_public_key(&PublicKey::from(&new_key))
+            .with_password(
+                b"password",
+                Argon2Params {
+                    m_cost: 64,
+                    t_cost: 1,
+                    p_cost: 1,
+                },
+            );
+        let mut rekeyed = Vec::new();
+        rekey_archive(Cursor::new(&archive), old_config, &mut rekeyed, new_config).unwrap();
+        // Only the header changes
+        assert_eq!(
+            &rekeyed[rekeyed.len() - 100..],
+            &archive[archive.len() - 100..]
+        );
+
+        // Readable with the new key, or the new password
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&new_key));
+        assert_eq!(read_all(&rekeyed, config).unwrap(), files);
+        let mut config = ArchiveReaderConfig::new();
+        config.with_password(b"password");
+        assert_eq!(read_all(&rekeyed, config).unwrap(), files);
+
+        // But not with the old one
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&old_key));
+        assert!(matches!(
+            read_all(&rekeyed, config),
+            Err(Error::ConfigError(ConfigError::PrivateKeyNotFound))
+        ));
+
+        // The old key is needed to rekey
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&[new_key]);
+        let mut new_config = ArchiveWriterConfig::new();
+        new_config.add_public_key(&PublicKey::from(&old_key));
+        assert!(rekey_archive(Cursor::new(&archive), config, Vec::new(), new_config).is_err());
+
+        // At least one new recipient is needed
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&old_key));
+        assert!(rekey_archive(
+            Cursor::new(&archive),
+            config,
+            Vec::new(),
+            ArchiveWriterConfig::new()
+        )
+        .is_err());
+
+        // Unencrypted archives can't be rekeyed
+        let (mla, _, _) = build_archive(Some(Layers::COMPRESS), false);
+        let mut new_config = ArchiveWriterConfig::new();
+        new_config.add_public_key(&PublicKey::from(&old_key));
+        assert!(matches!(
+            rekey_archive(
+                Cursor::new(mla.into_raw()),
+                ArchiveReaderConfig::new(),
+                Vec::new(),
+                new_config
+            ),
+            Err(Error::BadAPIArgument(_))
+        ));
+    }
+
+    #[test]
+    fn rekey_archive_signature() {
+        let old_key = StaticSecret::from([2; 32]);
+        let new_key = StaticSecret::from([4; 32]);
+        let old_signing_key = SigningKey::from_bytes(&[1; 32]);
+        let new_signing_key = SigningKey::from_bytes(&[3; 32]);
+        let mut config = ArchiveWriterConfig::default();
+        config
+            .add_public_key(&PublicKey::from(&old_key))
+            .sign_with(&old_signing_key);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("my_file", 4, [1, 2, 3, 4].as_ref()).unwrap();
+        mla.finalize().unwrap();
+        let archive = mla.into_raw();
+
+        let rekey = |new_config: ArchiveWriterConfig| {
+            let mut old_config = ArchiveReaderConfig::new();
+            old_config
+                .add_private_keys(std::slice::from_ref(&old_key))
+                .verify_with(&old_signing_key.verifying_key());
+            let mut rekeyed = Vec::new();
+            rekey_archive(Cursor::new(&archive), old_config, &mut rekeyed, new_config)
+                .map(|_| rekeyed)
+        };
+        let mut new_config = ArchiveWriterConfig::new();
+        new_config
+            .add_public_key(&PublicKey::from(&new_key))
+            .sign_with(&new_signing_key);
+        let signed = rekey(new_config).unwrap();
+        let mut new_config = ArchiveWriterConfig::new();
+        new_config.add_public_key(&PublicKey::from(&new_key));
+        let unsigned = rekey(new_config).unwrap();
+
+        // Signed again with the new signing key
+        verify_archive(Cursor::new(&signed), &new_signing_key.verifying_key()).unwrap();
+        assert!(verify_archive(Cursor::new(&signed), &old_signing_key.verifying_key()).is_err());
+        let mut config = ArchiveReaderConfig::new();
+        config
+            .add_private_keys(std::slice::from_ref(&new_key))
+            .verify_with(&new_signing_key.verifying_key());
+        assert_eq!(
+            read_all(&signed, config).unwrap(),
+            vec![("my_file".to_string(), vec![1, 2, 3, 4])]
+        );
+
+        // Or not signed anymore
+        assert!(matches!(
+            verify_archive(Cursor::new(&unsigned), &old_signing_key.verifying_key()),
+            Err(Error::MissingSignature)
+        ));
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&new_key));
+        assert_eq!(
+            read_all(&unsigned, config).unwrap(),
+            vec![("my_file".to_string(), vec![1, 2, 3, 4])]
+        );
+
+        // The source signature is checked
+        let mut tampered = archive.clone();
+        let last = tampered.len() - 100;
+        tampered[last] ^= 1;
+        let mut old_config = ArchiveReaderConfig::new();
+        old_config
+            .add_private_keys(std::slice::from_ref(&old_key))
+            .verify_with(&old_signing_key.verifying_key());
+        let mut new_config = ArchiveWriterConfig::new();
+        new_config.add_public_key(&PublicKey::from(&new_key));
+        assert!(matches!(
+            rekey_archive(Cursor::new(&tampered), old_config, Vec::new(), new_config),
+            Err(Error::SignatureVerificationFailed)
+        ));
+    }
+
+    #[test]
+    fn convert_archive_round_trip() {
+        let key = StaticSecret::from([2; 32]);
+        let new_key = StaticSecret::from([4; 32]);
+        let metadata = FileMetadata {
+            mode: Some(0o640),
+            ..Default::default()
+        };
+        let mut config = ArchiveWriterConfig::default();
+        config.add_public_key(&PublicKey::from(&key));
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_directory("dir", None).unwrap();
+        mla.add_symlink("link", "dir/big", None).unwrap();
+        let big: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        mla.add_file("dir/big", big.len() as u64, big.as_slice())
+            .unwrap();
+        let id = mla
+            .start_file_with_metadata("with_metadata", &metadata)
+            .unwrap();
+        mla.append_file_content(id, 4, [1, 2, 3, 4].as_ref())
+            .unwrap();
+        mla.end_file(id).unwrap();
+        mla.add_file("empty", 0, [].as_ref()).unwrap();
+        mla.finalize().unwrap();
+        let encrypted = mla.into_raw();
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let files = read_all(&encrypted, config.clone()).unwrap();
+        assert!(read_all(&encrypted, ArchiveReaderConfig::new()).is_err());
+
+        // Encrypted -> unencrypted
+        let mut new_config = ArchiveWriterConfig::new();
+        new_config.set_layers(Layers::COMPRESS);
+        let mut decrypted = Vec::new();
+        let report =
+            convert_archive(Cursor::new(&encrypted), config, &mut decrypted, new_config).unwrap();
+        assert_eq!(report.files_converted, files.len());
+        assert_eq!(report.bytes_written, decrypted.len() as u64);
+        assert_eq!(
+            read_all(&decrypted, ArchiveReaderConfig::new()).unwrap(),
+            files
+        );
+
+        // Unencrypted -> encrypted, for another recipient
+        let mut new_config = ArchiveWriterConfig::default();
+        new_config.add_public_key(&PublicKey::from(&new_key));
+        let mut encrypted_again = Vec::new();
+        let report = convert_archive(
+            Cursor::new(&decrypted),
+            ArchiveReaderConfig::new(),
+            &mut encrypted_again,
+            new_config,
+        )
+        .unwrap();
+        assert_eq!(report.files_converted, files.len());
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&new_key));
+        assert_eq!(read_all(&encrypted_again, config.clone()).unwrap(), files);
+
+        // Directories, symbolic links and metadata are kept
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(&encrypted_again), config).unwrap();
+        assert_eq!(
+            mla_read.list_directories().unwrap().collect::<Vec<_>>(),
+            ["dir"]
+        );
+        assert_eq!(
+            mla_read.list_symlinks().unwrap().collect::<Vec<_>>(),
+            [("link", "dir/big")]
+        );
+        let file = mla_read.get_file("with_metadata").unwrap().unwrap();
+        assert_eq!(file.metadata, Some(metadata));
+
+        // The old key can't read the converted archive
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&[key]);
+        assert!(read_all(&encrypted_again, config).is_err());
+    }
+
+    #[test]
+    fn repair_archive_unfinished_files() {
+        // Writer killed before ending some files. Contents are not kept in
+        // memory, so that they are all written before the writer is killed
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::EMPTY).coalesce_threshold(0);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("complete", 4, [1, 2, 3, 4].as_ref()).unwrap();
+        let id_unfinished = mla.start_file("unfinished").unwrap();
+        mla.append_file_content(id_unfinished, 3, [5, 6, 7].as_ref())
+            .unwrap();
+        mla.start_file("unfinished_empty").unwrap();
+        mla.add_file("late", 2, [8, 9].as_ref()).unwrap();
+        mla.append_file_content(id_unfinished, 1, [10].as_ref())
+            .unwrap();
+        let archive = mla.abort();
+        assert!(
+            ArchiveReader::from_config(Cursor::new(&archive), ArchiveReaderConfig::new()).is_err()
+        );
+
+        let mut repaired = Vec::new();
+        let report = repair_archive(
+            archive.as_slice(),
+            &mut repaired,
+            ArchiveReaderConfig::new(),
+            ArchiveWriterConfig::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            report.repaired_files,
+            vec!["unfinished".to_string(), "unfinished_empty".to_string()]
+        );
+        assert!(matches!(
+            report.stopping_error,
+            FailSafeReadError::UnexpectedEOFOnNextBlock
+        ));
+        assert_eq!(
+            read_all(&repaired, ArchiveReaderConfig::new()).unwrap(),
+            vec![
+                ("complete".to_string(), vec![1, 2, 3, 4]),
+                ("late".to_string(), vec![8, 9]),
+                ("unfinished".to_string(), vec![5, 6, 7, 10]),
+                ("unfinished_empty".to_string(), vec![]),
+            ]
+        );
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(&repaired), ArchiveReaderConfig::new()).unwrap();
+        let report = mla_read.verify_integrity().unwrap();
+        assert_eq!(report.files_ok, 4);
+        assert!(report.archive_complete);
+
+        // Nothing to repair in a complete archive
+        let (mla, key, files) = build_archive(None, false);
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut repaired = Vec::new();
+        let report = repair_archive(
+            mla.into_raw().as_slice(),
+            &mut repaired,
+            config,
+            ArchiveWriterConfig::new(),
+        )
+        .unwrap();
+        assert!(report.repaired_files.is_empty());
+        assert!(matches!(
+            report.stopping_error,
+            FailSafeReadError::EndOfOriginalArchiveData
+        ));
+        let mla_read =
+            ArchiveReader::from_config(Cursor::new(&repaired), ArchiveReaderConfig::new()).unwrap();
+        assert_eq!(mla_read.list_files().unwrap().count(), files.len());
+    }
+
+    #[test]
+    fn verify_archive_signature() {
+        let signing_key = SigningKey::from_bytes(&[1; 32]);
+        let mut config = ArchiveWriterConfig::default();
+        config
+            .add_public_key(&PublicKey::from(&StaticSecret::from([2; 32])))
+            .sign_with(&signing_key);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("my_file", 4, [1, 2, 3, 4].as_ref()).unwrap();
+        mla.finalize().unwrap();
+        let mut dest = mla.into_raw();
+
+        // No private key is needed
+        verify_archive(Cursor::new(&dest), &signing_key.verifying_key()).unwrap();
+        assert!(matches!(
+            verify_archive(
+                Cursor::new(&dest),
+                &SigningKey::from_bytes(&[3; 32]).verifying_key()
+            ),
+            Err(Error::SignatureVerificationFailed)
+        ));
+        let last = dest.len() - 100;
+        dest[last] ^= 1;
+        assert!(matches!(
+            verify_archive(Cursor::new(&dest), &signing_key.verifying_key()),
+            Err(Error::SignatureVerificationFailed)
+        ));
+
+        // Unsigned archive
+        let (mla, _key, _files) = build_archive(None, false);
+        assert!(matches!(
+            verify_archive(Cursor::new(mla.into_raw()), &signing_key.verifying_key()),
+            Err(Error::MissingSignature)
+        ));
+    }
+
+    #[test]
+    fn apply_file_metadata() {
+        let path = std::env::temp_dir().join(format!("mla_metadata_{}", std::process::id()));
+        fs::write(&path, b"content").unwrap();
+        let metadata = FileMetadata {
+            mtime: Some(
+                std::time::SystemTime::UNIX_EPOCH + std::time::Duration::new(1_600_000_000, 0),
+            ),
+            mode: Some(0o640),
+            uid: None,
+            gid: None,
+        };
+        apply_metadata(&path, &metadata).unwrap();
+
+        let applied = FileMetadata::from(&fs::metadata(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(applied.mtime, metadata.mtime);
+        #[cfg(unix)]
+        assert_eq!(applied.mode, metadata.mode);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn create_symlink_unix() {
+        let link = std::env::temp_dir().join(format!("mla_symlink_{}", std::process::id()));
+        create_symlink(&link, "some/target").unwrap();
+        let target = fs::read_link(&link);
+        fs::remove_file(&link).unwrap();
+        assert_eq!(target.unwrap(), PathBuf::from("some/target"));
+    }
+
+    #[test]
+    fn sparse_file_writer() {
+        let fname = "sparse".to_string();
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        let id = mla.start_file(&fname).unwrap();
+        mla.append_file_sparse(
+            id,
+            8 + (4 << 20),
+            &[
+                SparseData::Data(b"head"),
+                SparseData::Zeroes(2 << 20),
+                SparseData::Data(b"tail"),
+                SparseData::Zeroes(2 << 20),
+            ],
+        )
+        .unwrap();
+        mla.end_file(id).unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        let path = std::env::temp_dir().join(format!("mla_sparse_{}", std::process::id()));
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest), ArchiveReaderConfig::new()).unwrap();
+        let mut export = HashMap::new();
+        export.insert(&fname, SparseFileWriter::create(&path).unwrap());
+        linear_extract(&mut mla_read, &mut export).unwrap();
+        export.remove(&fname).unwrap().finalize().unwrap();
+
+        let content = fs::read(&path);
+        #[cfg(unix)]
+        let blocks = {
+            use std::os::unix::fs::MetadataExt;
+            fs::metadata(&path).unwrap().blocks()
+        };
+        let sparse = supports_sparse(&path);
+        fs::remove_file(&path).unwrap();
+
+        let content = content.unwrap();
+        assert_eq!(content.len(), 8 + (4 << 20));
+        assert_eq!(&content[..4], b"head");
+        assert_eq!(&content[(2 << 20) + 4..(2 << 20) + 8], b"tail");
+        assert!(content[4..(2 << 20) + 4].iter().all(|byte| *byte == 0));
+        assert!(content[(2 << 20) + 8..].iter().all(|byte| *byte == 0));
+        #[cfg(unix)]
+        if sparse {
+            // Holes have not been allocated
+            assert!(blocks * 512 < 1 << 20);
+        }
+    }
+
+    #[test]
+    fn linear_extract_directories() {
+        let file = Vec::new();
+        let mut mla = ArchiveWriter::from_config(file, ArchiveWriterConfig::new()).unwrap();
+        mla.add_directory("/a/b", None).unwrap();
+        mla.add_directory("../outside", None).unwrap();
+        mla.add_file("my_file", 2, [1, 2].as_ref()).unwrap();
+        mla.finalize().unwrap();
+
+        let dest = mla.into_raw();
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest), ArchiveReaderConfig::new()).unwrap();
+        let output_dir =
+            std::env::temp_dir().join(format!("mla_directories_{}", std::process::id()));
+        fs::create_dir(&output_dir).unwrap();
+        let fname = "my_file".to_string();
+        let mut export: HashMap<&String, Vec<u8>> = HashMap::new();
+        export.insert(&fname, Vec::new());
+        let result = linear_extract_with_directories(&mut mla_read, &mut export, &output_dir);
+
+        let created = output_dir.join("a").join("b").is_dir();
+        let escaped = output_dir.join("..").join("outside").exists();
+        fs::remove_dir_all(&output_dir).unwrap();
+        result.unwrap();
+        assert!(created);
+        assert!(!escaped);
+        assert_eq!(export.get(&fname).unwrap(), &vec![1, 2]);
+    }
+
     #[test]
     fn stream_writer() {
         let file = Vec::new();
@@ -167,7 +2281,7 @@ mod tests {
         let mut sw = StreamWriter::new(&mut mla, id);
         sw.write_all(&fake_file[..5]).unwrap();
         sw.write_all(&fake_file[5..]).unwrap();
-        mla.end_file(id).unwrap();
+        sw.finalize().unwrap();
 
         // Using io::copy
         let id = mla.start_file("my_file2").unwrap();
@@ -176,7 +2290,16 @@ mod tests {
             io::copy(&mut fake_file.as_slice(), &mut sw).unwrap(),
             fake_file.len() as u64
         );
-        mla.end_file(id).unwrap();
+        sw.finalize().unwrap();
+
+        // Without finalization, the file is ended on drop
+        let id = mla.start_file("my_file3").unwrap();
+        {
+            let mut sw = StreamWriter::new(&mut mla, id);
+            sw.write_all(&fake_file).unwrap();
+        }
+        // The file is already ended
+        assert!(mla.end_file(id).is_err());
 
         mla.finalize().unwrap();
 
@@ -202,5 +2325,21 @@ mod tests {
             .read_to_end(&mut content2)
             .unwrap();
         assert_eq!(content2.as_slice(), fake_file.as_slice());
+        let mut content3 = Vec::new();
+        mla_read
+            .get_file("my_file3")
+            .unwrap()
+            .unwrap()
+            .data
+            .read_to_end(&mut content3)
+            .unwrap();
+        assert_eq!(content3.as_slice(), fake_file.as_slice());
+        // The file has been properly ended, with its hash
+        let mut hash = Sha256::new();
+        hash.update(&fake_file);
+        assert_eq!(
+            mla_read.get_hash("my_file3").unwrap().unwrap(),
+            hash.finalize().as_slice()
+        );
     }
 }
@@ -0,0 +1,186 @@
+//! X25519 keys import and export as JSON Web Keys (JWK, RFC 7517), with the
+//! "OKP" key type of RFC 8037:
+//! `{"kty":"OKP","crv":"X25519","x":"<public key>","d":"<private key>"}`,
+//! `x` and `d` being base64url encoded, without padding
+use crate::errors::Error;
+use serde::{Deserialize, Serialize};
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::{Zeroize, Zeroizing};
+
+const KEY_TYPE: &str = "OKP";
+const CURVE: &str = "X25519";
+const KEY_SIZE: usize = 32;
+
+#[derive(Serialize, Deserialize)]
+struct Jwk {
+    kty: String,
+    crv: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    x: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    d: Option<String>,
+}
+
+impl Drop for Jwk {
+    fn drop(&mut self) {
+        if let Some(d) = &mut self.d {
+            d.zeroize();
+        }
+    }
+}
+
+/// Parse `jwk`, checking it is an X25519 key
+fn parse(jwk: &str) -> Result<Jwk, Error> {
+    let jwk: Jwk = serde_json::from_str(jwk).or(Err(Error::InvalidECCKeyFormat))?;
+    if jwk.kty != KEY_TYPE || jwk.crv != CURVE {
+        return Err(Error::InvalidECCKeyFormat);
+    }
+    Ok(jwk)
+}
+
+/// Decode a base64url key field
+fn decode(field: &str) -> Result<Zeroizing<[u8; KEY_SIZE]>, Error> {
+    let decoded = Zeroizing::new(
+        base64::decode_config(field, base64::URL_SAFE_NO_PAD)
+            .or(Err(Error::InvalidECCKeyFormat))?,
+    );
+    if decoded.len() != KEY_SIZE {
+        return Err(Error::InvalidECCKeyFormat);
+    }
+    let mut key = Zeroizing::new([0u8; KEY_SIZE]);
+    key.copy_from_slice(&decoded);
+    Ok(key)
+}
+
+fn encode(key: &[u8]) -> String {
+    base64::encode_config(key, base64::URL_SAFE_NO_PAD)
+}
+
+/// Import the private key of `jwk`
+///
+/// If the public key `x` is present, it must be the one of the private key
+pub fn x25519_key_from_jwk(jwk: &str) -> Result<StaticSecret, Error> {
+    let jwk = parse(jwk)?;
+    let d = jwk.d.as_ref().ok_or(Error::InvalidECCKeyFormat)?;
+    let key = StaticSecret::from(*decode(d)?);
+    if let Some(x) = &jwk.x {
+        if PublicKey::from(&key).as_bytes() != &*decode(x)? {
+            return Err(Error::InvalidECCKeyFormat);
+        }
+    }
+    Ok(key)
+}
+
+/// Import the public key of `jwk`
+pub fn x25519_public_key_from_jwk(jwk: &str) -> Result<PublicKey, Error> {
+    let jwk = parse(jwk)?;
+    let x = jwk.x.as_ref().ok_or(Error::InvalidECCKeyFormat)?;
+    Ok(PublicKey::from(*decode(x)?))
+}
+
+/// Export `key` as a public JWK
+pub fn x25519_public_key_to_jwk(key: &PublicKey) -> String {
+    let jwk = Jwk {
+        kty: KEY_TYPE.to_string(),
+        crv: CURVE.to_string(),
+        x: Some(encode(key.as_bytes())),
+        d: None,
+    };
+    // Serializing strings can't fail
+    serde_json::to_string(&jwk).unwrap()
+}
+
+/// Export `key` as a private JWK, including its public key
+///
+/// The returned JWK contains the private key: it must be kept secret
+pub fn x25519_key_to_jwk(key: &StaticSecret) -> Zeroizing<String> {
+    let jwk = Jwk {
+        kty: KEY_TYPE.to_string(),
+        crv: CURVE.to_string(),
+        x: Some(encode(PublicKey::from(key).as_bytes())),
+        d: Some(encode(&Zeroizing::new(key.to_bytes())[..])),
+    };
+    // Serializing strings can't fail
+    Zeroizing::new(serde_json::to_string(&jwk).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+    use crate::{ArchiveReader, ArchiveWriter, Layers};
+    use std::io::{Cursor, Read};
+
+    // X25519 key from RFC 8037, section A.6
+    const RFC_PRIVATE: &str = r#"{"kty":"OKP","crv":"X25519","d":"dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo","x":"hSDwCYkwp1R0i33ctD73Wg2_Og0mOBr066SpjqqbTmo"}"#;
+
+    #[test]
+    fn jwk_roundtrip() {
+        let key = x25519_key_from_jwk(RFC_PRIVATE).unwrap();
+        let public = x25519_public_key_from_jwk(RFC_PRIVATE).unwrap();
+        assert_eq!(PublicKey::from(&key).as_bytes(), public.as_bytes());
+        assert_eq!(
+            x25519_public_key_to_jwk(&public),
+            r#"{"kty":"OKP","crv":"X25519","x":"hSDwCYkwp1R0i33ctD73Wg2_Og0mOBr066SpjqqbTmo"}"#
+        );
+
+        let exported = x25519_key_to_jwk(&key);
+        assert_eq!(
+            x25519_key_from_jwk(&exported).unwrap().to_bytes(),
+            key.to_bytes()
+        );
+        let public_jwk = x25519_public_key_to_jwk(&public);
+        assert_eq!(
+            x25519_public_key_from_jwk(&public_jwk).unwrap().as_bytes(),
+            public.as_bytes()
+        );
+        // No private key in a public JWK
+        assert!(x25519_key_from_jwk(&public_jwk).is_err());
+    }
+
+    #[test]
+    fn jwk_invalid() {
+        for jwk in &[
+            "",
+            "{}",
+            // Wrong key type, curve
+            r#"{"kty":"EC","crv":"X25519","x":"hSDwCYkwp1R0i33ctD73Wg2_Og0mOBr066SpjqqbTmo"}"#,
+            r#"{"kty":"OKP","crv":"Ed25519","x":"hSDwCYkwp1R0i33ctD73Wg2_Og0mOBr066SpjqqbTmo"}"#,
+            // Wrong size, encoding
+            r#"{"kty":"OKP","crv":"X25519","x":"hSDwCYkwp1R0i33ctD73Wg2_Og0mOBr066SpjqqbTm"}"#,
+            r#"{"kty":"OKP","crv":"X25519","x":"hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo="}"#,
+        ] {
+            assert!(matches!(
+                x25519_public_key_from_jwk(jwk),
+                Err(Error::InvalidECCKeyFormat)
+            ));
+        }
+        // Public key not matching the private one
+        let mismatch = r#"{"kty":"OKP","crv":"X25519","d":"dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo","x":"de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f"}"#;
+        assert!(x25519_key_from_jwk(mismatch).is_err());
+    }
+
+    #[test]
+    fn jwk_archive() {
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::ENCRYPT)
+            .add_public_key(&x25519_public_key_from_jwk(RFC_PRIVATE).unwrap());
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("my_file", 4, &[1, 2, 3, 4][..]).unwrap();
+        mla.finalize().unwrap();
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&[x25519_key_from_jwk(RFC_PRIVATE).unwrap()]);
+        let mut mla_read = ArchiveReader::from_config(Cursor::new(mla.into_raw()), config).unwrap();
+        let mut content = Vec::new();
+        mla_read
+            .get_file("my_file")
+            .unwrap()
+            .unwrap()
+            .data
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(content, vec![1, 2, 3, 4]);
+    }
+}
@@ -29,31 +29,711 @@ const DEFAULT_COMPRESSION_LEVEL: u32 = 5;
 /// Default value which seems advised by brotli libraries
 const BROTLI_LOG_WINDOW: u32 = 22;
 
-pub struct CompressionConfig {
-    compression_level: u32,
+/// Brotli quality range is 0-11
+const BROTLI_MAX_QUALITY: u32 = 11;
+
+/// Algorithm used to compress each block of the compression layer
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionAlgorithm {
+    /// Blocks are stored as is
+    None,
+    /// Brotli, with a quality in 0-11; bigger values cause denser, but slower
+    /// compression
+    Brotli { quality: u8 },
+    /// Zstandard, with a level in `zstd::compression_level_range()`
+    Zstd { level: i32 },
+    /// LZ4 frame format, favoring speed over ratio
+    Lz4,
 }
 
-impl std::default::Default for CompressionConfig {
+impl std::default::Default for CompressionAlgorithm {
     fn default() -> Self {
-        CompressionConfig {
-            compression_level: DEFAULT_COMPRESSION_LEVEL,
+        CompressionAlgorithm::Brotli {
+            quality: DEFAULT_COMPRESSION_LEVEL as u8,
+        }
+    }
+}
+
+impl CompressionAlgorithm {
+    fn check(&self) -> Result<(), ConfigError> {
+        let in_range = match self {
+            CompressionAlgorithm::Brotli { quality } => (*quality as u32) <= BROTLI_MAX_QUALITY,
+            CompressionAlgorithm::Zstd { level } => zstd::compression_level_range().contains(level),
+            CompressionAlgorithm::None | CompressionAlgorithm::Lz4 => true,
+        };
+        if in_range {
+            Ok(())
+        } else {
+            Err(ConfigError::CompressionLevelOutOfRange)
+        }
+    }
+
+    /// Same algorithm, with the compression level `level`
+    ///
+    /// Return `ConfigError::CompressionLevelOutOfRange` if `level` is not in
+    /// the range of the algorithm, or if it has no level
+    fn with_level(self, level: CompressionLevel) -> Result<Self, ConfigError> {
+        let algorithm = match self {
+            CompressionAlgorithm::Brotli { .. } if level <= BROTLI_MAX_QUALITY => {
+                CompressionAlgorithm::Brotli {
+                    quality: level as u8,
+                }
+            }
+            CompressionAlgorithm::Zstd { .. } if level <= i32::MAX as u32 => {
+                CompressionAlgorithm::Zstd {
+                    level: level as i32,
+                }
+            }
+            _ => return Err(ConfigError::CompressionLevelOutOfRange),
+        };
+        algorithm.check()?;
+        Ok(algorithm)
+    }
+
+    /// Identifier of the algorithm in `FilePrecompressedContent` blocks
+    pub(crate) fn precompressed_code(&self) -> u8 {
+        match self {
+            CompressionAlgorithm::None => 0,
+            CompressionAlgorithm::Brotli { .. } => 1,
+            CompressionAlgorithm::Zstd { .. } => 2,
+            CompressionAlgorithm::Lz4 => 3,
+        }
+    }
+
+    /// Algorithm of a `FilePrecompressedContent` block, from its identifier.
+    /// Levels are not stored, as decompression does not need them
+    pub(crate) fn from_precompressed_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(CompressionAlgorithm::default()),
+            2 => Some(CompressionAlgorithm::Zstd {
+                level: DEFAULT_COMPRESSION_LEVEL as i32,
+            }),
+            3 => Some(CompressionAlgorithm::Lz4),
+            _ => None,
+        }
+    }
+}
+
+/// Compression level of the configured algorithm: brotli quality in 0-11, or
+/// Zstandard level, see `ArchiveWriter::set_file_compression`
+pub type CompressionLevel = u32;
+
+/// Size of the file content sample used to estimate its entropy
+pub(crate) const ENTROPY_SAMPLE_SIZE: usize = 4 * 1024;
+
+/// Way to handle the compression of a file content, see
+/// `ArchiveWriter::start_file_with_hint`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompressionHint {
+    /// Rely on `ArchiveWriterConfig::skip_compression_for_extensions` and
+    /// `ArchiveWriterConfig::entropy_threshold`
+    #[default]
+    Auto,
+    /// Always compress the content
+    Compress,
+    /// Never compress the content
+    Store,
+}
+
+/// Shannon entropy of `data`, in bits per byte (from 0 to 8)
+pub(crate) fn estimate_entropy(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.;
+    }
+    let mut counts = [0u64; 256];
+    for byte in data {
+        counts[*byte as usize] += 1;
+    }
+    let total = data.len() as f64;
+    counts
+        .iter()
+        .filter(|count| **count != 0)
+        .map(|count| {
+            let p = *count as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[derive(Default)]
+pub struct CompressionConfig {
+    algorithm: CompressionAlgorithm,
+    /// Lowercase extensions of files not to compress
+    skip_extensions: Vec<String>,
+    /// Entropy (in bits per byte) above which a file is not compressed
+    entropy_threshold: Option<f64>,
+    /// Zstandard dictionary, shared by every block
+    dictionary: Option<Vec<u8>>,
+    /// Accept contents already compressed by the caller, see
+    /// `ArchiveWriter::append_file_content_precompressed`
+    pub(crate) accept_pre_compressed: bool,
+}
+
+impl CompressionConfig {
+    /// Check the algorithm parameters are in range, and that a dictionary is
+    /// only used with Zstandard
+    pub fn check(&self) -> Result<(), ConfigError> {
+        self.algorithm.check()?;
+        match (&self.dictionary, self.algorithm) {
+            (Some(_), CompressionAlgorithm::Zstd { .. }) | (None, _) => Ok(()),
+            (Some(_), _) => Err(ConfigError::DictionaryRequiresZstd),
+        }
+    }
+
+    /// Resolve `hint` for the file `filename`: `Some(true)` if its content
+    /// must not be compressed, `Some(false)` if it must be, `None` if its
+    /// entropy must be estimated first
+    pub(crate) fn resolve_hint(&self, filename: &str, hint: CompressionHint) -> Option<bool> {
+        match hint {
+            CompressionHint::Compress => Some(false),
+            CompressionHint::Store => Some(true),
+            CompressionHint::Auto => {
+                let skipped = filename.rsplit_once('.').is_some_and(|(_, ext)| {
+                    self.skip_extensions
+                        .iter()
+                        .any(|skipped| skipped.eq_ignore_ascii_case(ext))
+                });
+                if skipped {
+                    Some(true)
+                } else if self.entropy_threshold.is_some() {
+                    None
+                } else {
+                    Some(false)
+                }
+            }
+        }
+    }
+
+    /// Configured algorithm, with the compression level `level`
+    pub(crate) fn algorithm_with_level(
+        &self,
+        level: CompressionLevel,
+    ) -> Result<CompressionAlgorithm, ConfigError> {
+        self.algorithm.with_level(level)
+    }
+
+    /// Whether a content starting with `sample` must not be compressed
+    pub(crate) fn is_bypassed_sample(&self, sample: &[u8]) -> bool {
+        match self.entropy_threshold {
+            Some(threshold) => estimate_entropy(sample) > threshold,
+            None => false,
+        }
+    }
+
+    /// Get the persistent version, to be stored in the header
+    pub fn to_persistent(&self) -> CompressionPersistentConfig {
+        CompressionPersistentConfig {
+            algorithm: self.algorithm,
+            dictionary: self.dictionary.clone(),
+        }
+    }
+}
+
+/// Compression configuration stored in the header, to be reloaded
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(from = "PersistentAlgorithm", into = "PersistentAlgorithm")]
+pub struct CompressionPersistentConfig {
+    /// Algorithm used on writing; levels are informative only
+    algorithm: CompressionAlgorithm,
+    /// Zstandard dictionary, required to decompress
+    dictionary: Option<Vec<u8>>,
+}
+
+/// Serialized form of `CompressionPersistentConfig`
+///
+/// The first variants mirror `CompressionAlgorithm`, so that headers written
+/// before the dictionary support keep the same encoding. New variants must be
+/// added at the end
+#[derive(Serialize, Deserialize)]
+enum PersistentAlgorithm {
+    None,
+    Brotli {
+        quality: u8,
+    },
+    Zstd {
+        level: i32,
+    },
+    Lz4,
+    /// Zstandard, with a dictionary shared by every block
+    ZstdWithDictionary {
+        level: i32,
+        dictionary: Vec<u8>,
+    },
+}
+
+impl From<PersistentAlgorithm> for CompressionPersistentConfig {
+    fn from(persistent: PersistentAlgorithm) -> Self {
+        let (algorithm, dictionary) = match persistent {
+            PersistentAlgorithm::None => (CompressionAlgorithm::None, None),
+            PersistentAlgorithm::Brotli { quality } => {
+                (CompressionAlgorithm::Brotli { quality }, None)
+            }
+            PersistentAlgorithm::Zstd { level } => (CompressionAlgorithm::Zstd { level }, None),
+            PersistentAlgorithm::Lz4 => (CompressionAlgorithm::Lz4, None),
+            PersistentAlgorithm::ZstdWithDictionary { level, dictionary } => {
+                (CompressionAlgorithm::Zstd { level }, Some(dictionary))
+            }
+        };
+        CompressionPersistentConfig {
+            algorithm,
+            dictionary,
         }
     }
 }
 
+impl From<CompressionPersistentConfig> for PersistentAlgorithm {
+    fn from(config: CompressionPersistentConfig) -> Self {
+        match (config.algorithm, config.dictionary) {
+            (CompressionAlgorithm::Zstd { level }, Some(dictionary)) => {
+                PersistentAlgorithm::ZstdWithDictionary { level, dictionary }
+            }
+            // Dictionaries are only used by Zstandard, see `CompressionConfig::check`
+            (CompressionAlgorithm::None, _) => PersistentAlgorithm::None,
+            (CompressionAlgorithm::Brotli { quality }, _) => {
+                PersistentAlgorithm::Brotli { quality }
+            }
+            (CompressionAlgorithm::Zstd { level }, None) => PersistentAlgorithm::Zstd { level },
+            (CompressionAlgorithm::Lz4, _) => PersistentAlgorithm::Lz4,
+        }
+    }
+}
+
+/// Compression configuration used to read an archive
+#[derive(Default, Clone)]
+pub struct CompressionReaderConfig {
+    /// Archives in format v1 and v2 are always compressed with brotli
+    algorithm: CompressionAlgorithm,
+    /// Zstandard dictionary, from the header
+    dictionary: Option<Vec<u8>>,
+}
+
+impl CompressionReaderConfig {
+    pub fn load_persistent(&mut self, config: CompressionPersistentConfig) {
+        self.algorithm = config.algorithm;
+        self.dictionary = config.dictionary;
+    }
+
+    /// Algorithm used to compress the archive to read
+    pub fn algorithm(&self) -> CompressionAlgorithm {
+        self.algorithm
+    }
+}
+
 impl ArchiveWriterConfig {
-    /// Set the compression level
-    /// compression level (0-11); bigger values cause denser, but slower compression
+    /// Set the compression level of the current compression algorithm
+    /// (brotli, by default)
+    ///
+    /// Brotli quality is in 0-11; bigger values cause denser, but slower
+    /// compression. See `CompressionAlgorithm` for the other algorithms
     pub fn with_compression_level(&mut self, compression_level: u32) -> ConfigResult {
-        if compression_level > 11 {
-            Err(ConfigError::CompressionLevelOutOfRange)
-        } else {
-            self.compress.compression_level = compression_level;
-            Ok(self)
+        let algorithm = self.compress.algorithm.with_level(compression_level)?;
+        self.with_compression_algorithm(algorithm)
+    }
+
+    /// Use brotli with quality `level`, from 0 (fastest, least dense) to 11
+    /// (slowest, densest). Default is 5
+    ///
+    /// The range is verified on the writer creation, which fails with
+    /// `ConfigError::CompressionLevelOutOfRange`
+    pub fn compression_quality(&mut self, level: u8) -> &mut ArchiveWriterConfig {
+        self.compress.algorithm = CompressionAlgorithm::Brotli { quality: level };
+        self
+    }
+
+    /// Do not compress the content of files having one of the extensions
+    /// `exts` (case insensitive, without the leading '.'), such as already
+    /// compressed formats ("jpg", "mp4", "zip", etc.)
+    ///
+    /// See `ArchiveWriter::start_file_with_hint` for the granularity
+    pub fn skip_compression_for_extensions(&mut self, exts: &[&str]) -> &mut ArchiveWriterConfig {
+        self.compress.skip_extensions.extend(
+            exts.iter()
+                .map(|ext| ext.trim_start_matches('.').to_lowercase()),
+        );
+        self
+    }
+
+    /// Do not compress the content of files whose first 4KB (of their first
+    /// content block) have an estimated entropy above `threshold`, in bits
+    /// per byte (from 0 to 8). Random or encrypted data are close to 8
+    pub fn entropy_threshold(&mut self, threshold: f64) -> &mut ArchiveWriterConfig {
+        self.compress.entropy_threshold = Some(threshold);
+        self
+    }
+
+    /// Set the algorithm used by the compression layer (default: brotli, with
+    /// quality 5)
+    ///
+    /// The algorithm is stored in the archive header, for the reader to use
+    /// it
+    pub fn with_compression_algorithm(
+        &mut self,
+        algorithm: CompressionAlgorithm,
+    ) -> ConfigResult<'_> {
+        algorithm.check()?;
+        self.compress.algorithm = algorithm;
+        Ok(self)
+    }
+
+    /// Compress every block with the Zstandard dictionary `dictionary`, for
+    /// instance trained on samples of similar files with
+    /// `helpers::train_dictionary_from_samples`
+    ///
+    /// The dictionary is stored in the archive header, for the reader to load
+    /// it. It requires the Zstandard algorithm (see
+    /// `with_compression_algorithm`), which is verified on the writer
+    /// creation
+    pub fn with_zstd_dictionary(&mut self, dictionary: &[u8]) -> &mut ArchiveWriterConfig {
+        self.compress.dictionary = Some(dictionary.to_vec());
+        self
+    }
+
+    /// Accept file contents already compressed by the caller, with
+    /// `ArchiveWriter::append_file_content_precompressed` (disabled by
+    /// default)
+    ///
+    /// Archives using it can't be read by implementations unaware of
+    /// pre-compressed contents
+    pub fn accept_pre_compressed(&mut self, accept: bool) -> &mut ArchiveWriterConfig {
+        self.compress.accept_pre_compressed = accept;
+        self
+    }
+}
+
+// ---------- Codecs ----------
+
+/// Decompress `data`, compressed with `algorithm` by an external compressor,
+/// to at most `max_size` bytes
+///
+/// Unlike the blocks of the compression layer, `data` may be made of several
+/// frames, and does not use the Zstandard dictionary
+pub(crate) fn decompress_precompressed(
+    algorithm: CompressionAlgorithm,
+    data: &[u8],
+    max_size: u64,
+) -> io::Result<Vec<u8>> {
+    let decompressor: Box<dyn Read + '_> = match algorithm {
+        CompressionAlgorithm::None => Box::new(data),
+        CompressionAlgorithm::Brotli { .. } => Box::new(brotli::Decompressor::new(data, 4096)),
+        CompressionAlgorithm::Zstd { .. } => {
+            Box::new(zstd::stream::read::Decoder::with_buffer(data)?)
+        }
+        CompressionAlgorithm::Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(data)),
+    };
+    let mut content = Vec::new();
+    decompressor.take(max_size + 1).read_to_end(&mut content)?;
+    if content.len() as u64 > max_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Pre-compressed content bigger than expected",
+        ));
+    }
+    Ok(content)
+}
+
+/// Decompressor of one compressed block
+enum BlockDecompressor<R: Read> {
+    None(R),
+    Brotli(Box<brotli::Decompressor<R>>),
+    Zstd(zstd::stream::read::Decoder<'static, io::BufReader<R>>),
+    Lz4(lz4_flex::frame::FrameDecoder<R>),
+}
+
+impl<R: Read> BlockDecompressor<R> {
+    /// Decompressor of a block starting at the current position of `inner`
+    ///
+    /// `buffer_size` is the compressed size of the block, if known. Otherwise,
+    /// `inner` is read by small amounts, to stop right after the block end.
+    /// As it comes from the archive, the buffer is capped to the size of an
+    /// uncompressed block
+    ///
+    /// `dictionary` is the Zstandard dictionary, empty if none
+    fn new(
+        algorithm: CompressionAlgorithm,
+        dictionary: &[u8],
+        inner: R,
+        buffer_size: Option<usize>,
+    ) -> io::Result<Self> {
+        Ok(match algorithm {
+            CompressionAlgorithm::None => BlockDecompressor::None(inner),
+            CompressionAlgorithm::Brotli { .. } => {
+                BlockDecompressor::Brotli(Box::new(brotli::Decompressor::new(
+                    inner,
+                    buffer_size.map_or(1, |size| {
+                        std::cmp::min(size, UNCOMPRESSED_DATA_SIZE as usize)
+                    }),
+                )))
+            }
+            CompressionAlgorithm::Zstd { .. } => {
+                let inner = match buffer_size {
+                    Some(_) => {
+                        io::BufReader::with_capacity(zstd::zstd_safe::DCtx::in_size(), inner)
+                    }
+                    None => io::BufReader::with_capacity(1, inner),
+                };
+                BlockDecompressor::Zstd(
+                    zstd::stream::read::Decoder::with_dictionary(inner, dictionary)?.single_frame(),
+                )
+            }
+            // The LZ4 frame decoder never reads past the end of the frame
+            CompressionAlgorithm::Lz4 => {
+                BlockDecompressor::Lz4(lz4_flex::frame::FrameDecoder::new(inner))
+            }
+        })
+    }
+
+    fn into_inner(self) -> R {
+        match self {
+            BlockDecompressor::None(inner) => inner,
+            BlockDecompressor::Brotli(decompressor) => decompressor.into_inner(),
+            BlockDecompressor::Zstd(decompressor) => decompressor.finish().into_inner(),
+            BlockDecompressor::Lz4(decompressor) => decompressor.into_inner(),
+        }
+    }
+
+    /// Consume the end of the block, if any, once its uncompressed content
+    /// has been entirely read
+    fn consume_block_end(&mut self) -> io::Result<()> {
+        match self {
+            // Nothing after the content
+            BlockDecompressor::None(_) => Ok(()),
+            // Due to the brotli implementation, a few bytes might remains,
+            // even if we already obtain the expected number of bytes. Thanks
+            // to their format, the decompressors are able to stop at the end
+            // of the current block
+            _ => io::copy(self, &mut io::sink()).map(|_| ()),
         }
     }
 }
 
+impl<R: Read> Read for BlockDecompressor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            BlockDecompressor::None(inner) => inner.read(buf),
+            BlockDecompressor::Brotli(decompressor) => decompressor.read(buf),
+            BlockDecompressor::Zstd(decompressor) => decompressor.read(buf),
+            BlockDecompressor::Lz4(decompressor) => decompressor.read(buf),
+        }
+    }
+}
+
+/// Compressor of one block
+enum BlockCompressor<W: Write> {
+    None(W),
+    Brotli(Box<brotli::CompressorWriter<W>>),
+    Zstd(zstd::stream::zio::Writer<W, zstd::stream::raw::Encoder<'static>>),
+    Lz4(lz4_flex::frame::FrameEncoder<W>),
+    Stored(StoredBlockWriter<W>),
+}
+
+impl<W: Write> BlockCompressor<W> {
+    /// Compressor of a block; if `bypass` is set, the block is stored as is
+    ///
+    /// `dictionary` is the Zstandard dictionary, empty if none
+    fn new(
+        algorithm: CompressionAlgorithm,
+        dictionary: &[u8],
+        inner: W,
+        bypass: bool,
+    ) -> io::Result<Self> {
+        if bypass && algorithm != CompressionAlgorithm::None {
+            return Ok(BlockCompressor::Stored(StoredBlockWriter::new(
+                algorithm, inner,
+            )));
+        }
+        Ok(match algorithm {
+            CompressionAlgorithm::None => BlockCompressor::None(inner),
+            CompressionAlgorithm::Brotli { quality } => BlockCompressor::Brotli(Box::new(
+                brotli::CompressorWriter::new(inner, 0, quality as u32, BROTLI_LOG_WINDOW),
+            )),
+            CompressionAlgorithm::Zstd { level } => {
+                BlockCompressor::Zstd(zstd::stream::zio::Writer::new(
+                    inner,
+                    zstd::stream::raw::Encoder::with_dictionary(level, dictionary)?,
+                ))
+            }
+            CompressionAlgorithm::Lz4 => {
+                BlockCompressor::Lz4(lz4_flex::frame::FrameEncoder::new(inner))
+            }
+        })
+    }
+
+    /// End the compressed block, and return the inner writer
+    fn finish(self) -> io::Result<W> {
+        match self {
+            BlockCompressor::None(inner) => Ok(inner),
+            BlockCompressor::Brotli(compressor) => Ok(compressor.into_inner()),
+            BlockCompressor::Zstd(mut compressor) => {
+                compressor.finish()?;
+                Ok(compressor.into_inner().0)
+            }
+            BlockCompressor::Lz4(compressor) => Ok(compressor.finish()?),
+            BlockCompressor::Stored(writer) => writer.finish(),
+        }
+    }
+
+    /// Try to end the compressed block, and return the inner writer even on
+    /// error
+    fn into_inner(self) -> W {
+        match self {
+            BlockCompressor::None(inner) => inner,
+            BlockCompressor::Brotli(compressor) => compressor.into_inner(),
+            BlockCompressor::Zstd(mut compressor) => {
+                let _ = compressor.finish();
+                compressor.into_inner().0
+            }
+            BlockCompressor::Lz4(mut compressor) => {
+                let _ = compressor.try_finish();
+                compressor.into_inner()
+            }
+            BlockCompressor::Stored(writer) => writer.inner,
+        }
+    }
+}
+
+impl<W: Write> Write for BlockCompressor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            BlockCompressor::None(inner) => inner.write(buf),
+            BlockCompressor::Brotli(compressor) => compressor.write(buf),
+            BlockCompressor::Zstd(compressor) => compressor.write(buf),
+            BlockCompressor::Lz4(compressor) => compressor.write(buf),
+            BlockCompressor::Stored(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            BlockCompressor::None(inner) => inner.flush(),
+            BlockCompressor::Brotli(compressor) => compressor.flush(),
+            BlockCompressor::Zstd(compressor) => compressor.flush(),
+            BlockCompressor::Lz4(compressor) => compressor.flush(),
+            BlockCompressor::Stored(writer) => writer.inner.flush(),
+        }
+    }
+}
+
+/// Maximum payload of a stored piece: brotli uncompressed meta-block (using 4
+/// nibbles for its length), zstd raw block (at most 128KB) and LZ4
+/// uncompressed block (default maximum block size, 64KB)
+const STORED_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Writer of a block stored as is, but framed using the format of the
+/// compression algorithm, so that decompressors read it transparently:
+/// * brotli: uncompressed meta-blocks (RFC 7932, section 9.2)
+/// * zstd: raw blocks in a single frame (RFC 8878, section 3.1.1.2)
+/// * LZ4: uncompressed blocks in a single frame
+struct StoredBlockWriter<W: Write> {
+    inner: W,
+    algorithm: CompressionAlgorithm,
+    /// Whether the frame header has already been written
+    started: bool,
+}
+
+/// Brotli stream header (RFC 7932, section 9.1), as (bits, bit count), for a
+/// window of `BROTLI_LOG_WINDOW` bits
+const BROTLI_STREAM_HEADER: (u32, u32) = ((((BROTLI_LOG_WINDOW - 17) << 1) | 1), 4);
+
+/// Little-endian zstd magic number, frame header descriptor (no content
+/// size, no checksum, no dictionary) and window descriptor (128KB window)
+const ZSTD_STORED_FRAME_HEADER: [u8; 6] = [0x28, 0xB5, 0x2F, 0xFD, 0x00, 0x38];
+
+impl<W: Write> StoredBlockWriter<W> {
+    fn new(algorithm: CompressionAlgorithm, inner: W) -> Self {
+        Self {
+            inner,
+            algorithm,
+            started: false,
+        }
+    }
+
+    /// Write `count` least significant bits of `bits`, brotli style (LSB
+    /// first), padded with zeroes to a byte boundary
+    fn write_bits(&mut self, bits: u32, count: u32) -> io::Result<()> {
+        let bytes = bits.to_le_bytes();
+        self.inner.write_all(&bytes[..count.div_ceil(8) as usize])
+    }
+
+    /// Write the frame header, if not already done
+    fn start(&mut self) -> io::Result<()> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+        match self.algorithm {
+            // Brotli stream header is merged with the first meta-block one
+            CompressionAlgorithm::None | CompressionAlgorithm::Brotli { .. } => Ok(()),
+            CompressionAlgorithm::Zstd { .. } => self.inner.write_all(&ZSTD_STORED_FRAME_HEADER),
+            CompressionAlgorithm::Lz4 => {
+                // Reuse the header produced by the encoder, without its
+                // EndMark
+                let frame = lz4_flex::frame::FrameEncoder::new(Vec::new()).finish()?;
+                self.inner.write_all(&frame[..frame.len() - 4])
+            }
+        }
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        let first = !self.started;
+        self.start()?;
+        match self.algorithm {
+            CompressionAlgorithm::None => {}
+            CompressionAlgorithm::Brotli { .. } => {
+                // ISLAST = 1, ISLASTEMPTY = 1
+                let (mut bits, mut count) = (0b11, 2);
+                if first {
+                    bits = (bits << BROTLI_STREAM_HEADER.1) | BROTLI_STREAM_HEADER.0;
+                    count += BROTLI_STREAM_HEADER.1;
+                }
+                self.write_bits(bits, count)?;
+            }
+            // Last block: empty raw block
+            CompressionAlgorithm::Zstd { .. } => self.inner.write_all(&[0x01, 0x00, 0x00])?,
+            // EndMark
+            CompressionAlgorithm::Lz4 => self.inner.write_all(&[0, 0, 0, 0])?,
+        }
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for StoredBlockWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let first = !self.started;
+        self.start()?;
+        let buf = &buf[..std::cmp::min(buf.len(), STORED_CHUNK_SIZE)];
+        let len = buf.len() as u32;
+        match self.algorithm {
+            CompressionAlgorithm::None => {}
+            CompressionAlgorithm::Brotli { .. } => {
+                // ISLAST = 0, MNIBBLES = 4 (0b00), MLEN - 1, ISUNCOMPRESSED = 1
+                let (mut bits, mut count) = ((1 << 19) | ((len - 1) << 3), 20);
+                if first {
+                    bits = (bits << BROTLI_STREAM_HEADER.1) | BROTLI_STREAM_HEADER.0;
+                    count += BROTLI_STREAM_HEADER.1;
+                }
+                self.write_bits(bits, count)?;
+            }
+            CompressionAlgorithm::Zstd { .. } => {
+                // Last_Block = 0, Block_Type = Raw (0), Block_Size
+                self.inner.write_all(&(len << 3).to_le_bytes()[..3])?;
+            }
+            CompressionAlgorithm::Lz4 => {
+                // High bit set for uncompressed blocks
+                self.inner.write_all(&(len | (1 << 31)).to_le_bytes())?;
+            }
+        }
+        self.inner.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 // ---------- Reader ----------
 
 /// See `CompressionLayerWriter` for more information
@@ -65,7 +745,7 @@ enum CompressionLayerReaderState<R: Read> {
     InData {
         read: u32,
         uncompressed_size: u32,
-        decompressor: brotli::Decompressor<R>,
+        decompressor: BlockDecompressor<R>,
     },
     /// Empty is a placeholder to allow state replacement
     Empty,
@@ -80,6 +760,13 @@ struct SizesInfo {
 }
 
 impl SizesInfo {
+    /// Whether the sizes are consistent, as they are read from the archive:
+    /// there is at least one block, and the last one is not bigger than the
+    /// others
+    fn is_valid(&self) -> bool {
+        !self.compressed_sizes.is_empty() && self.last_block_size <= UNCOMPRESSED_DATA_SIZE
+    }
+
     /// Get the uncompressed block size of block `block_num`
     fn uncompressed_block_size_at(&self, block_num: usize) -> u32 {
         if block_num < self.compressed_sizes.len() - 1 {
@@ -105,6 +792,10 @@ impl SizesInfo {
 pub struct CompressionLayerReader<'a, R: 'a + Read> {
     state: CompressionLayerReaderState<Box<dyn 'a + LayerReader<'a, R>>>,
     sizes_info: Option<SizesInfo>,
+    // From config
+    algorithm: CompressionAlgorithm,
+    /// Zstandard dictionary, empty if none
+    dictionary: Vec<u8>,
     /// Position in the under-layer (uncompressed stream)
     // /!\ Due to the decompressor having a block size of the compressed size,
     // any read on it may forward the inner layer to the beginning of the next
@@ -140,11 +831,16 @@ impl<R: Read> CompressionLayerReaderState<R> {
 }
 
 impl<'a, R: 'a + Read> CompressionLayerReader<'a, R> {
-    pub fn new(mut inner: Box<dyn 'a + LayerReader<'a, R>>) -> Result<Self, Error> {
+    pub fn new(
+        mut inner: Box<dyn 'a + LayerReader<'a, R>>,
+        config: &CompressionReaderConfig,
+    ) -> Result<Self, Error> {
         let underlayer_pos = inner.seek(SeekFrom::Current(0))? as u64;
         Ok(Self {
             state: CompressionLayerReaderState::Ready(inner),
             sizes_info: None,
+            algorithm: config.algorithm,
+            dictionary: config.dictionary.clone().unwrap_or_default(),
             underlayer_pos,
         })
     }
@@ -167,7 +863,7 @@ impl<'a, R: 'a + Read> CompressionLayerReader<'a, R> {
         &self,
         inner: S,
         uncompressed_pos: u64,
-    ) -> Result<brotli::Decompressor<S>, Error> {
+    ) -> Result<BlockDecompressor<S>, Error> {
         // Ensure it's a starting position
         if uncompressed_pos % (UNCOMPRESSED_DATA_SIZE as u64) != 0 {
             return Err(Error::BadAPIArgument(
@@ -184,10 +880,12 @@ impl<'a, R: 'a + Read> CompressionLayerReader<'a, R> {
         match &self.sizes_info {
             Some(sizes_info) => {
                 // Use index for faster decompression
-                Ok(brotli::Decompressor::new(
+                Ok(BlockDecompressor::new(
+                    self.algorithm,
+                    &self.dictionary,
                     inner,
-                    sizes_info.compressed_block_size_at(uncompressed_pos) as usize,
-                ))
+                    Some(sizes_info.compressed_block_size_at(uncompressed_pos) as usize),
+                )?)
             }
             None => Err(Error::MissingMetadata),
         }
@@ -249,9 +947,14 @@ impl<'a, R: 'a + Read> CompressionLayerReader<'a, R> {
             Some(SizesInfo {
                 compressed_sizes, ..
             }) => {
-                // Move the underlayer at the start of the block
-                let start_position: u32 = compressed_sizes.iter().take(block_num as usize).sum();
-                inner.seek(SeekFrom::Start(start_position as u64))?;
+                // Move the underlayer at the start of the block. Summed as
+                // u64, as the compressed stream can be bigger than 4GB
+                let start_position: u64 = compressed_sizes
+                    .iter()
+                    .take(block_num as usize)
+                    .map(|size| u64::from(*size))
+                    .sum();
+                inner.seek(SeekFrom::Start(start_position))?;
             }
             None => {
                 return Err(Error::MissingMetadata);
@@ -281,12 +984,13 @@ impl<'a, R: 'a + Read + Seek> LayerReader<'a, R> for CompressionLayerReader<'a,
                 let len = inner.read_u32::<LittleEndian>()? as u64;
 
                 // Read SizesInfo
-                inner.seek(SeekFrom::Start(pos - len))?;
+                let start = pos.checked_sub(len).ok_or(Error::DeserializationError)?;
+                inner.seek(SeekFrom::Start(start))?;
                 self.sizes_info = match bincode::config()
                     .limit(BINCODE_MAX_DESERIALIZE)
                     .deserialize_from(inner.take(len))
                 {
-                    Ok(sinfo) => Some(sinfo),
+                    Ok(sinfo) if SizesInfo::is_valid(&sinfo) => Some(sinfo),
                     _ => {
                         return Err(Error::DeserializationError);
                     }
@@ -316,7 +1020,13 @@ impl<'a, R: 'a + Read + Seek> Read for CompressionLayerReader<'a, R> {
         let old_state = std::mem::replace(&mut self.state, CompressionLayerReaderState::Empty);
         match old_state {
             CompressionLayerReaderState::Ready(mut inner) => {
-                self.sync_inner_with_uncompressed_pos(&mut inner, self.underlayer_pos)?;
+                if let Err(err) =
+                    self.sync_inner_with_uncompressed_pos(&mut inner, self.underlayer_pos)
+                {
+                    // Keep the inner layer, to be able to recover from this error
+                    self.state = CompressionLayerReaderState::Ready(inner);
+                    return Err(err.into());
+                }
                 let decompressor = self.new_decompressor_at(inner, self.underlayer_pos)?;
                 let uncompressed_size = self.uncompressed_block_size_at(self.underlayer_pos)?;
                 self.state = CompressionLayerReaderState::InData {
@@ -343,7 +1053,15 @@ impl<'a, R: 'a + Read + Seek> Read for CompressionLayerReader<'a, R> {
                     return self.read(buf);
                 }
                 let size = std::cmp::min((uncompressed_size - read) as usize, buf.len());
-                let read_add = decompressor.read(&mut buf[..size])?;
+                let read_add = match decompressor.read(&mut buf[..size]) {
+                    Ok(read_add) => read_add,
+                    Err(err) => {
+                        // The decompressor state is now unknown. Keep the
+                        // inner layer, to be able to recover with a seek
+                        self.state = CompressionLayerReaderState::Ready(decompressor.into_inner());
+                        return Err(err);
+                    }
+                };
                 self.underlayer_pos += read_add as u64;
                 self.state = CompressionLayerReaderState::InData {
                     read: read + read_add as u32,
@@ -378,14 +1096,26 @@ impl<'a, R: Read + Seek> Seek for CompressionLayerReader<'a, R> {
                         let old_state =
                             std::mem::replace(&mut self.state, CompressionLayerReaderState::Empty);
                         let mut inner = old_state.into_inner();
-                        self.sync_inner_with_uncompressed_pos(&mut inner, rounded_pos)?;
+                        if let Err(err) =
+                            self.sync_inner_with_uncompressed_pos(&mut inner, rounded_pos)
+                        {
+                            // Keep the inner layer, to be able to recover from this error
+                            self.state = CompressionLayerReaderState::Ready(inner);
+                            return Err(err.into());
+                        }
 
                         // New decompressor at the start of the block
                         let mut decompressor = self.new_decompressor_at(inner, rounded_pos)?;
                         let uncompressed_size = self.uncompressed_block_size_at(rounded_pos)?;
 
                         // Move forward inside the block to reach the expected position
-                        io::copy(&mut (&mut decompressor).take(inside_block), &mut io::sink())?;
+                        if let Err(err) =
+                            io::copy(&mut (&mut decompressor).take(inside_block), &mut io::sink())
+                        {
+                            self.state =
+                                CompressionLayerReaderState::Ready(decompressor.into_inner());
+                            return Err(err);
+                        }
                         self.state = CompressionLayerReaderState::InData {
                             read: inside_block as u32,
                             uncompressed_size,
@@ -414,7 +1144,11 @@ impl<'a, R: Read + Seek> Seek for CompressionLayerReader<'a, R> {
 
                         let end_pos = (&self.sizes_info).as_ref().unwrap().max_uncompressed_pos();
                         let distance_from_end = -pos;
-                        self.seek(SeekFrom::Start(end_pos - distance_from_end as u64))
+                        match end_pos.checked_sub(distance_from_end as u64) {
+                            Some(pos) => self.seek(SeekFrom::Start(pos)),
+                            // Seeking before the start is invalid
+                            None => Err(Error::EndOfStream.into()),
+                        }
                     }
                 }
             }
@@ -459,7 +1193,7 @@ enum CompressionLayerWriterState<W: Write> {
     Ready(W),
     /// How many uncompressed bytes have already been written for the current
     /// block
-    InData(u32, brotli::CompressorWriter<WriterWithCount<W>>),
+    InData(u32, BlockCompressor<WriterWithCount<W>>),
     /// Empty is a placeholder to allow state replacement
     Empty,
 }
@@ -488,7 +1222,14 @@ pub struct CompressionLayerWriter<'a, W: 'a + Write> {
     // / C])`, with `C = UNCOMPRESSED_DATA_SIZE`
     compressed_sizes: Vec<u32>,
     // From config
-    compression_level: u32,
+    algorithm: CompressionAlgorithm,
+    /// Zstandard dictionary, empty if none
+    dictionary: Vec<u8>,
+    /// If set, the next block started is stored as is
+    bypass: bool,
+    /// If set, the next block started is compressed with this algorithm, a
+    /// level of `algorithm`, instead
+    level_override: Option<CompressionAlgorithm>,
 }
 
 impl<W: Write> CompressionLayerWriterState<W> {
@@ -506,6 +1247,19 @@ impl<W: Write> CompressionLayerWriterState<W> {
     }
 }
 
+/// Upper bound of the framing a compressor may add to a block: brotli, zstd
+/// and LZ4 grow incompressible data by less than 1/256th of its size and a
+/// few bytes of headers
+const MAX_BLOCK_OVERHEAD: u64 = 256;
+
+/// Upper bound of the output size of a `CompressionLayerWriter` fed with
+/// `size` bytes, if they are not compressible at all
+pub(crate) fn max_compressed_size(size: u64) -> u64 {
+    let blocks = std::cmp::max(1, size.div_ceil(u64::from(UNCOMPRESSED_DATA_SIZE)));
+    // Blocks, then the footer (bincode `SizesInfo` and its length)
+    size + size / 256 + blocks * (MAX_BLOCK_OVERHEAD + 1) + (8 + 4 * blocks + 4) + 4
+}
+
 impl<'a, W: 'a + Write> CompressionLayerWriter<'a, W> {
     pub fn new(
         inner: Box<dyn 'a + LayerWriter<'a, W>>,
@@ -514,7 +1268,10 @@ impl<'a, W: 'a + Write> CompressionLayerWriter<'a, W> {
         Self {
             state: CompressionLayerWriterState::Ready(inner),
             compressed_sizes: Vec::new(),
-            compression_level: config.compression_level,
+            algorithm: config.algorithm,
+            dictionary: config.dictionary.clone().unwrap_or_default(),
+            bypass: false,
+            level_override: None,
         }
     }
 }
@@ -528,6 +1285,18 @@ impl<'a, W: 'a + Write> LayerWriter<'a, W> for CompressionLayerWriter<'a, W> {
         self.state.into_inner().into_raw()
     }
 
+    /// As blocks have a fixed uncompressed size, the current block is kept in
+    /// its mode; only the blocks started while `bypass` is set are stored
+    fn set_compression_bypass(&mut self, bypass: bool) {
+        self.bypass = bypass;
+    }
+
+    /// As for `set_compression_bypass`, only the blocks started while the
+    /// level is overridden use it
+    fn set_compression_level(&mut self, algorithm: Option<CompressionAlgorithm>) {
+        self.level_override = algorithm;
+    }
+
     fn finalize(&mut self) -> Result<(), Error> {
         // Use this mem::replace trick to be able to get back the compressor
         // inner and freely move from CompressionLayerWriterState to others
@@ -536,7 +1305,7 @@ impl<'a, W: 'a + Write> LayerWriter<'a, W> for CompressionLayerWriter<'a, W> {
         let mut inner = match old_state {
             CompressionLayerWriterState::Ready(inner) => inner,
             CompressionLayerWriterState::InData(written, compress) => {
-                let inner_count = compress.into_inner();
+                let inner_count = compress.finish()?;
                 self.compressed_sizes.push(inner_count.pos);
                 last_block_size = written;
                 inner_count.into_inner()
@@ -592,12 +1361,12 @@ impl<'a, W: 'a + Write> Write for CompressionLayerWriter<'a, W> {
         match old_state {
             CompressionLayerWriterState::Ready(inner) => {
                 let inner_count = WriterWithCount::new(inner);
-                let mut compress = brotli::CompressorWriter::new(
+                let mut compress = BlockCompressor::new(
+                    self.level_override.unwrap_or(self.algorithm),
+                    &self.dictionary,
                     inner_count,
-                    0,
-                    self.compression_level,
-                    BROTLI_LOG_WINDOW,
-                );
+                    self.bypass,
+                )?;
                 let size = std::cmp::min(UNCOMPRESSED_DATA_SIZE as usize, buf.len());
                 let written = compress.write(&buf[..size])?;
                 self.state = CompressionLayerWriterState::InData(written as u32, compress);
@@ -610,7 +1379,7 @@ impl<'a, W: 'a + Write> Write for CompressionLayerWriter<'a, W> {
                     ).into());
                 }
                 if written == UNCOMPRESSED_DATA_SIZE {
-                    let inner_count = compress.into_inner();
+                    let inner_count = compress.finish()?;
                     self.compressed_sizes.push(inner_count.pos);
                     self.state = CompressionLayerWriterState::Ready(inner_count.into_inner());
                     // Start a new block, fill it with new values!
@@ -644,12 +1413,21 @@ impl<'a, W: 'a + Write> Write for CompressionLayerWriter<'a, W> {
 
 pub struct CompressionLayerFailSafeReader<'a, R: 'a + Read> {
     state: CompressionLayerReaderState<Box<dyn 'a + LayerFailSafeReader<'a, R>>>,
+    // From config
+    algorithm: CompressionAlgorithm,
+    /// Zstandard dictionary, empty if none
+    dictionary: Vec<u8>,
 }
 
 impl<'a, R: 'a + Read> CompressionLayerFailSafeReader<'a, R> {
-    pub fn new(inner: Box<dyn 'a + LayerFailSafeReader<'a, R>>) -> Result<Self, Error> {
+    pub fn new(
+        inner: Box<dyn 'a + LayerFailSafeReader<'a, R>>,
+        config: &CompressionReaderConfig,
+    ) -> Result<Self, Error> {
         Ok(Self {
             state: CompressionLayerReaderState::Ready(inner),
+            algorithm: config.algorithm,
+            dictionary: config.dictionary.clone().unwrap_or_default(),
         })
     }
 }
@@ -678,11 +1456,12 @@ impl<'a, R: 'a + Read> Read for CompressionLayerFailSafeReader<'a, R> {
             CompressionLayerReaderState::Ready(inner) => {
                 // Default values, for "repair" mode
 
-                // Use a block size of `1` to ensure the decompression
-                // will stop on the first byte of the next CompressionBlock.
-                // This is slower, but we don't have index, and
-                // therefore we don't know the compressed block size
-                let decompressor = brotli::Decompressor::new(inner, 1);
+                // Read the inner layer by small amounts to ensure the
+                // decompression will stop on the first byte of the next
+                // CompressionBlock. This is slower, but we don't have index,
+                // and therefore we don't know the compressed block size
+                let decompressor =
+                    BlockDecompressor::new(self.algorithm, &self.dictionary, inner, None)?;
                 self.state = CompressionLayerReaderState::InData {
                     read: 0,
                     // Default values, for "repair" mode
@@ -703,18 +1482,22 @@ impl<'a, R: 'a + Read> Read for CompressionLayerFailSafeReader<'a, R> {
                     .into());
                 }
                 if read == uncompressed_size {
-                    // Consume the rest of the current decompressor. Due to the
-                    // brotli implementation, a few bytes might remains, even if
-                    // we already obtain the expected number of bytes. Thanks to
-                    // the brotli format, the decompressor is able to stop at
-                    // the end of the current block.
-                    io::copy(&mut decompressor, &mut io::sink())?;
+                    // Consume the rest of the current decompressor
+                    decompressor.consume_block_end()?;
                     // Start a new block, fill it with new values
                     self.state = CompressionLayerReaderState::Ready(decompressor.into_inner());
                     return self.read(buf);
                 }
                 let size = std::cmp::min((uncompressed_size - read) as usize, buf.len());
-                let read_add = decompressor.read(&mut buf[..size])?;
+                let read_add = match decompressor.read(&mut buf[..size]) {
+                    Ok(read_add) => read_add,
+                    Err(err) => {
+                        // The decompressor state is now unknown. Keep the
+                        // inner layer, to be able to recover with a seek
+                        self.state = CompressionLayerReaderState::Ready(decompressor.into_inner());
+                        return Err(err);
+                    }
+                };
                 self.state = CompressionLayerReaderState::InData {
                     read: read + read_add as u32,
                     uncompressed_size,
@@ -739,7 +1522,7 @@ mod tests {
     use crate::layers::raw::{RawLayerFailSafeReader, RawLayerReader, RawLayerWriter};
     use rand::distributions::{Alphanumeric, Distribution, Standard};
     use rand::rngs::StdRng;
-    use rand::SeedableRng;
+    use rand::{RngCore, SeedableRng};
     use std::io::{Cursor, Read, Write};
     use std::time::Instant;
 
@@ -871,8 +1654,13 @@ mod tests {
             comp.finalize().unwrap();
             let file = comp.into_raw();
             let buf = Cursor::new(file.as_slice());
-            let mut decomp =
-                Box::new(CompressionLayerReader::new(Box::new(RawLayerReader::new(buf))).unwrap());
+            let mut decomp = Box::new(
+                CompressionLayerReader::new(
+                    Box::new(RawLayerReader::new(buf)),
+                    &Default::default(),
+                )
+                .unwrap(),
+            );
             decomp.initialize().unwrap();
             let mut buf = Vec::new();
             decomp.read_to_end(&mut buf).unwrap();
@@ -904,9 +1692,10 @@ mod tests {
             comp.finalize().unwrap();
             let file = comp.into_raw();
             let mut decomp = Box::new(
-                CompressionLayerFailSafeReader::new(Box::new(RawLayerFailSafeReader::new(
-                    file.as_slice(),
-                )))
+                CompressionLayerFailSafeReader::new(
+                    Box::new(RawLayerFailSafeReader::new(file.as_slice())),
+                    &Default::default(),
+                )
                 .unwrap(),
             );
             let mut buf = Vec::new();
@@ -944,9 +1733,10 @@ mod tests {
             let stop = file.len() / 2;
 
             let mut decomp = Box::new(
-                CompressionLayerFailSafeReader::new(Box::new(RawLayerFailSafeReader::new(
-                    &file[..stop],
-                )))
+                CompressionLayerFailSafeReader::new(
+                    Box::new(RawLayerFailSafeReader::new(&file[..stop])),
+                    &Default::default(),
+                )
                 .unwrap(),
             );
             let mut buf = Vec::new();
@@ -987,8 +1777,10 @@ mod tests {
 
         let file = comp.into_raw();
         let buf = Cursor::new(file.as_slice());
-        let mut decomp =
-            Box::new(CompressionLayerReader::new(Box::new(RawLayerReader::new(buf))).unwrap());
+        let mut decomp = Box::new(
+            CompressionLayerReader::new(Box::new(RawLayerReader::new(buf)), &Default::default())
+                .unwrap(),
+        );
         decomp.initialize().unwrap();
 
         // Check the footer has been correctly re-read
@@ -1013,8 +1805,13 @@ mod tests {
 
             let file = comp.into_raw();
             let buf = Cursor::new(file.as_slice());
-            let mut decomp =
-                Box::new(CompressionLayerReader::new(Box::new(RawLayerReader::new(buf))).unwrap());
+            let mut decomp = Box::new(
+                CompressionLayerReader::new(
+                    Box::new(RawLayerReader::new(buf)),
+                    &Default::default(),
+                )
+                .unwrap(),
+            );
             decomp.initialize().unwrap();
 
             // Seek in the first block
@@ -1028,14 +1825,14 @@ mod tests {
             let pos = decomp
                 .seek(SeekFrom::Start((UNCOMPRESSED_DATA_SIZE + 4).into()))
                 .unwrap();
-            assert_eq!(pos, (UNCOMPRESSED_DATA_SIZE + 4).into());
+            assert_eq!(pos, u64::from(UNCOMPRESSED_DATA_SIZE + 4));
             let mut buf = [0u8; 5];
             decomp.read_exact(&mut buf).unwrap();
             assert_eq!(&buf, &bytes[pos as usize..(pos + 5) as usize]);
 
             // Seek relatively (same block)
             let pos = decomp.seek(SeekFrom::Current(2)).unwrap();
-            assert_eq!(pos, (UNCOMPRESSED_DATA_SIZE + 4 + 5 + 2).into());
+            assert_eq!(pos, u64::from(UNCOMPRESSED_DATA_SIZE + 4 + 5 + 2));
             let mut buf = [0u8; 5];
             decomp.read_exact(&mut buf).unwrap();
             assert_eq!(&buf, &bytes[pos as usize..(pos + 5) as usize]);
@@ -1044,14 +1841,14 @@ mod tests {
             let pos = decomp
                 .seek(SeekFrom::Current(UNCOMPRESSED_DATA_SIZE.into()))
                 .unwrap();
-            assert_eq!(pos, (UNCOMPRESSED_DATA_SIZE * 2 + 4 + 5 + 2 + 5).into());
+            assert_eq!(pos, u64::from(UNCOMPRESSED_DATA_SIZE * 2 + 4 + 5 + 2 + 5));
             let mut buf = [0u8; 5];
             decomp.read_exact(&mut buf).unwrap();
             assert_eq!(&buf, &bytes[pos as usize..(pos + 5) as usize]);
 
             // Seek relatively (backward)
             let pos = decomp.seek(SeekFrom::Current(-5)).unwrap();
-            assert_eq!(pos, (UNCOMPRESSED_DATA_SIZE * 2 + 4 + 5 + 2 + 5).into());
+            assert_eq!(pos, u64::from(UNCOMPRESSED_DATA_SIZE * 2 + 4 + 5 + 2 + 5));
             let mut buf = [0u8; 5];
             decomp.read_exact(&mut buf).unwrap();
             assert_eq!(&buf, &bytes[pos as usize..(pos + 5) as usize]);
@@ -1065,6 +1862,309 @@ mod tests {
         }
     }
 
+    /// Algorithms, with non default levels
+    const ALGORITHMS: [CompressionAlgorithm; 4] = [
+        CompressionAlgorithm::None,
+        CompressionAlgorithm::Brotli { quality: 2 },
+        CompressionAlgorithm::Zstd { level: 3 },
+        CompressionAlgorithm::Lz4,
+    ];
+
+    #[test]
+    fn compress_algorithms() {
+        for data in [get_data(), get_uncompressable_data()] {
+            let bytes = data.as_slice();
+            for algorithm in ALGORITHMS.iter() {
+                let mut config = ArchiveWriterConfig::new();
+                config.with_compression_algorithm(*algorithm).unwrap();
+                let mut comp = Box::new(CompressionLayerWriter::new(
+                    Box::new(RawLayerWriter::new(Vec::new())),
+                    &config.compress,
+                ));
+                comp.write_all(bytes).unwrap();
+                comp.finalize().unwrap();
+                let file = comp.into_raw();
+                let reader_config = CompressionReaderConfig {
+                    algorithm: *algorithm,
+                    dictionary: None,
+                };
+
+                // Linear read
+                let mut decomp = Box::new(
+                    CompressionLayerReader::new(
+                        Box::new(RawLayerReader::new(Cursor::new(file.as_slice()))),
+                        &reader_config,
+                    )
+                    .unwrap(),
+                );
+                decomp.initialize().unwrap();
+                let mut buf = Vec::new();
+                decomp.read_to_end(&mut buf).unwrap();
+                assert_eq!(buf.as_slice(), bytes, "{:?}", algorithm);
+
+                // Seek in the second block
+                let pos = UNCOMPRESSED_DATA_SIZE as u64 + 42;
+                decomp.seek(SeekFrom::Start(pos)).unwrap();
+                let mut buf = [0u8; 5];
+                decomp.read_exact(&mut buf).unwrap();
+                assert_eq!(&buf, &bytes[pos as usize..(pos + 5) as usize]);
+
+                // Fail-safe read, without the index
+                let mut decomp = Box::new(
+                    CompressionLayerFailSafeReader::new(
+                        Box::new(RawLayerFailSafeReader::new(file.as_slice())),
+                        &reader_config,
+                    )
+                    .unwrap(),
+                );
+                let mut buf = Vec::new();
+                let _ = decomp.read_to_end(&mut buf);
+                // Stored blocks can't be distinguished from the footer
+                assert_eq!(&buf[..bytes.len()], bytes, "{:?}", algorithm);
+            }
+        }
+    }
+
+    #[test]
+    fn stored_block() {
+        let data = get_data();
+        for algorithm in ALGORITHMS.iter() {
+            for size in [0, 1, STORED_CHUNK_SIZE, 3 * STORED_CHUNK_SIZE + 1].iter() {
+                let mut writer = StoredBlockWriter::new(*algorithm, Vec::new());
+                writer.write_all(&data[..*size]).unwrap();
+                let mut block = writer.finish().unwrap();
+                // Followed by another block
+                block.extend_from_slice(b"next");
+
+                for buffer_size in [None, Some(block.len() - 4)].iter() {
+                    let mut src = block.as_slice();
+                    let mut decompressor =
+                        BlockDecompressor::new(*algorithm, &[], &mut src, *buffer_size).unwrap();
+                    let mut buf = vec![0; *size];
+                    decompressor.read_exact(&mut buf).unwrap();
+                    assert_eq!(buf.as_slice(), &data[..*size], "{:?}", algorithm);
+                    if *algorithm != CompressionAlgorithm::None {
+                        decompressor.consume_block_end().unwrap();
+                        if buffer_size.is_none() {
+                            // Stop right after the block end
+                            drop(decompressor);
+                            assert_eq!(src, b"next");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn compress_bypass() {
+        let data = get_data();
+        let split = UNCOMPRESSED_DATA_SIZE as usize + 10;
+        for algorithm in ALGORITHMS.iter() {
+            let mut config = ArchiveWriterConfig::new();
+            config.with_compression_algorithm(*algorithm).unwrap();
+            let mut comp = Box::new(CompressionLayerWriter::new(
+                Box::new(RawLayerWriter::new(Vec::new())),
+                &config.compress,
+            ));
+            // The two first blocks start while bypassing compression
+            comp.set_compression_bypass(true);
+            comp.write_all(&data[..split]).unwrap();
+            comp.set_compression_bypass(false);
+            comp.write_all(&data[split..]).unwrap();
+            comp.finalize().unwrap();
+            let file = comp.into_raw();
+            let reader_config = CompressionReaderConfig {
+                algorithm: *algorithm,
+                dictionary: None,
+            };
+
+            let mut decomp = Box::new(
+                CompressionLayerReader::new(
+                    Box::new(RawLayerReader::new(Cursor::new(file.as_slice()))),
+                    &reader_config,
+                )
+                .unwrap(),
+            );
+            decomp.initialize().unwrap();
+            let sizes_info = decomp.sizes_info.as_ref().unwrap();
+            assert!(sizes_info.compressed_sizes[0] >= UNCOMPRESSED_DATA_SIZE);
+            assert!(sizes_info.compressed_sizes[1] >= UNCOMPRESSED_DATA_SIZE);
+            if let CompressionAlgorithm::Brotli { .. } | CompressionAlgorithm::Zstd { .. } =
+                algorithm
+            {
+                // Alphanumeric data are compressed by entropy coders
+                assert!(sizes_info.compressed_sizes[2] < sizes_info.last_block_size);
+            }
+            let mut buf = Vec::new();
+            decomp.read_to_end(&mut buf).unwrap();
+            assert_eq!(buf, data, "{:?}", algorithm);
+
+            // Seek in the second (stored) block
+            let pos = UNCOMPRESSED_DATA_SIZE as u64 + 42;
+            decomp.seek(SeekFrom::Start(pos)).unwrap();
+            let mut buf = [0u8; 5];
+            decomp.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, &data[pos as usize..(pos + 5) as usize]);
+
+            let mut decomp = Box::new(
+                CompressionLayerFailSafeReader::new(
+                    Box::new(RawLayerFailSafeReader::new(file.as_slice())),
+                    &reader_config,
+                )
+                .unwrap(),
+            );
+            let mut buf = Vec::new();
+            let _ = decomp.read_to_end(&mut buf);
+            assert_eq!(&buf[..data.len()], data.as_slice(), "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn compression_hint() {
+        let mut config = ArchiveWriterConfig::new();
+        assert_eq!(
+            config.compress.resolve_hint("a.jpg", CompressionHint::Auto),
+            Some(false)
+        );
+        config.skip_compression_for_extensions(&["jpg", ".MP4"]);
+        for (name, bypass) in [
+            ("a.jpg", Some(true)),
+            ("dir/a.JPG", Some(true)),
+            ("a.mp4", Some(true)),
+            ("a.txt", Some(false)),
+            ("jpg", Some(false)),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                config.compress.resolve_hint(name, CompressionHint::Auto),
+                *bypass,
+                "{}",
+                name
+            );
+        }
+        assert_eq!(
+            config
+                .compress
+                .resolve_hint("a.jpg", CompressionHint::Compress),
+            Some(false)
+        );
+        assert_eq!(
+            config
+                .compress
+                .resolve_hint("a.txt", CompressionHint::Store),
+            Some(true)
+        );
+
+        // Pending the content sample
+        config.entropy_threshold(7.5);
+        assert_eq!(
+            config.compress.resolve_hint("a.txt", CompressionHint::Auto),
+            None
+        );
+        assert!(!config
+            .compress
+            .is_bypassed_sample(&get_data()[..ENTROPY_SAMPLE_SIZE]));
+        assert!(config
+            .compress
+            .is_bypassed_sample(&get_uncompressable_data()[..ENTROPY_SAMPLE_SIZE]));
+
+        assert_eq!(estimate_entropy(&[]), 0.);
+        assert_eq!(estimate_entropy(&[42; 100]), 0.);
+        assert_eq!(estimate_entropy(&[0, 1, 2, 3]), 2.);
+    }
+
+    #[test]
+    fn compress_algorithm_config() {
+        let mut config = ArchiveWriterConfig::new();
+        // Brotli by default
+        config.with_compression_level(11).unwrap();
+        assert_eq!(
+            config.compress.algorithm,
+            CompressionAlgorithm::Brotli { quality: 11 }
+        );
+        assert!(config.with_compression_level(12).is_err());
+        assert!(config
+            .with_compression_algorithm(CompressionAlgorithm::Brotli { quality: 12 })
+            .is_err());
+
+        config
+            .with_compression_algorithm(CompressionAlgorithm::Zstd { level: 1 })
+            .unwrap()
+            .with_compression_level(19)
+            .unwrap();
+        assert_eq!(
+            config.compress.algorithm,
+            CompressionAlgorithm::Zstd { level: 19 }
+        );
+        assert!(config.with_compression_level(1000).is_err());
+
+        config
+            .with_compression_algorithm(CompressionAlgorithm::Lz4)
+            .unwrap();
+        assert!(config.with_compression_level(1).is_err());
+        assert_eq!(config.compress.algorithm, CompressionAlgorithm::Lz4);
+    }
+
+    #[test]
+    fn compress_quality() {
+        // Compressible input: words picked in a small vocabulary
+        let mut rng: StdRng = SeedableRng::from_seed([0u8; 32]);
+        let words = [
+            "multi", "layer", "archive", "compress", "encrypt", "sign", " ", "\n",
+        ];
+        let mut bytes = Vec::new();
+        while bytes.len() < UNCOMPRESSED_DATA_SIZE as usize / 4 {
+            bytes.extend_from_slice(words[rng.next_u32() as usize % words.len()].as_bytes());
+        }
+
+        let mut results = Vec::new();
+        for quality in [0, 9].iter() {
+            let mut config = ArchiveWriterConfig::new();
+            config
+                .enable_layer(Layers::COMPRESS)
+                .compression_quality(*quality);
+            config.check().unwrap();
+            let mut comp = Box::new(CompressionLayerWriter::new(
+                Box::new(RawLayerWriter::new(Vec::new())),
+                &config.compress,
+            ));
+            let now = Instant::now();
+            comp.write_all(&bytes).unwrap();
+            comp.finalize().unwrap();
+            let elapsed = now.elapsed();
+            let file = comp.into_raw();
+
+            let mut decomp = Box::new(
+                CompressionLayerReader::new(
+                    Box::new(RawLayerReader::new(Cursor::new(file.as_slice()))),
+                    &Default::default(),
+                )
+                .unwrap(),
+            );
+            decomp.initialize().unwrap();
+            let mut buf = Vec::new();
+            decomp.read_to_end(&mut buf).unwrap();
+            assert_eq!(buf, bytes);
+            results.push((file.len(), elapsed));
+        }
+        let (size_fast, time_fast) = results[0];
+        let (size_dense, time_dense) = results[1];
+        assert!(size_fast > size_dense);
+        assert!(time_fast < time_dense);
+
+        // Out of range quality is rejected on check
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .enable_layer(Layers::COMPRESS)
+            .compression_quality(12);
+        assert!(config.check().is_err());
+        // ... but only if the layer is enabled
+        config.disable_layer(Layers::COMPRESS);
+        assert!(config.check().is_ok());
+    }
+
     #[test]
     fn sizes_info() {
         let sizes_info = SizesInfo {
@@ -1087,6 +2187,19 @@ mod tests {
             sizes_info.compressed_block_size_at(UNCOMPRESSED_DATA_SIZE as u64 + 1),
             2
         );
+        assert!(sizes_info.is_valid());
+
+        // Inconsistent sizes, from a corrupted archive
+        assert!(!SizesInfo {
+            compressed_sizes: Vec::new(),
+            last_block_size: 0,
+        }
+        .is_valid());
+        assert!(!SizesInfo {
+            compressed_sizes: vec![1],
+            last_block_size: UNCOMPRESSED_DATA_SIZE + 1,
+        }
+        .is_valid());
     }
 
     #[test]
@@ -1129,14 +2242,18 @@ mod tests {
         // Check content
         let buf = Cursor::new(file.as_slice());
         let mut buf_out = Vec::new();
-        let mut decomp =
-            Box::new(CompressionLayerReader::new(Box::new(RawLayerReader::new(buf))).unwrap());
+        let mut decomp = Box::new(
+            CompressionLayerReader::new(Box::new(RawLayerReader::new(buf)), &Default::default())
+                .unwrap(),
+        );
         decomp.initialize().unwrap();
         decomp.read_to_end(&mut buf_out).unwrap();
         let buf2 = Cursor::new(file2.as_slice());
         let mut buf2_out = Vec::new();
-        let mut decomp =
-            Box::new(CompressionLayerReader::new(Box::new(RawLayerReader::new(buf2))).unwrap());
+        let mut decomp = Box::new(
+            CompressionLayerReader::new(Box::new(RawLayerReader::new(buf2)), &Default::default())
+                .unwrap(),
+        );
         decomp.initialize().unwrap();
         decomp.read_to_end(&mut buf2_out).unwrap();
         assert_eq!(buf_out, buf2_out);
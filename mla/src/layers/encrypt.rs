@@ -1,5 +1,12 @@
 use crate::crypto::aesgcm::{AesGcm256, ConstantTimeEq, Tag, TAG_LENGTH};
 use crate::crypto::ecc::{retrieve_key, store_key_for_multi_recipients, MultiRecipientPersistent};
+use crate::crypto::file::{FileDecryptor, FileRecipientsPersistent};
+use crate::crypto::password::{
+    retrieve_key_with_password, store_key_for_password, Argon2Params, PasswordPersistent,
+};
+use crate::crypto::threshold::{
+    retrieve_key_with_threshold, store_key_for_threshold, ThresholdConfig, ThresholdPersistent,
+};
 
 use crate::layers::traits::{LayerFailSafeReader, LayerReader, LayerWriter};
 use crate::Error;
@@ -8,9 +15,14 @@ use std::io::{BufReader, Cursor, Read, Seek, SeekFrom, Write};
 
 use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
 use crate::errors::ConfigError;
+use ed25519_parser::{
+    parse_openssl_ed25519_privkey, parse_openssl_ed25519_privkey_der, parse_openssl_ed25519_pubkey,
+    parse_openssl_ed25519_pubkey_der,
+};
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaChaRng;
 use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
 
 use serde::{Deserialize, Serialize};
 
@@ -49,6 +61,63 @@ fn build_nonce(nonce_prefix: [u8; NONCE_SIZE], current_ctr: u32) -> Nonce {
 pub struct EncryptionPersistentConfig {
     multi_recipient: MultiRecipientPersistent,
     nonce: [u8; NONCE_SIZE],
+    /// Present if the archive can also be decrypted with a password
+    password: Option<PasswordPersistent>,
+    /// Present if the archive can also be decrypted with enough shares of
+    /// its key, since format v7
+    threshold: Option<ThresholdPersistent>,
+}
+
+impl EncryptionPersistentConfig {
+    /// Configuration in the layout of format v2 to v6 archives, `None` if it
+    /// uses features they do not support
+    pub(crate) fn to_v2(&self) -> Option<EncryptionPersistentConfigV2> {
+        if self.threshold.is_some() {
+            return None;
+        }
+        Some(EncryptionPersistentConfigV2 {
+            multi_recipient: self.multi_recipient.clone(),
+            nonce: self.nonce,
+            password: self.password.clone(),
+        })
+    }
+}
+
+/// Configuration stored in the header of format v2 to v6 archives
+#[derive(Serialize, Deserialize)]
+pub(crate) struct EncryptionPersistentConfigV2 {
+    multi_recipient: MultiRecipientPersistent,
+    nonce: [u8; NONCE_SIZE],
+    password: Option<PasswordPersistent>,
+}
+
+impl From<EncryptionPersistentConfigV2> for EncryptionPersistentConfig {
+    fn from(config: EncryptionPersistentConfigV2) -> Self {
+        EncryptionPersistentConfig {
+            multi_recipient: config.multi_recipient,
+            nonce: config.nonce,
+            password: config.password,
+            threshold: None,
+        }
+    }
+}
+
+/// Configuration stored in the header of format v1 archives
+#[derive(Deserialize)]
+pub(crate) struct EncryptionPersistentConfigV1 {
+    multi_recipient: MultiRecipientPersistent,
+    nonce: [u8; NONCE_SIZE],
+}
+
+impl From<EncryptionPersistentConfigV1> for EncryptionPersistentConfig {
+    fn from(config: EncryptionPersistentConfigV1) -> Self {
+        EncryptionPersistentConfig {
+            multi_recipient: config.multi_recipient,
+            nonce: config.nonce,
+            password: None,
+            threshold: None,
+        }
+    }
 }
 
 pub struct EncryptionConfig {
@@ -58,8 +127,23 @@ pub struct EncryptionConfig {
     key: [u8; KEY_SIZE],
     /// Symmetric encryption nonce
     nonce: [u8; NONCE_SIZE],
+    /// Password with which to encrypt the symmetric encryption key, if any
+    password: Option<(Zeroizing<Vec<u8>>, Argon2Params)>,
+    /// Recipients of the shares of the symmetric encryption key, if any
+    threshold: Option<ThresholdConfig>,
+    /// Seed from which all the randomness is derived, for reproducible
+    /// archives, if any
+    seed: Option<[u8; 32]>,
 }
 
+/// Streams of a seeded CSPRNG, one for each use of randomness, see
+/// `EncryptionConfig::csprng`
+pub(crate) const CSPRNG_STREAM_KEY: u64 = 0;
+pub(crate) const CSPRNG_STREAM_HEADER: u64 = 1;
+pub(crate) const CSPRNG_STREAM_ARCHIVE_ID: u64 = 2;
+/// Followed by one stream per file, by identifier
+pub(crate) const CSPRNG_STREAM_FILES: u64 = 3;
+
 impl std::default::Default for EncryptionConfig {
     fn default() -> Self {
         // Use OsRng from crate rand, that uses getrandom() from crate getrandom.
@@ -84,42 +168,149 @@ impl std::default::Default for EncryptionConfig {
             ecc_keys: Vec::new(),
             key,
             nonce,
+            password: None,
+            threshold: None,
+            seed: None,
         }
     }
 }
 
+impl Drop for EncryptionConfig {
+    fn drop(&mut self) {
+        self.key.zeroize();
+        self.seed.zeroize();
+    }
+}
+
+/// The symmetric key and the seed are zeroized on drop, the password is in a
+/// `Zeroizing`
+impl ZeroizeOnDrop for EncryptionConfig {}
+
 impl EncryptionConfig {
     /// Consistency check
     pub fn check(&self) -> Result<(), ConfigError> {
-        if self.ecc_keys.is_empty() {
-            Err(ConfigError::EncryptionKeyIsMissing)
-        } else {
-            Ok(())
+        if let Some(threshold) = &self.threshold {
+            threshold.check()?;
+        }
+        match &self.password {
+            Some((_password, params)) => params.check(),
+            None if self.ecc_keys.is_empty() && self.threshold.is_none() => {
+                Err(ConfigError::EncryptionKeyIsMissing)
+            }
+            None => Ok(()),
         }
     }
 
-    pub fn to_persistent(&self) -> Result<EncryptionPersistentConfig, ConfigError> {
-        let mut rng = ChaChaRng::from_entropy();
-        if let Ok(multi_recipient) =
-            store_key_for_multi_recipients(&self.ecc_keys, &self.key, &mut rng)
-        {
-            Ok(EncryptionPersistentConfig {
-                multi_recipient,
-                nonce: self.nonce,
-            })
-        } else {
-            Err(ConfigError::ECIESComputationError)
+    /// Use an existing archive key and nonce, instead of the generated ones
+    pub(crate) fn set_parameters(&mut self, key: [u8; KEY_SIZE], nonce: [u8; NONCE_SIZE]) {
+        self.key = key;
+        self.nonce = nonce;
+    }
+
+    /// Derive the key, the nonce, and every later randomness from `seed`
+    pub(crate) fn set_seed(&mut self, seed: [u8; 32]) {
+        self.seed = Some(seed);
+        let mut csprng = self.csprng(CSPRNG_STREAM_KEY);
+        self.key = csprng.gen::<[u8; KEY_SIZE]>();
+        self.nonce = csprng.gen::<[u8; NONCE_SIZE]>();
+    }
+
+    /// CSPRNG for the use `stream` of randomness (see `CSPRNG_STREAM_*`):
+    /// derived from the seed if any, from the entropy otherwise
+    pub(crate) fn csprng(&self, stream: u64) -> ChaChaRng {
+        match self.seed {
+            Some(seed) => {
+                let mut csprng = ChaChaRng::from_seed(seed);
+                csprng.set_stream(stream);
+                csprng
+            }
+            // See `EncryptionConfig::default`
+            None => ChaChaRng::from_entropy(),
         }
     }
+
+    pub fn to_persistent(&self) -> Result<EncryptionPersistentConfig, ConfigError> {
+        let mut rng = self.csprng(CSPRNG_STREAM_HEADER);
+        let multi_recipient = store_key_for_multi_recipients(&self.ecc_keys, &self.key, &mut rng)
+            .or(Err(ConfigError::ECIESComputationError))?;
+        let password = match &self.password {
+            Some((password, params)) => Some(
+                store_key_for_password(password, *params, &self.key, &mut rng)
+                    .or(Err(ConfigError::PasswordKeyDerivationError))?,
+            ),
+            None => None,
+        };
+        let threshold = match &self.threshold {
+            Some(threshold) => Some(
+                store_key_for_threshold(threshold, &self.key, &mut rng)
+                    .or(Err(ConfigError::ECIESComputationError))?,
+            ),
+            None => None,
+        };
+        Ok(EncryptionPersistentConfig {
+            multi_recipient,
+            nonce: self.nonce,
+            password,
+            threshold,
+        })
+    }
 }
 
 impl ArchiveWriterConfig {
     /// Set public keys to use
+    ///
+    /// The archive can be decrypted by any of the corresponding private keys
     pub fn add_public_keys(&mut self, keys: &[PublicKey]) -> &mut ArchiveWriterConfig {
         self.encrypt.ecc_keys.extend_from_slice(keys);
         self
     }
 
+    /// Add a public key to use, see `add_public_keys`
+    pub fn add_public_key(&mut self, key: &PublicKey) -> &mut ArchiveWriterConfig {
+        self.encrypt.ecc_keys.push(*key);
+        self
+    }
+
+    /// Add a public key to use, in PEM format, see `add_public_keys`
+    ///
+    /// X25519 and Ed25519 keys are supported, as generated by
+    /// `openssl genpkey -algorithm x25519` then `openssl pkey -pubout`
+    pub fn add_public_key_pem(&mut self, pem: &str) -> Result<&mut ArchiveWriterConfig, Error> {
+        let key =
+            parse_openssl_ed25519_pubkey(pem.as_bytes()).or(Err(Error::InvalidECCKeyFormat))?;
+        Ok(self.add_public_key(&key))
+    }
+
+    /// Add a public key to use, in DER format, see `add_public_key_pem`
+    pub fn add_public_key_der(&mut self, der: &[u8]) -> Result<&mut ArchiveWriterConfig, Error> {
+        let key = parse_openssl_ed25519_pubkey_der(der).or(Err(Error::InvalidECCKeyFormat))?;
+        Ok(self.add_public_key(&key))
+    }
+
+    /// Set a password to use, with the Argon2id parameters to derive a key from it
+    ///
+    /// This can be combined with public keys: the archive can then be
+    /// decrypted either with the password or with one of the private keys
+    pub fn with_password(
+        &mut self,
+        password: &[u8],
+        params: Argon2Params,
+    ) -> &mut ArchiveWriterConfig {
+        self.encrypt.password = Some((Zeroizing::new(password.to_vec()), params));
+        self
+    }
+
+    /// Split the archive key in shares, so that `threshold` of its recipients
+    /// are needed to decrypt the archive (see `ThresholdConfig`)
+    ///
+    /// Public keys set with `add_public_keys` and the password, if any, can
+    /// still decrypt the archive alone: for the threshold to be the only way,
+    /// do not set them
+    pub fn set_threshold(&mut self, threshold: ThresholdConfig) -> &mut ArchiveWriterConfig {
+        self.encrypt.threshold = Some(threshold);
+        self
+    }
+
     /// Return the key used for encryption
     pub fn encryption_key(&self) -> &[u8; KEY_SIZE] {
         &self.encrypt.key
@@ -131,30 +322,58 @@ impl ArchiveWriterConfig {
     }
 }
 
+#[derive(Clone)]
 pub struct EncryptionReaderConfig {
     /// Private key(s) to use
     private_keys: Vec<StaticSecret>,
+    /// Password to use, if any
+    password: Option<Zeroizing<Vec<u8>>>,
+    /// Private keys of the holders of key shares, see
+    /// `ArchiveWriterConfig::set_threshold`
+    threshold_keys: Vec<StaticSecret>,
     /// Symmetric encryption key and nonce, if decrypted successfully from header
     encrypt_parameters: Option<([u8; KEY_SIZE], [u8; NONCE_SIZE])>,
+    /// Number of recipients of the archive, from header
+    recipients_count: usize,
 }
 
 impl std::default::Default for EncryptionReaderConfig {
     fn default() -> Self {
         Self {
             private_keys: Vec::new(),
+            password: None,
+            threshold_keys: Vec::new(),
             encrypt_parameters: None,
+            recipients_count: 0,
+        }
+    }
+}
+
+impl Drop for EncryptionReaderConfig {
+    fn drop(&mut self) {
+        // Zeroize in place: resetting the `Option` to `None` could leave
+        // arbitrary bytes in place of the key
+        if let Some((key, nonce)) = &mut self.encrypt_parameters {
+            key.zeroize();
+            nonce.zeroize();
         }
     }
 }
 
+/// The symmetric key is zeroized on drop, `StaticSecret` zeroizes itself and
+/// the password is in a `Zeroizing`
+impl ZeroizeOnDrop for EncryptionReaderConfig {}
+
 impl EncryptionReaderConfig {
     pub fn load_persistent(
         &mut self,
         config: EncryptionPersistentConfig,
     ) -> Result<(), ConfigError> {
-        if self.private_keys.is_empty() {
+        if self.private_keys.is_empty() && self.password.is_none() && self.threshold_keys.is_empty()
+        {
             return Err(ConfigError::PrivateKeyNotSet);
         }
+        self.recipients_count = config.multi_recipient.recipients_count();
         for private_key in &self.private_keys {
             match retrieve_key(&config.multi_recipient, private_key) {
                 Ok(Some(key)) => {
@@ -167,10 +386,50 @@ impl EncryptionReaderConfig {
             };
         }
 
-        if self.encrypt_parameters.is_none() {
-            return Err(ConfigError::PrivateKeyNotFound);
+        let mut insufficient_shares = false;
+        if let (None, false, Some(persist)) = (
+            &self.encrypt_parameters,
+            self.threshold_keys.is_empty(),
+            &config.threshold,
+        ) {
+            match retrieve_key_with_threshold(persist, &self.threshold_keys) {
+                Ok(Some(key)) => self.encrypt_parameters = Some((key, config.nonce)),
+                Ok(None) => insufficient_shares = true,
+                Err(_) => {}
+            }
+        }
+
+        // Only fallback on the password, as its derivation is costly
+        if let (None, Some(password), Some(persist)) =
+            (&self.encrypt_parameters, &self.password, &config.password)
+        {
+            match retrieve_key_with_password(persist, password) {
+                Ok(Some(key)) => self.encrypt_parameters = Some((key, config.nonce)),
+                // Parameters of the header refused, see `Argon2Params`
+                Err(Error::ConfigError(error)) => return Err(error),
+                Ok(None) | Err(_) => {}
+            }
+        }
+
+        match self.encrypt_parameters {
+            Some(_) => Ok(()),
+            None if insufficient_shares => Err(ConfigError::InsufficientShares),
+            None => Err(ConfigError::PrivateKeyNotFound),
         }
-        Ok(())
+    }
+
+    /// Number of public key recipients of the archive, once loaded
+    pub(crate) fn recipients_count(&self) -> usize {
+        self.recipients_count
+    }
+
+    /// Decryptor of a file encrypted for its own recipients, if one of the
+    /// private keys is among them. Passwords are not considered
+    pub(crate) fn file_decryptor(
+        &self,
+        recipients: &FileRecipientsPersistent,
+    ) -> Result<FileDecryptor, Error> {
+        FileDecryptor::new(recipients, &self.private_keys)
     }
 }
 
@@ -181,6 +440,54 @@ impl ArchiveReaderConfig {
         self
     }
 
+    /// Add a private key to use, in PEM format (PKCS#8)
+    ///
+    /// X25519 and Ed25519 keys are supported, as generated by
+    /// `openssl genpkey -algorithm x25519`
+    pub fn add_private_key_pem(&mut self, pem: &str) -> Result<&mut ArchiveReaderConfig, Error> {
+        let key =
+            parse_openssl_ed25519_privkey(pem.as_bytes()).or(Err(Error::InvalidECCKeyFormat))?;
+        Ok(self.add_private_keys(&[key]))
+    }
+
+    /// Add a private key to use, in DER format (PKCS#8), see
+    /// `add_private_key_pem`
+    pub fn add_private_key_der(&mut self, der: &[u8]) -> Result<&mut ArchiveReaderConfig, Error> {
+        let key = parse_openssl_ed25519_privkey_der(der).or(Err(Error::InvalidECCKeyFormat))?;
+        Ok(self.add_private_keys(&[key]))
+    }
+
+    /// Add private keys of holders of shares of the archive key, see
+    /// `ArchiveWriterConfig::set_threshold`
+    ///
+    /// The archive is decrypted if they give at least the threshold of
+    /// shares, `Error::InsufficientShares` being returned on opening
+    /// otherwise. As a share can't count twice, a key already added is
+    /// refused with `Error::BadAPIArgument`
+    pub fn add_threshold_keys(&mut self, keys: &[StaticSecret]) -> Result<(), Error> {
+        for key in keys {
+            let public = PublicKey::from(key);
+            if self
+                .encrypt
+                .threshold_keys
+                .iter()
+                .any(|known| PublicKey::from(known).as_bytes() == public.as_bytes())
+            {
+                return Err(Error::BadAPIArgument(
+                    "[add_threshold_keys] The same key is given twice".to_string(),
+                ));
+            }
+            self.encrypt.threshold_keys.push(key.clone());
+        }
+        Ok(())
+    }
+
+    /// Set the password to use
+    pub fn with_password(&mut self, password: &[u8]) -> &mut ArchiveReaderConfig {
+        self.encrypt.password = Some(Zeroizing::new(password.to_vec()));
+        self
+    }
+
     /// Retrieve key and nonce used for encryption
     pub fn get_encrypt_parameters(&self) -> Option<([u8; KEY_SIZE], [u8; NONCE_SIZE])> {
         self.encrypt.encrypt_parameters
@@ -189,15 +496,25 @@ impl ArchiveReaderConfig {
 
 // ---------- Writer ----------
 
+/// Upper bound of the output size of an `EncryptionLayerWriter` fed with
+/// `size` bytes: a tag is added to each chunk
+pub(crate) fn max_encrypted_size(size: u64) -> u64 {
+    size + (size / CHUNK_SIZE + 1) * TAG_LENGTH as u64
+}
+
 pub struct EncryptionLayerWriter<'a, W: 'a + Write> {
     inner: Box<dyn 'a + LayerWriter<'a, W>>,
     cipher: AesGcm256,
     /// Symmetric encryption Key
-    key: [u8; KEY_SIZE],
+    key: Zeroizing<[u8; KEY_SIZE]>,
     /// Symmetric encryption nonce prefix, see `build_nonce`
     nonce_prefix: [u8; NONCE_SIZE],
     current_chunk_offset: u64,
     current_ctr: u32,
+    /// Encrypted data of the current chunk, not yet written to `inner`. A
+    /// chunk is written at once, along with its tag, so that a reader at the
+    /// other end of a stream (socket, pipe) gets whole chunks
+    pending: Vec<u8>,
 }
 
 impl<'a, W: 'a + Write> EncryptionLayerWriter<'a, W> {
@@ -207,11 +524,12 @@ impl<'a, W: 'a + Write> EncryptionLayerWriter<'a, W> {
     ) -> Result<Self, Error> {
         Ok(Self {
             inner,
-            key: config.key,
+            key: Zeroizing::new(config.key),
             nonce_prefix: config.nonce,
             cipher: AesGcm256::new(&config.key, &build_nonce(config.nonce, 0), b"")?,
             current_chunk_offset: 0,
             current_ctr: 0,
+            pending: Vec::with_capacity(CHUNK_SIZE as usize + TAG_LENGTH),
         })
     }
 
@@ -220,13 +538,28 @@ impl<'a, W: 'a + Write> EncryptionLayerWriter<'a, W> {
         self.current_ctr += 1;
         self.current_chunk_offset = 0;
         let cipher = AesGcm256::new(
-            &self.key,
+            &self.key[..],
             &build_nonce(self.nonce_prefix, self.current_ctr),
             b"",
         )?;
         let old_cipher = std::mem::replace(&mut self.cipher, cipher);
         Ok(old_cipher.into_tag())
     }
+
+    /// Write the end of the current chunk, with its tag, and flush it
+    fn write_chunk_end(&mut self) -> Result<(), Error> {
+        let tag = self.renew_cipher()?;
+        self.pending.extend_from_slice(&tag);
+        self.write_pending()?;
+        Ok(self.inner.flush()?)
+    }
+
+    /// Write the pending encrypted data
+    fn write_pending(&mut self) -> io::Result<()> {
+        self.inner.write_all(&self.pending)?;
+        self.pending.clear();
+        Ok(())
+    }
 }
 
 impl<'a, W: 'a + Write> LayerWriter<'a, W> for EncryptionLayerWriter<'a, W> {
@@ -239,9 +572,8 @@ impl<'a, W: 'a + Write> LayerWriter<'a, W> for EncryptionLayerWriter<'a, W> {
     }
 
     fn finalize(&mut self) -> Result<(), Error> {
-        // Write the tag of the current chunk
-        let tag = self.renew_cipher()?;
-        self.inner.write_all(&tag)?;
+        // Write the current chunk and its tag
+        self.write_chunk_end()?;
 
         // Recursive call
         self.inner.finalize()
@@ -257,10 +589,8 @@ impl<'a, W: Write> Write for EncryptionLayerWriter<'a, W> {
                 Error::WrongWriterState("[EncryptWriter] Chunk too big".to_string()).into(),
             );
         } else if self.current_chunk_offset == CHUNK_SIZE {
-            // Prepare a new cipher
-            let tag = self.renew_cipher()?;
-            // Write the previous chunk tag
-            self.inner.write_all(&tag)?;
+            // Write the previous chunk, with its tag, and prepare a new cipher
+            self.write_chunk_end()?;
         }
 
         // StreamingCipher is working in place, so we use a temporary buffer
@@ -272,12 +602,14 @@ impl<'a, W: Write> Write for EncryptionLayerWriter<'a, W> {
         let buf_src = BufReader::new(buf);
         io::copy(&mut buf_src.take(size), &mut buf_tmp)?;
         self.cipher.encrypt(&mut buf_tmp);
-        self.inner.write_all(&buf_tmp)?;
+        self.pending.extend_from_slice(&buf_tmp);
         self.current_chunk_offset += size;
         Ok(size as usize)
     }
 
     fn flush(&mut self) -> io::Result<()> {
+        // The chunk is not complete: its tag will follow
+        self.write_pending()?;
         self.inner.flush()
     }
 }
@@ -288,10 +620,10 @@ impl<'a, W: Write> Write for EncryptionLayerWriter<'a, W> {
 // keep the struct separated for any possible future difference
 pub struct EncryptionLayerReader<'a, R: Read + Seek> {
     inner: Box<dyn 'a + LayerReader<'a, R>>,
-    cipher: AesGcm256,
-    key: [u8; KEY_SIZE],
+    key: Zeroizing<[u8; KEY_SIZE]>,
     nonce: [u8; NONCE_SIZE],
-    chunk_cache: Cursor<Vec<u8>>,
+    /// Decrypted data of the current chunk
+    chunk_cache: Cursor<Zeroizing<Vec<u8>>>,
     current_chunk_number: u32,
 }
 
@@ -303,10 +635,9 @@ impl<'a, R: 'a + Read + Seek> EncryptionLayerReader<'a, R> {
         match config.encrypt_parameters {
             Some((key, nonce)) => Ok(Self {
                 inner,
-                cipher: AesGcm256::new(&key, &build_nonce(nonce, 0), b"")?,
-                key,
+                key: Zeroizing::new(key),
                 nonce,
-                chunk_cache: Cursor::new(Vec::with_capacity(CHUNK_SIZE as usize)),
+                chunk_cache: Cursor::new(Zeroizing::new(Vec::with_capacity(CHUNK_SIZE as usize))),
                 current_chunk_number: 0,
             }),
             None => Err(Error::PrivateKeyNeeded),
@@ -316,45 +647,67 @@ impl<'a, R: 'a + Read + Seek> EncryptionLayerReader<'a, R> {
     /// Load the `self.current_chunk_number` chunk in cache
     /// Assume the inner layer is in the correct position
     fn load_in_cache(&mut self) -> Result<Option<()>, Error> {
-        self.cipher = AesGcm256::new(
-            &self.key,
-            &build_nonce(self.nonce, self.current_chunk_number),
-            b"",
-        )?;
-
         // Clear current, now useless, allocated memory
-        self.chunk_cache.get_mut().clear();
-
-        // Load the current encrypted chunk and the corresponding tag in memory
-        let mut data_and_tag = Vec::with_capacity(CHUNK_SIZE as usize + TAG_LENGTH);
-        let data_and_tag_read = (&mut self.inner)
-            .take(CHUNK_SIZE + TAG_LENGTH as u64)
-            .read_to_end(&mut data_and_tag)?;
-        // If the inner is at the end of the stream, we cannot read any
-        // additional byte -> we must stop
-        if data_and_tag_read == 0 {
-            return Ok(None);
-        }
+        self.chunk_cache.get_mut().zeroize();
 
-        // If it is the last block, we may have read less than `CHUNK_SIZE +
-        // TAG_LENGTH` bytes. But the `TAG_LENGTH` last bytes are always the tag
-        // bytes -> extract it
-        let mut tag = [0u8; TAG_LENGTH];
-        tag.copy_from_slice(&data_and_tag[data_and_tag_read - TAG_LENGTH..]);
-        data_and_tag.resize(data_and_tag_read - TAG_LENGTH, 0);
-        let mut data = data_and_tag;
-
-        // Decrypt and verify the current chunk
-        let expected_tag = self.cipher.decrypt(data.as_mut_slice());
-        if expected_tag.ct_eq(&tag).unwrap_u8() != 1 {
-            Err(Error::AuthenticatedDecryptionWrongTag)
-        } else {
-            self.chunk_cache = Cursor::new(data);
-            Ok(Some(()))
+        match decrypt_chunk(
+            &mut self.inner,
+            &self.key,
+            self.nonce,
+            self.current_chunk_number,
+        )? {
+            Some(data) => {
+                self.chunk_cache = Cursor::new(data);
+                Ok(Some(()))
+            }
+            None => Ok(None),
         }
     }
 }
 
+/// Read the chunk `chunk_number` and its tag from `src`, then decrypt and
+/// authenticate it. Returns `None` if `src` is at the end of the stream
+fn decrypt_chunk<R: Read>(
+    src: R,
+    key: &[u8; KEY_SIZE],
+    nonce: [u8; NONCE_SIZE],
+    chunk_number: u32,
+) -> Result<Option<Zeroizing<Vec<u8>>>, Error> {
+    let mut cipher = AesGcm256::new(&key[..], &build_nonce(nonce, chunk_number), b"")?;
+
+    // Load the encrypted chunk and the corresponding tag in memory
+    // Zeroized on drop, as it may be decrypted before its tag is checked
+    let mut data_and_tag = Zeroizing::new(Vec::with_capacity(CHUNK_SIZE as usize + TAG_LENGTH));
+    let data_and_tag_read = src
+        .take(CHUNK_SIZE + TAG_LENGTH as u64)
+        .read_to_end(&mut data_and_tag)?;
+    // If the inner is at the end of the stream, we cannot read any
+    // additional byte -> we must stop
+    if data_and_tag_read == 0 {
+        return Ok(None);
+    }
+
+    // If it is the last block, we may have read less than `CHUNK_SIZE +
+    // TAG_LENGTH` bytes. But the `TAG_LENGTH` last bytes are always the tag
+    // bytes -> extract it. A shorter chunk (truncated data) cannot be
+    // authenticated
+    if data_and_tag_read < TAG_LENGTH {
+        return Err(Error::AuthenticatedDecryptionWrongTag);
+    }
+    let mut tag = [0u8; TAG_LENGTH];
+    tag.copy_from_slice(&data_and_tag[data_and_tag_read - TAG_LENGTH..]);
+    data_and_tag.resize(data_and_tag_read - TAG_LENGTH, 0);
+    let mut data = data_and_tag;
+
+    // Decrypt and verify the current chunk
+    let expected_tag = cipher.decrypt(data.as_mut_slice());
+    if expected_tag.ct_eq(&tag).unwrap_u8() != 1 {
+        Err(Error::AuthenticatedDecryptionWrongTag)
+    } else {
+        Ok(Some(data))
+    }
+}
+
 impl<'a, R: 'a + Read + Seek> LayerReader<'a, R> for EncryptionLayerReader<'a, R> {
     fn into_inner(self) -> Option<Box<dyn 'a + LayerReader<'a, R>>> {
         Some(self.inner)
@@ -381,7 +734,9 @@ impl<'a, R: 'a + Read + Seek> Read for EncryptionLayerReader<'a, R> {
             // Cache totally consumed, renew it
             self.current_chunk_number += 1;
             if self.load_in_cache()?.is_none() {
-                // No more byte in the inner layer
+                // No more byte in the inner layer. Stay on the last chunk, to
+                // keep the current position consistent
+                self.current_chunk_number -= 1;
                 return Ok(0);
             }
             return self.read(buf);
@@ -401,14 +756,6 @@ fn no_tag_position_to_tag_position(position: u64) -> u64 {
     cur_chunk * CHUNK_TAG_SIZE + cur_chunk_pos
 }
 
-fn tag_position_to_no_tag_position(position: u64) -> u64 {
-    // Assume the position is not inside a tag. If so, round to the end of the
-    // current chunk
-    let cur_chunk = position / CHUNK_TAG_SIZE;
-    let cur_chunk_pos = position % CHUNK_TAG_SIZE;
-    cur_chunk * CHUNK_SIZE + std::cmp::min(cur_chunk_pos, CHUNK_SIZE)
-}
-
 impl<'a, R: 'a + Read + Seek> Seek for EncryptionLayerReader<'a, R> {
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
         // `pos` is the position without considering tags
@@ -429,19 +776,11 @@ impl<'a, R: 'a + Read + Seek> Seek for EncryptionLayerReader<'a, R> {
                 Ok(pos)
             }
             SeekFrom::Current(value) => {
-                // Inner layer is at the start of the next chunk. The last chunk
-                // may not be CHUNK_SIZE long.
-                let current_inner = tag_position_to_no_tag_position(self.inner.seek(pos)?);
-                let current_inner_chunk = {
-                    let chunk_nb = current_inner / CHUNK_SIZE;
-                    if chunk_nb == 0 {
-                        // Only one chunk, witch is not CHUNK_SIZE long
-                        0
-                    } else {
-                        chunk_nb - 1
-                    }
-                };
-                let current = current_inner_chunk * CHUNK_SIZE + self.chunk_cache.position();
+                // The inner layer position cannot be used here, as the last
+                // chunk may not be CHUNK_SIZE long. Rely on the chunk actually
+                // present in cache instead
+                let current =
+                    u64::from(self.current_chunk_number) * CHUNK_SIZE + self.chunk_cache.position();
                 if value == 0 {
                     // Optimization
                     Ok(current)
@@ -461,7 +800,10 @@ impl<'a, R: 'a + Read + Seek> Seek for EncryptionLayerReader<'a, R> {
                 let end_inner_pos = self.inner.seek(SeekFrom::End(0))?;
                 let cur_chunk = end_inner_pos / CHUNK_TAG_SIZE;
                 let cur_chunk_pos = end_inner_pos % CHUNK_TAG_SIZE;
-                let end_pos = cur_chunk * CHUNK_SIZE + cur_chunk_pos - TAG_LENGTH as u64;
+                // A last chunk shorter than a TAG comes from truncated data
+                let end_pos = (cur_chunk * CHUNK_SIZE + cur_chunk_pos)
+                    .checked_sub(TAG_LENGTH as u64)
+                    .ok_or(Error::AuthenticatedDecryptionWrongTag)?;
                 self.seek(SeekFrom::Start((pos + end_pos as i64) as u64))
             }
         }
@@ -473,7 +815,7 @@ impl<'a, R: 'a + Read + Seek> Seek for EncryptionLayerReader<'a, R> {
 pub struct EncryptionLayerFailSafeReader<'a, R: Read> {
     inner: Box<dyn 'a + LayerFailSafeReader<'a, R>>,
     cipher: AesGcm256,
-    key: [u8; KEY_SIZE],
+    key: Zeroizing<[u8; KEY_SIZE]>,
     nonce: [u8; NONCE_SIZE],
     current_chunk_number: u32,
     current_chunk_offset: u64,
@@ -488,7 +830,7 @@ impl<'a, R: 'a + Read> EncryptionLayerFailSafeReader<'a, R> {
             Some((key, nonce)) => Ok(Self {
                 inner,
                 cipher: AesGcm256::new(&key, &build_nonce(nonce, 0), b"")?,
-                key,
+                key: Zeroizing::new(key),
                 nonce,
                 current_chunk_number: 0,
                 current_chunk_offset: 0,
@@ -519,7 +861,7 @@ impl<'a, R: Read> Read for EncryptionLayerFailSafeReader<'a, R> {
             self.current_chunk_number += 1;
             self.current_chunk_offset = 0;
             self.cipher = AesGcm256::new(
-                &self.key,
+                &self.key[..],
                 &build_nonce(self.nonce, self.current_chunk_number),
                 b"",
             )?;
@@ -539,6 +881,70 @@ impl<'a, R: Read> Read for EncryptionLayerFailSafeReader<'a, R> {
     }
 }
 
+// ---------- Stream Reader ----------
+
+/// Read the data linearly, without seeking, for non-seekable sources. Unlike
+/// the `EncryptionLayerFailSafeReader`, a chunk is only provided once its tag
+/// has been checked
+pub struct EncryptionLayerStreamReader<'a, R: Read> {
+    inner: Box<dyn 'a + LayerFailSafeReader<'a, R>>,
+    key: Zeroizing<[u8; KEY_SIZE]>,
+    nonce: [u8; NONCE_SIZE],
+    /// Decrypted data of the current chunk
+    chunk_cache: Cursor<Zeroizing<Vec<u8>>>,
+    /// Number of the next chunk to load
+    next_chunk_number: u32,
+}
+
+impl<'a, R: 'a + Read> EncryptionLayerStreamReader<'a, R> {
+    pub fn new(
+        inner: Box<dyn 'a + LayerFailSafeReader<'a, R>>,
+        config: &EncryptionReaderConfig,
+    ) -> Result<Self, Error> {
+        match config.encrypt_parameters {
+            Some((key, nonce)) => Ok(Self {
+                inner,
+                key: Zeroizing::new(key),
+                nonce,
+                chunk_cache: Cursor::new(Zeroizing::new(Vec::new())),
+                next_chunk_number: 0,
+            }),
+            None => Err(Error::PrivateKeyNeeded),
+        }
+    }
+}
+
+impl<'a, R: 'a + Read> LayerFailSafeReader<'a, R> for EncryptionLayerStreamReader<'a, R> {
+    fn into_inner(self) -> Option<Box<dyn 'a + LayerFailSafeReader<'a, R>>> {
+        Some(self.inner)
+    }
+
+    fn into_raw(self: Box<Self>) -> R {
+        self.inner.into_raw()
+    }
+}
+
+impl<'a, R: 'a + Read> Read for EncryptionLayerStreamReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.chunk_cache.position() == self.chunk_cache.get_ref().len() as u64 {
+            // Cache totally consumed, renew it
+            self.chunk_cache.get_mut().zeroize();
+            match decrypt_chunk(
+                &mut self.inner,
+                &self.key,
+                self.nonce,
+                self.next_chunk_number,
+            )? {
+                Some(data) => self.chunk_cache = Cursor::new(data),
+                // No more chunk
+                None => return Ok(0),
+            }
+            self.next_chunk_number += 1;
+        }
+        self.chunk_cache.read(buf)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -563,6 +969,9 @@ mod tests {
                     ecc_keys: Vec::new(),
                     key: KEY,
                     nonce: NONCE,
+                    password: None,
+                    threshold: None,
+                    seed: None,
                 },
             )
             .unwrap(),
@@ -585,7 +994,10 @@ mod tests {
         let buf = Cursor::new(out.as_slice());
         let config = EncryptionReaderConfig {
             private_keys: Vec::new(),
+            password: None,
+            threshold_keys: Vec::new(),
             encrypt_parameters: Some((KEY, NONCE)),
+            recipients_count: 0,
         };
         let mut encrypt_r =
             EncryptionLayerReader::new(Box::new(RawLayerReader::new(buf)), &config).unwrap();
@@ -602,7 +1014,10 @@ mod tests {
 
         let config = EncryptionReaderConfig {
             private_keys: Vec::new(),
+            password: None,
+            threshold_keys: Vec::new(),
             encrypt_parameters: Some((KEY, NONCE)),
+            recipients_count: 0,
         };
         let mut encrypt_r = EncryptionLayerFailSafeReader::new(
             Box::new(RawLayerFailSafeReader::new(out.as_slice())),
@@ -625,7 +1040,10 @@ mod tests {
 
         let config = EncryptionReaderConfig {
             private_keys: Vec::new(),
+            password: None,
+            threshold_keys: Vec::new(),
             encrypt_parameters: Some((KEY, NONCE)),
+            recipients_count: 0,
         };
         let mut encrypt_r = EncryptionLayerFailSafeReader::new(
             Box::new(RawLayerFailSafeReader::new(&out[..stop])),
@@ -648,7 +1066,10 @@ mod tests {
         let buf = Cursor::new(out.as_slice());
         let config = EncryptionReaderConfig {
             private_keys: Vec::new(),
+            password: None,
+            threshold_keys: Vec::new(),
             encrypt_parameters: Some((KEY, NONCE)),
+            recipients_count: 0,
         };
         let mut encrypt_r =
             EncryptionLayerReader::new(Box::new(RawLayerReader::new(buf)), &config).unwrap();
@@ -660,7 +1081,7 @@ mod tests {
         // Seek and decrypt twice the same thing
         let pos = encrypt_r.seek(SeekFrom::Current(0)).unwrap();
         // test the current position retrievial
-        assert_eq!(pos, tag_position_to_no_tag_position(FAKE_FILE.len() as u64));
+        assert_eq!(pos, FAKE_FILE.len() as u64);
         // decrypt twice the same thing, with an offset
         let pos = encrypt_r.seek(SeekFrom::Start(5)).unwrap();
         assert_eq!(pos, 5);
@@ -683,6 +1104,9 @@ mod tests {
                     ecc_keys: Vec::new(),
                     key: KEY,
                     nonce: NONCE,
+                    password: None,
+                    threshold: None,
+                    seed: None,
                 },
             )
             .unwrap(),
@@ -705,7 +1129,10 @@ mod tests {
         let buf = Cursor::new(out.as_slice());
         let config = EncryptionReaderConfig {
             private_keys: Vec::new(),
+            password: None,
+            threshold_keys: Vec::new(),
             encrypt_parameters: Some((KEY, NONCE)),
+            recipients_count: 0,
         };
         let mut encrypt_r =
             EncryptionLayerReader::new(Box::new(RawLayerReader::new(buf)), &config).unwrap();
@@ -713,6 +1140,9 @@ mod tests {
         let mut output = Vec::new();
         encrypt_r.read_to_end(&mut output).unwrap();
         assert_eq!(output, data);
+        // The current position is retrieved even after several chunks
+        let pos = encrypt_r.stream_position().unwrap();
+        assert_eq!(pos, length as u64);
 
         // Seek and decrypt twice the same thing
         let pos = encrypt_r.seek(SeekFrom::Start(CHUNK_SIZE)).unwrap();
@@ -720,5 +1150,89 @@ mod tests {
         let mut output = Vec::new();
         encrypt_r.read_to_end(&mut output).unwrap();
         assert_eq!(output.as_slice(), &data[CHUNK_SIZE as usize..]);
+
+        // Current position in the middle of the last chunk
+        encrypt_r.seek(SeekFrom::Start(CHUNK_SIZE + 10)).unwrap();
+        let mut buf = [0u8; 5];
+        encrypt_r.read_exact(&mut buf).unwrap();
+        let pos = encrypt_r.stream_position().unwrap();
+        assert_eq!(pos, CHUNK_SIZE + 15);
+    }
+
+    /// Run the destructor of `value`, then return the `N` bytes at `address`,
+    /// which must be inside `value`. Its memory is only freed afterwards, so
+    /// that it is still owned when read
+    fn read_after_drop<T, const N: usize>(value: Box<T>, address: usize) -> [u8; N] {
+        let ptr = Box::into_raw(value);
+        assert!(address >= ptr as usize && address + N <= ptr as usize + std::mem::size_of::<T>());
+        // SAFETY: `ptr` comes from a `Box`, dropped once, and its memory,
+        // allocated with the layout of `T`, is freed after being read
+        unsafe {
+            std::ptr::drop_in_place(ptr);
+            let data = std::ptr::read(address as *const [u8; N]);
+            std::alloc::dealloc(ptr as *mut u8, std::alloc::Layout::new::<T>());
+            data
+        }
+    }
+
+    #[test]
+    fn keys_zeroized_on_drop() {
+        // Writer configuration: the generated archive key
+        let config = Box::new(ArchiveWriterConfig::new());
+        let key = *config.encryption_key();
+        let key_address = config.encryption_key().as_ptr() as usize;
+        assert_ne!(key, [0; KEY_SIZE]);
+        assert_eq!(read_after_drop(config, key_address), [0; KEY_SIZE]);
+
+        // Writer layer
+        let encrypt_w = Box::new(
+            EncryptionLayerWriter::new(
+                Box::new(RawLayerWriter::new(Vec::new())),
+                &EncryptionConfig {
+                    ecc_keys: Vec::new(),
+                    key,
+                    nonce: NONCE,
+                    password: None,
+                    threshold: None,
+                    seed: None,
+                },
+            )
+            .unwrap(),
+        );
+        let key_address = encrypt_w.key.as_ptr() as usize;
+        assert_eq!(*encrypt_w.key, key);
+        assert_eq!(read_after_drop(encrypt_w, key_address), [0; KEY_SIZE]);
+
+        // Reader configuration: private keys and the decrypted archive key
+        let private_key = StaticSecret::from([7; 32]);
+        let private_key_bytes = private_key.to_bytes();
+        let mut config = Box::new(ArchiveReaderConfig::new());
+        config.add_private_keys(&[private_key]);
+        config.encrypt.encrypt_parameters = Some((key, NONCE));
+        let key_address = config
+            .encrypt
+            .encrypt_parameters
+            .as_ref()
+            .unwrap()
+            .0
+            .as_ptr() as usize;
+        // The private keys are in a buffer of their own: drop them as their
+        // `Vec` would, while it still owns the buffer
+        let private_keys = &mut config.encrypt.private_keys;
+        assert_eq!(private_keys[0].to_bytes(), private_key_bytes);
+        let private_keys_ptr = private_keys.as_mut_ptr();
+        let private_keys_len = private_keys.len();
+        // SAFETY: the keys are dropped once, the `Vec` being emptied first,
+        // and their buffer is read before the `Vec` frees it
+        let dropped_private_key: [u8; 32] = unsafe {
+            private_keys.set_len(0);
+            std::ptr::drop_in_place(std::ptr::slice_from_raw_parts_mut(
+                private_keys_ptr,
+                private_keys_len,
+            ));
+            std::ptr::read(private_keys_ptr as *const [u8; 32])
+        };
+        assert_eq!(dropped_private_key, [0; 32]);
+        assert_eq!(read_after_drop(config, key_address), [0; KEY_SIZE]);
     }
 }
@@ -0,0 +1,242 @@
+use crate::layers::traits::{LayerReader, LayerWriter};
+use crate::{Error, BINCODE_MAX_DESERIALIZE};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::cell::RefCell;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::rc::Rc;
+
+use crate::config::ArchiveWriterConfig;
+use crate::Layers;
+
+// ---------- Config ----------
+
+impl ArchiveWriterConfig {
+    /// Store the names of the archive files, unencrypted, so that they can be
+    /// listed without any key (see `ArchiveReader::list_files_public`)
+    ///
+    /// Only the names are revealed: the contents, and everything else, stay
+    /// protected by the other layers. The listing is covered by the signature,
+    /// if enabled. Such archives can't be read by a `StreamArchiveReader`
+    pub fn public_file_listing(&mut self, enabled: bool) -> &mut ArchiveWriterConfig {
+        if enabled {
+            self.enable_layer(Layers::PUBLIC_LISTING)
+        } else {
+            self.disable_layer(Layers::PUBLIC_LISTING)
+        }
+    }
+}
+
+/// Parse the listing ending `src` (ie. once the trailing signature, if any,
+/// is removed): [listing][listing length]
+///
+/// Return the listing and the size of the data preceding it
+fn read_listing<R: Read + Seek>(mut src: R) -> Result<(Vec<String>, u64), Error> {
+    let size = src.seek(SeekFrom::End(0))?;
+    if size < 4 {
+        return Err(Error::DeserializationError);
+    }
+    src.seek(SeekFrom::End(-4))?;
+    let len = src.read_u32::<LittleEndian>()? as u64;
+    let data_size = (size - 4)
+        .checked_sub(len)
+        .ok_or(Error::DeserializationError)?;
+    src.seek(SeekFrom::Start(data_size))?;
+    match bincode::config()
+        .limit(BINCODE_MAX_DESERIALIZE)
+        .deserialize_from(src.take(len))
+    {
+        Ok(listing) => Ok((listing, data_size)),
+        _ => Err(Error::DeserializationError),
+    }
+}
+
+/// Read the public listing of `src`, the layered data of an archive (after
+/// its header)
+pub(crate) fn read_public_listing<R: Read + Seek>(src: R) -> Result<Vec<String>, Error> {
+    read_listing(src).map(|(listing, _data_size)| listing)
+}
+
+// ---------- Writer ----------
+
+/// Append, at the end of the data, the listing provided through the shared
+/// `listing` once the archive is complete:
+/// [data][listing][listing length (u32)]
+pub struct PublicListingLayerWriter<'a, W: 'a + Write> {
+    inner: Box<dyn 'a + LayerWriter<'a, W>>,
+    /// File names, set by the archive writer before finalization
+    listing: Rc<RefCell<Vec<String>>>,
+}
+
+impl<'a, W: 'a + Write> PublicListingLayerWriter<'a, W> {
+    pub fn new(inner: Box<dyn 'a + LayerWriter<'a, W>>) -> Self {
+        Self {
+            inner,
+            listing: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    /// Listing to fill before finalization, still available once this layer
+    /// is boxed under other layers
+    pub fn listing(&self) -> Rc<RefCell<Vec<String>>> {
+        self.listing.clone()
+    }
+}
+
+impl<'a, W: 'a + Write> LayerWriter<'a, W> for PublicListingLayerWriter<'a, W> {
+    fn into_inner(self) -> Option<Box<dyn 'a + LayerWriter<'a, W>>> {
+        Some(self.inner)
+    }
+
+    fn into_raw(self: Box<Self>) -> W {
+        self.inner.into_raw()
+    }
+
+    fn finalize(&mut self) -> Result<(), Error> {
+        // Append the listing
+        let listing = self.listing.borrow();
+        let len = match bincode::serialized_size(&*listing) {
+            Ok(len) if len <= u32::MAX as u64 => len as u32,
+            _ => return Err(Error::SerializationError),
+        };
+        if bincode::serialize_into(&mut self.inner, &*listing).is_err() {
+            return Err(Error::SerializationError);
+        }
+        self.inner.write_u32::<LittleEndian>(len)?;
+
+        // Recursive call
+        self.inner.finalize()
+    }
+}
+
+impl<'a, W: Write> Write for PublicListingLayerWriter<'a, W> {
+    /// Wrapper on inner
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    /// Wrapper on inner
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// ---------- Reader ----------
+
+/// Provide the data without its trailing listing
+pub struct PublicListingLayerReader<'a, R: Read + Seek> {
+    inner: Box<dyn 'a + LayerReader<'a, R>>,
+    /// Size of the data, without the listing. Only known after
+    /// initialization
+    data_size: u64,
+    /// Current position in the data
+    pos: u64,
+}
+
+impl<'a, R: 'a + Read + Seek> PublicListingLayerReader<'a, R> {
+    pub fn new(inner: Box<dyn 'a + LayerReader<'a, R>>) -> Self {
+        Self {
+            inner,
+            data_size: 0,
+            pos: 0,
+        }
+    }
+}
+
+impl<'a, R: 'a + Read + Seek> LayerReader<'a, R> for PublicListingLayerReader<'a, R> {
+    fn into_inner(self) -> Option<Box<dyn 'a + LayerReader<'a, R>>> {
+        Some(self.inner)
+    }
+
+    fn into_raw(self: Box<Self>) -> R {
+        self.inner.into_raw()
+    }
+
+    fn initialize(&mut self) -> Result<(), Error> {
+        // Recursive call
+        self.inner.initialize()?;
+
+        // Skip the footer: [listing][listing length]
+        let (_listing, data_size) = read_listing(&mut self.inner)?;
+        self.data_size = data_size;
+        self.pos = self.inner.seek(SeekFrom::Start(0))?;
+        Ok(())
+    }
+}
+
+impl<'a, R: 'a + Read + Seek> Seek for PublicListingLayerReader<'a, R> {
+    fn seek(&mut self, ask_pos: SeekFrom) -> io::Result<u64> {
+        self.pos = match ask_pos {
+            SeekFrom::Start(_) | SeekFrom::Current(_) => self.inner.seek(ask_pos)?,
+            SeekFrom::End(pos) => {
+                let end_pos = self.data_size as i64 + pos;
+                if end_pos < 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Invalid seek to a negative position",
+                    ));
+                }
+                self.inner.seek(SeekFrom::Start(end_pos as u64))?
+            }
+        };
+        Ok(self.pos)
+    }
+}
+
+impl<'a, R: 'a + Read + Seek> Read for PublicListingLayerReader<'a, R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        // Do not read the listing
+        let remaining = self.data_size.saturating_sub(self.pos);
+        let size = std::cmp::min(into.len() as u64, remaining) as usize;
+        let read = self.inner.read(&mut into[..size])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::layers::raw::{RawLayerReader, RawLayerWriter};
+    use std::io::Cursor;
+
+    static FAKE_FILE: [u8; 26] = *b"abcdefghijklmnopqrstuvwxyz";
+
+    #[test]
+    fn public_listing_layer() {
+        let mut listing_w = Box::new(PublicListingLayerWriter::new(Box::new(
+            RawLayerWriter::new(Vec::new()),
+        )));
+        let listing = listing_w.listing();
+        listing_w.write_all(&FAKE_FILE[..21]).unwrap();
+        listing_w.write_all(&FAKE_FILE[21..]).unwrap();
+        *listing.borrow_mut() = vec!["a".to_string(), "b".to_string()];
+        listing_w.finalize().unwrap();
+        let out = listing_w.into_raw();
+
+        assert_eq!(
+            read_public_listing(Cursor::new(out.as_slice())).unwrap(),
+            ["a", "b"]
+        );
+
+        let buf = Cursor::new(out.as_slice());
+        let mut listing_r = PublicListingLayerReader::new(Box::new(RawLayerReader::new(buf)));
+        listing_r.initialize().unwrap();
+        let mut output = Vec::new();
+        listing_r.read_to_end(&mut output).unwrap();
+        assert_eq!(output.as_slice(), &FAKE_FILE);
+
+        // Seek
+        assert_eq!(listing_r.seek(SeekFrom::End(-3)).unwrap(), 23);
+        let mut output = Vec::new();
+        listing_r.read_to_end(&mut output).unwrap();
+        assert_eq!(output.as_slice(), &FAKE_FILE[23..]);
+
+        // Truncated data
+        assert!(matches!(
+            read_public_listing(Cursor::new(&out[..out.len() - 1])),
+            Err(Error::DeserializationError)
+        ));
+    }
+}
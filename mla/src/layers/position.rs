@@ -1,6 +1,7 @@
 use std::io;
 use std::io::Write;
 
+use crate::config::CompressionAlgorithm;
 use crate::layers::traits::LayerWriter;
 use crate::Error;
 
@@ -28,6 +29,11 @@ impl<'a, W: 'a + Write> PositionLayerWriter<'a, W> {
         self.pos = 0;
         before
     }
+
+    /// Count from `pos`, for instance to continue data already written
+    pub fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
 }
 
 impl<'a, W: 'a + Write> LayerWriter<'a, W> for PositionLayerWriter<'a, W> {
@@ -45,6 +51,14 @@ impl<'a, W: 'a + Write> LayerWriter<'a, W> for PositionLayerWriter<'a, W> {
         // Recursive call
         self.inner.finalize()
     }
+
+    fn set_compression_bypass(&mut self, bypass: bool) {
+        self.inner.set_compression_bypass(bypass)
+    }
+
+    fn set_compression_level(&mut self, algorithm: Option<CompressionAlgorithm>) {
+        self.inner.set_compression_level(algorithm)
+    }
 }
 
 impl<'a, W: 'a + Write> Write for PositionLayerWriter<'a, W> {
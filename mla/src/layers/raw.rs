@@ -1,5 +1,7 @@
+use std::cell::Cell;
 use std::io;
 use std::io::{Read, Seek, SeekFrom, Write};
+use std::rc::Rc;
 
 use crate::layers::traits::{LayerFailSafeReader, LayerReader, LayerWriter};
 use crate::Error;
@@ -9,11 +11,22 @@ use crate::Error;
 /// Dummy layer, standing for the last layer (wrapping I/O)
 pub struct RawLayerWriter<W: Write> {
     inner: W,
+    /// Number of bytes written to `inner` so far
+    written: Rc<Cell<u64>>,
 }
 
 impl<W: Write> RawLayerWriter<W> {
     pub fn new(inner: W) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            written: Rc::new(Cell::new(0)),
+        }
+    }
+
+    /// Counter of the bytes written to the inner writer, still available
+    /// once this layer is boxed under other layers
+    pub fn written_counter(&self) -> Rc<Cell<u64>> {
+        self.written.clone()
     }
 }
 
@@ -27,7 +40,9 @@ impl<'a, W: Write> LayerWriter<'a, W> for RawLayerWriter<W> {
     }
 
     fn finalize(&mut self) -> Result<(), Error> {
-        // No recursive call, this is the last layer
+        // No recursive call, this is the last layer. Flush the destination,
+        // so that its pending errors are reported
+        self.inner.flush()?;
         Ok(())
     }
 }
@@ -35,7 +50,9 @@ impl<'a, W: Write> LayerWriter<'a, W> for RawLayerWriter<W> {
 impl<W: Write> Write for RawLayerWriter<W> {
     /// Wrapper on inner
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.inner.write(buf)
+        let size = self.inner.write(buf)?;
+        self.written.set(self.written.get() + size as u64);
+        Ok(size)
     }
 
     /// Wrapper on inner
@@ -88,7 +105,10 @@ impl<R: Read + Seek> Seek for RawLayerReader<R> {
     fn seek(&mut self, ask_pos: SeekFrom) -> io::Result<u64> {
         match ask_pos {
             SeekFrom::Start(pos) => {
-                self.inner.seek(SeekFrom::Start(self.offset_pos + pos))?;
+                let inner_pos = self.offset_pos.checked_add(pos).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek past u64::MAX")
+                })?;
+                self.inner.seek(SeekFrom::Start(inner_pos))?;
                 Ok(pos)
             }
             SeekFrom::Current(_pos) => {
@@ -217,6 +237,9 @@ mod tests {
 
         assert_eq!(raw_r.seek(SeekFrom::Start(0)).unwrap(), 0);
         assert_eq!(raw_r.seek(SeekFrom::Start(3)).unwrap(), 3);
+        // Past u64::MAX, once the offset is added
+        assert!(raw_r.seek(SeekFrom::Start(u64::MAX)).is_err());
+        assert_eq!(raw_r.seek(SeekFrom::Start(3)).unwrap(), 3);
         let mut buf = Vec::new();
         raw_r.read_to_end(&mut buf).unwrap();
         assert_eq!(buf.as_slice(), b"def");
@@ -0,0 +1,429 @@
+use crate::crypto::hash::HashWrapperReader;
+use crate::layers::traits::{LayerFailSafeReader, LayerReader, LayerWriter};
+use crate::Error;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+use crate::errors::ConfigError;
+use crate::Layers;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey, SIGNATURE_LENGTH};
+use sha2::{Digest, Sha256};
+use zeroize::ZeroizeOnDrop;
+
+// ---------- Config ----------
+
+#[derive(Default)]
+pub struct SignatureConfig {
+    /// Key used to sign the archive
+    signing_key: Option<SigningKey>,
+}
+
+/// `SigningKey` zeroizes itself on drop
+impl ZeroizeOnDrop for SignatureConfig {}
+
+impl SignatureConfig {
+    /// Consistency check
+    pub fn check(&self) -> Result<(), ConfigError> {
+        if self.signing_key.is_none() {
+            Err(ConfigError::SigningKeyIsMissing)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl ArchiveWriterConfig {
+    /// Sign the archive with `signing_key`, and enable the corresponding layer
+    ///
+    /// The signature covers the whole archive, header included
+    pub fn sign_with(&mut self, signing_key: &SigningKey) -> &mut ArchiveWriterConfig {
+        self.sign.signing_key = Some(signing_key.clone());
+        self.enable_layer(Layers::SIGN)
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct SignatureReaderConfig {
+    /// Key used to verify the archive signature, if any
+    verifying_key: Option<VerifyingKey>,
+}
+
+impl ArchiveReaderConfig {
+    /// Verify the archive signature with `verifying_key` on opening
+    ///
+    /// Opening an archive which is not signed, or not signed by the
+    /// corresponding key, then fails
+    pub fn verify_with(&mut self, verifying_key: &VerifyingKey) -> &mut ArchiveReaderConfig {
+        self.sign.verifying_key = Some(*verifying_key);
+        self
+    }
+
+    /// Key to verify the archive signature with, if any
+    pub(crate) fn verifying_key(&self) -> Option<&VerifyingKey> {
+        self.sign.verifying_key.as_ref()
+    }
+
+    /// Remove the key to verify the archive signature with, if any, so that
+    /// the signature can be checked by the caller instead
+    #[cfg(feature = "async")]
+    pub(crate) fn take_verifying_key(&mut self) -> Option<VerifyingKey> {
+        self.sign.verifying_key.take()
+    }
+}
+
+/// Check the signature of the data in `src`, starting from its current
+/// position (ie. just after the archive header) and ending with the
+/// signature.
+///
+/// `header_hash` is the hash of the archive header, which is signed too
+pub(crate) fn check_signature<R: Read + Seek>(
+    mut src: R,
+    verifying_key: &VerifyingKey,
+    mut header_hash: Sha256,
+) -> Result<(), Error> {
+    let start = src.stream_position()?;
+    let size = src.seek(SeekFrom::End(0))? - start;
+    if size < SIGNATURE_LENGTH as u64 {
+        return Err(Error::SignatureVerificationFailed);
+    }
+
+    // Hash the whole data, then get the signature
+    src.seek(SeekFrom::Start(start))?;
+    io::copy(
+        &mut HashWrapperReader::new(
+            (&mut src).take(size - SIGNATURE_LENGTH as u64),
+            &mut header_hash,
+        ),
+        &mut io::sink(),
+    )?;
+    let mut signature = [0u8; SIGNATURE_LENGTH];
+    src.read_exact(&mut signature)?;
+
+    verify_signature(verifying_key, header_hash, &signature)
+}
+
+/// Check `signature` against `hash`, the hash of everything signed
+pub(crate) fn verify_signature(
+    verifying_key: &VerifyingKey,
+    hash: Sha256,
+    signature: &[u8; SIGNATURE_LENGTH],
+) -> Result<(), Error> {
+    verifying_key
+        .verify(&hash.finalize(), &Signature::from_bytes(signature))
+        .or(Err(Error::SignatureVerificationFailed))
+}
+
+// ---------- Writer ----------
+
+/// Compute the signature of everything written, and append it at the end of
+/// the data:
+/// [data][signature of SHA-256(header . data)]
+pub struct SignatureLayerWriter<'a, W: 'a + Write> {
+    inner: Box<dyn 'a + LayerWriter<'a, W>>,
+    signing_key: SigningKey,
+    /// Hash of the data signed so far
+    hash: Sha256,
+}
+
+impl<'a, W: 'a + Write> SignatureLayerWriter<'a, W> {
+    /// `header` is the already written archive header, to be signed too
+    pub fn new(
+        inner: Box<dyn 'a + LayerWriter<'a, W>>,
+        config: &SignatureConfig,
+        header: &[u8],
+    ) -> Result<Self, Error> {
+        match &config.signing_key {
+            Some(signing_key) => {
+                let mut hash = Sha256::new();
+                hash.update(header);
+                Ok(Self {
+                    inner,
+                    signing_key: signing_key.clone(),
+                    hash,
+                })
+            }
+            None => Err(ConfigError::SigningKeyIsMissing.into()),
+        }
+    }
+}
+
+impl<'a, W: 'a + Write> LayerWriter<'a, W> for SignatureLayerWriter<'a, W> {
+    fn into_inner(self) -> Option<Box<dyn 'a + LayerWriter<'a, W>>> {
+        Some(self.inner)
+    }
+
+    fn into_raw(self: Box<Self>) -> W {
+        self.inner.into_raw()
+    }
+
+    fn finalize(&mut self) -> Result<(), Error> {
+        // Append the signature
+        let signature = self.signing_key.sign(&self.hash.clone().finalize());
+        self.inner.write_all(&signature.to_bytes())?;
+
+        // Recursive call
+        self.inner.finalize()
+    }
+}
+
+impl<'a, W: Write> Write for SignatureLayerWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hash.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// ---------- Reader ----------
+
+/// Provide the data without its trailing signature. The signature itself is
+/// checked with `check_signature`
+pub struct SignatureLayerReader<'a, R: Read + Seek> {
+    inner: Box<dyn 'a + LayerReader<'a, R>>,
+    /// Size of the data, without the signature. Only known after
+    /// initialization
+    data_size: u64,
+    /// Current position in the data
+    pos: u64,
+}
+
+impl<'a, R: 'a + Read + Seek> SignatureLayerReader<'a, R> {
+    pub fn new(inner: Box<dyn 'a + LayerReader<'a, R>>) -> Self {
+        Self {
+            inner,
+            data_size: 0,
+            pos: 0,
+        }
+    }
+}
+
+impl<'a, R: 'a + Read + Seek> LayerReader<'a, R> for SignatureLayerReader<'a, R> {
+    fn into_inner(self) -> Option<Box<dyn 'a + LayerReader<'a, R>>> {
+        Some(self.inner)
+    }
+
+    fn into_raw(self: Box<Self>) -> R {
+        self.inner.into_raw()
+    }
+
+    fn initialize(&mut self) -> Result<(), Error> {
+        // Recursive call
+        self.inner.initialize()?;
+
+        // Skip the footer: [signature]
+        let size = self.inner.seek(SeekFrom::End(0))?;
+        if size < SIGNATURE_LENGTH as u64 {
+            return Err(Error::SignatureVerificationFailed);
+        }
+        self.data_size = size - SIGNATURE_LENGTH as u64;
+        self.pos = self.inner.seek(SeekFrom::Start(0))?;
+        Ok(())
+    }
+}
+
+impl<'a, R: 'a + Read + Seek> Seek for SignatureLayerReader<'a, R> {
+    fn seek(&mut self, ask_pos: SeekFrom) -> io::Result<u64> {
+        self.pos = match ask_pos {
+            SeekFrom::Start(_) | SeekFrom::Current(_) => self.inner.seek(ask_pos)?,
+            SeekFrom::End(pos) => {
+                let end_pos = self.data_size as i64 + pos;
+                if end_pos < 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Invalid seek to a negative position",
+                    ));
+                }
+                self.inner.seek(SeekFrom::Start(end_pos as u64))?
+            }
+        };
+        Ok(self.pos)
+    }
+}
+
+impl<'a, R: 'a + Read + Seek> Read for SignatureLayerReader<'a, R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        // Do not read the signature
+        let remaining = self.data_size.saturating_sub(self.pos);
+        let size = std::cmp::min(into.len() as u64, remaining) as usize;
+        let read = self.inner.read(&mut into[..size])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+// ---------- FailSafeReader ----------
+
+/// Provide the data without its trailing signature. As the end of the data is
+/// unknown, the last `SIGNATURE_LENGTH` bytes read are always kept aside
+pub struct SignatureLayerFailSafeReader<'a, R: Read> {
+    inner: Box<dyn 'a + LayerFailSafeReader<'a, R>>,
+    /// Data read but not yet provided, at least `SIGNATURE_LENGTH` bytes if
+    /// the data is long enough
+    pending: Vec<u8>,
+}
+
+impl<'a, R: 'a + Read> SignatureLayerFailSafeReader<'a, R> {
+    pub fn new(inner: Box<dyn 'a + LayerFailSafeReader<'a, R>>) -> Self {
+        Self {
+            inner,
+            pending: Vec::with_capacity(SIGNATURE_LENGTH * 2),
+        }
+    }
+}
+
+impl<'a, R: 'a + Read> LayerFailSafeReader<'a, R> for SignatureLayerFailSafeReader<'a, R> {
+    fn into_inner(self) -> Option<Box<dyn 'a + LayerFailSafeReader<'a, R>>> {
+        Some(self.inner)
+    }
+
+    fn into_raw(self: Box<Self>) -> R {
+        self.inner.into_raw()
+    }
+}
+
+impl<'a, R: 'a + Read> Read for SignatureLayerFailSafeReader<'a, R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        if into.is_empty() {
+            return Ok(0);
+        }
+        // Ensure there is at least one byte more than a signature, unless the
+        // end of the data have been reached
+        while self.pending.len() <= SIGNATURE_LENGTH {
+            let mut buf = vec![0u8; std::cmp::max(into.len(), SIGNATURE_LENGTH)];
+            let read = self.inner.read(&mut buf)?;
+            if read == 0 {
+                // Only the signature might remain
+                return Ok(0);
+            }
+            self.pending.extend_from_slice(&buf[..read]);
+        }
+
+        let size = std::cmp::min(into.len(), self.pending.len() - SIGNATURE_LENGTH);
+        into[..size].copy_from_slice(&self.pending[..size]);
+        self.pending.drain(..size);
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::layers::raw::{RawLayerFailSafeReader, RawLayerReader, RawLayerWriter};
+    use std::io::Cursor;
+
+    static FAKE_HEADER: &[u8] = b"header";
+    static FAKE_FILE: [u8; 26] = *b"abcdefghijklmnopqrstuvwxyz";
+
+    fn signing_key(key: u8) -> SigningKey {
+        SigningKey::from_bytes(&[key; 32])
+    }
+
+    fn header_hash() -> Sha256 {
+        let mut hash = Sha256::new();
+        hash.update(FAKE_HEADER);
+        hash
+    }
+
+    fn sign_write() -> Vec<u8> {
+        let config = SignatureConfig {
+            signing_key: Some(signing_key(1)),
+        };
+        let mut sign_w = Box::new(
+            SignatureLayerWriter::new(
+                Box::new(RawLayerWriter::new(Vec::new())),
+                &config,
+                FAKE_HEADER,
+            )
+            .unwrap(),
+        );
+        sign_w.write_all(&FAKE_FILE[..21]).unwrap();
+        sign_w.write_all(&FAKE_FILE[21..]).unwrap();
+        sign_w.finalize().unwrap();
+
+        let out = sign_w.into_raw();
+        assert_eq!(out.len(), FAKE_FILE.len() + SIGNATURE_LENGTH);
+        out
+    }
+
+    #[test]
+    fn sign_layer() {
+        let out = sign_write();
+
+        let buf = Cursor::new(out.as_slice());
+        let mut sign_r = SignatureLayerReader::new(Box::new(RawLayerReader::new(buf)));
+        sign_r.initialize().unwrap();
+        let mut output = Vec::new();
+        sign_r.read_to_end(&mut output).unwrap();
+        assert_eq!(output.as_slice(), &FAKE_FILE);
+
+        // Seek
+        assert_eq!(sign_r.seek(SeekFrom::End(-3)).unwrap(), 23);
+        let mut output = Vec::new();
+        sign_r.read_to_end(&mut output).unwrap();
+        assert_eq!(output.as_slice(), &FAKE_FILE[23..]);
+        sign_r.seek(SeekFrom::Start(2)).unwrap();
+        sign_r.seek(SeekFrom::Current(1)).unwrap();
+        let mut output = Vec::new();
+        sign_r.read_to_end(&mut output).unwrap();
+        assert_eq!(output.as_slice(), &FAKE_FILE[3..]);
+    }
+
+    #[test]
+    fn sign_layer_check() {
+        let mut out = sign_write();
+        let verifying_key = signing_key(1).verifying_key();
+
+        check_signature(Cursor::new(&out), &verifying_key, header_hash()).unwrap();
+
+        // Correct key, but wrong header
+        assert!(matches!(
+            check_signature(Cursor::new(&out), &verifying_key, Sha256::new()),
+            Err(Error::SignatureVerificationFailed)
+        ));
+
+        // Wrong key
+        assert!(matches!(
+            check_signature(
+                Cursor::new(&out),
+                &signing_key(2).verifying_key(),
+                header_hash()
+            ),
+            Err(Error::SignatureVerificationFailed)
+        ));
+
+        // Truncated data
+        assert!(matches!(
+            check_signature(
+                Cursor::new(&out[..out.len() - 1]),
+                &verifying_key,
+                header_hash()
+            ),
+            Err(Error::SignatureVerificationFailed)
+        ));
+
+        // Modified data
+        out[3] ^= 1;
+        assert!(matches!(
+            check_signature(Cursor::new(&out), &verifying_key, header_hash()),
+            Err(Error::SignatureVerificationFailed)
+        ));
+    }
+
+    #[test]
+    fn sign_failsafe_layer() {
+        let out = sign_write();
+
+        let mut sign_r = SignatureLayerFailSafeReader::new(Box::new(RawLayerFailSafeReader::new(
+            out.as_slice(),
+        )));
+        let mut output = Vec::new();
+        sign_r.read_to_end(&mut output).unwrap();
+        assert_eq!(output.as_slice(), &FAKE_FILE);
+    }
+}
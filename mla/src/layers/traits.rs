@@ -1,3 +1,4 @@
+use crate::config::CompressionAlgorithm;
 use crate::Error;
 use std::io::{Read, Seek, Write};
 
@@ -16,6 +17,20 @@ pub trait LayerWriter<'a, W: Write>: Write {
     /// This method is responsible of recursively calling (postfix) `finalize`
     /// on inner layer if any
     fn finalize(&mut self) -> Result<(), Error>;
+
+    /// Hint that the data written next is not worth compressing
+    ///
+    /// Only meaningful for the compression layer; layers on top of it must
+    /// forward the hint
+    fn set_compression_bypass(&mut self, _bypass: bool) {}
+
+    /// Hint that the data written next must be compressed with `algorithm`,
+    /// the configured one with another level, or with the configured one if
+    /// `None`
+    ///
+    /// Only meaningful for the compression layer; layers on top of it must
+    /// forward the hint
+    fn set_compression_level(&mut self, _algorithm: Option<CompressionAlgorithm>) {}
 }
 
 /// Trait to be implemented by layer readers
@@ -1,7 +1,14 @@
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::fs::File;
+use std::hash::BuildHasher;
 use std::io;
 use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 #[macro_use]
 extern crate bitflags;
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
@@ -9,36 +16,153 @@ use serde::{Deserialize, Serialize};
 
 mod layers;
 use crate::layers::compress::{
-    CompressionLayerFailSafeReader, CompressionLayerReader, CompressionLayerWriter,
+    decompress_precompressed, max_compressed_size, CompressionLayerFailSafeReader,
+    CompressionLayerReader, CompressionLayerWriter, ENTROPY_SAMPLE_SIZE,
 };
 use crate::layers::encrypt::{
-    EncryptionLayerFailSafeReader, EncryptionLayerReader, EncryptionLayerWriter,
+    max_encrypted_size, EncryptionLayerFailSafeReader, EncryptionLayerReader,
+    EncryptionLayerStreamReader, EncryptionLayerWriter, EncryptionReaderConfig,
+    CSPRNG_STREAM_ARCHIVE_ID, CSPRNG_STREAM_FILES,
+};
+use crate::layers::listing::{
+    read_public_listing, PublicListingLayerReader, PublicListingLayerWriter,
 };
 use crate::layers::position::PositionLayerWriter;
 use crate::layers::raw::{RawLayerFailSafeReader, RawLayerReader, RawLayerWriter};
+use crate::layers::sign::{
+    check_signature, SignatureLayerFailSafeReader, SignatureLayerReader, SignatureLayerWriter,
+};
 use crate::layers::traits::{LayerFailSafeReader, LayerReader, LayerWriter};
 pub mod errors;
 use crate::errors::{Error, FailSafeReadError};
 
 pub mod config;
-use crate::config::{ArchivePersistentConfig, ArchiveReaderConfig, ArchiveWriterConfig};
+use crate::config::{
+    ArchivePersistentConfig, ArchivePersistentConfigV1, ArchivePersistentConfigV2,
+    ArchivePersistentConfigV3, ArchiveReaderConfig, ArchiveWriterConfig, CompressionAlgorithm,
+    CompressionHint, CompressionLevel, DuplicatePolicy, FileLayout, MetadataEncoding,
+    MAX_COMMENT_LENGTH,
+};
 
 #[doc(hidden)]
 pub mod crypto;
-use crate::crypto::hash::{HashWrapperReader, Sha256Hash};
+use crate::crypto::aesgcm::TAG_LENGTH;
+use crate::crypto::file::{
+    FileDecryptor, FileEncryptReader, FileEncryptor, FileRecipientsPersistent,
+};
+use crate::crypto::hash::{
+    Checksum, ChecksumAlgorithm, ChecksumHasher, ChecksumWrapperReader, HashWrapperReader,
+    Sha256Hash,
+};
+use ed25519_dalek::SIGNATURE_LENGTH;
+use rand::{Rng, RngCore};
+use rand_chacha::ChaChaRng;
 use sha2::{Digest, Sha256};
 use x25519_dalek::PublicKey;
 
+pub mod metadata;
+use crate::metadata::FileMetadata;
+
 pub mod helpers;
+use crate::helpers::StreamWriter;
+
+pub mod progress;
+
+pub mod audit;
+use crate::audit::AuditEventType;
+
+pub mod tree;
+
+pub mod extract;
+
+pub mod grep;
+
+pub mod merge;
+
+pub mod stats;
+use crate::progress::{NoopProgressReporter, ReadProgressReporter};
+use crate::stats::WriteStatistics;
+
+pub mod split;
+
+pub mod seekable;
+
+pub mod builder;
+
+pub mod bloom;
+
+pub mod concurrent;
+
+pub mod diff;
+
+pub mod merkle;
+
+pub mod union;
+
+mod recover;
+
+mod chunking;
+use crate::chunking::Chunker;
+
+#[cfg(feature = "cbor")]
+mod cbor;
+
+pub mod checkpoint;
+
+#[cfg(feature = "async")]
+pub mod async_io;
+#[cfg(feature = "futures-io")]
+pub mod futures_io;
+
+#[cfg(feature = "tar")]
+pub mod tar_export;
+#[cfg(feature = "tar")]
+pub mod tar_import;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "mmap")]
+pub mod mmap;
+
+#[cfg(feature = "keys")]
+pub mod keys;
 
 // -------- Constants --------
 
 const MLA_MAGIC: &[u8; 3] = b"MLA";
-const MLA_FORMAT_VERSION: u32 = 1;
+const MLA_FORMAT_VERSION: u32 = 7;
+/// Minor format version, for evolutions readable by any reader of the same
+/// major version (see `FeatureFlags`)
+const MLA_FORMAT_MINOR_VERSION: u32 = 0;
+/// Size of the archive identifier, in the header since format v6
+pub const ARCHIVE_ID_SIZE: usize = 16;
 /// Maximum number of UTF-8 characters supported in each file's "name" (which is free
 /// to be used as a filename, an absolute path, or... ?). 32KiB was chosen because it
 /// supports any path a Windows NT, Linux, FreeBSD, OpenBSD, or NetBSD kernel supports.
 const FILENAME_MAX_SIZE: u64 = 65536;
+/// Maximum size of a serialized `Checksum`, to avoid allocating too much on
+/// corrupted data
+const CHECKSUM_MAX_SIZE: u64 = 1024;
+/// Maximum size of a serialized `FileMetadata`, to avoid allocating too much
+/// on corrupted data
+const METADATA_MAX_SIZE: u64 = 1024;
+/// Maximum size of a serialized `UserMetadata` block content (key and
+/// value), to avoid allocating too much on corrupted data
+const USER_METADATA_MAX_SIZE: u64 = 64 * 1024;
+/// Maximum size of a serialized `FileRecipientsPersistent`, to avoid
+/// allocating too much on corrupted data
+const RECIPIENTS_MAX_SIZE: u64 = 1024 * 1024;
+/// Maximum size of a `FilePrecompressedContent` block content, compressed and
+/// decompressed, as it is loaded in memory to be read
+const PRECOMPRESSED_MAX_SIZE: u64 = 16 * 1024 * 1024;
+/// Maximum size of a serialized `DirectoryEntry`
+const DIRECTORY_MAX_SIZE: u64 = FILENAME_MAX_SIZE + METADATA_MAX_SIZE;
+/// Maximum size of a serialized `Symlink`
+const SYMLINK_MAX_SIZE: u64 = 2 * FILENAME_MAX_SIZE + METADATA_MAX_SIZE;
+/// Size of the reads of file contents cut in blocks, see
+/// `ArchiveWriterConfig::block_boundary`
+const CHUNKING_READ_SIZE: usize = 64 * 1024;
 /// Maximum allowed object size (in bytes) to deserialize in-memory, to avoid DoS on
 /// malformed files
 pub(crate) const BINCODE_MAX_DESERIALIZE: u64 = 512 * 1024 * 1024;
@@ -49,12 +173,16 @@ bitflags! {
     /// [File to blocks decomposition]
     /// [Compression (COMPRESS)]
     /// [Encryption (ENCRYPT)]
+    /// [Public listing of the file names (PUBLIC_LISTING)]
+    /// [Signature (SIGN)]
     /// [Raw File I/O]
     /// ```
     #[derive(Serialize, Deserialize)]
     pub struct Layers: u8 {
         const ENCRYPT = 0b0000_0001;
         const COMPRESS = 0b0000_0010;
+        const SIGN = 0b0000_0100;
+        const PUBLIC_LISTING = 0b0000_1000;
         /// Recommended layering
         const DEFAULT = Self::ENCRYPT.bits | Self::COMPRESS.bits;
         /// No additional layer (ie, for debugging purpose)
@@ -69,14 +197,153 @@ impl std::default::Default for Layers {
     }
 }
 
-pub type ArchiveFileID = u64;
+bitflags! {
+    /// Features used by an archive, stored in its footer since format v4.
+    ///
+    /// A reader rejects an archive using a feature it does not implement
+    /// (`Error::UnsupportedFeature`), instead of misreading it. Archives in
+    /// older formats do not declare their features
+    pub struct FeatureFlags: u32 {
+        /// At least one `FileMetadata` block
+        const HAS_FILE_METADATA = 0b0000_0001;
+        /// At least one `FileChecksum` block
+        const HAS_CHECKSUMS = 0b0000_0010;
+        /// At least one `Symlink` block
+        const HAS_SYMLINKS = 0b0000_0100;
+        /// At least one `DirectoryEntry` block
+        const HAS_DIRECTORIES = 0b0000_1000;
+        /// At least one `FileSparseHole` block
+        const HAS_SPARSE_HOLES = 0b0001_0000;
+        /// At least one `Dedup` block
+        const HAS_DEDUP = 0b0010_0000;
+        /// At least one file encrypted for its own recipients
+        /// (`FileRecipients` and `FileTag` blocks)
+        const HAS_FILE_RECIPIENTS = 0b0100_0000;
+        /// At least one `FilePrecompressedContent` block
+        const HAS_PRECOMPRESSED = 0b1000_0000;
+        /// Blocks describing the entries are CBOR-encoded (`CborEncoded`
+        /// blocks), see `MetadataEncoding::Cbor`
+        const HAS_CBOR_BLOCKS = 0b1_0000_0000;
+    }
+}
+
+/// Features implemented by this reader
+const SUPPORTED_FEATURES: FeatureFlags = FeatureFlags::all();
+
+/// Check that `features`, used by an archive, are all in `supported`
+fn check_features(features: FeatureFlags, supported: FeatureFlags) -> Result<(), Error> {
+    let unsupported = features - supported;
+    if unsupported.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::UnsupportedFeature(unsupported))
+    }
+}
+
+/// Version of the format of an archive, as found in its header
+///
+/// A new major version can't be read by older readers. Minor versions, since
+/// format v4, only add features declared in `FeatureFlags`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl FormatVersion {
+    /// Version of the archives written by this implementation
+    pub const CURRENT: FormatVersion = FormatVersion {
+        major: MLA_FORMAT_VERSION,
+        minor: MLA_FORMAT_MINOR_VERSION,
+    };
+
+    /// Whether the footer starts with the `FeatureFlags`
+    fn has_features(&self) -> bool {
+        self.major >= 4
+    }
+
+    /// Whether the header has a comment
+    fn has_comment(&self) -> bool {
+        self.major >= 5
+    }
+
+    /// Whether the header has an archive identifier
+    fn has_archive_id(&self) -> bool {
+        self.major >= 6
+    }
+
+    /// Whether the header encryption configuration can have key shares, see
+    /// `ArchiveWriterConfig::set_threshold`
+    fn has_threshold(&self) -> bool {
+        self.major >= 7
+    }
+}
+
+/// Identifier of a file inside an archive, as used in the blocks
+///
+/// On disk, it is stored as a little-endian `u64`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ArchiveFileID(pub u64);
+
+impl ArchiveFileID {
+    /// Greatest possible identifier
+    pub const MAX: ArchiveFileID = ArchiveFileID(u64::MAX);
+
+    /// Identifier following this one, `None` on overflow
+    fn checked_next(self) -> Option<ArchiveFileID> {
+        self.0.checked_add(1).map(ArchiveFileID)
+    }
+}
+
+impl fmt::Display for ArchiveFileID {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FileID({})", self.0)
+    }
+}
+
+impl fmt::Debug for ArchiveFileID {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<u64> for ArchiveFileID {
+    fn from(id: u64) -> Self {
+        ArchiveFileID(id)
+    }
+}
+
+impl From<ArchiveFileID> for u64 {
+    fn from(id: ArchiveFileID) -> Self {
+        id.0
+    }
+}
 
 // -------- MLA Format Header --------
 
 struct ArchiveHeader {
+    format: FormatVersion,
+    /// Unencrypted comment, since format v5
+    comment: Option<String>,
+    /// Random identifier of the archive, since format v6
+    archive_id: Option<[u8; ARCHIVE_ID_SIZE]>,
     config: ArchivePersistentConfig,
 }
 
+/// Read a comment, as a length (u32) then UTF-8 text, from `src`. An empty
+/// comment is returned as None
+fn read_comment<T: Read>(src: &mut T) -> Result<Option<String>, Error> {
+    let length = src.read_u32::<LittleEndian>()? as usize;
+    if length > MAX_COMMENT_LENGTH {
+        return Err(Error::DeserializationError);
+    }
+    let mut comment = vec![0u8; length];
+    src.read_exact(&mut comment)?;
+    let comment = String::from_utf8(comment).or(Err(Error::DeserializationError))?;
+    Ok(Some(comment).filter(|comment| !comment.is_empty()))
+}
+
 impl ArchiveHeader {
     fn from<T: Read>(src: &mut T) -> Result<Self, Error> {
         let mut buf = vec![00u8; MLA_MAGIC.len()];
@@ -85,29 +352,114 @@ impl ArchiveHeader {
             return Err(Error::WrongMagic);
         }
         let version = src.read_u32::<LittleEndian>()?;
-        if version != MLA_FORMAT_VERSION {
-            return Err(Error::UnsupportedVersion);
-        }
-        let config: ArchivePersistentConfig = match bincode::config()
-            .limit(BINCODE_MAX_DESERIALIZE)
-            .deserialize_from(src)
-        {
-            Ok(config) => config,
+        let mut minor = 0;
+        let mut comment = None;
+        let mut archive_id = None;
+        let mut bincode_config = bincode::config();
+        bincode_config.limit(BINCODE_MAX_DESERIALIZE);
+        let config: ArchivePersistentConfig = match version {
+            // Format v1 is still supported for reading
+            1 => match bincode_config.deserialize_from::<_, ArchivePersistentConfigV1>(src) {
+                Ok(config) => config.into(),
+                _ => {
+                    return Err(Error::DeserializationError);
+                }
+            },
+            // Format v2 is still supported for reading
+            2 => match bincode_config.deserialize_from::<_, ArchivePersistentConfigV2>(src) {
+                Ok(config) => config.into(),
+                _ => {
+                    return Err(Error::DeserializationError);
+                }
+            },
+            // Format v3 only differs by its header, without minor version,
+            // and its footer, without features
+            3 => match bincode_config.deserialize_from::<_, ArchivePersistentConfigV3>(src) {
+                Ok(config) => config.into(),
+                _ => {
+                    return Err(Error::DeserializationError);
+                }
+            },
+            // Formats v4 to v6 only differ by their header, without comment
+            // (v4), archive identifier (v4, v5) and key shares (v4 to v6)
+            4 | 5 | 6 | MLA_FORMAT_VERSION => {
+                // Any minor version can be read
+                minor = src.read_u32::<LittleEndian>()?;
+                if version >= 5 {
+                    comment = read_comment(src)?;
+                }
+                if version >= 6 {
+                    let mut id = [0u8; ARCHIVE_ID_SIZE];
+                    src.read_exact(&mut id)?;
+                    archive_id = Some(id);
+                }
+                let config = if version >= 7 {
+                    bincode_config.deserialize_from(src)
+                } else {
+                    bincode_config
+                        .deserialize_from::<_, ArchivePersistentConfigV3>(src)
+                        .map(ArchivePersistentConfig::from)
+                };
+                match config {
+                    Ok(config) => config,
+                    _ => {
+                        return Err(Error::DeserializationError);
+                    }
+                }
+            }
             _ => {
-                return Err(Error::DeserializationError);
+                return Err(Error::UnsupportedVersion);
             }
         };
-        Ok(ArchiveHeader { config })
+        Ok(ArchiveHeader {
+            format: FormatVersion {
+                major: version,
+                minor,
+            },
+            comment,
+            archive_id,
+            config,
+        })
     }
 
+    /// Dump the header. Only format v3 to v6 and the current one can be
+    /// written; a comment requires v5 or later, an archive identifier v6 or
+    /// later, key shares the current one
     fn dump<T: Write>(&self, dest: &mut T) -> Result<(), Error> {
-        dest.write_all(MLA_MAGIC)?;
-        dest.write_u32::<LittleEndian>(MLA_FORMAT_VERSION)?;
-        if bincode::config()
-            .limit(BINCODE_MAX_DESERIALIZE)
-            .serialize_into(dest, &self.config)
-            .is_err()
+        if (self.comment.is_some() && !self.format.has_comment())
+            || (self.archive_id.is_some() && !self.format.has_archive_id())
         {
+            return Err(Error::UnsupportedVersion);
+        }
+        dest.write_all(MLA_MAGIC)?;
+        match self.format.major {
+            3 => dest.write_u32::<LittleEndian>(3)?,
+            4 | 5 | 6 | MLA_FORMAT_VERSION => {
+                dest.write_u32::<LittleEndian>(self.format.major)?;
+                dest.write_u32::<LittleEndian>(self.format.minor)?;
+            }
+            _ => return Err(Error::UnsupportedVersion),
+        }
+        if self.format.has_comment() {
+            let comment = self.comment.as_deref().unwrap_or_default().as_bytes();
+            if comment.len() > MAX_COMMENT_LENGTH {
+                return Err(Error::SerializationError);
+            }
+            dest.write_u32::<LittleEndian>(comment.len() as u32)?;
+            dest.write_all(comment)?;
+        }
+        if self.format.has_archive_id() {
+            dest.write_all(&self.archive_id.unwrap_or_default())?;
+        }
+        let mut bincode_config = bincode::config();
+        bincode_config.limit(BINCODE_MAX_DESERIALIZE);
+        let serialized = if self.format.has_threshold() {
+            bincode_config.serialize_into(dest, &self.config)
+        } else {
+            let config = self.config.to_v3().ok_or(Error::UnsupportedVersion)?;
+            bincode_config.serialize_into(dest, &config)
+        };
+        if serialized.is_err() {
             return Err(Error::SerializationError);
         }
         Ok(())
@@ -117,27 +469,49 @@ impl ArchiveHeader {
 // -------- MLA Format Footer --------
 
 struct ArchiveFooter {
+    /// Features used by the archive (empty before format v4)
+    features: FeatureFlags,
     /// Filename -> Corresponding FileInfo
     files_info: HashMap<String, FileInfo>,
+    /// Directory entries
+    directories: BTreeSet<String>,
+    /// Symbolic link name -> Corresponding target
+    symlinks: BTreeMap<String, String>,
+    /// Comment, protected by the layers
+    comment: Option<String>,
+    /// Creation time, in seconds since the UNIX epoch
+    created_at: Option<u64>,
 }
 
 impl ArchiveFooter {
     /// Footer:
     /// ```ascii-art
-    /// [files_info][files_info length]
+    /// [features][files_info][directories (optional)][symlinks (optional)][comment (optional)][created_at (optional)][footer length]
     /// ```
+    ///
+    /// Optional parts are omitted if they, and the following ones, are empty.
+    /// Features are absent before format v4. An empty comment stands for no
+    /// comment, when followed by a creation time
 
     /// Performs zero-copy serialization of a footer
+    #[allow(clippy::too_many_arguments)]
     fn serialize_into<W: Write>(
         mut dest: W,
+        features: FeatureFlags,
         files_info: &HashMap<String, ArchiveFileID>,
         ids_info: &HashMap<ArchiveFileID, FileInfo>,
+        directories: &BTreeSet<String>,
+        symlinks: &BTreeMap<String, String>,
+        comment: Option<&str>,
+        created_at: Option<u64>,
     ) -> Result<(), Error> {
-        let mut serialization_len = 0;
+        dest.write_u32::<LittleEndian>(features.bits())?;
+        let mut serialization_len = 4;
 
         // Combine `files_info` and `ids_info` to ArchiveFooter.files_info,
-        // avoiding copies (only references)
-        let mut tmp: HashMap<&String, &FileInfo> = HashMap::new();
+        // avoiding copies (only references). Files are sorted by name, so that
+        // the footer doesn't depend on the order of a HashMap
+        let mut tmp: BTreeMap<&String, &FileInfo> = BTreeMap::new();
         for (k, i) in files_info {
             let v = ids_info.get(i).ok_or_else(|| {
                 Error::WrongWriterState(
@@ -161,31 +535,161 @@ impl ArchiveFooter {
             }
         };
 
+        // A creation time requires the previous parts
+        let comment = match (comment, created_at) {
+            (None, Some(_)) => Some(""),
+            (comment, _) => comment,
+        };
+        if !directories.is_empty() || !symlinks.is_empty() || comment.is_some() {
+            if bincode::config()
+                .limit(BINCODE_MAX_DESERIALIZE)
+                .serialize_into(&mut dest, directories)
+                .is_err()
+            {
+                return Err(Error::SerializationError);
+            };
+            serialization_len += match bincode::serialized_size(directories) {
+                Ok(size) => size,
+                Err(_) => {
+                    return Err(Error::SerializationError);
+                }
+            };
+        }
+        if !symlinks.is_empty() || comment.is_some() {
+            if bincode::config()
+                .limit(BINCODE_MAX_DESERIALIZE)
+                .serialize_into(&mut dest, symlinks)
+                .is_err()
+            {
+                return Err(Error::SerializationError);
+            };
+            serialization_len += match bincode::serialized_size(symlinks) {
+                Ok(size) => size,
+                Err(_) => {
+                    return Err(Error::SerializationError);
+                }
+            };
+        }
+        if let Some(comment) = comment {
+            if bincode::config()
+                .limit(BINCODE_MAX_DESERIALIZE)
+                .serialize_into(&mut dest, comment)
+                .is_err()
+            {
+                return Err(Error::SerializationError);
+            };
+            serialization_len += match bincode::serialized_size(comment) {
+                Ok(size) => size,
+                Err(_) => {
+                    return Err(Error::SerializationError);
+                }
+            };
+        }
+        if let Some(created_at) = created_at {
+            dest.write_u64::<LittleEndian>(created_at)?;
+            serialization_len += 8;
+        }
+
         // footer length
         dest.write_u32::<LittleEndian>(serialization_len as u32)?;
         Ok(())
     }
 
-    /// Parses and instantiates a footer from serialized data
-    fn deserialize_from<R: Read + Seek>(mut src: R) -> Result<ArchiveFooter, Error> {
+    /// Parses and instantiates a footer from serialized data, of an archive in
+    /// `format`
+    fn deserialize_from<R: Read + Seek>(
+        mut src: R,
+        format: FormatVersion,
+    ) -> Result<ArchiveFooter, Error> {
         // Read the footer length
         let pos = src.seek(SeekFrom::End(-4))?;
         let len = src.read_u32::<LittleEndian>()? as u64;
 
-        // Prepare for deserialization
-        src.seek(SeekFrom::Start(pos - len))?;
+        // Prepare for deserialization. An incoherent length is found in
+        // truncated or unfinished archives
+        let start = pos.checked_sub(len).ok_or(Error::DeserializationError)?;
+        src.seek(SeekFrom::Start(start))?;
+
+        let mut src = src.take(len);
+        let features = if format.has_features() {
+            // Unknown features are kept, to be reported
+            FeatureFlags {
+                bits: src.read_u32::<LittleEndian>()?,
+            }
+        } else {
+            FeatureFlags::empty()
+        };
 
         // Read files_info
         let files_info: HashMap<String, FileInfo> = match bincode::config()
             .limit(BINCODE_MAX_DESERIALIZE)
-            .deserialize_from(&mut src.take(len))
+            .deserialize_from(&mut src)
         {
             Ok(finfo) => finfo,
             _ => {
                 return Err(Error::DeserializationError);
             }
         };
-        Ok(ArchiveFooter { files_info })
+
+        // Read directories, if any
+        let directories = if src.limit() > 0 {
+            match bincode::config()
+                .limit(BINCODE_MAX_DESERIALIZE)
+                .deserialize_from(&mut src)
+            {
+                Ok(directories) => directories,
+                _ => {
+                    return Err(Error::DeserializationError);
+                }
+            }
+        } else {
+            BTreeSet::new()
+        };
+
+        // Read symlinks, if any
+        let symlinks = if src.limit() > 0 {
+            match bincode::config()
+                .limit(BINCODE_MAX_DESERIALIZE)
+                .deserialize_from(&mut src)
+            {
+                Ok(symlinks) => symlinks,
+                _ => {
+                    return Err(Error::DeserializationError);
+                }
+            }
+        } else {
+            BTreeMap::new()
+        };
+
+        // Read the comment, if any
+        let comment = if src.limit() > 0 {
+            match bincode::config()
+                .limit(MAX_COMMENT_LENGTH as u64 + 8)
+                .deserialize_from(&mut src)
+            {
+                Ok(comment) => Some(comment).filter(|comment: &String| !comment.is_empty()),
+                _ => {
+                    return Err(Error::DeserializationError);
+                }
+            }
+        } else {
+            None
+        };
+
+        // Read the creation time, if any
+        let created_at = if src.limit() > 0 {
+            Some(src.read_u64::<LittleEndian>()?)
+        } else {
+            None
+        };
+        Ok(ArchiveFooter {
+            features,
+            files_info,
+            directories,
+            symlinks,
+            comment,
+            created_at,
+        })
     }
 }
 
@@ -197,6 +701,22 @@ impl ArchiveFooter {
 enum ArchiveFileBlockType {
     FileStart = 0x00,
     FileContent = 0x01,
+    FileSparseHole = 0x02,
+    FileDedup = 0x03,
+    FilePrecompressedContent = 0x04,
+    CborEncoded = 0x05,
+
+    // Optional blocks, see `OPTIONAL_BLOCK_TYPES`
+    FileChecksum = 0x80,
+    FileMetadata = 0x81,
+    DirectoryEntry = 0x82,
+    Symlink = 0x83,
+    FileSizeHint = 0x84,
+    FileRecipients = 0x85,
+    FileTag = 0x86,
+    UserMetadata = 0x87,
+    FileAlias = 0x88,
+    Padding = 0x89,
 
     EndOfArchiveData = 0xFE,
     EndOfFile = 0xFF,
@@ -210,16 +730,53 @@ impl TryFrom<u8> for ArchiveFileBlockType {
             Ok(ArchiveFileBlockType::FileStart)
         } else if value == ArchiveFileBlockType::FileContent as u8 {
             Ok(ArchiveFileBlockType::FileContent)
+        } else if value == ArchiveFileBlockType::FileSparseHole as u8 {
+            Ok(ArchiveFileBlockType::FileSparseHole)
+        } else if value == ArchiveFileBlockType::FileDedup as u8 {
+            Ok(ArchiveFileBlockType::FileDedup)
+        } else if value == ArchiveFileBlockType::FilePrecompressedContent as u8 {
+            Ok(ArchiveFileBlockType::FilePrecompressedContent)
+        } else if value == ArchiveFileBlockType::CborEncoded as u8 {
+            Ok(ArchiveFileBlockType::CborEncoded)
         } else if value == ArchiveFileBlockType::EndOfFile as u8 {
             Ok(ArchiveFileBlockType::EndOfFile)
         } else if value == ArchiveFileBlockType::EndOfArchiveData as u8 {
             Ok(ArchiveFileBlockType::EndOfArchiveData)
+        } else if value == ArchiveFileBlockType::FileChecksum as u8 {
+            Ok(ArchiveFileBlockType::FileChecksum)
+        } else if value == ArchiveFileBlockType::FileMetadata as u8 {
+            Ok(ArchiveFileBlockType::FileMetadata)
+        } else if value == ArchiveFileBlockType::DirectoryEntry as u8 {
+            Ok(ArchiveFileBlockType::DirectoryEntry)
+        } else if value == ArchiveFileBlockType::Symlink as u8 {
+            Ok(ArchiveFileBlockType::Symlink)
+        } else if value == ArchiveFileBlockType::FileSizeHint as u8 {
+            Ok(ArchiveFileBlockType::FileSizeHint)
+        } else if value == ArchiveFileBlockType::FileRecipients as u8 {
+            Ok(ArchiveFileBlockType::FileRecipients)
+        } else if value == ArchiveFileBlockType::FileTag as u8 {
+            Ok(ArchiveFileBlockType::FileTag)
+        } else if value == ArchiveFileBlockType::UserMetadata as u8 {
+            Ok(ArchiveFileBlockType::UserMetadata)
+        } else if value == ArchiveFileBlockType::FileAlias as u8 {
+            Ok(ArchiveFileBlockType::FileAlias)
+        } else if value == ArchiveFileBlockType::Padding as u8 {
+            Ok(ArchiveFileBlockType::Padding)
         } else {
             Err(Error::WrongBlockSubFileType)
         }
     }
 }
 
+/// Block types in this range are optional: they are followed by an ID and the
+/// length of their content, so that readers unaware of them can skip them
+const OPTIONAL_BLOCK_TYPES: std::ops::Range<u8> = 0x80..0xFE;
+
+/// Is `block_type` a block type supported by this implementation
+fn block_kind_is_known(block_type: u8) -> bool {
+    ArchiveFileBlockType::try_from(block_type).is_ok()
+}
+
 #[derive(Debug)]
 pub enum ArchiveFileBlock<T: Read> {
     /// Usually, a file is made of:
@@ -238,10 +795,106 @@ pub enum ArchiveFileBlock<T: Read> {
         data: Option<T>,
         id: ArchiveFileID,
     },
+    /// Run of `length` zero bytes in the file content, not stored
+    FileSparseHole { id: ArchiveFileID, length: u64 },
+    /// File content of `uncompressed_length` bytes, compressed by the caller
+    /// with `algorithm` and stored as is in `data`, see
+    /// `ArchiveWriter::append_file_content_precompressed`
+    FilePrecompressedContent {
+        id: ArchiveFileID,
+        algorithm: CompressionAlgorithm,
+        uncompressed_length: u64,
+        data: Vec<u8>,
+    },
+    /// The whole file content is the one of the file `refers_to_id`, ended
+    /// before, whose blocks must be read instead
+    Dedup {
+        id: ArchiveFileID,
+        refers_to_id: ArchiveFileID,
+    },
+    /// Checksum of the whole file, if enabled - just before the EndOfFile
+    FileChecksum { id: ArchiveFileID, digest: Checksum },
+    /// Metadata of the file, if provided - just after the FileStart
+    FileMetadata {
+        id: ArchiveFileID,
+        metadata: FileMetadata,
+    },
+    /// Directory, not related to any file
+    DirectoryEntry {
+        path: String,
+        metadata: Option<FileMetadata>,
+    },
+    /// Symbolic link, not related to any file
+    Symlink {
+        link_name: String,
+        target: String,
+        metadata: Option<FileMetadata>,
+    },
+    /// Size of the file content, announced by the writer before writing it.
+    /// It is only a hint: the actual content may differ
+    FileSizeHint { id: ArchiveFileID, size: u64 },
+    /// Key of a file encrypted for its own recipients - just after the
+    /// FileStart, see `ArchiveWriter::start_file_for_recipients`
+    FileRecipients {
+        id: ArchiveFileID,
+        recipients: FileRecipientsPersistent,
+    },
+    /// Authentication tag of the content of a file encrypted for its own
+    /// recipients - before the FileChecksum, if any
+    FileTag {
+        id: ArchiveFileID,
+        tag: [u8; TAG_LENGTH],
+    },
+    /// User-defined metadata entry of the file, anywhere before its
+    /// EndOfFile, see `ArchiveWriter::set_file_metadata`
+    UserMetadata {
+        id: ArchiveFileID,
+        key: String,
+        value: Vec<u8>,
+    },
+    /// Additional name, `new_name`, of the file `original_id`, ended before,
+    /// see `ArchiveWriter::rename_file`. Not related to any opened file
+    FileAlias {
+        original_id: ArchiveFileID,
+        new_name: String,
+    },
+    /// `length` bytes of random content, not related to any file, see
+    /// `ArchiveWriterConfig::padding_strategy`. The content is written by the
+    /// writer right after the block, and skipped on parsing
+    Padding { length: u64 },
     /// End of file (last block) - contains the SHA256 of the whole file
     EndOfFile { id: ArchiveFileID, hash: Sha256Hash },
     /// End of archive data (no more files after that)
     EndOfArchiveData,
+    /// Optional block of a type unknown to this implementation, for instance
+    /// added by a newer version of the format. Its `length` bytes of content
+    /// are skipped on parsing
+    Unknown { kind: u8, length: u64 },
+}
+
+/// Decompress the content of a `FilePrecompressedContent` block, checking it
+/// has the announced length
+fn decompress_block_content(
+    algorithm: CompressionAlgorithm,
+    data: &[u8],
+    uncompressed_length: u64,
+) -> Result<Vec<u8>, Error> {
+    let content = decompress_precompressed(algorithm, data, uncompressed_length)?;
+    if content.len() as u64 != uncompressed_length {
+        return Err(Error::WrongReaderState(
+            "[FilePrecompressedContent] Content shorter than announced".to_string(),
+        ));
+    }
+    Ok(content)
+}
+
+/// Handle an `ArchiveFileBlock::Unknown`: ignore it if `skip`, or fail
+fn check_unknown_block(kind: u8, skip: bool) -> Result<(), Error> {
+    if skip {
+        Ok(())
+    } else {
+        Err(Error::UnknownBlockType(kind))
+    }
 }
 
 impl<T> ArchiveFileBlock<T>
@@ -252,7 +905,7 @@ where
         match self {
             ArchiveFileBlock::FileStart { filename, id } => {
                 dest.write_u8(ArchiveFileBlockType::FileStart as u8)?;
-                dest.write_u64::<LittleEndian>(*id)?;
+                dest.write_u64::<LittleEndian>(id.0)?;
                 let bytes = filename.as_bytes();
                 let length = bytes.len() as u64;
                 if length > FILENAME_MAX_SIZE {
@@ -264,7 +917,7 @@ where
             }
             ArchiveFileBlock::FileContent { length, data, id } => {
                 dest.write_u8(ArchiveFileBlockType::FileContent as u8)?;
-                dest.write_u64::<LittleEndian>(*id)?;
+                dest.write_u64::<LittleEndian>(id.0)?;
                 dest.write_u64::<LittleEndian>(*length)?;
                 match data {
                     None => {
@@ -279,9 +932,154 @@ where
                 }
                 Ok(())
             }
+            ArchiveFileBlock::FileSparseHole { id, length } => {
+                dest.write_u8(ArchiveFileBlockType::FileSparseHole as u8)?;
+                dest.write_u64::<LittleEndian>(id.0)?;
+                dest.write_u64::<LittleEndian>(*length)?;
+                Ok(())
+            }
+            ArchiveFileBlock::Dedup { id, refers_to_id } => {
+                dest.write_u8(ArchiveFileBlockType::FileDedup as u8)?;
+                dest.write_u64::<LittleEndian>(id.0)?;
+                dest.write_u64::<LittleEndian>(refers_to_id.0)?;
+                Ok(())
+            }
+            ArchiveFileBlock::FilePrecompressedContent {
+                id,
+                algorithm,
+                uncompressed_length,
+                data,
+            } => {
+                dest.write_u8(ArchiveFileBlockType::FilePrecompressedContent as u8)?;
+                dest.write_u64::<LittleEndian>(id.0)?;
+                dest.write_u8(algorithm.precompressed_code())?;
+                dest.write_u64::<LittleEndian>(*uncompressed_length)?;
+                dest.write_u64::<LittleEndian>(data.len() as u64)?;
+                dest.write_all(data)?;
+                Ok(())
+            }
+            ArchiveFileBlock::FileChecksum { id, digest } => {
+                dest.write_u8(ArchiveFileBlockType::FileChecksum as u8)?;
+                dest.write_u64::<LittleEndian>(id.0)?;
+                // Optional block content: [algorithm][digest]
+                let bytes = digest.as_bytes();
+                dest.write_u64::<LittleEndian>(1 + bytes.len() as u64)?;
+                dest.write_u8(digest.algorithm() as u8)?;
+                dest.write_all(bytes)?;
+                Ok(())
+            }
+            ArchiveFileBlock::FileMetadata { id, metadata } => {
+                let bytes = bincode::serialize(metadata).map_err(|_| Error::SerializationError)?;
+                let length = bytes.len() as u64;
+                if length > METADATA_MAX_SIZE {
+                    return Err(Error::SerializationError);
+                }
+                dest.write_u8(ArchiveFileBlockType::FileMetadata as u8)?;
+                dest.write_u64::<LittleEndian>(id.0)?;
+                dest.write_u64::<LittleEndian>(length)?;
+                dest.write_all(&bytes)?;
+                Ok(())
+            }
+            ArchiveFileBlock::DirectoryEntry { path, metadata } => {
+                if path.len() as u64 > FILENAME_MAX_SIZE {
+                    return Err(Error::FilenameTooLong);
+                }
+                let bytes = bincode::serialize(&(&path, &metadata))
+                    .map_err(|_| Error::SerializationError)?;
+                dest.write_u8(ArchiveFileBlockType::DirectoryEntry as u8)?;
+                // Directories are not files: the optional block ID is unused
+                dest.write_u64::<LittleEndian>(0)?;
+                dest.write_u64::<LittleEndian>(bytes.len() as u64)?;
+                dest.write_all(&bytes)?;
+                Ok(())
+            }
+            ArchiveFileBlock::Symlink {
+                link_name,
+                target,
+                metadata,
+            } => {
+                if link_name.len() as u64 > FILENAME_MAX_SIZE
+                    || target.len() as u64 > FILENAME_MAX_SIZE
+                {
+                    return Err(Error::FilenameTooLong);
+                }
+                let bytes = bincode::serialize(&(&link_name, &target, &metadata))
+                    .map_err(|_| Error::SerializationError)?;
+                dest.write_u8(ArchiveFileBlockType::Symlink as u8)?;
+                // Symbolic links are not files: the optional block ID is unused
+                dest.write_u64::<LittleEndian>(0)?;
+                dest.write_u64::<LittleEndian>(bytes.len() as u64)?;
+                dest.write_all(&bytes)?;
+                Ok(())
+            }
+            ArchiveFileBlock::FileSizeHint { id, size } => {
+                dest.write_u8(ArchiveFileBlockType::FileSizeHint as u8)?;
+                dest.write_u64::<LittleEndian>(id.0)?;
+                // Optional block content: [size]
+                dest.write_u64::<LittleEndian>(8)?;
+                dest.write_u64::<LittleEndian>(*size)?;
+                Ok(())
+            }
+            ArchiveFileBlock::FileRecipients { id, recipients } => {
+                let bytes =
+                    bincode::serialize(recipients).map_err(|_| Error::SerializationError)?;
+                let length = bytes.len() as u64;
+                if length > RECIPIENTS_MAX_SIZE {
+                    return Err(Error::SerializationError);
+                }
+                dest.write_u8(ArchiveFileBlockType::FileRecipients as u8)?;
+                dest.write_u64::<LittleEndian>(id.0)?;
+                dest.write_u64::<LittleEndian>(length)?;
+                dest.write_all(&bytes)?;
+                Ok(())
+            }
+            ArchiveFileBlock::FileTag { id, tag } => {
+                dest.write_u8(ArchiveFileBlockType::FileTag as u8)?;
+                dest.write_u64::<LittleEndian>(id.0)?;
+                // Optional block content: [tag]
+                dest.write_u64::<LittleEndian>(TAG_LENGTH as u64)?;
+                dest.write_all(tag)?;
+                Ok(())
+            }
+            ArchiveFileBlock::UserMetadata { id, key, value } => {
+                let bytes =
+                    bincode::serialize(&(&key, &value)).map_err(|_| Error::SerializationError)?;
+                let length = bytes.len() as u64;
+                if length > USER_METADATA_MAX_SIZE {
+                    return Err(Error::SerializationError);
+                }
+                dest.write_u8(ArchiveFileBlockType::UserMetadata as u8)?;
+                dest.write_u64::<LittleEndian>(id.0)?;
+                dest.write_u64::<LittleEndian>(length)?;
+                dest.write_all(&bytes)?;
+                Ok(())
+            }
+            ArchiveFileBlock::FileAlias {
+                original_id,
+                new_name,
+            } => {
+                let bytes = new_name.as_bytes();
+                let length = bytes.len() as u64;
+                if length > FILENAME_MAX_SIZE {
+                    return Err(Error::FilenameTooLong);
+                }
+                dest.write_u8(ArchiveFileBlockType::FileAlias as u8)?;
+                dest.write_u64::<LittleEndian>(original_id.0)?;
+                dest.write_u64::<LittleEndian>(length)?;
+                dest.write_all(bytes)?;
+                Ok(())
+            }
+            ArchiveFileBlock::Padding { length } => {
+                dest.write_u8(ArchiveFileBlockType::Padding as u8)?;
+                // Padding is not related to any file: the optional block ID
+                // is unused
+                dest.write_u64::<LittleEndian>(0)?;
+                dest.write_u64::<LittleEndian>(*length)?;
+                Ok(())
+            }
             ArchiveFileBlock::EndOfFile { id, hash } => {
                 dest.write_u8(ArchiveFileBlockType::EndOfFile as u8)?;
-                dest.write_u64::<LittleEndian>(*id)?;
+                dest.write_u64::<LittleEndian>(id.0)?;
                 dest.write_all(hash)?;
                 Ok(())
             }
@@ -289,14 +1087,46 @@ where
                 dest.write_u8(ArchiveFileBlockType::EndOfArchiveData as u8)?;
                 Ok(())
             }
+            ArchiveFileBlock::Unknown { .. } => Err(Error::AssertionError(String::from(
+                "Unknown blocks can't be written, their content is not kept",
+            ))),
+        }
+    }
+
+    /// Write the block to `dest` with `encoding`. File contents are always
+    /// written natively
+    fn dump_encoded<U: Write>(
+        &mut self,
+        dest: &mut U,
+        encoding: MetadataEncoding,
+    ) -> Result<(), Error> {
+        match encoding {
+            MetadataEncoding::Native => self.dump(dest),
+            #[cfg(feature = "cbor")]
+            MetadataEncoding::Cbor => match self {
+                ArchiveFileBlock::FileContent { .. }
+                | ArchiveFileBlock::FilePrecompressedContent { .. }
+                | ArchiveFileBlock::Padding { .. } => self.dump(dest),
+                _ => cbor::dump(self, dest),
+            },
         }
     }
 
     fn from(src: &mut T) -> Result<Self, Error> {
         let byte = src.read_u8()?;
+        if !block_kind_is_known(byte) && OPTIONAL_BLOCK_TYPES.contains(&byte) {
+            // Unknown optional block: skip its content
+            let _id = src.read_u64::<LittleEndian>()?;
+            let length = src.read_u64::<LittleEndian>()?;
+            let skipped = io::copy(&mut (&mut *src).take(length), &mut io::sink())?;
+            if skipped != length {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            return Ok(ArchiveFileBlock::Unknown { kind: byte, length });
+        }
         match ArchiveFileBlockType::try_from(byte)? {
             ArchiveFileBlockType::FileStart => {
-                let id = src.read_u64::<LittleEndian>()?;
+                let id = ArchiveFileID(src.read_u64::<LittleEndian>()?);
                 let length = src.read_u64::<LittleEndian>()?;
                 if length > FILENAME_MAX_SIZE {
                     return Err(Error::FilenameTooLong);
@@ -309,7 +1139,7 @@ where
                 })
             }
             ArchiveFileBlockType::FileContent => {
-                let id = src.read_u64::<LittleEndian>()?;
+                let id = ArchiveFileID(src.read_u64::<LittleEndian>()?);
                 let length = src.read_u64::<LittleEndian>()?;
                 // /!\ WARNING: to avoid loading this entire subfileblock's contents
                 // in-memory, the `data` reader is None; the `src` now starts at the
@@ -320,17 +1150,215 @@ where
                     id,
                 })
             }
+            ArchiveFileBlockType::FileSparseHole => {
+                let id = ArchiveFileID(src.read_u64::<LittleEndian>()?);
+                let length = src.read_u64::<LittleEndian>()?;
+                Ok(ArchiveFileBlock::FileSparseHole { id, length })
+            }
+            ArchiveFileBlockType::FileDedup => {
+                let id = ArchiveFileID(src.read_u64::<LittleEndian>()?);
+                let refers_to_id = ArchiveFileID(src.read_u64::<LittleEndian>()?);
+                Ok(ArchiveFileBlock::Dedup { id, refers_to_id })
+            }
+            ArchiveFileBlockType::FilePrecompressedContent => {
+                let id = ArchiveFileID(src.read_u64::<LittleEndian>()?);
+                let algorithm = CompressionAlgorithm::from_precompressed_code(src.read_u8()?)
+                    .ok_or(Error::DeserializationError)?;
+                let uncompressed_length = src.read_u64::<LittleEndian>()?;
+                let length = src.read_u64::<LittleEndian>()?;
+                if uncompressed_length > PRECOMPRESSED_MAX_SIZE || length > PRECOMPRESSED_MAX_SIZE {
+                    return Err(Error::DeserializationError);
+                }
+                let mut data = vec![0u8; length as usize];
+                src.read_exact(&mut data)?;
+                Ok(ArchiveFileBlock::FilePrecompressedContent {
+                    id,
+                    algorithm,
+                    uncompressed_length,
+                    data,
+                })
+            }
             ArchiveFileBlockType::EndOfFile => {
-                let id = src.read_u64::<LittleEndian>()?;
+                let id = ArchiveFileID(src.read_u64::<LittleEndian>()?);
                 let mut hash = Sha256Hash::default();
                 src.read_exact(&mut hash)?;
                 Ok(ArchiveFileBlock::EndOfFile { id, hash })
             }
-            ArchiveFileBlockType::EndOfArchiveData => Ok(ArchiveFileBlock::EndOfArchiveData),
-        }
-    }
-}
-
+            ArchiveFileBlockType::FileChecksum => {
+                let id = ArchiveFileID(src.read_u64::<LittleEndian>()?);
+                let length = src.read_u64::<LittleEndian>()?;
+                if !(1..=CHECKSUM_MAX_SIZE).contains(&length) {
+                    return Err(Error::DeserializationError);
+                }
+                let algorithm = src.read_u8()?;
+                let mut digest = vec![0u8; length as usize - 1];
+                src.read_exact(&mut digest)?;
+                Ok(ArchiveFileBlock::FileChecksum {
+                    id,
+                    digest: Checksum::from_bytes(algorithm, &digest)?,
+                })
+            }
+            ArchiveFileBlockType::FileMetadata => {
+                let id = ArchiveFileID(src.read_u64::<LittleEndian>()?);
+                let length = src.read_u64::<LittleEndian>()?;
+                if length > METADATA_MAX_SIZE {
+                    return Err(Error::DeserializationError);
+                }
+                let mut data = vec![0u8; length as usize];
+                src.read_exact(&mut data)?;
+                Ok(ArchiveFileBlock::FileMetadata {
+                    id,
+                    metadata: bincode::deserialize(&data)
+                        .map_err(|_| Error::DeserializationError)?,
+                })
+            }
+            ArchiveFileBlockType::DirectoryEntry => {
+                let _id = src.read_u64::<LittleEndian>()?;
+                let length = src.read_u64::<LittleEndian>()?;
+                if length > DIRECTORY_MAX_SIZE {
+                    return Err(Error::DeserializationError);
+                }
+                let mut data = vec![0u8; length as usize];
+                src.read_exact(&mut data)?;
+                let (path, metadata) =
+                    bincode::deserialize(&data).map_err(|_| Error::DeserializationError)?;
+                Ok(ArchiveFileBlock::DirectoryEntry { path, metadata })
+            }
+            ArchiveFileBlockType::Symlink => {
+                let _id = src.read_u64::<LittleEndian>()?;
+                let length = src.read_u64::<LittleEndian>()?;
+                if length > SYMLINK_MAX_SIZE {
+                    return Err(Error::DeserializationError);
+                }
+                let mut data = vec![0u8; length as usize];
+                src.read_exact(&mut data)?;
+                let (link_name, target, metadata) =
+                    bincode::deserialize(&data).map_err(|_| Error::DeserializationError)?;
+                Ok(ArchiveFileBlock::Symlink {
+                    link_name,
+                    target,
+                    metadata,
+                })
+            }
+            ArchiveFileBlockType::FileSizeHint => {
+                let id = ArchiveFileID(src.read_u64::<LittleEndian>()?);
+                let length = src.read_u64::<LittleEndian>()?;
+                if length != 8 {
+                    return Err(Error::DeserializationError);
+                }
+                let size = src.read_u64::<LittleEndian>()?;
+                Ok(ArchiveFileBlock::FileSizeHint { id, size })
+            }
+            ArchiveFileBlockType::FileRecipients => {
+                let id = ArchiveFileID(src.read_u64::<LittleEndian>()?);
+                let length = src.read_u64::<LittleEndian>()?;
+                if length > RECIPIENTS_MAX_SIZE {
+                    return Err(Error::DeserializationError);
+                }
+                let mut data = vec![0u8; length as usize];
+                src.read_exact(&mut data)?;
+                Ok(ArchiveFileBlock::FileRecipients {
+                    id,
+                    recipients: bincode::deserialize(&data)
+                        .map_err(|_| Error::DeserializationError)?,
+                })
+            }
+            ArchiveFileBlockType::FileTag => {
+                let id = ArchiveFileID(src.read_u64::<LittleEndian>()?);
+                let length = src.read_u64::<LittleEndian>()?;
+                if length != TAG_LENGTH as u64 {
+                    return Err(Error::DeserializationError);
+                }
+                let mut tag = [0u8; TAG_LENGTH];
+                src.read_exact(&mut tag)?;
+                Ok(ArchiveFileBlock::FileTag { id, tag })
+            }
+            ArchiveFileBlockType::UserMetadata => {
+                let id = ArchiveFileID(src.read_u64::<LittleEndian>()?);
+                let length = src.read_u64::<LittleEndian>()?;
+                if length > USER_METADATA_MAX_SIZE {
+                    return Err(Error::DeserializationError);
+                }
+                let mut data = vec![0u8; length as usize];
+                src.read_exact(&mut data)?;
+                let (key, value) =
+                    bincode::deserialize(&data).map_err(|_| Error::DeserializationError)?;
+                Ok(ArchiveFileBlock::UserMetadata { id, key, value })
+            }
+            ArchiveFileBlockType::FileAlias => {
+                let original_id = ArchiveFileID(src.read_u64::<LittleEndian>()?);
+                let length = src.read_u64::<LittleEndian>()?;
+                if length > FILENAME_MAX_SIZE {
+                    return Err(Error::FilenameTooLong);
+                }
+                let mut new_name = vec![0u8; length as usize];
+                src.read_exact(&mut new_name)?;
+                Ok(ArchiveFileBlock::FileAlias {
+                    original_id,
+                    new_name: String::from_utf8(new_name)?,
+                })
+            }
+            ArchiveFileBlockType::Padding => {
+                let _id = src.read_u64::<LittleEndian>()?;
+                let length = src.read_u64::<LittleEndian>()?;
+                let skipped = io::copy(&mut (&mut *src).take(length), &mut io::sink())?;
+                if skipped != length {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                }
+                Ok(ArchiveFileBlock::Padding { length })
+            }
+            ArchiveFileBlockType::EndOfArchiveData => Ok(ArchiveFileBlock::EndOfArchiveData),
+            #[cfg(feature = "cbor")]
+            ArchiveFileBlockType::CborEncoded => cbor::from(src),
+            #[cfg(not(feature = "cbor"))]
+            ArchiveFileBlockType::CborEncoded => {
+                Err(Error::UnsupportedFeature(FeatureFlags::HAS_CBOR_BLOCKS))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "fuzzing")]
+impl<T: Read> ArchiveFileBlock<T> {
+    /// Parse a block from `src`, as the readers do. Only meant for fuzzing
+    #[doc(hidden)]
+    pub fn fuzz_from(src: &mut T) -> Result<Self, Error> {
+        ArchiveFileBlock::from(src)
+    }
+}
+
+/// Part of a file content, see `ArchiveWriter::append_file_sparse`
+#[derive(Debug, Clone, Copy)]
+pub enum SparseData<'a> {
+    /// Run of zero bytes, of the given length
+    Zeroes(u64),
+    /// Regular content
+    Data(&'a [u8]),
+}
+
+impl<'a> SparseData<'a> {
+    /// Length of the content described, in bytes
+    pub fn len(&self) -> u64 {
+        match self {
+            SparseData::Zeroes(length) => *length,
+            SparseData::Data(content) => content.len() as u64,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Outcome of `ArchiveWriter::add_file_if_changed`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddResult {
+    /// The file has been added, with this content hash
+    Added(Sha256Hash),
+    /// The content hash is the previous one, the file has not been added
+    Skipped,
+}
+
 #[derive(Debug, Clone)]
 pub enum ArchiveWriterState {
     /// Initialized, with files opened
@@ -412,13 +1440,17 @@ macro_rules! check_state_file_opened {
     }};
 }
 
+/// Small contents appended to the opened file `id`, not written yet, see
+/// `ArchiveWriterConfig::coalesce_threshold`
+struct WriteCoalesceBuffer {
+    id: ArchiveFileID,
+    data: Vec<u8>,
+}
+
 pub struct ArchiveWriter<'a, W: 'a + Write> {
     /// MLA Archive format writer
     ///
     /// Configuration
-    // config is not used for now after archive creation,
-    // but it could in the future
-    #[allow(dead_code)]
     config: ArchiveWriterConfig,
     ///
     /// Internals part:
@@ -431,6 +1463,10 @@ pub struct ArchiveWriter<'a, W: 'a + Write> {
     ///
     /// This is done to keep a quick check for filename existence
     files_info: HashMap<String, ArchiveFileID>,
+    /// Directory entries added
+    directories: BTreeSet<String>,
+    /// Symbolic links added, with their target
+    symlinks: BTreeMap<String, String>,
     /// ID -> Corresponding FileInfo
     ///
     /// File chunks identify their relative file using the `ArchiveFileID`.
@@ -444,6 +1480,57 @@ pub struct ArchiveWriter<'a, W: 'a + Write> {
     next_id: ArchiveFileID,
     /// Current file being written (for continuous block detection)
     current_id: ArchiveFileID,
+    /// Checksums of the opened files, if enabled
+    checksums: HashMap<ArchiveFileID, ChecksumHasher>,
+    /// Names of the opened files, for progress reporting
+    opened_names: HashMap<ArchiveFileID, String>,
+    /// Opened files whose content must not be compressed (`Some(true)`), or
+    /// waiting for a content sample to decide (`None`)
+    compression_bypass: HashMap<ArchiveFileID, Option<bool>>,
+    /// Opened files whose content is compressed with another level of the
+    /// configured algorithm, see `set_file_compression`
+    compression_levels: HashMap<ArchiveFileID, CompressionAlgorithm>,
+    /// Names of the files abandoned, kept reserved as their start is in the
+    /// archive
+    abandoned_names: HashSet<String>,
+    /// Features used so far, declared in the footer
+    features: FeatureFlags,
+    /// Content of the opened files, not written yet, if deduplication is
+    /// enabled
+    dedup_pending: HashMap<ArchiveFileID, Vec<u8>>,
+    /// Small contents of an opened file, to be written as one block
+    coalesce: Option<WriteCoalesceBuffer>,
+    /// Contents of the opened files, not written yet, see
+    /// `ArchiveWriterConfig::file_layout`
+    layout_pending: HashMap<ArchiveFileID, Vec<u8>>,
+    /// With `FileLayout::Sequential`, file whose contents are written as they
+    /// are appended
+    layout_writing: Option<ArchiveFileID>,
+    /// Contents of the opened files since their last block boundary, see
+    /// `ArchiveWriterConfig::block_boundary`
+    chunkers: HashMap<ArchiveFileID, Chunker>,
+    /// Randomness of the padding, see `ArchiveWriterConfig::padding_strategy`.
+    /// It follows the archive identifier, which is not secret either
+    padding_rng: ChaChaRng,
+    /// (SHA-256, size) of the content of ended files -> ID of the file with
+    /// this content written in the archive
+    dedup_index: HashMap<(Sha256Hash, u64), ArchiveFileID>,
+    /// Size of the archive header, written on creation
+    header_size: u64,
+    /// Sizes announced for the opened files, see `set_file_size_hint`
+    size_hints: HashMap<ArchiveFileID, u64>,
+    /// Number of files ended with another size than the announced one
+    size_hint_mismatches: usize,
+    /// Number of bytes written in the destination so far
+    raw_written: Rc<Cell<u64>>,
+    /// Total size of the content appended to files so far
+    content_size: u64,
+    /// Creation time, for `WriteStatistics::duration`, if available
+    created: Option<Instant>,
+    /// Encryption of the opened files encrypted for their own recipients
+    file_encryptors: HashMap<ArchiveFileID, FileEncryptor>,
+    /// File names for the public listing layer, if enabled
+    public_listing: Option<Rc<RefCell<Vec<String>>>>,
 }
 
 // This is an unstable feature for now (`Vec.remove_item`), use a function
@@ -457,19 +1544,42 @@ pub fn vec_remove_item<T: std::cmp::PartialEq>(vec: &mut Vec<T>, item: &T) -> Op
 }
 
 impl<'a, W: Write> ArchiveWriter<'a, W> {
+    /// Create an archive writing to `dest`, which is never seeked: it can be
+    /// a pipe or `io::stdout()`, for instance
     pub fn from_config(dest: W, config: ArchiveWriterConfig) -> Result<Self, Error> {
         // Ensure config is correct
         config.check()?;
 
         // Write archive header
-        let mut dest: Box<dyn LayerWriter<W>> = Box::new(RawLayerWriter::new(dest));
+        let raw = RawLayerWriter::new(dest);
+        let raw_written = raw.written_counter();
+        let mut dest: Box<dyn LayerWriter<W>> = Box::new(raw);
+        let mut header = Vec::new();
         ArchiveHeader {
+            format: FormatVersion::CURRENT,
+            comment: config.comment.clone(),
+            archive_id: Some(
+                config
+                    .encrypt
+                    .csprng(CSPRNG_STREAM_ARCHIVE_ID)
+                    .gen::<[u8; ARCHIVE_ID_SIZE]>(),
+            ),
             config: config.to_persistent()?,
             // TODO public_key hashes for easier decryption
         }
-        .dump(&mut dest)?;
+        .dump(&mut header)?;
+        dest.write_all(&header)?;
 
         // Enable layers depending on user option
+        if config.is_layers_enabled(Layers::SIGN) {
+            dest = Box::new(SignatureLayerWriter::new(dest, &config.sign, &header)?);
+        }
+        let mut public_listing = None;
+        if config.is_layers_enabled(Layers::PUBLIC_LISTING) {
+            let listing_dest = PublicListingLayerWriter::new(dest);
+            public_listing = Some(listing_dest.listing());
+            dest = Box::new(listing_dest);
+        }
         if config.is_layers_enabled(Layers::ENCRYPT) {
             dest = Box::new(EncryptionLayerWriter::new(dest, &config.encrypt)?);
         }
@@ -482,18 +1592,66 @@ impl<'a, W: Write> ArchiveWriter<'a, W> {
         final_dest.reset_position();
 
         // Build initial archive
-        Ok(ArchiveWriter {
+        Ok(Self::with_dest(
+            config,
+            final_dest,
+            header.len() as u64,
+            raw_written,
+            public_listing,
+        ))
+    }
+
+    /// Build a writer with no entry yet, writing the archive data to `dest`
+    fn with_dest(
+        config: ArchiveWriterConfig,
+        dest: Box<PositionLayerWriter<'a, W>>,
+        header_size: u64,
+        raw_written: Rc<Cell<u64>>,
+        public_listing: Option<Rc<RefCell<Vec<String>>>>,
+    ) -> Self {
+        let mut padding_rng = config.encrypt.csprng(CSPRNG_STREAM_ARCHIVE_ID);
+        // Skip the archive identifier
+        padding_rng.gen::<[u8; ARCHIVE_ID_SIZE]>();
+        ArchiveWriter {
             config,
-            dest: final_dest,
+            dest,
             state: ArchiveWriterState::OpenedFiles {
                 ids: Vec::new(),
                 hashes: HashMap::new(),
             },
             files_info: HashMap::new(),
+            directories: BTreeSet::new(),
+            symlinks: BTreeMap::new(),
             ids_info: HashMap::new(),
-            next_id: 0,
-            current_id: 0,
-        })
+            next_id: ArchiveFileID(0),
+            current_id: ArchiveFileID(0),
+            checksums: HashMap::new(),
+            opened_names: HashMap::new(),
+            compression_bypass: HashMap::new(),
+            compression_levels: HashMap::new(),
+            abandoned_names: HashSet::new(),
+            features: FeatureFlags::empty(),
+            dedup_pending: HashMap::new(),
+            coalesce: None,
+            layout_pending: HashMap::new(),
+            layout_writing: None,
+            chunkers: HashMap::new(),
+            padding_rng,
+            dedup_index: HashMap::new(),
+            header_size,
+            size_hints: HashMap::new(),
+            size_hint_mismatches: 0,
+            raw_written,
+            content_size: 0,
+            // `Instant::now` panics on wasm32-unknown-unknown
+            created: if cfg!(target_arch = "wasm32") {
+                None
+            } else {
+                Some(Instant::now())
+            },
+            file_encryptors: HashMap::new(),
+            public_listing,
+        }
     }
 
     pub fn new(dest: W, public_keys: &[PublicKey]) -> Result<Self, Error> {
@@ -502,7 +1660,126 @@ impl<'a, W: Write> ArchiveWriter<'a, W> {
         Self::from_config(dest, config)
     }
 
-    pub fn finalize(&mut self) -> Result<(), Error> {
+    /// Estimate the size of an archive with this configuration, holding
+    /// `files`, as (filename, content size)
+    ///
+    /// The estimate is an upper bound, as file contents are assumed not to be
+    /// compressible. It assumes each file is written at once, like with
+    /// `add_file`, without metadata. This is a pure computation: nothing is
+    /// written, and the files already in this archive are not considered.
+    ///
+    /// A filename too long is reported with `Error::FilenameTooLong`
+    pub fn estimate_output_size(&self, files: &[(&str, u64)]) -> Result<u64, Error> {
+        // Size of the blocks, as dumped by the writer
+        let mut data_size = 0;
+        let mut blocks = Vec::new();
+        let checksum = self
+            .config
+            .checksum_algorithm()
+            .map(|algorithm| ChecksumHasher::new(algorithm).finalize());
+        let mut files_info = HashMap::new();
+        let mut ids_info = HashMap::new();
+        for (id, (filename, size)) in files.iter().enumerate() {
+            let id = ArchiveFileID(id as u64);
+            blocks.clear();
+            ArchiveFileBlock::FileStart::<std::io::Empty> {
+                filename: filename.to_string(),
+                id,
+            }
+            .dump_encoded(&mut blocks, self.config.metadata_encoding)?;
+            if *size > 0 {
+                // Block header only, without the content
+                ArchiveFileBlock::FileContent {
+                    id,
+                    length: 0,
+                    data: Some(std::io::empty()),
+                }
+                .dump_encoded(&mut blocks, self.config.metadata_encoding)?;
+            }
+            if let Some(digest) = checksum.clone() {
+                ArchiveFileBlock::FileChecksum::<std::io::Empty> { id, digest }
+                    .dump_encoded(&mut blocks, self.config.metadata_encoding)?;
+            }
+            ArchiveFileBlock::EndOfFile::<std::io::Empty> { id, hash: [0; 32] }
+                .dump_encoded(&mut blocks, self.config.metadata_encoding)?;
+            data_size += blocks.len() as u64 + size + self.config.padding_strategy.max_size();
+
+            files_info.insert(filename.to_string(), id);
+            ids_info.insert(
+                id,
+                FileInfo {
+                    offsets: vec![0],
+                    size: *size,
+                    eof_offset: 0,
+                },
+            );
+        }
+        blocks.clear();
+        ArchiveFileBlock::EndOfArchiveData::<std::io::Empty> {}
+            .dump_encoded(&mut blocks, self.config.metadata_encoding)?;
+        ArchiveFooter::serialize_into(
+            &mut blocks,
+            FeatureFlags::empty(),
+            &files_info,
+            &ids_info,
+            &BTreeSet::new(),
+            &BTreeMap::new(),
+            self.config.encrypted_comment.as_deref(),
+            // Only its size matters
+            Some(0),
+        )?;
+        data_size += blocks.len() as u64;
+
+        data_size = self.max_layered_size(data_size);
+        if self.config.is_layers_enabled(Layers::PUBLIC_LISTING) {
+            // Serialized names, then the listing length
+            let names: Vec<&str> = files.iter().map(|(filename, _size)| *filename).collect();
+            data_size += bincode::serialized_size(&names).or(Err(Error::SerializationError))? + 4;
+        }
+        if self.config.is_layers_enabled(Layers::SIGN) {
+            data_size += SIGNATURE_LENGTH as u64;
+        }
+        Ok(self.header_size + data_size)
+    }
+
+    /// Upper bound of the size of `size` bytes of archive data, once through
+    /// the compression and encryption layers
+    fn max_layered_size(&self, mut size: u64) -> u64 {
+        // Layers, from the upper one
+        if self.config.is_layers_enabled(Layers::COMPRESS) {
+            size = max_compressed_size(size);
+        }
+        if self.config.is_layers_enabled(Layers::ENCRYPT) {
+            size = max_encrypted_size(size);
+        }
+        size
+    }
+
+    /// Check that writing a file content of `size` bytes can't make the
+    /// archive exceed `ArchiveWriterConfig::max_bytes`
+    fn check_size_limit(&mut self, size: u64) -> Result<(), Error> {
+        let limit = match self.config.max_bytes {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        // The encryption layer keeps the current chunk until it is complete:
+        // write it, so that it is counted. Flushing the compression layer
+        // would instead end its current block
+        if self.config.is_layers_enabled(Layers::ENCRYPT)
+            && !self.config.is_layers_enabled(Layers::COMPRESS)
+        {
+            self.dest.flush()?;
+        }
+        // Block type, ID and length, then the content
+        let block_size = self.max_layered_size(size.saturating_add(1 + 8 + 8));
+        if self.raw_written.get().saturating_add(block_size) > limit {
+            return Err(Error::ArchiveSizeLimitExceeded(limit));
+        }
+        Ok(())
+    }
+
+    /// Write the end of the archive, and return statistics on its creation
+    pub fn finalize(&mut self) -> Result<WriteStatistics, Error> {
         // Check final state (empty ids, empty hashes)
         check_state!(self.state, OpenedFiles);
         match &mut self.state {
@@ -523,15 +1800,50 @@ impl<'a, W: Write> ArchiveWriter<'a, W> {
         self.state = ArchiveWriterState::Finalized;
 
         // Mark the end of the data
-
+        if self.config.metadata_encoding != MetadataEncoding::Native {
+            self.features |= FeatureFlags::HAS_CBOR_BLOCKS;
+        }
         // Use std::io::Empty as a readable placeholder type
-        ArchiveFileBlock::EndOfArchiveData::<std::io::Empty> {}.dump(&mut self.dest)?;
-
-        ArchiveFooter::serialize_into(&mut self.dest, &self.files_info, &self.ids_info)?;
+        ArchiveFileBlock::EndOfArchiveData::<std::io::Empty> {}
+            .dump_encoded(&mut self.dest, self.config.metadata_encoding)?;
+
+        ArchiveFooter::serialize_into(
+            &mut self.dest,
+            self.features,
+            &self.files_info,
+            &self.ids_info,
+            &self.directories,
+            &self.symlinks,
+            self.config.encrypted_comment.as_deref(),
+            Some(
+                self.config
+                    .created_at
+                    .unwrap_or_else(SystemTime::now)
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |elapsed| elapsed.as_secs()),
+            ),
+        )?;
+
+        if let Some(listing) = &self.public_listing {
+            let mut names: Vec<String> = self.files_info.keys().cloned().collect();
+            names.sort();
+            *listing.borrow_mut() = names;
+        }
 
         // Recursive call
         self.dest.finalize()?;
-        Ok(())
+
+        let total_bytes_written = self.raw_written.get();
+        Ok(WriteStatistics {
+            files_written: self.files_info.len(),
+            total_bytes_written,
+            total_bytes_uncompressed: self.content_size,
+            compression_ratio: self.content_size as f64 / total_bytes_written as f64,
+            duration: self
+                .created
+                .map_or(Duration::default(), |created| created.elapsed()),
+            size_hint_mismatches: self.size_hint_mismatches,
+        })
     }
 
     /// Add the current offset to the corresponding list if the file id is not
@@ -552,6 +1864,25 @@ impl<'a, W: Write> ArchiveWriter<'a, W> {
         Ok(())
     }
 
+    /// Write a `Padding` block, if required by the padding strategy at the
+    /// current offset
+    fn write_padding(&mut self) -> Result<(), Error> {
+        let position = self.dest.position();
+        let length = match self
+            .config
+            .padding_strategy
+            .length(position, &mut self.padding_rng)
+        {
+            Some(length) => length,
+            None => return Ok(()),
+        };
+        ArchiveFileBlock::Padding::<std::io::Empty> { length }.dump(&mut self.dest)?;
+        let mut content = vec![0u8; length as usize];
+        self.padding_rng.fill_bytes(&mut content);
+        self.dest.write_all(&content)?;
+        Ok(())
+    }
+
     /// Set the EoF offset to the current offset for the corresponding file id
     fn mark_eof(&mut self, id: ArchiveFileID) -> Result<(), Error> {
         let offset = self.dest.position();
@@ -579,16 +1910,52 @@ impl<'a, W: Write> ArchiveWriter<'a, W> {
         Ok(())
     }
 
-    pub fn start_file(&mut self, filename: &str) -> Result<ArchiveFileID, Error> {
-        check_state!(self.state, OpenedFiles);
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(
+                archive_path = self.config.archive_path.as_deref(),
+                file_name = filename.as_ref(),
+                file_id = tracing::field::Empty
+            )
+        )
+    )]
+    pub fn start_file(&mut self, filename: impl AsRef<str>) -> Result<ArchiveFileID, Error> {
+        let id = self.start_file_with_hint(filename, CompressionHint::Auto)?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("file_id", id.0);
+        Ok(id)
+    }
 
-        if self.files_info.contains_key(filename) {
-            return Err(Error::DuplicateFilename);
+    /// Start a new file, whose content compression is handled according to
+    /// `hint`
+    ///
+    /// With `CompressionHint::Auto` and an entropy threshold configured, the
+    /// entropy is estimated on the first 4KB of the first content block.
+    ///
+    /// Compression is bypassed by compression layer block (4MB of archive
+    /// data): a block is stored as is if it starts while writing the content
+    /// of a bypassed file. As a result, it mostly benefits to large files
+    pub fn start_file_with_hint(
+        &mut self,
+        filename: impl AsRef<str>,
+        hint: CompressionHint,
+    ) -> Result<ArchiveFileID, Error> {
+        check_state!(self.state, OpenedFiles);
+        if let Some(limit) = self.config.max_files {
+            if self.files_info.len() >= limit {
+                return Err(Error::FileCountLimitExceeded(limit));
+            }
         }
+        let filename = self.config.path_normalization.apply(filename.as_ref())?;
+        let filename = self.available_filename(&filename)?;
+        let filename = filename.as_str();
 
         // Create ID for this file
         let id = self.next_id;
-        self.next_id += 1;
+        self.next_id = id.checked_next().ok_or(Error::FileIDExhausted)?;
+        self.write_padding()?;
         self.current_id = id;
         self.files_info.insert(filename.to_string(), id);
 
@@ -606,7 +1973,7 @@ impl<'a, W: Write> ArchiveWriter<'a, W> {
             filename: filename.to_string(),
             id,
         }
-        .dump(&mut self.dest)?;
+        .dump_encoded(&mut self.dest, self.config.metadata_encoding)?;
 
         match &mut self.state {
             ArchiveWriterState::OpenedFiles { ids, hashes } => {
@@ -620,547 +1987,3633 @@ impl<'a, W: Write> ArchiveWriter<'a, W> {
                 ));
             }
         }
+        if let Some(algorithm) = self.config.checksum_algorithm() {
+            self.checksums.insert(id, ChecksumHasher::new(algorithm));
+        }
+        if self.config.is_layers_enabled(Layers::COMPRESS) {
+            match self.config.compress.resolve_hint(filename, hint) {
+                Some(false) => {}
+                bypass => {
+                    self.compression_bypass.insert(id, bypass);
+                }
+            }
+        }
+        if self.config.dedup {
+            self.dedup_pending.insert(id, Vec::new());
+        }
+        self.opened_names.insert(id, filename.to_string());
+        self.config.progress.on_file_start(filename);
         Ok(id)
     }
 
-    pub fn append_file_content<U: Read>(
+    /// Start a new file, whose content is encrypted with a key of its own,
+    /// for `recipients` only
+    ///
+    /// The archive must be encrypted: reading the file requires the archive
+    /// key, as for the other files, then the private key of one of
+    /// `recipients` (passwords are not supported). The file name, size and
+    /// metadata are visible to any reader of the archive. Its content is
+    /// neither compressed nor deduplicated, and sparse holes are stored as
+    /// regular content. Its hash and checksum are the ones of the encrypted
+    /// content, so that its integrity can be verified without its key
+    pub fn start_file_for_recipients(
+        &mut self,
+        filename: impl AsRef<str>,
+        recipients: &[PublicKey],
+    ) -> Result<ArchiveFileID, Error> {
+        if !self.config.is_layers_enabled(Layers::ENCRYPT) {
+            return Err(Error::BadAPIArgument(
+                "[start_file_for_recipients] The archive must be encrypted".to_string(),
+            ));
+        }
+        if recipients.is_empty() {
+            return Err(Error::BadAPIArgument(
+                "[start_file_for_recipients] At least one recipient is needed".to_string(),
+            ));
+        }
+        let id = self.start_file(filename)?;
+        let mut csprng = self
+            .config
+            .encrypt
+            .csprng(CSPRNG_STREAM_FILES.wrapping_add(id.0));
+        let (encryptor, recipients) = FileEncryptor::new(recipients, &mut csprng)?;
+        self.append_file_recipients(id, recipients)?;
+        self.file_encryptors.insert(id, encryptor);
+        Ok(id)
+    }
+
+    /// Emit the `recipients` block of file `id`, whose content is then
+    /// written as is. To be found by readers, it must directly follow the
+    /// start of the file
+    pub(crate) fn append_file_recipients(
         &mut self,
         id: ArchiveFileID,
-        size: u64,
-        src: U,
+        recipients: FileRecipientsPersistent,
     ) -> Result<(), Error> {
         check_state_file_opened!(&self.state, &id);
 
-        if size == 0 {
-            // Avoid creating 0-sized block
-            return Ok(());
+        self.mark_continuous_block(id)?;
+        // Use std::io::Empty as a readable placeholder type
+        ArchiveFileBlock::FileRecipients::<std::io::Empty> { id, recipients }
+            .dump_encoded(&mut self.dest, self.config.metadata_encoding)?;
+        self.features |= FeatureFlags::HAS_FILE_RECIPIENTS;
+        // Encrypted data is not compressible, and must not be deduplicated
+        if self.config.is_layers_enabled(Layers::COMPRESS) {
+            self.compression_bypass.insert(id, Some(true));
         }
+        self.dedup_pending.remove(&id);
+        Ok(())
+    }
+
+    /// Emit the authentication `tag` of the content of `id`, encrypted for
+    /// its own recipients
+    pub(crate) fn append_file_tag(
+        &mut self,
+        id: ArchiveFileID,
+        tag: [u8; TAG_LENGTH],
+    ) -> Result<(), Error> {
+        check_state_file_opened!(&self.state, &id);
 
         self.mark_continuous_block(id)?;
-        self.extend_file_size(id, size)?;
-        let src = self.state.wrap_with_hash(id, src)?;
+        // Use std::io::Empty as a readable placeholder type
+        ArchiveFileBlock::FileTag::<std::io::Empty> { id, tag }
+            .dump_encoded(&mut self.dest, self.config.metadata_encoding)?;
+        Ok(())
+    }
 
-        ArchiveFileBlock::FileContent {
-            id,
-            length: size,
-            data: Some(src),
+    /// Add a directory entry, with optional `metadata`
+    ///
+    /// Directories are only needed to keep track of empty directories, or of
+    /// directories metadata: a file path can be used without adding its
+    /// parent directories first
+    pub fn add_directory(
+        &mut self,
+        path: &str,
+        metadata: Option<FileMetadata>,
+    ) -> Result<(), Error> {
+        check_state!(self.state, OpenedFiles);
+        let path = self.config.path_normalization.apply(path)?;
+        let path = path.as_str();
+
+        if self.is_path_used(path) {
+            return Err(Error::DuplicateFilename);
         }
-        .dump(&mut self.dest)
+        // Use std::io::Empty as a readable placeholder type
+        ArchiveFileBlock::DirectoryEntry::<std::io::Empty> {
+            path: path.to_string(),
+            metadata,
+        }
+        .dump_encoded(&mut self.dest, self.config.metadata_encoding)?;
+        self.features |= FeatureFlags::HAS_DIRECTORIES;
+        self.directories.insert(path.to_string());
+        Ok(())
     }
 
-    pub fn end_file(&mut self, id: ArchiveFileID) -> Result<(), Error> {
-        check_state_file_opened!(&self.state, &id);
+    /// Add a symbolic link named `link_name`, pointing to `target`, with
+    /// optional `metadata`
+    ///
+    /// `target` is stored as is, and is not required to be in the archive
+    pub fn add_symlink(
+        &mut self,
+        link_name: &str,
+        target: &str,
+        metadata: Option<FileMetadata>,
+    ) -> Result<(), Error> {
+        check_state!(self.state, OpenedFiles);
+        let link_name = self.config.path_normalization.apply(link_name)?;
+        let link_name = link_name.as_str();
 
-        let hash = match &mut self.state {
-            ArchiveWriterState::OpenedFiles { ids, hashes } => {
-                let hash = hashes.remove(&id).ok_or_else(|| {
-                    Error::WrongWriterState("[EndFile] Unable to retrieve the hash".to_string())
-                })?;
-                vec_remove_item(ids, &id);
-                hash.finalize().try_into().or_else(
-                    // Never happens, as hash is a Sha256, to a Sha256Hash
-                    |_| {
-                        Err(Error::WrongWriterState(
-                            "[EndFile] Hash with wrong size".to_string(),
-                        ))
-                    },
-                )?
-            }
-            _ => {
-                // Never happens, due to `check_state_file_opened!`
-                return Err(Error::WrongWriterState(
-                    "[EndFile] State have changes inside end_file".to_string(),
-                ));
+        if self.is_path_used(link_name) {
+            return Err(Error::DuplicateFilename);
+        }
+        // Use std::io::Empty as a readable placeholder type
+        ArchiveFileBlock::Symlink::<std::io::Empty> {
+            link_name: link_name.to_string(),
+            target: target.to_string(),
+            metadata,
+        }
+        .dump_encoded(&mut self.dest, self.config.metadata_encoding)?;
+        self.features |= FeatureFlags::HAS_SYMLINKS;
+        self.symlinks
+            .insert(link_name.to_string(), target.to_string());
+        Ok(())
+    }
+
+    /// Make the file `old_name`, already ended, also available as `new_name`
+    ///
+    /// The content is not written again: a `FileAlias` block refers to the
+    /// original file. Readers list the file under both names. Combined with
+    /// `open_append`, it renames a file of an existing archive without
+    /// rewriting it
+    pub fn rename_file(&mut self, old_name: &str, new_name: &str) -> Result<(), Error> {
+        check_state!(self.state, OpenedFiles);
+        let old_name = self.config.path_normalization.apply(old_name)?;
+        let old_name = old_name.as_str();
+        let new_name = self.config.path_normalization.apply(new_name)?;
+        let new_name = new_name.as_str();
+
+        let original_id = match self.files_info.get(old_name) {
+            Some(id) if !self.opened_names.contains_key(id) => *id,
+            Some(_) => {
+                return Err(Error::BadAPIArgument(format!(
+                    "The file {} is not ended",
+                    old_name
+                )))
             }
+            None => return Err(Error::BadAPIArgument(format!("Unknown file {}", old_name))),
         };
-
-        self.mark_continuous_block(id)?;
-        self.mark_eof(id)?;
+        if self.is_path_used(new_name) {
+            return Err(Error::DuplicateFilename);
+        }
         // Use std::io::Empty as a readable placeholder type
-        ArchiveFileBlock::EndOfFile::<std::io::Empty> { id, hash }.dump(&mut self.dest)?;
-
+        ArchiveFileBlock::FileAlias::<std::io::Empty> {
+            original_id,
+            new_name: new_name.to_string(),
+        }
+        .dump_encoded(&mut self.dest, self.config.metadata_encoding)?;
+        self.files_info.insert(new_name.to_string(), original_id);
         Ok(())
     }
 
-    pub fn add_file<U: Read>(&mut self, filename: &str, size: u64, src: U) -> Result<(), Error> {
-        let id = self.start_file(filename)?;
-        self.append_file_content(id, size, src)?;
-        self.end_file(id)
+    /// Files, directories and symbolic links share the same namespace
+    fn is_path_used(&self, path: &str) -> bool {
+        self.files_info.contains_key(path)
+            || self.abandoned_names.contains(path)
+            || self.directories.contains(path)
+            || self.symlinks.contains_key(path)
     }
 
-    /// Unwraps the inner writer
-    pub fn into_raw(self) -> W {
-        self.dest.into_raw()
+    /// Name to use for a new file named `filename`, according to the
+    /// configured `DuplicatePolicy`
+    fn available_filename(&self, filename: &str) -> Result<String, Error> {
+        if !self.is_path_used(filename) {
+            return Ok(filename.to_string());
+        }
+        match self.config.duplicate_policy {
+            DuplicatePolicy::Error => Err(Error::DuplicateFilename),
+            DuplicatePolicy::Rename => Ok((2..)
+                .map(|index: u64| format!("{}_{}", filename, index))
+                .find(|candidate| !self.is_path_used(candidate))
+                .expect("Names can't all be used")),
+        }
     }
 
-    pub fn flush(&mut self) -> io::Result<()> {
-        self.dest.flush()
+    /// Name of the opened file `id`, which might differ from the one given to
+    /// `start_file` with `DuplicatePolicy::Rename`
+    pub fn filename(&self, id: ArchiveFileID) -> Option<&str> {
+        self.opened_names.get(&id).map(String::as_str)
     }
-}
 
-// -------- Reader --------
+    /// Start a new file, with associated `metadata`
+    ///
+    /// See `start_file`
+    pub fn start_file_with_metadata(
+        &mut self,
+        filename: impl AsRef<str>,
+        metadata: &FileMetadata,
+    ) -> Result<ArchiveFileID, Error> {
+        let id = self.start_file(filename)?;
+        self.append_file_metadata(id, metadata)?;
+        Ok(id)
+    }
 
-#[derive(Debug)]
-pub struct ArchiveFile<T: Read> {
-    /// File inside a MLA Archive
-    pub filename: String,
-    pub data: T,
-    pub size: u64,
-}
+    /// Emit the `metadata` block of file `id`. To be found by readers, it must
+    /// directly follow the start of the file
+    pub(crate) fn append_file_metadata(
+        &mut self,
+        id: ArchiveFileID,
+        metadata: &FileMetadata,
+    ) -> Result<(), Error> {
+        check_state_file_opened!(&self.state, &id);
 
-#[derive(PartialEq, Debug)]
-enum BlocksToFileReaderState {
-    // Remaining size
-    InFile(usize),
-    Ready,
-    Finish,
-}
+        self.mark_continuous_block(id)?;
+        // Use std::io::Empty as a readable placeholder type
+        ArchiveFileBlock::FileMetadata::<std::io::Empty> {
+            id,
+            metadata: metadata.clone(),
+        }
+        .dump_encoded(&mut self.dest, self.config.metadata_encoding)?;
+        self.features |= FeatureFlags::HAS_FILE_METADATA;
+        Ok(())
+    }
 
-#[derive(Debug)]
-pub struct BlocksToFileReader<'a, R: Read + Seek> {
-    /// This structure wraps the internals to get back a file's content
-    src: &'a mut R,
-    state: BlocksToFileReaderState,
-    /// id of the File being read
-    id: ArchiveFileID,
-    /// position in `offsets` of the last offset used
-    current_offset: usize,
-    /// List of offsets of continuous blocks corresponding to where the file can be read
-    offsets: Vec<u64>,
-}
+    /// Announce that the content of the opened file `id` will be `size`
+    /// bytes long, for instance when copying from a `File`
+    ///
+    /// A `FileSizeHint` block is emitted immediately, so that linear readers,
+    /// such as `ArchiveEntries`, can report the file size before reaching its
+    /// end. The hint is not enforced: if the content written ends up with
+    /// another size, the archive is still valid (such files are counted in
+    /// `WriteStatistics::size_hint_mismatches`), and the footer holds the
+    /// actual size
+    pub fn set_file_size_hint(&mut self, id: ArchiveFileID, size: u64) -> Result<(), Error> {
+        check_state_file_opened!(&self.state, &id);
 
-impl<'a, R: Read + Seek> BlocksToFileReader<'a, R> {
-    fn new(src: &mut R, id: ArchiveFileID, offsets: Vec<u64>) -> BlocksToFileReader<R> {
-        BlocksToFileReader {
-            src,
-            state: BlocksToFileReaderState::Ready,
-            id,
-            current_offset: 0,
-            offsets,
-        }
+        self.mark_continuous_block(id)?;
+        // Use std::io::Empty as a readable placeholder type
+        ArchiveFileBlock::FileSizeHint::<std::io::Empty> { id, size }
+            .dump_encoded(&mut self.dest, self.config.metadata_encoding)?;
+        self.size_hints.insert(id, size);
+        Ok(())
     }
 
-    /// Move `self.src` to the next continuous block
-    fn move_to_next_block(&mut self) -> Result<(), Error> {
-        self.current_offset += 1;
-        if self.current_offset >= self.offsets.len() {
-            return Err(Error::WrongReaderState(
-                "[BlocksToFileReader] No more continuous blocks".to_string(),
-            ));
+    /// Compress the content of the opened file `id` with `level`, instead of
+    /// the level of `ArchiveWriterConfig`, for instance a denser one for text
+    /// logs, or a faster one for already compressed data
+    ///
+    /// `level` applies to the configured algorithm, with the same ranges as
+    /// `ArchiveWriterConfig::with_compression_level`;
+    /// `ConfigError::CompressionLevelOutOfRange` is returned otherwise.
+    /// Decompression does not depend on the level, so the archive stays
+    /// readable by any reader. As for `start_file_with_hint`, the level
+    /// applies by compression layer block (4MB of archive data): a block uses
+    /// it if it starts while writing the content of `id`. Without the
+    /// compression layer, `level` is ignored
+    pub fn set_file_compression(
+        &mut self,
+        id: ArchiveFileID,
+        level: CompressionLevel,
+    ) -> Result<(), Error> {
+        check_state_file_opened!(&self.state, &id);
+
+        let algorithm = self.config.compress.algorithm_with_level(level)?;
+        self.compression_levels.insert(id, algorithm);
+        Ok(())
+    }
+
+    /// Attach the user-defined metadata entry `key` = `value` to the opened
+    /// file `id`, for instance its origin or a digest computed beforehand
+    ///
+    /// Entries can be set at any time before the end of the file, and are
+    /// read back with `ArchiveReader::get_file_metadata`; for a given key, the
+    /// last value set is kept. Key and value are limited to 64KiB together
+    pub fn set_file_metadata(
+        &mut self,
+        id: ArchiveFileID,
+        key: &str,
+        value: &[u8],
+    ) -> Result<(), Error> {
+        check_state_file_opened!(&self.state, &id);
+
+        self.mark_continuous_block(id)?;
+        // Use std::io::Empty as a readable placeholder type
+        ArchiveFileBlock::UserMetadata::<std::io::Empty> {
+            id,
+            key: key.to_string(),
+            value: value.to_vec(),
         }
-        self.src
-            .seek(SeekFrom::Start(self.offsets[self.current_offset]))?;
+        .dump_encoded(&mut self.dest, self.config.metadata_encoding)?;
         Ok(())
     }
-}
 
-impl<'a, T: Read + Seek> Read for BlocksToFileReader<'a, T> {
-    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
-        let (remaining, count) = match self.state {
-            BlocksToFileReaderState::Ready => {
-                // Start a new block FileContent
-                match ArchiveFileBlock::from(&mut self.src)? {
-                    ArchiveFileBlock::FileContent { length, id, .. } => {
-                        if id != self.id {
-                            self.move_to_next_block()?;
-                            return self.read(into);
-                        }
-                        let count = self.src.by_ref().take(length as u64).read(into)?;
-                        let length_usize = length as usize;
-                        (length_usize - count, count)
-                    }
-                    ArchiveFileBlock::EndOfFile { id, .. } => {
-                        if id != self.id {
-                            self.move_to_next_block()?;
-                            return self.read(into);
-                        }
-                        self.state = BlocksToFileReaderState::Finish;
-                        return Ok(0);
-                    }
-                    ArchiveFileBlock::FileStart { id, .. } => {
-                        if id != self.id {
-                            self.move_to_next_block()?;
-                            return self.read(into);
-                        }
-                        return Err(Error::WrongReaderState(
-                            "[BlocksToFileReader] Start with a wrong block type".to_string(),
-                        )
-                        .into());
-                    }
-                    ArchiveFileBlock::EndOfArchiveData => {
-                        return Err(Error::WrongReaderState(
-                            "[BlocksToFileReader] Try to read the end of the archive".to_string(),
-                        )
-                        .into());
-                    }
-                }
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(
+                archive_path = self.config.archive_path.as_deref(),
+                file_name = self.opened_names.get(&id).map(String::as_str),
+                file_id = id.0,
+                bytes = size
+            )
+        )
+    )]
+    pub fn append_file_content<U: Read>(
+        &mut self,
+        id: ArchiveFileID,
+        size: u64,
+        src: U,
+    ) -> Result<(), Error> {
+        check_state_file_opened!(&self.state, &id);
+
+        if size == 0 {
+            // Avoid creating 0-sized block
+            return Ok(());
+        }
+        if let Some(pending) = self.dedup_pending.get(&id) {
+            if (pending.len() as u64).saturating_add(size) <= self.config.dedup_max_file_size {
+                self.check_size_limit(size)?;
+                return self.buffer_file_content(id, size, src);
             }
-            BlocksToFileReaderState::InFile(remaining) => {
-                let count = self.src.by_ref().take(remaining as u64).read(into)?;
-                (remaining - count, count)
+            // Too big to be kept in memory: the file is written without
+            // deduplication
+            self.write_pending_content(id)?;
+        }
+        match self.config.file_layout {
+            FileLayout::AsAppended => {}
+            FileLayout::Sequential => match self.layout_writing {
+                Some(writing) if writing != id => {
+                    return self.keep_layout_content(id, size, src);
+                }
+                _ => {
+                    self.layout_writing = Some(id);
+                    self.write_layout_content(id)?;
+                }
+            },
+            FileLayout::Interleaved { chunk_size } => {
+                self.keep_layout_content(id, size, src)?;
+                return self.write_interleaved_chunks(chunk_size);
             }
-            BlocksToFileReaderState::Finish => {
-                return Ok(0);
+        }
+        if size < self.config.coalesce_threshold as u64 {
+            return self.coalesce_file_content(id, size, src);
+        }
+        self.check_size_limit(size)?;
+        // Contents kept before come first
+        self.write_coalesced_content()?;
+        self.write_file_content(id, size, src)
+    }
+
+    /// Write `size` bytes from `src` as the content of `id`, in one
+    /// `FileContent` block, or in blocks cut according to
+    /// `ArchiveWriterConfig::block_boundary`. The size limit must have been
+    /// checked
+    fn write_file_content<U: Read>(
+        &mut self,
+        id: ArchiveFileID,
+        size: u64,
+        src: U,
+    ) -> Result<(), Error> {
+        let boundary = match self.config.block_boundary {
+            Some(boundary) => boundary,
+            None => return self.dump_file_content(id, size, src),
+        };
+        let mut src = src.take(size);
+        let mut buf = vec![0u8; CHUNKING_READ_SIZE];
+        let mut read = 0;
+        loop {
+            let count = match src.read(&mut buf) {
+                Ok(0) => break,
+                Ok(count) => count,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+            read += count as u64;
+            let blocks = self
+                .chunkers
+                .entry(id)
+                .or_insert_with(|| Chunker::new(boundary))
+                .push(&buf[..count]);
+            for block in blocks {
+                self.dump_file_content(id, block.len() as u64, block.as_slice())?;
             }
+        }
+        if read != size {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(())
+    }
+
+    /// Write the content of `id` since its last block boundary, if any, as
+    /// one block
+    fn write_chunked_content(&mut self, id: ArchiveFileID) -> Result<(), Error> {
+        let content = match self.chunkers.remove(&id) {
+            Some(chunker) => chunker.finish(),
+            None => return Ok(()),
         };
-        if remaining > 0 {
-            self.state = BlocksToFileReaderState::InFile(remaining);
-        } else {
-            // remaining is 0 (> never happens thanks to take)
-            self.state = BlocksToFileReaderState::Ready;
+        if content.is_empty() {
+            return Ok(());
         }
-        Ok(count)
+        self.dump_file_content(id, content.len() as u64, content.as_slice())
     }
-}
 
-#[derive(Serialize, Deserialize)]
-#[cfg_attr(test, derive(PartialEq, Debug))]
-struct FileInfo {
-    /// File information to save in the footer
+    /// Write `size` bytes from `src` as a `FileContent` block of `id`
+    fn dump_file_content<U: Read>(
+        &mut self,
+        id: ArchiveFileID,
+        size: u64,
+        mut src: U,
+    ) -> Result<(), Error> {
+        self.mark_continuous_block(id)?;
+        self.extend_file_size(id, size)?;
+        let (bypass, sample) = self.compression_bypass_of(id, size, &mut src)?;
+        let src = FileEncryptReader::new(
+            io::Cursor::new(sample).chain(src),
+            self.file_encryptors.get_mut(&id),
+        );
+        let src = self.state.wrap_with_hash(id, src)?;
+
+        self.dest.set_compression_bypass(bypass);
+        self.dest
+            .set_compression_level(self.compression_levels.get(&id).copied());
+        match self.checksums.get_mut(&id) {
+            Some(checksum) => ArchiveFileBlock::FileContent {
+                id,
+                length: size,
+                data: Some(ChecksumWrapperReader::new(src, checksum)),
+            }
+            .dump_encoded(&mut self.dest, self.config.metadata_encoding)?,
+            None => ArchiveFileBlock::FileContent {
+                id,
+                length: size,
+                data: Some(src),
+            }
+            .dump_encoded(&mut self.dest, self.config.metadata_encoding)?,
+        }
+        self.dest.set_compression_bypass(false);
+        self.dest.set_compression_level(None);
+        self.report_bytes_written(id, size);
+        Ok(())
+    }
+
+    /// Append to the file `id` a content already compressed with
+    /// `algorithm`, read as `length` bytes from `compressed_data`
     ///
-    /// Offsets of continuous chunks of `ArchiveFileBlock`
-    offsets: Vec<u64>,
-    /// Size of the file, in bytes
-    size: u64,
-    /// Offset of the ArchiveFileBlock::EndOfFile
+    /// The compressed data is stored as is, in a block of its own, and is not
+    /// compressed again by the compression layer. It is only decompressed to
+    /// compute the file hash, checksum and size. It may be made of several
+    /// frames, but must decompress to at most 16MB: bigger contents must be
+    /// appended in several calls, each one independently compressed.
     ///
-    /// This offset is used to retrieve information from the EoF tag, such as
-    /// the file hash
-    eof_offset: u64,
-}
+    /// This requires `ArchiveWriterConfig::accept_pre_compressed`. Files
+    /// encrypted for their own recipients are not supported, and files with
+    /// a pre-compressed content are not deduplicated. Invalid arguments,
+    /// including data which can't be decompressed, are reported with
+    /// `Error::BadAPIArgument`
+    pub fn append_file_content_precompressed<U: Read>(
+        &mut self,
+        id: ArchiveFileID,
+        length: u64,
+        compressed_data: U,
+        algorithm: CompressionAlgorithm,
+    ) -> Result<(), Error> {
+        check_state_file_opened!(&self.state, &id);
 
-pub struct ArchiveReader<'a, R: 'a + Read + Seek> {
-    /// MLA Archive format Reader
+        if !self.config.compress.accept_pre_compressed {
+            return Err(Error::BadAPIArgument(
+                "[append_file_content_precompressed] Pre-compressed contents are not accepted, see `ArchiveWriterConfig::accept_pre_compressed`".to_string(),
+            ));
+        }
+        if algorithm == CompressionAlgorithm::None {
+            return Err(Error::BadAPIArgument(
+                "[append_file_content_precompressed] Use `append_file_content` for uncompressed contents".to_string(),
+            ));
+        }
+        if length > PRECOMPRESSED_MAX_SIZE {
+            return Err(Error::BadAPIArgument(
+                "[append_file_content_precompressed] Compressed content too big".to_string(),
+            ));
+        }
+        if self.file_encryptors.contains_key(&id) {
+            return Err(Error::BadAPIArgument(
+                "[append_file_content_precompressed] Not supported for files encrypted for their own recipients".to_string(),
+            ));
+        }
+        if length == 0 {
+            // Avoid creating 0-sized block
+            return Ok(());
+        }
+        self.check_size_limit(length)?;
 
-    /// User's reading configuration
-    // config is not used for now after reader creation,
-    // but it could in the future
-    #[allow(dead_code)]
-    config: ArchiveReaderConfig,
-    /// Source
-    src: Box<dyn 'a + LayerReader<'a, R>>,
-    /// Metadata (from footer if any)
-    metadata: Option<ArchiveFooter>,
-}
+        let mut data = Vec::new();
+        compressed_data.take(length).read_to_end(&mut data)?;
+        if data.len() as u64 != length {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        let content =
+            decompress_precompressed(algorithm, &data, PRECOMPRESSED_MAX_SIZE).map_err(|_| {
+                Error::BadAPIArgument(
+                    "[append_file_content_precompressed] Unable to decompress the content"
+                        .to_string(),
+                )
+            })?;
+        let size = content.len() as u64;
+        if size == 0 {
+            return Ok(());
+        }
 
-impl<'b, R: 'b + Read + Seek> ArchiveReader<'b, R> {
-    pub fn from_config(mut src: R, mut config: ArchiveReaderConfig) -> Result<Self, Error> {
-        let header = ArchiveHeader::from(&mut src)?;
-        config.load_persistent(header.config)?;
+        // The contents kept in memory, for deduplication, the file layout or
+        // coalescing, if any, must come first
+        self.write_pending_content(id)?;
+        self.write_layout_content(id)?;
+        self.write_coalesced_content()?;
+        self.write_chunked_content(id)?;
+        self.mark_continuous_block(id)?;
+        self.extend_file_size(id, size)?;
+        io::copy(
+            &mut self.state.wrap_with_hash(id, content.as_slice())?,
+            &mut io::sink(),
+        )?;
+        if let Some(checksum) = self.checksums.get_mut(&id) {
+            checksum.update(&content);
+        }
 
-        // Pin the current position (after header) as the new 0
-        let mut raw_src = Box::new(RawLayerReader::new(src));
-        raw_src.reset_position()?;
+        self.dest.set_compression_bypass(true);
+        ArchiveFileBlock::FilePrecompressedContent::<io::Empty> {
+            id,
+            algorithm,
+            uncompressed_length: size,
+            data,
+        }
+        .dump_encoded(&mut self.dest, self.config.metadata_encoding)?;
+        self.dest.set_compression_bypass(false);
+        self.features |= FeatureFlags::HAS_PRECOMPRESSED;
+        self.report_bytes_written(id, size);
+        Ok(())
+    }
 
-        // Enable layers depending on user option. Order is relevant
-        let mut src: Box<dyn 'b + LayerReader<'b, R>> = raw_src;
-        if config.layers_enabled.contains(Layers::ENCRYPT) {
-            src = Box::new(EncryptionLayerReader::new(src, &config.encrypt)?);
+    /// Keep `size` bytes from `src` in memory, with the contents of `id` kept
+    /// before, until they reach the coalescing threshold together
+    fn coalesce_file_content<U: Read>(
+        &mut self,
+        id: ArchiveFileID,
+        size: u64,
+        src: U,
+    ) -> Result<(), Error> {
+        if self.coalesce.as_ref().is_some_and(|buffer| buffer.id != id) {
+            self.write_coalesced_content()?;
         }
-        if config.layers_enabled.contains(Layers::COMPRESS) {
-            src = Box::new(CompressionLayerReader::new(src)?);
+        // The contents kept are written as one block
+        let kept = self
+            .coalesce
+            .as_ref()
+            .map_or(0, |buffer| buffer.data.len() as u64);
+        self.check_size_limit(kept + size)?;
+        let threshold = self.config.coalesce_threshold;
+        let buffer = self.coalesce.get_or_insert_with(|| WriteCoalesceBuffer {
+            id,
+            data: Vec::with_capacity(threshold),
+        });
+        let read = src.take(size).read_to_end(&mut buffer.data)?;
+        if read as u64 != size {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
         }
-        src.initialize()?;
-
-        // Read the footer
-        let metadata = Some(ArchiveFooter::deserialize_from(&mut src)?);
-
-        // Reset the position for further uses
-        src.seek(SeekFrom::Start(0))?;
+        if buffer.data.len() >= threshold {
+            self.write_coalesced_content()?;
+        }
+        Ok(())
+    }
 
-        Ok(ArchiveReader {
-            config,
-            src,
-            metadata,
-        })
+    /// Write the small contents kept in memory, if any, as one block
+    fn write_coalesced_content(&mut self) -> Result<(), Error> {
+        match self.coalesce.take() {
+            Some(buffer) => {
+                self.write_file_content(buffer.id, buffer.data.len() as u64, buffer.data.as_slice())
+            }
+            None => Ok(()),
+        }
     }
 
-    pub fn new(src: R) -> Result<Self, Error> {
-        Self::from_config(src, ArchiveReaderConfig::new())
+    /// Keep `size` bytes from `src` in memory, as the content of `id`, until
+    /// the file layout allows to write it
+    fn keep_layout_content<U: Read>(
+        &mut self,
+        id: ArchiveFileID,
+        size: u64,
+        src: U,
+    ) -> Result<(), Error> {
+        self.check_size_limit(size)?;
+        let pending = self.layout_pending.entry(id).or_default();
+        let read = src.take(size).read_to_end(pending)?;
+        if read as u64 != size {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(())
     }
 
-    /// Return an iterator on filenames present in the archive
-    ///
-    /// Order is not relevant, and may change
-    pub fn list_files(&self) -> Result<impl Iterator<Item = &String>, Error> {
-        if let Some(ArchiveFooter { files_info, .. }) = &self.metadata {
-            Ok(files_info.keys())
-        } else {
-            Err(Error::MissingMetadata)
+    /// Write the content of `id` kept for the file layout, if any, as one
+    /// block
+    fn write_layout_content(&mut self, id: ArchiveFileID) -> Result<(), Error> {
+        match self.layout_pending.remove(&id) {
+            Some(content) if !content.is_empty() => {
+                self.write_file_content(id, content.len() as u64, content.as_slice())
+            }
+            _ => Ok(()),
         }
     }
 
-    pub fn get_hash<'a>(&'a mut self, filename: &str) -> Result<Option<Sha256Hash>, Error> {
-        if let Some(ArchiveFooter { files_info }) = &self.metadata {
-            // Get file relative information
-            let file_info = match files_info.get(filename) {
-                None => return Ok(None),
-                Some(finfo) => finfo,
-            };
-            // Set the inner layer at the start of the EoF tag
-            self.src.seek(SeekFrom::Start(file_info.eof_offset))?;
-
-            // Return the file hash
-            match ArchiveFileBlock::from(&mut self.src)? {
-                ArchiveFileBlock::EndOfFile { hash, .. } => Ok(Some(hash)),
-                _ => Err(Error::WrongReaderState(
-                    "[ArchiveReader] eof_offset must point to a EoF".to_string(),
-                )),
+    /// With `FileLayout::Interleaved`, write rounds of `chunk_size` bytes of
+    /// each opened file, as long as all of them have that much content kept
+    fn write_interleaved_chunks(&mut self, chunk_size: usize) -> Result<(), Error> {
+        let ids: Vec<ArchiveFileID> = match &self.state {
+            // Deduplicated files are written when ended
+            ArchiveWriterState::OpenedFiles { ids, .. } => ids
+                .iter()
+                .filter(|id| !self.dedup_pending.contains_key(id))
+                .copied()
+                .collect(),
+            _ => return Ok(()),
+        };
+        while !ids.is_empty()
+            && ids.iter().all(|id| {
+                self.layout_pending
+                    .get(id)
+                    .is_some_and(|pending| pending.len() >= chunk_size)
+            })
+        {
+            for id in &ids {
+                let chunk: Vec<u8> = match self.layout_pending.get_mut(id) {
+                    Some(pending) => pending.drain(..chunk_size).collect(),
+                    None => continue,
+                };
+                self.write_file_content(*id, chunk.len() as u64, chunk.as_slice())?;
             }
-        } else {
-            Err(Error::MissingMetadata)
         }
+        Ok(())
     }
 
-    #[allow(clippy::type_complexity)]
-    pub fn get_file<'a>(
-        &'a mut self,
-        filename: String,
-    ) -> Result<Option<ArchiveFile<BlocksToFileReader<Box<dyn 'b + LayerReader<'b, R>>>>>, Error>
-    {
-        if let Some(ArchiveFooter { files_info }) = &self.metadata {
-            // Get file relative information
-            let file_info = match files_info.get(&filename) {
-                None => return Ok(None),
-                Some(finfo) => finfo,
-            };
-            if file_info.offsets.is_empty() {
-                return Err(Error::WrongReaderState(
-                    "[ArchiveReader] A file must have at least one offset".to_string(),
-                ));
+    /// Keep `size` bytes from `src` in memory, as the content of `id`, until
+    /// it is known whether it is a duplicate
+    fn buffer_file_content<U: Read>(
+        &mut self,
+        id: ArchiveFileID,
+        size: u64,
+        src: U,
+    ) -> Result<(), Error> {
+        self.extend_file_size(id, size)?;
+        let src = self.state.wrap_with_hash(id, src.take(size))?;
+        let pending = self.dedup_pending.get_mut(&id).ok_or_else(|| {
+            Error::WrongWriterState("[buffer_file_content] Unable to find the ID".to_string())
+        })?;
+        let read = match self.checksums.get_mut(&id) {
+            Some(checksum) => ChecksumWrapperReader::new(src, checksum).read_to_end(pending)?,
+            None => { src }.read_to_end(pending)?,
+        };
+        if read as u64 != size {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        self.report_bytes_written(id, size);
+        Ok(())
+    }
+
+    /// Write the content of `id` kept in memory, if any. It has already been
+    /// hashed
+    fn write_pending_content(&mut self, id: ArchiveFileID) -> Result<(), Error> {
+        let content = match self.dedup_pending.remove(&id) {
+            Some(content) if !content.is_empty() => content,
+            _ => return Ok(()),
+        };
+        let size = content.len() as u64;
+        self.mark_continuous_block(id)?;
+        let mut src = content.as_slice();
+        let (bypass, sample) = self.compression_bypass_of(id, size, &mut src)?;
+        self.dest.set_compression_bypass(bypass);
+        self.dest
+            .set_compression_level(self.compression_levels.get(&id).copied());
+        ArchiveFileBlock::FileContent {
+            id,
+            length: size,
+            data: Some(io::Cursor::new(sample).chain(src)),
+        }
+        .dump_encoded(&mut self.dest, self.config.metadata_encoding)?;
+        self.dest.set_compression_bypass(false);
+        self.dest.set_compression_level(None);
+        Ok(())
+    }
+
+    /// End the content of `id`, with `hash`: write it, or a reference to an
+    /// identical content already written
+    fn end_pending_content(&mut self, id: ArchiveFileID, hash: Sha256Hash) -> Result<(), Error> {
+        let size = match self.dedup_pending.get(&id) {
+            Some(content) if !content.is_empty() => content.len() as u64,
+            _ => return self.write_pending_content(id),
+        };
+        let refers_to_id = match self.dedup_index.get(&(hash, size)) {
+            Some(refers_to_id) => *refers_to_id,
+            None => {
+                self.write_pending_content(id)?;
+                self.dedup_index.insert((hash, size), id);
+                return Ok(());
             }
-            // Set the inner layer at the start of the file
-            self.src.seek(SeekFrom::Start(file_info.offsets[0]))?;
+        };
+        self.dedup_pending.remove(&id);
+        self.write_dedup_block(id, refers_to_id)
+    }
 
-            // Read file information header
-            let id_file_block = match ArchiveFileBlock::from(&mut self.src)? {
-                ArchiveFileBlock::FileStart { id, .. } => id,
-                _ => {
-                    return Err(Error::WrongReaderState(
-                        "[ArchiveReader] A file must start with a FileStart".to_string(),
-                    ));
-                }
-            };
+    /// Write a `Dedup` block for `id`, referring to the content of
+    /// `refers_to_id`
+    fn write_dedup_block(
+        &mut self,
+        id: ArchiveFileID,
+        refers_to_id: ArchiveFileID,
+    ) -> Result<(), Error> {
+        // The content is read through the blocks of `refers_to_id`, after the
+        // `Dedup` one
+        let offsets = self
+            .ids_info
+            .get(&refers_to_id)
+            .map(|info| info.offsets.clone())
+            .ok_or_else(|| {
+                Error::WrongWriterState("[write_dedup_block] Unable to find the ID".to_string())
+            })?;
+        self.mark_continuous_block(id)?;
+        // Use std::io::Empty as a readable placeholder type
+        ArchiveFileBlock::Dedup::<std::io::Empty> { id, refers_to_id }
+            .dump_encoded(&mut self.dest, self.config.metadata_encoding)?;
+        self.features |= FeatureFlags::HAS_DEDUP;
+        if let Some(file_info) = self.ids_info.get_mut(&id) {
+            file_info.offsets.extend(offsets);
+        }
+        Ok(())
+    }
 
-            // Instantiate the file representation
-            let reader =
-                BlocksToFileReader::new(&mut self.src, id_file_block, file_info.offsets.clone());
-            Ok(Some(ArchiveFile {
-                filename,
-                data: reader,
-                size: file_info.size,
-            }))
-        } else {
-            Err(Error::MissingMetadata)
+    /// Set the content of `id`, empty so far, to the one of the ended file
+    /// `refers_to_id`, whose hash state is `hash`
+    ///
+    /// This is used to repair deduplicated files, whose content cannot be read
+    /// again. The checksum of `id`, if enabled, is not written
+    pub(crate) fn append_file_dedup(
+        &mut self,
+        id: ArchiveFileID,
+        refers_to_id: ArchiveFileID,
+        hash: Sha256,
+    ) -> Result<(), Error> {
+        check_state_file_opened!(&self.state, &id);
+        let size = match (self.ids_info.get(&id), self.ids_info.get(&refers_to_id)) {
+            (Some(file_info), Some(original)) if file_info.size == 0 => original.size,
+            _ => {
+                return Err(Error::WrongWriterState(
+                    "[append_file_dedup] Unable to refer to this content".to_string(),
+                ));
+            }
+        };
+        if let ArchiveWriterState::OpenedFiles { ids, hashes } = &mut self.state {
+            if ids.contains(&refers_to_id) {
+                return Err(Error::WrongWriterState(
+                    "[append_file_dedup] The referred file is not ended".to_string(),
+                ));
+            }
+            hashes.insert(id, hash);
         }
+        self.dedup_pending.remove(&id);
+        self.checksums.remove(&id);
+        self.extend_file_size(id, size)?;
+        self.write_dedup_block(id, refers_to_id)
     }
-}
 
-// This code is very similar with MLAArchiveReader
+    /// Whether the content of `id` must not be compressed, deciding it on the
+    /// beginning of `src` if needed. The bytes read from `src` to decide are
+    /// returned, to be written before the rest of `src`
+    fn compression_bypass_of<U: Read>(
+        &mut self,
+        id: ArchiveFileID,
+        size: u64,
+        src: &mut U,
+    ) -> Result<(bool, Vec<u8>), Error> {
+        let mut sample = Vec::new();
+        let bypass = match self.compression_bypass.get(&id) {
+            None => false,
+            Some(Some(bypass)) => *bypass,
+            Some(None) => {
+                let sample_size = std::cmp::min(size, ENTROPY_SAMPLE_SIZE as u64);
+                src.take(sample_size).read_to_end(&mut sample)?;
+                let bypass = self.config.compress.is_bypassed_sample(&sample);
+                self.compression_bypass.insert(id, Some(bypass));
+                bypass
+            }
+        };
+        Ok((bypass, sample))
+    }
 
-pub struct ArchiveFailSafeReader<'a, R: 'a + Read> {
-    /// MLA Archive format Reader (fail-safe)
+    /// Report a block of `size` bytes written for the file `id`
+    fn report_bytes_written(&mut self, id: ArchiveFileID, size: u64) {
+        self.content_size += size;
+        let total = self.ids_info.get(&id).map_or(size, |info| info.size);
+        self.config.progress.on_bytes_written(id, size, total);
+    }
 
-    /// User's reading configuration
-    // config is not used for now after reader creation,
-    // but it could in the future
-    #[allow(dead_code)]
-    config: ArchiveReaderConfig,
-    /// Source
-    src: Box<dyn 'a + LayerFailSafeReader<'a, R>>,
-}
+    /// Append `length` bytes to the file `id`, described by `data` parts
+    ///
+    /// Runs of zero bytes (`SparseData::Zeroes`) are stored as holes, taking
+    /// a constant space in the archive whatever their length. `length` must
+    /// be the total length of `data`
+    pub fn append_file_sparse(
+        &mut self,
+        id: ArchiveFileID,
+        length: u64,
+        data: &[SparseData],
+    ) -> Result<(), Error> {
+        check_state_file_opened!(&self.state, &id);
 
-// Size of the repaired file blocks
-const CACHE_SIZE: usize = 8 * 1024 * 1024; // 8MB
+        let data_length = data
+            .iter()
+            .try_fold(0u64, |acc, part| acc.checked_add(part.len()));
+        if data_length != Some(length) {
+            return Err(Error::BadAPIArgument(
+                "[append_file_sparse] `length` is not the length of `data`".to_string(),
+            ));
+        }
 
-/// Used to update the error state only if it was NoError
-/// ```
-/// update_error!(error_var, FailSafeReadError::...)
-/// ``̀
-macro_rules! update_error {
-    ( $x:ident = $y:expr ) => {
-        #[allow(clippy::single_match)]
-        match $x {
-            FailSafeReadError::NoError => {
-                $x = $y;
+        for part in data {
+            match part {
+                SparseData::Data(content) => {
+                    self.append_file_content(id, content.len() as u64, *content)?
+                }
+                SparseData::Zeroes(0) => {
+                    // Avoid creating 0-sized block
+                }
+                SparseData::Zeroes(length) => self.append_file_hole(id, *length)?,
             }
-            _ => {}
         }
-    };
-}
+        Ok(())
+    }
 
-impl<'b, R: 'b + Read> ArchiveFailSafeReader<'b, R> {
-    pub fn from_config(mut src: R, mut config: ArchiveReaderConfig) -> Result<Self, Error> {
-        let header = ArchiveHeader::from(&mut src)?;
-        config.load_persistent(header.config)?;
+    /// Append a hole of `length` zero bytes to the file `id`
+    fn append_file_hole(&mut self, id: ArchiveFileID, length: u64) -> Result<(), Error> {
+        if self.file_encryptors.contains_key(&id) {
+            // Holes can't be encrypted, store the zeroes as content
+            return self.append_file_content(id, length, io::repeat(0).take(length));
+        }
+        // Files with holes are not deduplicated
+        self.write_pending_content(id)?;
+        self.write_layout_content(id)?;
+        self.write_coalesced_content()?;
+        self.write_chunked_content(id)?;
+        self.mark_continuous_block(id)?;
+        self.extend_file_size(id, length)?;
+
+        // Hashes and checksums are computed on the actual content
+        let mut src = self.state.wrap_with_hash(id, io::repeat(0).take(length))?;
+        match self.checksums.get_mut(&id) {
+            Some(checksum) => io::copy(
+                &mut ChecksumWrapperReader::new(src, checksum),
+                &mut io::sink(),
+            )?,
+            None => io::copy(&mut src, &mut io::sink())?,
+        };
 
-        // Enable layers depending on user option. Order is relevant
-        let mut src: Box<dyn 'b + LayerFailSafeReader<'b, R>> =
-            Box::new(RawLayerFailSafeReader::new(src));
-        if config.layers_enabled.contains(Layers::ENCRYPT) {
-            src = Box::new(EncryptionLayerFailSafeReader::new(src, &config.encrypt)?);
+        // Use std::io::Empty as a readable placeholder type
+        ArchiveFileBlock::FileSparseHole::<std::io::Empty> { id, length }
+            .dump_encoded(&mut self.dest, self.config.metadata_encoding)?;
+        self.features |= FeatureFlags::HAS_SPARSE_HOLES;
+        self.report_bytes_written(id, length);
+        Ok(())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(
+                archive_path = self.config.archive_path.as_deref(),
+                file_name = self.opened_names.get(&id).map(String::as_str),
+                file_id = id.0,
+                bytes = tracing::field::Empty
+            )
+        )
+    )]
+    pub fn end_file(&mut self, id: ArchiveFileID) -> Result<(), Error> {
+        check_state_file_opened!(&self.state, &id);
+        // Unless they are written as appended, contents kept for another file
+        // are written with the rest of it
+        if self.config.file_layout == FileLayout::AsAppended
+            || self.coalesce.as_ref().is_some_and(|buffer| buffer.id == id)
+        {
+            self.write_coalesced_content()?;
         }
-        if config.layers_enabled.contains(Layers::COMPRESS) {
-            src = Box::new(CompressionLayerFailSafeReader::new(src)?);
+        self.write_layout_content(id)?;
+        self.write_chunked_content(id)?;
+        if self.layout_writing == Some(id) {
+            self.layout_writing = None;
         }
 
-        Ok(Self { config, src })
+        if let Some(encryptor) = self.file_encryptors.remove(&id) {
+            self.append_file_tag(id, encryptor.into_tag())?;
+        }
+
+        let hash = match &mut self.state {
+            ArchiveWriterState::OpenedFiles { ids, hashes } => {
+                let hash = hashes.remove(&id).ok_or_else(|| {
+                    Error::WrongWriterState("[EndFile] Unable to retrieve the hash".to_string())
+                })?;
+                vec_remove_item(ids, &id);
+                hash.finalize().try_into().or_else(
+                    // Never happens, as hash is a Sha256, to a Sha256Hash
+                    |_| {
+                        Err(Error::WrongWriterState(
+                            "[EndFile] Hash with wrong size".to_string(),
+                        ))
+                    },
+                )?
+            }
+            _ => {
+                // Never happens, due to `check_state_file_opened!`
+                return Err(Error::WrongWriterState(
+                    "[EndFile] State have changes inside end_file".to_string(),
+                ));
+            }
+        };
+
+        self.end_pending_content(id, hash)?;
+        if let (Some(hint), Some(file_info)) = (self.size_hints.remove(&id), self.ids_info.get(&id))
+        {
+            if file_info.size != hint {
+                self.size_hint_mismatches += 1;
+            }
+        }
+        self.mark_continuous_block(id)?;
+        if let Some(checksum) = self.checksums.remove(&id) {
+            // Use std::io::Empty as a readable placeholder type
+            ArchiveFileBlock::FileChecksum::<std::io::Empty> {
+                id,
+                digest: checksum.finalize(),
+            }
+            .dump_encoded(&mut self.dest, self.config.metadata_encoding)?;
+            self.features |= FeatureFlags::HAS_CHECKSUMS;
+        }
+        self.mark_eof(id)?;
+        // Use std::io::Empty as a readable placeholder type
+        ArchiveFileBlock::EndOfFile::<std::io::Empty> { id, hash }
+            .dump_encoded(&mut self.dest, self.config.metadata_encoding)?;
+
+        self.compression_bypass.remove(&id);
+        self.compression_levels.remove(&id);
+        if let Some(name) = self.opened_names.remove(&id) {
+            self.config.progress.on_file_end(&name);
+        }
+        #[cfg(feature = "tracing")]
+        if let Some(file_info) = self.ids_info.get(&id) {
+            tracing::Span::current().record("bytes", file_info.size);
+        }
+        if let FileLayout::Interleaved { chunk_size } = self.config.file_layout {
+            // The other files may now complete a round
+            self.write_interleaved_chunks(chunk_size)?;
+        }
+        Ok(())
     }
 
-    pub fn new(src: R) -> Result<Self, Error> {
-        Self::from_config(src, ArchiveReaderConfig::new())
+    pub fn add_file<U: Read>(
+        &mut self,
+        filename: impl AsRef<str>,
+        size: u64,
+        src: U,
+    ) -> Result<(), Error> {
+        let id = self.start_file(filename)?;
+        self.append_file_content(id, size, src)?;
+        self.end_file(id)
     }
 
-    /// Fail-safe / best-effort conversion of the current archive to a correct
-    /// one. On success, returns the reason conversion terminates (ideally,
-    /// EndOfOriginalArchiveData)
-    #[allow(clippy::cognitive_complexity)]
-    pub fn convert_to_archive<W: Write>(
+    /// Add a file named `filename`, whose content is read from `src` until
+    /// its end, and return its size
+    ///
+    /// On error, either while reading `src` or writing the archive, the file
+    /// is abandoned: it is not ended, and not listed in the archive footer.
+    /// Its name remains used
+    pub fn add_file_from_reader<R: Read>(
         &mut self,
-        output: &mut ArchiveWriter<W>,
-    ) -> Result<FailSafeReadError, Error> {
-        let mut error = FailSafeReadError::NoError;
+        filename: &str,
+        src: &mut R,
+    ) -> Result<u64, Error> {
+        let id = self.start_file(filename)?;
+        self.stream_file_content(id, src)
+    }
 
-        // Associate an id retrieved from the archive to repair, to the
-        // corresponding output file id
-        let mut id_failsafe2id_output: HashMap<ArchiveFileID, ArchiveFileID> = HashMap::new();
-        // Associate an id retrieved from the archive to corresponding filename
-        let mut id_failsafe2filename: HashMap<ArchiveFileID, String> = HashMap::new();
-        // List of IDs from the archive already fully added
-        let mut id_failsafe_done = Vec::new();
-        // Associate an id retrieved from the archive with its ongoing Hash
-        let mut id_failsafe2hash: HashMap<ArchiveFileID, Sha256> = HashMap::new();
+    /// Add the file at `path`, named `archive_name` in the archive, and
+    /// return its size
+    ///
+    /// The file metadata (modification time, permissions, owner) are stored
+    /// if they can be read. On error while copying its content, the file is
+    /// abandoned, see `add_file_from_reader`
+    pub fn add_file_from_path(&mut self, path: &Path, archive_name: &str) -> Result<u64, Error> {
+        let mut file = File::open(path)?;
+        let id = match file.metadata() {
+            Ok(metadata) => {
+                self.start_file_with_metadata(archive_name, &FileMetadata::from(&metadata))?
+            }
+            Err(_) => self.start_file(archive_name)?,
+        };
+        self.stream_file_content(id, &mut file)
+    }
 
-        'read_block: loop {
-            match ArchiveFileBlock::from(&mut self.src) {
-                Err(Error::IOError(err)) => {
-                    if let std::io::ErrorKind::UnexpectedEof = err.kind() {
-                        update_error!(error = FailSafeReadError::UnexpectedEOFOnNextBlock);
-                        break;
-                    }
-                    update_error!(error = FailSafeReadError::IOErrorOnNextBlock(err));
-                    break;
-                }
+    /// Add a file named `filename`, whose content is read from `src` until
+    /// its end, unless the SHA-256 hash of this content is `previous_hash`
+    ///
+    /// This is intended for incremental archiving: `previous_hash` is the
+    /// hash of the file in a previous archive, as returned by
+    /// `ArchiveReader::get_hash`, or by a previous call. When `previous_hash`
+    /// is set, the content is first read to compute its hash, so that nothing
+    /// is written for a skipped file, and its name remains available; `src`
+    /// is then rewound to add it. In any case, the content is streamed as with
+    /// `add_file_from_reader`, and never kept in memory
+    pub fn add_file_if_changed<R: Read + Seek>(
+        &mut self,
+        filename: &str,
+        mut src: R,
+        previous_hash: Option<Sha256Hash>,
+    ) -> Result<AddResult, Error> {
+        if let Some(previous_hash) = previous_hash {
+            let start = src.stream_position()?;
+            let mut hash = Sha256::new();
+            io::copy(
+                &mut HashWrapperReader::new(&mut src, &mut hash),
+                &mut io::sink(),
+            )?;
+            if Sha256Hash::from(hash.finalize()) == previous_hash {
+                return Ok(AddResult::Skipped);
+            }
+            src.seek(SeekFrom::Start(start))?;
+        }
+        // The hash returned is the one of the content actually added
+        let mut hash = Sha256::new();
+        self.add_file_from_reader(filename, &mut HashWrapperReader::new(src, &mut hash))?;
+        Ok(AddResult::Added(hash.finalize().into()))
+    }
+
+    /// Start the files `names`, and return a `StreamWriter` for each of them
+    ///
+    /// The `StreamWriter`s share the archive, so they can be written in any
+    /// order, interleaving the files content. Each file is ended when its
+    /// `StreamWriter` is finalized or dropped; the archive is available again
+    /// once all of them are gone.
+    /// If a file can't be started, the ones already started are abandoned
+    pub fn open_files<'b, const N: usize>(
+        &'b mut self,
+        names: [&str; N],
+    ) -> Result<[StreamWriter<'a, 'b, W>; N], Error> {
+        let mut ids = [ArchiveFileID::default(); N];
+        for (index, name) in names.iter().enumerate() {
+            match self.start_file(name) {
+                Ok(id) => ids[index] = id,
                 Err(err) => {
-                    update_error!(error = FailSafeReadError::ErrorOnNextBlock(err));
-                    break;
+                    for id in &ids[..index] {
+                        self.abandon_file(*id)?;
+                    }
+                    return Err(err);
                 }
-                Ok(block) => {
-                    match block {
-                        ArchiveFileBlock::FileStart { filename, id } => {
-                            if let Some(_id_output) = id_failsafe2id_output.get(&id) {
-                                update_error!(error = FailSafeReadError::ArchiveFileIDReuse(id));
-                                break 'read_block;
-                            }
-                            if id_failsafe_done.contains(&id) {
-                                update_error!(
-                                    error = FailSafeReadError::ArchiveFileIDAlreadyClose(id)
-                                );
-                                break 'read_block;
-                            }
+            }
+        }
+        let archive = Rc::new(RefCell::new(self));
+        Ok(ids.map(|id| StreamWriter::shared(archive.clone(), id)))
+    }
 
-                            id_failsafe2filename.insert(id, filename.clone());
-                            let id_output = match output.start_file(&filename) {
-                                Err(Error::DuplicateFilename) => {
-                                    update_error!(
-                                        error = FailSafeReadError::FilenameReuse(filename)
-                                    );
-                                    break 'read_block;
-                                }
-                                Err(err) => {
-                                    return Err(err);
-                                }
-                                Ok(id) => id,
-                            };
-                            id_failsafe2id_output.insert(id, id_output);
-                            id_failsafe2hash.insert(id, Sha256::default());
-                        }
-                        ArchiveFileBlock::FileContent { length, id, .. } => {
-                            let id_output = match id_failsafe2id_output.get(&id) {
-                                Some(id_output) => *id_output,
-                                None => {
-                                    update_error!(
-                                        error = FailSafeReadError::ContentForUnknownFile(id)
-                                    );
-                                    break 'read_block;
-                                }
-                            };
-                            if id_failsafe_done.contains(&id) {
-                                update_error!(
-                                    error = FailSafeReadError::ArchiveFileIDAlreadyClose(id)
-                                );
-                                break 'read_block;
-                            }
-                            let fname = id_failsafe2filename.get(&id).expect(
-                                "`id_failsafe2filename` not more sync with `id_failsafe2id_output`",
-                            );
-                            let hash = id_failsafe2hash.get_mut(&id).expect(
-                                "`id_failsafe2hash` not more sync with `id_failsafe2id_output`",
-                            );
+    /// Copy `src` to the content of the opened file `id`, then end it, or
+    /// abandon it on error
+    fn stream_file_content<R: Read>(
+        &mut self,
+        id: ArchiveFileID,
+        src: &mut R,
+    ) -> Result<u64, Error> {
+        // Merge small writes into bigger content blocks
+        let mut stream = io::BufWriter::with_capacity(CACHE_SIZE, StreamWriter::new(self, id));
+        match io::copy(src, &mut stream) {
+            Ok(size) => {
+                let stream = stream.into_inner().map_err(|err| err.into_error())?;
+                stream.finalize()?;
+                Ok(size)
+            }
+            Err(err) => {
+                // Do not write the pending data
+                let (stream, _) = stream.into_parts();
+                stream.abandon()?;
+                Err(err.into())
+            }
+        }
+    }
 
-                            let src = &mut (&mut self.src).take(length as u64);
-                            'content: loop {
-                                let mut buf = Vec::with_capacity(CACHE_SIZE);
-                                'buf_fill: loop {
-                                    // Read bytes one per one to take the maximum of it
-                                    let mut mini_buf = [0u8; 1];
-                                    match src.read(&mut mini_buf) {
-                                        Ok(read) => {
-                                            if read == 0 {
-                                                // EOF
-                                                break 'buf_fill;
-                                            }
-                                            buf.push(mini_buf[0]);
-                                        }
-                                        Err(err) => {
-                                            // Stop reconstruction
-                                            output.append_file_content(
-                                                id_output,
-                                                buf.len() as u64,
-                                                buf.as_slice(),
-                                            )?;
-                                            update_error!(
-                                                error = FailSafeReadError::ErrorInFile(
-                                                    err,
-                                                    fname.clone()
-                                                )
-                                            );
-                                            break 'read_block;
-                                        }
-                                    }
-                                    // Cache full
-                                    if buf.len() == CACHE_SIZE {
-                                        break 'buf_fill;
-                                    }
-                                }
-                                output.append_file_content(
-                                    id_output,
-                                    buf.len() as u64,
-                                    buf.as_slice(),
-                                )?;
-                                hash.update(buf.as_slice());
-                                if buf.len() < CACHE_SIZE {
-                                    // EOF
-                                    break 'content;
-                                }
-                            }
+    /// Stop writing the opened file `id`, without ending it. It is not listed
+    /// in the footer, and its name can't be used again
+    pub(crate) fn abandon_file(&mut self, id: ArchiveFileID) -> Result<(), Error> {
+        check_state_file_opened!(&self.state, &id);
+        if self.coalesce.as_ref().is_some_and(|buffer| buffer.id == id) {
+            self.coalesce = None;
+        }
+        self.layout_pending.remove(&id);
+        self.chunkers.remove(&id);
+        if self.layout_writing == Some(id) {
+            self.layout_writing = None;
+        }
+        if let ArchiveWriterState::OpenedFiles { ids, hashes } = &mut self.state {
+            hashes.remove(&id);
+            vec_remove_item(ids, &id);
+        }
+        if let Some(name) = self
+            .files_info
+            .iter()
+            .find(|(_, file_id)| **file_id == id)
+            .map(|(name, _)| name.clone())
+        {
+            self.files_info.remove(&name);
+            self.abandoned_names.insert(name);
+        }
+        self.ids_info.remove(&id);
+        self.checksums.remove(&id);
+        self.compression_bypass.remove(&id);
+        self.compression_levels.remove(&id);
+        self.opened_names.remove(&id);
+        self.dedup_pending.remove(&id);
+        self.size_hints.remove(&id);
+        self.file_encryptors.remove(&id);
+        if let FileLayout::Interleaved { chunk_size } = self.config.file_layout {
+            self.write_interleaved_chunks(chunk_size)?;
+        }
+        Ok(())
+    }
+
+    /// Unwraps the inner writer
+    pub fn into_raw(self) -> W {
+        self.dest.into_raw()
+    }
+
+    /// Finalize the archive, if not already done, and return the inner
+    /// writer
+    ///
+    /// Unlike `into_raw`, the returned writer always holds a complete
+    /// archive. It is left at the end of the archive: to read it back, with
+    /// a `Cursor` for instance, seek to its start first
+    pub fn into_inner(mut self) -> Result<W, Error> {
+        if !matches!(self.state, ArchiveWriterState::Finalized) {
+            self.finalize()?;
+        }
+        Ok(self.into_raw())
+    }
+
+    /// Abandon the archive being written, for instance after an error, and
+    /// return the inner writer so that the caller can truncate or discard it
+    ///
+    /// Unlike `finalize`, neither the end of the archive data nor the footer
+    /// are written, and layers are not finalized: the result cannot be opened
+    /// by `ArchiveReader`. Dropping an `ArchiveWriter` does not finalize it
+    /// either; `abort` makes the intent explicit and gives back the writer
+    pub fn abort(self) -> W {
+        self.dest.into_raw()
+    }
+
+    /// Write the contents kept in memory, see
+    /// `ArchiveWriterConfig::coalesce_threshold` and
+    /// `ArchiveWriterConfig::block_boundary`, and flush the layers
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_coalesced_content()?;
+        let mut ids: Vec<ArchiveFileID> = self.chunkers.keys().copied().collect();
+        ids.sort();
+        for id in ids {
+            self.write_chunked_content(id)?;
+        }
+        self.dest.flush()
+    }
+}
+
+// -------- Reader --------
+
+#[derive(Debug)]
+pub struct ArchiveFile<T: Read> {
+    /// File inside a MLA Archive
+    pub filename: String,
+    pub data: T,
+    pub size: u64,
+    /// Metadata of the file, if stored in the archive
+    pub metadata: Option<FileMetadata>,
+}
+
+/// Result of `ArchiveReader::verify_integrity`
+#[derive(Debug, Default)]
+pub struct VerificationReport {
+    /// Number of files fully read, without any error
+    pub files_ok: usize,
+    /// Files for which an error has been detected, with the first error met
+    pub files_with_errors: Vec<(String, Error)>,
+    /// The whole archive data has been read, up to `EndOfArchiveData`, and
+    /// every file has been ended
+    pub archive_complete: bool,
+}
+
+/// Order of the files returned by `ArchiveReader::list_files_sorted`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    /// By name, comparing Unicode code points
+    ByName,
+    /// By decreasing size, then by name
+    BySize,
+    /// In the order the files have been started in the archive
+    ByInsertionOrder,
+}
+
+/// File of the archive, as listed by `ArchiveReader::list_files_sorted`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSummary {
+    pub name: String,
+    /// Size of the file content, `None` if unknown
+    pub size: Option<u64>,
+}
+
+/// State of a file during `ArchiveReader::verify_integrity`
+struct FileIntegrity {
+    filename: String,
+    hash: Sha256,
+    checksum: ChecksumHasher,
+    error: Option<Error>,
+    /// Size recorded in the footer, if any, not yet met in the file content
+    remaining: Option<u64>,
+}
+
+impl FileIntegrity {
+    /// Account for `length` bytes of content, reporting an error if the
+    /// content goes beyond the size recorded in the footer
+    fn consume(&mut self, length: u64) {
+        if let Some(remaining) = self.remaining {
+            match remaining.checked_sub(length) {
+                Some(remaining) => self.remaining = Some(remaining),
+                None => {
+                    self.remaining = None;
+                    self.error.get_or_insert(Error::WrongReaderState(
+                        "[VerifyIntegrity] File content bigger than its recorded size".to_string(),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+enum BlocksToFileReaderState {
+    // Remaining size
+    InFile(usize),
+    // Remaining size of the current hole
+    InHole(u64),
+    // Decompressed content of the current pre-compressed block, and the
+    // position in it
+    InBuffer(Vec<u8>, usize),
+    Ready,
+    Finish,
+}
+
+#[derive(Debug)]
+pub struct BlocksToFileReader<'a, R: Read + Seek> {
+    /// This structure wraps the internals to get back a file's content
+    src: &'a mut R,
+    state: BlocksToFileReaderState,
+    /// id of the File being read
+    id: ArchiveFileID,
+    /// position in `offsets` of the last offset used
+    current_offset: usize,
+    /// List of offsets of continuous blocks corresponding to where the file can be read
+    offsets: Vec<u64>,
+    /// Checksum of the content read so far, if it must be verified
+    checksum: Option<ChecksumHasher>,
+    /// Ignore blocks of unknown type, instead of failing
+    skip_unknown_blocks: bool,
+    /// Decryption of the content, if encrypted for the file own recipients
+    decryptor: Option<FileDecryptor>,
+    /// Progress reporting, see `ArchiveReader::with_progress`
+    progress: Option<FileReadProgress<'a>>,
+}
+
+/// Progress of the read of a file, reported to a `ReadProgressReporter`
+struct FileReadProgress<'a> {
+    reporter: &'a mut dyn ReadProgressReporter,
+    filename: String,
+    /// Bytes read so far
+    total: u64,
+    /// `on_file_end` has already been called
+    ended: bool,
+}
+
+impl fmt::Debug for FileReadProgress<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileReadProgress")
+            .field("filename", &self.filename)
+            .field("total", &self.total)
+            .finish()
+    }
+}
+
+impl<'a, R: Read + Seek> BlocksToFileReader<'a, R> {
+    fn new(src: &mut R, id: ArchiveFileID, offsets: Vec<u64>) -> BlocksToFileReader<R> {
+        BlocksToFileReader {
+            src,
+            state: BlocksToFileReaderState::Ready,
+            id,
+            current_offset: 0,
+            offsets,
+            checksum: None,
+            skip_unknown_blocks: true,
+            decryptor: None,
+            progress: None,
+        }
+    }
+
+    /// Report the read of the file `filename` to `reporter`
+    fn with_progress(mut self, reporter: &'a mut dyn ReadProgressReporter, filename: &str) -> Self {
+        self.progress = Some(FileReadProgress {
+            reporter,
+            filename: filename.to_string(),
+            total: 0,
+            ended: false,
+        });
+        self
+    }
+
+    /// Fail on blocks of unknown type, instead of ignoring them
+    fn with_unknown_blocks_rejected(mut self) -> Self {
+        self.skip_unknown_blocks = false;
+        self
+    }
+
+    /// Verify the file content read against its `FileChecksum` block, if any
+    fn with_checksums_verification(mut self) -> Self {
+        // BLAKE3 is the only supported algorithm for now
+        self.checksum = Some(ChecksumHasher::new(ChecksumAlgorithm::Blake3));
+        self
+    }
+
+    /// Decrypt the file content with `decryptor`, and check its `FileTag`
+    fn with_decryptor(mut self, decryptor: FileDecryptor) -> Self {
+        self.decryptor = Some(decryptor);
+        self
+    }
+
+    /// Move `self.src` to the next continuous block
+    fn move_to_next_block(&mut self) -> Result<(), Error> {
+        self.current_offset += 1;
+        if self.current_offset >= self.offsets.len() {
+            return Err(Error::WrongReaderState(
+                "[BlocksToFileReader] No more continuous blocks".to_string(),
+            ));
+        }
+        self.src
+            .seek(SeekFrom::Start(self.offsets[self.current_offset]))?;
+        Ok(())
+    }
+
+    /// Save the current reading position, to be able to `restore` it later
+    #[cfg(feature = "async")]
+    pub(crate) fn checkpoint(&mut self) -> Result<BlocksToFileReaderCheckpoint, Error> {
+        Ok(BlocksToFileReaderCheckpoint {
+            position: self.src.stream_position()?,
+            state: self.state.clone(),
+            id: self.id,
+            current_offset: self.current_offset,
+            checksum: self.checksum.clone(),
+            decryptor: self.decryptor.clone(),
+        })
+    }
+
+    /// Go back to a position previously saved with `checkpoint`
+    ///
+    /// This is used to resume a read interrupted by an error of `self.src`
+    #[cfg(feature = "async")]
+    pub(crate) fn restore(
+        &mut self,
+        checkpoint: &BlocksToFileReaderCheckpoint,
+    ) -> Result<(), Error> {
+        self.src.seek(SeekFrom::Start(checkpoint.position))?;
+        self.state = checkpoint.state.clone();
+        self.id = checkpoint.id;
+        self.current_offset = checkpoint.current_offset;
+        self.checksum = checkpoint.checksum.clone();
+        self.decryptor = checkpoint.decryptor.clone();
+        Ok(())
+    }
+}
+
+/// Read the content of the file `id`, named `filename`, from its blocks in
+/// `src` listed in `files_info`. This is used by linear readers to get the
+/// content a `Dedup` block refers to, written before
+pub(crate) fn read_referred_content<'a, R: Read + Seek>(
+    src: &'a mut R,
+    files_info: Option<&HashMap<String, FileInfo>>,
+    filename: &str,
+    id: ArchiveFileID,
+) -> Result<BlocksToFileReader<'a, R>, Error> {
+    let offsets = files_info
+        .ok_or(Error::MissingMetadata)?
+        .get(filename)
+        .map(|file_info| file_info.offsets.clone())
+        .ok_or_else(|| {
+            Error::WrongReaderState("[Dedup] Unable to find the referred file".to_string())
+        })?;
+    src.seek(SeekFrom::Start(*offsets.first().ok_or_else(|| {
+        Error::WrongReaderState("[Dedup] A file must have at least one offset".to_string())
+    })?))?;
+    match ArchiveFileBlock::from(&mut *src)? {
+        ArchiveFileBlock::FileStart { id: start_id, .. } if start_id == id => {}
+        _ => {
+            return Err(Error::WrongReaderState(
+                "[Dedup] The referred file does not start there".to_string(),
+            ));
+        }
+    }
+    Ok(BlocksToFileReader::new(src, id, offsets))
+}
+
+/// Reading position of a `BlocksToFileReader`
+#[cfg(feature = "async")]
+pub(crate) struct BlocksToFileReaderCheckpoint {
+    /// Position in the source
+    position: u64,
+    state: BlocksToFileReaderState,
+    id: ArchiveFileID,
+    current_offset: usize,
+    checksum: Option<ChecksumHasher>,
+    decryptor: Option<FileDecryptor>,
+}
+
+impl<'a, T: Read + Seek> BlocksToFileReader<'a, T> {
+    /// Read the file content, from the current position in its blocks
+    fn read_blocks(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        let (remaining, count) = match self.state {
+            BlocksToFileReaderState::Ready => {
+                // Start a new block FileContent
+                match ArchiveFileBlock::from(&mut self.src)? {
+                    ArchiveFileBlock::FileContent { length, id, .. } => {
+                        if id != self.id {
+                            self.move_to_next_block()?;
+                            return self.read_blocks(into);
                         }
-                        ArchiveFileBlock::EndOfFile { id, hash } => {
-                            let id_output = match id_failsafe2id_output.get(&id) {
-                                Some(id_output) => *id_output,
-                                None => {
-                                    update_error!(error = FailSafeReadError::EOFForUnknownFile(id));
-                                    break 'read_block;
-                                }
-                            };
-                            if id_failsafe_done.contains(&id) {
-                                update_error!(
-                                    error = FailSafeReadError::ArchiveFileIDAlreadyClose(id)
-                                );
-                                break 'read_block;
-                            }
-                            match id_failsafe2hash.remove(&id) {
-                                Some(hash_archive) => {
+                        let count = self.src.by_ref().take(length as u64).read(into)?;
+                        if let Some(checksum) = &mut self.checksum {
+                            checksum.update(&into[..count]);
+                        }
+                        if let Some(decryptor) = &mut self.decryptor {
+                            decryptor.decrypt(&mut into[..count]);
+                        }
+                        let length_usize = length as usize;
+                        (length_usize - count, count)
+                    }
+                    ArchiveFileBlock::FilePrecompressedContent {
+                        id,
+                        algorithm,
+                        uncompressed_length,
+                        data,
+                    } => {
+                        if id != self.id {
+                            self.move_to_next_block()?;
+                            return self.read_blocks(into);
+                        }
+                        if self.decryptor.is_some() {
+                            return Err(Error::WrongReaderState(
+                                "[BlocksToFileReader] Pre-compressed content in an encrypted file"
+                                    .to_string(),
+                            )
+                            .into());
+                        }
+                        let content =
+                            decompress_block_content(algorithm, &data, uncompressed_length)?;
+                        if let Some(checksum) = &mut self.checksum {
+                            checksum.update(&content);
+                        }
+                        if !content.is_empty() {
+                            self.state = BlocksToFileReaderState::InBuffer(content, 0);
+                        }
+                        return self.read_blocks(into);
+                    }
+                    ArchiveFileBlock::FileSparseHole { id, length } => {
+                        if id != self.id {
+                            self.move_to_next_block()?;
+                        } else if self.decryptor.is_some() {
+                            // Holes are not authenticated
+                            return Err(Error::WrongReaderState(
+                                "[BlocksToFileReader] Hole in an encrypted file".to_string(),
+                            )
+                            .into());
+                        } else if length > 0 {
+                            self.state = BlocksToFileReaderState::InHole(length);
+                        }
+                        return self.read_blocks(into);
+                    }
+                    ArchiveFileBlock::EndOfFile { id, .. } => {
+                        if id != self.id {
+                            self.move_to_next_block()?;
+                            return self.read_blocks(into);
+                        }
+                        if self.decryptor.is_some() {
+                            // The content has not been authenticated
+                            return Err(Error::AuthenticatedDecryptionWrongTag.into());
+                        }
+                        self.state = BlocksToFileReaderState::Finish;
+                        return Ok(0);
+                    }
+                    ArchiveFileBlock::FileTag { id, tag } => {
+                        if id != self.id {
+                            self.move_to_next_block()?;
+                            return self.read_blocks(into);
+                        }
+                        if let Some(decryptor) = self.decryptor.take() {
+                            decryptor.check_tag(&tag)?;
+                        }
+                        return self.read_blocks(into);
+                    }
+                    ArchiveFileBlock::FileChecksum { id, digest } => {
+                        if id != self.id {
+                            self.move_to_next_block()?;
+                            return self.read_blocks(into);
+                        }
+                        if let Some(checksum) = &self.checksum {
+                            if checksum.finalize() != digest {
+                                return Err(Error::ChecksumMismatch.into());
+                            }
+                        }
+                        return self.read_blocks(into);
+                    }
+                    ArchiveFileBlock::FileMetadata { id, .. }
+                    | ArchiveFileBlock::FileSizeHint { id, .. }
+                    | ArchiveFileBlock::FileRecipients { id, .. }
+                    | ArchiveFileBlock::UserMetadata { id, .. } => {
+                        if id != self.id {
+                            self.move_to_next_block()?;
+                        }
+                        return self.read_blocks(into);
+                    }
+                    ArchiveFileBlock::DirectoryEntry { .. }
+                    | ArchiveFileBlock::Symlink { .. }
+                    | ArchiveFileBlock::FileAlias { .. }
+                    | ArchiveFileBlock::Padding { .. } => {
+                        // Not related to any file, they do not break the
+                        // continuity of blocks
+                        return self.read_blocks(into);
+                    }
+                    ArchiveFileBlock::FileStart { id, .. } => {
+                        if id != self.id {
+                            self.move_to_next_block()?;
+                            return self.read_blocks(into);
+                        }
+                        return Err(Error::WrongReaderState(
+                            "[BlocksToFileReader] Start with a wrong block type".to_string(),
+                        )
+                        .into());
+                    }
+                    ArchiveFileBlock::EndOfArchiveData => {
+                        return Err(Error::WrongReaderState(
+                            "[BlocksToFileReader] Try to read the end of the archive".to_string(),
+                        )
+                        .into());
+                    }
+                    ArchiveFileBlock::Unknown { kind, .. } => {
+                        check_unknown_block(kind, self.skip_unknown_blocks)?;
+                        return self.read_blocks(into);
+                    }
+                    ArchiveFileBlock::Dedup { id, refers_to_id } => {
+                        self.move_to_next_block()?;
+                        if id != self.id {
+                            return self.read_blocks(into);
+                        }
+                        // Continue with the blocks of `refers_to_id`, whose
+                        // offsets follow
+                        match ArchiveFileBlock::from(&mut self.src)? {
+                            ArchiveFileBlock::FileStart { id, .. } if id == refers_to_id => {}
+                            _ => {
+                                return Err(Error::WrongReaderState(
+                                    "[BlocksToFileReader] Deduplicated content not found"
+                                        .to_string(),
+                                )
+                                .into());
+                            }
+                        }
+                        self.id = refers_to_id;
+                        return self.read_blocks(into);
+                    }
+                }
+            }
+            BlocksToFileReaderState::InFile(remaining) => {
+                let count = self.src.by_ref().take(remaining as u64).read(into)?;
+                if let Some(checksum) = &mut self.checksum {
+                    checksum.update(&into[..count]);
+                }
+                if let Some(decryptor) = &mut self.decryptor {
+                    decryptor.decrypt(&mut into[..count]);
+                }
+                (remaining - count, count)
+            }
+            BlocksToFileReaderState::InHole(remaining) => {
+                let count = std::cmp::min(remaining, into.len() as u64) as usize;
+                into[..count].fill(0);
+                if let Some(checksum) = &mut self.checksum {
+                    checksum.update(&into[..count]);
+                }
+                self.state = if remaining > count as u64 {
+                    BlocksToFileReaderState::InHole(remaining - count as u64)
+                } else {
+                    BlocksToFileReaderState::Ready
+                };
+                return Ok(count);
+            }
+            BlocksToFileReaderState::InBuffer(ref content, ref mut position) => {
+                let count = std::cmp::min(content.len() - *position, into.len());
+                into[..count].copy_from_slice(&content[*position..*position + count]);
+                *position += count;
+                if *position == content.len() {
+                    self.state = BlocksToFileReaderState::Ready;
+                }
+                return Ok(count);
+            }
+            BlocksToFileReaderState::Finish => {
+                return Ok(0);
+            }
+        };
+        if remaining > 0 {
+            self.state = BlocksToFileReaderState::InFile(remaining);
+        } else {
+            // remaining is 0 (> never happens thanks to take)
+            self.state = BlocksToFileReaderState::Ready;
+        }
+        Ok(count)
+    }
+}
+
+impl<'a, T: Read + Seek> Read for BlocksToFileReader<'a, T> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        let count = self.read_blocks(into)?;
+        if let Some(progress) = &mut self.progress {
+            if count > 0 {
+                progress.total += count as u64;
+                progress
+                    .reporter
+                    .on_bytes_read(&progress.filename, progress.total);
+            } else if !into.is_empty() && !progress.ended {
+                progress.ended = true;
+                progress.reporter.on_file_end(&progress.filename);
+            }
+        }
+        Ok(count)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+struct FileInfo {
+    /// File information to save in the footer
+    ///
+    /// Offsets of continuous chunks of `ArchiveFileBlock`
+    offsets: Vec<u64>,
+    /// Size of the file, in bytes
+    size: u64,
+    /// Offset of the ArchiveFileBlock::EndOfFile
+    ///
+    /// This offset is used to retrieve information from the EoF tag, such as
+    /// the file hash
+    eof_offset: u64,
+}
+
+pub struct ArchiveReader<'a, R: 'a + Read + Seek> {
+    /// MLA Archive format Reader
+
+    /// User's reading configuration
+    config: ArchiveReaderConfig,
+    /// Source
+    src: Box<dyn 'a + LayerReader<'a, R>>,
+    /// Metadata (from footer if any)
+    metadata: Option<ArchiveFooter>,
+    /// The archive has been recovered from truncated data, see
+    /// `ArchiveReader::from_config_recover`
+    truncated: bool,
+    /// Size of the archive, as stored, in bytes
+    archive_size: u64,
+    /// Format of the archive, from its header
+    format: FormatVersion,
+    /// Unencrypted comment, from its header
+    comment: Option<String>,
+    /// Identifier, from its header
+    archive_id: Option<[u8; ARCHIVE_ID_SIZE]>,
+    /// Progress reporting of files read
+    progress: Box<dyn ReadProgressReporter>,
+    /// Number of files and total size of their content, if known, cached
+    /// after the first computation, see `file_count`
+    file_stats: Option<(usize, Option<u64>)>,
+}
+
+/// Open the file `filename` of the archive data `src` (the upper layer),
+/// from the footer `files_info`, according to `config`
+fn open_file<'a, S: Read + Seek>(
+    src: &'a mut S,
+    files_info: &HashMap<String, FileInfo>,
+    config: &ArchiveReaderConfig,
+    filename: &str,
+) -> Result<Option<ArchiveFile<BlocksToFileReader<'a, S>>>, Error> {
+    // Get file relative information
+    let file_info = match files_info.get(filename) {
+        None => return Ok(None),
+        Some(finfo) => finfo,
+    };
+    if file_info.offsets.is_empty() {
+        return Err(Error::WrongReaderState(
+            "[ArchiveReader] A file must have at least one offset".to_string(),
+        ));
+    }
+    // Set the inner layer at the start of the file
+    src.seek(SeekFrom::Start(file_info.offsets[0]))?;
+
+    // Read file information header
+    let id_file_block = match ArchiveFileBlock::from(&mut *src)? {
+        ArchiveFileBlock::FileStart { id, .. } => id,
+        _ => {
+            return Err(Error::WrongReaderState(
+                "[ArchiveReader] A file must start with a FileStart".to_string(),
+            ));
+        }
+    };
+
+    // Metadata and recipients, if any, are right after the FileStart
+    let mut metadata = None;
+    let mut decryptor = None;
+    loop {
+        let position = src.stream_position()?;
+        match ArchiveFileBlock::from(&mut *src)? {
+            ArchiveFileBlock::FileMetadata {
+                id,
+                metadata: found,
+            } if id == id_file_block && metadata.is_none() => {
+                metadata = Some(found);
+            }
+            ArchiveFileBlock::FileRecipients { id, recipients }
+                if id == id_file_block && decryptor.is_none() =>
+            {
+                decryptor = Some(config.encrypt.file_decryptor(&recipients)?);
+            }
+            _ => {
+                src.seek(SeekFrom::Start(position))?;
+                break;
+            }
+        }
+    }
+
+    // Instantiate the file representation
+    let mut reader = BlocksToFileReader::new(src, id_file_block, file_info.offsets.clone());
+    if let Some(decryptor) = decryptor {
+        reader = reader.with_decryptor(decryptor);
+    }
+    if config.checksums_verification {
+        reader = reader.with_checksums_verification();
+    }
+    if !config.skip_unknown_blocks {
+        reader = reader.with_unknown_blocks_rejected();
+    }
+    Ok(Some(ArchiveFile {
+        filename: filename.to_string(),
+        data: reader,
+        size: file_info.size,
+        metadata,
+    }))
+}
+
+/// Enable the layers of `config` on `src`, positioned after the archive
+/// header, and initialize them
+fn open_layers<'b, R: 'b + Read + Seek>(
+    src: R,
+    config: &ArchiveReaderConfig,
+) -> Result<Box<dyn 'b + LayerReader<'b, R>>, Error> {
+    // Pin the current position (after header) as the new 0
+    let mut raw_src = Box::new(RawLayerReader::new(src));
+    raw_src.reset_position()?;
+
+    // Enable layers depending on user option. Order is relevant
+    let mut src: Box<dyn 'b + LayerReader<'b, R>> = raw_src;
+    if config.layers_enabled.contains(Layers::SIGN) {
+        src = Box::new(SignatureLayerReader::new(src));
+    }
+    if config.layers_enabled.contains(Layers::PUBLIC_LISTING) {
+        src = Box::new(PublicListingLayerReader::new(src));
+    }
+    if config.layers_enabled.contains(Layers::ENCRYPT) {
+        src = Box::new(EncryptionLayerReader::new(src, &config.encrypt)?);
+    }
+    if config.layers_enabled.contains(Layers::COMPRESS) {
+        src = Box::new(CompressionLayerReader::new(src, &config.compress)?);
+    }
+    src.initialize()?;
+    Ok(src)
+}
+
+/// Length of `src` from its current position, which is kept
+fn remaining_len<R: Seek>(src: &mut R) -> io::Result<u64> {
+    let pos = src.stream_position()?;
+    let end = src.seek(SeekFrom::End(0))?;
+    src.seek(SeekFrom::Start(pos))?;
+    Ok(end.saturating_sub(pos))
+}
+
+/// Read the header of `src`, check its signature if `config` requires it,
+/// and load its persistent configuration into `config`. Return the archive
+/// format version, comment and identifier
+#[allow(clippy::type_complexity)]
+fn read_header<R: Read + Seek>(
+    src: &mut R,
+    config: &mut ArchiveReaderConfig,
+) -> Result<(FormatVersion, Option<String>, Option<[u8; ARCHIVE_ID_SIZE]>), Error> {
+    // Keep the header hash, in case it must be signed
+    let mut header_hash = Sha256::new();
+    let header = ArchiveHeader::from(&mut HashWrapperReader::new(&mut *src, &mut header_hash))?;
+
+    // Check the signature first, before using anything from the archive
+    if let Some(verifying_key) = config.verifying_key() {
+        if !header.config.layers_enabled.contains(Layers::SIGN) {
+            return Err(Error::MissingSignature);
+        }
+        let pos = src.stream_position()?;
+        check_signature(&mut *src, verifying_key, header_hash)?;
+        src.seek(SeekFrom::Start(pos))?;
+    }
+    config.load_persistent(header.config)?;
+    Ok((header.format, header.comment, header.archive_id))
+}
+
+impl<'b, R: 'b + Read + Seek> ArchiveReader<'b, R> {
+    pub fn from_config(mut src: R, mut config: ArchiveReaderConfig) -> Result<Self, Error> {
+        let archive_size = remaining_len(&mut src)?;
+        let (format, comment, archive_id) = read_header(&mut src, &mut config)?;
+        let mut src = open_layers(src, &config)?;
+
+        // Read the footer, and check the archive can be read
+        let footer = ArchiveFooter::deserialize_from(&mut src, format)?;
+        let mut supported = SUPPORTED_FEATURES;
+        if config.metadata_encoding == MetadataEncoding::Native {
+            // CBOR blocks must be explicitly accepted
+            supported.remove(FeatureFlags::HAS_CBOR_BLOCKS);
+        }
+        check_features(footer.features, supported)?;
+        let metadata = Some(footer);
+
+        // Reset the position for further uses
+        src.seek(SeekFrom::Start(0))?;
+
+        Ok(ArchiveReader {
+            config,
+            src,
+            metadata,
+            truncated: false,
+            archive_size,
+            format,
+            comment,
+            archive_id,
+            progress: Box::new(NoopProgressReporter),
+            file_stats: None,
+        })
+    }
+
+    pub fn new(src: R) -> Result<Self, Error> {
+        Self::from_config(src, ArchiveReaderConfig::new())
+    }
+
+    /// Return the sorted names of the files of the archive `src`, from its
+    /// public listing, without any key (see
+    /// `ArchiveWriterConfig::public_file_listing`)
+    ///
+    /// Only the listing is read: the signature, if any, is not checked. Use
+    /// `helpers::verify_archive` to ensure the listing is genuine.
+    /// `Error::MissingPublicListing` is returned if the archive has none
+    pub fn list_files_public(mut src: R) -> Result<Vec<String>, Error> {
+        let header = ArchiveHeader::from(&mut src)?;
+        let layers = header.config.layers_enabled;
+        if !layers.contains(Layers::PUBLIC_LISTING) {
+            return Err(Error::MissingPublicListing);
+        }
+        // Pin the current position (after header) as the new 0
+        let mut raw_src = Box::new(RawLayerReader::new(src));
+        raw_src.reset_position()?;
+        let mut src: Box<dyn 'b + LayerReader<'b, R>> = raw_src;
+        if layers.contains(Layers::SIGN) {
+            src = Box::new(SignatureLayerReader::new(src));
+        }
+        src.initialize()?;
+        read_public_listing(src)
+    }
+
+    /// Report the progress of files read, with `get_file` or
+    /// `helpers::linear_extract`, to `reporter`
+    pub fn with_progress(&mut self, reporter: Box<dyn ReadProgressReporter>) -> &mut Self {
+        self.progress = reporter;
+        self
+    }
+
+    /// Unwraps the underlying reader, for instance to pass the archive data
+    /// further once read
+    ///
+    /// Its position is unspecified
+    pub fn into_inner(self) -> R {
+        self.src.into_raw()
+    }
+
+    /// Format version of the archive, from its header
+    pub fn format_version(&self) -> FormatVersion {
+        self.format
+    }
+
+    /// Unencrypted comment of the archive, if any, see
+    /// `ArchiveWriterConfig::comment`
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    /// Random identifier of the archive, from its header. None for archives
+    /// older than format v6
+    pub fn archive_id(&self) -> Option<[u8; ARCHIVE_ID_SIZE]> {
+        self.archive_id
+    }
+
+    /// Comment of the archive protected by its layers, if any, see
+    /// `ArchiveWriterConfig::encrypted_comment`
+    pub fn encrypted_comment(&self) -> Option<&str> {
+        self.metadata
+            .as_ref()
+            .and_then(|footer| footer.comment.as_deref())
+    }
+
+    /// Creation time of the archive, as set by the writer on finalization
+    /// (see `ArchiveWriterConfig::set_created_at`), to the second. None for
+    /// archives written before its introduction, and for recovered ones
+    pub fn created_at(&self) -> Option<SystemTime> {
+        self.metadata
+            .as_ref()
+            .and_then(|footer| footer.created_at)
+            .and_then(|secs| UNIX_EPOCH.checked_add(Duration::from_secs(secs)))
+    }
+
+    /// Features used by the archive, as declared in its footer. Always empty
+    /// for archives older than format v4, and for recovered ones
+    pub fn features(&self) -> FeatureFlags {
+        self.metadata
+            .as_ref()
+            .map_or(FeatureFlags::empty(), |footer| footer.features)
+    }
+
+    /// Return an iterator on filenames present in the archive
+    ///
+    /// Order is not relevant, and may change. The iterator is lazy: names are
+    /// borrowed from the footer, loaded once when the archive is opened, and
+    /// nothing is read from the archive nor copied while iterating. To scan
+    /// the archive data instead, for instance without footer, see `entries`
+    pub fn list_files(&self) -> Result<impl Iterator<Item = &String>, Error> {
+        if let Some(ArchiveFooter { files_info, .. }) = &self.metadata {
+            self.config
+                .audit(self.archive_id, AuditEventType::FileListAccess);
+            Ok(files_info.keys())
+        } else {
+            Err(Error::MissingMetadata)
+        }
+    }
+
+    /// Return the files present in the archive, each one with its other names
+    /// given by `ArchiveWriter::rename_file`, sorted
+    ///
+    /// Aliases share the content of their original file. For them, the name of
+    /// the original file is read from the archive
+    pub fn list_files_with_aliases(&mut self) -> Result<Vec<(String, Vec<String>)>, Error> {
+        let files_info = match &self.metadata {
+            Some(ArchiveFooter { files_info, .. }) => files_info,
+            None => return Err(Error::MissingMetadata),
+        };
+        self.config
+            .audit(self.archive_id, AuditEventType::FileListAccess);
+        // Aliases start at the same offset than their original file
+        let mut by_offset: BTreeMap<u64, Vec<String>> = BTreeMap::new();
+        for (filename, file_info) in files_info {
+            let offset = *file_info
+                .offsets
+                .first()
+                .ok_or(Error::DeserializationError)?;
+            by_offset.entry(offset).or_default().push(filename.clone());
+        }
+
+        let mut files = Vec::with_capacity(by_offset.len());
+        for (offset, mut names) in by_offset {
+            if names.len() == 1 {
+                files.push((names.remove(0), Vec::new()));
+                continue;
+            }
+            self.src.seek(SeekFrom::Start(offset))?;
+            let original = match ArchiveFileBlock::from(&mut self.src)? {
+                ArchiveFileBlock::FileStart { filename, .. } => filename,
+                _ => {
+                    return Err(Error::WrongReaderState(
+                        "[list_files_with_aliases] A file must start with a FileStart".to_string(),
+                    ))
+                }
+            };
+            names.retain(|name| name != &original);
+            names.sort();
+            files.push((original, names));
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Return the files present in the archive with their size, in `order`
+    ///
+    /// Sizes are read from the footer, without reading the file contents
+    pub fn list_files_sorted(&self, order: SortOrder) -> Result<Vec<FileSummary>, Error> {
+        let files_info = match &self.metadata {
+            Some(ArchiveFooter { files_info, .. }) => files_info,
+            None => return Err(Error::MissingMetadata),
+        };
+        self.config
+            .audit(self.archive_id, AuditEventType::FileListAccess);
+        let mut files: Vec<(&String, &FileInfo)> = files_info.iter().collect();
+        match order {
+            SortOrder::ByName => files.sort_by_key(|(name, _)| *name),
+            SortOrder::BySize => files.sort_by(|(name1, info1), (name2, info2)| {
+                info2.size.cmp(&info1.size).then_with(|| name1.cmp(name2))
+            }),
+            // Files start in the order of their `FileStart` blocks. Aliases
+            // share the one of their original file
+            SortOrder::ByInsertionOrder => files.sort_by_key(|(name, info)| {
+                (info.offsets.first().copied().unwrap_or_default(), *name)
+            }),
+        }
+        Ok(files
+            .into_iter()
+            .map(|(name, info)| FileSummary {
+                name: name.clone(),
+                size: Some(info.size),
+            })
+            .collect())
+    }
+
+    /// Return the size, in bytes, of the file `filename`, without reading its
+    /// content
+    ///
+    /// Sizes are stored in the archive footer, and are exact: hints given with
+    /// `ArchiveWriter::set_file_size_hint` are only used by linear readers,
+    /// without footer. `None` is returned if the file is not in the archive
+    pub fn get_file_size(&self, filename: &str) -> Result<Option<u64>, Error> {
+        if let Some(ArchiveFooter { files_info, .. }) = &self.metadata {
+            Ok(files_info.get(filename).map(|file_info| file_info.size))
+        } else {
+            Err(Error::MissingMetadata)
+        }
+    }
+
+    /// Return the number of files in the archive, names given by
+    /// `ArchiveWriter::rename_file` included
+    ///
+    /// The count is taken from the footer if available. Otherwise, the archive
+    /// data are scanned once, file contents being skipped, and the result is
+    /// kept for the next calls, as for `total_uncompressed_size`
+    pub fn file_count(&mut self) -> Result<usize, Error> {
+        Ok(self.file_stats()?.0)
+    }
+
+    /// Return the total size, in bytes, of the file contents, as given by
+    /// `file_count`
+    ///
+    /// Without footer, the size is the sum of the hints given with
+    /// `ArchiveWriter::set_file_size_hint`, and `None` is returned if a file
+    /// has none
+    pub fn total_uncompressed_size(&mut self) -> Result<Option<u64>, Error> {
+        Ok(self.file_stats()?.1)
+    }
+
+    fn file_stats(&mut self) -> Result<(usize, Option<u64>), Error> {
+        if let Some(stats) = self.file_stats {
+            return Ok(stats);
+        }
+        let stats = if let Some(ArchiveFooter { files_info, .. }) = &self.metadata {
+            (
+                files_info.len(),
+                Some(files_info.values().map(|file_info| file_info.size).sum()),
+            )
+        } else {
+            self.scan_file_stats()?
+        };
+        self.file_stats = Some(stats);
+        Ok(stats)
+    }
+
+    /// Count the ended files, and their aliases, from the archive data
+    fn scan_file_stats(&mut self) -> Result<(usize, Option<u64>), Error> {
+        let mut hints = HashMap::new();
+        // Files ended, and aliases, by ID of their content
+        let mut files = Vec::new();
+        self.src.seek(SeekFrom::Start(0))?;
+        // Merge small reads, like the ones on ArchiveFileBlock reading
+        let mut src = io::BufReader::new(&mut self.src);
+        loop {
+            match ArchiveFileBlock::from(&mut src)? {
+                ArchiveFileBlock::FileSizeHint { id, size } => {
+                    hints.insert(id, size);
+                }
+                ArchiveFileBlock::FileContent { length, .. } => {
+                    src.seek_relative(length as i64)?;
+                }
+                ArchiveFileBlock::EndOfFile { id, .. } => files.push(id),
+                ArchiveFileBlock::FileAlias { original_id, .. } => files.push(original_id),
+                ArchiveFileBlock::EndOfArchiveData => break,
+                ArchiveFileBlock::Unknown { kind, .. } => {
+                    check_unknown_block(kind, self.config.skip_unknown_blocks)?;
+                }
+                _ => {}
+            }
+        }
+        let total = files.iter().map(|id| hints.get(id)).sum::<Option<u64>>();
+        Ok((files.len(), total))
+    }
+
+    /// Return the user-defined metadata of the file `filename`, set with
+    /// `ArchiveWriter::set_file_metadata`, without reading its content
+    ///
+    /// The blocks of the file are scanned, contents being skipped. The map is
+    /// empty if the file has no user-defined metadata
+    pub fn get_file_metadata(&mut self, filename: &str) -> Result<HashMap<String, Vec<u8>>, Error> {
+        let file_info = match &self.metadata {
+            Some(ArchiveFooter { files_info, .. }) => {
+                files_info.get(filename).ok_or_else(|| {
+                    Error::BadAPIArgument(format!("[get_file_metadata] Unknown file {}", filename))
+                })?
+            }
+            None => return Err(Error::MissingMetadata),
+        };
+        let mut user_metadata = HashMap::new();
+        // Merge small reads, like the ones on ArchiveFileBlock reading
+        let mut src = io::BufReader::new(&mut self.src);
+        let mut file_id = None;
+        'offsets: for offset in &file_info.offsets {
+            src.seek(SeekFrom::Start(*offset))?;
+            loop {
+                match ArchiveFileBlock::from(&mut src)? {
+                    ArchiveFileBlock::FileStart { id, .. } if file_id.is_none() => {
+                        file_id = Some(id);
+                    }
+                    ArchiveFileBlock::UserMetadata { id, key, value } if Some(id) == file_id => {
+                        user_metadata.insert(key, value);
+                    }
+                    ArchiveFileBlock::FileContent { length, id, .. } if Some(id) == file_id => {
+                        src.seek_relative(length as i64)?;
+                    }
+                    ArchiveFileBlock::EndOfFile { id, .. } if Some(id) == file_id => {
+                        break 'offsets;
+                    }
+                    ArchiveFileBlock::FileSparseHole { id, .. }
+                    | ArchiveFileBlock::FilePrecompressedContent { id, .. }
+                    | ArchiveFileBlock::Dedup { id, .. }
+                    | ArchiveFileBlock::FileChecksum { id, .. }
+                    | ArchiveFileBlock::FileMetadata { id, .. }
+                    | ArchiveFileBlock::FileSizeHint { id, .. }
+                    | ArchiveFileBlock::FileRecipients { id, .. }
+                    | ArchiveFileBlock::FileTag { id, .. }
+                        if Some(id) == file_id => {}
+                    ArchiveFileBlock::DirectoryEntry { .. }
+                    | ArchiveFileBlock::Symlink { .. }
+                    | ArchiveFileBlock::FileAlias { .. }
+                    | ArchiveFileBlock::Padding { .. }
+                    | ArchiveFileBlock::Unknown { .. } => {
+                        // Not related to any file, they do not break the
+                        // continuity of blocks
+                    }
+                    // Block of another file: end of these continuous blocks
+                    _ => continue 'offsets,
+                }
+            }
+        }
+        Ok(user_metadata)
+    }
+
+    /// Check whether the file `filename` is present in the archive, without
+    /// reading any file content
+    ///
+    /// Unlike `get_file`, nothing is read from the archive if its footer, the
+    /// filename index, is available. Otherwise, the archive data are scanned
+    /// until the `FileStart` of `filename`, file contents being skipped
+    pub fn file_exists(&mut self, filename: &str) -> Result<bool, Error> {
+        self.contains_all(&[filename])
+    }
+
+    /// Check whether all the files `filenames` are present in the archive,
+    /// in one pass, see `file_exists`
+    pub fn contains_all(&mut self, filenames: &[&str]) -> Result<bool, Error> {
+        if let Some(ArchiveFooter { files_info, .. }) = &self.metadata {
+            return Ok(filenames
+                .iter()
+                .all(|filename| files_info.contains_key(*filename)));
+        }
+
+        let mut missing: HashSet<&str> = filenames.iter().copied().collect();
+        self.src.seek(SeekFrom::Start(0))?;
+        // Merge small reads, like the ones on ArchiveFileBlock reading
+        let mut src = io::BufReader::new(&mut self.src);
+        while !missing.is_empty() {
+            match ArchiveFileBlock::from(&mut src)? {
+                ArchiveFileBlock::FileStart { filename, .. } => {
+                    missing.remove(filename.as_str());
+                }
+                ArchiveFileBlock::FileContent { length, .. } => {
+                    src.seek_relative(length as i64)?;
+                }
+                ArchiveFileBlock::EndOfArchiveData => break,
+                ArchiveFileBlock::Unknown { kind, .. } => {
+                    check_unknown_block(kind, self.config.skip_unknown_blocks)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(missing.is_empty())
+    }
+
+    /// Return the filenames present in the archive, with their size in bytes
+    ///
+    /// Order is not relevant, and may change. Sizes are always known for
+    /// archives read with their footer
+    pub fn list_files_with_sizes(&self) -> Result<Vec<(String, Option<u64>)>, Error> {
+        if let Some(ArchiveFooter { files_info, .. }) = &self.metadata {
+            self.config
+                .audit(self.archive_id, AuditEventType::FileListAccess);
+            Ok(files_info
+                .iter()
+                .map(|(fname, file_info)| (fname.clone(), Some(file_info.size)))
+                .collect())
+        } else {
+            Err(Error::MissingMetadata)
+        }
+    }
+
+    /// Return an iterator on directory entries present in the archive, sorted
+    pub fn list_directories(&self) -> Result<impl Iterator<Item = &str>, Error> {
+        if let Some(ArchiveFooter { directories, .. }) = &self.metadata {
+            Ok(directories.iter().map(String::as_str))
+        } else {
+            Err(Error::MissingMetadata)
+        }
+    }
+
+    /// Return an iterator on symbolic links present in the archive, as
+    /// `(link name, target)`, sorted by link name
+    pub fn list_symlinks(&self) -> Result<impl Iterator<Item = (&str, &str)>, Error> {
+        if let Some(ArchiveFooter { symlinks, .. }) = &self.metadata {
+            Ok(symlinks
+                .iter()
+                .map(|(link_name, target)| (link_name.as_str(), target.as_str())))
+        } else {
+            Err(Error::MissingMetadata)
+        }
+    }
+
+    /// Return an iterator on every entry names present in the archive, with
+    /// their kind
+    ///
+    /// Files come first, in no relevant order, then directories and symbolic
+    /// links
+    pub fn list_entries(&self) -> Result<impl Iterator<Item = (&str, EntryKind)>, Error> {
+        if let Some(footer) = &self.metadata {
+            let files = footer
+                .files_info
+                .keys()
+                .map(|fname| (fname.as_str(), EntryKind::File));
+            let directories = footer
+                .directories
+                .iter()
+                .map(|path| (path.as_str(), EntryKind::Directory));
+            let symlinks = footer
+                .symlinks
+                .keys()
+                .map(|link_name| (link_name.as_str(), EntryKind::Symlink));
+            Ok(files.chain(directories).chain(symlinks))
+        } else {
+            Err(Error::MissingMetadata)
+        }
+    }
+
+    pub fn get_hash<'a>(&'a mut self, filename: &str) -> Result<Option<Sha256Hash>, Error> {
+        if let Some(ArchiveFooter { files_info, .. }) = &self.metadata {
+            // Get file relative information
+            let file_info = match files_info.get(filename) {
+                None => return Ok(None),
+                Some(finfo) => finfo,
+            };
+            // Set the inner layer at the start of the EoF tag
+            self.src.seek(SeekFrom::Start(file_info.eof_offset))?;
+
+            // Return the file hash
+            match ArchiveFileBlock::from(&mut self.src)? {
+                ArchiveFileBlock::EndOfFile { hash, .. } => Ok(Some(hash)),
+                _ => Err(Error::WrongReaderState(
+                    "[ArchiveReader] eof_offset must point to a EoF".to_string(),
+                )),
+            }
+        } else {
+            Err(Error::MissingMetadata)
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(
+                archive_path = self.config.archive_path.as_deref(),
+                file_name = filename.as_ref(),
+                bytes = tracing::field::Empty
+            )
+        )
+    )]
+    pub fn get_file<'a>(
+        &'a mut self,
+        filename: impl AsRef<str>,
+    ) -> Result<Option<ArchiveFile<BlocksToFileReader<Box<dyn 'b + LayerReader<'b, R>>>>>, Error>
+    {
+        if let Some(ArchiveFooter { files_info, .. }) = &self.metadata {
+            let filename = filename.as_ref();
+            let progress = self.progress.as_mut();
+            let file = open_file(&mut self.src, files_info, &self.config, filename)?;
+            if let Some(file) = &file {
+                #[cfg(feature = "tracing")]
+                tracing::Span::current().record("bytes", file.size);
+                self.config.audit(
+                    self.archive_id,
+                    AuditEventType::FileContentAccess {
+                        filename: filename.to_string(),
+                        bytes_read: file.size,
+                    },
+                );
+            }
+            Ok(file.map(move |mut file| {
+                progress.on_file_start(filename, Some(file.size));
+                file.data = file.data.with_progress(progress, filename);
+                file
+            }))
+        } else {
+            Err(Error::MissingMetadata)
+        }
+    }
+
+    /// Open `filename` as `get_file` does, without reporting the access, to
+    /// load the data needed to open it. Return whether the file exists
+    #[cfg(feature = "async")]
+    pub(crate) fn prepare_file(&mut self, filename: &str) -> Result<bool, Error> {
+        match &self.metadata {
+            Some(ArchiveFooter { files_info, .. }) => {
+                Ok(open_file(&mut self.src, files_info, &self.config, filename)?.is_some())
+            }
+            None => Err(Error::MissingMetadata),
+        }
+    }
+
+    /// Return the content of every file present in the archive, by filename
+    ///
+    /// The whole content is loaded in memory. The archive is read linearly,
+    /// once, which is faster than calling `get_file` on each file (see
+    /// `helpers::linear_extract`)
+    pub fn get_all_files(&mut self) -> Result<HashMap<String, Vec<u8>>, Error> {
+        let fnames: Vec<String> = self.list_files()?.cloned().collect();
+        let mut export: HashMap<&String, Vec<u8>> =
+            fnames.iter().map(|fname| (fname, Vec::new())).collect();
+        helpers::linear_extract(self, &mut export)?;
+        Ok(export
+            .into_iter()
+            .map(|(fname, content)| (fname.clone(), content))
+            .collect())
+    }
+
+    /// Return an iterator on files present in the archive, in their order of
+    /// appearance
+    ///
+    /// The archive is read linearly while iterating, see `ArchiveEntries`
+    #[allow(clippy::type_complexity)]
+    pub fn entries<'a>(
+        &'a mut self,
+    ) -> Result<ArchiveEntries<'a, io::BufReader<&'a mut Box<dyn 'b + LayerReader<'b, R>>>>, Error>
+    {
+        // Seek at the beginning
+        self.src.seek(SeekFrom::Start(0))?;
+        let files_info = self.metadata.as_ref().map(|footer| &footer.files_info);
+        // Use a BufReader to cache, by merging them into one bigger read, small
+        // read calls (like the ones on ArchiveFileBlock reading)
+        Ok(ArchiveEntries::new(
+            io::BufReader::new(&mut self.src),
+            files_info,
+            self.config.skip_unknown_blocks,
+            self.config.encrypt.clone(),
+        ))
+    }
+
+    /// Verify the integrity of the archive, without extracting anything
+    ///
+    /// The archive is read linearly, once. This checks:
+    /// * the encryption tags, if the archive is encrypted
+    /// * the structure of the archive: each file is started once, ended once,
+    ///   and the archive data ends with an `EndOfArchiveData`
+    /// * the SHA-256 hash of each file, stored in its `EndOfFile`
+    /// * the checksum of each file, if present in the archive
+    /// * the size of each file, if the footer is available: the content of a
+    ///   file, and its sparse holes, can't be bigger than its recorded size
+    ///
+    /// Errors located in a file are reported in the returned
+    /// `VerificationReport`. If the content of a file can't be read, the scan
+    /// stops there, and files not ended yet are reported with
+    /// `Error::UnterminatedFile`. Other errors, such as an unreadable block or
+    /// an inconsistent structure, are returned as is.
+    pub fn verify_integrity(&mut self) -> Result<VerificationReport, Error> {
+        let has_dedup = self.features().contains(FeatureFlags::HAS_DEDUP);
+        // Seek at the beginning
+        self.src.seek(SeekFrom::Start(0))?;
+        // Use a BufReader to cache, by merging them into one bigger read, small
+        // read calls (like the ones on ArchiveFileBlock reading)
+        let mut src = io::BufReader::new(&mut self.src);
+
+        let mut report = VerificationReport::default();
+        // Files started, not yet ended
+        let mut files: HashMap<ArchiveFileID, FileIntegrity> = HashMap::new();
+        // Files already ended
+        let mut ended: HashSet<ArchiveFileID> = HashSet::new();
+        // Hash and checksum states of the files ended, if some files refer to
+        // the content of others
+        let mut ended_states: HashMap<ArchiveFileID, (Sha256, ChecksumHasher)> = HashMap::new();
+
+        loop {
+            match ArchiveFileBlock::from(&mut src)? {
+                ArchiveFileBlock::FileStart { filename, id } => {
+                    if files.contains_key(&id) || ended.contains(&id) {
+                        return Err(Error::WrongReaderState(
+                            "[VerifyIntegrity] A file ID is being reused".to_string(),
+                        ));
+                    }
+                    // Files missing from the footer have no content
+                    let remaining = self
+                        .metadata
+                        .as_ref()
+                        .map(|footer| footer.files_info.get(&filename).map_or(0, |info| info.size));
+                    files.insert(
+                        id,
+                        FileIntegrity {
+                            filename,
+                            hash: Sha256::default(),
+                            // BLAKE3 is the only supported algorithm for now
+                            checksum: ChecksumHasher::new(ChecksumAlgorithm::Blake3),
+                            error: None,
+                            remaining,
+                        },
+                    );
+                }
+                ArchiveFileBlock::FileContent { length, id, .. } => {
+                    let file = files.get_mut(&id).ok_or_else(|| {
+                        Error::WrongReaderState(
+                            "[VerifyIntegrity] Content for an unknown file".to_string(),
+                        )
+                    })?;
+                    file.consume(length);
+                    let copy_src = &mut HashWrapperReader::new(
+                        ChecksumWrapperReader::new((&mut src).take(length), &mut file.checksum),
+                        &mut file.hash,
+                    );
+                    let error = match io::copy(copy_src, &mut io::sink()) {
+                        Ok(copied) if copied == length => None,
+                        Ok(_) => Some(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                        Err(err) => Some(err.into()),
+                    };
+                    if let Some(error) = error {
+                        // The archive can't be read further
+                        file.error = Some(error);
+                        for (_id, file) in files.drain() {
+                            report.files_with_errors.push((
+                                file.filename,
+                                file.error.unwrap_or(Error::UnterminatedFile),
+                            ));
+                        }
+                        return Ok(report);
+                    }
+                }
+                ArchiveFileBlock::FilePrecompressedContent {
+                    id,
+                    algorithm,
+                    uncompressed_length,
+                    data,
+                } => {
+                    let file = files.get_mut(&id).ok_or_else(|| {
+                        Error::WrongReaderState(
+                            "[VerifyIntegrity] Content for an unknown file".to_string(),
+                        )
+                    })?;
+                    file.consume(uncompressed_length);
+                    // The block has been read entirely: the scan can go on
+                    // even if its content is wrong
+                    match decompress_block_content(algorithm, &data, uncompressed_length) {
+                        Ok(content) => {
+                            file.hash.update(&content);
+                            file.checksum.update(&content);
+                        }
+                        Err(error) => {
+                            file.error.get_or_insert(error);
+                        }
+                    }
+                }
+                ArchiveFileBlock::FileSparseHole { id, length } => {
+                    let file = files.get_mut(&id).ok_or_else(|| {
+                        Error::WrongReaderState(
+                            "[VerifyIntegrity] Content for an unknown file".to_string(),
+                        )
+                    })?;
+                    file.consume(length);
+                    // A hole can be long to hash, and useless to hash if the
+                    // file is already known to be wrong
+                    if file.error.is_none() {
+                        io::copy(
+                            &mut HashWrapperReader::new(
+                                ChecksumWrapperReader::new(
+                                    io::repeat(0).take(length),
+                                    &mut file.checksum,
+                                ),
+                                &mut file.hash,
+                            ),
+                            &mut io::sink(),
+                        )?;
+                    }
+                }
+                ArchiveFileBlock::FileChecksum { id, digest } => {
+                    let file = files.get_mut(&id).ok_or_else(|| {
+                        Error::WrongReaderState(
+                            "[VerifyIntegrity] Checksum for an unknown file".to_string(),
+                        )
+                    })?;
+                    if file.error.is_none() && file.checksum.finalize() != digest {
+                        file.error = Some(Error::ChecksumMismatch);
+                    }
+                }
+                ArchiveFileBlock::FileMetadata { id, .. } => {
+                    if !files.contains_key(&id) {
+                        return Err(Error::WrongReaderState(
+                            "[VerifyIntegrity] Metadata for an unknown file".to_string(),
+                        ));
+                    }
+                }
+                ArchiveFileBlock::FileSizeHint { id, .. } => {
+                    if !files.contains_key(&id) {
+                        return Err(Error::WrongReaderState(
+                            "[VerifyIntegrity] Size hint for an unknown file".to_string(),
+                        ));
+                    }
+                }
+                ArchiveFileBlock::UserMetadata { id, .. } => {
+                    if !files.contains_key(&id) {
+                        return Err(Error::WrongReaderState(
+                            "[VerifyIntegrity] User metadata for an unknown file".to_string(),
+                        ));
+                    }
+                }
+                ArchiveFileBlock::FileRecipients { id, .. }
+                | ArchiveFileBlock::FileTag { id, .. } => {
+                    // The encrypted content is verified through its hash and
+                    // checksum, without its key
+                    if !files.contains_key(&id) {
+                        return Err(Error::WrongReaderState(
+                            "[VerifyIntegrity] Recipients for an unknown file".to_string(),
+                        ));
+                    }
+                }
+                ArchiveFileBlock::Dedup { id, refers_to_id } => {
+                    let file = files.get_mut(&id).ok_or_else(|| {
+                        Error::WrongReaderState(
+                            "[VerifyIntegrity] Content for an unknown file".to_string(),
+                        )
+                    })?;
+                    // As if the content of `refers_to_id` was read again
+                    let (hash, checksum) = ended_states.get(&refers_to_id).ok_or_else(|| {
+                        Error::WrongReaderState(
+                            "[VerifyIntegrity] Reference to a file not ended".to_string(),
+                        )
+                    })?;
+                    file.hash = hash.clone();
+                    file.checksum = checksum.clone();
+                }
+                ArchiveFileBlock::DirectoryEntry { .. }
+                | ArchiveFileBlock::Symlink { .. }
+                | ArchiveFileBlock::Padding { .. } => {}
+                ArchiveFileBlock::FileAlias { original_id, .. } => {
+                    if !ended.contains(&original_id) {
+                        return Err(Error::WrongReaderState(
+                            "[VerifyIntegrity] Alias of a file not ended".to_string(),
+                        ));
+                    }
+                }
+                ArchiveFileBlock::EndOfFile { id, hash } => {
+                    let file = files.remove(&id).ok_or_else(|| {
+                        Error::WrongReaderState(
+                            "[VerifyIntegrity] Termination of an unknown file".to_string(),
+                        )
+                    })?;
+                    ended.insert(id);
+                    if has_dedup {
+                        ended_states.insert(id, (file.hash.clone(), file.checksum.clone()));
+                    }
+                    let mut error = file.error;
+                    if error.is_none() && file.hash.finalize().as_slice() != hash {
+                        error = Some(Error::HashMismatch);
+                    }
+                    match error {
+                        Some(error) => report.files_with_errors.push((file.filename, error)),
+                        None => report.files_ok += 1,
+                    }
+                }
+                ArchiveFileBlock::EndOfArchiveData => {
+                    report.archive_complete = files.is_empty();
+                    for (_id, file) in files.drain() {
+                        report
+                            .files_with_errors
+                            .push((file.filename, Error::UnterminatedFile));
+                    }
+                    return Ok(report);
+                }
+                ArchiveFileBlock::Unknown { kind, .. } => {
+                    check_unknown_block(kind, self.config.skip_unknown_blocks)?;
+                }
+            }
+        }
+    }
+}
+
+// -------- Entries --------
+
+/// Linear scan of an archive blocks, shared between `ArchiveEntries` and the
+/// `ArchiveEntry` it yields
+/// Kind of an entry of the archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+}
+
+/// Entry met by an `EntriesScanner`, not yet yielded
+enum PendingEntry {
+    File {
+        id: ArchiveFileID,
+        filename: String,
+        metadata: Option<FileMetadata>,
+        size_hint: Option<u64>,
+    },
+    Directory {
+        path: String,
+        metadata: Option<FileMetadata>,
+    },
+    Symlink {
+        link_name: String,
+        target: String,
+        metadata: Option<FileMetadata>,
+    },
+}
+
+/// Content block being read by an `EntriesScanner`
+enum ScannedBlock {
+    /// `FileContent` block, with its remaining bytes in the source
+    Data(u64),
+    /// `FileSparseHole` block, with its remaining length
+    Hole(u64),
+    /// `FilePrecompressedContent` block, not read yet
+    Compressed {
+        algorithm: CompressionAlgorithm,
+        data: Vec<u8>,
+        uncompressed_length: u64,
+    },
+    /// Decompressed content of a `FilePrecompressedContent` block, and the
+    /// position in it
+    Decompressed(Vec<u8>, usize),
+}
+
+impl ScannedBlock {
+    /// Bytes of the file content not read yet
+    fn remaining(&self) -> u64 {
+        match self {
+            ScannedBlock::Data(remaining) | ScannedBlock::Hole(remaining) => *remaining,
+            ScannedBlock::Compressed {
+                uncompressed_length,
+                ..
+            } => *uncompressed_length,
+            ScannedBlock::Decompressed(content, position) => (content.len() - position) as u64,
+        }
+    }
+}
+
+struct EntriesScanner<S: Read> {
+    src: S,
+    /// Entries met, not yet yielded
+    pending: VecDeque<PendingEntry>,
+    /// Files whose EndOfFile has been reached
+    ended: HashSet<ArchiveFileID>,
+    /// Files for which at least one content block has been skipped
+    skipped: HashSet<ArchiveFileID>,
+    /// Current content block, with its file ID
+    block: Option<(ArchiveFileID, ScannedBlock)>,
+    /// EndOfArchiveData has been reached
+    finished: bool,
+    /// Metadata of files, not yet yielded
+    metadata: HashMap<ArchiveFileID, FileMetadata>,
+    /// Announced sizes of files, not yet yielded
+    size_hints: HashMap<ArchiveFileID, u64>,
+    /// ID of the file whose FileStart, and optionally FileMetadata,
+    /// FileSizeHint or FileRecipients, are the last blocks read, if any
+    last_started: Option<ArchiveFileID>,
+    /// Ignore blocks of unknown type, instead of failing
+    skip_unknown_blocks: bool,
+    /// Private keys, for the files encrypted for their own recipients
+    encrypt: EncryptionReaderConfig,
+    /// Decryption of the files encrypted for their own recipients, not yet
+    /// ended. `None` if none of the private keys is a recipient
+    decryptors: HashMap<ArchiveFileID, Option<FileDecryptor>>,
+}
+
+impl<S: Read> EntriesScanner<S> {
+    /// Read the next block, skipping the rest of the current content block
+    fn next_block(&mut self) -> Result<(), Error> {
+        if let Some((id, block)) = self.block.take() {
+            if block.remaining() > 0 {
+                if let ScannedBlock::Data(remaining) = block {
+                    io::copy(&mut (&mut self.src).take(remaining), &mut io::sink())?;
+                }
+                self.skipped.insert(id);
+            }
+        }
+        let last_started = self.last_started.take();
+        match ArchiveFileBlock::from(&mut self.src)? {
+            ArchiveFileBlock::FileStart { filename, id } => {
+                self.pending.push_back(PendingEntry::File {
+                    id,
+                    filename,
+                    metadata: None,
+                    size_hint: None,
+                });
+                self.last_started = Some(id);
+            }
+            ArchiveFileBlock::FileContent { length, id, .. } => {
+                self.block = Some((id, ScannedBlock::Data(length)));
+            }
+            ArchiveFileBlock::FilePrecompressedContent {
+                id,
+                algorithm,
+                uncompressed_length,
+                data,
+            } => {
+                self.block = Some((
+                    id,
+                    ScannedBlock::Compressed {
+                        algorithm,
+                        data,
+                        uncompressed_length,
+                    },
+                ));
+            }
+            ArchiveFileBlock::FileSparseHole { id, length } => {
+                self.block = Some((id, ScannedBlock::Hole(length)));
+            }
+            ArchiveFileBlock::EndOfFile { id, .. } => {
+                if let Some(Some(_)) = self.decryptors.remove(&id) {
+                    if !self.skipped.contains(&id) {
+                        // The content has not been authenticated
+                        return Err(Error::AuthenticatedDecryptionWrongTag);
+                    }
+                }
+                self.ended.insert(id);
+            }
+            ArchiveFileBlock::FileChecksum { .. } | ArchiveFileBlock::FileAlias { .. } => {
+                // The content of an alias is stored earlier in the archive
+            }
+            ArchiveFileBlock::Padding { .. } => {}
+            ArchiveFileBlock::FileRecipients { id, recipients } => {
+                let decryptor = self.encrypt.file_decryptor(&recipients).ok();
+                self.decryptors.insert(id, decryptor);
+                if last_started == Some(id) {
+                    self.last_started = last_started;
+                }
+            }
+            ArchiveFileBlock::FileTag { id, tag } => {
+                if let Some(Some(decryptor)) = self.decryptors.remove(&id) {
+                    // A partially read content can't be authenticated
+                    if !self.skipped.contains(&id) {
+                        decryptor.check_tag(&tag)?;
+                    }
+                }
+            }
+            ArchiveFileBlock::Dedup { id, .. } => {
+                // The content is stored earlier in the archive, out of reach
+                self.skipped.insert(id);
+            }
+            ArchiveFileBlock::FileMetadata { id, metadata } => {
+                self.metadata.insert(id, metadata);
+                if last_started == Some(id) {
+                    self.last_started = last_started;
+                }
+            }
+            ArchiveFileBlock::FileSizeHint { id, size } => {
+                self.size_hints.insert(id, size);
+                if last_started == Some(id) {
+                    self.last_started = last_started;
+                }
+            }
+            ArchiveFileBlock::UserMetadata { id, .. } => {
+                if last_started == Some(id) {
+                    self.last_started = last_started;
+                }
+            }
+            ArchiveFileBlock::DirectoryEntry { path, metadata } => {
+                self.pending
+                    .push_back(PendingEntry::Directory { path, metadata });
+            }
+            ArchiveFileBlock::Symlink {
+                link_name,
+                target,
+                metadata,
+            } => {
+                self.pending.push_back(PendingEntry::Symlink {
+                    link_name,
+                    target,
+                    metadata,
+                });
+            }
+            ArchiveFileBlock::EndOfArchiveData => {
+                self.finished = true;
+            }
+            ArchiveFileBlock::Unknown { kind, .. } => {
+                check_unknown_block(kind, self.skip_unknown_blocks)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Move to the next entry
+    fn next_entry(&mut self) -> Result<Option<PendingEntry>, Error> {
+        loop {
+            if let Some(entry) = self.pending.pop_front() {
+                if let PendingEntry::File { id, filename, .. } = entry {
+                    // Metadata and size hint, if any, are in the blocks
+                    // following the FileStart
+                    while self.last_started == Some(id) {
+                        self.next_block()?;
+                    }
+                    let metadata = self.metadata.remove(&id);
+                    let size_hint = self.size_hints.remove(&id);
+                    return Ok(Some(PendingEntry::File {
+                        id,
+                        filename,
+                        metadata,
+                        size_hint,
+                    }));
+                }
+                return Ok(Some(entry));
+            }
+            if self.finished {
+                return Ok(None);
+            }
+            self.next_block()?;
+        }
+    }
+
+    /// Read the content of file `id`, skipping blocks of other files
+    fn read_file(&mut self, id: ArchiveFileID, into: &mut [u8]) -> Result<usize, Error> {
+        if self.skipped.contains(&id) {
+            return Err(Error::WrongReaderState(
+                "[ArchiveEntries] File content has already been skipped".to_string(),
+            ));
+        }
+        if let Some(None) = self.decryptors.get(&id) {
+            return Err(Error::PrivateKeyNeeded);
+        }
+        if into.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some((block_id, block)) = &mut self.block {
+                if *block_id == id && block.remaining() > 0 {
+                    let count = match block {
+                        ScannedBlock::Hole(remaining) => {
+                            if self.decryptors.contains_key(&id) {
+                                // Holes are not authenticated
+                                return Err(Error::WrongReaderState(
+                                    "[ArchiveEntries] Hole in an encrypted file".to_string(),
+                                ));
+                            }
+                            let count = std::cmp::min(*remaining, into.len() as u64) as usize;
+                            into[..count].fill(0);
+                            *remaining -= count as u64;
+                            count
+                        }
+                        ScannedBlock::Data(remaining) => {
+                            let count = (&mut self.src).take(*remaining).read(into)?;
+                            if let Some(Some(decryptor)) = self.decryptors.get_mut(&id) {
+                                decryptor.decrypt(&mut into[..count]);
+                            }
+                            *remaining -= count as u64;
+                            count
+                        }
+                        ScannedBlock::Compressed {
+                            algorithm,
+                            data,
+                            uncompressed_length,
+                        } => {
+                            if self.decryptors.contains_key(&id) {
+                                return Err(Error::WrongReaderState(
+                                    "[ArchiveEntries] Pre-compressed content in an encrypted file"
+                                        .to_string(),
+                                ));
+                            }
+                            let content =
+                                decompress_block_content(*algorithm, data, *uncompressed_length)?;
+                            *block = ScannedBlock::Decompressed(content, 0);
+                            continue;
+                        }
+                        ScannedBlock::Decompressed(content, position) => {
+                            let count = std::cmp::min(content.len() - *position, into.len());
+                            into[..count].copy_from_slice(&content[*position..*position + count]);
+                            *position += count;
+                            count
+                        }
+                    };
+                    if count == 0 {
+                        return Err(Error::IOError(io::ErrorKind::UnexpectedEof.into()));
+                    }
+                    return Ok(count);
+                }
+            }
+            if self.ended.contains(&id) {
+                return Ok(0);
+            }
+            if self.finished {
+                return Err(Error::WrongReaderState(
+                    "[ArchiveEntries] End of archive reached before the end of file".to_string(),
+                ));
+            }
+            self.next_block()?;
+        }
+    }
+}
+
+/// Iterator on entries (files, directories and symbolic links) of an archive,
+/// in their order of appearance
+///
+/// The archive is read linearly, without seeking, while iterating. As a
+/// result:
+/// - the content of an entry must be read before moving to the next one. Once
+///   the iterator has been advanced, the remaining content of previous entries
+///   is skipped, and reading it returns an error
+/// - for interleaved files, parts of an entry content might have been skipped
+///   before the entry is yielded. `ArchiveReader::get_file` should be used
+///   instead
+/// - the content of deduplicated files (see
+///   `ArchiveWriterConfig::with_dedup`) cannot be read
+/// - the content of a file encrypted for its own recipients (see
+///   `ArchiveWriter::start_file_for_recipients`) is only authenticated once
+///   entirely read
+pub struct ArchiveEntries<'a, S: Read> {
+    scanner: Rc<RefCell<EntriesScanner<S>>>,
+    /// Files information from the footer, if available
+    files_info: Option<&'a HashMap<String, FileInfo>>,
+}
+
+impl<'a, S: Read> ArchiveEntries<'a, S> {
+    fn new(
+        src: S,
+        files_info: Option<&'a HashMap<String, FileInfo>>,
+        skip_unknown_blocks: bool,
+        encrypt: EncryptionReaderConfig,
+    ) -> Self {
+        ArchiveEntries {
+            scanner: Rc::new(RefCell::new(EntriesScanner {
+                src,
+                pending: VecDeque::new(),
+                ended: HashSet::new(),
+                skipped: HashSet::new(),
+                block: None,
+                finished: false,
+                metadata: HashMap::new(),
+                size_hints: HashMap::new(),
+                last_started: None,
+                skip_unknown_blocks,
+                encrypt,
+                decryptors: HashMap::new(),
+            })),
+            files_info,
+        }
+    }
+}
+
+impl<'a, S: 'a + Read> ArchiveEntries<'a, Box<dyn 'a + LayerFailSafeReader<'a, S>>> {
+    /// Iterate on the archive read from `src`, which doesn't need to be
+    /// seekable
+    ///
+    /// As the other layers rely on seeking, this is only supported for
+    /// archives without any layer, or with only the compression layer, read
+    /// through its fail-safe reader. Files size is then unknown, unless
+    /// announced by the writer (see `ArchiveWriter::set_file_size_hint`).
+    pub fn from_reader(mut src: S) -> Result<Self, Error> {
+        let header = ArchiveHeader::from(&mut src)?;
+        let layers = header.config.layers_enabled;
+        if !(layers.is_empty() || layers == Layers::COMPRESS) {
+            return Err(Error::BadAPIArgument(
+                "[ArchiveEntries] Only archives without layers, or only compressed, can be read without seeking".to_string(),
+            ));
+        }
+        let mut config = ArchiveReaderConfig::new();
+        config.load_persistent(header.config)?;
+        let mut src: Box<dyn 'a + LayerFailSafeReader<'a, S>> =
+            Box::new(RawLayerFailSafeReader::new(src));
+        if layers.contains(Layers::COMPRESS) {
+            src = Box::new(CompressionLayerFailSafeReader::new(src, &config.compress)?);
+        }
+        Ok(Self::new(
+            src,
+            None,
+            true,
+            EncryptionReaderConfig::default(),
+        ))
+    }
+}
+
+impl<'a, S: Read> Iterator for ArchiveEntries<'a, S> {
+    type Item = Result<ArchiveEntry<S>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.scanner.borrow_mut().next_entry();
+        let data = ArchiveEntryReader {
+            scanner: self.scanner.clone(),
+            id: None,
+        };
+        match next {
+            Ok(Some(PendingEntry::File {
+                id,
+                filename,
+                metadata,
+                size_hint,
+            })) => {
+                // The footer size is exact, the hint is only announced
+                let size = self
+                    .files_info
+                    .and_then(|files_info| files_info.get(&filename))
+                    .map(|file_info| file_info.size)
+                    .or(size_hint);
+                Some(Ok(ArchiveEntry {
+                    filename,
+                    kind: EntryKind::File,
+                    size,
+                    metadata,
+                    link_target: None,
+                    data: ArchiveEntryReader {
+                        id: Some(id),
+                        ..data
+                    },
+                }))
+            }
+            Ok(Some(PendingEntry::Directory { path, metadata })) => Some(Ok(ArchiveEntry {
+                filename: path,
+                kind: EntryKind::Directory,
+                size: Some(0),
+                metadata,
+                link_target: None,
+                data,
+            })),
+            Ok(Some(PendingEntry::Symlink {
+                link_name,
+                target,
+                metadata,
+            })) => Some(Ok(ArchiveEntry {
+                filename: link_name,
+                kind: EntryKind::Symlink,
+                size: Some(0),
+                metadata,
+                link_target: Some(target),
+                data,
+            })),
+            Ok(None) => None,
+            Err(err) => {
+                // The scan can't be trusted anymore, stop there
+                let mut scanner = self.scanner.borrow_mut();
+                scanner.pending.clear();
+                scanner.finished = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Entry yielded by `ArchiveEntries`
+///
+/// Directories and symbolic links have an empty content
+pub struct ArchiveEntry<S: Read> {
+    /// Path of the entry (the link name, for a symbolic link)
+    pub filename: String,
+    pub kind: EntryKind,
+    /// Size of the file, in bytes, if known. Without footer, this is the size
+    /// announced by the writer, if any, which may differ from the content
+    pub size: Option<u64>,
+    /// Metadata of the entry, if stored in the archive
+    pub metadata: Option<FileMetadata>,
+    /// Target of the symbolic link, for `EntryKind::Symlink`
+    pub link_target: Option<String>,
+    data: ArchiveEntryReader<S>,
+}
+
+impl<S: Read> ArchiveEntry<S> {
+    /// Get the file content reader, dropping the other information
+    pub fn into_data(self) -> ArchiveEntryReader<S> {
+        self.data
+    }
+}
+
+impl<S: Read> Read for ArchiveEntry<S> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        self.data.read(into)
+    }
+}
+
+/// Content of an `ArchiveEntry`
+pub struct ArchiveEntryReader<S: Read> {
+    scanner: Rc<RefCell<EntriesScanner<S>>>,
+    /// id of the File being read, `None` for entries without content
+    id: Option<ArchiveFileID>,
+}
+
+impl<S: Read> Read for ArchiveEntryReader<S> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        match self.id {
+            Some(id) => Ok(self.scanner.borrow_mut().read_file(id, into)?),
+            None => Ok(0),
+        }
+    }
+}
+
+// This code is very similar with MLAArchiveReader
+
+pub struct ArchiveFailSafeReader<'a, R: 'a + Read> {
+    /// MLA Archive format Reader (fail-safe)
+
+    /// User's reading configuration
+    // config is not used for now after reader creation,
+    // but it could in the future
+    #[allow(dead_code)]
+    config: ArchiveReaderConfig,
+    /// Source
+    src: Box<dyn 'a + LayerFailSafeReader<'a, R>>,
+}
+
+// Size of the repaired file blocks
+const CACHE_SIZE: usize = 8 * 1024 * 1024; // 8MB
+
+/// Used to update the error state only if it was NoError
+/// ```
+/// update_error!(error_var, FailSafeReadError::...)
+/// ``̀
+macro_rules! update_error {
+    ( $x:ident = $y:expr ) => {
+        #[allow(clippy::single_match)]
+        match $x {
+            FailSafeReadError::NoError => {
+                $x = $y;
+            }
+            _ => {}
+        }
+    };
+}
+
+impl<'b, R: 'b + Read> ArchiveFailSafeReader<'b, R> {
+    pub fn from_config(mut src: R, mut config: ArchiveReaderConfig) -> Result<Self, Error> {
+        let header = ArchiveHeader::from(&mut src)?;
+        config.load_persistent(header.config)?;
+
+        // Enable layers depending on user option. Order is relevant
+        let mut src: Box<dyn 'b + LayerFailSafeReader<'b, R>> =
+            Box::new(RawLayerFailSafeReader::new(src));
+        if config.layers_enabled.contains(Layers::SIGN) {
+            src = Box::new(SignatureLayerFailSafeReader::new(src));
+        }
+        // The public listing, if any, is only seen as trailing data, after the
+        // `EndOfArchiveData`
+        if config.layers_enabled.contains(Layers::ENCRYPT) {
+            src = Box::new(EncryptionLayerFailSafeReader::new(src, &config.encrypt)?);
+        }
+        if config.layers_enabled.contains(Layers::COMPRESS) {
+            src = Box::new(CompressionLayerFailSafeReader::new(src, &config.compress)?);
+        }
+
+        Ok(Self { config, src })
+    }
+
+    pub fn new(src: R) -> Result<Self, Error> {
+        Self::from_config(src, ArchiveReaderConfig::new())
+    }
+
+    /// Fail-safe / best-effort conversion of the current archive to a correct
+    /// one. On success, returns the reason conversion terminates (ideally,
+    /// EndOfOriginalArchiveData)
+    #[allow(clippy::cognitive_complexity)]
+    pub fn convert_to_archive<W: Write>(
+        &mut self,
+        output: &mut ArchiveWriter<W>,
+    ) -> Result<FailSafeReadError, Error> {
+        let mut error = FailSafeReadError::NoError;
+
+        // Associate an id retrieved from the archive to repair, to the
+        // corresponding output file id
+        let mut id_failsafe2id_output: HashMap<ArchiveFileID, ArchiveFileID> = HashMap::new();
+        // Associate an id retrieved from the archive to corresponding filename
+        let mut id_failsafe2filename: HashMap<ArchiveFileID, String> = HashMap::new();
+        // List of IDs from the archive already fully added
+        let mut id_failsafe_done = Vec::new();
+        // Associate an id retrieved from the archive with its ongoing Hash
+        let mut id_failsafe2hash: HashMap<ArchiveFileID, Sha256> = HashMap::new();
+        // Associate an id from the archive already fully added with its Hash
+        // state, for deduplicated files referring to it
+        let mut id_failsafe_done2hash: HashMap<ArchiveFileID, Sha256> = HashMap::new();
+
+        'read_block: loop {
+            match ArchiveFileBlock::from(&mut self.src) {
+                Err(Error::IOError(err)) => {
+                    if let std::io::ErrorKind::UnexpectedEof = err.kind() {
+                        update_error!(error = FailSafeReadError::UnexpectedEOFOnNextBlock);
+                        break;
+                    }
+                    update_error!(error = FailSafeReadError::IOErrorOnNextBlock(err));
+                    break;
+                }
+                Err(err) => {
+                    update_error!(error = FailSafeReadError::ErrorOnNextBlock(err));
+                    break;
+                }
+                Ok(block) => {
+                    match block {
+                        ArchiveFileBlock::FileStart { filename, id } => {
+                            if let Some(_id_output) = id_failsafe2id_output.get(&id) {
+                                update_error!(error = FailSafeReadError::ArchiveFileIDReuse(id));
+                                break 'read_block;
+                            }
+                            if id_failsafe_done.contains(&id) {
+                                update_error!(
+                                    error = FailSafeReadError::ArchiveFileIDAlreadyClose(id)
+                                );
+                                break 'read_block;
+                            }
+
+                            id_failsafe2filename.insert(id, filename.clone());
+                            let id_output = match output.start_file(&filename) {
+                                Err(Error::DuplicateFilename) => {
+                                    update_error!(
+                                        error = FailSafeReadError::FilenameReuse(filename)
+                                    );
+                                    break 'read_block;
+                                }
+                                Err(err) => {
+                                    return Err(err);
+                                }
+                                Ok(id) => id,
+                            };
+                            id_failsafe2id_output.insert(id, id_output);
+                            id_failsafe2hash.insert(id, Sha256::default());
+                        }
+                        ArchiveFileBlock::FileContent { length, id, .. } => {
+                            let id_output = match id_failsafe2id_output.get(&id) {
+                                Some(id_output) => *id_output,
+                                None => {
+                                    update_error!(
+                                        error = FailSafeReadError::ContentForUnknownFile(id)
+                                    );
+                                    break 'read_block;
+                                }
+                            };
+                            if id_failsafe_done.contains(&id) {
+                                update_error!(
+                                    error = FailSafeReadError::ArchiveFileIDAlreadyClose(id)
+                                );
+                                break 'read_block;
+                            }
+                            let fname = id_failsafe2filename.get(&id).expect(
+                                "`id_failsafe2filename` not more sync with `id_failsafe2id_output`",
+                            );
+                            let hash = id_failsafe2hash.get_mut(&id).expect(
+                                "`id_failsafe2hash` not more sync with `id_failsafe2id_output`",
+                            );
+
+                            let src = &mut (&mut self.src).take(length as u64);
+                            'content: loop {
+                                let mut buf = Vec::with_capacity(CACHE_SIZE);
+                                'buf_fill: loop {
+                                    // Read bytes one per one to take the maximum of it
+                                    let mut mini_buf = [0u8; 1];
+                                    match src.read(&mut mini_buf) {
+                                        Ok(read) => {
+                                            if read == 0 {
+                                                // EOF
+                                                break 'buf_fill;
+                                            }
+                                            buf.push(mini_buf[0]);
+                                        }
+                                        Err(err) => {
+                                            // Stop reconstruction
+                                            output.append_file_content(
+                                                id_output,
+                                                buf.len() as u64,
+                                                buf.as_slice(),
+                                            )?;
+                                            update_error!(
+                                                error = FailSafeReadError::ErrorInFile(
+                                                    err,
+                                                    fname.clone()
+                                                )
+                                            );
+                                            break 'read_block;
+                                        }
+                                    }
+                                    // Cache full
+                                    if buf.len() == CACHE_SIZE {
+                                        break 'buf_fill;
+                                    }
+                                }
+                                output.append_file_content(
+                                    id_output,
+                                    buf.len() as u64,
+                                    buf.as_slice(),
+                                )?;
+                                hash.update(buf.as_slice());
+                                if buf.len() < CACHE_SIZE {
+                                    // EOF
+                                    break 'content;
+                                }
+                            }
+                        }
+                        ArchiveFileBlock::FilePrecompressedContent {
+                            id,
+                            algorithm,
+                            uncompressed_length,
+                            data,
+                        } => {
+                            let id_output = match id_failsafe2id_output.get(&id) {
+                                Some(id_output) => *id_output,
+                                None => {
+                                    update_error!(
+                                        error = FailSafeReadError::ContentForUnknownFile(id)
+                                    );
+                                    break 'read_block;
+                                }
+                            };
+                            if id_failsafe_done.contains(&id) {
+                                update_error!(
+                                    error = FailSafeReadError::ArchiveFileIDAlreadyClose(id)
+                                );
+                                break 'read_block;
+                            }
+                            let fname = id_failsafe2filename.get(&id).expect(
+                                "`id_failsafe2filename` not more sync with `id_failsafe2id_output`",
+                            );
+                            let hash = id_failsafe2hash.get_mut(&id).expect(
+                                "`id_failsafe2hash` not more sync with `id_failsafe2id_output`",
+                            );
+                            // Repaired as a regular content, `output` may not
+                            // accept pre-compressed ones
+                            match decompress_precompressed(algorithm, &data, uncompressed_length) {
+                                Ok(content) => {
+                                    output.append_file_content(
+                                        id_output,
+                                        content.len() as u64,
+                                        content.as_slice(),
+                                    )?;
+                                    hash.update(content.as_slice());
+                                }
+                                Err(err) => {
+                                    update_error!(
+                                        error = FailSafeReadError::ErrorInFile(err, fname.clone())
+                                    );
+                                    break 'read_block;
+                                }
+                            }
+                        }
+                        ArchiveFileBlock::FileSparseHole { id, length } => {
+                            let id_output = match id_failsafe2id_output.get(&id) {
+                                Some(id_output) => *id_output,
+                                None => {
+                                    update_error!(
+                                        error = FailSafeReadError::ContentForUnknownFile(id)
+                                    );
+                                    break 'read_block;
+                                }
+                            };
+                            if id_failsafe_done.contains(&id) {
+                                update_error!(
+                                    error = FailSafeReadError::ArchiveFileIDAlreadyClose(id)
+                                );
+                                break 'read_block;
+                            }
+                            let hash = id_failsafe2hash.get_mut(&id).expect(
+                                "`id_failsafe2hash` not more sync with `id_failsafe2id_output`",
+                            );
+                            output.append_file_sparse(
+                                id_output,
+                                length,
+                                &[SparseData::Zeroes(length)],
+                            )?;
+                            io::copy(
+                                &mut HashWrapperReader::new(io::repeat(0).take(length), hash),
+                                &mut io::sink(),
+                            )?;
+                        }
+                        ArchiveFileBlock::Dedup { id, refers_to_id } => {
+                            let id_output = match id_failsafe2id_output.get(&id) {
+                                Some(id_output) => *id_output,
+                                None => {
+                                    update_error!(
+                                        error = FailSafeReadError::ContentForUnknownFile(id)
+                                    );
+                                    break 'read_block;
+                                }
+                            };
+                            if id_failsafe_done.contains(&id) {
+                                update_error!(
+                                    error = FailSafeReadError::ArchiveFileIDAlreadyClose(id)
+                                );
+                                break 'read_block;
+                            }
+                            // The referred content must have been repaired
+                            let (refers_to_output, hash) = match (
+                                id_failsafe2id_output.get(&refers_to_id),
+                                id_failsafe_done2hash.get(&refers_to_id),
+                            ) {
+                                (Some(refers_to_output), Some(hash)) => {
+                                    (*refers_to_output, hash.clone())
+                                }
+                                _ => {
+                                    update_error!(
+                                        error =
+                                            FailSafeReadError::ContentForUnknownFile(refers_to_id)
+                                    );
+                                    break 'read_block;
+                                }
+                            };
+                            output.append_file_dedup(id_output, refers_to_output, hash.clone())?;
+                            id_failsafe2hash.insert(id, hash);
+                        }
+                        ArchiveFileBlock::FileChecksum { .. } => {
+                            // The checksum is recomputed by `output`, if enabled
+                        }
+                        ArchiveFileBlock::FileMetadata { id, metadata } => {
+                            let id_output = match id_failsafe2id_output.get(&id) {
+                                Some(id_output) => *id_output,
+                                None => {
+                                    update_error!(
+                                        error = FailSafeReadError::ContentForUnknownFile(id)
+                                    );
+                                    break 'read_block;
+                                }
+                            };
+                            if id_failsafe_done.contains(&id) {
+                                update_error!(
+                                    error = FailSafeReadError::ArchiveFileIDAlreadyClose(id)
+                                );
+                                break 'read_block;
+                            }
+                            output.append_file_metadata(id_output, &metadata)?;
+                        }
+                        ArchiveFileBlock::FileSizeHint { id, size } => {
+                            let id_output = match id_failsafe2id_output.get(&id) {
+                                Some(id_output) => *id_output,
+                                None => {
+                                    update_error!(
+                                        error = FailSafeReadError::ContentForUnknownFile(id)
+                                    );
+                                    break 'read_block;
+                                }
+                            };
+                            if id_failsafe_done.contains(&id) {
+                                update_error!(
+                                    error = FailSafeReadError::ArchiveFileIDAlreadyClose(id)
+                                );
+                                break 'read_block;
+                            }
+                            output.set_file_size_hint(id_output, size)?;
+                        }
+                        ArchiveFileBlock::UserMetadata { id, key, value } => {
+                            let id_output = match id_failsafe2id_output.get(&id) {
+                                Some(id_output) => *id_output,
+                                None => {
+                                    update_error!(
+                                        error = FailSafeReadError::ContentForUnknownFile(id)
+                                    );
+                                    break 'read_block;
+                                }
+                            };
+                            if id_failsafe_done.contains(&id) {
+                                update_error!(
+                                    error = FailSafeReadError::ArchiveFileIDAlreadyClose(id)
+                                );
+                                break 'read_block;
+                            }
+                            output.set_file_metadata(id_output, &key, &value)?;
+                        }
+                        ArchiveFileBlock::FileRecipients { id, recipients } => {
+                            let id_output = match id_failsafe2id_output.get(&id) {
+                                Some(id_output) => *id_output,
+                                None => {
+                                    update_error!(
+                                        error = FailSafeReadError::ContentForUnknownFile(id)
+                                    );
+                                    break 'read_block;
+                                }
+                            };
+                            if id_failsafe_done.contains(&id) {
+                                update_error!(
+                                    error = FailSafeReadError::ArchiveFileIDAlreadyClose(id)
+                                );
+                                break 'read_block;
+                            }
+                            // The content is copied still encrypted, with its key
+                            output.append_file_recipients(id_output, recipients)?;
+                        }
+                        ArchiveFileBlock::FileTag { id, tag } => {
+                            let id_output = match id_failsafe2id_output.get(&id) {
+                                Some(id_output) => *id_output,
+                                None => {
+                                    update_error!(
+                                        error = FailSafeReadError::ContentForUnknownFile(id)
+                                    );
+                                    break 'read_block;
+                                }
+                            };
+                            if id_failsafe_done.contains(&id) {
+                                update_error!(
+                                    error = FailSafeReadError::ArchiveFileIDAlreadyClose(id)
+                                );
+                                break 'read_block;
+                            }
+                            output.append_file_tag(id_output, tag)?;
+                        }
+                        ArchiveFileBlock::DirectoryEntry { path, metadata } => {
+                            match output.add_directory(&path, metadata) {
+                                Err(Error::DuplicateFilename) => {
+                                    update_error!(error = FailSafeReadError::FilenameReuse(path));
+                                    break 'read_block;
+                                }
+                                Err(err) => {
+                                    return Err(err);
+                                }
+                                Ok(()) => {}
+                            }
+                        }
+                        ArchiveFileBlock::Symlink {
+                            link_name,
+                            target,
+                            metadata,
+                        } => match output.add_symlink(&link_name, &target, metadata) {
+                            Err(Error::DuplicateFilename) => {
+                                update_error!(error = FailSafeReadError::FilenameReuse(link_name));
+                                break 'read_block;
+                            }
+                            Err(err) => {
+                                return Err(err);
+                            }
+                            Ok(()) => {}
+                        },
+                        ArchiveFileBlock::FileAlias {
+                            original_id,
+                            new_name,
+                        } => {
+                            let fname = match id_failsafe2filename.get(&original_id) {
+                                Some(fname) if id_failsafe_done.contains(&original_id) => fname,
+                                _ => {
+                                    update_error!(
+                                        error =
+                                            FailSafeReadError::ContentForUnknownFile(original_id)
+                                    );
+                                    break 'read_block;
+                                }
+                            };
+                            match output.rename_file(fname, &new_name) {
+                                Err(Error::DuplicateFilename) => {
+                                    update_error!(
+                                        error = FailSafeReadError::FilenameReuse(new_name)
+                                    );
+                                    break 'read_block;
+                                }
+                                Err(err) => {
+                                    return Err(err);
+                                }
+                                Ok(()) => {}
+                            }
+                        }
+                        ArchiveFileBlock::EndOfFile { id, hash } => {
+                            let id_output = match id_failsafe2id_output.get(&id) {
+                                Some(id_output) => *id_output,
+                                None => {
+                                    update_error!(error = FailSafeReadError::EOFForUnknownFile(id));
+                                    break 'read_block;
+                                }
+                            };
+                            if id_failsafe_done.contains(&id) {
+                                update_error!(
+                                    error = FailSafeReadError::ArchiveFileIDAlreadyClose(id)
+                                );
+                                break 'read_block;
+                            }
+                            match id_failsafe2hash.remove(&id) {
+                                Some(hash_archive) => {
+                                    id_failsafe_done2hash.insert(id, hash_archive.clone());
                                     let computed_hash = hash_archive.finalize();
                                     if computed_hash.as_slice() != hash {
                                         update_error!(
@@ -1181,624 +5634,3584 @@ impl<'b, R: 'b + Read> ArchiveFailSafeReader<'b, R> {
                                 }
                             };
 
-                            output.end_file(id_output)?;
-                            id_failsafe_done.push(id);
-                        }
-                        ArchiveFileBlock::EndOfArchiveData => {
-                            // Expected end
-                            update_error!(error = FailSafeReadError::EndOfOriginalArchiveData);
-                            break 'read_block;
-                        }
-                    }
-                }
-            };
+                            output.end_file(id_output)?;
+                            id_failsafe_done.push(id);
+                        }
+                        ArchiveFileBlock::EndOfArchiveData => {
+                            // Expected end
+                            update_error!(error = FailSafeReadError::EndOfOriginalArchiveData);
+                            break 'read_block;
+                        }
+                        ArchiveFileBlock::Padding { .. } | ArchiveFileBlock::Unknown { .. } => {
+                            // Content already skipped, nothing to repair
+                        }
+                    }
+                }
+            };
+        }
+
+        let mut unfinished_files = Vec::new();
+
+        // Clean-up files still opened
+        for (id_failsafe, id_output) in id_failsafe2id_output {
+            if id_failsafe_done.contains(&id_failsafe) {
+                // File is OK
+                continue;
+            }
+
+            let fname = id_failsafe2filename
+                .get(&id_failsafe)
+                .expect("`id_failsafe2filename` not more sync with `id_failsafe2id_output`");
+            output.end_file(id_output)?;
+
+            unfinished_files.push(fname.clone());
+        }
+
+        // Report which files are not completed, if any
+        if !unfinished_files.is_empty() {
+            error = FailSafeReadError::UnfinishedFiles {
+                filenames: unfinished_files,
+                stopping_error: Box::new(error),
+            };
+        }
+
+        output.finalize()?;
+        Ok(error)
+    }
+}
+
+// -------- Stream reader --------
+
+/// Reader of an archive from a non-seekable source, such as a pipe or the
+/// standard input, see `ArchiveReader::from_stream_config`
+///
+/// The archive is read once, linearly: files can be iterated on with
+/// `entries` or extracted with `linear_extract`, but can't be accessed by
+/// name. Encrypted data is authenticated before being provided
+pub struct StreamArchiveReader<'a, R: 'a + Read> {
+    /// User's reading configuration
+    config: ArchiveReaderConfig,
+    /// Source
+    src: Box<dyn 'a + LayerFailSafeReader<'a, R>>,
+}
+
+impl ArchiveReader<'static, io::Empty> {
+    /// Read the archive from `src`, which doesn't need to be seekable
+    ///
+    /// As the signature is at the end of the archive, it can't be verified
+    /// before providing the data: `ArchiveReaderConfig::verify_with` is
+    /// refused with `Error::BadAPIArgument`
+    pub fn from_stream_config<'a, S: 'a + Read>(
+        src: S,
+        config: ArchiveReaderConfig,
+    ) -> Result<StreamArchiveReader<'a, S>, Error> {
+        StreamArchiveReader::from_config(src, config)
+    }
+}
+
+impl<'a, R: 'a + Read> StreamArchiveReader<'a, R> {
+    pub fn from_config(mut src: R, mut config: ArchiveReaderConfig) -> Result<Self, Error> {
+        if config.verifying_key().is_some() {
+            return Err(Error::BadAPIArgument(
+                "[StreamArchiveReader] The signature can't be verified without seeking".to_string(),
+            ));
+        }
+        let header = ArchiveHeader::from(&mut src)?;
+        if header
+            .config
+            .layers_enabled
+            .contains(Layers::PUBLIC_LISTING)
+        {
+            // The end of the encrypted data can't be found before the listing
+            return Err(Error::BadAPIArgument(
+                "[StreamArchiveReader] An archive with a public listing can't be streamed"
+                    .to_string(),
+            ));
+        }
+        config.load_persistent(header.config)?;
+
+        // Enable layers depending on user option. Order is relevant
+        let mut src: Box<dyn 'a + LayerFailSafeReader<'a, R>> =
+            Box::new(RawLayerFailSafeReader::new(src));
+        if config.layers_enabled.contains(Layers::SIGN) {
+            // Only remove the trailing signature
+            src = Box::new(SignatureLayerFailSafeReader::new(src));
+        }
+        if config.layers_enabled.contains(Layers::ENCRYPT) {
+            src = Box::new(EncryptionLayerStreamReader::new(src, &config.encrypt)?);
+        }
+        if config.layers_enabled.contains(Layers::COMPRESS) {
+            src = Box::new(CompressionLayerFailSafeReader::new(src, &config.compress)?);
+        }
+
+        Ok(Self { config, src })
+    }
+
+    /// Return an iterator on files present in the archive, in their order of
+    /// appearance, consuming the reader
+    ///
+    /// Files size is unknown, unless announced by the writer (see
+    /// `ArchiveWriter::set_file_size_hint`)
+    #[allow(clippy::type_complexity)]
+    pub fn entries(
+        self,
+    ) -> ArchiveEntries<'static, io::BufReader<Box<dyn 'a + LayerFailSafeReader<'a, R>>>> {
+        // Use a BufReader to cache, by merging them into one bigger read, small
+        // read calls (like the ones on ArchiveFileBlock reading)
+        ArchiveEntries::new(
+            io::BufReader::new(self.src),
+            None,
+            self.config.skip_unknown_blocks,
+            self.config.encrypt.clone(),
+        )
+    }
+
+    /// Extract the files listed in `export` to their corresponding writer,
+    /// consuming the reader. Other files are skipped
+    pub fn linear_extract<W: Write, S: BuildHasher>(
+        self,
+        export: &mut HashMap<&String, W, S>,
+    ) -> Result<(), Error> {
+        for entry in self.entries() {
+            let mut entry = entry?;
+            if entry.kind != EntryKind::File {
+                continue;
+            }
+            if let Some(writer) = export.get_mut(&entry.filename) {
+                io::copy(&mut entry, writer)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::{
+        BlockBoundary, CompressionAlgorithm, CompressionHint, PaddingStrategy,
+        PathNormalizationPolicy,
+    };
+    use crate::crypto::password::Argon2Params;
+    use crate::crypto::threshold::ThresholdConfig;
+    use crate::errors::ConfigError;
+    use ed25519_dalek::{SigningKey, VerifyingKey};
+    use ed25519_parser::{parse_openssl_ed25519_privkey, parse_openssl_ed25519_pubkey};
+    use hex;
+    use rand::{Rng, RngCore, SeedableRng};
+    use rand_chacha::ChaChaRng;
+    use std::io::{Cursor, Empty, Read};
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    #[test]
+    fn read_dump_header() {
+        let header = ArchiveHeader {
+            format: FormatVersion::CURRENT,
+            comment: None,
+            archive_id: Some([7; ARCHIVE_ID_SIZE]),
+            config: ArchivePersistentConfig {
+                layers_enabled: Layers::default(),
+                encrypt: None,
+                compress: None,
+            },
+        };
+        let mut buf = Vec::new();
+        header.dump(&mut buf).unwrap();
+        println!("{:?}", buf);
+
+        let header_rebuild = ArchiveHeader::from(&mut buf.as_slice()).unwrap();
+        assert_eq!(header_rebuild.format, FormatVersion::CURRENT);
+        assert_eq!(header_rebuild.archive_id, Some([7; ARCHIVE_ID_SIZE]));
+        assert_eq!(header_rebuild.config.layers_enabled, Layers::default());
+    }
+
+    #[test]
+    fn dump_block() {
+        let mut buf = Vec::new();
+        let id = ArchiveFileID(0);
+        let hash = Sha256Hash::default();
+
+        // std::io::Empty is used because a type with Read is needed
+        ArchiveFileBlock::FileStart::<Empty> {
+            id,
+            filename: String::from("foobaré.exe"),
+        }
+        .dump(&mut buf)
+        .unwrap();
+
+        let fake_content = vec![1, 2, 3, 4];
+        let mut block = ArchiveFileBlock::FileContent {
+            id,
+            length: fake_content.len() as u64,
+            data: Some(fake_content.as_slice()),
+        };
+        block.dump(&mut buf).unwrap();
+
+        // std::io::Empty is used because a type with Read is needed
+        ArchiveFileBlock::EndOfFile::<Empty> { id, hash }
+            .dump(&mut buf)
+            .unwrap();
+
+        println!("{:?}", buf);
+    }
+
+    #[test]
+    fn blocks_to_file() {
+        // Create several blocks
+        let mut buf = Vec::new();
+        let id = ArchiveFileID(0);
+        let hash = Sha256Hash::default();
+
+        let fake_content = vec![1, 2, 3, 4];
+        let mut block = ArchiveFileBlock::FileContent {
+            id,
+            length: fake_content.len() as u64,
+            data: Some(fake_content.as_slice()),
+        };
+        block.dump(&mut buf).unwrap();
+        let fake_content2 = vec![5, 6, 7, 8];
+        let mut block = ArchiveFileBlock::FileContent {
+            id,
+            length: fake_content2.len() as u64,
+            data: Some(fake_content2.as_slice()),
+        };
+        block.dump(&mut buf).unwrap();
+
+        // std::io::Empty is used because a type with Read is needed
+        ArchiveFileBlock::EndOfFile::<Empty> { id, hash }
+            .dump(&mut buf)
+            .unwrap();
+
+        let mut data_source = std::io::Cursor::new(buf);
+        let mut reader = BlocksToFileReader::new(&mut data_source, id, vec![0]);
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).unwrap();
+        assert_eq!(output.len(), fake_content.len() + fake_content2.len());
+        let mut expected_output = Vec::new();
+        expected_output.extend(fake_content);
+        expected_output.extend(fake_content2);
+        assert_eq!(output, expected_output);
+        assert_eq!(reader.state, BlocksToFileReaderState::Finish);
+    }
+
+    #[test]
+    fn skip_unknown_optional_block() {
+        let mut buf = Vec::new();
+        let id = ArchiveFileID(0);
+
+        // Optional block of an unknown type: [type][id][length][payload]
+        buf.push(0x90);
+        buf.write_u64::<LittleEndian>(id.0).unwrap();
+        buf.write_u64::<LittleEndian>(3).unwrap();
+        buf.extend(&[1, 2, 3]);
+
+        let fake_content = vec![4, 5, 6, 7];
+        ArchiveFileBlock::FileContent {
+            id,
+            length: fake_content.len() as u64,
+            data: Some(fake_content.as_slice()),
+        }
+        .dump(&mut buf)
+        .unwrap();
+
+        let mut src = buf.as_slice();
+        match ArchiveFileBlock::from(&mut src).unwrap() {
+            ArchiveFileBlock::Unknown { kind, length } => {
+                assert_eq!(kind, 0x90);
+                assert_eq!(length, 3);
+            }
+            _ => panic!("Unknown optional block not reported"),
+        }
+        match ArchiveFileBlock::from(&mut src).unwrap() {
+            ArchiveFileBlock::FileContent { length, .. } => {
+                assert_eq!(length, fake_content.len() as u64);
+            }
+            _ => panic!("Unknown optional block not skipped"),
+        }
+        assert_eq!(src, fake_content.as_slice());
+
+        // Unknown mandatory block types are still refused
+        let mut buf = vec![0x10];
+        buf.write_u64::<LittleEndian>(id.0).unwrap();
+        assert!(ArchiveFileBlock::<&[u8]>::from(&mut buf.as_slice()).is_err());
+    }
+
+    #[test]
+    fn new_mla() {
+        let file = Vec::new();
+        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let mut mla = ArchiveWriter::new(file, std::slice::from_ref(&PublicKey::from(&key)))
+            .expect("Writer init failed");
+
+        let fake_file = vec![1, 2, 3, 4];
+        mla.add_file("my_file", fake_file.len() as u64, fake_file.as_slice())
+            .unwrap();
+        let fake_file = vec![5, 6, 7, 8];
+        let fake_file2 = vec![9, 10, 11, 12];
+        let id = mla.start_file("my_file2").unwrap();
+        mla.append_file_content(id, fake_file.len() as u64, fake_file.as_slice())
+            .unwrap();
+        mla.append_file_content(id, fake_file2.len() as u64, fake_file2.as_slice())
+            .unwrap();
+        mla.end_file(id).unwrap();
+        mla.finalize().unwrap();
+
+        let mla_key = *mla.config.encryption_key();
+        let mla_nonce = *mla.config.encryption_nonce();
+        let dest = mla.into_raw();
+        let buf = Cursor::new(dest.as_slice());
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+        assert_eq!(
+            (mla_key, mla_nonce),
+            mla_read.config.get_encrypt_parameters().unwrap()
+        );
+
+        let mut file = mla_read.get_file("my_file".to_string()).unwrap().unwrap();
+        let mut rez = Vec::new();
+        file.data.read_to_end(&mut rez).unwrap();
+        assert_eq!(rez, vec![1, 2, 3, 4]);
+        // Explicit drop here, because otherwise mla_read.get_file() cannot be
+        // recall. It is not detected by the NLL analysis
+        drop(file);
+        let mut file2 = mla_read.get_file("my_file2".to_string()).unwrap().unwrap();
+        let mut rez2 = Vec::new();
+        file2.data.read_to_end(&mut rez2).unwrap();
+        assert_eq!(rez2, vec![5, 6, 7, 8, 9, 10, 11, 12]);
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn build_archive(
+        layers: Option<Layers>,
+        interleaved: bool,
+    ) -> (
+        ArchiveWriter<'static, Vec<u8>>,
+        StaticSecret,
+        Vec<(String, Vec<u8>)>,
+    ) {
+        // Build an archive with 3 files
+        let file = Vec::new();
+        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(layers.unwrap_or_default())
+            .add_public_keys(&[PublicKey::from(&key)]);
+        let mut mla = ArchiveWriter::from_config(file, config).expect("Writer init failed");
+
+        let fname1 = "my_file1".to_string();
+        let fname2 = "my_file2".to_string();
+        let fname3 = "my_file3".to_string();
+        let fake_file_part1 = vec![1, 2, 3];
+        let fake_file_part2 = vec![4, 5, 6, 7, 8];
+        let mut fake_file1 = Vec::new();
+        fake_file1.extend_from_slice(fake_file_part1.as_slice());
+        fake_file1.extend_from_slice(fake_file_part2.as_slice());
+        let fake_file2 = vec![9, 10, 11, 12];
+        let fake_file3 = vec![13, 14, 15];
+
+        if interleaved {
+            // Interleaved writes, expected result is:
+            // [File1 start]
+            // [File1 content 1 2 3]
+            // [File2 start]
+            // [File2 content 9 10 11 12]
+            // [File3 start]
+            // [File3 content 13 14 15]
+            // [File3 end]
+            // [File1 content 4 5 6 7 8]
+            // [File1 end]
+            // [File2 end]
+            let id_file1 = mla.start_file(&fname1).unwrap();
+            mla.append_file_content(
+                id_file1,
+                fake_file_part1.len() as u64,
+                fake_file_part1.as_slice(),
+            )
+            .unwrap();
+            let id_file2 = mla.start_file(&fname2).unwrap();
+            mla.append_file_content(id_file2, fake_file2.len() as u64, fake_file2.as_slice())
+                .unwrap();
+            mla.add_file(&fname3, fake_file3.len() as u64, fake_file3.as_slice())
+                .unwrap();
+            mla.append_file_content(
+                id_file1,
+                fake_file_part2.len() as u64,
+                fake_file_part2.as_slice(),
+            )
+            .unwrap();
+            mla.end_file(id_file1).unwrap();
+            mla.end_file(id_file2).unwrap();
+        } else {
+            mla.add_file(&fname1, fake_file1.len() as u64, fake_file1.as_slice())
+                .unwrap();
+            mla.add_file(&fname2, fake_file2.len() as u64, fake_file2.as_slice())
+                .unwrap();
+            mla.add_file(&fname3, fake_file3.len() as u64, fake_file3.as_slice())
+                .unwrap();
+        }
+
+        mla.finalize().unwrap();
+
+        (
+            mla,
+            key,
+            vec![
+                (fname1, fake_file1),
+                (fname2, fake_file2),
+                (fname3, fake_file3),
+            ],
+        )
+    }
+
+    #[test]
+    fn interleaved_files() {
+        // Build an archive with 3 interleaved files
+        let (mla, key, files) = build_archive(None, true);
+
+        let dest = mla.into_raw();
+        let buf = Cursor::new(dest.as_slice());
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+
+        for (fname, content) in files {
+            let mut file = mla_read.get_file(fname).unwrap().unwrap();
+            let mut rez = Vec::new();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(rez, content);
+        }
+    }
+
+    #[test]
+    fn mla_multi_layering() {
+        // Test the building-then-reading of a file using different layering
+        // approach
+
+        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        let mut rng = ChaChaRng::seed_from_u64(0);
+
+        for layering in &[
+            Layers::DEBUG,
+            Layers::ENCRYPT,
+            Layers::COMPRESS,
+            Layers::default(),
+        ] {
+            println!("Layering: {:?}", layering);
+
+            // Build initial file in a stream
+            let file = Vec::new();
+            let key = StaticSecret::new(&mut rng);
+            let mut config = ArchiveWriterConfig::new();
+            config
+                .set_layers(*layering)
+                .add_public_keys(std::slice::from_ref(&PublicKey::from(&key)));
+            let mut mla = ArchiveWriter::from_config(file, config).expect("Writer init failed");
+
+            // Write a file in one part
+            let fake_file = vec![1, 2, 3, 4];
+            mla.add_file("my_file", fake_file.len() as u64, fake_file.as_slice())
+                .unwrap();
+            // Write a file in multiple part
+            let fake_file = vec![5, 6, 7, 8];
+            let fake_file2 = vec![9, 10, 11, 12];
+            let id = mla.start_file("my_file2").unwrap();
+            mla.append_file_content(id, fake_file.len() as u64, fake_file.as_slice())
+                .unwrap();
+            mla.append_file_content(id, fake_file2.len() as u64, fake_file2.as_slice())
+                .unwrap();
+            mla.end_file(id).unwrap();
+            mla.finalize().unwrap();
+
+            // Read the obtained stream
+            let dest = mla.into_raw();
+            let buf = Cursor::new(dest.as_slice());
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+
+            let mut file = mla_read.get_file("my_file".to_string()).unwrap().unwrap();
+            let mut rez = Vec::new();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(rez, vec![1, 2, 3, 4]);
+            // Explicit drop here, because otherwise mla_read.get_file() cannot be
+            // recall. It is not detected by the NLL analysis
+            drop(file);
+            let mut file2 = mla_read.get_file("my_file2".to_string()).unwrap().unwrap();
+
+            // Read the file in 2 blocks: 6, then 2 bytes (it is made of two 4-bytes block)
+            let mut rez2 = [0u8; 6];
+            file2.data.read_exact(&mut rez2).unwrap();
+            assert_eq!(rez2, [5, 6, 7, 8, 9, 10]);
+            let mut rez3 = Vec::new();
+            let mut final_rez = Vec::new();
+            file2.data.read_to_end(&mut rez3).unwrap();
+            final_rez.extend(&rez2);
+            final_rez.extend(rez3);
+            assert_eq!(final_rez, vec![5, 6, 7, 8, 9, 10, 11, 12]);
+        }
+    }
+
+    #[test]
+    fn multiple_recipients() {
+        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let unrelated_key = StaticSecret::new(&mut rng);
+        let fake_file = vec![1, 2, 3, 4];
+
+        for count in &[1, 2, 3, 10] {
+            let keys: Vec<StaticSecret> =
+                (0..*count).map(|_| StaticSecret::new(&mut rng)).collect();
+            let mut config = ArchiveWriterConfig::default();
+            for key in &keys {
+                config.add_public_key(&PublicKey::from(key));
+            }
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            mla.add_file("my_file", fake_file.len() as u64, fake_file.as_slice())
+                .unwrap();
+            mla.finalize().unwrap();
+            let dest = mla.into_raw();
+
+            // Any recipient can decrypt the archive
+            for key in &keys {
+                let mut config = ArchiveReaderConfig::new();
+                config.add_private_keys(std::slice::from_ref(key));
+                let mut mla_read =
+                    ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+                let mut buf = Vec::new();
+                mla_read
+                    .get_file("my_file")
+                    .unwrap()
+                    .unwrap()
+                    .data
+                    .read_to_end(&mut buf)
+                    .unwrap();
+                assert_eq!(buf, fake_file);
+            }
+
+            // ... but not an unrelated key
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&unrelated_key));
+            assert!(matches!(
+                ArchiveReader::from_config(Cursor::new(dest.as_slice()), config),
+                Err(Error::ConfigError(ConfigError::PrivateKeyNotFound))
+            ));
+        }
+    }
+
+    #[test]
+    fn threshold() {
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let keys: Vec<StaticSecret> = (0..3).map(|_| StaticSecret::new(&mut rng)).collect();
+        let publics: Vec<PublicKey> = keys.iter().map(PublicKey::from).collect();
+        let fake_file = vec![1, 2, 3, 4];
+
+        let mut config = ArchiveWriterConfig::default();
+        config.set_threshold(ThresholdConfig::new(2, &publics));
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("my_file", fake_file.len() as u64, fake_file.as_slice())
+            .unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        // Any 2 of the 3 recipients can decrypt the archive
+        for (a, b) in [(0, 1), (0, 2), (2, 1)] {
+            let mut config = ArchiveReaderConfig::new();
+            config
+                .add_threshold_keys(&[keys[a].clone(), keys[b].clone()])
+                .unwrap();
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+            let mut buf = Vec::new();
+            mla_read
+                .get_file("my_file")
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut buf)
+                .unwrap();
+            assert_eq!(buf, fake_file);
+        }
+
+        // ... but not a single one of them, alone as a share or as a recipient
+        let mut config = ArchiveReaderConfig::new();
+        config
+            .add_threshold_keys(std::slice::from_ref(&keys[1]))
+            .unwrap();
+        assert!(matches!(
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), config),
+            Err(Error::InsufficientShares)
+        ));
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&keys[1]));
+        assert!(matches!(
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), config),
+            Err(Error::ConfigError(ConfigError::PrivateKeyNotFound))
+        ));
+
+        // A share counts once
+        let mut config = ArchiveReaderConfig::new();
+        assert!(matches!(
+            config.add_threshold_keys(&[keys[0].clone(), keys[0].clone()]),
+            Err(Error::BadAPIArgument(_))
+        ));
+
+        // The threshold must be reachable
+        let mut config = ArchiveWriterConfig::default();
+        config.set_threshold(ThresholdConfig::new(4, &publics));
+        assert!(matches!(
+            ArchiveWriter::from_config(Vec::new(), config),
+            Err(Error::ConfigError(ConfigError::InvalidThreshold))
+        ));
+    }
+
+    #[test]
+    fn password() {
+        // Weak parameters, to keep tests fast
+        let params = Argon2Params {
+            m_cost: 64,
+            t_cost: 1,
+            p_cost: 1,
+        };
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let fake_file = vec![1, 2, 3, 4];
+
+        for with_key in &[false, true] {
+            let mut config = ArchiveWriterConfig::default();
+            config.with_password(b"my password", params);
+            if *with_key {
+                config.add_public_key(&PublicKey::from(&key));
+            }
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            mla.add_file("my_file", fake_file.len() as u64, fake_file.as_slice())
+                .unwrap();
+            mla.finalize().unwrap();
+            let dest = mla.into_raw();
+
+            // The password is enough to decrypt the archive
+            let mut config = ArchiveReaderConfig::new();
+            config.with_password(b"my password");
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+            let mut buf = Vec::new();
+            mla_read
+                .get_file("my_file")
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut buf)
+                .unwrap();
+            assert_eq!(buf, fake_file);
+
+            // So is the private key, if any
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            assert_eq!(
+                ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).is_ok(),
+                *with_key
+            );
+
+            // But not a wrong password
+            let mut config = ArchiveReaderConfig::new();
+            config.with_password(b"wrong password");
+            assert!(matches!(
+                ArchiveReader::from_config(Cursor::new(dest.as_slice()), config),
+                Err(Error::ConfigError(ConfigError::PrivateKeyNotFound))
+            ));
+        }
+
+        // Oversized parameters in a crafted header are refused, before
+        // deriving the key
+        let mut config = ArchiveWriterConfig::default();
+        config.with_password(b"my password", params);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.finalize().unwrap();
+        let mut dest = mla.into_raw();
+        let serialized = bincode::serialize(&params).unwrap();
+        let position = dest
+            .windows(serialized.len())
+            .position(|window| window == serialized.as_slice())
+            .unwrap();
+        dest[position..position + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut config = ArchiveReaderConfig::new();
+        config.with_password(b"my password");
+        assert!(matches!(
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), config),
+            Err(Error::ConfigError(ConfigError::InvalidArgon2Params))
+        ));
+
+        // Invalid parameters are rejected on creation
+        let mut config = ArchiveWriterConfig::default();
+        config.with_password(
+            b"my password",
+            Argon2Params {
+                m_cost: 64,
+                t_cost: 0,
+                p_cost: 1,
+            },
+        );
+        assert!(matches!(
+            ArchiveWriter::from_config(Vec::new(), config),
+            Err(Error::ConfigError(ConfigError::InvalidArgon2Params))
+        ));
+    }
+
+    #[test]
+    fn signed_archive() {
+        let signing_key = SigningKey::from_bytes(&[1; 32]);
+        let other_key = SigningKey::from_bytes(&[2; 32]);
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let fake_file = vec![1, 2, 3, 4];
+
+        let mut config = ArchiveWriterConfig::default();
+        config
+            .add_public_key(&PublicKey::from(&key))
+            .sign_with(&signing_key);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("my_file", fake_file.len() as u64, fake_file.as_slice())
+            .unwrap();
+        mla.finalize().unwrap();
+        let mut dest = mla.into_raw();
+
+        let open = |data: &[u8], verifying_key: Option<&VerifyingKey>| {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            if let Some(verifying_key) = verifying_key {
+                config.verify_with(verifying_key);
+            }
+            ArchiveReader::from_config(Cursor::new(data.to_vec()), config)
+        };
+
+        // Correct signature, or no verification at all
+        for verifying_key in &[Some(&signing_key.verifying_key()), None] {
+            let mut mla_read = open(&dest, *verifying_key).unwrap();
+            let mut buf = Vec::new();
+            mla_read
+                .get_file("my_file")
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut buf)
+                .unwrap();
+            assert_eq!(buf, fake_file);
+        }
+
+        // Wrong key
+        assert!(matches!(
+            open(&dest, Some(&other_key.verifying_key())),
+            Err(Error::SignatureVerificationFailed)
+        ));
+
+        // Truncated archive
+        assert!(matches!(
+            open(&dest[..dest.len() - 1], Some(&signing_key.verifying_key())),
+            Err(Error::SignatureVerificationFailed)
+        ));
+
+        // Modified header (the ephemeral public key)
+        dest[10] ^= 1;
+        assert!(matches!(
+            open(&dest, Some(&signing_key.verifying_key())),
+            Err(Error::SignatureVerificationFailed)
+        ));
+        dest[10] ^= 1;
+
+        // Fail-safe reading ignores the signature
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_fsread = ArchiveFailSafeReader::from_config(dest.as_slice(), config).unwrap();
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new())
+            .expect("Writer init failed");
+        assert!(matches!(
+            mla_fsread.convert_to_archive(&mut mla_w).unwrap(),
+            FailSafeReadError::EndOfOriginalArchiveData
+        ));
+
+        // Unsigned archive
+        let (mla, key, _files) = build_archive(None, false);
+        let mut config = ArchiveReaderConfig::new();
+        config
+            .add_private_keys(std::slice::from_ref(&key))
+            .verify_with(&signing_key.verifying_key());
+        assert!(matches!(
+            ArchiveReader::from_config(Cursor::new(mla.into_raw()), config),
+            Err(Error::MissingSignature)
+        ));
+
+        // Signing key is required
+        let mut config = ArchiveWriterConfig::new();
+        config.enable_layer(Layers::SIGN);
+        assert!(matches!(
+            ArchiveWriter::from_config(Vec::new(), config),
+            Err(Error::ConfigError(ConfigError::SigningKeyIsMissing))
+        ));
+    }
+
+    #[test]
+    fn file_checksums() {
+        let fake_file = vec![1, 2, 3, 4, 5, 6, 7, 8];
+
+        // Unencrypted and uncompressed archive, to be able to tamper with it
+        let mut config = ArchiveWriterConfig::new();
+        config.enable_file_checksums(ChecksumAlgorithm::Blake3);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let id = mla.start_file("my_file").unwrap();
+        mla.append_file_content(id, 4, &fake_file[..4]).unwrap();
+        mla.append_file_content(id, 4, &fake_file[4..]).unwrap();
+        mla.end_file(id).unwrap();
+        mla.finalize().unwrap();
+        let mut dest = mla.into_raw();
+
+        let read = |data: &[u8], verify: bool| {
+            let mut config = ArchiveReaderConfig::new();
+            config.verify_checksums(verify);
+            let mut mla_read = ArchiveReader::from_config(Cursor::new(data), config).unwrap();
+            let mut file = mla_read.get_file("my_file").unwrap().unwrap();
+            let mut rez = Vec::new();
+            file.data.read_to_end(&mut rez).map(|_| rez)
+        };
+        assert_eq!(read(&dest, true).unwrap(), fake_file);
+
+        // Tamper with the content
+        let pos = dest
+            .windows(fake_file.len() / 2)
+            .position(|w| w == &fake_file[4..])
+            .unwrap();
+        dest[pos] ^= 1;
+        let err = read(&dest, true).unwrap_err();
+        assert_eq!(err.to_string(), Error::ChecksumMismatch.to_string());
+
+        // Without verification, the content is still readable
+        let mut expected = fake_file.clone();
+        expected[4] ^= 1;
+        assert_eq!(read(&dest, false).unwrap(), expected);
+
+        // So does the integrity verification
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), ArchiveReaderConfig::new())
+                .unwrap();
+        let report = mla_read.verify_integrity().unwrap();
+        assert_eq!(report.files_ok, 0);
+        match report.files_with_errors.as_slice() {
+            [(fname, Error::ChecksumMismatch)] => assert_eq!(fname, "my_file"),
+            _ => panic!("Checksum mismatch not detected"),
+        }
+
+        // Linear extraction verifies checksums too
+        let mut config = ArchiveReaderConfig::new();
+        config.verify_checksums(true);
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+        match helpers::linear_extract_with_filter(&mut mla_read, |_| true, |_| Some(Vec::new())) {
+            Err(Error::ChecksumMismatch) => {}
+            _ => panic!("Checksum mismatch not detected"),
+        }
+    }
+
+    #[test]
+    fn dedup() {
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let mut content = vec![0u8; 1024 * 1024];
+        rng.fill_bytes(&mut content);
+        let other = vec![1u8; 1000];
+        let fnames: Vec<String> = (0..10).map(|i| format!("copy_{}", i)).collect();
+
+        let build = |dedup: bool, max_file_size: u64| {
+            // Without compression, which could also benefit from duplicates
+            let mut config = ArchiveWriterConfig::new();
+            config
+                .enable_file_checksums(ChecksumAlgorithm::Blake3)
+                .with_dedup(dedup)
+                .dedup_max_file_size(max_file_size);
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            for (i, fname) in fnames.iter().enumerate() {
+                if i == 5 {
+                    mla.add_file("other", other.len() as u64, other.as_slice())
+                        .unwrap();
+                }
+                // Content provided in several parts
+                let id = mla.start_file(fname).unwrap();
+                mla.append_file_content(id, 1000, &content[..1000]).unwrap();
+                mla.append_file_content(id, content.len() as u64 - 1000, &content[1000..])
+                    .unwrap();
+                mla.end_file(id).unwrap();
+            }
+            mla.finalize().unwrap();
+            mla.into_raw()
+        };
+        let dest = build(true, u64::MAX);
+        // The content is stored only once
+        assert!(dest.len() < content.len() * 11 / 10);
+        assert!(build(false, u64::MAX).len() > content.len() * 10);
+        // Files bigger than the limit are written without deduplication
+        let not_deduplicated = build(true, content.len() as u64 - 1);
+        assert!(not_deduplicated.len() > content.len() * 10);
+        let mut mla_read = ArchiveReader::from_config(
+            Cursor::new(not_deduplicated.as_slice()),
+            ArchiveReaderConfig::new(),
+        )
+        .unwrap();
+        for fname in &fnames {
+            let mut rez = Vec::new();
+            mla_read
+                .get_file(fname)
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut rez)
+                .unwrap();
+            assert_eq!(rez, content);
+        }
+        assert_eq!(mla_read.get_all_files().unwrap()["other"], other);
+
+        let mut expected: HashMap<String, Vec<u8>> = fnames
+            .iter()
+            .map(|fname| (fname.clone(), content.clone()))
+            .collect();
+        expected.insert("other".to_string(), other.clone());
+
+        let mut config = ArchiveReaderConfig::new();
+        config.verify_checksums(true);
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+        assert!(mla_read.features().contains(FeatureFlags::HAS_DEDUP));
+        for (fname, data) in expected.iter() {
+            let mut file = mla_read.get_file(fname).unwrap().unwrap();
+            assert_eq!(file.size, data.len() as u64);
+            let mut rez = Vec::new();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(&rez, data);
+        }
+
+        // Linear extraction
+        let mut export: HashMap<&String, Vec<u8>> =
+            expected.keys().map(|fname| (fname, Vec::new())).collect();
+        helpers::linear_extract(&mut mla_read, &mut export).unwrap();
+        for (fname, data) in export.iter() {
+            assert_eq!(data, &expected[*fname]);
+        }
+
+        let report = mla_read.verify_integrity().unwrap();
+        assert_eq!(report.files_ok, expected.len());
+        assert!(report.files_with_errors.is_empty());
+        let stats = mla_read.statistics().unwrap();
+        assert_eq!(
+            stats.total_uncompressed_bytes,
+            Some(content.len() as u64 * 10 + other.len() as u64)
+        );
+
+        // Fail-safe conversion keeps the deduplication
+        let mut mla_fsread =
+            ArchiveFailSafeReader::from_config(dest.as_slice(), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new())
+            .expect("Writer init failed");
+        assert!(matches!(
+            mla_fsread.convert_to_archive(&mut mla_w).unwrap(),
+            FailSafeReadError::EndOfOriginalArchiveData
+        ));
+        let repaired = mla_w.into_raw();
+        assert!(repaired.len() < content.len() * 11 / 10);
+        let mut mla_read = ArchiveReader::from_config(
+            Cursor::new(repaired.as_slice()),
+            ArchiveReaderConfig::new(),
+        )
+        .unwrap();
+        for (fname, data) in expected.iter() {
+            let mut rez = Vec::new();
+            let mut file = mla_read.get_file(fname).unwrap().unwrap();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(&rez, data);
+        }
+    }
+
+    #[test]
+    fn verify_integrity() {
+        for layers in &[Layers::default(), Layers::EMPTY] {
+            let (mla, key, files) = build_archive(Some(*layers), true);
+            let mut dest = mla.into_raw();
+            let open = |data: &[u8]| {
+                let mut config = ArchiveReaderConfig::new();
+                config.add_private_keys(std::slice::from_ref(&key));
+                ArchiveReader::from_config(Cursor::new(data.to_vec()), config).unwrap()
+            };
+
+            let report = open(&dest).verify_integrity().unwrap();
+            assert_eq!(report.files_ok, files.len());
+            assert!(report.files_with_errors.is_empty());
+            assert!(report.archive_complete);
+
+            if layers.is_empty() {
+                // Tamper with the content of "my_file2"
+                let pos = dest.windows(4).position(|w| w == [9, 10, 11, 12]).unwrap();
+                dest[pos] ^= 1;
+                let report = open(&dest).verify_integrity().unwrap();
+                assert_eq!(report.files_ok, files.len() - 1);
+                assert!(report.archive_complete);
+                match report.files_with_errors.as_slice() {
+                    [(fname, Error::HashMismatch)] => assert_eq!(fname, "my_file2"),
+                    _ => panic!("Tampered file not detected"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn verify_integrity_recorded_size() {
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::EMPTY);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let content = vec![3u8; 1234];
+        mla.add_file("my_file", content.len() as u64, content.as_slice())
+            .unwrap();
+        mla.finalize().unwrap();
+        let mut dest = mla.into_raw();
+
+        // Record a smaller size in the footer, the last place it appears
+        let size = 1234u64.to_le_bytes();
+        let pos = dest.windows(8).rposition(|w| w == size).unwrap();
+        dest[pos..pos + 8].copy_from_slice(&1000u64.to_le_bytes());
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest), ArchiveReaderConfig::new()).unwrap();
+        assert_eq!(mla_read.get_file("my_file").unwrap().unwrap().size, 1000);
+        let report = mla_read.verify_integrity().unwrap();
+        assert_eq!(report.files_ok, 0);
+        match report.files_with_errors.as_slice() {
+            [(fname, Error::WrongReaderState(_))] => assert_eq!(fname, "my_file"),
+            _ => panic!("Bigger content not detected"),
+        }
+    }
+
+    #[test]
+    fn file_metadata() {
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let metadata = metadata::FileMetadata {
+            mtime: Some(std::time::SystemTime::UNIX_EPOCH),
+            mode: Some(0o755),
+            uid: Some(1000),
+            gid: Some(100),
+        };
+
+        // Interleave a file with metadata and a file without
+        let mut mla =
+            ArchiveWriter::new(Vec::new(), std::slice::from_ref(&PublicKey::from(&key))).unwrap();
+        let id1 = mla.start_file_with_metadata("my_file1", &metadata).unwrap();
+        let id2 = mla.start_file("my_file2").unwrap();
+        mla.append_file_content(id2, 2, [3, 4].as_ref()).unwrap();
+        mla.append_file_content(id1, 2, [1, 2].as_ref()).unwrap();
+        mla.end_file(id2).unwrap();
+        mla.end_file(id1).unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let check = |data: &[u8]| {
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(data), config.clone()).unwrap();
+            for (fname, expected, content) in &[
+                ("my_file1", Some(&metadata), [1, 2]),
+                ("my_file2", None, [3, 4]),
+            ] {
+                let mut file = mla_read.get_file(*fname).unwrap().unwrap();
+                assert_eq!(file.metadata.as_ref(), *expected);
+                let mut rez = Vec::new();
+                file.data.read_to_end(&mut rez).unwrap();
+                assert_eq!(&rez, content);
+            }
+
+            let entries: Vec<_> = mla_read
+                .entries()
+                .unwrap()
+                .map(|entry| entry.unwrap().metadata)
+                .collect();
+            assert_eq!(entries, vec![Some(metadata.clone()), None]);
+        };
+        check(&dest);
+
+        // Metadata are kept on fail-safe conversion
+        let mut mla_fsread =
+            ArchiveFailSafeReader::from_config(dest.as_slice(), config.clone()).unwrap();
+        let mut mla_w =
+            ArchiveWriter::new(Vec::new(), std::slice::from_ref(&PublicKey::from(&key))).unwrap();
+        mla_fsread.convert_to_archive(&mut mla_w).unwrap();
+        check(&mla_w.into_raw());
+    }
+
+    #[test]
+    fn user_metadata() {
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+
+        // Interleaved files, entries set before, between and after contents
+        let mut mla =
+            ArchiveWriter::new(Vec::new(), std::slice::from_ref(&PublicKey::from(&key))).unwrap();
+        let id1 = mla.start_file("my_file1").unwrap();
+        mla.set_file_metadata(id1, "source_url", b"https://example.com/file1")
+            .unwrap();
+        let id2 = mla.start_file("my_file2").unwrap();
+        mla.append_file_content(id1, 2, [1, 2].as_ref()).unwrap();
+        mla.append_file_content(id2, 2, [3, 4].as_ref()).unwrap();
+        mla.set_file_metadata(id1, "content_type", b"text/plain")
+            .unwrap();
+        mla.append_file_content(id1, 2, [5, 6].as_ref()).unwrap();
+        mla.set_file_metadata(id1, "sha1_before_archive", &[0xAB; 20])
+            .unwrap();
+        mla.set_file_metadata(id1, "content_type", b"application/octet-stream")
+            .unwrap();
+        mla.end_file(id2).unwrap();
+        mla.end_file(id1).unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        let expected: HashMap<String, Vec<u8>> = [
+            ("source_url", &b"https://example.com/file1"[..]),
+            ("content_type", &b"application/octet-stream"[..]),
+            ("sha1_before_archive", &[0xAB; 20][..]),
+        ]
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_vec()))
+        .collect();
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let check = |data: &[u8]| {
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(data), config.clone()).unwrap();
+            assert_eq!(mla_read.get_file_metadata("my_file1").unwrap(), expected);
+            assert!(mla_read.get_file_metadata("my_file2").unwrap().is_empty());
+            assert!(matches!(
+                mla_read.get_file_metadata("unknown"),
+                Err(Error::BadAPIArgument(_))
+            ));
+
+            // Contents are not altered
+            for (fname, content) in [("my_file1", &[1, 2, 5, 6][..]), ("my_file2", &[3, 4][..])] {
+                let mut rez = Vec::new();
+                mla_read
+                    .get_file(fname)
+                    .unwrap()
+                    .unwrap()
+                    .data
+                    .read_to_end(&mut rez)
+                    .unwrap();
+                assert_eq!(rez, content);
+            }
+        };
+        check(&dest);
+
+        // User metadata are kept on fail-safe conversion
+        let mut mla_fsread =
+            ArchiveFailSafeReader::from_config(dest.as_slice(), config.clone()).unwrap();
+        let mut mla_w =
+            ArchiveWriter::new(Vec::new(), std::slice::from_ref(&PublicKey::from(&key))).unwrap();
+        mla_fsread.convert_to_archive(&mut mla_w).unwrap();
+        check(&mla_w.into_raw());
+
+        // Entries are bounded in size
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        let id = mla.start_file("file").unwrap();
+        let too_long = vec![0; USER_METADATA_MAX_SIZE as usize];
+        assert!(matches!(
+            mla.set_file_metadata(id, "key", &too_long),
+            Err(Error::SerializationError)
+        ));
+    }
+
+    #[test]
+    fn file_size_hint() {
+        let new_writer = || {
+            let mut config = ArchiveWriterConfig::new();
+            config.set_layers(Layers::EMPTY);
+            ArchiveWriter::from_config(Vec::new(), config).unwrap()
+        };
+
+        // A hint after metadata, a wrong hint, and a file without hint
+        let mut mla = new_writer();
+        let id1 = mla
+            .start_file_with_metadata("exact", &FileMetadata::default())
+            .unwrap();
+        mla.set_file_size_hint(id1, 2).unwrap();
+        mla.append_file_content(id1, 2, [1, 2].as_ref()).unwrap();
+        mla.end_file(id1).unwrap();
+        let id2 = mla.start_file("wrong").unwrap();
+        mla.set_file_size_hint(id2, 100).unwrap();
+        mla.append_file_content(id2, 2, [3, 4].as_ref()).unwrap();
+        mla.append_file_content(id2, 1, [5].as_ref()).unwrap();
+        mla.end_file(id2).unwrap();
+        mla.add_file("none", 1, [6].as_ref()).unwrap();
+        assert!(mla.set_file_size_hint(id1, 2).is_err());
+        // Only the wrong hint is reported
+        assert_eq!(mla.finalize().unwrap().size_hint_mismatches, 1);
+        let dest = mla.into_raw();
+
+        let expected: &[(&str, Option<u64>, &[u8])] = &[
+            ("exact", Some(2), &[1, 2]),
+            ("wrong", Some(100), &[3, 4, 5]),
+            ("none", None, &[6]),
+        ];
+        let check = |data: &[u8]| {
+            // Linear readers, without footer, rely on the hints
+            let entries = ArchiveEntries::from_reader(data).unwrap();
+            let mut count = 0;
+            for (entry, (fname, hint, content)) in entries.zip(expected) {
+                let mut entry = entry.unwrap();
+                assert_eq!(&entry.filename, fname);
+                assert_eq!(entry.size, *hint);
+                let mut buf = Vec::new();
+                entry.read_to_end(&mut buf).unwrap();
+                assert_eq!(&buf, content);
+                count += 1;
+            }
+            assert_eq!(count, expected.len());
+
+            // With the footer, sizes are exact and the content is unaltered
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(data), ArchiveReaderConfig::new()).unwrap();
+            for (fname, _hint, content) in expected {
+                assert_eq!(
+                    mla_read.get_file_size(fname).unwrap(),
+                    Some(content.len() as u64)
+                );
+                let mut file = mla_read.get_file(fname).unwrap().unwrap();
+                let mut buf = Vec::new();
+                file.data.read_to_end(&mut buf).unwrap();
+                assert_eq!(&buf, content);
+            }
+            let report = mla_read.verify_integrity().unwrap();
+            assert_eq!(report.files_ok, expected.len());
+        };
+        check(&dest);
+
+        // Hints are kept on fail-safe conversion
+        let mut mla_fsread =
+            ArchiveFailSafeReader::from_config(dest.as_slice(), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut mla_w = new_writer();
+        mla_fsread.convert_to_archive(&mut mla_w).unwrap();
+        check(&mla_w.into_raw());
+    }
+
+    #[test]
+    fn file_id_exhausted() {
+        assert_eq!(format!("{}", ArchiveFileID(42)), "FileID(42)");
+        assert_eq!(format!("{:?}", ArchiveFileID(42)), "42");
+        assert_eq!(u64::from(ArchiveFileID::MAX), u64::MAX);
+
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::EMPTY);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        // Ids beyond the u32 range are kept as is
+        mla.next_id = ArchiveFileID(u64::from(u32::MAX) + 1);
+        let id = mla.start_file("big").unwrap();
+        assert_eq!(id, ArchiveFileID(1 << 32));
+        mla.append_file_content(id, 2, [1, 2].as_ref()).unwrap();
+        mla.end_file(id).unwrap();
+
+        // The last identifiers can't be allocated without overflowing
+        mla.next_id = ArchiveFileID(u64::MAX - 1);
+        let id = mla.start_file("last").unwrap();
+        assert_eq!(id, ArchiveFileID(u64::MAX - 1));
+        mla.end_file(id).unwrap();
+        match mla.start_file("overflow") {
+            Err(Error::FileIDExhausted) => {}
+            _ => panic!("FileIDExhausted expected"),
+        }
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        // Round-trip, with and without the footer
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut file = mla_read.get_file("big").unwrap().unwrap();
+        let mut buf = Vec::new();
+        file.data.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, vec![1, 2]);
+        assert!(mla_read.get_file("overflow").unwrap().is_none());
+        let names: Vec<String> = ArchiveEntries::from_reader(dest.as_slice())
+            .unwrap()
+            .map(|entry| entry.unwrap().filename)
+            .collect();
+        assert_eq!(names, vec!["big", "last"]);
+    }
+
+    #[test]
+    fn duplicate_file_policy() {
+        let build = |policy: Option<DuplicatePolicy>| {
+            let mut config = ArchiveWriterConfig::new();
+            if let Some(policy) = policy {
+                config.duplicate_file_policy(policy);
+            }
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            mla.add_file("file", 1, &[1][..]).unwrap();
+            mla.add_directory("dir", None).unwrap();
+            let renamed = ["file", "file", "dir"]
+                .iter()
+                .enumerate()
+                .map(|(i, fname)| {
+                    let id = mla.start_file(fname)?;
+                    let renamed = mla.filename(id).unwrap().to_string();
+                    mla.append_file_content(id, 1, &[i as u8 + 2][..])?;
+                    mla.end_file(id)?;
+                    Ok(renamed)
+                })
+                .collect::<Vec<Result<String, Error>>>();
+            // Directories are never renamed
+            assert!(matches!(
+                mla.add_directory("file", None),
+                Err(Error::DuplicateFilename)
+            ));
+            mla.finalize().unwrap();
+            (renamed, mla.into_raw())
+        };
+
+        // Default and Error policies
+        for policy in [None, Some(DuplicatePolicy::Error)] {
+            let (renamed, dest) = build(policy);
+            assert!(renamed
+                .iter()
+                .all(|fname| matches!(fname, Err(Error::DuplicateFilename))));
+            let mla_read =
+                ArchiveReader::from_config(Cursor::new(dest), ArchiveReaderConfig::new()).unwrap();
+            assert_eq!(mla_read.list_files().unwrap().count(), 1);
+        }
+
+        // Rename policy
+        let (renamed, dest) = build(Some(DuplicatePolicy::Rename));
+        let renamed: Vec<String> = renamed.into_iter().map(Result::unwrap).collect();
+        assert_eq!(renamed, vec!["file_2", "file_3", "dir_2"]);
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest), ArchiveReaderConfig::new()).unwrap();
+        for (fname, expected) in [("file", 1), ("file_2", 2), ("file_3", 3), ("dir_2", 4)] {
+            let mut content = Vec::new();
+            let mut file = mla_read.get_file(fname).unwrap().unwrap();
+            file.data.read_to_end(&mut content).unwrap();
+            assert_eq!(content, vec![expected]);
+        }
+    }
+
+    #[test]
+    fn directories() {
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let metadata = metadata::FileMetadata {
+            mode: Some(0o700),
+            ..Default::default()
+        };
+
+        let mut mla =
+            ArchiveWriter::new(Vec::new(), std::slice::from_ref(&PublicKey::from(&key))).unwrap();
+        mla.add_directory("empty/dir", Some(metadata)).unwrap();
+        // A directory entry in the middle of a file content
+        let id = mla.start_file("a/my_file").unwrap();
+        mla.append_file_content(id, 2, [1, 2].as_ref()).unwrap();
+        mla.add_directory("a", None).unwrap();
+        mla.append_file_content(id, 2, [3, 4].as_ref()).unwrap();
+        mla.end_file(id).unwrap();
+        // Directories and files share the same namespace
+        assert!(matches!(
+            mla.add_directory("a", None),
+            Err(Error::DuplicateFilename)
+        ));
+        assert!(matches!(
+            mla.add_directory("a/my_file", None),
+            Err(Error::DuplicateFilename)
+        ));
+        assert!(matches!(
+            mla.start_file("empty/dir"),
+            Err(Error::DuplicateFilename)
+        ));
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let check = |data: &[u8]| {
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(data), config.clone()).unwrap();
+            assert_eq!(
+                mla_read.list_directories().unwrap().collect::<Vec<_>>(),
+                vec!["a", "empty/dir"]
+            );
+            assert_eq!(
+                mla_read.list_files().unwrap().collect::<Vec<_>>(),
+                vec!["a/my_file"]
+            );
+            let mut file = mla_read.get_file("a/my_file").unwrap().unwrap();
+            let mut rez = Vec::new();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(rez, vec![1, 2, 3, 4]);
+        };
+        check(&dest);
+
+        // Directories are kept on fail-safe conversion
+        let mut mla_fsread =
+            ArchiveFailSafeReader::from_config(dest.as_slice(), config.clone()).unwrap();
+        let mut mla_w =
+            ArchiveWriter::new(Vec::new(), std::slice::from_ref(&PublicKey::from(&key))).unwrap();
+        mla_fsread.convert_to_archive(&mut mla_w).unwrap();
+        check(&mla_w.into_raw());
+    }
+
+    #[test]
+    fn sparse_file() {
+        const HOLE: u64 = 1 << 30;
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+
+        let mut mla =
+            ArchiveWriter::new(Vec::new(), std::slice::from_ref(&PublicKey::from(&key))).unwrap();
+        let id = mla.start_file("sparse").unwrap();
+        let other_id = mla.start_file("other").unwrap();
+        mla.append_file_sparse(
+            id,
+            4 + HOLE,
+            &[SparseData::Data(b"head"), SparseData::Zeroes(HOLE)],
+        )
+        .unwrap();
+        // Interleave with another file, to check the hole is a continuous block
+        mla.append_file_content(other_id, 2, [1, 2].as_ref())
+            .unwrap();
+        mla.append_file_sparse(id, 4, &[SparseData::Data(b"tail")])
+            .unwrap();
+        mla.end_file(other_id).unwrap();
+        // `length` must match the whole `data`
+        assert!(matches!(
+            mla.append_file_sparse(id, 1, &[SparseData::Zeroes(2)]),
+            Err(Error::BadAPIArgument(_))
+        ));
+        mla.end_file(id).unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+        // Much smaller than a dense encoding
+        assert!((dest.len() as u64) < 1 << 20);
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+        let mut file = mla_read.get_file("sparse").unwrap().unwrap();
+        assert_eq!(file.size, 8 + HOLE);
+        let mut hash = Sha256::default();
+        let mut buf = [0u8; 4];
+        file.data.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"head");
+        hash.update(buf);
+        // Content is hashed by chunks, without loading the whole hole in
+        // memory. Comparing hashes checks the hole is made of zeroes
+        let mut chunk = vec![0u8; 1 << 20];
+        let mut zeroes = 0;
+        while zeroes < HOLE {
+            let count = file.data.read(&mut chunk).unwrap();
+            assert!(count > 0);
+            hash.update(&chunk[..count]);
+            zeroes += count as u64;
+        }
+        assert_eq!(zeroes, HOLE);
+        let mut rez = Vec::new();
+        file.data.read_to_end(&mut rez).unwrap();
+        assert_eq!(rez, b"tail");
+        hash.update(&rez);
+        assert_eq!(
+            mla_read.get_hash("sparse").unwrap().unwrap(),
+            hash.finalize().as_slice()
+        );
+
+        let mut file = mla_read.get_file("other").unwrap().unwrap();
+        let mut rez = Vec::new();
+        file.data.read_to_end(&mut rez).unwrap();
+        assert_eq!(rez, vec![1, 2]);
+    }
+
+    #[test]
+    fn symlinks() {
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let metadata = metadata::FileMetadata {
+            uid: Some(1000),
+            ..Default::default()
+        };
+
+        let mut mla =
+            ArchiveWriter::new(Vec::new(), std::slice::from_ref(&PublicKey::from(&key))).unwrap();
+        mla.add_directory("dir", None).unwrap();
+        let id = mla.start_file("dir/my_file").unwrap();
+        mla.append_file_content(id, 2, [1, 2].as_ref()).unwrap();
+        mla.add_symlink("link", "dir/my_file", Some(metadata.clone()))
+            .unwrap();
+        mla.end_file(id).unwrap();
+        // Symbolic links share the namespace of files and directories
+        for name in ["link", "dir", "dir/my_file"] {
+            assert!(matches!(
+                mla.add_symlink(name, "target", None),
+                Err(Error::DuplicateFilename)
+            ));
+        }
+        assert!(matches!(
+            mla.start_file("link"),
+            Err(Error::DuplicateFilename)
+        ));
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let check = |data: &[u8]| {
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(data), config.clone()).unwrap();
+            assert_eq!(
+                mla_read.list_symlinks().unwrap().collect::<Vec<_>>(),
+                vec![("link", "dir/my_file")]
+            );
+            assert_eq!(
+                mla_read.list_entries().unwrap().collect::<Vec<_>>(),
+                vec![
+                    ("dir/my_file", EntryKind::File),
+                    ("dir", EntryKind::Directory),
+                    ("link", EntryKind::Symlink)
+                ]
+            );
+            let mut file = mla_read.get_file("dir/my_file").unwrap().unwrap();
+            let mut rez = Vec::new();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(rez, vec![1, 2]);
+
+            // Entries are yielded in their order of appearance
+            let entries = mla_read
+                .entries()
+                .unwrap()
+                .map(|entry| {
+                    let mut entry = entry.unwrap();
+                    let mut content = Vec::new();
+                    entry.data.read_to_end(&mut content).unwrap();
+                    (entry.filename, entry.kind, entry.link_target, content)
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                entries,
+                vec![
+                    ("dir".to_string(), EntryKind::Directory, None, vec![]),
+                    ("dir/my_file".to_string(), EntryKind::File, None, vec![1, 2]),
+                    (
+                        "link".to_string(),
+                        EntryKind::Symlink,
+                        Some("dir/my_file".to_string()),
+                        vec![]
+                    ),
+                ]
+            );
+        };
+        check(&dest);
+
+        // Symbolic links are kept on fail-safe conversion
+        let mut mla_fsread =
+            ArchiveFailSafeReader::from_config(dest.as_slice(), config.clone()).unwrap();
+        let mut mla_w =
+            ArchiveWriter::new(Vec::new(), std::slice::from_ref(&PublicKey::from(&key))).unwrap();
+        mla_fsread.convert_to_archive(&mut mla_w).unwrap();
+        check(&mla_w.into_raw());
+    }
+
+    #[test]
+    fn unknown_blocks() {
+        // Optional block of a type added by a future version: type, id,
+        // length and content
+        let mut custom = vec![0x90];
+        custom.extend_from_slice(&0u64.to_le_bytes());
+        custom.extend_from_slice(&5u64.to_le_bytes());
+        custom.extend_from_slice(b"extra");
+        assert!(matches!(
+            ArchiveFileBlock::from(&mut custom.as_slice()),
+            Ok(ArchiveFileBlock::Unknown {
+                kind: 0x90,
+                length: 5
+            })
+        ));
+
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        let id = mla.start_file("my_file").unwrap();
+        mla.append_file_content(id, 2, [1, 2].as_ref()).unwrap();
+        mla.dest.write_all(&custom).unwrap();
+        mla.append_file_content(id, 2, [3, 4].as_ref()).unwrap();
+        mla.end_file(id).unwrap();
+        mla.dest.write_all(&custom).unwrap();
+        mla.add_file("other_file", 1, [5].as_ref()).unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        // By default, the unknown blocks are skipped
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut rez = Vec::new();
+        let mut file = mla_read.get_file("my_file").unwrap().unwrap();
+        file.data.read_to_end(&mut rez).unwrap();
+        assert_eq!(rez, vec![1, 2, 3, 4]);
+        let fnames = ["my_file".to_string(), "other_file".to_string()];
+        let mut export: HashMap<&String, Vec<u8>> =
+            fnames.iter().map(|fname| (fname, Vec::new())).collect();
+        helpers::linear_extract(&mut mla_read, &mut export).unwrap();
+        assert_eq!(export[&fnames[0]], vec![1, 2, 3, 4]);
+        assert_eq!(export[&fnames[1]], vec![5]);
+        assert_eq!(mla_read.entries().unwrap().count(), 2);
+        assert_eq!(mla_read.verify_integrity().unwrap().files_ok, 2);
+
+        // Otherwise, they are reported
+        let mut config = ArchiveReaderConfig::new();
+        config.skip_unknown_blocks(false);
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+        let mut file = mla_read.get_file("my_file").unwrap().unwrap();
+        assert!(file.data.read_to_end(&mut Vec::new()).is_err());
+        let mut export: HashMap<&String, Vec<u8>> =
+            fnames.iter().map(|fname| (fname, Vec::new())).collect();
+        assert!(matches!(
+            helpers::linear_extract(&mut mla_read, &mut export),
+            Err(Error::UnknownBlockType(0x90))
+        ));
+        assert!(mla_read.entries().unwrap().any(|entry| entry.is_err()));
+        assert!(matches!(
+            mla_read.verify_integrity(),
+            Err(Error::UnknownBlockType(0x90))
+        ));
+    }
+
+    #[test]
+    fn symlinks_unknown_to_reader() {
+        // Simulate a reader without symbolic link support, by replacing the
+        // Symlink block type with an unknown optional one
+        let unknown_type = 0xA0;
+        assert!(!block_kind_is_known(unknown_type));
+
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        let id = mla.start_file("my_file").unwrap();
+        mla.append_file_content(id, 2, [1, 2].as_ref()).unwrap();
+        mla.add_symlink("link", "my_file", None).unwrap();
+        mla.append_file_content(id, 2, [3, 4].as_ref()).unwrap();
+        mla.end_file(id).unwrap();
+        mla.add_file("other_file", 1, [5].as_ref()).unwrap();
+        mla.finalize().unwrap();
+        let mut dest = mla.into_raw();
+
+        let mut block = Vec::new();
+        ArchiveFileBlock::Symlink::<std::io::Empty> {
+            link_name: "link".to_string(),
+            target: "my_file".to_string(),
+            metadata: None,
+        }
+        .dump(&mut block)
+        .unwrap();
+        let pos = dest
+            .windows(block.len())
+            .position(|window| window == block.as_slice())
+            .unwrap();
+        dest[pos] = unknown_type;
+
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut file = mla_read.get_file("my_file").unwrap().unwrap();
+        let mut rez = Vec::new();
+        file.data.read_to_end(&mut rez).unwrap();
+        assert_eq!(rez, vec![1, 2, 3, 4]);
+
+        let fnames = ["my_file".to_string(), "other_file".to_string()];
+        let mut export: HashMap<&String, Vec<u8>> =
+            fnames.iter().map(|fname| (fname, Vec::new())).collect();
+        helpers::linear_extract(&mut mla_read, &mut export).unwrap();
+        assert_eq!(export[&fnames[0]], vec![1, 2, 3, 4]);
+        assert_eq!(export[&fnames[1]], vec![5]);
+
+        let kinds = mla_read
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                (entry.filename, entry.kind)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                ("my_file".to_string(), EntryKind::File),
+                ("other_file".to_string(), EntryKind::File)
+            ]
+        );
+    }
+
+    #[test]
+    fn list_and_read_files() {
+        // Build an archive with 3 files
+        let (mla, key, files) = build_archive(None, false);
+
+        let dest = mla.into_raw();
+        let buf = Cursor::new(dest.as_slice());
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+
+        // Check the list of files is correct
+        let mut sorted_list: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+        sorted_list.sort();
+        assert_eq!(
+            sorted_list,
+            files
+                .iter()
+                .map(|(x, _y)| x.clone())
+                .collect::<Vec<String>>(),
+        );
+
+        // Get and check file per file, not in the writing order
+        for (fname, content) in files.iter().rev() {
+            let mut mla_file = mla_read.get_file(fname.clone()).unwrap().unwrap();
+            assert_eq!(mla_file.filename, fname.clone());
+            let mut buf = Vec::new();
+            mla_file.data.read_to_end(&mut buf).unwrap();
+            assert_eq!(&buf, content);
+        }
+    }
+
+    #[test]
+    fn get_file_name_types() {
+        let (mla, key, files) = build_archive(None, false);
+
+        let dest = mla.into_raw();
+        let buf = Cursor::new(dest.as_slice());
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+
+        let (fname, content) = &files[0];
+        let mut results = Vec::new();
+        let mut file = mla_read.get_file(fname.as_str()).unwrap().unwrap();
+        let mut buf = Vec::new();
+        file.data.read_to_end(&mut buf).unwrap();
+        results.push((file.filename, buf));
+        let mut file = mla_read.get_file(fname).unwrap().unwrap();
+        let mut buf = Vec::new();
+        file.data.read_to_end(&mut buf).unwrap();
+        results.push((file.filename, buf));
+        let mut file = mla_read.get_file(fname.clone()).unwrap().unwrap();
+        let mut buf = Vec::new();
+        file.data.read_to_end(&mut buf).unwrap();
+        results.push((file.filename, buf));
+
+        for (filename, data) in results {
+            assert_eq!(&filename, fname);
+            assert_eq!(&data, content);
+        }
+    }
+
+    #[test]
+    fn entries() {
+        // Build an archive with 3 files
+        let (mla, key, files) = build_archive(None, false);
+
+        let dest = mla.into_raw();
+        let buf = Cursor::new(dest.as_slice());
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+
+        // Files are yielded in the writing order
+        let mut count = 0;
+        for (entry, (fname, content)) in mla_read.entries().unwrap().zip(files.iter()) {
+            let mut entry = entry.unwrap();
+            assert_eq!(&entry.filename, fname);
+            assert_eq!(entry.size, Some(content.len() as u64));
+            let mut buf = Vec::new();
+            entry.read_to_end(&mut buf).unwrap();
+            assert_eq!(&buf, content);
+            count += 1;
+        }
+        assert_eq!(count, files.len());
+
+        // Entries can be skipped, or only their data kept
+        let mut entries = mla_read.entries().unwrap();
+        entries.next().unwrap().unwrap();
+        let mut data = entries.next().unwrap().unwrap().into_data();
+        entries.next().unwrap().unwrap();
+        assert!(entries.next().is_none());
+        // ... but the content of a skipped entry is no more available
+        assert!(data.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn entries_interleaved() {
+        let (mla, key, files) = build_archive(None, true);
+
+        let dest = mla.into_raw();
+        let buf = Cursor::new(dest.as_slice());
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+
+        // The first file can be read entirely, skipping the other files
+        // content which is interleaved
+        let mut entries = mla_read.entries().unwrap();
+        let mut entry = entries.next().unwrap().unwrap();
+        assert_eq!(entry.filename, files[0].0);
+        let mut buf = Vec::new();
+        entry.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, files[0].1);
+
+        // Other files content has been partially skipped
+        for (fname, _content) in files[1..].iter() {
+            let mut entry = entries.next().unwrap().unwrap();
+            assert_eq!(&entry.filename, fname);
+            assert!(entry.read_to_end(&mut Vec::new()).is_err());
+        }
+        assert!(entries.next().is_none());
+    }
+
+    #[test]
+    fn entries_from_reader() {
+        let (mla, _key, files) = build_archive(Some(Layers::EMPTY), false);
+        let dest = mla.into_raw();
+
+        // No seek is needed
+        let mut count = 0;
+        for (entry, (fname, content)) in ArchiveEntries::from_reader(dest.as_slice())
+            .unwrap()
+            .zip(files.iter())
+        {
+            let mut entry = entry.unwrap();
+            assert_eq!(&entry.filename, fname);
+            assert_eq!(entry.size, None);
+            let mut buf = Vec::new();
+            entry.read_to_end(&mut buf).unwrap();
+            assert_eq!(&buf, content);
+            count += 1;
+        }
+        assert_eq!(count, files.len());
+
+        // Compressed archives are read through the fail-safe reader, which
+        // doesn't seek either
+        let (mla, _key, files) = build_archive(Some(Layers::COMPRESS), false);
+        let dest = mla.into_raw();
+        let mut count = 0;
+        for (entry, (fname, content)) in ArchiveEntries::from_reader(dest.as_slice())
+            .unwrap()
+            .zip(files.iter())
+        {
+            let mut entry = entry.unwrap();
+            assert_eq!(&entry.filename, fname);
+            let mut buf = Vec::new();
+            entry.read_to_end(&mut buf).unwrap();
+            assert_eq!(&buf, content);
+            count += 1;
+        }
+        assert_eq!(count, files.len());
+
+        // Other layers requires seeking
+        let (mla, _key, _files) = build_archive(None, false);
+        let dest = mla.into_raw();
+        assert!(ArchiveEntries::from_reader(dest.as_slice()).is_err());
+    }
+
+    #[test]
+    fn convert_failsafe() {
+        // Build an archive with 3 files
+        let (mla, key, files) = build_archive(None, false);
+
+        // Prepare the failsafe reader
+        let dest = mla.into_raw();
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_fsread = ArchiveFailSafeReader::from_config(dest.as_slice(), config).unwrap();
+
+        // Prepare the writer
+        let dest_w = Vec::new();
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .enable_layer(Layers::COMPRESS)
+            .enable_layer(Layers::ENCRYPT)
+            .add_public_keys(&[PublicKey::from(&key)]);
+        let mut mla_w = ArchiveWriter::from_config(dest_w, config).expect("Writer init failed");
+
+        // Conversion
+        match mla_fsread.convert_to_archive(&mut mla_w).unwrap() {
+            FailSafeReadError::EndOfOriginalArchiveData => {
+                // We expect to ends with the final tag - all files have been
+                // read and we stop on the tag before the footer
+            }
+            status => {
+                panic!("Unexpected status: {}", status);
+            }
+        };
+
+        // New archive can now be checked
+        let dest2 = mla_w.into_raw();
+        let buf2 = Cursor::new(dest2.as_slice());
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(buf2, config).unwrap();
+
+        // Check the list of files is correct
+        let mut sorted_list: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+        sorted_list.sort();
+        assert_eq!(
+            sorted_list,
+            files
+                .iter()
+                .map(|(x, _y)| x.clone())
+                .collect::<Vec<String>>(),
+        );
+
+        // Get and check file per file, not in the writing order
+        for (fname, content) in files.iter().rev() {
+            let mut mla_file = mla_read.get_file(fname.clone()).unwrap().unwrap();
+            assert_eq!(mla_file.filename, fname.clone());
+            let mut buf = Vec::new();
+            mla_file.data.read_to_end(&mut buf).unwrap();
+            assert_eq!(&buf, content);
+        }
+    }
+
+    #[test]
+    fn convert_trunc_failsafe() {
+        for interleaved in &[false, true] {
+            // Build an archive with 3 files, without compressing to truncate at the correct place
+            let (mla, key, files) =
+                build_archive(Some(Layers::default() ^ Layers::COMPRESS), *interleaved);
+            // Truncate the resulting file (before the footer, hopefully after the header), and prepare the failsafe reader
+            let footer_size = bincode::serialized_size(&mla.files_info).unwrap() as usize + 4;
+            let dest = mla.into_raw();
+
+            for remove in &[1, 10, 30, 50, 70, 95, 100] {
+                let mut config = ArchiveReaderConfig::new();
+                config.add_private_keys(std::slice::from_ref(&key));
+                let mut mla_fsread = ArchiveFailSafeReader::from_config(
+                    &dest[..dest.len() - footer_size - remove],
+                    config,
+                )
+                .expect("Unable to create");
+
+                // Prepare the writer
+                let dest_w = Vec::new();
+                let mut mla_w = ArchiveWriter::new(dest_w, &[PublicKey::from(&key)])
+                    .expect("Writer init failed");
+
+                // Conversion
+                let _status = mla_fsread.convert_to_archive(&mut mla_w).unwrap();
+
+                // New archive can now be checked
+                let dest2 = mla_w.into_raw();
+                let buf2 = Cursor::new(dest2.as_slice());
+                let mut config = ArchiveReaderConfig::new();
+                config.add_private_keys(std::slice::from_ref(&key));
+                let mut mla_read = ArchiveReader::from_config(buf2, config).unwrap();
+
+                // Check *the start of* the files list is correct
+                let expected = files
+                    .iter()
+                    .map(|(x, _y)| x.clone())
+                    .collect::<Vec<String>>();
+                let mut file_list = mla_read
+                    .list_files()
+                    .unwrap()
+                    .cloned()
+                    .collect::<Vec<String>>();
+                file_list.sort();
+                assert_eq!(
+                    file_list[..],
+                    expected[..file_list.len()],
+                    "File lists not equal {} interleaving and {} bytes removed",
+                    if *interleaved { "with" } else { "without" },
+                    remove
+                );
+
+                // Get and check file per file, not in the writing order
+                for (fname, content) in files.iter().rev() {
+                    // The file may be missing
+                    let mut mla_file = match mla_read.get_file(fname.clone()).unwrap() {
+                        Some(mla_file) => mla_file,
+                        None => continue,
+                    };
+                    // If the file is present, ensure there are bytes and the first
+                    // bytes are the same
+                    assert_eq!(mla_file.filename, fname.clone());
+                    let mut buf = Vec::new();
+                    mla_file.data.read_to_end(&mut buf).unwrap();
+                    assert_ne!(
+                        buf.len(),
+                        0,
+                        "Read 0 bytes from subfile {} {} interleaving and {} bytes removed",
+                        mla_file.filename,
+                        if *interleaved { "with" } else { "without" },
+                        remove
+                    );
+                    assert_eq!(&buf[..], &content[..buf.len()]);
+                }
+            }
+            // /!\ This test doesn't ensure the code is doing the best effort; it only check the result is correct.
+        }
+    }
+
+    #[test]
+    fn avoid_duplicate_filename() {
+        let buf = Vec::new();
+        let config = ArchiveWriterConfig::new();
+        let mut mla = ArchiveWriter::from_config(buf, config).unwrap();
+        mla.add_file("Test", 4, vec![1, 2, 3, 4].as_slice())
+            .unwrap();
+        assert!(mla
+            .add_file("Test", 4, vec![1, 2, 3, 4].as_slice())
+            .is_err());
+        assert!(mla.start_file("Test").is_err());
+    }
+
+    #[test]
+    fn check_file_size() {
+        // Build an archive with 3 non-interleaved files and another with
+        // interleaved files
+        for interleaved in &[false, true] {
+            let (mla, key, files) = build_archive(None, *interleaved);
+
+            for (fname, data) in &files {
+                let id = mla.files_info.get(fname).unwrap();
+                let size = mla.ids_info.get(id).unwrap().size;
+                assert_eq!(size, data.len() as u64);
+            }
+
+            let dest = mla.into_raw();
+            let buf = Cursor::new(dest.as_slice());
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+
+            for (fname, data) in &files {
+                let mla_file = mla_read.get_file(fname.to_string()).unwrap().unwrap();
+                assert_eq!(mla_file.size, data.len() as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn failsafe_detect_integrity() {
+        // Build an archive with 3 files
+        let (mla, _key, files) = build_archive(Some(Layers::DEBUG), false);
+
+        // Swap the first 2 bytes of file1
+        let mut dest = mla.into_raw();
+        let expect = files[0].1.as_slice();
+        let pos: Vec<usize> = dest
+            .iter()
+            .enumerate()
+            .filter_map(|e| {
+                if e.0 + expect.len() < dest.len() && &dest[e.0..e.0 + expect.len()] == expect {
+                    Some(e.0)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        dest.swap(pos[0], pos[0] + 1);
+
+        // Prepare the failsafe reader
+        let mut mla_fsread =
+            ArchiveFailSafeReader::from_config(dest.as_slice(), ArchiveReaderConfig::new())
+                .unwrap();
+
+        // Prepare the writer
+        let dest_w = Vec::new();
+        let mut mla_w = ArchiveWriter::from_config(dest_w, ArchiveWriterConfig::new())
+            .expect("Writer init failed");
+
+        // Conversion
+        match mla_fsread.convert_to_archive(&mut mla_w).unwrap() {
+            FailSafeReadError::UnfinishedFiles {
+                filenames,
+                stopping_error,
+            } => {
+                // We expect to ends with a HashDiffers on first file
+                assert_eq!(filenames, vec![files[0].0.to_string()]);
+                match *stopping_error {
+                    FailSafeReadError::HashDiffers { .. } => {}
+                    _ => {
+                        panic!("Unexpected stopping_error: {}", stopping_error);
+                    }
+                }
+            }
+            status => {
+                panic!("Unexpected status: {}", status);
+            }
+        };
+    }
+
+    #[test]
+    fn compression_algorithms() {
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let content: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        for algorithm in [
+            CompressionAlgorithm::None,
+            CompressionAlgorithm::Brotli { quality: 1 },
+            CompressionAlgorithm::Zstd { level: 3 },
+            CompressionAlgorithm::Lz4,
+        ]
+        .iter()
+        {
+            let mut config = ArchiveWriterConfig::new();
+            config
+                .enable_layer(Layers::COMPRESS | Layers::ENCRYPT)
+                .add_public_keys(&[PublicKey::from(&key)])
+                .with_compression_algorithm(*algorithm)
+                .unwrap();
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            mla.add_file("file", content.len() as u64, content.as_slice())
+                .unwrap();
+            mla.finalize().unwrap();
+            let archive = mla.into_raw();
+
+            // The algorithm is found in the header
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(archive.as_slice()), config).unwrap();
+            assert_eq!(mla_read.config.compress.algorithm(), *algorithm);
+            let mut rez = Vec::new();
+            let mut file = mla_read.get_file("file").unwrap().unwrap();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(rez, content, "{:?}", algorithm);
+
+            // Repair mode
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            let mut mla_fsread =
+                ArchiveFailSafeReader::from_config(archive.as_slice(), config).unwrap();
+            let mut mla_w =
+                ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+            match mla_fsread.convert_to_archive(&mut mla_w).unwrap() {
+                FailSafeReadError::EndOfOriginalArchiveData => {}
+                status => panic!("Unexpected status: {}", status),
+            }
+            let mut mla_read = ArchiveReader::from_config(
+                Cursor::new(mla_w.into_raw()),
+                ArchiveReaderConfig::new(),
+            )
+            .unwrap();
+            let mut rez = Vec::new();
+            let mut file = mla_read.get_file("file").unwrap().unwrap();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(rez, content, "{:?}", algorithm);
         }
+    }
 
-        let mut unfinished_files = Vec::new();
+    #[test]
+    fn zstd_dictionary() {
+        // Similar JSON documents, such as API responses
+        let docs: Vec<Vec<u8>> = (0..100u32)
+            .map(|i| {
+                format!(
+                    concat!(
+                        r#"{{"id":{},"user":{{"name":"user{}","email":"user{}@example.com","#,
+                        r#""roles":["reader","writer"],"active":{}}},"request":{{"method":"GET","#,
+                        r#""path":"/api/v1/items/{}","headers":{{"accept":"application/json","#,
+                        r#""user-agent":"mla-client/1.0"}}}},"status":200,"items":[{{"#,
+                        r#""sku":"SKU-{:05}","price":{}.99,"currency":"EUR"}}]}}"#
+                    ),
+                    i,
+                    i,
+                    i,
+                    i % 2 == 0,
+                    i * 7,
+                    i * 13,
+                    i % 50
+                )
+                .into_bytes()
+            })
+            .collect();
+        let samples: Vec<&[u8]> = docs.iter().map(Vec::as_slice).collect();
+        let dictionary = helpers::train_dictionary_from_samples(&samples, 4096).unwrap();
 
-        // Clean-up files still opened
-        for (id_failsafe, id_output) in id_failsafe2id_output {
-            if id_failsafe_done.contains(&id_failsafe) {
-                // File is OK
-                continue;
+        let key = StaticSecret::from([4; 32]);
+        let build = |dictionary: Option<&[u8]>, layers: Layers, docs: &[Vec<u8>]| {
+            let mut config = ArchiveWriterConfig::new();
+            config
+                .set_layers(layers)
+                .add_public_keys(&[PublicKey::from(&key)])
+                .with_compression_algorithm(CompressionAlgorithm::Zstd { level: 3 })
+                .unwrap();
+            if let Some(dictionary) = dictionary {
+                config.with_zstd_dictionary(dictionary);
+            }
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            let header_size = mla.header_size;
+            for (index, doc) in docs.iter().enumerate() {
+                mla.add_file(format!("{}.json", index), doc.len() as u64, doc.as_slice())
+                    .unwrap();
             }
+            mla.finalize().unwrap();
+            (mla.into_raw(), header_size)
+        };
 
-            let fname = id_failsafe2filename
-                .get(&id_failsafe)
-                .expect("`id_failsafe2filename` not more sync with `id_failsafe2id_output`");
-            output.end_file(id_output)?;
+        // Stored one per archive, documents are much smaller with the
+        // dictionary (the header, holding the dictionary, is not counted)
+        let data_size = |dictionary: Option<&[u8]>| -> usize {
+            docs.iter()
+                .map(|doc| {
+                    let (archive, header_size) =
+                        build(dictionary, Layers::COMPRESS, std::slice::from_ref(doc));
+                    archive.len() - header_size as usize
+                })
+                .sum()
+        };
+        let without = data_size(None);
+        let with = data_size(Some(&dictionary));
+        assert!(without >= 2 * with, "{} < 2 * {}", without, with);
 
-            unfinished_files.push(fname.clone());
+        // The dictionary is loaded from the header, including in repair mode
+        let (archive, _) = build(Some(&dictionary), Layers::DEFAULT, &docs);
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(archive.as_slice()), config.clone()).unwrap();
+        for (index, doc) in docs.iter().enumerate() {
+            let mut file = mla_read
+                .get_file(format!("{}.json", index))
+                .unwrap()
+                .unwrap();
+            let mut rez = Vec::new();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(&rez, doc);
         }
-
-        // Report which files are not completed, if any
-        if !unfinished_files.is_empty() {
-            error = FailSafeReadError::UnfinishedFiles {
-                filenames: unfinished_files,
-                stopping_error: Box::new(error),
-            };
+        let mut mla_fsread =
+            ArchiveFailSafeReader::from_config(archive.as_slice(), config).unwrap();
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        match mla_fsread.convert_to_archive(&mut mla_w).unwrap() {
+            FailSafeReadError::EndOfOriginalArchiveData => {}
+            status => panic!("Unexpected status: {}", status),
         }
 
-        output.finalize()?;
-        Ok(error)
+        // Only Zstandard uses a dictionary
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::COMPRESS)
+            .with_zstd_dictionary(&dictionary);
+        assert!(matches!(
+            ArchiveWriter::from_config(Vec::new(), config),
+            Err(Error::ConfigError(ConfigError::DictionaryRequiresZstd))
+        ));
     }
-}
-
-#[cfg(test)]
-pub(crate) mod tests {
-    use super::*;
-    use ed25519_parser::{parse_openssl_ed25519_privkey, parse_openssl_ed25519_pubkey};
-    use hex;
-    use rand::SeedableRng;
-    use rand_chacha::ChaChaRng;
-    use std::io::{Cursor, Empty, Read};
-    use x25519_dalek::{PublicKey, StaticSecret};
 
     #[test]
-    fn read_dump_header() {
-        let header = ArchiveHeader {
-            config: ArchivePersistentConfig {
-                layers_enabled: Layers::default(),
-                encrypt: None,
-            },
+    fn compression_bypass() {
+        let build = |name: &str, content: &[u8]| {
+            let mut config = ArchiveWriterConfig::new();
+            config
+                .enable_layer(Layers::COMPRESS)
+                .skip_compression_for_extensions(&["bin"])
+                .with_compression_algorithm(CompressionAlgorithm::Zstd { level: 1 })
+                .unwrap();
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            mla.add_file(name, content.len() as u64, content).unwrap();
+            mla.finalize().unwrap();
+            mla.into_raw()
         };
-        let mut buf = Vec::new();
-        header.dump(&mut buf).unwrap();
-        println!("{:?}", buf);
 
-        let header_rebuild = ArchiveHeader::from(&mut buf.as_slice()).unwrap();
-        assert_eq!(header_rebuild.config.layers_enabled, Layers::default());
+        // Blocks started in the content of "big.bin" are stored as is
+        let content: Vec<u8> = (0..9 * 1024 * 1024).map(|i| (i % 7) as u8).collect();
+        let stored = build("big.bin", &content);
+        let compressed = build("big.txt", &content);
+        assert!(stored.len() > 4 * 1024 * 1024);
+        assert!(compressed.len() < 1024 * 1024);
+        for archive in [stored, compressed].iter() {
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(archive), ArchiveReaderConfig::new())
+                    .unwrap();
+            let name = mla_read.list_files().unwrap().next().unwrap().clone();
+            let mut rez = Vec::new();
+            let mut file = mla_read.get_file(name).unwrap().unwrap();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(rez, content);
+        }
     }
 
     #[test]
-    fn dump_block() {
-        let mut buf = Vec::new();
-        let id = 0;
-        let hash = Sha256Hash::default();
+    fn file_compression_level() {
+        // Text-like content, spanning several compression blocks
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let words = ["alpha ", "beta ", "gamma ", "delta ", "epsilon\n"];
+        let content: Vec<u8> = (0..720_000)
+            .flat_map(|_| words[rng.gen_range(0, words.len())].bytes())
+            .collect();
+        assert!(content.len() > 4 * 1024 * 1024);
+        let small = b"small file".to_vec();
+        let build = |big_level: Option<CompressionLevel>, other_level: Option<CompressionLevel>| {
+            // The compressed footer holds the creation time: fix it, to
+            // compare the archive sizes
+            let mut config = ArchiveWriterConfig::new();
+            config
+                .enable_layer(Layers::COMPRESS)
+                .compression_quality(5)
+                .set_created_at(UNIX_EPOCH);
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            mla.add_file("small", small.len() as u64, small.as_slice())
+                .unwrap();
+            for (name, level) in [("big", big_level), ("other", other_level)] {
+                let id = mla.start_file(name).unwrap();
+                if let Some(level) = level {
+                    mla.set_file_compression(id, level).unwrap();
+                }
+                mla.append_file_content(id, content.len() as u64, content.as_slice())
+                    .unwrap();
+                mla.end_file(id).unwrap();
+            }
+            assert!(mla.compression_levels.is_empty());
+            mla.finalize().unwrap();
+            mla.into_raw()
+        };
 
-        // std::io::Empty is used because a type with Read is needed
-        ArchiveFileBlock::FileStart::<Empty> {
-            id,
-            filename: String::from("foobaré.exe"),
+        let dense = build(Some(11), None);
+        let fast = build(Some(0), None);
+        assert!(dense.len() < fast.len());
+        // The level of "other" is the configured one, whatever the one of
+        // "big"
+        assert_eq!(fast.len(), build(Some(0), Some(5)).len());
+        for archive in [dense, fast].iter() {
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(archive), ArchiveReaderConfig::new())
+                    .unwrap();
+            for (name, expected) in [("small", &small), ("big", &content), ("other", &content)] {
+                let mut rez = Vec::new();
+                let mut file = mla_read.get_file(name).unwrap().unwrap();
+                file.data.read_to_end(&mut rez).unwrap();
+                assert_eq!(&rez, expected);
+            }
         }
-        .dump(&mut buf)
-        .unwrap();
 
-        let fake_content = vec![1, 2, 3, 4];
-        let mut block = ArchiveFileBlock::FileContent {
-            id,
-            length: fake_content.len() as u64,
-            data: Some(fake_content.as_slice()),
-        };
-        block.dump(&mut buf).unwrap();
+        // Levels are checked against the configured algorithm
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        let id = mla.start_file("file").unwrap();
+        assert!(matches!(
+            mla.set_file_compression(id, 12),
+            Err(Error::ConfigError(ConfigError::CompressionLevelOutOfRange))
+        ));
+    }
 
-        // std::io::Empty is used because a type with Read is needed
-        ArchiveFileBlock::EndOfFile::<Empty> { id, hash }
-            .dump(&mut buf)
-            .unwrap();
+    #[test]
+    fn compression_bypass_decision() {
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let mut random = vec![0u8; 10_000];
+        rng.fill_bytes(&mut random);
+        let text = b"Lorem ipsum dolor sit amet, consectetur adipiscing elit ".repeat(100);
 
-        println!("{:?}", buf);
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .enable_layer(Layers::COMPRESS)
+            .skip_compression_for_extensions(&["jpg"])
+            .entropy_threshold(7.5);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let files: &[(&str, CompressionHint, &[u8], Option<bool>)] = &[
+            ("random.dat", CompressionHint::Auto, &random, Some(true)),
+            ("text.txt", CompressionHint::Auto, &text, Some(false)),
+            ("text.jpg", CompressionHint::Auto, &text, Some(true)),
+            ("random.jpg", CompressionHint::Compress, &random, None),
+            ("text.dat", CompressionHint::Store, &text, Some(true)),
+        ];
+        for (name, hint, content, _) in files {
+            let id = mla.start_file_with_hint(name, *hint).unwrap();
+            // Decision made on the first content block only
+            mla.append_file_content(id, 5000, &content[..5000]).unwrap();
+            mla.append_file_content(id, content.len() as u64 - 5000, &content[5000..])
+                .unwrap();
+        }
+        for (id, (name, _, _, bypass)) in files.iter().enumerate() {
+            assert_eq!(
+                mla.compression_bypass
+                    .get(&ArchiveFileID(id as u64))
+                    .copied(),
+                bypass.map(Some),
+                "{}",
+                name
+            );
+            mla.end_file(ArchiveFileID(id as u64)).unwrap();
+        }
+        assert!(mla.compression_bypass.is_empty());
+        mla.finalize().unwrap();
+
+        // Samples are kept in the content
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(mla.into_raw()), ArchiveReaderConfig::new())
+                .unwrap();
+        for (name, _, content, _) in files {
+            let mut rez = Vec::new();
+            let mut file = mla_read.get_file(name).unwrap().unwrap();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(rez.as_slice(), *content, "{}", name);
+        }
     }
 
     #[test]
-    fn blocks_to_file() {
-        // Create several blocks
-        let mut buf = Vec::new();
-        let id = 0;
-        let hash = Sha256Hash::default();
+    fn add_file_from_reader() {
+        let mut content = vec![0u8; 2 * CACHE_SIZE + 3];
+        ChaChaRng::seed_from_u64(0).fill_bytes(&mut content);
+        let path = std::env::temp_dir().join(format!("mla_from_path_{}", std::process::id()));
+        std::fs::write(&path, &content[..1000]).unwrap();
 
-        let fake_content = vec![1, 2, 3, 4];
-        let mut block = ArchiveFileBlock::FileContent {
-            id,
-            length: fake_content.len() as u64,
-            data: Some(fake_content.as_slice()),
-        };
-        block.dump(&mut buf).unwrap();
-        let fake_content2 = vec![5, 6, 7, 8];
-        let mut block = ArchiveFileBlock::FileContent {
-            id,
-            length: fake_content2.len() as u64,
-            data: Some(fake_content2.as_slice()),
-        };
-        block.dump(&mut buf).unwrap();
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::COMPRESS);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).expect("Writer init failed");
+        let size = mla
+            .add_file_from_reader("reader", &mut content.as_slice())
+            .unwrap();
+        assert_eq!(size, content.len() as u64);
+        assert_eq!(mla.add_file_from_path(&path, "path").unwrap(), 1000);
+        std::fs::remove_file(&path).unwrap();
+        assert!(mla
+            .add_file_from_path(&path, "missing")
+            .unwrap_err()
+            .to_string()
+            .contains("NotFound"));
+        mla.finalize().unwrap();
 
-        // std::io::Empty is used because a type with Read is needed
-        ArchiveFileBlock::EndOfFile::<Empty> { id, hash }
-            .dump(&mut buf)
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(mla.into_raw()), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut files: Vec<&String> = mla_read.list_files().unwrap().collect();
+        files.sort();
+        assert_eq!(files, ["path", "reader"]);
+        let mut data = Vec::new();
+        mla_read
+            .get_file("reader")
+            .unwrap()
+            .unwrap()
+            .data
+            .read_to_end(&mut data)
             .unwrap();
+        assert_eq!(data, content);
+        let file = mla_read.get_file("path").unwrap().unwrap();
+        assert_eq!(file.size, 1000);
+        assert!(file.metadata.unwrap().mtime.is_some());
+    }
 
-        let mut data_source = std::io::Cursor::new(buf);
-        let mut reader = BlocksToFileReader::new(&mut data_source, id, vec![0]);
-        let mut output = Vec::new();
-        reader.read_to_end(&mut output).unwrap();
-        assert_eq!(output.len(), fake_content.len() + fake_content2.len());
-        let mut expected_output = Vec::new();
-        expected_output.extend(fake_content);
-        expected_output.extend(fake_content2);
-        assert_eq!(output, expected_output);
-        assert_eq!(reader.state, BlocksToFileReaderState::Finish);
+    /// Provide `count` bytes, then fail
+    struct FailingReader {
+        count: usize,
+    }
+
+    impl Read for FailingReader {
+        fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+            if self.count == 0 {
+                return Err(io::Error::other("Read failure"));
+            }
+            let count = std::cmp::min(self.count, into.len());
+            into[..count].fill(1);
+            self.count -= count;
+            Ok(count)
+        }
     }
 
     #[test]
-    fn new_mla() {
-        let file = Vec::new();
-        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+    fn open_files() {
         let mut rng = ChaChaRng::seed_from_u64(0);
-        let key = StaticSecret::new(&mut rng);
-        let mut mla = ArchiveWriter::new(file, std::slice::from_ref(&PublicKey::from(&key)))
-            .expect("Writer init failed");
+        let contents: Vec<Vec<u8>> = [10_000, 0, 300_000]
+            .iter()
+            .map(|size| {
+                let mut data = vec![0u8; *size];
+                rng.fill_bytes(&mut data);
+                data
+            })
+            .collect();
 
-        let fake_file = vec![1, 2, 3, 4];
-        mla.add_file("my_file", fake_file.len() as u64, fake_file.as_slice())
-            .unwrap();
-        let fake_file = vec![5, 6, 7, 8];
-        let fake_file2 = vec![9, 10, 11, 12];
-        let id = mla.start_file("my_file2").unwrap();
-        mla.append_file_content(id, fake_file.len() as u64, fake_file.as_slice())
-            .unwrap();
-        mla.append_file_content(id, fake_file2.len() as u64, fake_file2.as_slice())
-            .unwrap();
-        mla.end_file(id).unwrap();
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::COMPRESS);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        {
+            let [mut w1, w2, mut w3] = mla.open_files(["a", "b", "c"]).unwrap();
+            // Interleave chunks of random sizes
+            let (mut pos1, mut pos3) = (0, 0);
+            while pos1 < contents[0].len() || pos3 < contents[2].len() {
+                for (writer, data, pos) in [
+                    (&mut w1, &contents[0], &mut pos1),
+                    (&mut w3, &contents[2], &mut pos3),
+                ] {
+                    let end = std::cmp::min(data.len(), *pos + rng.gen_range(1, 5000));
+                    writer.write_all(&data[*pos..end]).unwrap();
+                    *pos = end;
+                }
+            }
+            // Ended by drop or finalize
+            drop(w2);
+            w1.finalize().unwrap();
+        }
+        // The archive is usable again
+        mla.add_file("d", 1, [1].as_ref()).unwrap();
+        // Already used name: no file is left started
+        assert!(matches!(
+            mla.open_files(["e", "d"]),
+            Err(Error::DuplicateFilename)
+        ));
+        mla.finalize().unwrap();
+
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(mla.into_raw()), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut files: Vec<&String> = mla_read.list_files().unwrap().collect();
+        files.sort();
+        assert_eq!(files, ["a", "b", "c", "d"]);
+        for (filename, expected) in ["a", "b", "c"].iter().zip(&contents) {
+            let mut data = Vec::new();
+            mla_read
+                .get_file(filename)
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut data)
+                .unwrap();
+            assert_eq!(&data, expected);
+        }
+    }
+
+    #[test]
+    fn add_file_from_reader_error() {
+        for layers in [Layers::EMPTY, Layers::COMPRESS] {
+            let mut config = ArchiveWriterConfig::new();
+            config.set_layers(layers);
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            mla.add_file("before", 1, [1].as_ref()).unwrap();
+            let id = mla.start_file("interleaved").unwrap();
+            // Errors before and after a first content block
+            for (filename, count) in [("broken", CACHE_SIZE + 10), ("broken_early", 10)] {
+                assert!(matches!(
+                    mla.add_file_from_reader(filename, &mut FailingReader { count }),
+                    Err(Error::IOError(_))
+                ));
+                // The name is still used
+                assert!(matches!(
+                    mla.add_file(filename, 0, io::empty()),
+                    Err(Error::DuplicateFilename)
+                ));
+            }
+            // The writer is still usable
+            mla.append_file_content(id, 2, [2, 3].as_ref()).unwrap();
+            mla.end_file(id).unwrap();
+            mla.add_file("after", 1, [4].as_ref()).unwrap();
+            mla.finalize().unwrap();
+
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(mla.into_raw()), ArchiveReaderConfig::new())
+                    .unwrap();
+            let mut files: Vec<&String> = mla_read.list_files().unwrap().collect();
+            files.sort();
+            assert_eq!(files, ["after", "before", "interleaved"]);
+            let mut data = Vec::new();
+            mla_read
+                .get_file("interleaved")
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut data)
+                .unwrap();
+            assert_eq!(data, vec![2, 3]);
+        }
+    }
+
+    #[test]
+    fn abort() {
+        for layers in [Layers::EMPTY, Layers::DEFAULT].iter() {
+            let key = StaticSecret::new(&mut ChaChaRng::seed_from_u64(0));
+            let mut config = ArchiveWriterConfig::new();
+            config
+                .set_layers(*layers)
+                .add_public_keys(&[PublicKey::from(&key)]);
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            mla.add_file("complete", 3, [1, 2, 3].as_ref()).unwrap();
+            // Abort while a file is opened
+            let id = mla.start_file("partial").unwrap();
+            mla.append_file_content(id, 3, [1, 2, 3].as_ref()).unwrap();
+            let mut dest = mla.abort();
+
+            assert!(dest.starts_with(MLA_MAGIC));
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            assert!(ArchiveReader::from_config(Cursor::new(&dest), config).is_err());
+
+            // The writer can be reused
+            dest.clear();
+            let mut mla = ArchiveWriter::from_config(dest, ArchiveWriterConfig::new()).unwrap();
+            mla.add_file("file", 1, [4].as_ref()).unwrap();
+            mla.finalize().unwrap();
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(mla.into_raw()), ArchiveReaderConfig::new())
+                    .unwrap();
+            assert_eq!(mla_read.list_files().unwrap().count(), 1);
+            assert!(mla_read.get_file("file").unwrap().is_some());
+        }
+    }
+
+    #[test]
+    fn into_inner() {
+        let write = |mla: &mut ArchiveWriter<Cursor<Vec<u8>>>| {
+            mla.add_file("file", 3, [1, 2, 3].as_ref()).unwrap();
+        };
+        let mut mla =
+            ArchiveWriter::from_config(Cursor::new(Vec::new()), ArchiveWriterConfig::new())
+                .unwrap();
+        write(&mut mla);
+        // Finalized by `into_inner`
+        let mut cursor = mla.into_inner().unwrap();
+        let mut mla =
+            ArchiveWriter::from_config(Cursor::new(Vec::new()), ArchiveWriterConfig::new())
+                .unwrap();
+        write(&mut mla);
+        mla.finalize().unwrap();
+        // Already finalized
+        let mut expected = mla.into_inner().unwrap().into_inner();
+        // Archives only differ by their random identifier, after the magic,
+        // the format version and the empty comment length
+        let id_range = 15..15 + ARCHIVE_ID_SIZE;
+        expected[id_range.clone()].copy_from_slice(&cursor.get_ref()[id_range]);
+        assert_eq!(cursor.get_ref(), &expected);
+
+        // Unfinished archives can't be finalized
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        mla.start_file("unfinished").unwrap();
+        assert!(matches!(mla.into_inner(), Err(Error::WrongWriterState(_))));
+
+        // Once read, the reader gives back the archive data. The archive is
+        // read from the current position
+        cursor.set_position(0);
+        let mut mla_read = ArchiveReader::from_config(cursor, ArchiveReaderConfig::new()).unwrap();
+        let mut content = Vec::new();
+        let mut file = mla_read.get_file("file").unwrap().unwrap();
+        file.data.read_to_end(&mut content).unwrap();
+        assert_eq!(content, vec![1, 2, 3]);
+        assert_eq!(mla_read.into_inner().into_inner(), expected);
+    }
+
+    #[test]
+    fn list_files_sorted() {
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::COMPRESS);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        // Contents written in several parts, some interleaved
+        let parts: [(&str, &[u64]); 4] = [
+            ("zèbre", &[10, 20]),
+            ("Zoé", &[300]),
+            ("arbre", &[5, 5, 5]),
+            ("été", &[]),
+        ];
+        let mut total = 0;
+        let ids: Vec<ArchiveFileID> = parts
+            .iter()
+            .map(|(name, _)| mla.start_file(name).unwrap())
+            .collect();
+        for (id, (_, lengths)) in ids.iter().zip(parts.iter()) {
+            for length in lengths.iter() {
+                let data = vec![1u8; *length as usize];
+                mla.append_file_content(*id, *length, data.as_slice())
+                    .unwrap();
+                total += length;
+            }
+        }
+        for id in ids {
+            mla.end_file(id).unwrap();
+        }
         mla.finalize().unwrap();
 
-        let mla_key = *mla.config.encryption_key();
-        let mla_nonce = *mla.config.encryption_nonce();
-        let dest = mla.into_raw();
-        let buf = Cursor::new(dest.as_slice());
-        let mut config = ArchiveReaderConfig::new();
-        config.add_private_keys(std::slice::from_ref(&key));
-        let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+        let mla_read =
+            ArchiveReader::from_config(Cursor::new(mla.into_raw()), ArchiveReaderConfig::new())
+                .unwrap();
+        let names = |order| {
+            mla_read
+                .list_files_sorted(order)
+                .unwrap()
+                .into_iter()
+                .map(|file| file.name)
+                .collect::<Vec<String>>()
+        };
+        // Code point order: uppercase letters, then lowercase ones, then
+        // accented ones
+        assert_eq!(names(SortOrder::ByName), ["Zoé", "arbre", "zèbre", "été"]);
         assert_eq!(
-            (mla_key, mla_nonce),
-            mla_read.config.get_encrypt_parameters().unwrap()
+            names(SortOrder::ByInsertionOrder),
+            ["zèbre", "Zoé", "arbre", "été"]
+        );
+        let by_size = mla_read.list_files_sorted(SortOrder::BySize).unwrap();
+        assert_eq!(
+            by_size,
+            vec![
+                FileSummary {
+                    name: "Zoé".to_string(),
+                    size: Some(300)
+                },
+                FileSummary {
+                    name: "zèbre".to_string(),
+                    size: Some(30)
+                },
+                FileSummary {
+                    name: "arbre".to_string(),
+                    size: Some(15)
+                },
+                FileSummary {
+                    name: "été".to_string(),
+                    size: Some(0)
+                },
+            ]
+        );
+        assert_eq!(
+            by_size.iter().map(|file| file.size.unwrap()).sum::<u64>(),
+            total
         );
-
-        let mut file = mla_read.get_file("my_file".to_string()).unwrap().unwrap();
-        let mut rez = Vec::new();
-        file.data.read_to_end(&mut rez).unwrap();
-        assert_eq!(rez, vec![1, 2, 3, 4]);
-        // Explicit drop here, because otherwise mla_read.get_file() cannot be
-        // recall. It is not detected by the NLL analysis
-        drop(file);
-        let mut file2 = mla_read.get_file("my_file2".to_string()).unwrap().unwrap();
-        let mut rez2 = Vec::new();
-        file2.data.read_to_end(&mut rez2).unwrap();
-        assert_eq!(rez2, vec![5, 6, 7, 8, 9, 10, 11, 12]);
     }
 
-    #[allow(clippy::type_complexity)]
-    pub(crate) fn build_archive(
-        layers: Option<Layers>,
-        interleaved: bool,
-    ) -> (
-        ArchiveWriter<'static, Vec<u8>>,
-        StaticSecret,
-        Vec<(String, Vec<u8>)>,
-    ) {
-        // Build an archive with 3 files
-        let file = Vec::new();
-        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+    #[test]
+    fn encrypted_stream_over_channel() {
+        // Sink sending each write to another thread, as a socket would
+        struct ChannelWriter(std::sync::mpsc::SyncSender<Vec<u8>>);
+
+        impl Write for ChannelWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.send(buf.to_vec()).map_err(io::Error::other)?;
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // The writer blocks until the reader has received the previous write
+        let (sender, receiver) = std::sync::mpsc::sync_channel::<Vec<u8>>(0);
+        let (progress_sender, progress) = std::sync::mpsc::channel();
+        let reader = std::thread::spawn(move || {
+            let mut archive = Vec::new();
+            for data in receiver {
+                archive.extend_from_slice(&data);
+                progress_sender.send(archive.len() as u64).unwrap();
+            }
+            archive
+        });
+
         let mut rng = ChaChaRng::seed_from_u64(0);
         let key = StaticSecret::new(&mut rng);
         let mut config = ArchiveWriterConfig::new();
         config
-            .set_layers(layers.unwrap_or_default())
+            .set_layers(Layers::ENCRYPT)
             .add_public_keys(&[PublicKey::from(&key)]);
-        let mut mla = ArchiveWriter::from_config(file, config).expect("Writer init failed");
-
-        let fname1 = "my_file1".to_string();
-        let fname2 = "my_file2".to_string();
-        let fname3 = "my_file3".to_string();
-        let fake_file_part1 = vec![1, 2, 3];
-        let fake_file_part2 = vec![4, 5, 6, 7, 8];
-        let mut fake_file1 = Vec::new();
-        fake_file1.extend_from_slice(fake_file_part1.as_slice());
-        fake_file1.extend_from_slice(fake_file_part2.as_slice());
-        let fake_file2 = vec![9, 10, 11, 12];
-        let fake_file3 = vec![13, 14, 15];
-
-        if interleaved {
-            // Interleaved writes, expected result is:
-            // [File1 start]
-            // [File1 content 1 2 3]
-            // [File2 start]
-            // [File2 content 9 10 11 12]
-            // [File3 start]
-            // [File3 content 13 14 15]
-            // [File3 end]
-            // [File1 content 4 5 6 7 8]
-            // [File1 end]
-            // [File2 end]
-            let id_file1 = mla.start_file(&fname1).unwrap();
-            mla.append_file_content(
-                id_file1,
-                fake_file_part1.len() as u64,
-                fake_file_part1.as_slice(),
-            )
-            .unwrap();
-            let id_file2 = mla.start_file(&fname2).unwrap();
-            mla.append_file_content(id_file2, fake_file2.len() as u64, fake_file2.as_slice())
-                .unwrap();
-            mla.add_file(&fname3, fake_file3.len() as u64, fake_file3.as_slice())
-                .unwrap();
-            mla.append_file_content(
-                id_file1,
-                fake_file_part2.len() as u64,
-                fake_file_part2.as_slice(),
-            )
-            .unwrap();
-            mla.end_file(id_file1).unwrap();
-            mla.end_file(id_file2).unwrap();
-        } else {
-            mla.add_file(&fname1, fake_file1.len() as u64, fake_file1.as_slice())
-                .unwrap();
-            mla.add_file(&fname2, fake_file2.len() as u64, fake_file2.as_slice())
+        let mut mla = ArchiveWriter::from_config(ChannelWriter(sender), config).unwrap();
+        let mut files = Vec::new();
+        for index in 0..3 {
+            let mut content = vec![0u8; 300 * 1024];
+            rng.fill_bytes(&mut content);
+            let fname = format!("file{}", index);
+            mla.add_file(&fname, content.len() as u64, content.as_slice())
                 .unwrap();
-            mla.add_file(&fname3, fake_file3.len() as u64, fake_file3.as_slice())
+            files.push((fname, content));
+        }
+
+        // Data are available to the reader before the end of the archive
+        mla.flush().unwrap();
+        let written = mla.raw_written.get();
+        assert!(written > 2 * 300 * 1024);
+        let mut received = 0;
+        while received < written {
+            received = progress
+                .recv_timeout(std::time::Duration::from_secs(10))
                 .unwrap();
         }
+        assert_eq!(received, written);
 
         mla.finalize().unwrap();
+        drop(mla);
+        let archive = reader.join().unwrap();
 
-        (
-            mla,
-            key,
-            vec![
-                (fname1, fake_file1),
-                (fname2, fake_file2),
-                (fname3, fake_file3),
-            ],
-        )
-    }
-
-    #[test]
-    fn interleaved_files() {
-        // Build an archive with 3 interleaved files
-        let (mla, key, files) = build_archive(None, true);
-
-        let dest = mla.into_raw();
-        let buf = Cursor::new(dest.as_slice());
         let mut config = ArchiveReaderConfig::new();
-        config.add_private_keys(std::slice::from_ref(&key));
-        let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
-
-        for (fname, content) in files {
-            let mut file = mla_read.get_file(fname).unwrap().unwrap();
+        config.add_private_keys(&[key]);
+        let mut mla_read = ArchiveReader::from_config(Cursor::new(archive), config).unwrap();
+        for (fname, content) in &files {
             let mut rez = Vec::new();
-            file.data.read_to_end(&mut rez).unwrap();
-            assert_eq!(rez, content);
+            mla_read
+                .get_file(fname)
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut rez)
+                .unwrap();
+            assert_eq!(&rez, content);
         }
     }
 
     #[test]
-    fn mla_multi_layering() {
-        // Test the building-then-reading of a file using different layering
-        // approach
+    fn estimate_output_size() {
+        let key = StaticSecret::from([3; 32]);
+        let configs = || {
+            let mut configs = Vec::new();
+            for layers in [
+                Layers::EMPTY,
+                Layers::COMPRESS,
+                Layers::ENCRYPT,
+                Layers::DEFAULT,
+            ] {
+                let mut config = ArchiveWriterConfig::new();
+                config
+                    .set_layers(layers)
+                    .add_public_keys(&[PublicKey::from(&key)]);
+                configs.push(config);
+            }
+            for algorithm in [
+                CompressionAlgorithm::Brotli { quality: 0 },
+                CompressionAlgorithm::Zstd { level: 3 },
+                CompressionAlgorithm::Lz4,
+            ] {
+                let mut config = ArchiveWriterConfig::new();
+                config
+                    .set_layers(Layers::COMPRESS)
+                    .with_compression_algorithm(algorithm)
+                    .unwrap();
+                configs.push(config);
+            }
+            let mut config = ArchiveWriterConfig::default();
+            config
+                .add_public_keys(&[PublicKey::from(&key)])
+                .sign_with(&SigningKey::from_bytes(&[1; 32]))
+                .enable_file_checksums(ChecksumAlgorithm::Blake3)
+                .encrypted_comment("comment");
+            configs.push(config);
+            configs
+        };
+        let write = |config: ArchiveWriterConfig, files: &[(&str, Vec<u8>)]| {
+            let sizes: Vec<(&str, u64)> = files
+                .iter()
+                .map(|(fname, content)| (*fname, content.len() as u64))
+                .collect();
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            let estimate = mla.estimate_output_size(&sizes).unwrap();
+            for (fname, content) in files {
+                mla.add_file(fname, content.len() as u64, content.as_slice())
+                    .unwrap();
+            }
+            mla.finalize().unwrap();
+            (estimate, mla.into_raw().len() as u64)
+        };
 
-        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        // Compressible content, on several compression blocks
+        let compressible = vec![
+            ("empty", Vec::new()),
+            ("zeroes", vec![0u8; 5 * 1024 * 1024]),
+            ("text", b"mla archive ".repeat(10_000)),
+        ];
+        for config in configs() {
+            let (estimate, actual) = write(config, &compressible);
+            assert!(estimate >= actual, "{} < {}", estimate, actual);
+        }
+
+        // Incompressible content, on several encryption chunks
         let mut rng = ChaChaRng::seed_from_u64(0);
+        let mut random = vec![0u8; 300 * 1024];
+        rng.fill_bytes(&mut random);
+        let incompressible = vec![("random", random), ("byte", vec![42])];
+        for config in configs() {
+            let (estimate, actual) = write(config, &incompressible);
+            assert!(estimate >= actual, "{} < {}", estimate, actual);
+            assert!(estimate <= 2 * actual, "{} > 2 * {}", estimate, actual);
+        }
 
-        for layering in &[
-            Layers::DEBUG,
-            Layers::ENCRYPT,
-            Layers::COMPRESS,
-            Layers::default(),
-        ] {
-            println!("Layering: {:?}", layering);
+        let mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        let too_long = "a".repeat(FILENAME_MAX_SIZE as usize + 1);
+        assert!(matches!(
+            mla.estimate_output_size(&[(&too_long, 0)]),
+            Err(Error::FilenameTooLong)
+        ));
+    }
 
-            // Build initial file in a stream
-            let file = Vec::new();
-            let key = StaticSecret::new(&mut rng);
+    #[test]
+    fn finalize_statistics() {
+        let key = StaticSecret::from([4; 32]);
+        for layers in [Layers::EMPTY, Layers::DEFAULT] {
             let mut config = ArchiveWriterConfig::new();
             config
-                .set_layers(*layering)
-                .add_public_keys(std::slice::from_ref(&PublicKey::from(&key)));
-            let mut mla = ArchiveWriter::from_config(file, config).expect("Writer init failed");
-
-            // Write a file in one part
-            let fake_file = vec![1, 2, 3, 4];
-            mla.add_file("my_file", fake_file.len() as u64, fake_file.as_slice())
-                .unwrap();
-            // Write a file in multiple part
-            let fake_file = vec![5, 6, 7, 8];
-            let fake_file2 = vec![9, 10, 11, 12];
-            let id = mla.start_file("my_file2").unwrap();
-            mla.append_file_content(id, fake_file.len() as u64, fake_file.as_slice())
+                .set_layers(layers)
+                .add_public_keys(&[PublicKey::from(&key)]);
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            let text = b"mla archive ".repeat(10_000);
+            let id1 = mla.start_file("text").unwrap();
+            let id2 = mla.start_file("zeroes").unwrap();
+            mla.append_file_content(id1, 1000, &text[..1000]).unwrap();
+            mla.append_file_content(id2, 5000, [0u8; 5000].as_ref())
                 .unwrap();
-            mla.append_file_content(id, fake_file2.len() as u64, fake_file2.as_slice())
+            mla.append_file_content(id1, text.len() as u64 - 1000, &text[1000..])
                 .unwrap();
-            mla.end_file(id).unwrap();
-            mla.finalize().unwrap();
-
-            // Read the obtained stream
+            mla.end_file(id1).unwrap();
+            mla.end_file(id2).unwrap();
+            mla.add_file("empty", 0, [].as_ref()).unwrap();
+            mla.add_directory("dir", None).unwrap();
+            let stats = mla.finalize().unwrap();
             let dest = mla.into_raw();
-            let buf = Cursor::new(dest.as_slice());
+
+            assert_eq!(stats.files_written, 3);
+            assert_eq!(stats.total_bytes_uncompressed, text.len() as u64 + 5000);
+            assert_eq!(stats.total_bytes_written, dest.len() as u64);
+            assert_eq!(
+                stats.compression_ratio,
+                stats.total_bytes_uncompressed as f64 / dest.len() as f64
+            );
+            if layers == Layers::DEFAULT {
+                assert!(stats.compression_ratio > 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn archive_limits() {
+        let new_writer = |max_bytes: Option<u64>| {
+            // Contents are written at once, for the bytes written to be exact
+            let mut config = ArchiveWriterConfig::new();
+            config
+                .set_layers(Layers::EMPTY)
+                .max_files(2)
+                .coalesce_threshold(0);
+            if let Some(limit) = max_bytes {
+                config.max_bytes(limit);
+            }
+            ArchiveWriter::from_config(Vec::new(), config).unwrap()
+        };
+
+        // Size of the archive once the content of "a" is written, without
+        // layers
+        let mut mla = new_writer(None);
+        let id = mla.start_file("a").unwrap();
+        mla.append_file_content(id, 100, [1u8; 100].as_ref())
+            .unwrap();
+        let limit = mla.raw_written.get();
+
+        // The content fits exactly, no more byte can be added
+        let mut mla = new_writer(Some(limit));
+        let id = mla.start_file("a").unwrap();
+        mla.append_file_content(id, 100, [1u8; 100].as_ref())
+            .unwrap();
+        match mla.append_file_content(id, 1, [2u8].as_ref()) {
+            Err(Error::ArchiveSizeLimitExceeded(value)) => assert_eq!(value, limit),
+            _ => panic!("ArchiveSizeLimitExceeded expected"),
+        }
+        // Nothing has been written
+        assert_eq!(mla.raw_written.get(), limit);
+        mla.end_file(id).unwrap();
+
+        // Files are limited too, directories are not
+        let id = mla.start_file("b").unwrap();
+        mla.end_file(id).unwrap();
+        match mla.start_file("c") {
+            Err(Error::FileCountLimitExceeded(value)) => assert_eq!(value, 2),
+            _ => panic!("FileCountLimitExceeded expected"),
+        }
+        mla.add_directory("dir", None).unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest), ArchiveReaderConfig::new()).unwrap();
+        let mut file = mla_read.get_file("a").unwrap().unwrap();
+        let mut buf = Vec::new();
+        file.data.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, vec![1u8; 100]);
+
+        // With layers, the upper bound of the stored size is used
+        let key = StaticSecret::from([7; 32]);
+        let mut config = ArchiveWriterConfig::default();
+        config
+            .add_public_keys(&[PublicKey::from(&key)])
+            .max_bytes(1000);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let id = mla.start_file("zeroes").unwrap();
+        match mla.append_file_content(id, 1000, [0u8; 1000].as_ref()) {
+            Err(Error::ArchiveSizeLimitExceeded(value)) => assert_eq!(value, 1000),
+            _ => panic!("ArchiveSizeLimitExceeded expected"),
+        }
+        mla.append_file_content(id, 100, [0u8; 100].as_ref())
+            .unwrap();
+    }
+
+    #[test]
+    fn file_recipients() {
+        let mut csprng = ChaChaRng::seed_from_u64(0);
+        let key_a = StaticSecret::new(&mut csprng);
+        let key_b = StaticSecret::new(&mut csprng);
+        let key_manager = StaticSecret::new(&mut csprng);
+        let public_a = PublicKey::from(&key_a);
+        let public_b = PublicKey::from(&key_b);
+        let public_manager = PublicKey::from(&key_manager);
+        let mut content_a = vec![0u8; 200 * 1024];
+        csprng.fill_bytes(&mut content_a);
+        let mut content_b = vec![42u8; 1000];
+        content_b[500..].fill(0);
+
+        let mut config = ArchiveWriterConfig::default();
+        config
+            .add_public_keys(&[public_a, public_b, public_manager])
+            .enable_file_checksums(ChecksumAlgorithm::Blake3)
+            .with_dedup(true);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let id_a = mla
+            .start_file_for_recipients("file_a", &[public_a, public_manager])
+            .unwrap();
+        let id_b = mla
+            .start_file_for_recipients("file_b", &[public_b, public_manager])
+            .unwrap();
+        // Interleaved, with a hole
+        mla.append_file_content(id_a, 1000, &content_a[..1000])
+            .unwrap();
+        mla.append_file_content(id_b, 500, &content_b[..500])
+            .unwrap();
+        mla.append_file_sparse(id_b, 500, &[SparseData::Zeroes(500)])
+            .unwrap();
+        mla.append_file_content(id_a, content_a.len() as u64 - 1000, &content_a[1000..])
+            .unwrap();
+        mla.end_file(id_b).unwrap();
+        mla.end_file(id_a).unwrap();
+        // Not deduplicated with the encrypted one
+        mla.add_file("common", 1000, content_b.as_slice()).unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        let open = |keys: &[StaticSecret]| {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(keys);
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap()
+        };
+        let read = |keys: &[StaticSecret], name: &str| -> Result<Vec<u8>, Error> {
+            let mut mla_read = open(keys);
+            let mut file = mla_read.get_file(name)?.unwrap();
+            assert_eq!(file.size, if name == "file_a" { 200 * 1024 } else { 1000 });
+            let mut content = Vec::new();
+            file.data.read_to_end(&mut content)?;
+            Ok(content)
+        };
+        assert_eq!(
+            read(std::slice::from_ref(&key_a), "file_a").unwrap(),
+            content_a
+        );
+        assert!(matches!(
+            read(std::slice::from_ref(&key_a), "file_b"),
+            Err(Error::PrivateKeyNeeded)
+        ));
+        assert_eq!(
+            read(std::slice::from_ref(&key_b), "file_b").unwrap(),
+            content_b
+        );
+        assert!(matches!(
+            read(std::slice::from_ref(&key_b), "file_a"),
+            Err(Error::PrivateKeyNeeded)
+        ));
+        assert_eq!(
+            read(std::slice::from_ref(&key_manager), "file_a").unwrap(),
+            content_a
+        );
+        assert_eq!(
+            read(std::slice::from_ref(&key_manager), "file_b").unwrap(),
+            content_b
+        );
+        for key in [&key_a, &key_b, &key_manager] {
+            assert_eq!(
+                read(std::slice::from_ref(&key), "common").unwrap(),
+                content_b
+            );
+        }
+
+        // The integrity can be verified without the file keys
+        let mut mla_read = open(std::slice::from_ref(&key_a));
+        assert!(mla_read
+            .features()
+            .contains(FeatureFlags::HAS_FILE_RECIPIENTS));
+        let report = mla_read.verify_integrity().unwrap();
+        assert_eq!(report.files_ok, 3);
+        assert!(report.files_with_errors.is_empty());
+        // Their content can't be sought
+        assert!(matches!(
+            mla_read.get_file_seekable("file_a"),
+            Err(Error::WrongReaderState(_))
+        ));
+
+        // Linear extraction and entries decrypt the files too
+        let fnames = ["file_a".to_string(), "file_b".to_string()];
+        let mut export: HashMap<&String, Vec<u8>> =
+            fnames.iter().map(|fname| (fname, Vec::new())).collect();
+        assert!(matches!(
+            helpers::linear_extract(&mut mla_read, &mut export),
+            Err(Error::PrivateKeyNeeded)
+        ));
+        let mut mla_read = open(std::slice::from_ref(&key_manager));
+        let mut export: HashMap<&String, Vec<u8>> =
+            fnames.iter().map(|fname| (fname, Vec::new())).collect();
+        helpers::linear_extract(&mut mla_read, &mut export).unwrap();
+        assert_eq!(export[&fnames[0]], content_a);
+        assert_eq!(export[&fnames[1]], content_b);
+        let mut mla_read = open(&[key_b]);
+        for entry in mla_read.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let mut content = Vec::new();
+            match entry.filename.as_str() {
+                "file_a" => assert!(entry.read_to_end(&mut content).is_err()),
+                _ => {
+                    entry.read_to_end(&mut content).unwrap();
+                    assert_eq!(content, content_b);
+                }
+            }
+        }
+
+        // Repairing keeps the files encrypted for their own recipients
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&[key_manager]);
+        let mut mla_fsread = ArchiveFailSafeReader::from_config(dest.as_slice(), config).unwrap();
+        let mut config = ArchiveWriterConfig::default();
+        config.add_public_keys(&[public_a]);
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        assert!(matches!(
+            mla_fsread.convert_to_archive(&mut mla_w).unwrap(),
+            FailSafeReadError::EndOfOriginalArchiveData
+        ));
+        let repaired = mla_w.into_raw();
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&[key_a]);
+        let mut mla_read = ArchiveReader::from_config(Cursor::new(repaired), config).unwrap();
+        let mut content = Vec::new();
+        let mut file = mla_read.get_file("file_a").unwrap().unwrap();
+        file.data.read_to_end(&mut content).unwrap();
+        assert_eq!(content, content_a);
+        assert!(matches!(
+            mla_read.get_file("file_b"),
+            Err(Error::PrivateKeyNeeded)
+        ));
+
+        // The archive itself must be encrypted
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        assert!(matches!(
+            mla.start_file_for_recipients("file", &[public_a]),
+            Err(Error::BadAPIArgument(_))
+        ));
+    }
+
+    #[test]
+    fn stream_reader() {
+        let key = StaticSecret::from([5; 32]);
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let mut big = vec![0u8; 300 * 1024];
+        rng.fill_bytes(&mut big);
+        let files = vec![
+            ("big".to_string(), big),
+            ("small".to_string(), b"small".to_vec()),
+            ("empty".to_string(), Vec::new()),
+        ];
+        let writer_config = move |layers: Layers| {
+            let mut config = ArchiveWriterConfig::new();
+            config
+                .set_layers(layers)
+                .add_public_keys(&[PublicKey::from(&StaticSecret::from([5; 32]))]);
+            if layers.contains(Layers::SIGN) {
+                config.sign_with(&SigningKey::from_bytes(&[6; 32]));
+            }
+            config
+        };
+        let reader_config = || {
             let mut config = ArchiveReaderConfig::new();
             config.add_private_keys(std::slice::from_ref(&key));
-            let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+            config
+        };
 
-            let mut file = mla_read.get_file("my_file".to_string()).unwrap().unwrap();
-            let mut rez = Vec::new();
-            file.data.read_to_end(&mut rez).unwrap();
-            assert_eq!(rez, vec![1, 2, 3, 4]);
-            // Explicit drop here, because otherwise mla_read.get_file() cannot be
-            // recall. It is not detected by the NLL analysis
-            drop(file);
-            let mut file2 = mla_read.get_file("my_file2".to_string()).unwrap().unwrap();
+        for layers in [
+            Layers::EMPTY,
+            Layers::COMPRESS,
+            Layers::DEFAULT,
+            Layers::ENCRYPT | Layers::SIGN,
+            Layers::all() - Layers::PUBLIC_LISTING,
+        ] {
+            // Pipe the writer output directly into the stream reader
+            let (mut pipe_reader, pipe_writer) = io::pipe().unwrap();
+            let to_write = files.clone();
+            let writer = std::thread::spawn(move || {
+                let mut mla =
+                    ArchiveWriter::from_config(pipe_writer, writer_config(layers)).unwrap();
+                mla.add_directory("dir", None).unwrap();
+                for (fname, content) in &to_write {
+                    mla.add_file(fname, content.len() as u64, content.as_slice())
+                        .unwrap();
+                }
+                mla.finalize().unwrap();
+            });
+            let mla_read =
+                ArchiveReader::from_stream_config(&mut pipe_reader, reader_config()).unwrap();
+            let mut count = 0;
+            for (entry, (fname, content)) in mla_read
+                .entries()
+                .filter(|entry| !matches!(entry, Ok(e) if e.kind != EntryKind::File))
+                .zip(&files)
+            {
+                let mut entry = entry.unwrap();
+                assert_eq!(&entry.filename, fname);
+                let mut buf = Vec::new();
+                entry.read_to_end(&mut buf).unwrap();
+                assert_eq!(&buf, content);
+                count += 1;
+            }
+            assert_eq!(count, files.len());
+            // Consume the footer, so the writer doesn't fail on a closed pipe
+            io::copy(&mut pipe_reader, &mut io::sink()).unwrap();
+            writer.join().unwrap();
+        }
 
-            // Read the file in 2 blocks: 6, then 2 bytes (it is made of two 4-bytes block)
-            let mut rez2 = [0u8; 6];
-            file2.data.read_exact(&mut rez2).unwrap();
-            assert_eq!(rez2, [5, 6, 7, 8, 9, 10]);
-            let mut rez3 = Vec::new();
-            let mut final_rez = Vec::new();
-            file2.data.read_to_end(&mut rez3).unwrap();
-            final_rez.extend(&rez2);
-            final_rez.extend(rez3);
-            assert_eq!(final_rez, vec![5, 6, 7, 8, 9, 10, 11, 12]);
+        // Linear extraction, from an in-memory, non-seekable, source
+        let mut mla =
+            ArchiveWriter::from_config(Vec::new(), writer_config(Layers::DEFAULT)).unwrap();
+        for (fname, content) in &files {
+            mla.add_file(fname, content.len() as u64, content.as_slice())
+                .unwrap();
         }
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+        let mut export: HashMap<&String, Vec<u8>> = HashMap::new();
+        export.insert(&files[1].0, Vec::new());
+        export.insert(&files[0].0, Vec::new());
+        ArchiveReader::from_stream_config(dest.as_slice(), reader_config())
+            .unwrap()
+            .linear_extract(&mut export)
+            .unwrap();
+        assert_eq!(export[&files[0].0], files[0].1);
+        assert_eq!(export[&files[1].0], files[1].1);
+
+        // Altered encrypted data is detected
+        let mut altered = dest.clone();
+        let index = altered.len() / 2;
+        altered[index] ^= 1;
+        let result = ArchiveReader::from_stream_config(altered.as_slice(), reader_config())
+            .unwrap()
+            .linear_extract(&mut export);
+        assert!(result.is_err());
+
+        // The signature can't be verified
+        let mut config = reader_config();
+        config.verify_with(&VerifyingKey::from(&SigningKey::from_bytes(&[6; 32])));
+        assert!(matches!(
+            ArchiveReader::from_stream_config(dest.as_slice(), config),
+            Err(Error::BadAPIArgument(_))
+        ));
     }
 
     #[test]
-    fn list_and_read_files() {
-        // Build an archive with 3 files
-        let (mla, key, files) = build_archive(None, false);
-
-        let dest = mla.into_raw();
-        let buf = Cursor::new(dest.as_slice());
+    fn get_file_size() {
+        let (mla, key, files) = build_archive(None, true);
         let mut config = ArchiveReaderConfig::new();
         config.add_private_keys(std::slice::from_ref(&key));
-        let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
-
-        // Check the list of files is correct
-        let mut sorted_list: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
-        sorted_list.sort();
-        assert_eq!(
-            sorted_list,
-            files
-                .iter()
-                .map(|(x, _y)| x.clone())
-                .collect::<Vec<String>>(),
-        );
+        let mut mla_read = ArchiveReader::from_config(Cursor::new(mla.into_raw()), config).unwrap();
 
-        // Get and check file per file, not in the writing order
-        for (fname, content) in files.iter().rev() {
-            let mut mla_file = mla_read.get_file(fname.clone()).unwrap().unwrap();
-            assert_eq!(mla_file.filename, fname.clone());
-            let mut buf = Vec::new();
-            mla_file.data.read_to_end(&mut buf).unwrap();
-            assert_eq!(&buf, content);
+        let mut sizes = mla_read.list_files_with_sizes().unwrap();
+        sizes.sort();
+        let mut expected: Vec<(String, Option<u64>)> = Vec::new();
+        for (fname, content) in files.iter() {
+            let mut file = mla_read.get_file(fname).unwrap().unwrap();
+            let extracted = io::copy(&mut file.data, &mut io::sink()).unwrap();
+            assert_eq!(extracted, content.len() as u64);
+            assert_eq!(mla_read.get_file_size(fname).unwrap(), Some(extracted));
+            expected.push((fname.clone(), Some(extracted)));
         }
+        expected.sort();
+        assert_eq!(sizes, expected);
+        assert_eq!(mla_read.get_file_size("unknown").unwrap(), None);
     }
 
     #[test]
-    fn convert_failsafe() {
-        // Build an archive with 3 files
-        let (mla, key, files) = build_archive(None, false);
-
-        // Prepare the failsafe reader
-        let dest = mla.into_raw();
+    fn get_all_files() {
+        let (mla, key, files) = build_archive(None, true);
         let mut config = ArchiveReaderConfig::new();
         config.add_private_keys(std::slice::from_ref(&key));
-        let mut mla_fsread = ArchiveFailSafeReader::from_config(dest.as_slice(), config).unwrap();
+        let mut mla_read = ArchiveReader::from_config(Cursor::new(mla.into_raw()), config).unwrap();
 
-        // Prepare the writer
-        let dest_w = Vec::new();
+        let all_files = mla_read.get_all_files().unwrap();
+        assert_eq!(all_files.len(), files.len());
+        for (fname, content) in files.iter() {
+            let mut file = mla_read.get_file(fname).unwrap().unwrap();
+            let mut rez = Vec::new();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(&rez, content);
+            assert_eq!(all_files.get(fname), Some(content));
+        }
+    }
+
+    #[test]
+    fn precompressed_content() {
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let mut content = vec![0u8; 200 * 1024];
+        rng.fill_bytes(&mut content[..1024]);
+        let compressed = zstd::encode_all(content.as_slice(), 3).unwrap();
+
+        let key = StaticSecret::from([2; 32]);
         let mut config = ArchiveWriterConfig::new();
         config
-            .enable_layer(Layers::COMPRESS)
-            .enable_layer(Layers::ENCRYPT)
-            .add_public_keys(&[PublicKey::from(&key)]);
-        let mut mla_w = ArchiveWriter::from_config(dest_w, config).expect("Writer init failed");
-
-        // Conversion
-        match mla_fsread.convert_to_archive(&mut mla_w).unwrap() {
-            FailSafeReadError::EndOfOriginalArchiveData => {
-                // We expect to ends with the final tag - all files have been
-                // read and we stop on the tag before the footer
-            }
-            status => {
-                panic!("Unexpected status: {}", status);
-            }
-        };
+            .add_public_keys(&[PublicKey::from(&key)])
+            .enable_file_checksums(ChecksumAlgorithm::Blake3)
+            .accept_pre_compressed(true);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        // Interleave a file compressed by the caller with the same content,
+        // compressed by the archive
+        let id_pre = mla.start_file("precompressed").unwrap();
+        let id_reg = mla.start_file("regular").unwrap();
+        mla.append_file_content_precompressed(
+            id_pre,
+            compressed.len() as u64,
+            compressed.as_slice(),
+            CompressionAlgorithm::Zstd { level: 3 },
+        )
+        .unwrap();
+        mla.append_file_content(id_reg, content.len() as u64, content.as_slice())
+            .unwrap();
+        // Pre-compressed and regular contents can be mixed in a file
+        mla.append_file_content(id_pre, 4, &[1, 2, 3, 4][..])
+            .unwrap();
+        mla.append_file_content(id_reg, 4, &[1, 2, 3, 4][..])
+            .unwrap();
+        // Invalid usages
+        assert!(matches!(
+            mla.append_file_content_precompressed(
+                id_pre,
+                4,
+                &[1, 2, 3, 4][..],
+                CompressionAlgorithm::None
+            ),
+            Err(Error::BadAPIArgument(_))
+        ));
+        assert!(matches!(
+            mla.append_file_content_precompressed(
+                id_pre,
+                4,
+                &[1, 2, 3, 4][..],
+                CompressionAlgorithm::Zstd { level: 3 }
+            ),
+            Err(Error::BadAPIArgument(_))
+        ));
+        mla.end_file(id_pre).unwrap();
+        mla.end_file(id_reg).unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+        content.extend_from_slice(&[1, 2, 3, 4]);
 
-        // New archive can now be checked
-        let dest2 = mla_w.into_raw();
-        let buf2 = Cursor::new(dest2.as_slice());
         let mut config = ArchiveReaderConfig::new();
         config.add_private_keys(std::slice::from_ref(&key));
-        let mut mla_read = ArchiveReader::from_config(buf2, config).unwrap();
-
-        // Check the list of files is correct
-        let mut sorted_list: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
-        sorted_list.sort();
-        assert_eq!(
-            sorted_list,
-            files
-                .iter()
-                .map(|(x, _y)| x.clone())
-                .collect::<Vec<String>>(),
-        );
-
-        // Get and check file per file, not in the writing order
-        for (fname, content) in files.iter().rev() {
-            let mut mla_file = mla_read.get_file(fname.clone()).unwrap().unwrap();
-            assert_eq!(mla_file.filename, fname.clone());
-            let mut buf = Vec::new();
-            mla_file.data.read_to_end(&mut buf).unwrap();
-            assert_eq!(&buf, content);
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), config.clone()).unwrap();
+        assert!(mla_read
+            .features()
+            .contains(FeatureFlags::HAS_PRECOMPRESSED));
+        for fname in ["precompressed", "regular"] {
+            let mut file = mla_read.get_file(fname).unwrap().unwrap();
+            let mut rez = Vec::new();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(rez, content);
+            assert_eq!(
+                mla_read.get_file_size(fname).unwrap(),
+                Some(content.len() as u64)
+            );
         }
+        let all_files = mla_read.get_all_files().unwrap();
+        assert_eq!(all_files["precompressed"], content);
+        assert_eq!(all_files["regular"], content);
+        // As files are interleaved, only the first entry can be read entirely
+        let mut entry = mla_read.entries().unwrap().next().unwrap().unwrap();
+        assert_eq!(entry.filename, "precompressed");
+        let mut rez = Vec::new();
+        entry.data.read_to_end(&mut rez).unwrap();
+        assert_eq!(rez, content);
+        drop(entry);
+        let report = mla_read.verify_integrity().unwrap();
+        assert_eq!(report.files_ok, 2);
+        assert!(report.files_with_errors.is_empty());
+
+        // Fail-safe conversion stores the content as a regular one
+        let mut mla_fsread = ArchiveFailSafeReader::from_config(dest.as_slice(), config).unwrap();
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new())
+            .expect("Writer init failed");
+        assert!(matches!(
+            mla_fsread.convert_to_archive(&mut mla_w).unwrap(),
+            FailSafeReadError::EndOfOriginalArchiveData
+        ));
+        let repaired = mla_w.into_raw();
+        let mut mla_read = ArchiveReader::from_config(
+            Cursor::new(repaired.as_slice()),
+            ArchiveReaderConfig::new(),
+        )
+        .unwrap();
+        assert!(!mla_read
+            .features()
+            .contains(FeatureFlags::HAS_PRECOMPRESSED));
+        let mut file = mla_read.get_file("precompressed").unwrap().unwrap();
+        let mut rez = Vec::new();
+        file.data.read_to_end(&mut rez).unwrap();
+        assert_eq!(rez, content);
+
+        // Pre-compressed contents must be explicitly accepted
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        let id = mla.start_file("file").unwrap();
+        assert!(matches!(
+            mla.append_file_content_precompressed(
+                id,
+                compressed.len() as u64,
+                compressed.as_slice(),
+                CompressionAlgorithm::Zstd { level: 3 }
+            ),
+            Err(Error::BadAPIArgument(_))
+        ));
     }
 
-    #[test]
-    fn convert_trunc_failsafe() {
-        for interleaved in &[false, true] {
-            // Build an archive with 3 files, without compressing to truncate at the correct place
-            let (mla, key, files) =
-                build_archive(Some(Layers::default() ^ Layers::COMPRESS), *interleaved);
-            // Truncate the resulting file (before the footer, hopefully after the header), and prepare the failsafe reader
-            let footer_size = bincode::serialized_size(&mla.files_info).unwrap() as usize + 4;
-            let dest = mla.into_raw();
-
-            for remove in &[1, 10, 30, 50, 70, 95, 100] {
-                let mut config = ArchiveReaderConfig::new();
-                config.add_private_keys(std::slice::from_ref(&key));
-                let mut mla_fsread = ArchiveFailSafeReader::from_config(
-                    &dest[..dest.len() - footer_size - remove],
-                    config,
-                )
-                .expect("Unable to create");
-
-                // Prepare the writer
-                let dest_w = Vec::new();
-                let mut mla_w = ArchiveWriter::new(dest_w, &[PublicKey::from(&key)])
-                    .expect("Writer init failed");
-
-                // Conversion
-                let _status = mla_fsread.convert_to_archive(&mut mla_w).unwrap();
-
-                // New archive can now be checked
-                let dest2 = mla_w.into_raw();
-                let buf2 = Cursor::new(dest2.as_slice());
-                let mut config = ArchiveReaderConfig::new();
-                config.add_private_keys(std::slice::from_ref(&key));
-                let mut mla_read = ArchiveReader::from_config(buf2, config).unwrap();
-
-                // Check *the start of* the files list is correct
-                let expected = files
-                    .iter()
-                    .map(|(x, _y)| x.clone())
-                    .collect::<Vec<String>>();
-                let mut file_list = mla_read
-                    .list_files()
-                    .unwrap()
-                    .cloned()
-                    .collect::<Vec<String>>();
-                file_list.sort();
-                assert_eq!(
-                    file_list[..],
-                    expected[..file_list.len()],
-                    "File lists not equal {} interleaving and {} bytes removed",
-                    if *interleaved { "with" } else { "without" },
-                    remove
-                );
+    /// Read + Seek wrapper counting the bytes read
+    struct CountingReader<T> {
+        inner: T,
+        read: Rc<RefCell<usize>>,
+    }
 
-                // Get and check file per file, not in the writing order
-                for (fname, content) in files.iter().rev() {
-                    // The file may be missing
-                    let mut mla_file = match mla_read.get_file(fname.clone()).unwrap() {
-                        Some(mla_file) => mla_file,
-                        None => continue,
-                    };
-                    // If the file is present, ensure there are bytes and the first
-                    // bytes are the same
-                    assert_eq!(mla_file.filename, fname.clone());
-                    let mut buf = Vec::new();
-                    mla_file.data.read_to_end(&mut buf).unwrap();
-                    assert_ne!(
-                        buf.len(),
-                        0,
-                        "Read 0 bytes from subfile {} {} interleaving and {} bytes removed",
-                        mla_file.filename,
-                        if *interleaved { "with" } else { "without" },
-                        remove
-                    );
-                    assert_eq!(&buf[..], &content[..buf.len()]);
-                }
-            }
-            // /!\ This test doesn't ensure the code is doing the best effort; it only check the result is correct.
+    impl<T: Read> Read for CountingReader<T> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let count = self.inner.read(buf)?;
+            *self.read.borrow_mut() += count;
+            Ok(count)
         }
     }
 
-    #[test]
-    fn avoid_duplicate_filename() {
-        let buf = Vec::new();
-        let config = ArchiveWriterConfig::new();
-        let mut mla = ArchiveWriter::from_config(buf, config).unwrap();
-        mla.add_file("Test", 4, vec![1, 2, 3, 4].as_slice())
-            .unwrap();
-        assert!(mla
-            .add_file("Test", 4, vec![1, 2, 3, 4].as_slice())
-            .is_err());
-        assert!(mla.start_file("Test").is_err());
+    impl<T: Seek> Seek for CountingReader<T> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
     }
 
     #[test]
-    fn check_file_size() {
-        // Build an archive with 3 non-interleaved files and another with
-        // interleaved files
-        for interleaved in &[false, true] {
-            let (mla, key, files) = build_archive(None, *interleaved);
-
-            for (fname, data) in &files {
-                let id = mla.files_info.get(fname).unwrap();
-                let size = mla.ids_info.get(id).unwrap().size;
-                assert_eq!(size, data.len() as u64);
-            }
-
-            let dest = mla.into_raw();
-            let buf = Cursor::new(dest.as_slice());
-            let mut config = ArchiveReaderConfig::new();
-            config.add_private_keys(std::slice::from_ref(&key));
-            let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+    fn get_file_without_scan() {
+        // The footer is the archive index: looking up a file only reads its
+        // own blocks, whatever the number of files in the archive
+        const FILE_COUNT: usize = 10_000;
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        for i in 0..FILE_COUNT {
+            mla.add_file(format!("file_{}", i), 8, [i as u8; 8].as_ref())
+                .unwrap();
+        }
+        mla.finalize().unwrap();
+        let archive = mla.into_raw();
 
-            for (fname, data) in &files {
-                let mla_file = mla_read.get_file(fname.to_string()).unwrap().unwrap();
-                assert_eq!(mla_file.size, data.len() as u64);
-            }
+        let read = Rc::new(RefCell::new(0));
+        let src = CountingReader {
+            inner: Cursor::new(archive.as_slice()),
+            read: read.clone(),
+        };
+        let mut mla_read = ArchiveReader::from_config(src, ArchiveReaderConfig::new()).unwrap();
+        for i in [0, FILE_COUNT / 2, FILE_COUNT - 1].iter() {
+            *read.borrow_mut() = 0;
+            let mut file = mla_read.get_file(format!("file_{}", i)).unwrap().unwrap();
+            let mut rez = Vec::new();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(rez, vec![*i as u8; 8]);
+            // Blocks of the file only, far less than the archive
+            assert!(*read.borrow() < 512);
         }
+        assert!(archive.len() > 100 * 512);
     }
 
     #[test]
-    fn failsafe_detect_integrity() {
-        // Build an archive with 3 files
-        let (mla, _key, files) = build_archive(Some(Layers::DEBUG), false);
-
-        // Swap the first 2 bytes of file1
-        let mut dest = mla.into_raw();
-        let expect = files[0].1.as_slice();
-        let pos: Vec<usize> = dest
-            .iter()
-            .enumerate()
-            .filter_map(|e| {
-                if e.0 + expect.len() < dest.len() && &dest[e.0..e.0 + expect.len()] == expect {
-                    Some(e.0)
-                } else {
-                    None
-                }
-            })
-            .collect();
-        dest.swap(pos[0], pos[0] + 1);
-
-        // Prepare the failsafe reader
-        let mut mla_fsread =
-            ArchiveFailSafeReader::from_config(dest.as_slice(), ArchiveReaderConfig::new())
+    fn file_exists() {
+        const FILE_COUNT: usize = 1000;
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        for i in 0..FILE_COUNT {
+            mla.add_file(format!("file_{}", i), 64, [i as u8; 64].as_ref())
                 .unwrap();
+        }
+        mla.finalize().unwrap();
+        let archive = mla.into_raw();
 
-        // Prepare the writer
-        let dest_w = Vec::new();
-        let mut mla_w = ArchiveWriter::from_config(dest_w, ArchiveWriterConfig::new())
-            .expect("Writer init failed");
+        let read = Rc::new(RefCell::new(0));
+        let src = CountingReader {
+            inner: Cursor::new(archive.as_slice()),
+            read: read.clone(),
+        };
+        let mut mla_read = ArchiveReader::from_config(src, ArchiveReaderConfig::new()).unwrap();
+        let target = format!("file_{}", FILE_COUNT - 1);
+
+        // With the footer, nothing is read, unlike `get_file`
+        *read.borrow_mut() = 0;
+        assert!(mla_read.file_exists(&target).unwrap());
+        assert!(!mla_read.file_exists("unknown").unwrap());
+        assert!(mla_read.contains_all(&["file_0", &target]).unwrap());
+        assert!(!mla_read.contains_all(&["file_0", "unknown"]).unwrap());
+        assert_eq!(*read.borrow(), 0);
+        assert!(mla_read.get_file(&target).unwrap().is_some());
+        assert!(*read.borrow() > 0);
+
+        // Without footer, file contents are skipped, and the scan stops on the
+        // last file looked for
+        mla_read.metadata = None;
+        *read.borrow_mut() = 0;
+        assert!(mla_read.file_exists("file_10").unwrap());
+        assert!(*read.borrow() < archive.len() / 2);
+        assert!(mla_read.contains_all(&["file_0", &target]).unwrap());
+        assert!(!mla_read.file_exists("unknown").unwrap());
+        assert!(!mla_read.contains_all(&["file_0", "unknown"]).unwrap());
+        assert!(mla_read.contains_all(&[]).unwrap());
+    }
 
-        // Conversion
-        match mla_fsread.convert_to_archive(&mut mla_w).unwrap() {
-            FailSafeReadError::UnfinishedFiles {
-                filenames,
-                stopping_error,
-            } => {
-                // We expect to ends with a HashDiffers on first file
-                assert_eq!(filenames, vec![files[0].0.to_string()]);
-                match *stopping_error {
-                    FailSafeReadError::HashDiffers { .. } => {}
-                    _ => {
-                        panic!("Unexpected stopping_error: {}", stopping_error);
-                    }
-                }
-            }
-            status => {
-                panic!("Unexpected status: {}", status);
-            }
+    #[test]
+    fn file_count() {
+        let mut config = ArchiveWriterConfig::new();
+        config.enable_layer(Layers::COMPRESS);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let id = mla.start_file("a").unwrap();
+        mla.set_file_size_hint(id, 3).unwrap();
+        mla.append_file_content(id, 3, &b"abc"[..]).unwrap();
+        mla.end_file(id).unwrap();
+        let id = mla.start_file("b").unwrap();
+        mla.set_file_size_hint(id, 1000).unwrap();
+        mla.append_file_content(id, 1000, vec![1u8; 1000].as_slice())
+            .unwrap();
+        mla.end_file(id).unwrap();
+        mla.rename_file("a", "c").unwrap();
+        mla.finalize().unwrap();
+        let archive = mla.into_raw();
+
+        let open = || {
+            let read = Rc::new(RefCell::new(0));
+            let src = CountingReader {
+                inner: Cursor::new(archive.as_slice()),
+                read: read.clone(),
+            };
+            let mla_read = ArchiveReader::from_config(src, ArchiveReaderConfig::new()).unwrap();
+            (mla_read, read)
         };
+
+        // From the footer, nothing is read
+        let (mut mla_read, read) = open();
+        *read.borrow_mut() = 0;
+        assert_eq!(mla_read.file_count().unwrap(), 3);
+        assert_eq!(mla_read.total_uncompressed_size().unwrap(), Some(1006));
+        assert_eq!(*read.borrow(), 0);
+
+        // Without footer, the archive data are scanned once
+        let (mut mla_read, read) = open();
+        mla_read.metadata = None;
+        assert_eq!(mla_read.file_count().unwrap(), 3);
+        *read.borrow_mut() = 0;
+        assert_eq!(mla_read.file_count().unwrap(), 3);
+        assert_eq!(mla_read.total_uncompressed_size().unwrap(), Some(1006));
+        assert_eq!(*read.borrow(), 0);
+
+        // Fresh scan, starting with the size
+        let (mut mla_read, read) = open();
+        mla_read.metadata = None;
+        assert_eq!(mla_read.total_uncompressed_size().unwrap(), Some(1006));
+        *read.borrow_mut() = 0;
+        assert_eq!(mla_read.file_count().unwrap(), 3);
+        assert_eq!(*read.borrow(), 0);
+
+        // Without hint, the size is unknown
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        mla.add_file("a", 3, &b"abc"[..]).unwrap();
+        mla.finalize().unwrap();
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(mla.into_raw()), ArchiveReaderConfig::new())
+                .unwrap();
+        mla_read.metadata = None;
+        assert_eq!(mla_read.file_count().unwrap(), 1);
+        assert_eq!(mla_read.total_uncompressed_size().unwrap(), None);
     }
 
     #[test]
@@ -1899,80 +9312,566 @@ pub(crate) mod tests {
         let fnames: Vec<String> = (0..=255).map(|i| format!("file_{}", i)).collect();
         let mut name2id: HashMap<_, _> = HashMap::new();
 
-        // Start files in normal order
-        (0..=255)
-            .map(|i| {
-                let id = mla.start_file(&fnames[i]).unwrap();
-                name2id.insert(&fnames[i], id);
-            })
-            .for_each(drop);
+        // Start files in normal order
+        (0..=255)
+            .map(|i| {
+                let id = mla.start_file(&fnames[i]).unwrap();
+                name2id.insert(&fnames[i], id);
+            })
+            .for_each(drop);
+
+        // Add some parts in reverse order
+        (0..=255)
+            .rev()
+            .map(|i| {
+                let id = name2id.get(&fnames[i]).unwrap();
+                mla.append_file_content(id.clone(), 32, &files.get(&fnames[i]).unwrap()[..32])
+                    .unwrap();
+            })
+            .for_each(drop);
+
+        // Add the rest of files in normal order
+        (0..=255)
+            .map(|i| {
+                let id = name2id.get(&fnames[i]).unwrap();
+                let data = &files.get(&fnames[i]).unwrap()[32..];
+                mla.append_file_content(id.clone(), data.len() as u64, data)
+                    .unwrap();
+            })
+            .for_each(drop);
+
+        // Finish files in reverse order
+        (0..=255)
+            .rev()
+            .map(|i| {
+                let id = name2id.get(&fnames[i]).unwrap();
+                mla.end_file(id.clone()).unwrap();
+            })
+            .for_each(drop);
+
+        // Add a big file
+        let fname_big = "big".to_string();
+        mla.add_file(
+            &fname_big,
+            files.get(&fname_big).unwrap().len() as u64,
+            files.get(&fname_big).unwrap().as_slice(),
+        )
+        .unwrap();
+
+        // Add sha256sum file
+        let fname_sha256sum = "sha256sum".to_string();
+        mla.add_file(
+            &fname_sha256sum,
+            files.get(&fname_sha256sum).unwrap().len() as u64,
+            files.get(&fname_sha256sum).unwrap().as_slice(),
+        )
+        .unwrap();
+        mla.finalize().unwrap();
+
+        // UNCOMMENT THESE LINES TO UPDATE THE FILE
+        // UPDATE THE VERSION NUMBER
+        /*
+        std::fs::File::create(std::path::Path::new(&format!(
+            "../samples/archive_v{}.mla",
+            MLA_FORMAT_VERSION
+        )))
+        .unwrap()
+        .write(&mla.into_raw())
+        .unwrap();
+         */
+    }
+
+    #[test]
+    fn check_archive_format_v1() {
+        check_archive_format(include_bytes!("../../samples/archive_v1.mla"));
+    }
+
+    #[test]
+    fn check_archive_format_v2() {
+        check_archive_format(include_bytes!("../../samples/archive_v2.mla"));
+    }
+
+    #[test]
+    fn check_archive_format_v3() {
+        check_archive_format(include_bytes!("../../samples/archive_v3.mla"));
+    }
+
+    #[test]
+    fn check_archive_format_v4() {
+        check_archive_format(include_bytes!("../../samples/archive_v4.mla"));
+    }
+
+    #[test]
+    fn check_archive_format_v5() {
+        check_archive_format(include_bytes!("../../samples/archive_v5.mla"));
+    }
+
+    #[test]
+    fn check_archive_format_v6() {
+        check_archive_format(include_bytes!("../../samples/archive_v6.mla"));
+    }
+
+    #[test]
+    fn check_archive_format_v7() {
+        check_archive_format(include_bytes!("../../samples/archive_v7.mla"));
+    }
+
+    #[test]
+    fn archive_id() {
+        let write = || {
+            let mut mla =
+                ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+            mla.add_file("file", 3, &[1, 2, 3][..]).unwrap();
+            mla.finalize().unwrap();
+            let mla_read =
+                ArchiveReader::from_config(Cursor::new(mla.into_raw()), ArchiveReaderConfig::new())
+                    .unwrap();
+            mla_read.archive_id().unwrap()
+        };
+        assert_ne!(write(), write());
+
+        // Older formats have no identifier
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test25519.pem");
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&[parse_openssl_ed25519_privkey(pem_priv).unwrap()]);
+        let mla_read = ArchiveReader::from_config(
+            Cursor::new(&include_bytes!("../../samples/archive_v5.mla")[..]),
+            config,
+        )
+        .unwrap();
+        assert_eq!(mla_read.archive_id(), None);
+    }
+
+    #[test]
+    fn comments() {
+        let plain = "Backup of the build server";
+        let secret = "Restore with the offline key";
+        let key = StaticSecret::from([3; 32]);
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::DEFAULT)
+            .add_public_keys(&[PublicKey::from(&key)])
+            .comment(plain)
+            .encrypted_comment(secret);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("file", 3, &[1, 2, 3][..]).unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        // The unencrypted comment is visible in the raw bytes, not the other
+        let contains = |text: &str| dest.windows(text.len()).any(|w| w == text.as_bytes());
+        assert!(contains(plain));
+        assert!(!contains(secret));
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&[key]);
+        let mla_read = ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+        assert_eq!(mla_read.comment(), Some(plain));
+        assert_eq!(mla_read.encrypted_comment(), Some(secret));
+
+        // Without comment
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        mla.finalize().unwrap();
+        let mla_read =
+            ArchiveReader::from_config(Cursor::new(mla.into_raw()), ArchiveReaderConfig::new())
+                .unwrap();
+        assert_eq!(mla_read.comment(), None);
+        assert_eq!(mla_read.encrypted_comment(), None);
+
+        // Older formats have no comment
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test25519.pem");
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&[parse_openssl_ed25519_privkey(pem_priv).unwrap()]);
+        let mla_read = ArchiveReader::from_config(
+            Cursor::new(&include_bytes!("../../samples/archive_v4.mla")[..]),
+            config,
+        )
+        .unwrap();
+        assert_eq!(mla_read.comment(), None);
+
+        // Comments are limited in size
+        let long = "a".repeat(MAX_COMMENT_LENGTH + 1);
+        for encrypted in [false, true] {
+            let mut config = ArchiveWriterConfig::new();
+            if encrypted {
+                config.encrypted_comment(&long);
+            } else {
+                config.comment(&long);
+            }
+            assert!(matches!(
+                ArchiveWriter::from_config(Vec::new(), config),
+                Err(Error::ConfigError(ConfigError::CommentTooLong))
+            ));
+        }
+    }
+
+    #[test]
+    fn created_at() {
+        // Set on finalization
+        let before = SystemTime::now();
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        mla.add_file("file", 3, &[1, 2, 3][..]).unwrap();
+        mla.finalize().unwrap();
+        let mla_read =
+            ArchiveReader::from_config(Cursor::new(mla.into_raw()), ArchiveReaderConfig::new())
+                .unwrap();
+        let created_at = mla_read.created_at().unwrap();
+        let delta = created_at
+            .duration_since(before)
+            .unwrap_or_else(|err| err.duration());
+        assert!(delta < Duration::from_secs(1));
+        assert_eq!(mla_read.encrypted_comment(), None);
+
+        // Set by the configuration, along with an encrypted comment
+        let time = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let mut config = ArchiveWriterConfig::new();
+        config.set_created_at(time).encrypted_comment("comment");
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.finalize().unwrap();
+        let mla_read =
+            ArchiveReader::from_config(Cursor::new(mla.into_raw()), ArchiveReaderConfig::new())
+                .unwrap();
+        assert_eq!(mla_read.created_at(), Some(time));
+        assert_eq!(mla_read.encrypted_comment(), Some("comment"));
+
+        // Older archives have no creation time
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test25519.pem");
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&[parse_openssl_ed25519_privkey(pem_priv).unwrap()]);
+        let mla_read = ArchiveReader::from_config(
+            Cursor::new(&include_bytes!("../../samples/archive_v4.mla")[..]),
+            config,
+        )
+        .unwrap();
+        assert_eq!(mla_read.created_at(), None);
+    }
+
+    #[test]
+    fn deterministic_seed() {
+        let mut csprng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut csprng);
+        let file_key = StaticSecret::new(&mut csprng);
+        let params = Argon2Params {
+            m_cost: 64,
+            t_cost: 1,
+            p_cost: 1,
+        };
+        let write = |seed: Option<[u8; 32]>| {
+            let mut config = ArchiveWriterConfig::default();
+            config
+                .add_public_key(&PublicKey::from(&key))
+                .with_password(b"my password", params)
+                .sign_with(&SigningKey::from_bytes(&[1; 32]));
+            if let Some(seed) = seed {
+                config.deterministic_seed(seed);
+            }
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            // Enough files for their order in a HashMap to vary
+            for i in 0..50 {
+                let content = vec![i as u8; 100 * i];
+                mla.add_file(
+                    &format!("file_{i}"),
+                    content.len() as u64,
+                    content.as_slice(),
+                )
+                .unwrap();
+            }
+            let id = mla
+                .start_file_for_recipients("secret", &[PublicKey::from(&file_key)])
+                .unwrap();
+            mla.append_file_content(id, 6, &b"secret"[..]).unwrap();
+            mla.end_file(id).unwrap();
+            mla.add_directory("dir", None).unwrap();
+            mla.add_symlink("link", "file_1", None).unwrap();
+            mla.finalize().unwrap();
+            mla.into_raw()
+        };
+
+        let first = write(Some([7; 32]));
+        assert_eq!(first, write(Some([7; 32])));
+        assert_ne!(first, write(Some([8; 32])));
+        assert_ne!(write(None), write(None));
+
+        // The archive is still a regular one, created at the epoch
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&[key, file_key]);
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(first.as_slice()), config).unwrap();
+        assert_eq!(mla_read.created_at(), Some(UNIX_EPOCH));
+        assert_eq!(mla_read.list_files().unwrap().count(), 51);
+        let mut content = Vec::new();
+        mla_read
+            .get_file("secret")
+            .unwrap()
+            .unwrap()
+            .data
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(content, b"secret");
+        let mut config = ArchiveReaderConfig::new();
+        config.with_password(b"my password");
+        ArchiveReader::from_config(Cursor::new(first.as_slice()), config).unwrap();
+    }
+
+    #[test]
+    fn public_file_listing() {
+        let key = StaticSecret::from([4; 32]);
+        let signing_key = SigningKey::from_bytes(&[1; 32]);
+        let files = [
+            ("zeta/secret_plans.txt", "TOP SECRET CONTENT"),
+            ("alpha.txt", "CONFIDENTIAL CONTENT"),
+        ];
+        let mut config = ArchiveWriterConfig::default();
+        config
+            .add_public_key(&PublicKey::from(&key))
+            .sign_with(&signing_key)
+            .public_file_listing(true);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let estimate = mla
+            .estimate_output_size(
+                &files
+                    .iter()
+                    .map(|(fname, content)| (*fname, content.len() as u64))
+                    .collect::<Vec<_>>(),
+            )
+            .unwrap();
+        for (fname, content) in &files {
+            mla.add_file(fname, content.len() as u64, content.as_bytes())
+                .unwrap();
+        }
+        mla.add_directory("zeta", None).unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+        assert!(dest.len() as u64 <= estimate);
+
+        // Only the names are public
+        let contains = |text: &str| dest.windows(text.len()).any(|w| w == text.as_bytes());
+        for (fname, content) in &files {
+            assert!(contains(fname));
+            assert!(!contains(content));
+        }
+        assert_eq!(
+            ArchiveReader::list_files_public(Cursor::new(dest.as_slice())).unwrap(),
+            ["alpha.txt", "zeta/secret_plans.txt"]
+        );
+
+        // The archive is read as usual, with the keys, and the listing is signed
+        let mut config = ArchiveReaderConfig::new();
+        config
+            .add_private_keys(std::slice::from_ref(&key))
+            .verify_with(&signing_key.verifying_key());
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+        for (fname, content) in &files {
+            let mut buf = Vec::new();
+            mla_read
+                .get_file(fname)
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut buf)
+                .unwrap();
+            assert_eq!(buf, content.as_bytes());
+        }
+        let mut altered = dest.clone();
+        let len = altered.len();
+        altered[len - SIGNATURE_LENGTH - 10] ^= 1;
+        assert!(matches!(
+            crate::helpers::verify_archive(
+                Cursor::new(altered.as_slice()),
+                &signing_key.verifying_key()
+            ),
+            Err(Error::SignatureVerificationFailed)
+        ));
+
+        // The listing is ignored on repair
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut repaired = Vec::new();
+        let report = crate::helpers::repair_archive(
+            dest.as_slice(),
+            &mut repaired,
+            config,
+            ArchiveWriterConfig::new(),
+        )
+        .unwrap();
+        assert!(matches!(
+            report.stopping_error,
+            FailSafeReadError::EndOfOriginalArchiveData
+        ));
+
+        // Not available without the option, and not streamable with it
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        mla.finalize().unwrap();
+        assert!(matches!(
+            ArchiveReader::list_files_public(Cursor::new(mla.into_raw())),
+            Err(Error::MissingPublicListing)
+        ));
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&[key]);
+        assert!(matches!(
+            ArchiveReader::from_stream_config(dest.as_slice(), config),
+            Err(Error::BadAPIArgument(_))
+        ));
+    }
 
-        // Add some parts in reverse order
-        (0..=255)
-            .rev()
-            .map(|i| {
-                let id = name2id.get(&fnames[i]).unwrap();
-                mla.append_file_content(id.clone(), 32, &files.get(&fnames[i]).unwrap()[..32])
-                    .unwrap();
-            })
-            .for_each(drop);
+    #[test]
+    fn pem_and_der_keys() {
+        let samples = [
+            (
+                include_str!("../../samples/test_x25519_pub.pem"),
+                include_str!("../../samples/test_x25519.pem"),
+                &include_bytes!("../../samples/test_x25519_pub.der")[..],
+                &include_bytes!("../../samples/test_x25519.der")[..],
+            ),
+            (
+                include_str!("../../samples/test25519_pub.pem"),
+                include_str!("../../samples/test25519.pem"),
+                &include_bytes!("../../samples/test25519_pub.der")[..],
+                &include_bytes!("../../samples/test25519.der")[..],
+            ),
+        ];
+        for (pub_pem, priv_pem, pub_der, priv_der) in samples {
+            for (pem_writer, pem_reader) in [(true, true), (false, false), (true, false)] {
+                let mut config = ArchiveWriterConfig::new();
+                config.set_layers(Layers::ENCRYPT);
+                if pem_writer {
+                    config.add_public_key_pem(pub_pem).unwrap();
+                } else {
+                    config.add_public_key_der(pub_der).unwrap();
+                }
+                let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+                mla.add_file("my_file", 4, &[1, 2, 3, 4][..]).unwrap();
+                mla.finalize().unwrap();
 
-        // Add the rest of files in normal order
-        (0..=255)
-            .map(|i| {
-                let id = name2id.get(&fnames[i]).unwrap();
-                let data = &files.get(&fnames[i]).unwrap()[32..];
-                mla.append_file_content(id.clone(), data.len() as u64, data)
+                let mut config = ArchiveReaderConfig::new();
+                if pem_reader {
+                    config.add_private_key_pem(priv_pem).unwrap();
+                } else {
+                    config.add_private_key_der(priv_der).unwrap();
+                }
+                let mut mla_read =
+                    ArchiveReader::from_config(Cursor::new(mla.into_raw()), config).unwrap();
+                let mut content = Vec::new();
+                mla_read
+                    .get_file("my_file")
+                    .unwrap()
+                    .unwrap()
+                    .data
+                    .read_to_end(&mut content)
                     .unwrap();
-            })
-            .for_each(drop);
-
-        // Finish files in reverse order
-        (0..=255)
-            .rev()
-            .map(|i| {
-                let id = name2id.get(&fnames[i]).unwrap();
-                mla.end_file(id.clone()).unwrap();
-            })
-            .for_each(drop);
+                assert_eq!(content, vec![1, 2, 3, 4]);
+            }
+        }
 
-        // Add a big file
-        let fname_big = "big".to_string();
-        mla.add_file(
-            &fname_big,
-            files.get(&fname_big).unwrap().len() as u64,
-            files.get(&fname_big).unwrap().as_slice(),
-        )
-        .unwrap();
+        // Invalid keys
+        let mut config = ArchiveReaderConfig::new();
+        assert!(matches!(
+            config.add_private_key_pem("not a key"),
+            Err(Error::InvalidECCKeyFormat)
+        ));
+        // A public key where a private one is expected
+        assert!(config
+            .add_private_key_pem(include_str!("../../samples/test_x25519_pub.pem"))
+            .is_err());
+        assert!(config.add_private_key_der(&[0x30, 0x00]).is_err());
+        let mut config = ArchiveWriterConfig::new();
+        assert!(config.add_public_key_pem("").is_err());
+        assert!(config
+            .add_public_key_der(include_bytes!("../../samples/test_x25519.der"))
+            .is_err());
+    }
 
-        // Add sha256sum file
-        let fname_sha256sum = "sha256sum".to_string();
-        mla.add_file(
-            &fname_sha256sum,
-            files.get(&fname_sha256sum).unwrap().len() as u64,
-            files.get(&fname_sha256sum).unwrap().as_slice(),
+    #[test]
+    fn format_version_and_features() {
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .enable_file_checksums(ChecksumAlgorithm::Blake3)
+            .with_dedup(true)
+            .accept_pre_compressed(true);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let id = mla
+            .start_file_with_metadata("file", &FileMetadata::default())
+            .unwrap();
+        mla.append_file_sparse(id, 10, &[SparseData::Zeroes(10)])
+            .unwrap();
+        mla.end_file(id).unwrap();
+        let compressed = zstd::encode_all(&[1, 2, 3, 4][..], 3).unwrap();
+        let id = mla.start_file("precompressed").unwrap();
+        mla.append_file_content_precompressed(
+            id,
+            compressed.len() as u64,
+            compressed.as_slice(),
+            CompressionAlgorithm::Zstd { level: 3 },
         )
         .unwrap();
+        mla.end_file(id).unwrap();
+        mla.add_file("data", 4, &[1, 2, 3, 4][..]).unwrap();
+        mla.add_file("same_data", 4, &[1, 2, 3, 4][..]).unwrap();
+        mla.add_directory("dir", None).unwrap();
+        mla.add_symlink("link", "file", None).unwrap();
         mla.finalize().unwrap();
+        let with_symlinks = mla.into_raw();
 
-        // UNCOMMENT THESE LINES TO UPDATE THE FILE
-        // UPDATE THE VERSION NUMBER
-        /*
-        std::fs::File::create(std::path::Path::new(&format!(
-            "../samples/archive_v{}.mla",
-            MLA_FORMAT_VERSION
-        )))
-        .unwrap()
-        .write(&mla.into_raw())
+        let mla_read = ArchiveReader::new(Cursor::new(with_symlinks.as_slice())).unwrap();
+        assert_eq!(mla_read.format_version(), FormatVersion::CURRENT);
+        // Except for files encrypted for their own recipients, which require
+        // the encryption layer, and CBOR-encoded blocks, not enabled here
+        assert_eq!(
+            mla_read.features(),
+            FeatureFlags::all() - FeatureFlags::HAS_FILE_RECIPIENTS - FeatureFlags::HAS_CBOR_BLOCKS
+        );
+        // A reader without symlink support rejects it
+        let supported = FeatureFlags::all() - FeatureFlags::HAS_SYMLINKS;
+        match check_features(mla_read.features(), supported) {
+            Err(Error::UnsupportedFeature(features)) => {
+                assert_eq!(features, FeatureFlags::HAS_SYMLINKS)
+            }
+            _ => panic!("Symbolic links must be reported"),
+        }
+
+        // Only used features are declared
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        mla.add_file("file", 1, [1].as_ref()).unwrap();
+        mla.finalize().unwrap();
+        let mut archive = mla.into_raw();
+        let mla_read = ArchiveReader::new(Cursor::new(archive.as_slice())).unwrap();
+        assert_eq!(mla_read.features(), FeatureFlags::empty());
+        assert!(check_features(mla_read.features(), supported).is_ok());
+        drop(mla_read);
+
+        // Features unknown to this reader, from a newer writer
+        let footer_len = u32::from_le_bytes(archive[archive.len() - 4..].try_into().unwrap());
+        let features_offset = archive.len() - 4 - footer_len as usize;
+        archive[features_offset + 3] = 0x80;
+        assert!(matches!(
+            ArchiveReader::new(Cursor::new(archive.as_slice())),
+            Err(Error::UnsupportedFeature(_))
+        ));
+
+        // Archives in older formats do not declare features
+        let mla_read = ArchiveReader::from_config(
+            Cursor::new(include_bytes!("../../samples/archive_v3.mla").as_ref()),
+            {
+                let mut config = ArchiveReaderConfig::new();
+                config.add_private_keys(&[parse_openssl_ed25519_privkey(include_bytes!(
+                    "../../samples/test25519.pem"
+                ))
+                .unwrap()]);
+                config
+            },
+        )
         .unwrap();
-         */
+        assert_eq!(
+            mla_read.format_version(),
+            FormatVersion { major: 3, minor: 0 }
+        );
+        assert_eq!(mla_read.features(), FeatureFlags::empty());
     }
 
-    #[test]
-    fn check_archive_format_v1() {
+    fn check_archive_format(mla_data: &'static [u8]) {
         let pem_priv: &'static [u8] = include_bytes!("../../samples/test25519.pem");
 
-        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v1.mla");
         let files = make_format_regression_files();
 
         // Build Reader
@@ -2019,6 +9918,568 @@ pub(crate) mod tests {
         }
     }
 
+    #[test]
+    fn coalesce_small_writes() {
+        let content: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        let build = |threshold: usize| {
+            let mut config = ArchiveWriterConfig::new();
+            config.coalesce_threshold(threshold);
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            let id = mla.start_file("file").unwrap();
+            let other = mla.start_file("other").unwrap();
+            for chunk in content.chunks(10) {
+                mla.append_file_content(id, chunk.len() as u64, chunk)
+                    .unwrap();
+            }
+            // Interleaved with another file
+            mla.append_file_content(other, 3, &[1, 2, 3][..]).unwrap();
+            mla.append_file_content(id, 5, &content[..5]).unwrap();
+            mla.end_file(other).unwrap();
+            mla.append_file_content(id, content.len() as u64, content.as_slice())
+                .unwrap();
+            mla.end_file(id).unwrap();
+            mla.finalize().unwrap();
+            mla.into_raw()
+        };
+        // Number of `FileContent` blocks, and content of the files
+        let read = |archive: &[u8]| {
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(archive), ArchiveReaderConfig::new())
+                    .unwrap();
+            let mut files = Vec::new();
+            for name in ["file", "other"] {
+                let mut rez = Vec::new();
+                let mut file = mla_read.get_file(name).unwrap().unwrap();
+                file.data.read_to_end(&mut rez).unwrap();
+                files.push(rez);
+            }
+            mla_read.src.seek(SeekFrom::Start(0)).unwrap();
+            let mut blocks = 0;
+            loop {
+                match ArchiveFileBlock::from(&mut mla_read.src).unwrap() {
+                    ArchiveFileBlock::FileContent { length, .. } => {
+                        blocks += 1;
+                        mla_read.src.seek(SeekFrom::Current(length as i64)).unwrap();
+                    }
+                    ArchiveFileBlock::EndOfArchiveData => break,
+                    _ => {}
+                }
+            }
+            (blocks, files)
+        };
+
+        let mut expected = content.clone();
+        expected.extend_from_slice(&content[..5]);
+        expected.extend_from_slice(&content);
+        let expected = vec![expected, vec![1, 2, 3]];
+        let uncoalesced = build(0);
+        let (blocks, files) = read(&uncoalesced);
+        assert_eq!(blocks, 100 + 3);
+        assert_eq!(files, expected);
+        let coalesced = build(4096);
+        let (blocks, files) = read(&coalesced);
+        // Contents kept are written when the other file is appended to, or
+        // when a file is ended
+        assert_eq!(blocks, 4);
+        assert_eq!(files, expected);
+        assert!(coalesced.len() < uncoalesced.len());
+    }
+
+    #[test]
+    fn file_layout() {
+        let content: Vec<u8> = (0..10_000).map(|i| (i % 251) as u8).collect();
+        let build = |layout: FileLayout| {
+            let mut config = ArchiveWriterConfig::new();
+            config.file_layout(layout).coalesce_threshold(0);
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            let id_a = mla.start_file("a").unwrap();
+            let id_b = mla.start_file("b").unwrap();
+            for chunk in content.chunks(1000) {
+                mla.append_file_content(id_a, chunk.len() as u64, chunk)
+                    .unwrap();
+                mla.append_file_content(id_b, chunk.len() as u64, chunk)
+                    .unwrap();
+            }
+            mla.end_file(id_a).unwrap();
+            mla.end_file(id_b).unwrap();
+            mla.finalize().unwrap();
+            (mla.into_raw(), id_a, id_b)
+        };
+        // IDs of the `FileContent` and `EndOfFile` blocks, in order, and
+        // content of the files
+        let read = |archive: &[u8]| {
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(archive), ArchiveReaderConfig::new())
+                    .unwrap();
+            let mut files = Vec::new();
+            for name in ["a", "b"] {
+                let mut rez = Vec::new();
+                let mut file = mla_read.get_file(name).unwrap().unwrap();
+                file.data.read_to_end(&mut rez).unwrap();
+                files.push(rez);
+            }
+            mla_read.src.seek(SeekFrom::Start(0)).unwrap();
+            let mut ids = Vec::new();
+            loop {
+                match ArchiveFileBlock::from(&mut mla_read.src).unwrap() {
+                    ArchiveFileBlock::FileContent { id, length, .. } => {
+                        ids.push(id);
+                        mla_read.src.seek(SeekFrom::Current(length as i64)).unwrap();
+                    }
+                    ArchiveFileBlock::EndOfFile { id, .. } => ids.push(id),
+                    ArchiveFileBlock::EndOfArchiveData => break,
+                    _ => {}
+                }
+            }
+            (ids, files)
+        };
+
+        let (as_appended, id_a, id_b) = build(FileLayout::AsAppended);
+        let (ids, files) = read(&as_appended);
+        // One block per call, in the order of the calls
+        let mut expected = Vec::new();
+        for _ in 0..11 {
+            expected.extend_from_slice(&[id_a, id_b]);
+        }
+        assert_eq!(ids, expected);
+        assert_eq!(files, vec![content.clone(), content.clone()]);
+
+        let (sequential, id_a, id_b) = build(FileLayout::Sequential);
+        let (ids, sequential_files) = read(&sequential);
+        // The blocks of `a` all come before the ones of `b`, which was kept
+        // in memory and written at once
+        let mut expected = vec![id_a; 11];
+        expected.extend_from_slice(&[id_b, id_b]);
+        assert_eq!(ids, expected);
+        assert_eq!(sequential_files, files);
+
+        let (interleaved, id_a, id_b) = build(FileLayout::Interleaved { chunk_size: 2500 });
+        let (ids, interleaved_files) = read(&interleaved);
+        // 4 rounds of content, then the ends of the files
+        let mut expected = Vec::new();
+        for _ in 0..5 {
+            expected.extend_from_slice(&[id_a, id_b]);
+        }
+        assert_eq!(ids, expected);
+        assert_eq!(interleaved_files, files);
+
+        // Chunks must not be empty
+        let mut config = ArchiveWriterConfig::new();
+        config.file_layout(FileLayout::Interleaved { chunk_size: 0 });
+        assert!(matches!(
+            ArchiveWriter::from_config(Vec::new(), config),
+            Err(Error::ConfigError(ConfigError::InvalidChunkSize))
+        ));
+    }
+
+    #[test]
+    fn block_boundary() {
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let mut content = vec![0u8; 1024 * 1024];
+        rng.fill_bytes(&mut content);
+        let mut shifted = vec![0xFF];
+        shifted.extend_from_slice(&content);
+
+        let build = |boundary: BlockBoundary, content: &[u8]| {
+            let mut config = ArchiveWriterConfig::new();
+            config.block_boundary(boundary);
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            let id = mla.start_file("file").unwrap();
+            for chunk in content.chunks(100_000) {
+                mla.append_file_content(id, chunk.len() as u64, chunk)
+                    .unwrap();
+            }
+            mla.end_file(id).unwrap();
+            mla.finalize().unwrap();
+            mla.into_raw()
+        };
+        // Data of the `FileContent` blocks, checking the file content
+        let blocks = |archive: &[u8], content: &[u8]| {
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(archive), ArchiveReaderConfig::new())
+                    .unwrap();
+            let mut rez = Vec::new();
+            let mut file = mla_read.get_file("file").unwrap().unwrap();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(rez, content);
+            mla_read.src.seek(SeekFrom::Start(0)).unwrap();
+            let mut blocks = Vec::new();
+            loop {
+                match ArchiveFileBlock::from(&mut mla_read.src).unwrap() {
+                    ArchiveFileBlock::FileContent { length, .. } => {
+                        let mut data = vec![0u8; length as usize];
+                        mla_read.src.read_exact(&mut data).unwrap();
+                        blocks.push(data);
+                    }
+                    ArchiveFileBlock::EndOfArchiveData => break,
+                    _ => {}
+                }
+            }
+            blocks
+        };
+        // Number of blocks of the shifted content not found in the original
+        let changed = |boundary: BlockBoundary| {
+            let original: HashSet<Vec<u8>> = blocks(&build(boundary, &content), &content)
+                .into_iter()
+                .collect();
+            let shifted = blocks(&build(boundary, &shifted), &shifted);
+            let changed = shifted
+                .iter()
+                .filter(|block| !original.contains(*block))
+                .count();
+            (changed, shifted.len())
+        };
+
+        // Only the first block changes
+        let (count, total) = changed(BlockBoundary::ContentDefined {
+            min: 2048,
+            avg: 8192,
+            max: 65536,
+        });
+        assert!(total > 50);
+        assert_eq!(count, 1);
+        // All blocks change
+        let (count, total) = changed(BlockBoundary::Fixed(8192));
+        assert_eq!(total, 1024 * 1024 / 8192 + 1);
+        assert_eq!(count, total);
+
+        for boundary in [
+            BlockBoundary::Fixed(0),
+            BlockBoundary::ContentDefined {
+                min: 4096,
+                avg: 2048,
+                max: 8192,
+            },
+        ] {
+            let mut config = ArchiveWriterConfig::new();
+            config.block_boundary(boundary);
+            assert!(matches!(
+                ArchiveWriter::from_config(Vec::new(), config),
+                Err(Error::ConfigError(ConfigError::InvalidChunkSize))
+            ));
+        }
+    }
+
+    #[test]
+    fn padding_strategy() {
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let files: Vec<(String, Vec<u8>)> = [0, 1, 100, 5000, 4096 - 17, 20_000]
+            .iter()
+            .enumerate()
+            .map(|(i, size)| {
+                let mut content = vec![0u8; *size];
+                rng.fill_bytes(&mut content);
+                (format!("file_{}", i), content)
+            })
+            .collect();
+        let build = |config: ArchiveWriterConfig| {
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            for (filename, content) in &files {
+                mla.add_file(filename, content.len() as u64, content.as_slice())
+                    .unwrap();
+            }
+            // Interleaved files
+            let id1 = mla.start_file("opened_1").unwrap();
+            mla.append_file_content(id1, 3, &b"abc"[..]).unwrap();
+            let id2 = mla.start_file("opened_2").unwrap();
+            mla.append_file_content(id2, 3, &b"def"[..]).unwrap();
+            mla.end_file(id1).unwrap();
+            mla.end_file(id2).unwrap();
+            mla.finalize().unwrap();
+            let archive = mla.into_raw();
+
+            let mut mla_read = ArchiveReader::from_config(
+                Cursor::new(archive.as_slice()),
+                ArchiveReaderConfig::new(),
+            )
+            .unwrap();
+            let mut expected = files.clone();
+            expected.push(("opened_1".to_string(), b"abc".to_vec()));
+            expected.push(("opened_2".to_string(), b"def".to_vec()));
+            for (filename, content) in &expected {
+                let mut rez = Vec::new();
+                let mut file = mla_read.get_file(filename.clone()).unwrap().unwrap();
+                file.data.read_to_end(&mut rez).unwrap();
+                assert_eq!(&rez, content);
+            }
+            // Offsets of the `FileStart` blocks
+            let offsets: Vec<u64> = mla_read
+                .metadata
+                .as_ref()
+                .unwrap()
+                .files_info
+                .values()
+                .map(|file_info| file_info.offsets[0])
+                .collect();
+            // Linear extraction, as without footer
+            let mut export: HashMap<&String, Vec<u8>> = expected
+                .iter()
+                .map(|(filename, _content)| (filename, Vec::new()))
+                .collect();
+            helpers::linear_extract(&mut mla_read, &mut export).unwrap();
+            for (filename, content) in &expected {
+                assert_eq!(&export[filename], content);
+            }
+            drop(mla_read);
+            (archive, offsets)
+        };
+
+        let (archive, offsets) = build(ArchiveWriterConfig::new());
+        let (padded, padded_offsets) = {
+            let mut config = ArchiveWriterConfig::new();
+            config.padding_strategy(PaddingStrategy::AlignTo(4096));
+            build(config)
+        };
+        assert!(padded.len() > archive.len());
+        assert!(offsets.iter().any(|offset| offset % 4096 != 0));
+        assert_eq!(padded_offsets.len(), files.len() + 2);
+        assert!(padded_offsets.iter().all(|offset| offset % 4096 == 0));
+
+        // Through the layers, positions are the ones of the archive data
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .enable_layer(Layers::COMPRESS)
+            .padding_strategy(PaddingStrategy::AlignTo(100));
+        let (_archive, offsets) = build(config);
+        assert!(offsets.iter().all(|offset| offset % 100 == 0));
+
+        // Random padding, following the seed
+        let jitter = |seed: [u8; 32]| {
+            let mut config = ArchiveWriterConfig::new();
+            config
+                .padding_strategy(PaddingStrategy::RandomJitter { min: 10, max: 1000 })
+                .deterministic_seed(seed);
+            build(config).0
+        };
+        let padded = jitter([0; 32]);
+        assert!(padded.len() >= archive.len() + (files.len() + 2) * (17 + 10));
+        assert_eq!(jitter([0; 32]), padded);
+        assert_ne!(jitter([1; 32]), padded);
+
+        for strategy in [
+            PaddingStrategy::AlignTo(0),
+            PaddingStrategy::RandomJitter { min: 2, max: 1 },
+        ] {
+            let mut config = ArchiveWriterConfig::new();
+            config.padding_strategy(strategy);
+            assert!(matches!(
+                ArchiveWriter::from_config(Vec::new(), config),
+                Err(Error::ConfigError(ConfigError::InvalidPaddingStrategy))
+            ));
+        }
+    }
+
+    #[test]
+    fn path_normalization() {
+        let mut config = ArchiveWriterConfig::new();
+        config.path_normalization(PathNormalizationPolicy::ForwardSlash);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("foo\\bar", 3, &b"abc"[..]).unwrap();
+        mla.add_directory("dir\\sub", None).unwrap();
+        mla.add_symlink("dir\\link", "..\\target", None).unwrap();
+        mla.rename_file("foo\\bar", "foo\\baz").unwrap();
+        assert!(matches!(
+            mla.add_file("foo/bar", 1, &b"d"[..]),
+            Err(Error::DuplicateFilename)
+        ));
+        mla.finalize().unwrap();
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(mla.into_raw()), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut files: Vec<&String> = mla_read.list_files().unwrap().collect();
+        files.sort();
+        assert_eq!(files, vec!["foo/bar", "foo/baz"]);
+        assert_eq!(
+            mla_read.list_directories().unwrap().collect::<Vec<_>>(),
+            vec!["dir/sub"]
+        );
+        // Targets are stored as is
+        assert_eq!(
+            mla_read.list_symlinks().unwrap().collect::<Vec<_>>(),
+            vec![("dir/link", "..\\target")]
+        );
+        let mut rez = Vec::new();
+        let mut file = mla_read.get_file("foo/bar").unwrap().unwrap();
+        file.data.read_to_end(&mut rez).unwrap();
+        assert_eq!(rez, b"abc");
+
+        let mut config = ArchiveWriterConfig::new();
+        config.path_normalization(PathNormalizationPolicy::Strict);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let long_component = "a".repeat(256);
+        for name in [
+            "..",
+            "../etc/passwd",
+            "a/../../b",
+            "a\\..\\b",
+            "/etc/passwd",
+            "\\share",
+            "a\0b",
+            long_component.as_str(),
+        ] {
+            assert!(matches!(
+                mla.add_file(name, 1, &b"a"[..]),
+                Err(Error::InvalidFilename(_))
+            ));
+            assert!(matches!(
+                mla.add_directory(name, None),
+                Err(Error::InvalidFilename(_))
+            ));
+            assert!(matches!(
+                mla.add_symlink(name, "target", None),
+                Err(Error::InvalidFilename(_))
+            ));
+        }
+        // Valid names are stored as given
+        for name in ["a/b", "a..b/./c", "a\\b", &"a".repeat(255)] {
+            mla.add_file(name, 1, &b"a"[..]).unwrap();
+        }
+        assert!(matches!(
+            mla.rename_file("a/b", "../b"),
+            Err(Error::InvalidFilename(_))
+        ));
+        mla.finalize().unwrap();
+        let mla_read =
+            ArchiveReader::from_config(Cursor::new(mla.into_raw()), ArchiveReaderConfig::new())
+                .unwrap();
+        assert!(mla_read
+            .list_files()
+            .unwrap()
+            .any(|filename| filename == "a\\b"));
+    }
+
+    #[test]
+    fn add_file_if_changed() {
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        mla.add_file("a", 9, &b"a content"[..]).unwrap();
+        mla.add_file("b", 9, &b"b content"[..]).unwrap();
+        mla.finalize().unwrap();
+        let previous = mla.into_raw();
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(previous), ArchiveReaderConfig::new()).unwrap();
+        let hash_a = mla_read.get_hash("a").unwrap().unwrap();
+        let hash_b = mla_read.get_hash("b").unwrap().unwrap();
+
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        assert_eq!(
+            mla.add_file_if_changed("a", Cursor::new(b"a content"), Some(hash_a))
+                .unwrap(),
+            AddResult::Skipped
+        );
+        let new_b: Sha256Hash = Sha256::digest(b"new b content").into();
+        assert_eq!(
+            mla.add_file_if_changed("b", Cursor::new(b"new b content"), Some(hash_b))
+                .unwrap(),
+            AddResult::Added(new_b)
+        );
+        assert_eq!(
+            mla.add_file_if_changed("c", Cursor::new(b"a content"), None)
+                .unwrap(),
+            AddResult::Added(hash_a)
+        );
+        // The content is hashed, then added, from the current position
+        let mut src = Cursor::new(b"skipped d content");
+        src.seek(SeekFrom::Start(8)).unwrap();
+        let new_d: Sha256Hash = Sha256::digest(b"d content").into();
+        assert_eq!(
+            mla.add_file_if_changed("d", src, Some(hash_a)).unwrap(),
+            AddResult::Added(new_d)
+        );
+        mla.finalize().unwrap();
+        let archive = mla.into_raw();
+
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(archive.as_slice()), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut files: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+        files.sort();
+        assert_eq!(files, vec!["b", "c", "d"]);
+        assert_eq!(mla_read.get_hash("b").unwrap(), Some(new_b));
+        assert_eq!(mla_read.get_hash("d").unwrap(), Some(new_d));
+        // Nothing has been written for the skipped file
+        mla_read.src.seek(SeekFrom::Start(0)).unwrap();
+        let mut started = Vec::new();
+        loop {
+            match ArchiveFileBlock::from(&mut mla_read.src).unwrap() {
+                ArchiveFileBlock::FileStart { filename, .. } => started.push(filename),
+                ArchiveFileBlock::FileContent { length, .. } => {
+                    mla_read.src.seek(SeekFrom::Current(length as i64)).unwrap();
+                }
+                ArchiveFileBlock::EndOfArchiveData => break,
+                _ => {}
+            }
+        }
+        assert_eq!(started, vec!["b", "c", "d"]);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn tracing_spans() {
+        use std::sync::{Arc, Mutex};
+        use tracing_subscriber::fmt::format::FmtSpan;
+
+        // Output of the subscriber, shared with the test
+        #[derive(Clone, Default)]
+        struct Output(Arc<Mutex<Vec<u8>>>);
+        impl Write for Output {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let output = Output::default();
+        let writer = output.clone();
+        // One line per span closed
+        let subscriber = tracing_subscriber::fmt::Subscriber::builder()
+            .with_writer(move || writer.clone())
+            .with_span_events(FmtSpan::CLOSE)
+            .with_ansi(false)
+            .without_time()
+            .with_level(false)
+            .with_target(false)
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            let _archive = tracing::info_span!("archive").entered();
+            let mut config = ArchiveWriterConfig::new();
+            config.archive_path("out.mla");
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            let id = mla.start_file("my_file").unwrap();
+            mla.append_file_content(id, 3, &b"abc"[..]).unwrap();
+            mla.append_file_content(id, 2, &b"de"[..]).unwrap();
+            mla.end_file(id).unwrap();
+            mla.finalize().unwrap();
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(mla.into_raw()), ArchiveReaderConfig::new())
+                    .unwrap();
+            mla_read.get_file("my_file").unwrap().unwrap();
+            // The field is omitted until a path is given
+            mla_read.config.archive_path("in.mla");
+            mla_read.get_all_files().unwrap();
+        });
+
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let spans: Vec<&str> = output
+            .lines()
+            .map(|line| line.split(": close").next().unwrap())
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                r#"archive:start_file{archive_path="out.mla" file_name="my_file" file_id=0}"#,
+                r#"archive:append_file_content{archive_path="out.mla" file_name="my_file" file_id=0 bytes=3}"#,
+                r#"archive:append_file_content{archive_path="out.mla" file_name="my_file" file_id=0 bytes=2}"#,
+                r#"archive:end_file{archive_path="out.mla" file_name="my_file" file_id=0 bytes=5}"#,
+                r#"archive:get_file{file_name="my_file" bytes=5}"#,
+                r#"archive:linear_extract{archive_path="in.mla" files=1}"#,
+                "archive",
+            ]
+        );
+    }
+
     #[test]
     fn empty_blocks() {
         // Add a file with containning an empty block - it should works
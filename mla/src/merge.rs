@@ -0,0 +1,353 @@
+use crate::errors::Error;
+use crate::{ArchiveFileID, ArchiveReader, ArchiveWriter, EntryKind, CACHE_SIZE};
+use std::collections::HashSet;
+use std::io::{Read, Seek, Write};
+
+/// Handling of an entry of the source archive with a name already used in
+/// the destination one
+///
+/// As archives are written linearly, an entry already written can't be
+/// replaced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// Do not merge the source entry
+    Skip,
+    /// Merge the source entry under a new name, made by appending the first
+    /// free numeric suffix (`name.1`, `name.2`, etc.)
+    Rename,
+}
+
+/// Options of `ArchiveWriter::merge_from`
+#[derive(Debug, Clone)]
+pub struct MergeOptions {
+    collision: CollisionPolicy,
+}
+
+impl std::default::Default for MergeOptions {
+    fn default() -> Self {
+        MergeOptions {
+            collision: CollisionPolicy::Skip,
+        }
+    }
+}
+
+impl MergeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how name collisions are handled (default: `CollisionPolicy::Skip`)
+    pub fn with_collision_policy(&mut self, collision: CollisionPolicy) -> &mut MergeOptions {
+        self.collision = collision;
+        self
+    }
+}
+
+/// Summary of a merge, see `ArchiveWriter::merge_from`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MergeReport {
+    /// Files merged, including the renamed ones
+    pub files_merged: u64,
+    pub directories_merged: u64,
+    pub symlinks_merged: u64,
+    /// Entries not merged, due to a name collision
+    pub files_skipped: u64,
+    /// Entries merged under a new name, due to a name collision
+    pub files_renamed: u64,
+}
+
+impl<'a, W: Write> ArchiveWriter<'a, W> {
+    /// Add every entry of the archive `reader` (files, with their metadata,
+    /// directories and symbolic links) to this archive
+    ///
+    /// The source archive is read linearly, except for files which content
+    /// is interleaved with other ones, read afterwards. Contents are written
+    /// again through this archive layers: they are compressed, encrypted and
+    /// signed according to this archive configuration, whatever the source
+    /// archive one; files encrypted for their own recipients are thus merged
+    /// as regular files, and need one of their private keys. Directories
+    /// already present in this archive are merged silently; other name
+    /// collisions are handled according to `options`
+    pub fn merge_from<'b, R: 'b + Read + Seek>(
+        &mut self,
+        reader: &mut ArchiveReader<'b, R>,
+        options: MergeOptions,
+    ) -> Result<MergeReport, Error> {
+        let mut report = MergeReport::default();
+        // Files written in several continuous chunks are interleaved with
+        // other ones, and can't be read in one linear pass
+        let interleaved: HashSet<String> = reader
+            .metadata
+            .as_ref()
+            .ok_or(Error::MissingMetadata)?
+            .files_info
+            .iter()
+            .filter(|(_fname, info)| info.offsets.len() > 1)
+            .map(|(fname, _info)| fname.clone())
+            .collect();
+        // Destination ID of the interleaved files, with their source name
+        let mut deferred: Vec<(ArchiveFileID, String)> = Vec::new();
+        let mut buf = Vec::with_capacity(CACHE_SIZE);
+
+        for entry in reader.entries()? {
+            let mut entry = entry?;
+            let name = match self.merged_name(&entry.filename, entry.kind, &options, &mut report) {
+                Some(name) => name,
+                None => continue,
+            };
+            match entry.kind {
+                EntryKind::File => {
+                    let id = match &entry.metadata {
+                        Some(metadata) => self.start_file_with_metadata(&name, metadata)?,
+                        None => self.start_file(&name)?,
+                    };
+                    if interleaved.contains(&entry.filename) {
+                        // The file is started right away, to reserve its name
+                        deferred.push((id, entry.filename));
+                    } else {
+                        self.copy_file_content(id, &mut entry, &mut buf)?;
+                        self.end_file(id)?;
+                    }
+                    report.files_merged += 1;
+                }
+                EntryKind::Directory => {
+                    self.add_directory(&name, entry.metadata)?;
+                    report.directories_merged += 1;
+                }
+                EntryKind::Symlink => {
+                    let target = entry.link_target.unwrap_or_default();
+                    self.add_symlink(&name, &target, entry.metadata)?;
+                    report.symlinks_merged += 1;
+                }
+            }
+        }
+
+        for (id, fname) in deferred {
+            let mut file = reader.get_file(&fname)?.ok_or_else(|| {
+                Error::WrongReaderState("[merge_from] A listed file can't be found".to_string())
+            })?;
+            self.copy_file_content(id, &mut file.data, &mut buf)?;
+            self.end_file(id)?;
+        }
+        Ok(report)
+    }
+
+    /// Name to use for the merged entry `name`, or `None` if it must not be
+    /// merged
+    fn merged_name(
+        &self,
+        name: &str,
+        kind: EntryKind,
+        options: &MergeOptions,
+        report: &mut MergeReport,
+    ) -> Option<String> {
+        if !self.is_path_used(name) {
+            return Some(name.to_string());
+        }
+        if kind == EntryKind::Directory && self.directories.contains(name) {
+            // Same directory, nothing to merge
+            return None;
+        }
+        match options.collision {
+            CollisionPolicy::Skip => {
+                report.files_skipped += 1;
+                None
+            }
+            CollisionPolicy::Rename => {
+                report.files_renamed += 1;
+                (1u64..)
+                    .map(|suffix| format!("{}.{}", name, suffix))
+                    .find(|new_name| !self.is_path_used(new_name))
+            }
+        }
+    }
+
+    /// Append the whole content of `src` to the file `id`
+    fn copy_file_content<U: Read>(
+        &mut self,
+        id: ArchiveFileID,
+        src: &mut U,
+        buf: &mut Vec<u8>,
+    ) -> Result<(), Error> {
+        loop {
+            buf.clear();
+            let count = src.take(CACHE_SIZE as u64).read_to_end(buf)?;
+            if count == 0 {
+                return Ok(());
+            }
+            self.append_file_content(id, count as u64, buf.as_slice())?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+    use crate::metadata::FileMetadata;
+    use crate::Layers;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use std::io::Cursor;
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    /// Build an unencrypted archive with 3 files, the 2 first ones being
+    /// interleaved, prefixed with `prefix`
+    fn build_archive(prefix: &str, content_seed: u8) -> Vec<u8> {
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        let metadata = FileMetadata {
+            mode: Some(0o600),
+            ..Default::default()
+        };
+        let id1 = mla
+            .start_file_with_metadata(format!("{}1", prefix), &metadata)
+            .unwrap();
+        let id2 = mla.start_file(format!("{}2", prefix)).unwrap();
+        mla.append_file_content(id1, 1, [content_seed].as_ref())
+            .unwrap();
+        mla.append_file_content(id2, 2, [content_seed + 1; 2].as_ref())
+            .unwrap();
+        mla.append_file_content(id1, 1, [content_seed + 2].as_ref())
+            .unwrap();
+        mla.end_file(id1).unwrap();
+        mla.end_file(id2).unwrap();
+        let data = vec![content_seed + 3; 3 * CACHE_SIZE];
+        mla.add_file(format!("{}3", prefix), data.len() as u64, data.as_slice())
+            .unwrap();
+        mla.add_directory("dir", None).unwrap();
+        mla.finalize().unwrap();
+        mla.into_raw()
+    }
+
+    fn open(archive: Vec<u8>) -> ArchiveReader<'static, Cursor<Vec<u8>>> {
+        ArchiveReader::from_config(Cursor::new(archive), ArchiveReaderConfig::new()).unwrap()
+    }
+
+    fn read_file<R: Read + Seek>(mla_read: &mut ArchiveReader<R>, fname: &str) -> Vec<u8> {
+        let mut file = mla_read.get_file(fname).unwrap().unwrap();
+        let mut content = Vec::new();
+        file.data.read_to_end(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    fn merge_from() {
+        // Merge two unencrypted archives into an encrypted one
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .enable_layer(Layers::ENCRYPT)
+            .add_public_keys(&[PublicKey::from(&key)]);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let report_a = mla
+            .merge_from(&mut open(build_archive("a", 10)), MergeOptions::new())
+            .unwrap();
+        let report_b = mla
+            .merge_from(&mut open(build_archive("b", 20)), MergeOptions::new())
+            .unwrap();
+        mla.finalize().unwrap();
+
+        assert_eq!(
+            report_a,
+            MergeReport {
+                files_merged: 3,
+                directories_merged: 1,
+                ..Default::default()
+            }
+        );
+        // "dir" is already there
+        assert_eq!(
+            report_b,
+            MergeReport {
+                files_merged: 3,
+                ..Default::default()
+            }
+        );
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(Cursor::new(mla.into_raw()), config).unwrap();
+        let mut files: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+        files.sort();
+        assert_eq!(files, vec!["a1", "a2", "a3", "b1", "b2", "b3"]);
+        for (prefix, seed) in [("a", 10), ("b", 20)].iter() {
+            let fname = format!("{}1", prefix);
+            assert_eq!(read_file(&mut mla_read, &fname), vec![*seed, seed + 2]);
+            let file = mla_read.get_file(&fname).unwrap().unwrap();
+            assert_eq!(file.metadata.unwrap().mode, Some(0o600));
+            let fname = format!("{}2", prefix);
+            assert_eq!(read_file(&mut mla_read, &fname), vec![seed + 1; 2]);
+            let fname = format!("{}3", prefix);
+            assert_eq!(
+                read_file(&mut mla_read, &fname),
+                vec![seed + 3; 3 * CACHE_SIZE]
+            );
+        }
+        assert_eq!(
+            mla_read.list_directories().unwrap().collect::<Vec<_>>(),
+            vec!["dir"]
+        );
+    }
+
+    #[test]
+    fn merge_from_collisions() {
+        // Skip
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        mla.merge_from(&mut open(build_archive("a", 10)), MergeOptions::new())
+            .unwrap();
+        let report = mla
+            .merge_from(&mut open(build_archive("a", 20)), MergeOptions::new())
+            .unwrap();
+        mla.finalize().unwrap();
+        assert_eq!(
+            report,
+            MergeReport {
+                files_skipped: 3,
+                ..Default::default()
+            }
+        );
+        let mut mla_read = open(mla.into_raw());
+        assert_eq!(mla_read.list_files().unwrap().count(), 3);
+        assert_eq!(read_file(&mut mla_read, "a2"), vec![11; 2]);
+
+        // Rename, with a suffix already used
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        mla.add_file("a1.1", 1, [0].as_ref()).unwrap();
+        mla.add_symlink("dir.1", "dir", None).unwrap();
+        mla.add_file("dir", 0, [].as_ref()).unwrap();
+        let mut options = MergeOptions::new();
+        options.with_collision_policy(CollisionPolicy::Rename);
+        mla.merge_from(&mut open(build_archive("a", 10)), options.clone())
+            .unwrap();
+        let report = mla
+            .merge_from(&mut open(build_archive("a", 20)), options)
+            .unwrap();
+        mla.finalize().unwrap();
+        // "dir" is a file, the directory is renamed, skipping the used
+        // "dir.1"
+        assert_eq!(
+            report,
+            MergeReport {
+                files_merged: 3,
+                directories_merged: 1,
+                files_renamed: 4,
+                ..Default::default()
+            }
+        );
+        let mut mla_read = open(mla.into_raw());
+        let mut files: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+        files.sort();
+        assert_eq!(
+            files,
+            vec!["a1", "a1.1", "a1.2", "a2", "a2.1", "a3", "a3.1", "dir"]
+        );
+        assert_eq!(read_file(&mut mla_read, "a1.1"), vec![0]);
+        assert_eq!(read_file(&mut mla_read, "a1.2"), vec![20, 22]);
+        assert_eq!(read_file(&mut mla_read, "a2.1"), vec![21; 2]);
+        assert_eq!(
+            mla_read.list_directories().unwrap().collect::<Vec<_>>(),
+            vec!["dir.2", "dir.3"]
+        );
+    }
+}
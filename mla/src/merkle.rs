@@ -0,0 +1,254 @@
+//! Merkle tree over the files of an archive, to prove that a file, with a
+//! given content, is in an archive without revealing the other files
+//!
+//! Leaves are the files, sorted by name. Each leaf hash binds a filename to
+//! the SHA-256 hash of its content:
+//! `SHA256(0x00 || u64_le(filename length) || filename || content hash)`.
+//! Each node hash is `SHA256(0x01 || left || right)`. On a level with an odd
+//! number of nodes, the last one is promoted as is to the next level. The
+//! root of an empty tree is `SHA256("")`.
+use crate::errors::Error;
+use crate::ArchiveReader;
+use sha2::{Digest, Sha256};
+use std::io::{self, Read, Seek};
+
+/// Domain separation prefixes, so that a leaf can't be passed for a node
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+fn leaf_hash(filename: &str, content_hash: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update((filename.len() as u64).to_le_bytes());
+    hasher.update(filename.as_bytes());
+    hasher.update(content_hash);
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Merkle tree over the files of an archive, see
+/// `ArchiveReader::build_merkle_tree`
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    /// Sorted filenames, one per leaf
+    filenames: Vec<String>,
+    /// Hashes of each level, from the leaves to the root
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+impl MerkleTree {
+    /// Build the tree of `files`, as (filename, content hash)
+    pub fn from_hashes(mut files: Vec<(String, [u8; 32])>) -> Self {
+        files.sort_by(|(fname_a, _), (fname_b, _)| fname_a.cmp(fname_b));
+        let mut levels = vec![files
+            .iter()
+            .map(|(filename, hash)| leaf_hash(filename, hash))
+            .collect::<Vec<_>>()];
+        while levels[levels.len() - 1].len() > 1 {
+            let next = levels[levels.len() - 1]
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(left, right),
+                    [last] => *last,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+        MerkleTree {
+            filenames: files.into_iter().map(|(filename, _)| filename).collect(),
+            levels,
+        }
+    }
+
+    /// Root hash of the tree
+    pub fn root(&self) -> [u8; 32] {
+        match self.levels[self.levels.len() - 1].first() {
+            Some(root) => *root,
+            None => Sha256::digest(&[]).into(),
+        }
+    }
+
+    /// Membership proof of `filename`, or `None` if it is not in the tree
+    pub fn generate_proof(&self, filename: &str) -> Option<MerkleProof> {
+        let mut index = self
+            .filenames
+            .binary_search_by(|fname| fname.as_str().cmp(filename))
+            .ok()?;
+        let mut path = Vec::new();
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = index ^ 1;
+            // A promoted node has no sibling
+            if sibling < level.len() {
+                path.push(ProofStep {
+                    hash: level[sibling],
+                    is_left: sibling < index,
+                });
+            }
+            index /= 2;
+        }
+        Some(MerkleProof { path })
+    }
+}
+
+/// Sibling of a node on the path from a leaf to the root
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ProofStep {
+    hash: [u8; 32],
+    /// Whether the sibling is the left node
+    is_left: bool,
+}
+
+/// Proof that a file is in a `MerkleTree`, see `MerkleTree::generate_proof`
+///
+/// It only holds the hashes of the siblings on the path from the file leaf
+/// to the root, which reveal nothing about the other files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    path: Vec<ProofStep>,
+}
+
+impl MerkleProof {
+    /// Check that the file `filename`, whose content hashes (SHA-256) to
+    /// `content_hash`, is in the tree of root `root`
+    pub fn verify(&self, filename: &str, content_hash: [u8; 32], root: [u8; 32]) -> bool {
+        let computed = self
+            .path
+            .iter()
+            .fold(leaf_hash(filename, &content_hash), |hash, step| {
+                if step.is_left {
+                    node_hash(&step.hash, &hash)
+                } else {
+                    node_hash(&hash, &step.hash)
+                }
+            });
+        computed == root
+    }
+}
+
+impl<'b, R: 'b + Read + Seek> ArchiveReader<'b, R> {
+    /// Build the Merkle tree of the archive files, see `merkle`
+    ///
+    /// The content of each file is read and hashed
+    pub fn build_merkle_tree(&mut self) -> Result<MerkleTree, Error> {
+        let fnames: Vec<String> = self.list_files()?.cloned().collect();
+        let mut files = Vec::with_capacity(fnames.len());
+        for fname in fnames {
+            let mut file = match self.get_file(fname.clone())? {
+                Some(file) => file,
+                None => continue,
+            };
+            let mut hasher = Sha256::new();
+            io::copy(&mut file.data, &mut hasher)?;
+            files.push((fname, hasher.finalize().into()));
+        }
+        Ok(MerkleTree::from_hashes(files))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ArchiveReaderConfig;
+    use crate::tests::build_archive;
+    use std::io::Cursor;
+
+    fn hash(content: &[u8]) -> [u8; 32] {
+        Sha256::digest(content).into()
+    }
+
+    #[test]
+    fn archive_merkle_tree() {
+        // Build an archive with 3 interleaved files
+        let (mla, key, files) = build_archive(None, true);
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(Cursor::new(mla.into_raw()), config).unwrap();
+        let tree = mla_read.build_merkle_tree().unwrap();
+
+        // Files are already sorted by name: the last one is promoted
+        let leaves: Vec<[u8; 32]> = files
+            .iter()
+            .map(|(fname, content)| leaf_hash(fname, &hash(content)))
+            .collect();
+        let expected = node_hash(&node_hash(&leaves[0], &leaves[1]), &leaves[2]);
+        assert_eq!(tree.root(), expected);
+
+        for (fname, content) in &files {
+            let proof = tree.generate_proof(fname).unwrap();
+            assert!(proof.verify(fname, hash(content), expected));
+            // Wrong content, filename or root
+            assert!(!proof.verify(fname, hash(b"other"), expected));
+            assert!(!proof.verify("other", hash(content), expected));
+            assert!(!proof.verify(fname, hash(content), leaves[0]));
+        }
+        assert_eq!(
+            tree.generate_proof(&files[0].0).unwrap().path,
+            vec![
+                ProofStep {
+                    hash: leaves[1],
+                    is_left: false
+                },
+                ProofStep {
+                    hash: leaves[2],
+                    is_left: false
+                },
+            ]
+        );
+        // The promoted leaf only has the upper sibling
+        assert_eq!(
+            tree.generate_proof(&files[2].0).unwrap().path,
+            vec![ProofStep {
+                hash: node_hash(&leaves[0], &leaves[1]),
+                is_left: true
+            }]
+        );
+        assert!(tree.generate_proof("unknown").is_none());
+    }
+
+    #[test]
+    fn merkle_tree_shapes() {
+        // Empty tree
+        let tree = MerkleTree::from_hashes(Vec::new());
+        assert_eq!(tree.root(), hash(b""));
+        assert!(tree.generate_proof("a").is_none());
+
+        // Single leaf: it is the root, with an empty proof
+        let tree = MerkleTree::from_hashes(vec![("a".to_string(), hash(b"a"))]);
+        assert_eq!(tree.root(), leaf_hash("a", &hash(b"a")));
+        assert!(tree
+            .generate_proof("a")
+            .unwrap()
+            .verify("a", hash(b"a"), tree.root()));
+
+        // Four leaves, given unsorted
+        let names = ["d", "b", "a", "c"];
+        let tree = MerkleTree::from_hashes(
+            names
+                .iter()
+                .map(|name| (name.to_string(), hash(name.as_bytes())))
+                .collect(),
+        );
+        let leaf = |name: &str| leaf_hash(name, &hash(name.as_bytes()));
+        let expected = node_hash(
+            &node_hash(&leaf("a"), &leaf("b")),
+            &node_hash(&leaf("c"), &leaf("d")),
+        );
+        assert_eq!(tree.root(), expected);
+        for name in &names {
+            let proof = tree.generate_proof(name).unwrap();
+            assert_eq!(proof.path.len(), 2);
+            assert!(proof.verify(name, hash(name.as_bytes()), expected));
+        }
+        // A proof does not hold for another position
+        let proof = tree.generate_proof("a").unwrap();
+        assert!(!proof.verify("b", hash(b"b"), expected));
+    }
+}
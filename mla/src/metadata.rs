@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::time::SystemTime;
+
+/// POSIX-like metadata of a file, optionally stored in the archive alongside
+/// its content
+///
+/// Every field is optional, as some of them are not meaningful on every
+/// platform (for instance, `uid` and `gid` on Windows)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMetadata {
+    /// Last modification time
+    pub mtime: Option<SystemTime>,
+    /// Permission bits (as in `st_mode`, without the file type)
+    pub mode: Option<u32>,
+    /// Owner user ID
+    pub uid: Option<u32>,
+    /// Owner group ID
+    pub gid: Option<u32>,
+}
+
+impl From<&fs::Metadata> for FileMetadata {
+    /// Get the metadata of a file on the current filesystem
+    fn from(metadata: &fs::Metadata) -> Self {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            FileMetadata {
+                mtime: metadata.modified().ok(),
+                mode: Some(metadata.mode() & 0o7777),
+                uid: Some(metadata.uid()),
+                gid: Some(metadata.gid()),
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            FileMetadata {
+                mtime: metadata.modified().ok(),
+                ..Default::default()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn serialize_metadata() {
+        let metadata = FileMetadata {
+            mtime: Some(SystemTime::UNIX_EPOCH + Duration::new(1_600_000_000, 42)),
+            mode: Some(0o644),
+            uid: Some(1000),
+            gid: None,
+        };
+        let data = bincode::serialize(&metadata).unwrap();
+        let metadata_rebuild: FileMetadata = bincode::deserialize(&data).unwrap();
+        assert_eq!(metadata, metadata_rebuild);
+    }
+}
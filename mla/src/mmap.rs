@@ -0,0 +1,127 @@
+use crate::config::ArchiveReaderConfig;
+use crate::errors::Error;
+use crate::ArchiveReader;
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// `Read + Seek` source over a memory-mapped file, see
+/// `ArchiveReader::from_mmap`
+///
+/// Reads are copies from the mapping: the OS handles the read-ahead and
+/// the page cache, instead of a buffer of the process
+pub struct MmapReader {
+    cursor: Cursor<Mmap>,
+}
+
+impl MmapReader {
+    /// Map the file at `path`
+    ///
+    /// The file must not be modified, or truncated, while it is mapped: other
+    /// processes doing so would change the data under the reader, and reading
+    /// the truncated part makes the process crash
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        // SAFETY: the mapping is read-only, and only read through `cursor`.
+        // Modifications of the file by others are documented above
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(MmapReader {
+            cursor: Cursor::new(mmap),
+        })
+    }
+
+    /// Size of the mapped file
+    pub fn len(&self) -> u64 {
+        self.cursor.get_ref().len() as u64
+    }
+
+    /// Whether the mapped file is empty
+    pub fn is_empty(&self) -> bool {
+        self.cursor.get_ref().is_empty()
+    }
+}
+
+impl Read for MmapReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.cursor.read(buf)
+    }
+}
+
+impl Seek for MmapReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.cursor.seek(pos)
+    }
+}
+
+impl ArchiveReader<'static, MmapReader> {
+    /// Open the archive file at `path` through a memory mapping, with
+    /// `config`, see `ArchiveReader::from_config`
+    ///
+    /// For large local archives, this avoids buffering reads in the process.
+    /// The file must not be modified while the reader is alive, see
+    /// `MmapReader::open`
+    pub fn from_mmap(path: &Path, mut config: ArchiveReaderConfig) -> Result<Self, Error> {
+        if config.archive_path.is_none() {
+            config.archive_path(&path.to_string_lossy());
+        }
+        ArchiveReader::from_config(MmapReader::open(path)?, config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ArchiveWriterConfig;
+    use crate::{ArchiveWriter, Layers};
+    use rand::{RngCore, SeedableRng};
+    use rand_chacha::ChaChaRng;
+    use std::io::BufReader;
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    #[test]
+    fn mmap_reader() {
+        let key = StaticSecret::from([5; 32]);
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::DEFAULT)
+            .add_public_keys(&[PublicKey::from(&key)]);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let mut files = Vec::new();
+        for i in 0..5 {
+            let mut content = vec![0u8; 300_000 * i];
+            rng.fill_bytes(&mut content);
+            let fname = format!("file_{}", i);
+            mla.add_file(&fname, content.len() as u64, content.as_slice())
+                .unwrap();
+            files.push((fname, content));
+        }
+        mla.finalize().unwrap();
+        let path = std::env::temp_dir().join(format!("mla_mmap_{}", std::process::id()));
+        std::fs::write(&path, mla.into_raw()).unwrap();
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&[key]);
+        let mut mla_mmap = ArchiveReader::from_mmap(&path, config.clone()).unwrap();
+        let mut mla_file =
+            ArchiveReader::from_config(BufReader::new(File::open(&path).unwrap()), config).unwrap();
+        // Same output than with a buffered file
+        for (fname, content) in &files {
+            let mut from_mmap = Vec::new();
+            let mut file = mla_mmap.get_file(fname).unwrap().unwrap();
+            file.data.read_to_end(&mut from_mmap).unwrap();
+            let mut from_file = Vec::new();
+            let mut file = mla_file.get_file(fname).unwrap().unwrap();
+            file.data.read_to_end(&mut from_file).unwrap();
+            assert_eq!(&from_mmap, content);
+            assert_eq!(from_mmap, from_file);
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(
+            ArchiveReader::from_mmap(&path, ArchiveReaderConfig::new()).is_err(),
+            "Missing file"
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use crate::ArchiveFileID;
+
+/// Hooks called by an `ArchiveWriter` while the archive is being written, to
+/// report its progress
+///
+/// Install one with `ArchiveWriterConfig::with_progress`
+pub trait ProgressReporter {
+    /// File `name` has been started
+    fn on_file_start(&mut self, name: &str);
+    /// A block of `bytes` bytes has been written for file `id`, for a total of
+    /// `total` bytes written for this file so far
+    fn on_bytes_written(&mut self, id: ArchiveFileID, bytes: u64, total: u64);
+    /// File `name` has been ended
+    fn on_file_end(&mut self, name: &str);
+}
+
+/// Reporter ignoring every event, used by default
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopProgressReporter;
+
+impl ProgressReporter for NoopProgressReporter {
+    #[inline(always)]
+    fn on_file_start(&mut self, _name: &str) {}
+    #[inline(always)]
+    fn on_bytes_written(&mut self, _id: ArchiveFileID, _bytes: u64, _total: u64) {}
+    #[inline(always)]
+    fn on_file_end(&mut self, _name: &str) {}
+}
+
+/// Reporter printing every file start and end to stderr, for CLI use
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LoggingProgressReporter;
+
+impl ProgressReporter for LoggingProgressReporter {
+    fn on_file_start(&mut self, name: &str) {
+        eprintln!("[+] {}", name);
+    }
+
+    fn on_bytes_written(&mut self, _id: ArchiveFileID, _bytes: u64, _total: u64) {}
+
+    fn on_file_end(&mut self, name: &str) {
+        eprintln!("[=] {}", name);
+    }
+}
+
+/// Hooks called by an `ArchiveReader` while files are being read, through
+/// `ArchiveReader::get_file` or `helpers::linear_extract`, or by an
+/// `AsyncArchiveReader` through `AsyncArchiveReader::get_file`, to report the
+/// progress
+///
+/// Install one with `ArchiveReader::with_progress` or
+/// `AsyncArchiveReader::with_progress`
+pub trait ReadProgressReporter {
+    /// File `name` is about to be read. `size_hint` is its size, if known
+    fn on_file_start(&mut self, name: &str, size_hint: Option<u64>);
+    /// Content of file `name` has been read, for a total of `bytes` bytes
+    /// read for this file so far
+    fn on_bytes_read(&mut self, name: &str, bytes: u64);
+    /// File `name` has been entirely read
+    fn on_file_end(&mut self, name: &str);
+}
+
+impl ReadProgressReporter for NoopProgressReporter {
+    #[inline(always)]
+    fn on_file_start(&mut self, _name: &str, _size_hint: Option<u64>) {}
+    #[inline(always)]
+    fn on_bytes_read(&mut self, _name: &str, _bytes: u64) {}
+    #[inline(always)]
+    fn on_file_end(&mut self, _name: &str) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+    use crate::helpers::linear_extract;
+    use crate::tests::build_archive;
+    use crate::{ArchiveReader, ArchiveWriter, SparseData};
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::io::{Cursor, Read};
+    use std::rc::Rc;
+
+    #[derive(Debug, Default, PartialEq, Eq)]
+    struct Events {
+        started: Vec<String>,
+        /// (id, bytes, total)
+        written: Vec<(ArchiveFileID, u64, u64)>,
+        ended: Vec<String>,
+    }
+
+    struct RecordingReporter(Rc<RefCell<Events>>);
+
+    impl ProgressReporter for RecordingReporter {
+        fn on_file_start(&mut self, name: &str) {
+            self.0.borrow_mut().started.push(name.to_string());
+        }
+        fn on_bytes_written(&mut self, id: ArchiveFileID, bytes: u64, total: u64) {
+            self.0.borrow_mut().written.push((id, bytes, total));
+        }
+        fn on_file_end(&mut self, name: &str) {
+            self.0.borrow_mut().ended.push(name.to_string());
+        }
+    }
+
+    #[test]
+    fn progress_callbacks() {
+        let events = Rc::new(RefCell::new(Events::default()));
+        let mut config = ArchiveWriterConfig::new();
+        config.with_progress(Box::new(RecordingReporter(events.clone())));
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+
+        // 4 blocks, interleaved, plus an empty one (not written)
+        let id1 = mla.start_file("file1").unwrap();
+        let id2 = mla.start_file("file2").unwrap();
+        mla.append_file_content(id1, 2, [1, 2].as_ref()).unwrap();
+        mla.append_file_content(id2, 3, [3, 4, 5].as_ref()).unwrap();
+        mla.append_file_content(id1, 0, [].as_ref()).unwrap();
+        mla.append_file_sparse(
+            id1,
+            12,
+            &[SparseData::Zeroes(10), SparseData::Data(&[6, 7])],
+        )
+        .unwrap();
+        mla.end_file(id2).unwrap();
+        mla.end_file(id1).unwrap();
+        mla.add_file("file3", 1, [8].as_ref()).unwrap();
+        mla.finalize().unwrap();
+
+        assert_eq!(
+            *events.borrow(),
+            Events {
+                started: vec![
+                    "file1".to_string(),
+                    "file2".to_string(),
+                    "file3".to_string()
+                ],
+                written: vec![
+                    (id1, 2, 2),
+                    (id2, 3, 3),
+                    (id1, 10, 12),
+                    (id1, 2, 14),
+                    (ArchiveFileID(2), 1, 1)
+                ],
+                ended: vec![
+                    "file2".to_string(),
+                    "file1".to_string(),
+                    "file3".to_string()
+                ],
+            }
+        );
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum ReadEvent {
+        Start(String, Option<u64>),
+        Read(String, u64),
+        End(String),
+    }
+
+    struct RecordingReadReporter(Rc<RefCell<Vec<ReadEvent>>>);
+
+    impl ReadProgressReporter for RecordingReadReporter {
+        fn on_file_start(&mut self, name: &str, size_hint: Option<u64>) {
+            self.0
+                .borrow_mut()
+                .push(ReadEvent::Start(name.to_string(), size_hint));
+        }
+        fn on_bytes_read(&mut self, name: &str, bytes: u64) {
+            self.0
+                .borrow_mut()
+                .push(ReadEvent::Read(name.to_string(), bytes));
+        }
+        fn on_file_end(&mut self, name: &str) {
+            self.0.borrow_mut().push(ReadEvent::End(name.to_string()));
+        }
+    }
+
+    /// Check the events of each file: started once, then increasing counts
+    /// up to its size, then ended once
+    fn check_read_events(events: &[ReadEvent], files: &[(String, Vec<u8>)]) {
+        for (fname, content) in files {
+            let file_events: Vec<&ReadEvent> = events
+                .iter()
+                .filter(|event| match event {
+                    ReadEvent::Start(name, _) | ReadEvent::Read(name, _) | ReadEvent::End(name) => {
+                        name == fname
+                    }
+                })
+                .collect();
+            assert_eq!(
+                file_events.first(),
+                Some(&&ReadEvent::Start(
+                    fname.clone(),
+                    Some(content.len() as u64)
+                ))
+            );
+            assert_eq!(file_events.last(), Some(&&ReadEvent::End(fname.clone())));
+            let counts: Vec<u64> = file_events[1..file_events.len() - 1]
+                .iter()
+                .map(|event| match event {
+                    ReadEvent::Read(_, bytes) => *bytes,
+                    _ => panic!("Unexpected event {:?}", event),
+                })
+                .collect();
+            assert!(!counts.is_empty());
+            assert!(counts.windows(2).all(|pair| pair[0] < pair[1]));
+            assert_eq!(counts.last(), Some(&(content.len() as u64)));
+        }
+    }
+
+    #[test]
+    fn read_progress_callbacks() {
+        let (mla, key, files) = build_archive(None, true);
+        let archive = mla.into_raw();
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(Cursor::new(archive), config).unwrap();
+
+        // `get_file`, read by small chunks
+        let events = Rc::new(RefCell::new(Vec::new()));
+        mla_read.with_progress(Box::new(RecordingReadReporter(events.clone())));
+        for (fname, _) in &files {
+            let mut file = mla_read.get_file(fname).unwrap().unwrap();
+            let mut buf = [0u8; 2];
+            while file.data.read(&mut buf).unwrap() > 0 {}
+            // Reading after the end doesn't end the file again
+            assert_eq!(file.data.read(&mut buf).unwrap(), 0);
+        }
+        check_read_events(&events.borrow(), &files);
+
+        // `linear_extract`, with interleaved files
+        let events = Rc::new(RefCell::new(Vec::new()));
+        mla_read.with_progress(Box::new(RecordingReadReporter(events.clone())));
+        let mut export: HashMap<&String, Vec<u8>> =
+            files.iter().map(|(fname, _)| (fname, Vec::new())).collect();
+        linear_extract(&mut mla_read, &mut export).unwrap();
+        check_read_events(&events.borrow(), &files);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_read_progress_callbacks() {
+        use crate::async_io::AsyncArchiveReader;
+        use tokio::io::AsyncReadExt;
+
+        let (mla, key, files) = build_archive(None, true);
+        let archive = mla.into_raw();
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = AsyncArchiveReader::from_config(Cursor::new(archive), config)
+            .await
+            .unwrap();
+
+        // Each file is started once, even if data is fetched while opening it
+        let events = Rc::new(RefCell::new(Vec::new()));
+        mla_read.with_progress(Box::new(RecordingReadReporter(events.clone())));
+        for (fname, _) in &files {
+            let mut file = mla_read.get_file(fname).await.unwrap().unwrap();
+            let mut buf = [0u8; 2];
+            while file.data.read(&mut buf).await.unwrap() > 0 {}
+        }
+        check_read_events(&events.borrow(), &files);
+    }
+}
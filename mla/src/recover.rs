@@ -0,0 +1,513 @@
+use crate::config::ArchiveReaderConfig;
+use crate::errors::Error;
+use crate::layers::compress::CompressionLayerFailSafeReader;
+use crate::layers::encrypt::EncryptionLayerFailSafeReader;
+use crate::layers::raw::RawLayerFailSafeReader;
+use crate::layers::traits::{LayerFailSafeReader, LayerReader};
+use crate::progress::NoopProgressReporter;
+use crate::{
+    check_unknown_block, read_header, remaining_len, ArchiveFileBlock, ArchiveFileID,
+    ArchiveFooter, ArchiveReader, FeatureFlags, FileInfo, Layers, CACHE_SIZE,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+/// Layer providing the archive data recovered by
+/// `ArchiveReader::from_config_recover`, kept in memory
+struct RecoveredLayerReader<R: Read + Seek> {
+    inner: R,
+    data: Cursor<Vec<u8>>,
+}
+
+impl<'a, R: Read + Seek> LayerReader<'a, R> for RecoveredLayerReader<R> {
+    fn into_inner(self) -> Option<Box<dyn 'a + LayerReader<'a, R>>> {
+        None
+    }
+
+    fn into_raw(self: Box<Self>) -> R {
+        self.inner
+    }
+
+    fn initialize(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for RecoveredLayerReader<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        self.data.read(into)
+    }
+}
+
+impl<R: Read + Seek> Seek for RecoveredLayerReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.data.seek(pos)
+    }
+}
+
+/// Decode the archive data of `src`, positioned after the header, until the
+/// first error
+fn decode_failsafe<'a, S: 'a + Read>(
+    src: S,
+    config: &ArchiveReaderConfig,
+) -> Result<Vec<u8>, Error> {
+    // The signature, if any, is at the end of the archive: a truncated archive
+    // does not have it, and the data are only used until the first error. The
+    // signature layer is therefore ignored, so that no data is kept aside
+    let mut src: Box<dyn 'a + LayerFailSafeReader<'a, S>> =
+        Box::new(RawLayerFailSafeReader::new(src));
+    if config.layers_enabled.contains(Layers::ENCRYPT) {
+        src = Box::new(EncryptionLayerFailSafeReader::new(src, &config.encrypt)?);
+    }
+    if config.layers_enabled.contains(Layers::COMPRESS) {
+        src = Box::new(CompressionLayerFailSafeReader::new(src, &config.compress)?);
+    }
+
+    let mut data = Vec::new();
+    let mut buf = vec![0u8; CACHE_SIZE];
+    loop {
+        match src.read(&mut buf) {
+            Ok(0) => break,
+            Ok(count) => data.extend_from_slice(&buf[..count]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(_) => break,
+        }
+    }
+    Ok(data)
+}
+
+/// Footer rebuilt from the archive data by `rebuild_footer`
+pub(crate) struct RebuiltFooter {
+    pub(crate) footer: ArchiveFooter,
+    /// Offset, in the archive data, of the `EndOfArchiveData` block if it has
+    /// been reached
+    pub(crate) end_offset: Option<u64>,
+    /// ID of each file of the footer in the archive data. Aliases share the ID
+    /// of their original file
+    pub(crate) ids: HashMap<String, ArchiveFileID>,
+    /// First file ID not used by the archive data
+    pub(crate) next_id: ArchiveFileID,
+}
+
+/// Rebuild the footer of an archive from its data, as `ArchiveWriter` would
+/// have, keeping only entries entirely written. Unless `skip_unknown_blocks`,
+/// a block of unknown type ends the scan
+///
+/// The data are the `data_size` bytes of `src` from its current position.
+/// They are read linearly, file contents being skipped
+pub(crate) fn rebuild_footer<R: Read + Seek>(
+    src: &mut R,
+    data_size: u64,
+    skip_unknown_blocks: bool,
+) -> Result<RebuiltFooter, Error> {
+    let start = src.stream_position()?;
+    // Use a BufReader to cache, by merging them into one bigger read, small
+    // read calls (like the ones on ArchiveFileBlock reading)
+    let mut src = io::BufReader::new(src);
+    let mut names: HashMap<ArchiveFileID, String> = HashMap::new();
+    let mut ids_info: HashMap<ArchiveFileID, FileInfo> = HashMap::new();
+    let mut files_info = HashMap::new();
+    let mut ids = HashMap::new();
+    // Information of the files ended, for deduplicated files referring to them
+    let mut ended_info: HashMap<ArchiveFileID, FileInfo> = HashMap::new();
+    let mut features = FeatureFlags::empty();
+    let mut directories = BTreeSet::new();
+    let mut symlinks = BTreeMap::new();
+    let mut current_id = None;
+    let mut next_id = ArchiveFileID(0);
+    let mut end_offset = None;
+
+    // Same as `ArchiveWriter::mark_continuous_block`
+    fn mark_continuous_block(
+        ids_info: &mut HashMap<ArchiveFileID, FileInfo>,
+        current_id: &mut Option<ArchiveFileID>,
+        id: ArchiveFileID,
+        offset: u64,
+    ) -> Result<(), Error> {
+        let file_info = ids_info.get_mut(&id).ok_or(Error::DeserializationError)?;
+        if *current_id != Some(id) {
+            file_info.offsets.push(offset);
+            *current_id = Some(id);
+        }
+        Ok(())
+    }
+
+    // Stop on the first error, such as the end of the recovered data
+    let _ = (|| -> Result<(), Error> {
+        loop {
+            let offset = src.stream_position()? - start;
+            if offset >= data_size {
+                return Ok(());
+            }
+            let block = ArchiveFileBlock::from(&mut src)?;
+            if src.stream_position()? - start > data_size {
+                return Err(Error::DeserializationError);
+            }
+            match block {
+                ArchiveFileBlock::FileStart { filename, id } => {
+                    if id >= next_id {
+                        next_id = id.checked_next().ok_or(Error::FileIDExhausted)?;
+                    }
+                    names.insert(id, filename);
+                    ids_info.insert(
+                        id,
+                        FileInfo {
+                            offsets: vec![offset],
+                            size: 0,
+                            eof_offset: 0,
+                        },
+                    );
+                    current_id = Some(id);
+                }
+                ArchiveFileBlock::FileContent { length, id, .. } => {
+                    mark_continuous_block(&mut ids_info, &mut current_id, id, offset)?;
+                    // The content must be entirely recovered
+                    let end = (src.stream_position()? - start)
+                        .checked_add(length)
+                        .filter(|end| *end <= data_size)
+                        .ok_or(Error::DeserializationError)?;
+                    src.seek(SeekFrom::Start(start + end))?;
+                    if let Some(file_info) = ids_info.get_mut(&id) {
+                        file_info.size += length;
+                    }
+                }
+                ArchiveFileBlock::FileSparseHole { length, id } => {
+                    mark_continuous_block(&mut ids_info, &mut current_id, id, offset)?;
+                    if let Some(file_info) = ids_info.get_mut(&id) {
+                        file_info.size += length;
+                    }
+                    features |= FeatureFlags::HAS_SPARSE_HOLES;
+                }
+                ArchiveFileBlock::FilePrecompressedContent {
+                    uncompressed_length,
+                    id,
+                    ..
+                } => {
+                    mark_continuous_block(&mut ids_info, &mut current_id, id, offset)?;
+                    if let Some(file_info) = ids_info.get_mut(&id) {
+                        file_info.size += uncompressed_length;
+                    }
+                    features |= FeatureFlags::HAS_PRECOMPRESSED;
+                }
+                ArchiveFileBlock::FileChecksum { id, .. } => {
+                    mark_continuous_block(&mut ids_info, &mut current_id, id, offset)?;
+                    features |= FeatureFlags::HAS_CHECKSUMS;
+                }
+                ArchiveFileBlock::FileMetadata { id, .. } => {
+                    mark_continuous_block(&mut ids_info, &mut current_id, id, offset)?;
+                    features |= FeatureFlags::HAS_FILE_METADATA;
+                }
+                ArchiveFileBlock::FileSizeHint { id, .. }
+                | ArchiveFileBlock::FileTag { id, .. }
+                | ArchiveFileBlock::UserMetadata { id, .. } => {
+                    mark_continuous_block(&mut ids_info, &mut current_id, id, offset)?;
+                }
+                ArchiveFileBlock::FileRecipients { id, .. } => {
+                    mark_continuous_block(&mut ids_info, &mut current_id, id, offset)?;
+                    features |= FeatureFlags::HAS_FILE_RECIPIENTS;
+                }
+                ArchiveFileBlock::Dedup { id, refers_to_id } => {
+                    mark_continuous_block(&mut ids_info, &mut current_id, id, offset)?;
+                    let original = ended_info
+                        .get(&refers_to_id)
+                        .ok_or(Error::DeserializationError)?;
+                    if let Some(file_info) = ids_info.get_mut(&id) {
+                        file_info.offsets.extend_from_slice(&original.offsets);
+                        file_info.size += original.size;
+                    }
+                    features |= FeatureFlags::HAS_DEDUP;
+                }
+                ArchiveFileBlock::EndOfFile { id, .. } => {
+                    mark_continuous_block(&mut ids_info, &mut current_id, id, offset)?;
+                    if let (Some(filename), Some(mut file_info)) =
+                        (names.remove(&id), ids_info.remove(&id))
+                    {
+                        file_info.eof_offset = offset;
+                        ended_info.insert(id, file_info.clone());
+                        ids.insert(filename.clone(), id);
+                        files_info.insert(filename, file_info);
+                    }
+                }
+                ArchiveFileBlock::FileAlias {
+                    original_id,
+                    new_name,
+                } => {
+                    let original = ended_info
+                        .get(&original_id)
+                        .ok_or(Error::DeserializationError)?;
+                    ids.insert(new_name.clone(), original_id);
+                    files_info.insert(new_name, original.clone());
+                }
+                ArchiveFileBlock::DirectoryEntry { path, .. } => {
+                    directories.insert(path);
+                    features |= FeatureFlags::HAS_DIRECTORIES;
+                }
+                ArchiveFileBlock::Symlink {
+                    link_name, target, ..
+                } => {
+                    symlinks.insert(link_name, target);
+                    features |= FeatureFlags::HAS_SYMLINKS;
+                }
+                ArchiveFileBlock::Padding { .. } => {}
+                ArchiveFileBlock::Unknown { kind, .. } => {
+                    check_unknown_block(kind, skip_unknown_blocks)?;
+                }
+                ArchiveFileBlock::EndOfArchiveData => {
+                    end_offset = Some(offset);
+                    return Ok(());
+                }
+            }
+        }
+    })();
+
+    Ok(RebuiltFooter {
+        footer: ArchiveFooter {
+            // Only the blocks known by this reader can have been recovered
+            features,
+            files_info,
+            directories,
+            symlinks,
+            // The footer, its comment and creation time, are lost
+            comment: None,
+            created_at: None,
+        },
+        end_offset,
+        ids,
+        next_id,
+    })
+}
+
+impl<'b, R: 'b + Read + Seek> ArchiveReader<'b, R> {
+    /// Best-effort opening of a possibly truncated archive, for instance after
+    /// a network failure or a full disk while writing it
+    ///
+    /// The archive data are read linearly until the first error. Only the
+    /// files whose `EndOfFile` block has been reached, along with every
+    /// directory and symbolic link entry met, are then available; files
+    /// partially written are not listed. `is_truncated` indicates whether the
+    /// end of the archive data has been reached.
+    ///
+    /// As the footer of a truncated archive is lost, the recovered data are
+    /// kept in memory. If the configuration requires a signature to be
+    /// verified, the archive must be complete
+    pub fn from_config_recover(mut src: R, mut config: ArchiveReaderConfig) -> Result<Self, Error> {
+        let archive_size = remaining_len(&mut src)?;
+        let (format, comment, archive_id) = read_header(&mut src, &mut config)?;
+        let data = decode_failsafe(&mut src, &config)?;
+        let rebuilt = rebuild_footer(
+            &mut Cursor::new(&data),
+            data.len() as u64,
+            config.skip_unknown_blocks,
+        )?;
+
+        Ok(ArchiveReader {
+            config,
+            src: Box::new(RecoveredLayerReader {
+                inner: src,
+                data: Cursor::new(data),
+            }),
+            metadata: Some(rebuilt.footer),
+            truncated: rebuilt.end_offset.is_none(),
+            archive_size,
+            format,
+            comment,
+            archive_id,
+            progress: Box::new(NoopProgressReporter),
+            file_stats: None,
+        })
+    }
+
+    /// Whether the archive has been opened by `from_config_recover` and the
+    /// end of its data was missing. Some entries might then be missing
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ArchiveWriterConfig;
+    use crate::tests::build_archive;
+    use crate::ArchiveWriter;
+    use ed25519_dalek::SigningKey;
+    use rand::{RngCore, SeedableRng};
+    use rand_chacha::ChaChaRng;
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    fn read_file<R: Read + Seek>(mla: &mut ArchiveReader<R>, filename: &str) -> Vec<u8> {
+        let mut content = Vec::new();
+        mla.get_file(filename)
+            .unwrap()
+            .unwrap()
+            .data
+            .read_to_end(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    fn recover_truncated() {
+        // Archives writing data as is, so that the offset after each file is
+        // known: the ones of the same archive writing only its first files
+        let signing_key = SigningKey::from_bytes(&[1; 32]);
+        for layers in [Layers::EMPTY, Layers::SIGN] {
+            let files: Vec<(String, Vec<u8>)> = (0..4)
+                .map(|i| (format!("file{}", i), vec![i as u8; 100 * (i + 1)]))
+                .collect();
+            let write = |count: usize| {
+                let mut config = ArchiveWriterConfig::new();
+                config.set_layers(layers);
+                if layers.contains(Layers::SIGN) {
+                    config.sign_with(&signing_key);
+                }
+                let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+                mla.add_directory("dir", None).unwrap();
+                for (filename, content) in &files[..count] {
+                    mla.add_file(filename, content.len() as u64, content.as_slice())
+                        .unwrap();
+                }
+                mla
+            };
+            let mut mla = write(files.len());
+            mla.finalize().unwrap();
+            let archive = mla.into_raw();
+            let ends: Vec<usize> = (0..=files.len())
+                .map(|count| write(count).abort().len())
+                .collect();
+
+            for (count, end) in ends.iter().enumerate() {
+                for len in [*end, end + 1] {
+                    let mut mla = ArchiveReader::from_config_recover(
+                        Cursor::new(&archive[..len]),
+                        ArchiveReaderConfig::new(),
+                    )
+                    .unwrap();
+                    // Only the EndOfArchiveData follows the last file
+                    assert_eq!(mla.is_truncated(), count < files.len() || len == *end);
+                    let mut filenames: Vec<&String> = mla.list_files().unwrap().collect();
+                    filenames.sort();
+                    let expected: Vec<&String> = files[..count].iter().map(|f| &f.0).collect();
+                    assert_eq!(filenames, expected);
+                    assert_eq!(mla.list_directories().unwrap().collect::<Vec<_>>(), ["dir"]);
+                    for (filename, content) in &files[..count] {
+                        assert_eq!(&read_file(&mut mla, filename), content);
+                        assert_eq!(
+                            mla.get_file_size(filename).unwrap(),
+                            Some(content.len() as u64)
+                        );
+                    }
+                }
+                // Just before the end of the file, it is not recovered
+                if count > 0 {
+                    let mla = ArchiveReader::from_config_recover(
+                        Cursor::new(&archive[..end - 1]),
+                        ArchiveReaderConfig::new(),
+                    )
+                    .unwrap();
+                    assert_eq!(mla.list_files().unwrap().count(), count - 1);
+                }
+            }
+
+            // The complete archive is entirely recovered
+            let mut mla = ArchiveReader::from_config_recover(
+                Cursor::new(archive.as_slice()),
+                ArchiveReaderConfig::new(),
+            )
+            .unwrap();
+            assert!(!mla.is_truncated());
+            assert_eq!(mla.list_files().unwrap().count(), files.len());
+            for (filename, content) in &files {
+                assert_eq!(&read_file(&mut mla, filename), content);
+            }
+
+            // The signature of a truncated archive can't be verified
+            if layers.contains(Layers::SIGN) {
+                let mut config = ArchiveReaderConfig::new();
+                config.verify_with(&signing_key.verifying_key());
+                assert!(ArchiveReader::from_config_recover(
+                    Cursor::new(archive.as_slice()),
+                    config.clone()
+                )
+                .is_ok());
+                assert!(ArchiveReader::from_config_recover(
+                    Cursor::new(&archive[..archive.len() - 1]),
+                    config
+                )
+                .is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn recover_truncated_layers() {
+        // Interleaved files, encrypted and compressed
+        let (mla, key, files) = build_archive(None, true);
+        let archive = mla.into_raw();
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+
+        let mut mla =
+            ArchiveReader::from_config_recover(Cursor::new(archive.as_slice()), config.clone())
+                .unwrap();
+        assert!(!mla.is_truncated());
+        for (filename, content) in &files {
+            assert_eq!(&read_file(&mut mla, filename), content);
+            let hash_recovered = mla.get_hash(filename).unwrap();
+            let mut mla_regular =
+                ArchiveReader::from_config(Cursor::new(archive.as_slice()), config.clone())
+                    .unwrap();
+            assert_eq!(hash_recovered, mla_regular.get_hash(filename).unwrap());
+        }
+
+        // Large files, to span several compression and encryption blocks
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let files: Vec<(String, Vec<u8>)> = (0..3)
+            .map(|i| {
+                let mut content = vec![0u8; 3 * 1024 * 1024];
+                rng.fill_bytes(&mut content);
+                (format!("file{}", i), content)
+            })
+            .collect();
+        let key = StaticSecret::from([2; 32]);
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::DEFAULT)
+            .add_public_keys(&[PublicKey::from(&key)]);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        for (filename, content) in &files {
+            mla.add_file(filename, content.len() as u64, content.as_slice())
+                .unwrap();
+        }
+        mla.finalize().unwrap();
+        let archive = mla.into_raw();
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&[key]);
+
+        let mut previous = 0;
+        for len in [
+            archive.len() / 4,
+            archive.len() / 2,
+            archive.len() * 3 / 4,
+            archive.len() - 1,
+        ] {
+            let mut mla =
+                ArchiveReader::from_config_recover(Cursor::new(&archive[..len]), config.clone())
+                    .unwrap();
+            let count = mla.list_files().unwrap().count();
+            // Files are recovered in order, and more data gives more files
+            assert!(count >= previous);
+            previous = count;
+            for (filename, content) in &files[..count] {
+                assert_eq!(&read_file(&mut mla, filename), content);
+            }
+            if count < files.len() {
+                assert!(mla.is_truncated());
+            }
+        }
+        assert_eq!(previous, files.len());
+
+        // A truncated header is an error
+        assert!(ArchiveReader::from_config_recover(Cursor::new(&archive[..3]), config).is_err());
+    }
+}
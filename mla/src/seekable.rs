@@ -0,0 +1,453 @@
+use crate::errors::Error;
+use crate::layers::traits::LayerReader;
+use crate::{ArchiveFileBlock, ArchiveFileID, ArchiveFooter, ArchiveReader};
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Content block of a file, as found in the archive
+struct ContentBlock {
+    /// Position of the block in the file content
+    start: u64,
+    length: u64,
+    /// Position of the block data in the archive, `None` for a sparse hole
+    data_offset: Option<u64>,
+}
+
+/// Content of a file of an archive, with `Read` and `Seek` support, as
+/// returned by `ArchiveReader::get_file_seekable`
+///
+/// Seeking only moves the underlying archive reader: only the blocks
+/// containing the data read are decrypted and decompressed. Checksums are
+/// not verified, as the content may not be read linearly
+pub struct SeekableFile<'a, R: Read + Seek> {
+    src: &'a mut R,
+    /// Content blocks, in the file order
+    blocks: Vec<ContentBlock>,
+    size: u64,
+    pos: u64,
+    /// `src` is at the position matching `pos`
+    synced: bool,
+}
+
+impl<'a, R: Read + Seek> SeekableFile<'a, R> {
+    /// Size of the file content, in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// List the content blocks of the file `id`, from the continuous blocks
+    /// at `offsets`
+    ///
+    /// If `linear`, blocks of other files are skipped instead of ending the
+    /// continuous blocks, for use without the footer offsets
+    fn new(
+        src: &'a mut R,
+        mut id: ArchiveFileID,
+        offsets: &[u64],
+        linear: bool,
+    ) -> Result<Self, Error> {
+        let mut blocks = Vec::new();
+        let mut size = 0;
+        'offsets: for offset in offsets {
+            src.seek(SeekFrom::Start(*offset))?;
+            loop {
+                match ArchiveFileBlock::from(&mut *src)? {
+                    ArchiveFileBlock::FileContent {
+                        length,
+                        id: block_id,
+                        ..
+                    } if block_id == id => {
+                        let data_offset = src.stream_position()?;
+                        blocks.push(ContentBlock {
+                            start: size,
+                            length,
+                            data_offset: Some(data_offset),
+                        });
+                        size += length;
+                        src.seek(SeekFrom::Start(data_offset + length))?;
+                    }
+                    ArchiveFileBlock::FileSparseHole {
+                        length,
+                        id: block_id,
+                    } if block_id == id => {
+                        blocks.push(ContentBlock {
+                            start: size,
+                            length,
+                            data_offset: None,
+                        });
+                        size += length;
+                    }
+                    ArchiveFileBlock::EndOfFile { id: block_id, .. } if block_id == id => {
+                        break 'offsets;
+                    }
+                    ArchiveFileBlock::FileStart { id: block_id, .. }
+                    | ArchiveFileBlock::FileChecksum { id: block_id, .. }
+                    | ArchiveFileBlock::FileMetadata { id: block_id, .. }
+                    | ArchiveFileBlock::FileSizeHint { id: block_id, .. }
+                        if block_id == id => {}
+                    ArchiveFileBlock::FilePrecompressedContent { id: block_id, .. }
+                        if block_id == id =>
+                    {
+                        return Err(Error::WrongReaderState(
+                            "[SeekableFile] Pre-compressed contents can't be sought".to_string(),
+                        ));
+                    }
+                    ArchiveFileBlock::FileRecipients { id: block_id, .. } if block_id == id => {
+                        return Err(Error::WrongReaderState(
+                            "[SeekableFile] Files encrypted for their own recipients can't be sought"
+                                .to_string(),
+                        ));
+                    }
+                    ArchiveFileBlock::DirectoryEntry { .. }
+                    | ArchiveFileBlock::Symlink { .. }
+                    | ArchiveFileBlock::FileAlias { .. }
+                    | ArchiveFileBlock::Padding { .. }
+                    | ArchiveFileBlock::Unknown { .. } => {
+                        // Not related to any file, they do not break the
+                        // continuity of blocks
+                    }
+                    ArchiveFileBlock::Dedup {
+                        id: block_id,
+                        refers_to_id,
+                    } if block_id == id => {
+                        if linear {
+                            return Err(Error::WrongReaderState(
+                                "[SeekableFile] Deduplicated files require the footer".to_string(),
+                            ));
+                        }
+                        // The blocks of `refers_to_id` are at the next offsets
+                        id = refers_to_id;
+                        continue 'offsets;
+                    }
+                    ArchiveFileBlock::EndOfArchiveData => {
+                        return Err(Error::WrongReaderState(
+                            "[SeekableFile] Try to read the end of the archive".to_string(),
+                        ));
+                    }
+                    ArchiveFileBlock::FileContent { length, .. } if linear => {
+                        let data_offset = src.stream_position()?;
+                        src.seek(SeekFrom::Start(data_offset + length))?;
+                    }
+                    _ if linear => {}
+                    _ => {
+                        // Block of another file: move to the next continuous
+                        // blocks
+                        continue 'offsets;
+                    }
+                }
+            }
+        }
+        blocks.retain(|block| block.length > 0);
+        Ok(SeekableFile {
+            src,
+            blocks,
+            size,
+            pos: 0,
+            synced: false,
+        })
+    }
+}
+
+impl<'a, R: Read + Seek> Read for SeekableFile<'a, R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.size || into.is_empty() {
+            return Ok(0);
+        }
+        let index = self.blocks.partition_point(|block| block.start <= self.pos) - 1;
+        let block = &self.blocks[index];
+        let in_block = self.pos - block.start;
+        let size = std::cmp::min(into.len() as u64, block.length - in_block) as usize;
+        let count = match block.data_offset {
+            None => {
+                into[..size].fill(0);
+                size
+            }
+            Some(data_offset) => {
+                if !self.synced {
+                    self.src.seek(SeekFrom::Start(data_offset + in_block))?;
+                }
+                self.src.read(&mut into[..size])?
+            }
+        };
+        if count == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.pos += count as u64;
+        // The next block data is somewhere else in the archive
+        self.synced = block.data_offset.is_some() && in_block + (count as u64) < block.length;
+        Ok(count)
+    }
+}
+
+impl<'a, R: Read + Seek> Seek for SeekableFile<'a, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek to a negative or overflowing position",
+            )
+        })?;
+        if new_pos != self.pos {
+            self.pos = new_pos;
+            self.synced = false;
+        }
+        Ok(self.pos)
+    }
+}
+
+impl<'b, R: 'b + Read + Seek> ArchiveReader<'b, R> {
+    /// Return the content of the file `filename`, with `Seek` support
+    ///
+    /// The content blocks of the file are first listed, using the footer
+    /// and skipping their data. Reading then only decodes the blocks
+    /// containing the requested bytes
+    #[allow(clippy::type_complexity)]
+    pub fn get_file_seekable<'a>(
+        &'a mut self,
+        filename: &str,
+    ) -> Result<Option<SeekableFile<'a, Box<dyn 'b + LayerReader<'b, R>>>>, Error> {
+        let file_info = match &self.metadata {
+            Some(ArchiveFooter { files_info, .. }) => match files_info.get(filename) {
+                Some(file_info) => file_info,
+                None => return Ok(None),
+            },
+            None => return Err(Error::MissingMetadata),
+        };
+        let first_offset = *file_info.offsets.first().ok_or_else(|| {
+            Error::WrongReaderState(
+                "[ArchiveReader] A file must have at least one offset".to_string(),
+            )
+        })?;
+        self.src.seek(SeekFrom::Start(first_offset))?;
+        let id = match ArchiveFileBlock::from(&mut self.src)? {
+            ArchiveFileBlock::FileStart { id, .. } => id,
+            _ => {
+                return Err(Error::WrongReaderState(
+                    "[ArchiveReader] A file must start with a FileStart".to_string(),
+                ));
+            }
+        };
+        let expected_size = file_info.size;
+        let offsets = file_info.offsets.clone();
+        let file = SeekableFile::new(&mut self.src, id, &offsets, false)?;
+        if file.size != expected_size {
+            return Err(Error::WrongReaderState(
+                "[ArchiveReader] The file content does not match its size".to_string(),
+            ));
+        }
+        Ok(Some(file))
+    }
+
+    /// Return the bytes `[start, end)` of the file `filename`
+    ///
+    /// Only the content blocks overlapping the range are read, the first and
+    /// last ones partially. If the footer is not available, the archive is
+    /// scanned linearly to find the file blocks, skipping their data
+    #[allow(clippy::type_complexity)]
+    pub fn read_file_range<'a>(
+        &'a mut self,
+        filename: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<Option<io::Take<SeekableFile<'a, Box<dyn 'b + LayerReader<'b, R>>>>>, Error> {
+        if start > end {
+            return Err(Error::BadAPIArgument(
+                "[read_file_range] start must be lower or equal to end".to_string(),
+            ));
+        }
+        let mut file = if self.metadata.is_some() {
+            match self.get_file_seekable(filename)? {
+                Some(file) => file,
+                None => return Ok(None),
+            }
+        } else {
+            match find_file_start(&mut self.src, filename)? {
+                Some((id, offset)) => SeekableFile::new(&mut self.src, id, &[offset], true)?,
+                None => return Ok(None),
+            }
+        };
+        if end > file.size {
+            return Err(Error::BadAPIArgument(format!(
+                "[read_file_range] end ({}) is after the end of the file ({} bytes)",
+                end, file.size
+            )));
+        }
+        file.seek(SeekFrom::Start(start))?;
+        Ok(Some(file.take(end - start)))
+    }
+}
+
+/// Scan `src` from its start for the beginning of file `filename`, skipping
+/// content data. Return its ID and the FileStart block offset
+fn find_file_start<R: Read + Seek>(
+    src: &mut R,
+    filename: &str,
+) -> Result<Option<(ArchiveFileID, u64)>, Error> {
+    src.seek(SeekFrom::Start(0))?;
+    loop {
+        let offset = src.stream_position()?;
+        match ArchiveFileBlock::from(&mut *src)? {
+            ArchiveFileBlock::FileStart { filename: name, id } if name == filename => {
+                return Ok(Some((id, offset)));
+            }
+            ArchiveFileBlock::FileContent { length, .. } => {
+                let data_offset = src.stream_position()?;
+                src.seek(SeekFrom::Start(data_offset + length))?;
+            }
+            ArchiveFileBlock::EndOfArchiveData => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+    use crate::{ArchiveWriter, Layers, SparseData};
+    use rand::{Rng, RngCore, SeedableRng};
+    use rand_chacha::ChaChaRng;
+    use std::io::Cursor;
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    #[test]
+    fn get_file_seekable() {
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let mut part1 = vec![0u8; 5 * 1024 * 1024];
+        rng.fill_bytes(&mut part1);
+        let part2: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+        let mut expected = part1.clone();
+        expected.extend(std::iter::repeat_n(0, 3000));
+        expected.extend_from_slice(&part2);
+
+        let key = StaticSecret::from([1; 32]);
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::DEFAULT)
+            .add_public_keys(&[PublicKey::from(&key)]);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        // Interleaved with another file and a directory
+        let id = mla.start_file("file").unwrap();
+        mla.set_file_size_hint(id, expected.len() as u64).unwrap();
+        mla.add_file("other", 3, [1, 2, 3].as_ref()).unwrap();
+        mla.append_file_content(id, part1.len() as u64, part1.as_slice())
+            .unwrap();
+        mla.add_directory("dir", None).unwrap();
+        mla.append_file_sparse(
+            id,
+            3000 + part2.len() as u64,
+            &[SparseData::Zeroes(3000), SparseData::Data(&part2)],
+        )
+        .unwrap();
+        mla.add_file("empty", 0, io::empty()).unwrap();
+        mla.end_file(id).unwrap();
+        mla.finalize().unwrap();
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&[key]);
+        let mut mla_read = ArchiveReader::from_config(Cursor::new(mla.into_raw()), config).unwrap();
+        assert!(mla_read.get_file_seekable("missing").unwrap().is_none());
+
+        let mut file = mla_read.get_file_seekable("file").unwrap().unwrap();
+        assert_eq!(file.size(), expected.len() as u64);
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).unwrap();
+        assert_eq!(data, expected);
+
+        // Random accesses, crossing blocks boundaries
+        let len = expected.len() as u64;
+        let mut positions: Vec<(SeekFrom, u64)> = vec![
+            (SeekFrom::Start(0), 0),
+            (SeekFrom::End(-10), len - 10),
+            (
+                SeekFrom::Start(part1.len() as u64 - 5),
+                part1.len() as u64 - 5,
+            ),
+            // After reading 8000 bytes
+            (SeekFrom::Current(-10000), part1.len() as u64 - 2005),
+        ];
+        for _ in 0..20 {
+            let pos = rng.gen_range(0, len);
+            positions.push((SeekFrom::Start(pos), pos));
+        }
+        for (seek, pos) in positions {
+            let mut buf = [0u8; 4000];
+            assert_eq!(file.seek(seek).unwrap(), pos);
+            let count = std::cmp::min(buf.len() as u64, len - pos) as usize;
+            file.read_exact(&mut buf[..count]).unwrap();
+            assert_eq!(&buf[..count], &expected[pos as usize..pos as usize + count]);
+            // Continue the read in place
+            if len - pos < 8000 {
+                continue;
+            }
+            file.read_exact(&mut buf).unwrap();
+            assert_eq!(
+                &buf[..],
+                &expected[pos as usize + count..pos as usize + count + buf.len()]
+            );
+        }
+
+        // Out of bounds
+        assert_eq!(file.seek(SeekFrom::End(10)).unwrap(), len + 10);
+        assert_eq!(file.read(&mut [0u8; 10]).unwrap(), 0);
+        assert!(file.seek(SeekFrom::Current(-(len as i64) - 11)).is_err());
+
+        let mut file = mla_read.get_file_seekable("empty").unwrap().unwrap();
+        assert_eq!(file.size(), 0);
+        assert_eq!(file.seek(SeekFrom::End(0)).unwrap(), 0);
+        assert_eq!(file.read(&mut [0u8; 10]).unwrap(), 0);
+        let mut data = Vec::new();
+        mla_read
+            .get_file_seekable("other")
+            .unwrap()
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, vec![1, 2, 3]);
+
+        // Byte ranges, with and without the footer
+        let mut ranges = vec![
+            (0, 0),
+            (0, len),
+            (10, 20),
+            (part1.len() as u64 - 100, part1.len() as u64 + 100),
+            (part1.len() as u64 + 10, part1.len() as u64 + 20),
+            (len - 10, len),
+        ];
+        for _ in 0..10 {
+            let a = rng.gen_range(0, len);
+            let b = rng.gen_range(a, len + 1);
+            ranges.push((a, b));
+        }
+        for with_footer in [true, false] {
+            if !with_footer {
+                mla_read.metadata = None;
+            }
+            for (start, end) in &ranges {
+                let mut data = Vec::new();
+                mla_read
+                    .read_file_range("file", *start, *end)
+                    .unwrap()
+                    .unwrap()
+                    .read_to_end(&mut data)
+                    .unwrap();
+                assert_eq!(data, &expected[*start as usize..*end as usize]);
+            }
+            let mut data = Vec::new();
+            mla_read
+                .read_file_range("other", 1, 3)
+                .unwrap()
+                .unwrap()
+                .read_to_end(&mut data)
+                .unwrap();
+            assert_eq!(data, vec![2, 3]);
+            assert!(mla_read.read_file_range("missing", 0, 0).unwrap().is_none());
+            assert!(mla_read.read_file_range("file", 20, 10).is_err());
+            assert!(mla_read.read_file_range("file", 0, len + 1).is_err());
+        }
+    }
+}
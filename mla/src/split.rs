@@ -0,0 +1,497 @@
+use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+use crate::errors::Error;
+use crate::{ArchiveReader, ArchiveWriter};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaChaRng;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+
+const VOLUME_MAGIC: &[u8; 4] = b"MLAV";
+/// Size of `VolumeHeader::set_id`, in bytes
+pub const VOLUME_SET_ID_SIZE: usize = 16;
+/// Size of a serialized `VolumeHeader`, in bytes
+pub const VOLUME_HEADER_SIZE: u64 = 28;
+
+/// Header at the start of each volume of a split archive
+///
+/// ```ascii-art
+/// [magic "MLAV"][set ID (16 bytes)][volume number (u32)][volume count (u32)]
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VolumeHeader {
+    /// Random identifier of the archive, shared by all its volumes
+    pub set_id: [u8; VOLUME_SET_ID_SIZE],
+    /// Number of this volume, starting from 1
+    pub number: u32,
+    /// Total number of volumes of the archive. It is 0 until the archive has
+    /// been finalized
+    pub count: u32,
+}
+
+impl VolumeHeader {
+    pub fn from<T: Read>(src: &mut T) -> Result<Self, Error> {
+        let mut magic = [0u8; 4];
+        src.read_exact(&mut magic)?;
+        if &magic != VOLUME_MAGIC {
+            return Err(Error::WrongMagic);
+        }
+        let mut set_id = [0u8; VOLUME_SET_ID_SIZE];
+        src.read_exact(&mut set_id)?;
+        Ok(VolumeHeader {
+            set_id,
+            number: src.read_u32::<LittleEndian>()?,
+            count: src.read_u32::<LittleEndian>()?,
+        })
+    }
+
+    fn dump<T: Write>(&self, dest: &mut T) -> Result<(), Error> {
+        dest.write_all(VOLUME_MAGIC)?;
+        dest.write_all(&self.set_id)?;
+        dest.write_u32::<LittleEndian>(self.number)?;
+        dest.write_u32::<LittleEndian>(self.count)?;
+        Ok(())
+    }
+}
+
+// -------- Writer --------
+
+/// Output of a `SplitArchiveWriter`, switching to a new volume file when the
+/// current one is full
+pub struct VolumeWriter {
+    name_template: String,
+    /// Identifier of the archive, written in each volume header
+    set_id: [u8; VOLUME_SET_ID_SIZE],
+    volume_size: u64,
+    /// Path of the volumes created so far, the last one being the current one
+    paths: Vec<PathBuf>,
+    current: BufWriter<File>,
+    /// Bytes written in the current volume, header included
+    written: u64,
+}
+
+impl VolumeWriter {
+    fn volume_path(name_template: &str, number: usize) -> PathBuf {
+        PathBuf::from(format!("{}.{:03}", name_template, number))
+    }
+
+    /// Create the volume following the current one, if any
+    fn create_volume(&mut self) -> Result<(), Error> {
+        let path = Self::volume_path(&self.name_template, self.paths.len() + 1);
+        let mut volume = BufWriter::new(File::create(&path)?);
+        VolumeHeader {
+            set_id: self.set_id,
+            number: self.paths.len() as u32 + 1,
+            count: 0,
+        }
+        .dump(&mut volume)?;
+        self.current.flush()?;
+        self.current = volume;
+        self.paths.push(path);
+        self.written = VOLUME_HEADER_SIZE;
+        Ok(())
+    }
+
+    /// Flush the last volume, then set the volume count in every header
+    fn finish(mut self) -> Result<Vec<PathBuf>, Error> {
+        self.current.flush()?;
+        for path in &self.paths {
+            let mut volume = OpenOptions::new().write(true).open(path)?;
+            volume.seek(SeekFrom::Start(VOLUME_HEADER_SIZE - 4))?;
+            volume.write_u32::<LittleEndian>(self.paths.len() as u32)?;
+        }
+        Ok(self.paths)
+    }
+}
+
+impl Write for VolumeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // Volumes are created only once there is data to put in them
+        if self.written >= self.volume_size {
+            self.create_volume()?;
+        }
+        let size = std::cmp::min(buf.len() as u64, self.volume_size - self.written) as usize;
+        let written = self.current.write(&buf[..size])?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.current.flush()
+    }
+}
+
+/// Writer of an archive split into volumes of at most `volume_size` bytes
+///
+/// The volumes are named after `name_template`, followed by their number on
+/// 3 digits starting from 1 (`archive.mla.001`, `archive.mla.002`, etc.).
+/// The archive is written with the usual `ArchiveWriter` API, and must be
+/// ended with `SplitArchiveWriter::finalize`, which sets the volume count in
+/// every volume header
+pub struct SplitArchiveWriter<'a> {
+    archive: ArchiveWriter<'a, VolumeWriter>,
+}
+
+impl<'a> SplitArchiveWriter<'a> {
+    pub fn new(
+        volume_size: u64,
+        name_template: &str,
+        config: ArchiveWriterConfig,
+    ) -> Result<Self, Error> {
+        if volume_size <= VOLUME_HEADER_SIZE {
+            return Err(Error::BadAPIArgument(format!(
+                "The volume size must be greater than the volume header size ({} bytes)",
+                VOLUME_HEADER_SIZE
+            )));
+        }
+        let mut set_id = [0u8; VOLUME_SET_ID_SIZE];
+        ChaChaRng::from_entropy().fill_bytes(&mut set_id);
+        let path = VolumeWriter::volume_path(name_template, 1);
+        let mut current = BufWriter::new(File::create(&path)?);
+        VolumeHeader {
+            set_id,
+            number: 1,
+            count: 0,
+        }
+        .dump(&mut current)?;
+        let volumes = VolumeWriter {
+            name_template: name_template.to_string(),
+            set_id,
+            volume_size,
+            paths: vec![path],
+            current,
+            written: VOLUME_HEADER_SIZE,
+        };
+        Ok(SplitArchiveWriter {
+            archive: ArchiveWriter::from_config(volumes, config)?,
+        })
+    }
+
+    /// Finalize the archive, then its volumes. Return the path of the
+    /// volumes, in order
+    pub fn finalize(mut self) -> Result<Vec<PathBuf>, Error> {
+        self.archive.finalize()?;
+        self.archive.into_raw().finish()
+    }
+}
+
+impl<'a> Deref for SplitArchiveWriter<'a> {
+    type Target = ArchiveWriter<'a, VolumeWriter>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.archive
+    }
+}
+
+impl<'a> DerefMut for SplitArchiveWriter<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.archive
+    }
+}
+
+// -------- Reader --------
+
+/// Source of a `SplitArchiveReader`: the concatenation of the volumes data,
+/// without their headers
+pub struct VolumeReader {
+    volumes: Vec<File>,
+    /// Position, in the concatenated data, of each volume start
+    starts: Vec<u64>,
+    /// Total size of the concatenated data
+    len: u64,
+    pos: u64,
+    /// The current volume file is at the position matching `pos`
+    synced: bool,
+}
+
+impl Read for VolumeReader {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || into.is_empty() {
+            return Ok(0);
+        }
+        let index = self.starts.partition_point(|start| *start <= self.pos) - 1;
+        let end = self.starts.get(index + 1).copied().unwrap_or(self.len);
+        let volume = &mut self.volumes[index];
+        if !self.synced {
+            volume.seek(SeekFrom::Start(
+                VOLUME_HEADER_SIZE + self.pos - self.starts[index],
+            ))?;
+        }
+        let size = std::cmp::min(into.len() as u64, end - self.pos) as usize;
+        let count = volume.read(&mut into[..size])?;
+        self.pos += count as u64;
+        // The next volume must be sought to its data
+        self.synced = self.pos < end;
+        Ok(count)
+    }
+}
+
+impl Seek for VolumeReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek to a negative or overflowing position",
+            )
+        })?;
+        if new_pos != self.pos {
+            self.pos = new_pos;
+            self.synced = false;
+        }
+        Ok(self.pos)
+    }
+}
+
+/// Reader of an archive split into volumes by a `SplitArchiveWriter`
+///
+/// The archive is read with the usual `ArchiveReader` API
+pub struct SplitArchiveReader<'a> {
+    archive: ArchiveReader<'a, VolumeReader>,
+}
+
+impl<'a> SplitArchiveReader<'a> {
+    /// Open the archive made of the volumes at `paths`, in any order
+    ///
+    /// Every volume of the archive, and only them, must be provided, once
+    pub fn from_volumes(paths: &[PathBuf], config: ArchiveReaderConfig) -> Result<Self, Error> {
+        let mut volumes = Vec::with_capacity(paths.len());
+        for path in paths {
+            let mut volume = File::open(path)?;
+            let header = VolumeHeader::from(&mut volume)?;
+            let size = volume.seek(SeekFrom::End(0))? - VOLUME_HEADER_SIZE;
+            volumes.push((header, volume, size));
+        }
+        volumes.sort_by_key(|(header, _, _)| header.number);
+
+        let set_id = volumes.first().map(|(header, _, _)| header.set_id);
+        for (index, (header, _, _)) in volumes.iter().enumerate() {
+            if Some(header.set_id) != set_id {
+                return Err(Error::WrongVolume(
+                    "The volumes belong to different archives".to_string(),
+                ));
+            }
+            if header.count == 0 {
+                return Err(Error::WrongVolume(
+                    "The archive has not been finalized".to_string(),
+                ));
+            }
+            if header.count as usize != volumes.len() || header.number as usize != index + 1 {
+                return Err(Error::WrongVolume(format!(
+                    "Expected {} volumes, numbered from 1",
+                    header.count
+                )));
+            }
+        }
+
+        let mut starts = Vec::with_capacity(volumes.len());
+        let mut len = 0;
+        for (_, _, size) in &volumes {
+            starts.push(len);
+            len += size;
+        }
+        let mut src = VolumeReader {
+            volumes: volumes.into_iter().map(|(_, volume, _)| volume).collect(),
+            starts,
+            len,
+            pos: 0,
+            synced: false,
+        };
+        src.seek(SeekFrom::Start(0))?;
+        Ok(SplitArchiveReader {
+            archive: ArchiveReader::from_config(src, config)?,
+        })
+    }
+}
+
+impl<'a> Deref for SplitArchiveReader<'a> {
+    type Target = ArchiveReader<'a, VolumeReader>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.archive
+    }
+}
+
+impl<'a> DerefMut for SplitArchiveReader<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.archive
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Layers;
+    use rand::{RngCore, SeedableRng};
+    use rand_chacha::ChaChaRng;
+    use std::fs;
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    #[test]
+    fn split_archive() {
+        let root = std::env::temp_dir().join(format!("mla_split_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let template = root.join("archive.mla");
+        let volume_size = 10 * 1024 * 1024;
+
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let files: Vec<(String, Vec<u8>)> = (0..100)
+            .map(|i| {
+                let mut content = vec![0u8; 256 * 1024 + i];
+                rng.fill_bytes(&mut content);
+                (format!("file{}", i), content)
+            })
+            .collect();
+        let key = StaticSecret::from([1; 32]);
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::ENCRYPT)
+            .add_public_keys(&[PublicKey::from(&key)]);
+        let mut mla =
+            SplitArchiveWriter::new(volume_size, template.to_str().unwrap(), config).unwrap();
+        for (filename, content) in &files {
+            mla.add_file(filename, content.len() as u64, content.as_slice())
+                .unwrap();
+        }
+        let paths = mla.finalize().unwrap();
+
+        // 25MB of data, in 3 volumes, sharing the same identifier
+        assert_eq!(paths.len(), 3);
+        let set_ids: Vec<[u8; VOLUME_SET_ID_SIZE]> = paths
+            .iter()
+            .map(|path| {
+                VolumeHeader::from(&mut File::open(path).unwrap())
+                    .unwrap()
+                    .set_id
+            })
+            .collect();
+        assert!(set_ids.iter().all(|set_id| *set_id == set_ids[0]));
+        for (i, path) in paths.iter().enumerate() {
+            assert_eq!(
+                path,
+                &PathBuf::from(format!("{}.{:03}", template.to_str().unwrap(), i + 1))
+            );
+            let size = fs::metadata(path).unwrap().len();
+            if i + 1 < paths.len() {
+                assert_eq!(size, volume_size);
+            } else {
+                assert!(size <= volume_size);
+            }
+            let header = VolumeHeader::from(&mut File::open(path).unwrap()).unwrap();
+            assert_eq!(
+                header,
+                VolumeHeader {
+                    set_id: set_ids[0],
+                    number: i as u32 + 1,
+                    count: 3
+                }
+            );
+        }
+
+        // Volumes are taken in any order
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let shuffled = vec![paths[2].clone(), paths[0].clone(), paths[1].clone()];
+        let mut mla_read = SplitArchiveReader::from_volumes(&shuffled, config.clone()).unwrap();
+        assert_eq!(mla_read.list_files().unwrap().count(), files.len());
+        for (filename, content) in files.iter().rev() {
+            let mut data = Vec::new();
+            mla_read
+                .get_file(filename)
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut data)
+                .unwrap();
+            assert_eq!(&data, content);
+        }
+
+        // Missing or duplicated volumes are detected
+        assert!(matches!(
+            SplitArchiveReader::from_volumes(&paths[..2], config.clone()),
+            Err(Error::WrongVolume(_))
+        ));
+        let duplicated = vec![paths[0].clone(), paths[1].clone(), paths[1].clone()];
+        assert!(matches!(
+            SplitArchiveReader::from_volumes(&duplicated, config),
+            Err(Error::WrongVolume(_))
+        ));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn split_archive_errors() {
+        let root = std::env::temp_dir().join(format!("mla_split_err_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let template = root.join("archive.mla");
+        let template = template.to_str().unwrap();
+
+        assert!(matches!(
+            SplitArchiveWriter::new(VOLUME_HEADER_SIZE, template, ArchiveWriterConfig::new()),
+            Err(Error::BadAPIArgument(_))
+        ));
+
+        // Tiny volumes, some of them holding a single byte of data
+        let mut mla =
+            SplitArchiveWriter::new(VOLUME_HEADER_SIZE + 1, template, ArchiveWriterConfig::new())
+                .unwrap();
+        mla.add_file("file", 3, [1, 2, 3].as_ref()).unwrap();
+        mla.flush().unwrap();
+        // Not finalized yet
+        let paths: Vec<PathBuf> = (1..=3)
+            .map(|i| VolumeWriter::volume_path(template, i))
+            .collect();
+        assert!(matches!(
+            SplitArchiveReader::from_volumes(&paths, ArchiveReaderConfig::new()),
+            Err(Error::WrongVolume(_))
+        ));
+        let paths = mla.finalize().unwrap();
+        assert!(paths.len() > 3);
+        let mut mla_read =
+            SplitArchiveReader::from_volumes(&paths, ArchiveReaderConfig::new()).unwrap();
+        let mut data = Vec::new();
+        mla_read
+            .get_file("file")
+            .unwrap()
+            .unwrap()
+            .data
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, vec![1, 2, 3]);
+
+        // Volumes of another archive, even with the same content, are rejected
+        let other_template = root.join("other.mla");
+        let mut mla = SplitArchiveWriter::new(
+            VOLUME_HEADER_SIZE + 1,
+            other_template.to_str().unwrap(),
+            ArchiveWriterConfig::new(),
+        )
+        .unwrap();
+        mla.add_file("file", 3, [1, 2, 3].as_ref()).unwrap();
+        let other_paths = mla.finalize().unwrap();
+        assert_eq!(other_paths.len(), paths.len());
+        let mut mixed = paths.clone();
+        mixed[1] = other_paths[1].clone();
+        assert!(matches!(
+            SplitArchiveReader::from_volumes(&mixed, ArchiveReaderConfig::new()),
+            Err(Error::WrongVolume(_))
+        ));
+
+        // Other files are rejected
+        fs::write(root.join("other"), b"not a volume").unwrap();
+        assert!(matches!(
+            SplitArchiveReader::from_volumes(&[root.join("other")], ArchiveReaderConfig::new()),
+            Err(Error::WrongMagic)
+        ));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,242 @@
+use crate::errors::Error;
+use crate::{check_unknown_block, ArchiveFileBlock, ArchiveFileID, ArchiveReader, Layers};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::Duration;
+
+/// Summary of an archive content, see `ArchiveReader::statistics`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ArchiveStatistics {
+    /// Number of files, directories and symbolic links excluded
+    pub file_count: usize,
+    /// Size of the archive as stored, in bytes (header, layers overhead and
+    /// footer included)
+    pub total_compressed_bytes: u64,
+    /// Total size of the files content, in bytes. Unknown for an archive
+    /// recovered from truncated data, as some files are missing
+    pub total_uncompressed_bytes: Option<u64>,
+    /// Name and size, in bytes, of the largest file, if any
+    pub largest_file: Option<(String, u64)>,
+    /// `total_uncompressed_bytes` over `total_compressed_bytes`, if known
+    pub compression_ratio: Option<f64>,
+    /// Number of public keys the archive is encrypted for (0 if it is not
+    /// encrypted)
+    pub encryption_recipients: usize,
+}
+
+impl fmt::Display for ArchiveStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Files: {}", self.file_count)?;
+        writeln!(f, "Archive size: {} bytes", self.total_compressed_bytes)?;
+        match self.total_uncompressed_bytes {
+            Some(size) => writeln!(f, "Content size: {} bytes", size)?,
+            None => writeln!(f, "Content size: unknown")?,
+        }
+        match self.compression_ratio {
+            Some(ratio) => writeln!(f, "Compression ratio: {:.2}", ratio)?,
+            None => writeln!(f, "Compression ratio: unknown")?,
+        }
+        match &self.largest_file {
+            Some((filename, size)) => writeln!(f, "Largest file: {} ({} bytes)", filename, size)?,
+            None => writeln!(f, "Largest file: none")?,
+        }
+        write!(f, "Encryption recipients: {}", self.encryption_recipients)
+    }
+}
+
+/// Summary of an archive creation, returned by `ArchiveWriter::finalize`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WriteStatistics {
+    /// Number of files in the archive, directories and symbolic links
+    /// excluded
+    pub files_written: usize,
+    /// Size of the archive as stored, in bytes (header, layers overhead and
+    /// footer included)
+    pub total_bytes_written: u64,
+    /// Total size of the content appended to files, in bytes
+    pub total_bytes_uncompressed: u64,
+    /// `total_bytes_uncompressed` over `total_bytes_written`
+    pub compression_ratio: f64,
+    /// Time elapsed since the writer creation
+    pub duration: Duration,
+    /// Number of files whose size differs from the one announced with
+    /// `ArchiveWriter::set_file_size_hint`
+    pub size_hint_mismatches: usize,
+}
+
+impl<'b, R: 'b + Read + Seek> ArchiveReader<'b, R> {
+    /// Compute statistics on the archive content
+    ///
+    /// The archive data are scanned linearly, without using the footer, and
+    /// file contents are skipped. For an archive opened with
+    /// `from_config_recover`, the scan stops at the end of the recovered data
+    /// and only entirely recovered files are counted
+    pub fn statistics(&mut self) -> Result<ArchiveStatistics, Error> {
+        self.src.seek(SeekFrom::Start(0))?;
+        // Merge small reads, like the ones on ArchiveFileBlock reading
+        let mut src = io::BufReader::new(&mut self.src);
+
+        let mut stats = ArchiveStatistics {
+            total_compressed_bytes: self.archive_size,
+            encryption_recipients: if self.config.layers_enabled.contains(Layers::ENCRYPT) {
+                self.config.encrypt.recipients_count()
+            } else {
+                0
+            },
+            ..Default::default()
+        };
+        let mut total_uncompressed_bytes = 0;
+        // Files started, not yet ended, with their size so far
+        let mut files: HashMap<ArchiveFileID, (String, u64)> = HashMap::new();
+        // Size of the files ended, for deduplicated files referring to them
+        let mut ended_sizes: HashMap<ArchiveFileID, u64> = HashMap::new();
+
+        let finished = loop {
+            let block = match ArchiveFileBlock::from(&mut src) {
+                Ok(block) => block,
+                Err(_) if self.truncated => break false,
+                Err(err) => return Err(err),
+            };
+            match block {
+                ArchiveFileBlock::FileStart { filename, id } => {
+                    files.insert(id, (filename, 0));
+                }
+                ArchiveFileBlock::FileContent { length, id, .. } => {
+                    src.seek_relative(length as i64)?;
+                    if let Some((_, size)) = files.get_mut(&id) {
+                        *size += length;
+                    }
+                }
+                ArchiveFileBlock::FileSparseHole { length, id }
+                | ArchiveFileBlock::FilePrecompressedContent {
+                    uncompressed_length: length,
+                    id,
+                    ..
+                } => {
+                    if let Some((_, size)) = files.get_mut(&id) {
+                        *size += length;
+                    }
+                }
+                ArchiveFileBlock::Dedup { id, refers_to_id } => {
+                    if let (Some((_, size)), Some(original)) =
+                        (files.get_mut(&id), ended_sizes.get(&refers_to_id))
+                    {
+                        *size += original;
+                    }
+                }
+                ArchiveFileBlock::EndOfFile { id, .. } => {
+                    if let Some((filename, size)) = files.remove(&id) {
+                        ended_sizes.insert(id, size);
+                        stats.file_count += 1;
+                        total_uncompressed_bytes += size;
+                        if stats
+                            .largest_file
+                            .as_ref()
+                            .is_none_or(|(_, largest)| size > *largest)
+                        {
+                            stats.largest_file = Some((filename, size));
+                        }
+                    }
+                }
+                ArchiveFileBlock::FileChecksum { .. }
+                | ArchiveFileBlock::FileMetadata { .. }
+                | ArchiveFileBlock::FileSizeHint { .. }
+                | ArchiveFileBlock::FileRecipients { .. }
+                | ArchiveFileBlock::FileTag { .. }
+                | ArchiveFileBlock::UserMetadata { .. }
+                | ArchiveFileBlock::DirectoryEntry { .. }
+                | ArchiveFileBlock::Symlink { .. }
+                | ArchiveFileBlock::FileAlias { .. }
+                | ArchiveFileBlock::Padding { .. } => {}
+                ArchiveFileBlock::Unknown { kind, .. } => {
+                    check_unknown_block(kind, self.config.skip_unknown_blocks)?;
+                }
+                ArchiveFileBlock::EndOfArchiveData => break true,
+            }
+        };
+
+        if finished {
+            stats.total_uncompressed_bytes = Some(total_uncompressed_bytes);
+            if stats.total_compressed_bytes > 0 {
+                stats.compression_ratio =
+                    Some(total_uncompressed_bytes as f64 / stats.total_compressed_bytes as f64);
+            }
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+    use crate::{ArchiveWriter, SparseData};
+    use std::io::Cursor;
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    #[test]
+    fn statistics() {
+        let keys = [StaticSecret::from([1; 32]), StaticSecret::from([2; 32])];
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::DEFAULT)
+            .add_public_keys(&[PublicKey::from(&keys[0]), PublicKey::from(&keys[1])]);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("small", 10, [1; 10].as_ref()).unwrap();
+        mla.add_directory("dir", None).unwrap();
+        let id = mla.start_file("large").unwrap();
+        mla.append_file_content(id, 1000, [2; 1000].as_ref())
+            .unwrap();
+        mla.append_file_sparse(id, 5000, &[SparseData::Zeroes(5000)])
+            .unwrap();
+        mla.end_file(id).unwrap();
+        mla.add_file("empty", 0, io::empty()).unwrap();
+        mla.finalize().unwrap();
+        let archive = mla.into_raw();
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&keys[1..]);
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(archive.as_slice()), config.clone()).unwrap();
+        let stats = mla_read.statistics().unwrap();
+        assert_eq!(stats.file_count, 3);
+        assert_eq!(stats.total_compressed_bytes, archive.len() as u64);
+        assert_eq!(stats.total_uncompressed_bytes, Some(6010));
+        assert_eq!(stats.largest_file, Some(("large".to_string(), 6000)));
+        assert_eq!(stats.compression_ratio, Some(6010. / archive.len() as f64));
+        assert_eq!(stats.encryption_recipients, 2);
+        assert_eq!(
+            stats.to_string(),
+            format!(
+                "Files: 3\nArchive size: {} bytes\nContent size: 6010 bytes\n\
+                 Compression ratio: {:.2}\nLargest file: large (6000 bytes)\n\
+                 Encryption recipients: 2",
+                archive.len(),
+                6010. / archive.len() as f64
+            )
+        );
+        // The reader is still usable
+        assert!(mla_read.get_file("small").unwrap().is_some());
+
+        // Truncated archive: only entirely recovered files are counted
+        let mut mla_read =
+            ArchiveReader::from_config_recover(Cursor::new(&archive[..archive.len() / 2]), config)
+                .unwrap();
+        let stats = mla_read.statistics().unwrap();
+        assert_eq!(stats.total_compressed_bytes, archive.len() as u64 / 2);
+        assert_eq!(stats.file_count, mla_read.list_files().unwrap().count());
+        assert_eq!(stats.total_uncompressed_bytes, None);
+        assert_eq!(stats.compression_ratio, None);
+
+        // Not encrypted archive
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        mla.finalize().unwrap();
+        let mut mla_read = ArchiveReader::new(Cursor::new(mla.into_raw())).unwrap();
+        let stats = mla_read.statistics().unwrap();
+        assert_eq!(stats.file_count, 0);
+        assert_eq!(stats.largest_file, None);
+        assert_eq!(stats.encryption_recipients, 0);
+        assert!(stats.to_string().contains("Largest file: none"));
+    }
+}
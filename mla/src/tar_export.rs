@@ -0,0 +1,464 @@
+use crate::errors::Error;
+use crate::metadata::FileMetadata;
+use crate::{ArchiveReader, EntryKind};
+use std::io::{self, Read, Seek, Write};
+use std::time::UNIX_EPOCH;
+use tar::{Builder, EntryType, Header};
+
+/// Format of the exported tar headers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TarFormat {
+    /// GNU tar format
+    Gnu,
+    /// POSIX (ustar) format
+    Posix,
+}
+
+/// Handling of the archive symbolic links on tar export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymlinkHandling {
+    /// Export them as tar symbolic links
+    Preserve,
+    /// Do not export them
+    Skip,
+}
+
+/// Options of `ArchiveReader::to_tar_with_config`
+#[derive(Debug, Clone)]
+pub struct TarExportOptions {
+    format: TarFormat,
+    /// Root directory of the exported entries, if any
+    prefix: Option<String>,
+    symlinks: SymlinkHandling,
+    /// Export the files metadata, if stored in the archive
+    metadata: bool,
+}
+
+impl std::default::Default for TarExportOptions {
+    fn default() -> Self {
+        TarExportOptions {
+            format: TarFormat::Gnu,
+            prefix: None,
+            symlinks: SymlinkHandling::Preserve,
+            metadata: true,
+        }
+    }
+}
+
+/// Default permissions of exported entries, without metadata
+const DEFAULT_FILE_MODE: u32 = 0o644;
+const DEFAULT_DIRECTORY_MODE: u32 = 0o755;
+const DEFAULT_SYMLINK_MODE: u32 = 0o777;
+
+impl TarExportOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the format of the tar headers (default: `TarFormat::Gnu`)
+    pub fn with_format(&mut self, format: TarFormat) -> &mut TarExportOptions {
+        self.format = format;
+        self
+    }
+
+    /// Export every entry under the `prefix` directory
+    pub fn with_prefix(&mut self, prefix: &str) -> &mut TarExportOptions {
+        self.prefix = Some(prefix.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Set how symbolic links are exported (default:
+    /// `SymlinkHandling::Preserve`)
+    pub fn with_symlinks(&mut self, symlinks: SymlinkHandling) -> &mut TarExportOptions {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// Export the metadata of files, if stored in the archive (default:
+    /// `true`). Otherwise, or without stored metadata, the entries are
+    /// exported with default permissions, owned by root, and dated from the
+    /// UNIX epoch
+    pub fn with_metadata(&mut self, metadata: bool) -> &mut TarExportOptions {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Path of the tar entry corresponding to the archive entry `name`
+    ///
+    /// Paths are made relative, the trivial way (as in `mlar to-tar`)
+    fn tar_path(&self, name: &str) -> String {
+        let name = name.trim_start_matches('/');
+        match &self.prefix {
+            Some(prefix) => format!("{}/{}", prefix, name),
+            None => name.to_string(),
+        }
+    }
+
+    fn header(
+        &self,
+        entry_type: EntryType,
+        size: u64,
+        metadata: Option<&FileMetadata>,
+        default_mode: u32,
+    ) -> Header {
+        let mut header = match self.format {
+            TarFormat::Gnu => Header::new_gnu(),
+            TarFormat::Posix => Header::new_ustar(),
+        };
+        header.set_entry_type(entry_type);
+        header.set_size(size);
+        let metadata = metadata.filter(|_| self.metadata);
+        header.set_mode(
+            metadata
+                .and_then(|metadata| metadata.mode)
+                .unwrap_or(default_mode),
+        );
+        header.set_uid(metadata.and_then(|metadata| metadata.uid).unwrap_or(0) as u64);
+        header.set_gid(metadata.and_then(|metadata| metadata.gid).unwrap_or(0) as u64);
+        header.set_mtime(
+            metadata
+                .and_then(|metadata| metadata.mtime)
+                .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+                .map(|mtime| mtime.as_secs())
+                .unwrap_or(0),
+        );
+        header
+    }
+}
+
+impl<'b, R: 'b + Read + Seek> ArchiveReader<'b, R> {
+    /// Write the archive content to `out`, as a tar archive, with the default
+    /// `TarExportOptions`
+    ///
+    /// See `to_tar_with_config`
+    pub fn to_tar<W: Write>(&mut self, out: W) -> Result<(), Error> {
+        self.to_tar_with_config(out, TarExportOptions::new())
+    }
+
+    /// Write the archive content to `out`, as a tar archive
+    ///
+    /// Directories come first, then files in their order of appearance in the
+    /// archive, then symbolic links. Unless file contents are interleaved, the
+    /// archive is then read linearly. `out` is only written sequentially, and
+    /// can be a pipe. Only the metadata of files are exported: directories
+    /// and symbolic links are exported with default ones
+    pub fn to_tar_with_config<W: Write>(
+        &mut self,
+        out: W,
+        options: TarExportOptions,
+    ) -> Result<(), Error> {
+        let mut builder = Builder::new(out);
+        self.append_to_tar_builder(&mut builder, &options)?;
+        builder.finish()?;
+        Ok(())
+    }
+
+    /// Append the archive content to `builder`, with the default
+    /// `TarExportOptions`
+    ///
+    /// See `stream_to_tar_builder_with_config`
+    pub fn stream_to_tar_builder<W: Write>(
+        &mut self,
+        builder: &mut Builder<W>,
+    ) -> Result<(), Error> {
+        self.stream_to_tar_builder_with_config(builder, &TarExportOptions::new())
+    }
+
+    /// Append the archive content to `builder`, in a single linear pass
+    ///
+    /// Entries are appended in their order of appearance in the archive, each
+    /// file content being streamed from the archive to `builder` without
+    /// intermediate buffering. `builder` is not finished, so that other
+    /// entries can be added to it.
+    ///
+    /// If a file content can't be read linearly, as it is interleaved with
+    /// other files or deduplicated, entries are appended as with
+    /// `to_tar_with_config` instead, seeking in the archive
+    pub fn stream_to_tar_builder_with_config<W: Write>(
+        &mut self,
+        builder: &mut Builder<W>,
+        options: &TarExportOptions,
+    ) -> Result<(), Error> {
+        let footer = self.metadata.as_ref().ok_or(Error::MissingMetadata)?;
+        if footer
+            .files_info
+            .values()
+            .any(|file_info| file_info.offsets.len() != 1)
+        {
+            return self.append_to_tar_builder(builder, options);
+        }
+        for entry in self.entries()? {
+            let entry = entry?;
+            match entry.kind {
+                EntryKind::Directory => {
+                    let mut header =
+                        options.header(EntryType::Directory, 0, None, DEFAULT_DIRECTORY_MODE);
+                    let path = format!(
+                        "{}/",
+                        options.tar_path(&entry.filename).trim_end_matches('/')
+                    );
+                    builder.append_data(&mut header, path, io::empty())?;
+                }
+                EntryKind::File => {
+                    let size = entry.size.ok_or_else(|| {
+                        Error::WrongReaderState(
+                            "[stream_to_tar_builder] Unknown file size".to_string(),
+                        )
+                    })?;
+                    let mut header = options.header(
+                        EntryType::Regular,
+                        size,
+                        entry.metadata.as_ref(),
+                        DEFAULT_FILE_MODE,
+                    );
+                    let path = options.tar_path(&entry.filename);
+                    builder.append_data(&mut header, path, entry)?;
+                }
+                EntryKind::Symlink => {
+                    if options.symlinks == SymlinkHandling::Preserve {
+                        let target = entry.link_target.as_deref().unwrap_or_default();
+                        let mut header =
+                            options.header(EntryType::Symlink, 0, None, DEFAULT_SYMLINK_MODE);
+                        builder.append_link(
+                            &mut header,
+                            options.tar_path(&entry.filename),
+                            target,
+                        )?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Append the archive content to `builder`, seeking to each file, see
+    /// `to_tar_with_config`
+    fn append_to_tar_builder<W: Write>(
+        &mut self,
+        builder: &mut Builder<W>,
+        options: &TarExportOptions,
+    ) -> Result<(), Error> {
+        let footer = self.metadata.as_ref().ok_or(Error::MissingMetadata)?;
+        let directories: Vec<String> = footer.directories.iter().cloned().collect();
+        let symlinks: Vec<(String, String)> = footer
+            .symlinks
+            .iter()
+            .map(|(link_name, target)| (link_name.clone(), target.clone()))
+            .collect();
+        let mut files: Vec<(u64, String)> = footer
+            .files_info
+            .iter()
+            .map(|(fname, info)| (info.offsets.first().copied().unwrap_or(0), fname.clone()))
+            .collect();
+        files.sort();
+
+        for path in directories {
+            let mut header = options.header(EntryType::Directory, 0, None, DEFAULT_DIRECTORY_MODE);
+            // As usual in tar archives, directory names end with a '/'
+            let path = format!("{}/", options.tar_path(&path).trim_end_matches('/'));
+            builder.append_data(&mut header, path, io::empty())?;
+        }
+        for (_offset, fname) in files {
+            let file = self.get_file(fname)?.ok_or_else(|| {
+                Error::WrongReaderState("[to_tar] A listed file can't be found".to_string())
+            })?;
+            let mut header = options.header(
+                EntryType::Regular,
+                file.size,
+                file.metadata.as_ref(),
+                DEFAULT_FILE_MODE,
+            );
+            builder.append_data(&mut header, options.tar_path(&file.filename), file.data)?;
+        }
+        if options.symlinks == SymlinkHandling::Preserve {
+            for (link_name, target) in symlinks {
+                let mut header = options.header(EntryType::Symlink, 0, None, DEFAULT_SYMLINK_MODE);
+                builder.append_link(&mut header, options.tar_path(&link_name), target)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+    use crate::{ArchiveWriter, Layers};
+    use std::io::Cursor;
+    use std::process::{Command, Stdio};
+    use std::time::Duration;
+
+    /// Build an unencrypted archive with a directory, interleaved files and a
+    /// symbolic link
+    fn build_archive() -> Vec<u8> {
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        mla.add_directory("dir", None).unwrap();
+        let metadata = FileMetadata {
+            mtime: Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000)),
+            mode: Some(0o600),
+            uid: None,
+            gid: None,
+        };
+        let id1 = mla
+            .start_file_with_metadata("/dir/file1", &metadata)
+            .unwrap();
+        let id2 = mla.start_file("file2").unwrap();
+        mla.append_file_content(id1, 2, [1, 2].as_ref()).unwrap();
+        mla.append_file_content(id2, 1, [3].as_ref()).unwrap();
+        mla.append_file_content(id1, 2, [4, 5].as_ref()).unwrap();
+        mla.end_file(id2).unwrap();
+        mla.end_file(id1).unwrap();
+        mla.add_symlink("link", "file2", None).unwrap();
+        mla.finalize().unwrap();
+        mla.into_raw()
+    }
+
+    /// List the content of `tar_data` with the `tar` command
+    fn tar_list(tar_data: &[u8]) -> Vec<String> {
+        let mut child = Command::new("tar")
+            .arg("-tf")
+            .arg("-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(tar_data).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn to_tar() {
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(build_archive()), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut tar_data = Vec::new();
+        mla_read.to_tar(&mut tar_data).unwrap();
+
+        assert_eq!(
+            tar_list(&tar_data),
+            vec!["dir/", "dir/file1", "file2", "link"]
+        );
+
+        // Check contents, metadata and link target
+        let mut tar_archive = tar::Archive::new(tar_data.as_slice());
+        let mut entries = tar_archive.entries().unwrap();
+        assert_eq!(
+            entries.next().unwrap().unwrap().header().entry_type(),
+            EntryType::Directory
+        );
+        let mut file1 = entries.next().unwrap().unwrap();
+        assert_eq!(file1.header().mode().unwrap(), 0o600);
+        assert_eq!(file1.header().mtime().unwrap(), 1_600_000_000);
+        let mut content = Vec::new();
+        file1.read_to_end(&mut content).unwrap();
+        assert_eq!(content, vec![1, 2, 4, 5]);
+        let mut file2 = entries.next().unwrap().unwrap();
+        assert_eq!(file2.header().mode().unwrap(), DEFAULT_FILE_MODE);
+        let mut content = Vec::new();
+        file2.read_to_end(&mut content).unwrap();
+        assert_eq!(content, vec![3]);
+        let link = entries.next().unwrap().unwrap();
+        assert_eq!(link.header().entry_type(), EntryType::Symlink);
+        assert_eq!(
+            link.link_name().unwrap().unwrap().to_str().unwrap(),
+            "file2"
+        );
+        assert!(entries.next().is_none());
+    }
+
+    #[test]
+    fn to_tar_with_config() {
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(build_archive()), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut options = TarExportOptions::new();
+        options
+            .with_format(TarFormat::Posix)
+            .with_prefix("root/")
+            .with_symlinks(SymlinkHandling::Skip)
+            .with_metadata(false);
+        let mut tar_data = Vec::new();
+        mla_read.to_tar_with_config(&mut tar_data, options).unwrap();
+
+        assert_eq!(
+            tar_list(&tar_data),
+            vec!["root/dir/", "root/dir/file1", "root/file2"]
+        );
+        let mut tar_archive = tar::Archive::new(tar_data.as_slice());
+        for entry in tar_archive.entries().unwrap() {
+            let entry = entry.unwrap();
+            assert!(entry.header().as_ustar().is_some());
+            assert_eq!(entry.header().mtime().unwrap(), 0);
+        }
+    }
+
+    /// Entries of `tar_data`, as (path, content)
+    fn tar_entries(tar_data: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut tar_archive = tar::Archive::new(tar_data);
+        tar_archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let path = entry.path().unwrap().to_str().unwrap().to_string();
+                let mut content = Vec::new();
+                entry.read_to_end(&mut content).unwrap();
+                (path, content)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stream_to_tar_builder() {
+        // Files written one after the other, on several compression blocks
+        let big: Vec<u8> = (0..5 * 1024 * 1024).map(|i| (i % 253) as u8).collect();
+        let mut config = ArchiveWriterConfig::new();
+        config.enable_layer(Layers::COMPRESS);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("big", big.len() as u64, big.as_slice())
+            .unwrap();
+        mla.add_directory("dir", None).unwrap();
+        mla.add_file("dir/small", 3, [1, 2, 3].as_ref()).unwrap();
+        mla.add_symlink("link", "big", None).unwrap();
+        mla.finalize().unwrap();
+        let archive = mla.into_raw();
+
+        // Entries are appended in their order of appearance, after the ones
+        // already in the builder
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(archive), ArchiveReaderConfig::new()).unwrap();
+        let mut builder = Builder::new(Vec::new());
+        let mut header = Header::new_gnu();
+        header.set_size(1);
+        builder
+            .append_data(&mut header, "first", [0].as_ref())
+            .unwrap();
+        mla_read.stream_to_tar_builder(&mut builder).unwrap();
+        let tar_data = builder.into_inner().unwrap();
+        assert_eq!(
+            tar_list(&tar_data),
+            vec!["first", "big", "dir/", "dir/small", "link"]
+        );
+        let entries = tar_entries(&tar_data);
+        assert_eq!(entries[1], ("big".to_string(), big));
+        assert_eq!(entries[3], ("dir/small".to_string(), vec![1, 2, 3]));
+
+        // Interleaved files can't be read linearly, they are exported as with
+        // `to_tar`
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(build_archive()), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut expected = Vec::new();
+        mla_read.to_tar(&mut expected).unwrap();
+        let mut builder = Builder::new(Vec::new());
+        mla_read.stream_to_tar_builder(&mut builder).unwrap();
+        assert_eq!(builder.into_inner().unwrap(), expected);
+    }
+}
@@ -0,0 +1,285 @@
+use crate::errors::Error;
+use crate::metadata::FileMetadata;
+use crate::tar_export::SymlinkHandling;
+use crate::{ArchiveWriter, CACHE_SIZE};
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::time::{Duration, UNIX_EPOCH};
+use tar::{Archive, EntryType, Header};
+
+/// Options of `ArchiveWriter::from_tar`
+#[derive(Debug, Clone)]
+pub struct TarImportOptions {
+    symlinks: SymlinkHandling,
+    /// Import the entries metadata
+    metadata: bool,
+}
+
+impl std::default::Default for TarImportOptions {
+    fn default() -> Self {
+        TarImportOptions {
+            symlinks: SymlinkHandling::Preserve,
+            metadata: true,
+        }
+    }
+}
+
+impl TarImportOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how symbolic links are imported (default:
+    /// `SymlinkHandling::Preserve`)
+    pub fn with_symlinks(&mut self, symlinks: SymlinkHandling) -> &mut TarImportOptions {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// Import the metadata (modification time, permissions, owner) of the
+    /// tar entries (default: `true`)
+    pub fn with_metadata(&mut self, metadata: bool) -> &mut TarImportOptions {
+        self.metadata = metadata;
+        self
+    }
+}
+
+/// Summary of a tar import, see `ArchiveWriter::from_tar`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ImportReport {
+    pub files_added: u64,
+    pub symlinks_added: u64,
+    pub directories_added: u64,
+    /// Total size of the files content added, in bytes
+    pub bytes_written: u64,
+    /// Entries not imported: unsupported types (hard links, devices, FIFOs,
+    /// etc.), or symbolic links with `SymlinkHandling::Skip`
+    pub entries_skipped: u64,
+}
+
+/// Metadata of a tar entry, as stored in a tar header
+fn header_metadata(header: &Header) -> io::Result<FileMetadata> {
+    Ok(FileMetadata {
+        mtime: Some(UNIX_EPOCH + Duration::from_secs(header.mtime()?)),
+        mode: Some(header.mode()? & 0o7777),
+        uid: u32::try_from(header.uid()?).ok(),
+        gid: u32::try_from(header.gid()?).ok(),
+    })
+}
+
+impl<'a, W: Write> ArchiveWriter<'a, W> {
+    /// Add every entry of the tar archive `tar_stream` to this archive: files,
+    /// directories and, depending on `options`, symbolic links
+    ///
+    /// Entries are read in one pass, so `tar_stream` can be a pipe. Long
+    /// names and large sizes from PAX and GNU extended headers are supported.
+    /// Directory names are stored without their trailing '/'. An error is
+    /// returned if a name is used twice.
+    pub fn from_tar<R: Read>(
+        &mut self,
+        tar_stream: R,
+        options: TarImportOptions,
+    ) -> Result<ImportReport, Error> {
+        let mut report = ImportReport::default();
+        let mut buf = Vec::with_capacity(CACHE_SIZE);
+        let mut tar_archive = Archive::new(tar_stream);
+        for entry in tar_archive.entries()? {
+            let mut entry = entry?;
+            let name = String::from_utf8(entry.path_bytes().into_owned())?;
+            let metadata = if options.metadata {
+                Some(header_metadata(entry.header())?)
+            } else {
+                None
+            };
+            match entry.header().entry_type() {
+                EntryType::Regular | EntryType::Continuous | EntryType::GNUSparse => {
+                    let size = entry.size();
+                    let id = match &metadata {
+                        Some(metadata) => self.start_file_with_metadata(&name, metadata)?,
+                        None => self.start_file(&name)?,
+                    };
+                    let mut written = 0;
+                    loop {
+                        buf.clear();
+                        let count = (&mut entry).take(CACHE_SIZE as u64).read_to_end(&mut buf)?;
+                        if count == 0 {
+                            break;
+                        }
+                        self.append_file_content(id, count as u64, buf.as_slice())?;
+                        written += count as u64;
+                    }
+                    if written != size {
+                        // Truncated tar archive
+                        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                    }
+                    self.end_file(id)?;
+                    report.files_added += 1;
+                    report.bytes_written += written;
+                }
+                EntryType::Directory => {
+                    self.add_directory(name.trim_end_matches('/'), metadata)?;
+                    report.directories_added += 1;
+                }
+                EntryType::Symlink if options.symlinks == SymlinkHandling::Preserve => {
+                    let target = match entry.link_name_bytes() {
+                        Some(target) => String::from_utf8(target.into_owned())?,
+                        None => {
+                            return Err(Error::BadAPIArgument(format!(
+                                "[from_tar] Symbolic link without target: {}",
+                                name
+                            )))
+                        }
+                    };
+                    self.add_symlink(&name, &target, metadata)?;
+                    report.symlinks_added += 1;
+                }
+                _ => {
+                    report.entries_skipped += 1;
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+    use crate::ArchiveReader;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use std::fs;
+    use std::io::Cursor;
+    use std::process::Command;
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    #[cfg(unix)]
+    #[test]
+    fn from_tar() {
+        // Build a real tarball, with the `tar` command, in PAX format
+        let root = std::env::temp_dir().join(format!("mla_from_tar_{}", std::process::id()));
+        let long_name = "a".repeat(150);
+        let files: Vec<(String, Vec<u8>)> = vec![
+            ("data/small".to_string(), b"small content".to_vec()),
+            (
+                format!("data/{}/{}", long_name, long_name),
+                (0..200_000u32).map(|i| i as u8).collect(),
+            ),
+            ("data/empty".to_string(), Vec::new()),
+        ];
+        for (fname, content) in &files {
+            let path = root.join(fname);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        fs::create_dir_all(root.join("data/empty_dir")).unwrap();
+        std::os::unix::fs::symlink("small", root.join("data/link")).unwrap();
+        let output = Command::new("tar")
+            .arg("--format=pax")
+            .arg("-C")
+            .arg(&root)
+            .arg("-cf")
+            .arg("-")
+            .arg("data")
+            .output();
+        fs::remove_dir_all(&root).unwrap();
+        let output = output.unwrap();
+        assert!(output.status.success());
+
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let mut mla =
+            ArchiveWriter::new(Vec::new(), std::slice::from_ref(&PublicKey::from(&key))).unwrap();
+        let report = mla
+            .from_tar(output.stdout.as_slice(), TarImportOptions::new())
+            .unwrap();
+        mla.finalize().unwrap();
+        assert_eq!(report.files_added, 3);
+        assert_eq!(report.symlinks_added, 1);
+        // data, data/empty_dir and data/aaa...
+        assert_eq!(report.directories_added, 3);
+        assert_eq!(
+            report.bytes_written,
+            files
+                .iter()
+                .map(|(_, content)| content.len() as u64)
+                .sum::<u64>()
+        );
+        assert_eq!(report.entries_skipped, 0);
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(Cursor::new(mla.into_raw()), config).unwrap();
+        for (fname, content) in &files {
+            let mut file = mla_read.get_file(fname.clone()).unwrap().unwrap();
+            let mut rez = Vec::new();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(&rez, content);
+        }
+        let small = mla_read.get_file("data/small").unwrap().unwrap();
+        assert!(small.metadata.unwrap().mtime.is_some());
+        assert!(mla_read
+            .list_directories()
+            .unwrap()
+            .any(|dir| dir == "data/empty_dir"));
+        assert_eq!(
+            mla_read.list_symlinks().unwrap().collect::<Vec<_>>(),
+            vec![("data/link", "small")]
+        );
+    }
+
+    #[test]
+    fn from_tar_options() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Symlink);
+        header.set_size(0);
+        builder.append_link(&mut header, "link", "target").unwrap();
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Fifo);
+        header.set_size(0);
+        builder
+            .append_data(&mut header, "fifo", io::empty())
+            .unwrap();
+        let mut header = Header::new_gnu();
+        header.set_size(2);
+        builder
+            .append_data(&mut header, "file", [1, 2].as_ref())
+            .unwrap();
+        let tar_data = builder.into_inner().unwrap();
+
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        let mut options = TarImportOptions::new();
+        options
+            .with_symlinks(SymlinkHandling::Skip)
+            .with_metadata(false);
+        let report = mla.from_tar(tar_data.as_slice(), options).unwrap();
+        mla.finalize().unwrap();
+        assert_eq!(
+            report,
+            ImportReport {
+                files_added: 1,
+                bytes_written: 2,
+                entries_skipped: 2,
+                ..Default::default()
+            }
+        );
+
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(mla.into_raw()), ArchiveReaderConfig::new())
+                .unwrap();
+        let file = mla_read.get_file("file").unwrap().unwrap();
+        assert!(file.metadata.is_none());
+        assert_eq!(mla_read.list_symlinks().unwrap().count(), 0);
+
+        // A truncated tar archive is reported
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        assert!(mla
+            .from_tar(
+                &tar_data[..tar_data.len() - 1024 - 511],
+                TarImportOptions::new()
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,319 @@
+use crate::errors::Error;
+use crate::metadata::FileMetadata;
+use crate::{ArchiveWriter, CACHE_SIZE};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use walkdir::{DirEntry, WalkDir};
+
+/// Options of `ArchiveWriter::add_directory_tree`
+#[derive(Debug, Clone, Default)]
+pub struct TreeAddOptions {
+    /// Store the metadata (modification time, permissions, owner) of files
+    /// and directories
+    pub preserve_metadata: bool,
+    /// Follow symbolic links, adding their target instead. Otherwise,
+    /// symbolic links are skipped
+    pub follow_symlinks: bool,
+    /// Entries to exclude, matched against their path relative to the root.
+    /// A directory excluded is not walked
+    pub exclude_patterns: Vec<glob::Pattern>,
+}
+
+impl TreeAddOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Summary of a directory tree addition, see
+/// `ArchiveWriter::add_directory_tree`
+#[derive(Debug, Default)]
+pub struct TreeAddReport {
+    pub files_added: u64,
+    pub directories_added: u64,
+    /// Total size of the files content added, in bytes
+    pub bytes_total: u64,
+    /// Entries which could not be read, with the corresponding error. A file
+    /// failing while being read is kept in the archive, truncated
+    pub errors: Vec<(PathBuf, io::Error)>,
+}
+
+/// Archive name of `path`, relative to the walked root: its components,
+/// separated by '/'
+fn relative_name(path: &Path) -> io::Result<String> {
+    let mut components = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => components.push(part.to_str().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "Path is not valid UTF-8")
+            })?),
+            Component::CurDir => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Path is not relative",
+                ))
+            }
+        }
+    }
+    Ok(components.join("/"))
+}
+
+fn entry_metadata(entry: &DirEntry, options: &TreeAddOptions) -> io::Result<Option<FileMetadata>> {
+    if options.preserve_metadata {
+        Ok(Some(FileMetadata::from(&entry.metadata()?)))
+    } else {
+        Ok(None)
+    }
+}
+
+impl<'a, W: Write> ArchiveWriter<'a, W> {
+    /// Recursively add the files and directories under `root`, named after
+    /// their path relative to `root` (with '/' as separator)
+    ///
+    /// Entries are added in file name order; `root` itself is not added as a
+    /// directory entry. Errors on the filesystem side (unreadable entries,
+    /// non UTF-8 names, symbolic link loops, etc.) do not stop the walk, and
+    /// are reported in `TreeAddReport::errors`. Archive errors, such as a name
+    /// already used, are returned
+    pub fn add_directory_tree(
+        &mut self,
+        root: &Path,
+        options: TreeAddOptions,
+    ) -> Result<TreeAddReport, Error> {
+        let mut report = TreeAddReport::default();
+        let mut buf = Vec::with_capacity(CACHE_SIZE);
+        let walker = WalkDir::new(root)
+            .follow_links(options.follow_symlinks)
+            .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+            .into_iter()
+            .filter_entry(|entry| match entry.path().strip_prefix(root) {
+                Ok(relative) => !options
+                    .exclude_patterns
+                    .iter()
+                    .any(|pattern| pattern.matches_path(relative)),
+                Err(_) => true,
+            });
+
+        for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    let path = err.path().unwrap_or(root).to_path_buf();
+                    report.errors.push((path, err.into()));
+                    continue;
+                }
+            };
+            let file_type = entry.file_type();
+            if file_type.is_symlink() {
+                // Only reached if links are not followed
+                continue;
+            }
+            let name = if entry.depth() == 0 {
+                if file_type.is_dir() {
+                    continue;
+                }
+                // `root` is a single file
+                entry
+                    .file_name()
+                    .to_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidData, "Path is not valid UTF-8")
+                    })
+            } else {
+                relative_name(
+                    entry
+                        .path()
+                        .strip_prefix(root)
+                        .unwrap_or_else(|_| entry.path()),
+                )
+            };
+            let name = match name {
+                Ok(name) => name,
+                Err(err) => {
+                    report.errors.push((entry.into_path(), err));
+                    continue;
+                }
+            };
+            let metadata = match entry_metadata(&entry, &options) {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    report.errors.push((entry.into_path(), err));
+                    continue;
+                }
+            };
+
+            if file_type.is_dir() {
+                self.add_directory(&name, metadata)?;
+                report.directories_added += 1;
+            } else if file_type.is_file() {
+                let mut file = match File::open(entry.path()) {
+                    Ok(file) => file,
+                    Err(err) => {
+                        report.errors.push((entry.into_path(), err));
+                        continue;
+                    }
+                };
+                let id = match &metadata {
+                    Some(metadata) => self.start_file_with_metadata(&name, metadata)?,
+                    None => self.start_file(&name)?,
+                };
+                // Read until the end, even if the file size changes meanwhile
+                loop {
+                    buf.clear();
+                    let count = match (&mut file).take(CACHE_SIZE as u64).read_to_end(&mut buf) {
+                        Ok(count) => count,
+                        Err(err) => {
+                            report.errors.push((entry.path().to_path_buf(), err));
+                            0
+                        }
+                    };
+                    if count == 0 {
+                        break;
+                    }
+                    self.append_file_content(id, count as u64, buf.as_slice())?;
+                    report.bytes_total += count as u64;
+                }
+                self.end_file(id)?;
+                report.files_added += 1;
+            } else {
+                report.errors.push((
+                    entry.into_path(),
+                    io::Error::new(io::ErrorKind::InvalidInput, "Unsupported file type"),
+                ));
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+    use crate::{ArchiveReader, EntryKind};
+    use std::fs;
+    use std::io::Cursor;
+
+    /// Create a `root` directory with files, an empty directory and a
+    /// symbolic link
+    fn build_tree(root: &Path) {
+        fs::create_dir_all(root.join("sub/deeper")).unwrap();
+        fs::create_dir_all(root.join("empty")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join("a.txt"), b"a content").unwrap();
+        fs::write(root.join("sub/b.log"), b"b content").unwrap();
+        fs::write(
+            root.join("sub/deeper/c.txt"),
+            vec![0x42; 3 * CACHE_SIZE + 1],
+        )
+        .unwrap();
+        fs::write(root.join("target/d.txt"), b"d").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("target", root.join("link")).unwrap();
+    }
+
+    fn add_tree(root: &Path, options: TreeAddOptions) -> (TreeAddReport, Vec<u8>) {
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        let report = mla.add_directory_tree(root, options).unwrap();
+        mla.finalize().unwrap();
+        (report, mla.into_raw())
+    }
+
+    #[test]
+    fn add_directory_tree() {
+        let root = std::env::temp_dir().join(format!("mla_tree_{}", std::process::id()));
+        build_tree(&root);
+        let (report, archive) = add_tree(
+            &root,
+            TreeAddOptions {
+                preserve_metadata: true,
+                exclude_patterns: vec![glob::Pattern::new("*.log").unwrap()],
+                ..Default::default()
+            },
+        );
+        let (report_follow, archive_follow) = add_tree(
+            &root,
+            TreeAddOptions {
+                follow_symlinks: true,
+                exclude_patterns: vec![glob::Pattern::new("sub").unwrap()],
+                ..Default::default()
+            },
+        );
+        fs::remove_dir_all(&root).unwrap();
+
+        // Symbolic links skipped, "*.log" excluded
+        assert!(report.errors.is_empty());
+        assert_eq!(report.files_added, 3);
+        assert_eq!(report.directories_added, 4);
+        assert_eq!(report.bytes_total, 9 + 3 * CACHE_SIZE as u64 + 1 + 1);
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(archive), ArchiveReaderConfig::new()).unwrap();
+        let mut entries: Vec<(String, EntryKind)> = mla_read
+            .list_entries()
+            .unwrap()
+            .map(|(name, kind)| (name.to_string(), kind))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            entries,
+            vec![
+                ("a.txt".to_string(), EntryKind::File),
+                ("empty".to_string(), EntryKind::Directory),
+                ("sub".to_string(), EntryKind::Directory),
+                ("sub/deeper".to_string(), EntryKind::Directory),
+                ("sub/deeper/c.txt".to_string(), EntryKind::File),
+                ("target".to_string(), EntryKind::Directory),
+                ("target/d.txt".to_string(), EntryKind::File),
+            ]
+        );
+        let mut file = mla_read.get_file("sub/deeper/c.txt").unwrap().unwrap();
+        assert!(file.metadata.as_ref().unwrap().mtime.is_some());
+        let mut content = Vec::new();
+        file.data.read_to_end(&mut content).unwrap();
+        assert_eq!(content, vec![0x42; 3 * CACHE_SIZE + 1]);
+
+        // Symbolic links followed, "sub" excluded with its content
+        assert!(report_follow.errors.is_empty());
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(archive_follow), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut files: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+        files.sort();
+        #[cfg(unix)]
+        assert_eq!(files, vec!["a.txt", "link/d.txt", "target/d.txt"]);
+        #[cfg(not(unix))]
+        assert_eq!(files, vec!["a.txt", "target/d.txt"]);
+        let file = mla_read.get_file("a.txt").unwrap().unwrap();
+        assert!(file.metadata.is_none());
+    }
+
+    #[test]
+    fn add_directory_tree_errors() {
+        let root = std::env::temp_dir().join(format!("mla_tree_err_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("file"), b"content").unwrap();
+
+        // A missing root is reported, not returned
+        let (report, _) = add_tree(&root.join("missing"), TreeAddOptions::new());
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].0, root.join("missing"));
+
+        // A single file is added by its name
+        let (report, archive) = add_tree(&root.join("file"), TreeAddOptions::new());
+        assert_eq!(report.files_added, 1);
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(archive), ArchiveReaderConfig::new()).unwrap();
+        assert!(mla_read.get_file("file").unwrap().is_some());
+
+        // Archive errors are returned
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        mla.add_file("file", 0, io::empty()).unwrap();
+        assert!(mla
+            .add_directory_tree(&root, TreeAddOptions::new())
+            .is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
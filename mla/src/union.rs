@@ -0,0 +1,119 @@
+use crate::errors::Error;
+use crate::layers::traits::LayerReader;
+use crate::{ArchiveFile, ArchiveReader, BlocksToFileReader};
+use std::collections::BTreeSet;
+use std::io::{Read, Seek};
+
+/// Several archives read as a single one, for instance successive backups
+///
+/// Archives are given by priority order: a file present in several archives
+/// is read from the first one containing it, for instance the most recent
+/// backup, the other ones being used as fallbacks.
+pub struct UnionArchiveReader<'a, R: 'a + Read + Seek> {
+    archives: Vec<ArchiveReader<'a, R>>,
+}
+
+impl<'a, R: 'a + Read + Seek> UnionArchiveReader<'a, R> {
+    /// Read `archives` as one, by decreasing priority
+    pub fn new(archives: Vec<ArchiveReader<'a, R>>) -> Self {
+        UnionArchiveReader { archives }
+    }
+
+    /// Return an iterator on the filenames present in at least one of the
+    /// archives, sorted and without duplicates
+    pub fn list_files(&self) -> Result<impl Iterator<Item = &String>, Error> {
+        let mut filenames = BTreeSet::new();
+        for archive in &self.archives {
+            filenames.extend(archive.list_files()?);
+        }
+        Ok(filenames.into_iter())
+    }
+
+    /// Check whether the file `filename` is present in one of the archives,
+    /// see `ArchiveReader::file_exists`
+    pub fn file_exists(&mut self, filename: &str) -> Result<bool, Error> {
+        Ok(self.archive_index(filename)?.is_some())
+    }
+
+    /// Return the file `filename`, from the first archive containing it, or
+    /// None if none does
+    #[allow(clippy::type_complexity)]
+    pub fn get_file<'b>(
+        &'b mut self,
+        filename: &str,
+    ) -> Result<Option<ArchiveFile<BlocksToFileReader<'b, Box<dyn 'a + LayerReader<'a, R>>>>>, Error>
+    {
+        match self.archive_index(filename)? {
+            Some(index) => self.archives[index].get_file(filename),
+            None => Ok(None),
+        }
+    }
+
+    /// Return the wrapped archives, in their priority order
+    pub fn into_inner(self) -> Vec<ArchiveReader<'a, R>> {
+        self.archives
+    }
+
+    /// Position, in `archives`, of the first one containing `filename`
+    fn archive_index(&mut self, filename: &str) -> Result<Option<usize>, Error> {
+        for (index, archive) in self.archives.iter_mut().enumerate() {
+            if archive.file_exists(filename)? {
+                return Ok(Some(index));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+    use crate::ArchiveWriter;
+    use std::io::Cursor;
+
+    fn build(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        for (fname, content) in files {
+            mla.add_file(fname, content.len() as u64, *content).unwrap();
+        }
+        mla.finalize().unwrap();
+        mla.into_raw()
+    }
+
+    #[test]
+    fn union_priority() {
+        // Daily backups, the most recent first
+        let archives = [
+            build(&[("a", b"a3"), ("c", b"c3")]),
+            build(&[("a", b"a2"), ("b", b"b2")]),
+            build(&[("a", b"a1"), ("b", b"b1"), ("d", b"d1")]),
+        ];
+        let readers = archives
+            .iter()
+            .map(|archive| {
+                ArchiveReader::from_config(
+                    Cursor::new(archive.as_slice()),
+                    ArchiveReaderConfig::new(),
+                )
+                .unwrap()
+            })
+            .collect();
+        let mut union = UnionArchiveReader::new(readers);
+
+        assert_eq!(
+            union.list_files().unwrap().collect::<Vec<_>>(),
+            ["a", "b", "c", "d"]
+        );
+        for (fname, expected) in [("a", b"a3"), ("b", b"b2"), ("c", b"c3"), ("d", b"d1")] {
+            assert!(union.file_exists(fname).unwrap());
+            let mut file = union.get_file(fname).unwrap().unwrap();
+            let mut content = Vec::new();
+            file.data.read_to_end(&mut content).unwrap();
+            assert_eq!(&content, expected);
+        }
+        assert!(!union.file_exists("e").unwrap());
+        assert!(union.get_file("e").unwrap().is_none());
+        assert_eq!(union.into_inner().len(), 3);
+    }
+}
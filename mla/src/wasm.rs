@@ -0,0 +1,25 @@
+//! WebAssembly helpers, for `wasm32-unknown-unknown` targets such as browsers
+//!
+//! The archive read and write logic only needs `Read`, `Seek` and `Write`
+//! implementations, and works over in-memory buffers (`Vec<u8>`,
+//! `io::Cursor`). The `wasm` feature provides the randomness needed by the
+//! encryption layer through the JavaScript `crypto` API, and the conversions
+//! below to exchange archives with JavaScript.
+//!
+//! Filesystem related API (such as `helpers::SparseFileWriter` or
+//! `ArchiveWriter::add_file_from_path`) still compile, but return I/O errors
+//! at runtime, as there is no filesystem on this target
+use js_sys::Uint8Array;
+use std::io::Cursor;
+
+/// Copy an archive, for instance as returned by `ArchiveWriter::into_raw`,
+/// to a JavaScript `Uint8Array`
+pub fn to_uint8array(archive: &[u8]) -> Uint8Array {
+    Uint8Array::from(archive)
+}
+
+/// Copy an archive from a JavaScript `Uint8Array`, to a source usable by
+/// `ArchiveReader`
+pub fn from_uint8array(array: &Uint8Array) -> Cursor<Vec<u8>> {
+    Cursor::new(array.to_vec())
+}
@@ -0,0 +1,270 @@
+//! Property-based tests of the archive round-trip, and of the reader on
+//! corrupted archives
+
+use mla::config::{ArchiveReaderConfig, ArchiveWriterConfig, CompressionAlgorithm};
+use mla::{ArchiveFailSafeReader, ArchiveReader, ArchiveWriter, Layers};
+use proptest::prelude::*;
+use proptest::sample::Index;
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// Size of the encryption layer chunks
+const ENCRYPT_CHUNK_SIZE: usize = 128 * 1024;
+/// Maximum size of a content part written at once
+const MAX_PART_SIZE: usize = 64 * 1024;
+
+const KEY: [u8; 32] = [6; 32];
+
+/// Parameters of a generated archive
+///
+/// The library configuration types are neither `Clone` nor `Debug`, as they
+/// hold keys: this describes them instead, and builds them
+#[derive(Debug, Clone, Default)]
+struct ArchiveParams {
+    encrypt: bool,
+    /// Compression layer algorithm, if the layer is enabled
+    compression: Option<CompressionAlgorithm>,
+}
+
+impl ArchiveParams {
+    fn writer_config(&self) -> ArchiveWriterConfig {
+        let mut config = ArchiveWriterConfig::new();
+        if self.encrypt {
+            config
+                .enable_layer(Layers::ENCRYPT)
+                .add_public_keys(&[PublicKey::from(&StaticSecret::from(KEY))]);
+        }
+        if let Some(algorithm) = self.compression {
+            config
+                .enable_layer(Layers::COMPRESS)
+                .with_compression_algorithm(algorithm)
+                .unwrap();
+        }
+        config
+    }
+
+    fn reader_config(&self) -> ArchiveReaderConfig {
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&[StaticSecret::from(KEY)]);
+        config
+    }
+}
+
+impl Arbitrary for ArchiveParams {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        let compression = prop_oneof![
+            Just(None),
+            Just(Some(CompressionAlgorithm::None)),
+            (0u8..=11).prop_map(|quality| Some(CompressionAlgorithm::Brotli { quality })),
+            (1i32..=19).prop_map(|level| Some(CompressionAlgorithm::Zstd { level })),
+            Just(Some(CompressionAlgorithm::Lz4)),
+        ];
+        (any::<bool>(), compression)
+            .prop_map(|(encrypt, compression)| ArchiveParams {
+                encrypt,
+                compression,
+            })
+            .boxed()
+    }
+}
+
+/// File names, including the empty one and names with path separators
+fn filename() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just(String::new()),
+        "[a-z0-9._-]{1,12}",
+        "(/?[a-z0-9._-]{0,6}){1,4}/?",
+        "[a-z]{0,4}\\\\[a-z]{0,4}",
+        "\\PC{1,16}",
+    ]
+}
+
+/// File contents: empty, single byte, around the encryption chunk size, or
+/// arbitrary
+fn content() -> impl Strategy<Value = Vec<u8>> {
+    let sized = prop_oneof![
+        Just(0),
+        Just(1),
+        Just(ENCRYPT_CHUNK_SIZE - 1),
+        Just(ENCRYPT_CHUNK_SIZE),
+        Just(ENCRYPT_CHUNK_SIZE + 1),
+        Just(2 * ENCRYPT_CHUNK_SIZE),
+    ]
+    .prop_flat_map(|size| (Just(size), any::<u8>()))
+    .prop_map(|(size, seed)| {
+        (0..size)
+            .map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed))
+            .collect()
+    });
+    prop_oneof![sized, prop::collection::vec(any::<u8>(), 0..4096)]
+}
+
+/// Write an archive with `files`, interleaving their contents.
+///
+/// Contents are written in parts of `part_size` bytes; each `order` index
+/// picks the file, among the ones not over, whose next part is written
+fn write_archive(
+    params: &ArchiveParams,
+    files: &HashMap<String, Vec<u8>>,
+    part_size: usize,
+    order: &[Index],
+) -> Vec<u8> {
+    let mut mla = ArchiveWriter::from_config(Vec::new(), params.writer_config()).unwrap();
+    // (id, remaining content) of opened files
+    let mut opened = Vec::new();
+    for (fname, content) in files {
+        opened.push((mla.start_file(fname).unwrap(), content.as_slice()));
+    }
+    let mut step = 0;
+    while !opened.is_empty() {
+        let pick = if order.is_empty() {
+            0
+        } else {
+            order[step % order.len()].index(opened.len())
+        };
+        step += 1;
+        let (id, remaining) = &mut opened[pick];
+        let size = std::cmp::min(part_size, remaining.len());
+        if size == 0 {
+            mla.end_file(*id).unwrap();
+            opened.remove(pick);
+            continue;
+        }
+        mla.append_file_content(*id, size as u64, &remaining[..size])
+            .unwrap();
+        *remaining = &remaining[size..];
+    }
+    mla.finalize().unwrap();
+    mla.into_raw()
+}
+
+/// Read `archive` in every possible way, while ignoring errors: every file
+/// of it, its integrity, and its fail-safe conversion
+fn read_everything(archive: &[u8], params: &ArchiveParams) {
+    let mut output =
+        ArchiveWriter::from_config(Vec::new(), ArchiveParams::default().writer_config()).unwrap();
+    if let Ok(mut mla_fsread) = ArchiveFailSafeReader::from_config(archive, params.reader_config())
+    {
+        let _ = mla_fsread.convert_to_archive(&mut output);
+    }
+
+    let mut mla_read =
+        match ArchiveReader::from_config(Cursor::new(archive), params.reader_config()) {
+            Ok(mla_read) => mla_read,
+            Err(_) => return,
+        };
+    let _ = mla_read.verify_integrity();
+    let fnames: Vec<String> = match mla_read.list_files() {
+        Ok(fnames) => fnames.cloned().collect(),
+        Err(_) => return,
+    };
+    for fname in fnames {
+        if let Ok(Some(mut file)) = mla_read.get_file(fname) {
+            let mut content = Vec::new();
+            let _ = file.data.read_to_end(&mut content);
+        }
+    }
+}
+
+/// Corruption applied to an archive
+#[derive(Debug, Clone)]
+enum Corruption {
+    /// XOR the byte at an index with a non-zero value
+    Flip(Index, u8),
+    /// Replace the bytes starting at an index
+    Overwrite(Index, Vec<u8>),
+    /// Keep only the bytes before an index
+    Truncate(Index),
+}
+
+fn corruption() -> impl Strategy<Value = Corruption> {
+    prop_oneof![
+        (any::<Index>(), 1u8..).prop_map(|(index, value)| Corruption::Flip(index, value)),
+        (any::<Index>(), prop::collection::vec(any::<u8>(), 1..16))
+            .prop_map(|(index, data)| Corruption::Overwrite(index, data)),
+        any::<Index>().prop_map(Corruption::Truncate),
+    ]
+}
+
+impl Corruption {
+    fn apply(&self, archive: &mut Vec<u8>) {
+        match self {
+            Corruption::Flip(index, value) => {
+                let position = index.index(archive.len());
+                archive[position] ^= value;
+            }
+            Corruption::Overwrite(index, data) => {
+                let position = index.index(archive.len());
+                let end = std::cmp::min(archive.len(), position + data.len());
+                archive[position..end].copy_from_slice(&data[..end - position]);
+            }
+            Corruption::Truncate(index) => {
+                let position = index.index(archive.len());
+                archive.truncate(position);
+            }
+        }
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn roundtrip(
+        params in any::<ArchiveParams>(),
+        files in prop::collection::hash_map(filename(), content(), 0..6),
+        part_size in 1..MAX_PART_SIZE,
+        order in prop::collection::vec(any::<Index>(), 0..32),
+    ) {
+        let archive = write_archive(&params, &files, part_size, &order);
+
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(archive), params.reader_config()).unwrap();
+        let mut fnames: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+        fnames.sort();
+        let mut expected: Vec<String> = files.keys().cloned().collect();
+        expected.sort();
+        prop_assert_eq!(fnames, expected);
+        for (fname, content) in &files {
+            let mut file = mla_read.get_file(fname.clone()).unwrap().unwrap();
+            prop_assert_eq!(file.size, content.len() as u64);
+            let mut data = Vec::new();
+            file.data.read_to_end(&mut data).unwrap();
+            prop_assert_eq!(&data, content);
+        }
+    }
+
+    #[test]
+    fn corrupted_archive(
+        params in any::<ArchiveParams>(),
+        files in prop::collection::hash_map(filename(), prop::collection::vec(any::<u8>(), 0..2048), 1..4),
+        corruptions in prop::collection::vec(corruption(), 1..4),
+    ) {
+        let mut archive = write_archive(&params, &files, MAX_PART_SIZE, &[]);
+        for corruption in &corruptions {
+            if archive.is_empty() {
+                break;
+            }
+            corruption.apply(&mut archive);
+        }
+        // Errors are expected, but never a panic
+        read_everything(&archive, &params);
+    }
+
+    #[test]
+    fn arbitrary_data(
+        params in any::<ArchiveParams>(),
+        data in prop::collection::vec(any::<u8>(), 0..4096),
+    ) {
+        read_everything(&data, &params);
+        // Behind a valid header
+        let mut archive = write_archive(&params, &HashMap::new(), MAX_PART_SIZE, &[]);
+        archive.truncate(8);
+        archive.extend_from_slice(&data);
+        read_everything(&archive, &params);
+    }
+}
@@ -0,0 +1,51 @@
+//! Archive round trip through JavaScript, run with `wasm-pack test --node --
+//! --features wasm` or `cargo test --target wasm32-unknown-unknown --features
+//! wasm` (with `wasm-bindgen-test-runner` as the target runner)
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use js_sys::{Function, Uint8Array};
+use mla::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+use mla::wasm::{from_uint8array, to_uint8array};
+use mla::{ArchiveReader, ArchiveWriter, Layers};
+use std::io::Read;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_test::wasm_bindgen_test;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+#[wasm_bindgen_test]
+fn roundtrip_through_javascript() {
+    let key = StaticSecret::from([1; 32]);
+    let mut config = ArchiveWriterConfig::new();
+    config
+        .set_layers(Layers::DEFAULT)
+        .add_public_keys(&[PublicKey::from(&key)]);
+    let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+    let content: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+    mla.add_file("file", content.len() as u64, content.as_slice())
+        .unwrap();
+    mla.add_directory("dir", None).unwrap();
+    mla.finalize().unwrap();
+    let archive = mla.into_raw();
+
+    // Hand the archive over to JavaScript, which gives back a copy of it
+    let array = to_uint8array(&archive);
+    assert_eq!(array.length() as usize, archive.len());
+    let copy = Function::new_with_args("data", "return data.slice()")
+        .call1(&JsValue::NULL, &array)
+        .unwrap()
+        .dyn_into::<Uint8Array>()
+        .unwrap();
+
+    let mut config = ArchiveReaderConfig::new();
+    config.add_private_keys(&[key]);
+    let mut mla_read = ArchiveReader::from_config(from_uint8array(&copy), config).unwrap();
+    let mut data = Vec::new();
+    mla_read
+        .get_file("file")
+        .unwrap()
+        .unwrap()
+        .data
+        .read_to_end(&mut data)
+        .unwrap();
+    assert_eq!(data, content);
+}
@@ -7,8 +7,11 @@ use hex;
 use humansize::{file_size_opts, FileSize};
 use mla::config::{ArchiveReaderConfig, ArchiveWriterConfig};
 use mla::errors::{Error, FailSafeReadError};
-use mla::helpers::linear_extract;
-use mla::{ArchiveFailSafeReader, ArchiveFile, ArchiveReader, ArchiveWriter, Layers};
+use mla::helpers::{linear_extract_with_directories, supports_sparse, SparseFileWriter};
+use mla::{
+    ArchiveFailSafeReader, ArchiveFile, ArchiveReader, ArchiveWriter, EntryKind, Layers,
+    StreamArchiveReader,
+};
 use rand::SeedableRng;
 use rand_chacha::ChaChaRng;
 use std::collections::{HashMap, HashSet};
@@ -197,6 +200,20 @@ fn open_mla_file<'a>(matches: &ArgMatches) -> Result<ArchiveReader<'a, File>, Er
     ArchiveReader::from_config(file, config)
 }
 
+/// Whether the archive must be read from stdin ("-" as input)
+fn is_stdin_input(matches: &ArgMatches) -> bool {
+    // Safe to use unwrap() because the option is required()
+    matches.value_of("input").unwrap() == "-"
+}
+
+// Utils: common code to load a mla_file from stdin, which is not seekable
+fn open_mla_stdin<'a>(matches: &ArgMatches) -> Result<StreamArchiveReader<'a, io::Stdin>, Error> {
+    let config = readerconfig_from_matches(matches);
+
+    // Instantiate reader
+    ArchiveReader::from_stream_config(io::stdin(), config)
+}
+
 // Utils: common code to load a mla_file from arguments, fail-safe mode
 fn open_failsafe_mla_file<'a>(
     matches: &ArgMatches,
@@ -415,7 +432,42 @@ fn create(matches: &ArgMatches) -> Result<(), Error> {
     Ok(())
 }
 
+/// List the files of an archive read from stdin, in the 'list' output format
+///
+/// Without the footer, sizes are computed by reading the files content, and
+/// hashes are not available
+fn list_stdin(matches: &ArgMatches) -> Result<(), Error> {
+    let mla = open_mla_stdin(matches)?;
+
+    let mut files = Vec::new();
+    for entry in mla.entries() {
+        let mut entry = entry?;
+        if entry.kind != EntryKind::File {
+            continue;
+        }
+        let size = if matches.is_present("verbose") {
+            io::copy(&mut entry, &mut io::sink())?
+        } else {
+            0
+        };
+        files.push((entry.filename, size));
+    }
+    files.sort();
+    for (fname, size) in files {
+        if matches.is_present("verbose") {
+            let size = size.file_size(file_size_opts::CONVENTIONAL).unwrap();
+            println!("{} - {}", fname, size);
+        } else {
+            println!("{}", fname);
+        }
+    }
+    Ok(())
+}
+
 fn list(matches: &ArgMatches) -> Result<(), Error> {
+    if is_stdin_input(matches) {
+        return list_stdin(matches);
+    }
     let mut mla = open_mla_file(matches)?;
 
     let mut iter: Vec<String> = mla.list_files()?.cloned().collect();
@@ -441,13 +493,26 @@ fn list(matches: &ArgMatches) -> Result<(), Error> {
     Ok(())
 }
 
+fn info(matches: &ArgMatches) -> Result<(), Error> {
+    let mut mla = open_mla_file(matches)?;
+    println!("{}", mla.statistics()?);
+    if let Some(archive_id) = mla.archive_id() {
+        println!("Archive ID: {}", hex::encode(archive_id));
+    }
+    if let Some(comment) = mla.comment() {
+        println!("Comment: {}", comment);
+    }
+    if let Some(comment) = mla.encrypted_comment() {
+        println!("Encrypted comment: {}", comment);
+    }
+    Ok(())
+}
+
 fn extract(matches: &ArgMatches) -> Result<(), Error> {
     let file_name_matcher = ExtractFileNameMatcher::from_matches(&matches);
     let output_dir = Path::new(matches.value_of_os("outputdir").unwrap());
     let verbose = matches.is_present("verbose");
 
-    let mut mla = open_mla_file(matches)?;
-
     // Create the output directory, if it does not exist
     if !output_dir.exists() {
         fs::create_dir(&output_dir).map_err(|err| {
@@ -468,6 +533,36 @@ fn extract(matches: &ArgMatches) -> Result<(), Error> {
         err
     })?;
 
+    // Holes are restored as is if the output filesystem supports them
+    let sparse = supports_sparse(&output_dir);
+
+    if is_stdin_input(matches) {
+        // The archive can only be read linearly, in its order
+        for entry in open_mla_stdin(matches)?.entries() {
+            let mut entry = entry?;
+            if entry.kind != EntryKind::File || !file_name_matcher.match_file_name(&entry.filename)
+            {
+                continue;
+            }
+            let mut extracted_file = match create_file(&output_dir, &entry.filename)? {
+                Some((file, _path)) => SparseFileWriter::new(file, sparse),
+                None => continue,
+            };
+
+            if verbose {
+                println!("{}", entry.filename);
+            }
+            io::copy(&mut entry, &mut extracted_file)
+                .and_then(|_| extracted_file.finalize())
+                .map_err(|err| {
+                    eprintln!(" [!] Unable to extract \"{}\" ({:?})", entry.filename, err);
+                    err
+                })?;
+        }
+        return Ok(());
+    }
+
+    let mut mla = open_mla_file(matches)?;
     let mut iter: Vec<String> = mla.list_files()?.cloned().collect();
     iter.sort();
 
@@ -485,7 +580,7 @@ fn extract(matches: &ArgMatches) -> Result<(), Error> {
                 None => continue,
             }
         }
-        return linear_extract(&mut mla, &mut export);
+        return linear_extract_with_directories(&mut mla, &mut export, &output_dir);
     }
 
     for fname in iter {
@@ -512,18 +607,20 @@ fn extract(matches: &ArgMatches) -> Result<(), Error> {
             }
             Ok(Some(subfile)) => subfile,
         };
-        let (mut extracted_file, _path) = match create_file(&output_dir, &fname)? {
-            Some(file) => file,
+        let mut extracted_file = match create_file(&output_dir, &fname)? {
+            Some((file, _path)) => SparseFileWriter::new(file, sparse),
             None => continue,
         };
 
         if verbose {
             println!("{}", fname);
         }
-        io::copy(&mut sub_file.data, &mut extracted_file).map_err(|err| {
-            eprintln!(" [!] Unable to extract \"{}\" ({:?})", fname, err);
-            err
-        })?;
+        io::copy(&mut sub_file.data, &mut extracted_file)
+            .and_then(|_| extracted_file.finalize())
+            .map_err(|err| {
+                eprintln!(" [!] Unable to extract \"{}\" ({:?})", fname, err);
+                err
+            })?;
     }
     Ok(())
 }
@@ -550,7 +647,7 @@ fn cat(matches: &ArgMatches) -> Result<(), Error> {
                 if !pat.matches(fname) {
                     continue;
                 }
-                match mla.get_file(fname.to_string()) {
+                match mla.get_file(fname) {
                     Err(err) => {
                         eprintln!(" [!] Error while looking up file \"{}\" ({:?})", fname, err);
                         continue;
@@ -574,7 +671,7 @@ fn cat(matches: &ArgMatches) -> Result<(), Error> {
     } else {
         // Retrieve all the files that are specified
         for fname in files_values {
-            match mla.get_file(fname.to_string()) {
+            match mla.get_file(fname) {
                 Err(err) => {
                     eprintln!(" [!] Error while looking up file \"{}\" ({:?})", fname, err);
                     continue;
@@ -709,7 +806,7 @@ fn main() {
     // Common arguments list, for homogeneity
     let input_args = vec![
         Arg::with_name("input")
-            .help("Archive path")
+            .help("Archive path. Use - for stdin ('list' and 'extract' only)")
             .long("input")
             .short("i")
             .number_of_values(1)
@@ -774,6 +871,11 @@ fn main() {
                         .help("Verbose listing, with additional information"),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("info")
+                .about("Display statistics on a MLA Archive")
+                .args(&input_args),
+        )
         .subcommand(
             SubCommand::with_name("extract")
                 .about("Extract files from a MLA Archive")
@@ -875,6 +977,8 @@ fn main() {
         create(matches)
     } else if let Some(matches) = matches.subcommand_matches("list") {
         list(matches)
+    } else if let Some(matches) = matches.subcommand_matches("info") {
+        info(matches)
     } else if let Some(matches) = matches.subcommand_matches("extract") {
         extract(matches)
     } else if let Some(matches) = matches.subcommand_matches("cat") {
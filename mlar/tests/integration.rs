@@ -554,6 +554,36 @@ fn test_stdio() {
     let archive_data = assert.get_output().stdout.clone();
     assert.success().stderr(String::from(&file_list));
 
+    // `mlar list -i - -k samples/test25519.pem`, reading the archive from stdin
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-i")
+        .arg("-")
+        .arg("-k")
+        .arg(ecc_private)
+        .write_stdin(archive_data.clone());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(file_list.clone());
+
+    // `mlar extract -i - -k samples/test25519.pem -o output_dir`
+    let output_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("-i")
+        .arg("-")
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("-o")
+        .arg(output_dir.path())
+        .write_stdin(archive_data.clone());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+    ensure_directory_content(output_dir.path(), &testfs.files);
+
     File::create(mlar_file.path())
         .unwrap()
         .write_all(&archive_data)
@@ -680,6 +710,16 @@ fn test_verbose_listing() {
     println!("{:?}", cmd);
     let assert = cmd.assert();
     assert.success();
+
+    // `mlar info -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("info").arg("-i").arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with(&format!("Files: {}\n", testfs.files.len())));
 }
 
 #[test]